@@ -0,0 +1,278 @@
+//! Daily aggregates of the events of a watcher, for the reports of the media operations teams.
+//!
+//! The time on slate is measured between the `detection` events switching the stream to and from
+//! a slate. A slate shown across midnight counts in both days, and is counted as a slate period
+//! of the day it started.
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use hawkeye_core::models::{VideoMode, WatcherEvent, WatcherEventKind};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Most events aggregated by a request, the aggregates are `truncated` beyond.
+pub const MAX_EVENTS: usize = 100_000;
+
+/// Days aggregated when the request does not set them, today included.
+pub const DEFAULT_DAYS: i64 = 7;
+
+/// Aggregates of a watcher over a period.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Analytics {
+    /// First day aggregated, as `YYYY-MM-DD` in UTC.
+    pub from: String,
+    /// Last day aggregated, included.
+    pub to: String,
+    pub days: Vec<DailyAnalytics>,
+    /// Whether the period had more than `MAX_EVENTS` events, the aggregates missing the latest.
+    pub truncated: bool,
+}
+
+/// Aggregates of the events of a day, in UTC.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct DailyAnalytics {
+    pub date: String,
+    /// Seconds the stream showed a slate.
+    pub slate_seconds: f64,
+    /// Times the stream switched to a slate.
+    pub slate_periods: u64,
+    /// Mean seconds on slate of the slate periods started in the day, `null` without any.
+    pub mean_slate_seconds: Option<f64>,
+    /// Transitions of the slates and triggers.
+    pub transitions: u64,
+    pub actions: u64,
+    pub failed_actions: u64,
+    /// Ratio of the actions that failed, `null` without any action.
+    pub action_failure_rate: Option<f64>,
+}
+
+#[derive(Default)]
+struct Day {
+    slate_ms: i64,
+    periods_ms: Vec<i64>,
+    transitions: u64,
+    actions: u64,
+    failed_actions: u64,
+}
+
+fn midnight(day: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.and_hms(0, 0, 0))
+}
+
+/// Aggregates the events, oldest first, of the days from `first_day` to `last_day` included. The
+/// slate still shown counts until `now`.
+pub fn daily(
+    events: &[WatcherEvent],
+    first_day: NaiveDate,
+    last_day: NaiveDate,
+    now: DateTime<Utc>,
+) -> Vec<DailyAnalytics> {
+    let count = (last_day - first_day).num_days() as usize + 1;
+    let mut days: Vec<Day> = (0..count).map(|_| Day::default()).collect();
+    let index = |time: DateTime<Utc>| {
+        let offset = (time.date().naive_utc() - first_day).num_days();
+        if offset < 0 || offset as usize >= count {
+            None
+        } else {
+            Some(offset as usize)
+        }
+    };
+    let start = midnight(first_day);
+    let end = midnight(last_day + Duration::days(1)).min(now);
+
+    let mut slate_since = None;
+    for (event, time) in events
+        .iter()
+        .filter_map(|event| event.time().map(|time| (event, time)))
+    {
+        let day = match index(time) {
+            Some(day) => day,
+            None => continue,
+        };
+        match event.kind {
+            WatcherEventKind::Detection => {
+                // The stream was already on a slate when the period started
+                if slate_since.is_none() && event.from == Some(VideoMode::Slate) {
+                    slate_since = Some(start);
+                }
+                match (slate_since, event.to == Some(VideoMode::Slate)) {
+                    (None, true) => slate_since = Some(time),
+                    (Some(since), false) => {
+                        add_slate_period(&mut days, &index, since, time);
+                        slate_since = None;
+                    }
+                    _ => (),
+                }
+            }
+            WatcherEventKind::Transition => days[day].transitions += 1,
+            WatcherEventKind::Action => {
+                days[day].actions += 1;
+                if event.success == Some(false) {
+                    days[day].failed_actions += 1;
+                }
+            }
+            WatcherEventKind::Stall => (),
+            // Nothing is detected until the worker starts again
+            WatcherEventKind::Shutdown => {
+                if let Some(since) = slate_since.take() {
+                    add_slate_period(&mut days, &index, since, time);
+                }
+            }
+        }
+    }
+    if let Some(since) = slate_since {
+        if since < end {
+            add_slate_period(&mut days, &index, since, end);
+        }
+    }
+
+    days.into_iter()
+        .enumerate()
+        .map(|(offset, day)| DailyAnalytics {
+            date: (first_day + Duration::days(offset as i64))
+                .format("%Y-%m-%d")
+                .to_string(),
+            slate_seconds: day.slate_ms as f64 / 1000.0,
+            slate_periods: day.periods_ms.len() as u64,
+            mean_slate_seconds: if day.periods_ms.is_empty() {
+                None
+            } else {
+                let total: i64 = day.periods_ms.iter().sum();
+                Some(total as f64 / 1000.0 / day.periods_ms.len() as f64)
+            },
+            transitions: day.transitions,
+            actions: day.actions,
+            failed_actions: day.failed_actions,
+            action_failure_rate: if day.actions == 0 {
+                None
+            } else {
+                Some(day.failed_actions as f64 / day.actions as f64)
+            },
+        })
+        .collect()
+}
+
+/// Adds the slate shown from `since` to `until` to the days it spans, and counts it in the day
+/// it started.
+fn add_slate_period(
+    days: &mut [Day],
+    index: &impl Fn(DateTime<Utc>) -> Option<usize>,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) {
+    if let Some(day) = index(since) {
+        days[day]
+            .periods_ms
+            .push((until - since).num_milliseconds());
+    }
+    let mut cursor = since;
+    while cursor < until {
+        let next_day = midnight(cursor.date().naive_utc() + Duration::days(1)).min(until);
+        if let Some(day) = index(cursor) {
+            days[day].slate_ms += (next_day - cursor).num_milliseconds();
+        }
+        cursor = next_day;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: &str, kind: WatcherEventKind) -> WatcherEvent {
+        WatcherEvent {
+            timestamp: timestamp.to_string(),
+            kind,
+            slate_id: None,
+            from: None,
+            to: None,
+            action: None,
+            success: None,
+            error: None,
+            latency_ms: None,
+        }
+    }
+
+    fn detection(timestamp: &str, from: VideoMode, to: VideoMode) -> WatcherEvent {
+        WatcherEvent {
+            from: Some(from),
+            to: Some(to),
+            ..event(timestamp, WatcherEventKind::Detection)
+        }
+    }
+
+    fn action(timestamp: &str, success: bool) -> WatcherEvent {
+        WatcherEvent {
+            success: Some(success),
+            ..event(timestamp, WatcherEventKind::Action)
+        }
+    }
+
+    #[test]
+    fn aggregates_the_events_by_day() {
+        let events = vec![
+            detection(
+                "2021-12-01T10:00:00.000Z",
+                VideoMode::Content,
+                VideoMode::Slate,
+            ),
+            event("2021-12-01T10:00:00.100Z", WatcherEventKind::Transition),
+            action("2021-12-01T10:00:00.200Z", true),
+            action("2021-12-01T10:00:00.300Z", false),
+            detection(
+                "2021-12-01T10:01:00.000Z",
+                VideoMode::Slate,
+                VideoMode::Content,
+            ),
+            event("2021-12-01T10:01:00.100Z", WatcherEventKind::Transition),
+            detection(
+                "2021-12-01T23:59:00.000Z",
+                VideoMode::Content,
+                VideoMode::Slate,
+            ),
+            detection(
+                "2021-12-02T00:01:00.000Z",
+                VideoMode::Slate,
+                VideoMode::Black,
+            ),
+        ];
+        let first_day = NaiveDate::from_ymd(2021, 12, 1);
+        let now = Utc.ymd(2021, 12, 3).and_hms(12, 0, 0);
+        let days = daily(&events, first_day, NaiveDate::from_ymd(2021, 12, 3), now);
+
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[0].date, "2021-12-01");
+        assert_eq!(days[0].slate_seconds, 120.0);
+        assert_eq!(days[0].slate_periods, 2);
+        assert_eq!(days[0].mean_slate_seconds, Some(90.0));
+        assert_eq!(days[0].transitions, 2);
+        assert_eq!(days[0].action_failure_rate, Some(0.5));
+
+        assert_eq!(days[1].slate_seconds, 60.0);
+        assert_eq!(days[1].slate_periods, 0);
+        assert_eq!(days[1].mean_slate_seconds, None);
+        assert_eq!(days[1].action_failure_rate, None);
+        assert_eq!(days[2].slate_seconds, 0.0);
+    }
+
+    #[test]
+    fn counts_the_slates_shown_at_the_edges() {
+        // On slate since before the period, and still on slate
+        let events = vec![
+            detection(
+                "2021-12-01T01:00:00.000Z",
+                VideoMode::Slate,
+                VideoMode::Content,
+            ),
+            detection(
+                "2021-12-01T11:00:00.000Z",
+                VideoMode::Content,
+                VideoMode::Slate,
+            ),
+        ];
+        let day = NaiveDate::from_ymd(2021, 12, 1);
+        let now = Utc.ymd(2021, 12, 1).and_hms(12, 0, 0);
+        let days = daily(&events, day, day, now);
+
+        assert_eq!(days[0].slate_seconds, 2.0 * 3600.0);
+        assert_eq!(days[0].slate_periods, 2);
+    }
+}
@@ -0,0 +1,191 @@
+//! Records who changed the watchers, and how.
+//!
+//! Every mutating call of the API is logged with the `audit` target, and stored with the watcher
+//! by the backend so it can be read back with `GET /v1/watchers/{id}/audit`.
+use crate::backend::Backend;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Entries kept in the audit log of each watcher, the older ones are dropped.
+pub const MAX_ENTRIES: usize = 100;
+
+/// Fields holding credentials, whose values are neither logged nor stored in the audit log.
+const SECRET_FIELDS: &[&str] = &["authorization", "headers", "passphrase", "stream_key"];
+
+/// Value of the secret fields in the changes.
+const REDACTED: &str = "[redacted]";
+
+/// A mutating call made to a watcher.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// RFC 3339 time of the call.
+    pub timestamp: String,
+    /// Identity of the client, see `auth::actor`.
+    pub actor: String,
+    pub action: String,
+    /// Changes made to the watcher definition, if any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+/// A field of the watcher definition that changed, `null` when missing.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Dotted path of the field, like `source.ingest_port`.
+    pub path: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+/// Records a call made to the watcher. Failing to store the entry does not fail the call, the
+/// entry is still in the logs.
+pub async fn record(backend: &Backend, id: &str, actor: &str, action: &str, changes: Vec<Change>) {
+    let entry = AuditEntry {
+        timestamp: Utc::now().to_rfc3339(),
+        actor: actor.to_string(),
+        action: action.to_string(),
+        changes,
+    };
+    log::info!(
+        target: "audit",
+        "{} {} watcher {}: {}",
+        entry.actor,
+        entry.action,
+        id,
+        serde_json::to_string(&entry.changes).unwrap_or_default()
+    );
+    if let Err(e) = backend.record_audit(id, &entry).await {
+        log::error!("Could not store the audit entry of watcher {}: {:?}", id, e);
+    }
+}
+
+/// Lists the fields that differ between two JSON documents. Objects are compared field by field,
+/// any other value is compared as a whole. The values of the `SECRET_FIELDS` are redacted.
+pub fn diff(before: &Value, after: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_into("", before, after, &mut changes);
+    changes
+}
+
+fn diff_into(path: &str, before: &Value, after: &Value, changes: &mut Vec<Change>) {
+    match (before, after) {
+        (Value::Object(before_fields), Value::Object(after_fields)) => {
+            let mut keys: Vec<&String> = before_fields.keys().chain(after_fields.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let field_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                let before = before_fields.get(key).unwrap_or(&Value::Null);
+                let after = after_fields.get(key).unwrap_or(&Value::Null);
+                if !SECRET_FIELDS.contains(&key.as_str()) {
+                    diff_into(&field_path, before, after, changes);
+                } else if before != after {
+                    changes.push(Change {
+                        path: field_path,
+                        from: redacted(before),
+                        to: redacted(after),
+                    });
+                }
+            }
+        }
+        _ if before != after => changes.push(Change {
+            path: path.to_string(),
+            from: redact(before),
+            to: redact(after),
+        }),
+        _ => (),
+    }
+}
+
+/// The document with the values of its `SECRET_FIELDS` redacted, at any depth.
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, field)| {
+                let field = if SECRET_FIELDS.contains(&key.as_str()) {
+                    redacted(field)
+                } else {
+                    redact(field)
+                };
+                (key.clone(), field)
+            })
+            .collect(),
+        Value::Array(items) => items.iter().map(redact).collect(),
+        other => other.clone(),
+    }
+}
+
+fn redacted(value: &Value) -> Value {
+    if value.is_null() {
+        Value::Null
+    } else {
+        json!(REDACTED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_lists_changed_fields() {
+        let before = json!({
+            "description": "old",
+            "source": { "ingest_port": 5000, "codec": "h264" },
+            "tags": { "team": "video" },
+        });
+        let after = json!({
+            "source": { "ingest_port": 5001, "codec": "h264" },
+            "tags": { "team": "video" },
+        });
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                Change {
+                    path: "description".to_string(),
+                    from: json!("old"),
+                    to: Value::Null,
+                },
+                Change {
+                    path: "source.ingest_port".to_string(),
+                    from: json!(5000),
+                    to: json!(5001),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_redacts_the_credentials() {
+        let before = json!({
+            "source": { "transport": { "protocol": "srt", "passphrase": "0123456789" } },
+            "transitions": [{ "actions": [{ "url": "https://a", "headers": { "X-Key": "abc" } }] }],
+        });
+        let after = json!({
+            "source": { "transport": { "protocol": "srt", "passphrase": "9876543210" } },
+            "transitions": [{ "actions": [{ "url": "https://b", "authorization": "Bearer abc" }] }],
+        });
+        assert_eq!(
+            diff(&before, &after),
+            vec![
+                Change {
+                    path: "source.transport.passphrase".to_string(),
+                    from: json!(REDACTED),
+                    to: json!(REDACTED),
+                },
+                Change {
+                    path: "transitions".to_string(),
+                    from: json!([{ "actions": [{ "url": "https://a", "headers": REDACTED }] }]),
+                    to: json!([{ "actions": [{ "url": "https://b", "authorization": REDACTED }] }]),
+                },
+            ]
+        );
+    }
+}
@@ -1,22 +1,368 @@
-use crate::config;
+use crate::{config, oidc};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
 use warp::Filter;
 
-pub fn verify() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
-    warp::header::<String>("authorization")
-        .and_then(|auth_header: String| async move {
-            match verify_token(auth_header) {
-                Ok(_) => Ok(()),
-                Err(_) => Err(warp::reject::custom(NoAuth)),
+/// How long the keys fetched from the JWKS URL are used before fetching them again.
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Least time between two fetches of the JWKS, so the tokens signed with unknown keys do not make
+/// the API fetch it for each request. The unknown key IDs are remembered for as long.
+const JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Unknown key IDs remembered at most, the following ones are only limited by
+/// `JWKS_REFETCH_INTERVAL`.
+const MAX_UNKNOWN_KIDS: usize = 1_000;
+
+/// Validated JWTs whose claims are kept at most, the ones of the following tokens are not.
+const MAX_VALIDATED_TOKENS: usize = 10_000;
+
+lazy_static! {
+    static ref JWKS_CACHE: RwLock<Option<(Instant, JwkSet)>> = RwLock::new(None);
+    /// Key IDs missing from the JWKS when it was last fetched, and when.
+    static ref UNKNOWN_KIDS: RwLock<HashMap<String, Instant>> = RwLock::new(HashMap::new());
+    /// Claims of the validated JWTs until they expire, by the digest of the token, so the
+    /// filters of a request, like `grant` and `actor`, validate its token once.
+    static ref VALIDATED_TOKENS: RwLock<HashMap<String, Arc<Claims>>> = RwLock::new(HashMap::new());
+}
+
+/// Token the API sends to the worker of a watcher, or of a pack, on the endpoints changing its
+/// detection: the HMAC-SHA256 of the `subject` of the worker, see `templates::pod_subject`.
+pub fn control_token(subject: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config::WORKER_TOKEN_KEY.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("control:{}", subject).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Token the worker of a watcher sends its heartbeats and events with: the HMAC-SHA256 of the
+/// ID of the watcher, so it only reaches the ones of its own watcher.
+pub fn report_token(watcher_id: &str) -> String {
+    hex::encode(report_mac(watcher_id).finalize().into_bytes())
+}
+
+/// Whether the bearer token of the `auth_header` is the `report_token` of the watcher, compared
+/// in constant time.
+pub fn is_report_token(watcher_id: &str, auth_header: &str) -> bool {
+    match hex::decode(auth_header.replace("Bearer ", "")) {
+        Ok(token) => report_mac(watcher_id).verify_slice(&token).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn report_mac(watcher_id: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config::WORKER_TOKEN_KEY.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("report:{}", watcher_id).as_bytes());
+    mac
+}
+
+/// Level of access granted to a client. Each scope includes the ones before it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Scope {
+    /// Can list and get watchers, the `viewer` role.
+    Read,
+    /// Can also start and stop watchers, the `operator` role.
+    Operate,
+    /// Full access, including creating, changing and deleting watchers.
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" | "viewer" => Ok(Scope::Read),
+            "operate" | "operator" => Ok(Scope::Operate),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!("Unknown scope: {}", other)),
+        }
+    }
+}
+
+/// What a client is granted: a scope on the watchers having all the given tags, or on every
+/// watcher when there are none.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Grant {
+    pub scope: Scope,
+    /// `key:value` tags the watchers must have, like `team:sports`.
+    pub tags: Vec<(String, String)>,
+}
+
+impl Grant {
+    /// A grant on every watcher.
+    pub fn all(scope: Scope) -> Self {
+        Self {
+            scope,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Whether the grant is limited to the watchers with some tags.
+    pub fn is_restricted(&self) -> bool {
+        !self.tags.is_empty()
+    }
+
+    /// Whether a watcher with these tags is in reach of the client.
+    pub fn covers(&self, tags: Option<&HashMap<String, String>>) -> bool {
+        self.tags
+            .iter()
+            .all(|(key, value)| tags.and_then(|tags| tags.get(key)) == Some(value))
+    }
+}
+
+impl FromStr for Grant {
+    type Err = String;
+
+    /// Parses a scope followed by the tags it is limited to, like `operate;team:sports`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';').map(str::trim);
+        let scope = parts.next().unwrap_or_default().parse()?;
+        let tags = parts
+            .filter(|tag| !tag.is_empty())
+            .map(parse_tag)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { scope, tags })
+    }
+}
+
+fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once(':') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Invalid tag {}, expected key:value", tag)),
+    }
+}
+
+/// Requires the client to be authenticated with at least the `required` scope.
+///
+/// Clients authenticate with a bearer token, either one of the static API keys or a JWT signed
+/// by one of the keys published in the configured JWKS URL, or by the OIDC provider.
+pub fn verify(required: Scope) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    grant(required).map(|_| ()).untuple_one()
+}
+
+/// Like `verify`, passing on the grant of the client to check the tags of the watchers it
+/// reaches.
+pub fn grant(required: Scope) -> impl Filter<Extract = (Grant,), Error = warp::Rejection> + Clone {
+    warp::header::<String>("authorization").and_then(move |auth_header: String| async move {
+        match granted(auth_header).await {
+            Some(grant) if grant.scope >= required => Ok(grant),
+            Some(_) => Err(warp::reject::custom(Forbidden)),
+            None => Err(warp::reject::custom(NoAuth)),
+        }
+    })
+}
+
+/// Identifies the authenticated client, for the audit log: the subject of the JWT, or a
+/// fingerprint of the API key that is safe to log.
+pub fn actor() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::header::<String>("authorization").then(identify)
+}
+
+async fn identify(auth_header: String) -> String {
+    verified_subject(&auth_header)
+        .await
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Identifies the client like `actor`, only when its API key or JWT is valid.
+pub async fn verified_subject(auth_header: &str) -> Option<String> {
+    let token = auth_header.replace("Bearer ", "");
+    if config::API_KEYS.contains_key(&token) {
+        let fingerprint = hex::encode(Sha256::digest(token.as_bytes()));
+        return Some(format!("api-key:{}", &fingerprint[..8]));
+    }
+    jwt_claims(&token).await.ok()?.sub.clone()
+}
+
+async fn granted(auth_header: String) -> Option<Grant> {
+    let token = auth_header.replace("Bearer ", "");
+    if let Some(grant) = config::API_KEYS.get(&token) {
+        return Some(grant.clone());
+    }
+    if config::JWKS_URL.is_some() || config::OIDC_ISSUER.is_some() {
+        match verify_jwt(&token).await {
+            Ok(grant) => return grant,
+            Err(e) => tracing::debug!("Rejected JWT: {:?}", e),
+        }
+    }
+    None
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    /// Subject of the token, identifying the client.
+    sub: Option<String>,
+    /// Unix time the token expires at, required by the validation.
+    exp: u64,
+    /// Space separated list of scopes, as defined by OAuth 2.0.
+    scope: Option<String>,
+    /// List of scopes, as used by some identity providers.
+    scp: Option<Vec<String>>,
+    /// `key:value` tags the watchers must have, the client reaching every watcher when missing.
+    hawkeye_tags: Option<Vec<String>>,
+    /// Other claims, like the groups of the client named by `HAWKEYE_OIDC_GROUPS_CLAIM`.
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+/// Validates the JWT and returns the highest scope it grants, on the watchers of its tags, or
+/// the grant of its groups when higher.
+async fn verify_jwt(token: &str) -> anyhow::Result<Option<Grant>> {
+    let claims = jwt_claims(token).await?;
+    let scopes = claims
+        .scope
+        .iter()
+        .flat_map(|s| s.split_whitespace().map(String::from).collect::<Vec<_>>())
+        .chain(claims.scp.iter().flatten().cloned());
+    let tags = claims
+        .hawkeye_tags
+        .iter()
+        .flatten()
+        .map(|tag| parse_tag(tag))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let scope_grant = scopes
+        .filter_map(|s| s.parse::<Scope>().ok())
+        .max()
+        .map(|scope| Grant { scope, tags });
+
+    // A single group is a string with some providers
+    let groups: Vec<String> = match claims.other.get(config::OIDC_GROUPS_CLAIM.as_str()) {
+        Some(Value::Array(groups)) => groups
+            .iter()
+            .filter_map(|group| group.as_str().map(String::from))
+            .collect(),
+        Some(Value::String(group)) => vec![group.clone()],
+        _ => Vec::new(),
+    };
+    let group_grant = oidc::group_grant(&config::OIDC_GROUP_ROLES, &groups);
+    Ok(oidc::best_grant(scope_grant.into_iter().chain(group_grant)))
+}
+
+/// Validates the JWT and returns its claims, the ones of the tokens already validated being kept
+/// until they expire.
+async fn jwt_claims(token: &str) -> anyhow::Result<Arc<Claims>> {
+    let digest = hex::encode(Sha256::digest(token.as_bytes()));
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if let Some(claims) = VALIDATED_TOKENS.read().await.get(&digest) {
+        if claims.exp > now {
+            return Ok(claims.clone());
+        }
+    }
+    let claims = Arc::new(validate_jwt(token).await?);
+    let mut validated = VALIDATED_TOKENS.write().await;
+    validated.retain(|_, claims| claims.exp > now);
+    if validated.len() < MAX_VALIDATED_TOKENS {
+        validated.insert(digest, claims.clone());
+    }
+    Ok(claims)
+}
+
+/// Validates the JWT with the key it was signed with, its audience and its issuer.
+async fn validate_jwt(token: &str) -> anyhow::Result<Claims> {
+    let header = decode_header(token)?;
+    let kid = header
+        .kid
+        .ok_or_else(|| anyhow::anyhow!("JWT without key ID"))?;
+    let key = decoding_key(&kid).await?;
+
+    let mut validation = Validation::new(header.alg);
+    // The tokens of the OIDC provider are issued by it, for the client of the API
+    let audience = config::JWT_AUDIENCE
+        .as_ref()
+        .or_else(|| config::OIDC_CLIENT_ID.as_ref());
+    if let Some(audience) = audience {
+        validation.set_audience(&[audience]);
+    }
+    let issuer = match config::JWT_ISSUER.as_ref() {
+        Some(issuer) => Some(issuer.clone()),
+        None if config::OIDC_ISSUER.is_some() => Some(oidc::discovery().await?.issuer),
+        None => None,
+    };
+    if let Some(issuer) = issuer {
+        validation.set_issuer(&[issuer]);
+    }
+    Ok(decode::<Claims>(token, &key, &validation)?.claims)
+}
+
+/// Finds the key used to sign the JWT, refreshing the cached JWKS when it expires or when the key
+/// is unknown, in case the keys were rotated. The JWKS is fetched at most once every
+/// `JWKS_REFETCH_INTERVAL`, the keys still unknown being rejected until then.
+async fn decoding_key(kid: &str) -> anyhow::Result<DecodingKey> {
+    {
+        let cache = JWKS_CACHE.read().await;
+        if let Some((fetched_at, jwks)) = cache.as_ref() {
+            if fetched_at.elapsed() < JWKS_CACHE_TTL {
+                if let Some(jwk) = jwks.find(kid) {
+                    return to_decoding_key(&jwk.algorithm);
+                }
             }
-        })
-        .untuple_one()
+        }
+    }
+    if let Some(found_at) = UNKNOWN_KIDS.read().await.get(kid) {
+        if found_at.elapsed() < JWKS_REFETCH_INTERVAL {
+            anyhow::bail!("Unknown JWT key ID: {}", kid);
+        }
+    }
+
+    // Fetching with the lock held, the requests waiting for it use the keys fetched
+    let mut cache = JWKS_CACHE.write().await;
+    if let Some((fetched_at, jwks)) = cache.as_ref() {
+        if fetched_at.elapsed() < JWKS_CACHE_TTL {
+            if let Some(jwk) = jwks.find(kid) {
+                return to_decoding_key(&jwk.algorithm);
+            }
+        }
+        if fetched_at.elapsed() < JWKS_REFETCH_INTERVAL {
+            remember_unknown(kid).await;
+            anyhow::bail!("Unknown JWT key ID: {}", kid);
+        }
+    }
+    let url = match config::JWKS_URL.as_ref() {
+        Some(url) => url.clone(),
+        None if config::OIDC_ISSUER.is_some() => oidc::discovery().await?.jwks_uri,
+        None => anyhow::bail!("JWKS URL is not configured"),
+    };
+    tracing::debug!("Fetching JWKS from {}", url);
+    let jwks: JwkSet = reqwest::get(url.as_str()).await?.json().await?;
+    let key = jwks.find(kid).map(|jwk| to_decoding_key(&jwk.algorithm));
+    *cache = Some((Instant::now(), jwks));
+    match key {
+        Some(key) => key,
+        None => {
+            remember_unknown(kid).await;
+            Err(anyhow::anyhow!("Unknown JWT key ID: {}", kid))
+        }
+    }
 }
 
-fn verify_token(auth_header: String) -> Result<(), ()> {
-    if auth_header.replace("Bearer ", "").as_str() == config::FIXED_TOKEN.as_str() {
-        Ok(())
-    } else {
-        Err(())
+async fn remember_unknown(kid: &str) {
+    let mut unknown = UNKNOWN_KIDS.write().await;
+    unknown.retain(|_, found_at| found_at.elapsed() < JWKS_REFETCH_INTERVAL);
+    if unknown.len() < MAX_UNKNOWN_KIDS {
+        unknown.insert(kid.to_string(), Instant::now());
+    }
+}
+
+fn to_decoding_key(algorithm: &AlgorithmParameters) -> anyhow::Result<DecodingKey> {
+    match algorithm {
+        AlgorithmParameters::RSA(rsa) => Ok(DecodingKey::from_rsa_components(&rsa.n, &rsa.e)?),
+        _ => Err(anyhow::anyhow!("Only RSA keys are supported in the JWKS")),
     }
 }
 
@@ -24,3 +370,56 @@ fn verify_token(auth_header: String) -> Result<(), ()> {
 pub struct NoAuth;
 
 impl warp::reject::Reject for NoAuth {}
+
+#[derive(Debug)]
+pub struct Forbidden;
+
+impl warp::reject::Reject for Forbidden {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_are_limited_to_their_tags() {
+        let grant: Grant = "operator;team:sports".parse().unwrap();
+        assert_eq!(grant.scope, Scope::Operate);
+        assert!(grant.is_restricted());
+        let mut tags = HashMap::new();
+        tags.insert("team".to_string(), "sports".to_string());
+        tags.insert("region".to_string(), "eu".to_string());
+        assert!(grant.covers(Some(&tags)));
+        tags.insert("team".to_string(), "news".to_string());
+        assert!(!grant.covers(Some(&tags)));
+        assert!(!grant.covers(None));
+
+        let grant: Grant = "admin".parse().unwrap();
+        assert_eq!(grant, Grant::all(Scope::Admin));
+        assert!(grant.covers(None));
+        assert!("viewer;team".parse::<Grant>().is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_keys_do_not_refetch_the_jwks() {
+        *JWKS_CACHE.write().await = Some((Instant::now(), JwkSet { keys: Vec::new() }));
+        // Without a JWKS URL, fetching the keys would fail with another error
+        for _ in 0..2 {
+            let error = decoding_key("rotated").await.err().unwrap();
+            assert_eq!(error.to_string(), "Unknown JWT key ID: rotated");
+        }
+        assert!(UNKNOWN_KIDS.read().await.contains_key("rotated"));
+    }
+
+    #[test]
+    fn report_tokens_are_only_valid_for_their_watcher() {
+        let token = report_token("abc");
+        assert_ne!(token, report_token("abd"));
+        assert!(is_report_token("abc", &format!("Bearer {}", token)));
+        assert!(!is_report_token("abd", &format!("Bearer {}", token)));
+        assert!(!is_report_token("abc", "Bearer not-hex"));
+        assert!(!is_report_token(
+            "abc",
+            &format!("Bearer {}", control_token("abc"))
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+pub mod kubernetes;
+pub mod memory;
+pub mod packs;
+pub mod ports;
+
+use crate::audit::AuditEntry;
+use crate::drift::Drift;
+use crate::events::EventQuery;
+use crate::revisions::Revision;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use hawkeye_core::models::{
+    Heartbeat, Source, Status, ThresholdChange, TransitionTrigger, Watcher, WatcherEvent,
+};
+use std::sync::Arc;
+use warp::hyper::body::Bytes;
+
+/// The backend shared by all the handlers of the API.
+pub type Backend = Arc<dyn WatcherBackend>;
+
+/// Stream of log lines written by a watcher worker.
+pub type LogStream = BoxStream<'static, anyhow::Result<Bytes>>;
+
+/// Runs the watcher workers and stores their definitions.
+///
+/// The handlers of the API only talk to the backend through this trait, so they don't depend on
+/// the platform the workers are running on. Errors are reserved to failures of the platform,
+/// missing watchers are reported with `None`.
+#[async_trait]
+pub trait WatcherBackend: Send + Sync {
+    /// Lists the watchers matching the query, with their calculated status.
+    async fn list_watchers(&self, query: &ListQuery) -> anyhow::Result<WatcherPage>;
+
+    /// Loads a watcher with its calculated status and ingest address.
+    async fn get_watcher(&self, id: &str) -> anyhow::Result<Option<Watcher>>;
+
+    /// Loads the watcher definition as it was stored, without any calculated field.
+    async fn get_watcher_config(&self, id: &str) -> anyhow::Result<Option<Watcher>>;
+
+    /// Calculates the status of the watcher.
+    async fn get_watcher_status(&self, id: &str) -> anyhow::Result<Option<Status>>;
+
+    /// Creates a new watcher, stopped. Nothing is left behind when the creation fails.
+    async fn create_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()>;
+
+    /// Replaces the definition of an existing watcher, restarting it when running.
+    async fn update_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()>;
+
+    /// Deletes the watcher, returns `false` when it does not exist.
+    async fn delete_watcher(&self, id: &str) -> anyhow::Result<bool>;
+
+    /// Moves the watcher to the current version of the worker. A running watcher is only
+    /// upgraded when restarting it is allowed.
+    async fn upgrade_watcher(&self, id: &str, restart: bool) -> anyhow::Result<()>;
+
+    /// Starts running the watcher worker.
+    async fn start_watcher(&self, id: &str) -> anyhow::Result<StatusChange>;
+
+    /// Stops the watcher worker, giving it the grace period to finish the actions in progress.
+    /// The grace period configured for the workers is used when missing.
+    async fn stop_watcher(
+        &self,
+        id: &str,
+        grace_seconds: Option<u32>,
+    ) -> anyhow::Result<StatusChange>;
+
+    /// Finds a free ingest port for a new watcher, the other ports of its source being free as
+    /// well, `None` when all of them are in use.
+    async fn allocate_ingest_port(&self, source: &Source) -> anyhow::Result<Option<u32>>;
+
+    /// Finds another watcher already using one of the ports and returns its ID.
+    async fn find_port_conflict(
+        &self,
+        ports: &[u32],
+        exclude_id: Option<&str>,
+    ) -> anyhow::Result<Option<String>>;
+
+    /// Reserves the name for the watcher, atomically so two watchers never get the same name.
+    /// Returns the ID of the other watcher holding it, if any.
+    async fn reserve_name(&self, name: &str, id: &str) -> anyhow::Result<Option<String>>;
+
+    /// Releases the name reserved for the watcher, unless another watcher took it since.
+    async fn release_name(&self, name: &str, id: &str) -> anyhow::Result<()>;
+
+    /// Fetches the latest video frame seen by a running watcher, as a PNG image.
+    async fn get_video_frame(&self, id: &str) -> anyhow::Result<Option<Bytes>>;
+
+    /// Fetches the Prometheus metrics of a running watcher, in the text exposition format.
+    async fn get_watcher_metrics(&self, id: &str) -> anyhow::Result<Option<String>>;
+
+    /// Reloads the slates and the transitions of a running watcher from its stored definition,
+    /// without restarting its worker. Returns `false` when the worker cannot reload them.
+    async fn reload_watcher(&self, id: &str) -> anyhow::Result<bool>;
+
+    /// Changes the threshold of a slate of a running watcher until its next reload or restart,
+    /// returning the comparison of its latest frame with the slate under the new threshold.
+    async fn set_watcher_threshold(
+        &self,
+        id: &str,
+        change: &ThresholdChange,
+    ) -> anyhow::Result<Option<serde_json::Value>>;
+
+    /// Samples the similarities of the frames of a running watcher with its slates for `seconds`,
+    /// returning their distribution with a suggested threshold for each slate.
+    async fn calibrate_watcher(
+        &self,
+        id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>>;
+
+    /// Records `seconds` of the incoming video of a running watcher to its archive, returning the
+    /// reference of the clip once it is uploaded.
+    async fn record_watcher(
+        &self,
+        id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>>;
+
+    /// Fetches what a running watcher is detecting, as reported by its worker.
+    async fn get_watcher_state(&self, id: &str) -> anyhow::Result<Option<serde_json::Value>>;
+
+    /// Fires the actions of transitions of a running watcher, returning their records once they
+    /// completed. Returns `None` when the worker cannot fire them.
+    async fn trigger_transition(
+        &self,
+        id: &str,
+        trigger: &TransitionTrigger,
+    ) -> anyhow::Result<Option<serde_json::Value>>;
+
+    /// Fetches the last actions executed by the worker of a running watcher, oldest first.
+    async fn get_action_history(&self, id: &str) -> anyhow::Result<Option<serde_json::Value>>;
+
+    /// Executes again an action of the history of a running watcher, returning the record of the
+    /// replay. Returns `None` when the worker cannot replay it.
+    async fn replay_action(
+        &self,
+        id: &str,
+        index: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>>;
+
+    /// Compares the stored definition of the watcher with its deployed objects, and with the
+    /// definition loaded by its worker when running.
+    async fn get_watcher_drift(&self, id: &str) -> anyhow::Result<Option<Vec<Drift>>>;
+
+    /// Reads the logs of the watcher worker, `None` when the worker is not running.
+    async fn get_watcher_logs(
+        &self,
+        id: &str,
+        query: &LogQuery,
+    ) -> anyhow::Result<Option<LogStream>>;
+
+    /// Appends an entry to the audit log of the watcher. Does nothing when the watcher does not
+    /// exist, like after its deletion.
+    async fn record_audit(&self, id: &str, entry: &AuditEntry) -> anyhow::Result<()>;
+
+    /// Reads the audit log of the watcher, oldest entries first.
+    async fn get_audit(&self, id: &str) -> anyhow::Result<Option<Vec<AuditEntry>>>;
+
+    /// Stores a revision of the watcher definition, numbered after its last revision whatever
+    /// the number it has, and returns its number. Returns `None` when the watcher does not exist.
+    async fn record_revision(&self, id: &str, revision: &Revision) -> anyhow::Result<Option<u64>>;
+
+    /// Reads the revisions of the watcher definition, oldest first.
+    async fn list_revisions(&self, id: &str) -> anyhow::Result<Option<Vec<Revision>>>;
+
+    /// Keeps the last heartbeat sent by the worker of the watcher, returns `false` when the watcher
+    /// does not exist.
+    async fn record_heartbeat(&self, id: &str, heartbeat: &Heartbeat) -> anyhow::Result<bool>;
+
+    /// Stores the events reported by the worker of the watcher, returns `false` when the watcher
+    /// does not exist. Fails with `events::NotConfigured` when the events are not kept.
+    async fn record_events(&self, id: &str, events: &[WatcherEvent]) -> anyhow::Result<bool>;
+
+    /// Reads the events of the watcher matching the query, oldest first. Fails with
+    /// `events::NotConfigured` when the events are not kept.
+    async fn list_events(
+        &self,
+        id: &str,
+        query: &EventQuery,
+    ) -> anyhow::Result<Option<Vec<WatcherEvent>>>;
+
+    /// Checks the platform running the watchers can be reached.
+    async fn healthcheck(&self) -> anyhow::Result<()>;
+
+    /// Whether the backend loaded the state of the watchers, so it can serve requests.
+    fn is_synced(&self) -> bool {
+        true
+    }
+}
+
+/// Selects the watchers returned while listing them.
+#[derive(Debug, Default, Clone)]
+pub struct ListQuery {
+    /// Maximum number of watchers returned in a single page.
+    pub limit: Option<u32>,
+    /// Token returned with the previous page.
+    pub continue_token: Option<String>,
+    /// Tags the watchers must have.
+    pub tags: Vec<(String, String)>,
+    /// Name the watchers must have.
+    pub name: Option<String>,
+    /// Whether the deleted watchers not purged yet are listed too.
+    pub include_deleted: bool,
+}
+
+/// A page of watchers.
+#[derive(Debug, Default, Clone)]
+pub struct WatcherPage {
+    pub watchers: Vec<Watcher>,
+    /// Token to fetch the next page, if any.
+    pub continue_token: Option<String>,
+}
+
+/// Selects the logs returned while reading the logs of a watcher.
+#[derive(Debug, Default, Clone)]
+pub struct LogQuery {
+    /// Keep streaming new log lines as they are written.
+    pub follow: bool,
+    /// Only return logs newer than this number of seconds.
+    pub since_seconds: Option<i64>,
+    /// Only return this number of lines from the end of the logs.
+    pub tail_lines: Option<i64>,
+}
+
+/// Result of a request to change the running status of a watcher.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StatusChange {
+    /// The watcher is moving towards the requested status.
+    Applied,
+    /// The watcher is already in the requested status.
+    Unchanged,
+    /// The watcher is transitioning between statuses and cannot be changed now.
+    Busy,
+    /// The watcher is in error and cannot be changed.
+    Refused,
+    /// The watcher is suspended and cannot be started until resumed.
+    Suspended,
+    /// The watcher does not exist.
+    NotFound,
+}
+
+/// Reports a running watcher as `stale` when its last heartbeat is too old. Watchers whose worker
+/// never sent a heartbeat, like the ones not configured to, are not reported.
+pub fn mark_stale(watcher: &mut Watcher, now: DateTime<Utc>) {
+    watcher.stale = match (watcher.status, watcher.last_heartbeat.as_ref()) {
+        (Some(Status::Running), Some(heartbeat)) => Some(heartbeat.is_stale(now)),
+        _ => None,
+    };
+}
+
+/// Seconds a name stays reserved for a watcher not having it yet, while it is created or renamed.
+const NAME_RESERVATION_SECONDS: i64 = 60;
+
+/// Whether the watcher holding the reservation of `name` since `held_since` still holds it: it
+/// has the name and is not deleted, or it is being created or renamed to it.
+pub fn holds_name(
+    holder: Option<&Watcher>,
+    name: &str,
+    held_since: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    match holder {
+        Some(watcher) if watcher.deleted_at.is_some() => false,
+        Some(watcher) if watcher.name.as_deref() == Some(name) => true,
+        _ => held_since.map_or(false, |since| {
+            now - since < chrono::Duration::seconds(NAME_RESERVATION_SECONDS)
+        }),
+    }
+}
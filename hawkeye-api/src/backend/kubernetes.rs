@@ -0,0 +1,2156 @@
+//! Runs the watchers in Kubernetes.
+//!
+//! Each watcher is made of a `ConfigMap` holding its definition, which is the source of truth
+//! for what are the watchers we have, a `Deployment` running the worker and a `Service` receiving
+//! the video feed. In the shared mode, the video feeds are received by a single `Service` of each
+//! namespace, managed by the `ports` reconciler instead. The packed watchers run in the `Deployment` of their pack,
+//! managed by the `packs` module.
+use crate::audit::{self, AuditEntry};
+use crate::auth;
+use crate::backend::{self, packs};
+use crate::backend::{ListQuery, LogQuery, LogStream, StatusChange, WatcherBackend, WatcherPage};
+use crate::cache::WatcherCache;
+use crate::config::{
+    CALL_WATCHER_TIMEOUT, INGEST_PORT_RANGE, NAMESPACE, NAMESPACES, OPERATOR_MODE,
+    POD_DISRUPTION_BUDGET, SHARED_SERVICE, WORKER_GRACE_PERIOD,
+};
+use crate::crd;
+use crate::drift::{self, Drift};
+use crate::events::{self, EventQuery};
+use crate::request_id;
+use crate::revisions::{self, Revision};
+use crate::telemetry;
+use crate::templates;
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use hawkeye_core::models::{
+    Heartbeat, Source, Status, ThresholdChange, TransitionTrigger, Watcher, WatcherEvent,
+    MAX_EXEC_TIMEOUT,
+};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::{ConfigMap, Container, Pod, Service};
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::{
+    DeleteParams, ListParams, LogParams, Patch, PatchParams, PostParams, Preconditions,
+};
+use kube::{Api, Client, ResourceExt};
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::Instrument;
+use warp::hyper::body::Bytes;
+
+/// Seconds the worker may take to upload a clip once it is recorded, as long as its own timeout.
+const CLIP_UPLOAD_SECONDS: u64 = 60;
+
+/// Runs the watchers as Kubernetes objects, reading them from the `WatcherCache` when possible.
+#[derive(Clone)]
+pub struct KubernetesBackend {
+    client: Client,
+    cache: WatcherCache,
+    /// Where the events reported by the workers are kept, when configured.
+    event_store: Option<events::Store>,
+}
+
+impl KubernetesBackend {
+    /// Starts watching the objects of the watchers in the background.
+    pub fn new(client: Client) -> Self {
+        let cache = WatcherCache::start(client.clone());
+        Self {
+            client,
+            cache,
+            event_store: None,
+        }
+    }
+
+    /// Keeps the events reported by the workers in the store.
+    pub fn with_event_store(mut self, store: events::Store) -> Self {
+        self.event_store = Some(store);
+        self
+    }
+
+    fn event_store(&self) -> anyhow::Result<&events::Store> {
+        self.event_store
+            .as_ref()
+            .ok_or_else(|| anyhow::Error::new(events::NotConfigured))
+    }
+
+    /// Finds the namespace of the watcher, `None` when the watcher does not exist.
+    async fn namespace_of(&self, id: &str) -> anyhow::Result<Option<String>> {
+        // Missing watchers are reported by the calls made in the only namespace
+        if NAMESPACES.len() == 1 {
+            return Ok(Some(NAMESPACES[0].clone()));
+        }
+        // Once synced, the cache has the ConfigMaps of all the watchers, the missing ones are
+        // not looked for in every namespace
+        if self.cache.is_synced() {
+            return Ok(self.cache.namespace_of(id));
+        }
+        if let Some(namespace) = self.cache.namespace_of(id) {
+            return Ok(Some(namespace));
+        }
+        let name = templates::configmap_name(id);
+        for namespace in NAMESPACES.iter() {
+            let config_maps: Api<ConfigMap> = Api::namespaced(self.client.clone(), namespace);
+            let config_map = config_maps.get(&name).await.map_err(anyhow::Error::from);
+            if not_found_as_none(config_map)?.is_some() {
+                return Ok(Some(namespace.clone()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl WatcherBackend for KubernetesBackend {
+    async fn list_watchers(&self, query: &ListQuery) -> anyhow::Result<WatcherPage> {
+        let mut labels = tag_labels(&query.tags);
+        if let Some(name) = query.name.as_ref() {
+            labels.push((templates::NAME_LABEL.to_string(), name.clone()));
+        }
+        // Pagination relies on the continue tokens of the API server, so only the complete list
+        // is served from the cache.
+        if query.limit.is_none() && query.continue_token.is_none() && self.cache.is_synced() {
+            let mut watchers = self.cache.watchers(&labels);
+            if !query.include_deleted {
+                watchers.retain(|w| w.deleted_at.is_none());
+            }
+            return Ok(WatcherPage {
+                watchers,
+                continue_token: None,
+            });
+        }
+
+        // The namespaces are listed one after the other, the continue token of the API server is
+        // prefixed with the namespace it belongs to.
+        let (index, continue_token) = match query.continue_token.as_deref() {
+            Some(token) => {
+                let (namespace, token) = token
+                    .split_once('/')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid continue token {}", token))?;
+                let index = NAMESPACES
+                    .iter()
+                    .position(|n| n == namespace)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown namespace {}", namespace))?;
+                (index, Some(token).filter(|t| !t.is_empty()))
+            }
+            None => (0, None),
+        };
+        let namespace = &NAMESPACES[index];
+        let mut selector = label_selector(&labels);
+        // Filtered by the API server, so the pages of the live watchers are full
+        if !query.include_deleted {
+            selector.push_str(&format!(",!{}", templates::DELETED_LABEL));
+        }
+        let mut page = list_watchers(
+            self.client.clone(),
+            namespace,
+            &selector,
+            query.limit,
+            continue_token,
+        )
+        .await?;
+        page.continue_token = match page.continue_token {
+            Some(token) => Some(format!("{}/{}", namespace, token)),
+            None => NAMESPACES.get(index + 1).map(|next| format!("{}/", next)),
+        };
+        Ok(page)
+    }
+
+    async fn get_watcher(&self, id: &str) -> anyhow::Result<Option<Watcher>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => get_watcher(self.client.clone(), &namespace, &self.cache, id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_watcher_config(&self, id: &str) -> anyhow::Result<Option<Watcher>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                not_found_as_none(get_watcher_config(self.client.clone(), &namespace, id).await)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn get_watcher_status(&self, id: &str) -> anyhow::Result<Option<Status>> {
+        let namespace = match self.namespace_of(id).await? {
+            Some(namespace) => namespace,
+            None => return Ok(None),
+        };
+        let deployments: Api<Deployment> = Api::namespaced(self.client.clone(), &namespace);
+        let deployment = deployments.get(&templates::deployment_name(id)).await;
+        match not_found_as_none(deployment.map_err(anyhow::Error::from))? {
+            Some(d) => Ok(Some(d.get_watcher_status())),
+            None => packs::get_packed_status(self.client.clone(), &namespace, id).await,
+        }
+    }
+
+    async fn create_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()> {
+        let namespace = watcher.namespace.as_deref().unwrap_or(NAMESPACE.as_str());
+        if *OPERATOR_MODE {
+            create_watcher_resource(self.client.clone(), namespace, id, watcher).await
+        } else {
+            create_watcher(self.client.clone(), namespace, id, watcher).await
+        }
+    }
+
+    async fn update_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()> {
+        let namespace = self
+            .namespace_of(id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Watcher {} does not exist", id))?;
+        // The operator applies the update and restarts the watcher
+        if *OPERATOR_MODE {
+            return replace_watcher_resource(self.client.clone(), &namespace, id, watcher).await;
+        }
+        let previous = get_watcher_config(self.client.clone(), &namespace, id).await?;
+        update_watcher(self.client.clone(), &namespace, id, watcher).await?;
+        if self.get_watcher_status(id).await? == Some(Status::Running) {
+            // The worker reloads the slates and the transitions, keeping the state of the stream
+            if !watcher.requires_restart(&previous) {
+                match reload_watcher(self.client.clone(), &namespace, id).await {
+                    Ok(true) => {
+                        tracing::debug!("Reloaded running watcher {} to apply the update", id);
+                        return Ok(());
+                    }
+                    Ok(false) => (),
+                    Err(e) => log::warn!("Could not reload watcher {}: {:?}", id, e),
+                }
+            }
+            // The worker of the pack restarts the watcher once its definition changed in the
+            // `ConfigMap` of the pack, updated with the watcher
+            if previous.pack.is_none() {
+                tracing::debug!("Restarting running watcher {} to apply the update", id);
+                restart_watcher(self.client.clone(), &namespace, id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_watcher(&self, id: &str) -> anyhow::Result<bool> {
+        let namespace = match self.namespace_of(id).await? {
+            Some(namespace) => namespace,
+            None => return Ok(false),
+        };
+        // The objects of the watcher are garbage collected with its resource
+        if *OPERATOR_MODE {
+            return Ok(delete_watcher_resource(self.client.clone(), &namespace, id)
+                .await
+                .is_ok());
+        }
+        Ok(delete_watcher(self.client.clone(), &namespace, id)
+            .await
+            .is_ok())
+    }
+
+    async fn upgrade_watcher(&self, id: &str, restart: bool) -> anyhow::Result<()> {
+        let namespace = self
+            .namespace_of(id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Watcher {} does not exist", id))?;
+        upgrade_watcher(self.client.clone(), &namespace, id, restart).await
+    }
+
+    async fn start_watcher(&self, id: &str) -> anyhow::Result<StatusChange> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => start_watcher(self.client.clone(), &namespace, id).await,
+            None => Ok(StatusChange::NotFound),
+        }
+    }
+
+    async fn stop_watcher(
+        &self,
+        id: &str,
+        grace_seconds: Option<u32>,
+    ) -> anyhow::Result<StatusChange> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                stop_watcher(self.client.clone(), &namespace, id, grace_seconds).await
+            }
+            None => Ok(StatusChange::NotFound),
+        }
+    }
+
+    async fn allocate_ingest_port(&self, source: &Source) -> anyhow::Result<Option<u32>> {
+        allocate_ingest_port(self.client.clone(), source).await
+    }
+
+    async fn find_port_conflict(
+        &self,
+        ports: &[u32],
+        exclude_id: Option<&str>,
+    ) -> anyhow::Result<Option<String>> {
+        find_port_conflict(self.client.clone(), ports, exclude_id).await
+    }
+
+    async fn reserve_name(&self, name: &str, id: &str) -> anyhow::Result<Option<String>> {
+        let config_maps: Api<ConfigMap> = Api::namespaced(self.client.clone(), &NAMESPACE);
+        let reservation_name = templates::name_reservation_name(name);
+        let reserved_at = Utc::now();
+        let reservation = templates::build_name_reservation(name, id, &reserved_at.to_rfc3339());
+        for _ in 0..NAME_RESERVATION_ATTEMPTS {
+            // Refused by the API server when the name is already reserved
+            match config_maps
+                .create(&PostParams::default(), &reservation)
+                .await
+            {
+                Ok(_) => return Ok(None),
+                Err(kube::Error::Api(response)) if response.code == 409 => (),
+                Err(e) => return Err(e.into()),
+            }
+            let current = config_maps.get(&reservation_name).await;
+            let current = match not_found_as_none(current.map_err(anyhow::Error::from))? {
+                Some(current) => current,
+                // Released meanwhile
+                None => continue,
+            };
+            let data = current.data.clone().unwrap_or_default();
+            let holder = data
+                .get(templates::RESERVATION_WATCHER_KEY)
+                .cloned()
+                .unwrap_or_default();
+            if holder == id {
+                return Ok(None);
+            }
+            let held_since = data
+                .get(templates::RESERVATION_TIME_KEY)
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc));
+            let holder_watcher = self.get_watcher_config(&holder).await?;
+            if backend::holds_name(holder_watcher.as_ref(), name, held_since, reserved_at) {
+                return Ok(Some(holder));
+            }
+            // Replacing with its resource version fails when another watcher took the name
+            // meanwhile
+            let mut taken = reservation.clone();
+            taken.metadata.resource_version = current.metadata.resource_version;
+            match config_maps
+                .replace(&reservation_name, &PostParams::default(), &taken)
+                .await
+            {
+                Ok(_) => return Ok(None),
+                Err(kube::Error::Api(response)) if response.code == 409 => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(anyhow::anyhow!("Could not reserve the name {}", name))
+    }
+
+    async fn release_name(&self, name: &str, id: &str) -> anyhow::Result<()> {
+        let config_maps: Api<ConfigMap> = Api::namespaced(self.client.clone(), &NAMESPACE);
+        let reservation_name = templates::name_reservation_name(name);
+        let current = config_maps.get(&reservation_name).await;
+        let current = match not_found_as_none(current.map_err(anyhow::Error::from))? {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        let holder = current
+            .data
+            .as_ref()
+            .and_then(|data| data.get(templates::RESERVATION_WATCHER_KEY));
+        if holder.map(String::as_str) != Some(id) {
+            return Ok(());
+        }
+        // Only deleted while unchanged, another watcher may have taken the name meanwhile
+        let dp = DeleteParams {
+            preconditions: Some(Preconditions {
+                resource_version: current.metadata.resource_version,
+                uid: None,
+            }),
+            ..DeleteParams::default()
+        };
+        match config_maps.delete(&reservation_name, &dp).await {
+            Err(kube::Error::Api(response)) if response.code == 404 || response.code == 409 => {
+                Ok(())
+            }
+            deleted => deleted.map(|_| ()).map_err(anyhow::Error::from),
+        }
+    }
+
+    async fn get_video_frame(&self, id: &str) -> anyhow::Result<Option<Bytes>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => get_video_frame(self.client.clone(), &namespace, id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_watcher_metrics(&self, id: &str) -> anyhow::Result<Option<String>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => get_watcher_metrics(self.client.clone(), &namespace, id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn reload_watcher(&self, id: &str) -> anyhow::Result<bool> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => reload_watcher(self.client.clone(), &namespace, id).await,
+            None => Ok(false),
+        }
+    }
+
+    async fn set_watcher_threshold(
+        &self,
+        id: &str,
+        change: &ThresholdChange,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                set_watcher_threshold(self.client.clone(), &namespace, id, change).await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn calibrate_watcher(
+        &self,
+        id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                calibrate_watcher(self.client.clone(), &namespace, id, seconds).await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn record_watcher(
+        &self,
+        id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => record_watcher(self.client.clone(), &namespace, id, seconds).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_watcher_state(&self, id: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => get_watcher_state(self.client.clone(), &namespace, id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn trigger_transition(
+        &self,
+        id: &str,
+        trigger: &TransitionTrigger,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                trigger_transition(self.client.clone(), &namespace, id, trigger).await
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn get_action_history(&self, id: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => get_action_history(self.client.clone(), &namespace, id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn replay_action(
+        &self,
+        id: &str,
+        index: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => replay_action(self.client.clone(), &namespace, id, index).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_watcher_logs(
+        &self,
+        id: &str,
+        query: &LogQuery,
+    ) -> anyhow::Result<Option<LogStream>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => get_watcher_logs(self.client.clone(), &namespace, id, query).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_watcher_drift(&self, id: &str) -> anyhow::Result<Option<Vec<Drift>>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                not_found_as_none(get_watcher_drift(self.client.clone(), &namespace, id).await)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn record_audit(&self, id: &str, entry: &AuditEntry) -> anyhow::Result<()> {
+        let namespace = match self.namespace_of(id).await? {
+            Some(namespace) => namespace,
+            None => return Ok(()),
+        };
+        let recorded = record_audit(self.client.clone(), &namespace, id, entry).await;
+        not_found_as_none(recorded)?;
+        Ok(())
+    }
+
+    async fn get_audit(&self, id: &str) -> anyhow::Result<Option<Vec<AuditEntry>>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                not_found_as_none(get_audit(self.client.clone(), &namespace, id).await)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn record_revision(&self, id: &str, revision: &Revision) -> anyhow::Result<Option<u64>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => not_found_as_none(
+                record_revision(self.client.clone(), &namespace, id, revision).await,
+            ),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_revisions(&self, id: &str) -> anyhow::Result<Option<Vec<Revision>>> {
+        match self.namespace_of(id).await? {
+            Some(namespace) => {
+                not_found_as_none(list_revisions(self.client.clone(), &namespace, id).await)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn record_heartbeat(&self, id: &str, heartbeat: &Heartbeat) -> anyhow::Result<bool> {
+        let namespace = match self.namespace_of(id).await? {
+            Some(namespace) => namespace,
+            None => return Ok(false),
+        };
+        let recorded = record_heartbeat(self.client.clone(), &namespace, id, heartbeat).await;
+        Ok(not_found_as_none(recorded)?.is_some())
+    }
+
+    async fn record_events(&self, id: &str, events: &[WatcherEvent]) -> anyhow::Result<bool> {
+        let store = self.event_store()?;
+        if self.get_watcher_config(id).await?.is_none() {
+            return Ok(false);
+        }
+        store.append(id, events).await?;
+        Ok(true)
+    }
+
+    async fn list_events(
+        &self,
+        id: &str,
+        query: &EventQuery,
+    ) -> anyhow::Result<Option<Vec<WatcherEvent>>> {
+        let store = self.event_store()?;
+        if self.get_watcher_config(id).await?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(store.query(id, query).await?))
+    }
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        self.client.apiserver_version().await?;
+        Ok(())
+    }
+
+    fn is_synced(&self) -> bool {
+        self.cache.is_synced()
+    }
+}
+
+/// Times the reservation of a name is attempted, when other watchers keep changing it meanwhile.
+const NAME_RESERVATION_ATTEMPTS: usize = 5;
+
+/// Turns the Kubernetes "not found" errors into `None`.
+pub(crate) fn not_found_as_none<T>(result: anyhow::Result<T>) -> anyhow::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => match e.downcast_ref::<kube::Error>() {
+            Some(kube::Error::Api(response)) if response.code == 404 => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+/// Converts the tags of the watchers into the labels of their `ConfigMap`.
+fn tag_labels(tags: &[(String, String)]) -> Vec<(String, String)> {
+    tags.iter()
+        .map(|(key, value)| (templates::tag_label(key), value.clone()))
+        .collect()
+}
+
+/// Builds the Kubernetes label selector matching the watchers with the given labels.
+fn label_selector(labels: &[(String, String)]) -> String {
+    let mut selector = String::from("app=hawkeye,watcher_id");
+    for (key, value) in labels {
+        selector.push_str(&format!(",{}={}", key, value));
+    }
+    selector
+}
+
+/// Lists a page of watchers from the API server, with their calculated status.
+#[tracing::instrument(skip_all)]
+pub async fn list_watchers(
+    client: Client,
+    namespace: &str,
+    label_selector: &str,
+    limit: Option<u32>,
+    continue_token: Option<&str>,
+) -> anyhow::Result<WatcherPage> {
+    let mut lp = ListParams::default().labels(label_selector).timeout(10);
+    if let Some(limit) = limit {
+        lp = lp.limit(limit);
+    }
+    if let Some(token) = continue_token {
+        lp = lp.continue_token(token);
+    }
+
+    // We use the ConfigMap as source of truth for what are the watchers we have, the page is
+    // defined by them.
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let config_maps = config_maps.list(&lp).await?;
+    let continue_token = config_maps.metadata.continue_.filter(|t| !t.is_empty());
+
+    let mut watchers: Vec<Watcher> = config_maps
+        .items
+        .iter()
+        .filter_map(|c| {
+            let data = c.data.as_ref()?;
+            let mut watcher: Watcher = serde_json::from_str(data.get("watcher.json")?).ok()?;
+            watcher.last_heartbeat = heartbeat_of(c);
+            Some(watcher)
+        })
+        .collect();
+    let mut deployments_index =
+        packs::packed_statuses(client.clone(), namespace, &config_maps.items).await?;
+
+    // Get only the K8S deployments of the watchers in this page, we want to return the status
+    // of each watcher
+    let ids: Vec<&str> = watchers
+        .iter()
+        .filter(|w| !w.is_packed())
+        .filter_map(|w| w.id.as_deref())
+        .collect();
+    if !ids.is_empty() {
+        let deploy_lp = ListParams::default()
+            .labels(&format!("app=hawkeye,watcher_id in ({})", ids.join(",")))
+            .timeout(10);
+        let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+        for deploy in deployments.list(&deploy_lp).await?.items {
+            let watcher_id = deploy
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get("watcher_id"))
+                .cloned();
+            if let Some(watcher_id) = watcher_id {
+                deployments_index.insert(watcher_id, deploy.get_watcher_status());
+            }
+        }
+    }
+
+    for watcher in watchers.iter_mut() {
+        let calculated_status = watcher
+            .id
+            .as_ref()
+            .and_then(|id| deployments_index.get(id))
+            .copied()
+            .unwrap_or(Status::Error);
+        watcher.status = Some(calculated_status);
+        backend::mark_stale(watcher, Utc::now());
+        // TODO: Comes from the service
+        watcher.source.ingest_ip = None;
+    }
+
+    Ok(WatcherPage {
+        watchers,
+        continue_token,
+    })
+}
+
+/// Loads a watcher with its calculated status, using the cached objects when available.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn get_watcher(
+    client: Client,
+    namespace: &str,
+    cache: &WatcherCache,
+    id: &str,
+) -> anyhow::Result<Option<Watcher>> {
+    // TODO: searching for a deployment could be a filter in this route
+    let deployment = match cache.deployment(namespace, id) {
+        Some(d) => Some(d),
+        None => {
+            let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+            let deployment = deployments.get(&templates::deployment_name(id)).await;
+            not_found_as_none(deployment.map_err(anyhow::Error::from))?
+        }
+    };
+
+    // We use the ConfigMap as source of truth for what are the watchers we have
+    let config_map = match cache.config_map(namespace, id) {
+        Some(config_map) => config_map,
+        None => {
+            let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+            let config_map = config_maps.get(&templates::configmap_name(id)).await;
+            match not_found_as_none(config_map.map_err(anyhow::Error::from))? {
+                Some(config_map) => config_map,
+                None => return Ok(None),
+            }
+        }
+    };
+    let contents = config_map
+        .data
+        .as_ref()
+        .and_then(|data| data.get("watcher.json"))
+        .ok_or_else(|| anyhow::anyhow!("ConfigMap of watcher {} has no watcher.json", id))?;
+    let mut w: Watcher = serde_json::from_str(contents)?;
+    w.last_heartbeat = heartbeat_of(&config_map);
+    // The packed watchers have no Deployment, their status comes from their pack
+    w.status = match deployment {
+        Some(d) => Some(d.get_watcher_status()),
+        None if w.is_packed() => {
+            let config_map = cache.config_map(namespace, id);
+            let pack = w
+                .pack
+                .as_deref()
+                .and_then(|p| cache.pack_deployment(namespace, p));
+            match (config_map, pack) {
+                (Some(config_map), Some(pack)) => {
+                    Some(packs::packed_status(id, &config_map, Some(&pack)))
+                }
+                (_, _) => packs::get_packed_status(client.clone(), namespace, id).await?,
+            }
+        }
+        None => return Ok(None),
+    };
+    backend::mark_stale(&mut w, Utc::now());
+
+    w.status_description = if let Some(Status::Pending) = w.status.as_ref() {
+        // Load more information why it's in pending status
+        // We get the reason the container is waiting, if available
+        let status_description = get_watcher_pod(client.clone(), namespace, id)
+            .await?
+            .and_then(|p| p.status)
+            .and_then(|ps| ps.container_statuses)
+            .and_then(|css| css.into_iter().next())
+            .and_then(|cs| cs.state)
+            .and_then(|cs| cs.waiting)
+            .and_then(|csw| csw.message);
+        tracing::debug!(
+            "Additional information for the Pending status: {:?}",
+            status_description.as_ref()
+        );
+        status_description
+    } else {
+        None
+    };
+
+    // Comes from the service, there is none when the worker connects to the sender
+    w.source.ingest_ip = if w.status != Some(Status::Error) && w.source.is_pushed() {
+        tracing::debug!("Getting ingest_ip from Service's LoadBalancer");
+        let services: Api<Service> = Api::namespaced(client, namespace);
+        let service = match SHARED_SERVICE.as_ref() {
+            Some(shared) => services.get_status(shared).await?,
+            None => services.get_status(&templates::service_name(id)).await?,
+        };
+        service
+            .status
+            .and_then(|s| s.load_balancer)
+            .and_then(|lbs| lbs.ingress)
+            .and_then(|lbs| lbs.into_iter().next())
+            .and_then(|lb| lb.hostname.or(lb.ip))
+    } else {
+        None
+    };
+
+    Ok(Some(w))
+}
+
+/// Loads the `Watcher` definition stored in the `ConfigMap`, which is the source of truth for
+/// what are the watchers we have.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn get_watcher_config(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Watcher> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let config_map = config_maps.get(&templates::configmap_name(id)).await?;
+    let contents = config_map
+        .data
+        .as_ref()
+        .and_then(|data| data.get("watcher.json"))
+        .ok_or_else(|| anyhow::anyhow!("ConfigMap of watcher {} has no watcher.json", id))?;
+    Ok(serde_json::from_str(contents)?)
+}
+
+/// Creates the `ConfigMap`, `Deployment` and `Service` objects of a new watcher. A packed watcher
+/// is assigned to a pack instead of getting a `Deployment`, its pack only runs it once started.
+///
+/// The objects are created one after the other. When one of them fails, the objects already
+/// created are deleted so no half-provisioned watcher is left behind.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn create_watcher(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<()> {
+    if watcher.source.ingest_port.is_none() {
+        return Err(anyhow::anyhow!("Watcher {} has no ingest port", id));
+    }
+
+    let mut watcher = watcher.clone();
+    // Held until the `ConfigMap` naming the pack is created
+    let _assignment = if watcher.is_packed() && watcher.pack.is_none() {
+        let assignment = packs::ASSIGNMENT.lock().await;
+        watcher.pack = Some(packs::assign_pack(client.clone(), namespace, &watcher).await?);
+        Some(assignment)
+    } else {
+        None
+    };
+    let watcher = &watcher;
+
+    // 1. Create ConfigMap
+    tracing::debug!("Creating ConfigMap instance");
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let contents = serde_json::to_string(watcher)?;
+    let mut config = templates::build_configmap(id, &contents, watcher);
+    config
+        .metadata
+        .annotations
+        .get_or_insert_with(Default::default)
+        .insert(
+            templates::CREATING_ANNOTATION.to_string(),
+            Utc::now().to_rfc3339(),
+        );
+    config_maps.create(&PostParams::default(), &config).await?;
+
+    // 2. Create Deployment with replicas=0
+    if !watcher.is_packed() {
+        tracing::debug!("Creating Deployment instance");
+        if let Err(e) = create_deployment(client.clone(), namespace, id, watcher).await {
+            rollback_watcher(client, namespace, id, false).await;
+            return Err(e.context("Failed to create the Deployment, the watcher was rolled back"));
+        }
+    }
+
+    // 3. Create Service/LoadBalancer, the shared one publishes the port otherwise. Not needed
+    // when the worker connects to the sender.
+    if SHARED_SERVICE.is_none() && watcher.source.is_pushed() {
+        tracing::debug!("Creating Service instance");
+        if let Err(e) = create_service(client.clone(), namespace, id, watcher).await {
+            rollback_watcher(client, namespace, id, !watcher.is_packed()).await;
+            return Err(e.context("Failed to create the Service, the watcher was rolled back"));
+        }
+    }
+
+    // 4. Create PodDisruptionBudget, the reconciliation creates it later when this fails. The
+    // pods of the packs run other watchers, they have none.
+    if *POD_DISRUPTION_BUDGET && !watcher.is_packed() {
+        tracing::debug!("Creating PodDisruptionBudget instance");
+        if let Err(e) = create_pdb(client, namespace, id).await {
+            log::error!(
+                "Could not create PodDisruptionBudget of watcher {}: {:?}",
+                id,
+                e
+            );
+        }
+    }
+
+    // The reconciliation takes the watcher over once the annotation expires when this fails
+    let patch = json!({
+        "metadata": {
+            "annotations": {
+                (templates::CREATING_ANNOTATION): null
+            }
+        }
+    });
+    if let Err(e) = config_maps
+        .patch(
+            &templates::configmap_name(id),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+    {
+        log::error!("Could not mark watcher {} as created: {:?}", id, e);
+    }
+
+    Ok(())
+}
+
+/// Deletes the objects of a watcher whose creation failed half-way.
+///
+/// Errors are only logged, the reconciliation reports any object left behind.
+async fn rollback_watcher(client: Client, namespace: &str, id: &str, deployment_created: bool) {
+    log::warn!("Rolling back the creation of watcher {}", id);
+    let dp = DeleteParams::default();
+
+    if deployment_created {
+        let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+        if let Err(e) = deployments
+            .delete(&templates::deployment_name(id), &dp)
+            .await
+        {
+            log::error!("Could not delete Deployment of watcher {}: {:?}", id, e);
+        }
+    }
+
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    if let Err(e) = config_maps
+        .delete(&templates::configmap_name(id), &dp)
+        .await
+    {
+        log::error!("Could not delete ConfigMap of watcher {}: {:?}", id, e);
+    }
+}
+
+/// Creates the `Deployment` running the watcher worker, with replicas=0.
+pub async fn create_deployment(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let deploy = templates::build_deployment(id, watcher);
+    deployments.create(&PostParams::default(), &deploy).await?;
+    Ok(())
+}
+
+/// Creates the `Service`/LoadBalancer receiving the video feed of the watcher.
+pub async fn create_service(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<()> {
+    let ingest_port = watcher
+        .source
+        .ingest_port
+        .ok_or_else(|| anyhow::anyhow!("Watcher {} has no ingest port", id))?;
+    let services: Api<Service> = Api::namespaced(client, namespace);
+    let svc = templates::build_service(id, ingest_port, watcher);
+    services.create(&PostParams::default(), &svc).await?;
+    Ok(())
+}
+
+/// Creates the `PodDisruptionBudget` protecting the worker from voluntary evictions.
+pub async fn create_pdb(client: Client, namespace: &str, id: &str) -> anyhow::Result<()> {
+    let pdbs: Api<PodDisruptionBudget> = Api::namespaced(client, namespace);
+    pdbs.create(&PostParams::default(), &templates::build_pdb(id))
+        .await?;
+    Ok(())
+}
+
+/// Replaces the `Watcher` definition of an existing watcher, updating the `ConfigMap`,
+/// `Deployment` and `Service` objects in place.
+///
+/// The `Service` is patched rather than recreated, so the LoadBalancer keeps its ingest address.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn update_watcher(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<()> {
+    let ingest_port = watcher
+        .source
+        .ingest_port
+        .ok_or_else(|| anyhow::anyhow!("Watcher {} has no ingest port", id))?;
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+
+    // The ConfigMap is replaced instead of patched, so tags removed from the watcher are also
+    // removed from the labels.
+    tracing::debug!("Updating ConfigMap instance");
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let mut config_map = config_maps.get(&templates::configmap_name(id)).await?;
+    config_map
+        .data
+        .get_or_insert_with(Default::default)
+        .insert("watcher.json".to_string(), serde_json::to_string(watcher)?);
+    let labels = config_map
+        .metadata
+        .labels
+        .get_or_insert_with(Default::default);
+    labels.retain(|key, _| {
+        !key.starts_with(templates::TAG_LABEL_PREFIX)
+            && key != templates::DELETED_LABEL
+            && key != templates::NAME_LABEL
+    });
+    labels.extend(templates::configmap_labels(id, watcher));
+    if watcher.deleted_at.is_some() {
+        labels.insert(templates::DELETED_LABEL.to_string(), "true".to_string());
+    }
+    config_maps
+        .replace(
+            &templates::configmap_name(id),
+            &PostParams::default(),
+            &config_map,
+        )
+        .await?;
+
+    match watcher.pack.as_deref() {
+        // The ports of the watcher are exposed by the pod of its pack
+        Some(pack) => packs::sync_pack(client.clone(), namespace, pack).await?,
+        None => {
+            // Only the pod template is replaced, replicas and the `target_status` label are
+            // preserved. Replaced rather than merged, so the node labels, tolerations and affinity
+            // removed from the watcher are also removed from the pods.
+            tracing::debug!("Updating Deployment instance");
+            let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+            let name = templates::deployment_name(id);
+            let mut existing = deployments.get(&name).await?;
+            let template = templates::build_deployment(id, watcher)
+                .spec
+                .map(|spec| spec.template)
+                .unwrap_or_default();
+            existing.spec.get_or_insert_with(Default::default).template = template;
+            deployments
+                .replace(&name, &PostParams::default(), &existing)
+                .await?;
+        }
+    }
+
+    if SHARED_SERVICE.is_some() {
+        return Ok(());
+    }
+    let services: Api<Service> = Api::namespaced(client, namespace);
+    if !watcher.source.is_pushed() || watcher.deleted_at.is_some() {
+        // The worker now connects to the sender, or the watcher is deleted, the feed is not
+        // received anymore and the LoadBalancer is released. A restored watcher gets a new one.
+        tracing::debug!("Deleting Service instance");
+        let deleted = services
+            .delete(&templates::service_name(id), &DeleteParams::default())
+            .await;
+        not_found_as_none(deleted.map_err(anyhow::Error::from))?;
+        return Ok(());
+    }
+    tracing::debug!("Updating Service instance");
+    let svc = templates::build_service(id, ingest_port, watcher);
+    let ports = svc.spec.as_ref().and_then(|spec| spec.ports.clone());
+    let svc_patch = json!({
+        "metadata": {
+            "annotations": templates::tls_annotations(&watcher.source.transport),
+        },
+        "spec": {
+            "ports": ports,
+        }
+    });
+    let patched = services
+        .patch(
+            &templates::service_name(id),
+            &patch_params,
+            &Patch::Merge(&svc_patch),
+        )
+        .await;
+    // Missing when the worker used to connect to the sender
+    if not_found_as_none(patched.map_err(anyhow::Error::from))?.is_none() {
+        services.create(&PostParams::default(), &svc).await?;
+    }
+
+    Ok(())
+}
+
+/// Triggers a rolling restart of the watcher pods, the same way `kubectl rollout restart` does.
+///
+/// Needed when only the `ConfigMap` changed, since the pod template alone would not be modified.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn restart_watcher(client: Client, namespace: &str, id: &str) -> anyhow::Result<()> {
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let restart_patch = json!({
+        "spec": {
+            "template": {
+                "metadata": {
+                    "annotations": {
+                        "kubectl.kubernetes.io/restartedAt": Utc::now().to_rfc3339(),
+                    }
+                }
+            }
+        }
+    });
+    deployments
+        .patch(
+            &templates::deployment_name(id),
+            &patch_params,
+            &Patch::Merge(&restart_patch),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Start a Watcher worker by making sure there's a positive replica count for the Kubernetes
+/// deployment.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn start_watcher(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<StatusChange> {
+    change_status(client, namespace, id, Status::Running).await
+}
+
+/// Stop a Watcher worker by making sure there's a replica count of 0 for the Kubernetes
+/// deployment.
+///
+/// The pod keeps the grace period of the deployment, which can only be shortened by deleting the
+/// pod again with the requested grace period.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn stop_watcher(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    grace_seconds: Option<u32>,
+) -> anyhow::Result<StatusChange> {
+    let change = change_status(client.clone(), namespace, id, Status::Ready).await?;
+    if let (StatusChange::Applied, Some(grace_seconds)) = (change, grace_seconds) {
+        // The pod of a pack keeps running the other watchers of the pack
+        let pod = get_watcher_pod(client.clone(), namespace, id)
+            .await?
+            .filter(|pod| pod.labels().get("watcher_id").map(String::as_str) == Some(id));
+        if let Some(pod) = pod {
+            let pods: Api<Pod> = Api::namespaced(client, namespace);
+            let dp = DeleteParams {
+                grace_period_seconds: Some(grace_seconds),
+                ..DeleteParams::default()
+            };
+            pods.delete(&pod.metadata.name.unwrap_or_default(), &dp)
+                .await?;
+        }
+    }
+    Ok(change)
+}
+
+async fn change_status(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    target: Status,
+) -> anyhow::Result<StatusChange> {
+    let deployments_client: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+
+    // Get the Kubernetes deployment for the Watcher, the packed watchers have none.
+    // TODO: probably better to just get the scale
+    let deployment = match deployments_client
+        .get(&templates::deployment_name(id))
+        .await
+    {
+        Ok(d) => d,
+        Err(_) => return packs::change_status(client, namespace, id, target).await,
+    };
+
+    // Actions and guards based on the current Watcher status.
+    let current = deployment.get_watcher_status();
+    if current == target {
+        return Ok(StatusChange::Unchanged);
+    }
+    match current {
+        Status::Pending => return Ok(StatusChange::Busy),
+        Status::Error => return Ok(StatusChange::Refused),
+        Status::Running | Status::Ready => (),
+    }
+
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+
+    // Set Kubernetes deployment replicas=1 to run, or replicas=0 to stop via patch.
+    let replicas = if target == Status::Running { 1 } else { 0 };
+    let deployment_scale_json = json!({
+        "apiVersion": "autoscaling/v1",
+        "spec": { "replicas": replicas },
+    });
+    deployments_client
+        .patch_scale(
+            deployment.metadata.name.as_ref().unwrap(),
+            &patch_params,
+            &Patch::Merge(&deployment_scale_json),
+        )
+        .await?;
+
+    // Update the status of the Watcher to indicate the status it should reach.
+    let status_label_json = json!({
+        "apiVersion": "apps/v1",
+        "metadata": {
+            "labels": {
+                "target_status": target,
+            }
+        }
+    });
+    deployments_client
+        .patch(
+            deployment.metadata.name.as_ref().unwrap(),
+            &patch_params,
+            &Patch::Merge(status_label_json),
+        )
+        .await?;
+
+    Ok(StatusChange::Applied)
+}
+
+/// Finds the lowest port in the configured range not used by any watcher, in any of
+/// the namespaces so watchers can be moved between them.
+///
+/// Returns `None` when all the ports in the range are in use.
+pub async fn allocate_ingest_port(client: Client, source: &Source) -> anyhow::Result<Option<u32>> {
+    let mut used_ports = HashSet::new();
+    for namespace in NAMESPACES.iter() {
+        used_ports.extend(used_ingest_ports(client.clone(), namespace).await?);
+    }
+    let (first, last) = *INGEST_PORT_RANGE;
+    Ok((first..=last).find(|port| {
+        source
+            .ports_from(*port)
+            .iter()
+            .all(|(_, port)| !used_ports.contains(port))
+    }))
+}
+
+/// Lists the ports used by the watchers of the namespace, either in their definition or in their
+/// `Service`.
+async fn used_ingest_ports(client: Client, namespace: &str) -> anyhow::Result<HashSet<u32>> {
+    let lp = ListParams::default().labels("app=hawkeye,watcher_id");
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let defined_ports = config_maps
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|c| {
+            let data = c.data?;
+            serde_json::from_str::<Watcher>(data.get("watcher.json")?).ok()
+        })
+        // The deleted watchers released their ports
+        .filter(|w| w.deleted_at.is_none())
+        .flat_map(|w| w.ports());
+
+    let services: Api<Service> = Api::namespaced(client, namespace);
+    Ok(services
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|svc| svc.spec.and_then(|spec| spec.ports))
+        .flatten()
+        .map(|port| port.port as u32)
+        .chain(defined_ports)
+        .collect())
+}
+
+/// Finds another watcher already using one of the ports, in any of the namespaces. Returns the ID
+/// of the conflicting watcher.
+pub async fn find_port_conflict(
+    client: Client,
+    ports: &[u32],
+    exclude_id: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    for namespace in NAMESPACES.iter() {
+        let conflict =
+            find_namespace_port_conflict(client.clone(), namespace, ports, exclude_id).await?;
+        if conflict.is_some() {
+            return Ok(conflict);
+        }
+    }
+    Ok(None)
+}
+
+/// Finds another watcher of the namespace already using one of the ports, either in its
+/// definition or in its `Service`.
+async fn find_namespace_port_conflict(
+    client: Client,
+    namespace: &str,
+    ports: &[u32],
+    exclude_id: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    let lp = ListParams::default().labels("app=hawkeye,watcher_id");
+    let is_other = |id: &String| Some(id.as_str()) != exclude_id;
+
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    for config_map in config_maps.list(&lp).await?.items {
+        let watcher = config_map
+            .data
+            .as_ref()
+            .and_then(|data| data.get("watcher.json"))
+            .and_then(|contents| serde_json::from_str::<Watcher>(contents).ok())
+            .filter(|w| w.deleted_at.is_none());
+        if let Some(watcher) = watcher {
+            let uses_port = watcher.ports().iter().any(|port| ports.contains(port));
+            match watcher.id {
+                Some(id) if uses_port && is_other(&id) => return Ok(Some(id)),
+                _ => (),
+            }
+        }
+    }
+
+    // Services can be left behind by watchers that were not fully deleted
+    let services: Api<Service> = Api::namespaced(client, namespace);
+    for svc in services.list(&lp).await?.items {
+        let id = svc
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get("watcher_id"))
+            .cloned();
+        let uses_port = svc
+            .spec
+            .and_then(|spec| spec.ports)
+            .into_iter()
+            .flatten()
+            .any(|port| ports.contains(&(port.port as u32)));
+        match id {
+            Some(id) if uses_port && is_other(&id) => return Ok(Some(id)),
+            _ => (),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Makes the `Watcher` resource the owner of the objects of the watcher, so they are garbage
+/// collected by Kubernetes when the resource is deleted.
+pub async fn set_watcher_owner(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    owner: OwnerReference,
+) -> anyhow::Result<()> {
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+    let owner_patch = json!({
+        "metadata": {
+            "ownerReferences": [owner],
+        }
+    });
+    let patch = Patch::Merge(&owner_patch);
+
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    config_maps
+        .patch(&templates::configmap_name(id), &patch_params, &patch)
+        .await?;
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    deployments
+        .patch(&templates::deployment_name(id), &patch_params, &patch)
+        .await?;
+    if SHARED_SERVICE.is_none() {
+        // Missing when the worker connects to the sender
+        let services: Api<Service> = Api::namespaced(client.clone(), namespace);
+        let patched = services
+            .patch(&templates::service_name(id), &patch_params, &patch)
+            .await;
+        not_found_as_none(patched.map_err(anyhow::Error::from))?;
+    }
+    if *POD_DISRUPTION_BUDGET {
+        let pdbs: Api<PodDisruptionBudget> = Api::namespaced(client, namespace);
+        pdbs.patch(&templates::pdb_name(id), &patch_params, &patch)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Creates the `Watcher` resource of a new watcher, the operator then creates its objects.
+pub async fn create_watcher_resource(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<()> {
+    let watchers: Api<crd::Watcher> = Api::namespaced(client, namespace);
+    let spec = crd::WatcherSpec {
+        watcher: watcher.clone(),
+    };
+    watchers
+        .create(&PostParams::default(), &crd::Watcher::new(id, spec))
+        .await?;
+    Ok(())
+}
+
+/// Replaces the spec of the `Watcher` resource, the operator then updates its objects.
+pub async fn replace_watcher_resource(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<()> {
+    let watchers: Api<crd::Watcher> = Api::namespaced(client, namespace);
+    let mut resource = watchers.get(id).await?;
+    resource.spec.watcher = watcher.clone();
+    watchers
+        .replace(id, &PostParams::default(), &resource)
+        .await?;
+    Ok(())
+}
+
+/// Deletes the `Watcher` resource, its objects are garbage collected by Kubernetes.
+pub async fn delete_watcher_resource(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<()> {
+    let watchers: Api<crd::Watcher> = Api::namespaced(client, namespace);
+    watchers.delete(id, &DeleteParams::default()).await?;
+    Ok(())
+}
+
+/// Deletes the `ConfigMap`, `Deployment` and `Service` objects of a watcher. The pack of a packed
+/// watcher stops running it.
+///
+/// Fails when the `ConfigMap` does not exist.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn delete_watcher(client: Client, namespace: &str, id: &str) -> anyhow::Result<()> {
+    let dp = DeleteParams::default();
+    let pack = get_watcher_config(client.clone(), namespace, id)
+        .await
+        .ok()
+        .and_then(|watcher| watcher.pack);
+
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let _ = deployments
+        .delete(&templates::deployment_name(id), &dp)
+        .await;
+
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let config_map_deleted = config_maps
+        .delete(&templates::configmap_name(id), &dp)
+        .await;
+
+    // The budget may exist even when disabled, if it was enabled when the watcher was created
+    let pdbs: Api<PodDisruptionBudget> = Api::namespaced(client.clone(), namespace);
+    let _ = pdbs.delete(&templates::pdb_name(id), &dp).await;
+
+    config_map_deleted?;
+    if let Some(pack) = pack {
+        packs::sync_pack(client.clone(), namespace, &pack).await?;
+    }
+    if SHARED_SERVICE.is_some() {
+        return Ok(());
+    }
+    // Missing when the worker connects to the sender
+    let services: Api<Service> = Api::namespaced(client, namespace);
+    let service_deleted = services.delete(&templates::service_name(id), &dp).await;
+    not_found_as_none(service_deleted.map_err(anyhow::Error::from))?;
+    Ok(())
+}
+
+/// Reads the audit log of the watcher, stored in an annotation of its `ConfigMap`.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn get_audit(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Vec<AuditEntry>> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let config_map = config_maps.get(&templates::configmap_name(id)).await?;
+    Ok(audit_entries(&config_map))
+}
+
+/// Times an audit entry is stored, when the `ConfigMap` of the watcher keeps changing meanwhile.
+const AUDIT_ATTEMPTS: usize = 5;
+
+/// Appends an entry to the audit log of the watcher, dropping the oldest entries beyond
+/// `audit::MAX_ENTRIES`.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn record_audit(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    entry: &AuditEntry,
+) -> anyhow::Result<()> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let mut attempt = 1;
+    loop {
+        let mut config_map = config_maps.get(&templates::configmap_name(id)).await?;
+        let mut entries = audit_entries(&config_map);
+        entries.push(entry.clone());
+        let excess = entries.len().saturating_sub(audit::MAX_ENTRIES);
+        entries.drain(..excess);
+        config_map
+            .metadata
+            .annotations
+            .get_or_insert_with(Default::default)
+            .insert(
+                templates::AUDIT_ANNOTATION.to_string(),
+                serde_json::to_string(&entries)?,
+            );
+        // Replacing with its resource version fails when the `ConfigMap` changed since, like
+        // when another entry was recorded meanwhile
+        let replaced = config_maps
+            .replace(
+                &templates::configmap_name(id),
+                &PostParams::default(),
+                &config_map,
+            )
+            .await;
+        match replaced {
+            Err(kube::Error::Api(response)) if response.code == 409 && attempt < AUDIT_ATTEMPTS => {
+                attempt += 1;
+            }
+            replaced => {
+                replaced?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Reads the revisions of the watcher, stored in a `ConfigMap` of their own since the
+/// definitions would not fit in an annotation. Fails when the watcher does not exist.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn list_revisions(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Vec<Revision>> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    config_maps.get(&templates::configmap_name(id)).await?;
+    let stored = config_maps.get(&templates::revisions_name(id)).await;
+    let stored = not_found_as_none(stored.map_err(anyhow::Error::from))?;
+    Ok(stored.as_ref().map(revision_entries).unwrap_or_default())
+}
+
+/// Appends a revision of the watcher, creating the `ConfigMap` of its revisions on its first one.
+/// Returns the number of the revision.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn record_revision(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    revision: &Revision,
+) -> anyhow::Result<u64> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let owner = config_maps.get(&templates::configmap_name(id)).await?;
+    let name = templates::revisions_name(id);
+    let stored = not_found_as_none(config_maps.get(&name).await.map_err(anyhow::Error::from))?;
+    let mut entries = stored.as_ref().map(revision_entries).unwrap_or_default();
+    let number = revisions::append(&mut entries, revision);
+    let contents = serde_json::to_string(&entries)?;
+    match stored {
+        // Replacing with its resource version fails when another change stored a revision since
+        Some(mut config_map) => {
+            config_map
+                .data
+                .get_or_insert_with(Default::default)
+                .insert(templates::REVISIONS_KEY.to_string(), contents);
+            config_maps
+                .replace(&name, &PostParams::default(), &config_map)
+                .await?;
+        }
+        None => {
+            let config_map = templates::build_revisions_configmap(id, &contents, &owner);
+            config_maps
+                .create(&PostParams::default(), &config_map)
+                .await?;
+        }
+    }
+    Ok(number)
+}
+
+fn revision_entries(config_map: &ConfigMap) -> Vec<Revision> {
+    config_map
+        .data
+        .as_ref()
+        .and_then(|data| data.get(templates::REVISIONS_KEY))
+        .and_then(|contents| serde_json::from_str(contents).ok())
+        .unwrap_or_default()
+}
+
+/// Keeps the last heartbeat of the worker in an annotation of the `ConfigMap` of the watcher.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn record_heartbeat(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    heartbeat: &Heartbeat,
+) -> anyhow::Result<()> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let patch = json!({
+        "metadata": {
+            "annotations": {
+                (templates::HEARTBEAT_ANNOTATION): serde_json::to_string(heartbeat)?
+            }
+        }
+    });
+    config_maps
+        .patch(
+            &templates::configmap_name(id),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await?;
+    Ok(())
+}
+
+/// The last heartbeat of the worker of the watcher, if any.
+pub fn heartbeat_of(config_map: &ConfigMap) -> Option<Heartbeat> {
+    config_map
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(templates::HEARTBEAT_ANNOTATION))
+        .and_then(|contents| serde_json::from_str(contents).ok())
+}
+
+fn audit_entries(config_map: &ConfigMap) -> Vec<AuditEntry> {
+    config_map
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(templates::AUDIT_ANNOTATION))
+        .and_then(|contents| serde_json::from_str(contents).ok())
+        .unwrap_or_default()
+}
+
+/// Replaces the worker container of the watcher, moving it to the configured Docker image.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn upgrade_watcher(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    restart: bool,
+) -> anyhow::Result<()> {
+    let watcher = get_watcher_config(client.clone(), namespace, id).await?;
+    if watcher.source.ingest_port.is_none() {
+        return Err(anyhow::anyhow!("Watcher {} has no ingest port", id));
+    }
+    // The pack is rolled out with the current worker, each watcher keeps its own ports
+    if let Some(pack) = watcher.pack.as_deref() {
+        return packs::sync_pack(client, namespace, pack).await;
+    }
+
+    if !restart {
+        return patch_container(client, namespace, id, &watcher).await;
+    }
+
+    // The old and new workers cannot run side by side on the same ingest port, so the worker
+    // is stopped before patching the deployment and started again afterwards, even when the
+    // upgrade failed, so it is never left stopped.
+    let upgraded = match scale_worker(client.clone(), namespace, id, 0).await {
+        Ok(()) => match wait_for_no_pods(client.clone(), namespace, id).await {
+            Ok(()) => patch_container(client.clone(), namespace, id, &watcher).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let started = scale_worker(client, namespace, id, 1).await;
+    upgraded.and(started)
+}
+
+/// Time waited for the worker to stop on top of its termination grace period, for the kubelet
+/// to kill it and the pod to be removed.
+const WORKER_STOP_MARGIN: Duration = Duration::from_secs(30);
+
+/// Sets the number of replicas of the watcher deployment, leaving the target status unchanged.
+async fn scale_worker(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    replicas: i32,
+) -> anyhow::Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+    let scale = json!({
+        "apiVersion": "autoscaling/v1",
+        "spec": { "replicas": replicas },
+    });
+    deployments
+        .patch_scale(
+            &templates::deployment_name(id),
+            &patch_params,
+            &Patch::Merge(&scale),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Waits until all the pods of the watcher are gone, the terminating ones included, for at most
+/// their termination grace period.
+///
+/// The replicas of the deployment status do not count the terminating pods, which still hold the
+/// ingest port.
+async fn wait_for_no_pods(client: Client, namespace: &str, id: &str) -> anyhow::Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let grace_seconds = deployments
+        .get(&templates::deployment_name(id))
+        .await?
+        .spec
+        .and_then(|spec| spec.template.spec)
+        .and_then(|spec| spec.termination_grace_period_seconds)
+        .unwrap_or_else(|| i64::from(*WORKER_GRACE_PERIOD));
+    let timeout = Duration::from_secs(grace_seconds.max(0) as u64) + WORKER_STOP_MARGIN;
+
+    let pods: Api<Pod> = Api::namespaced(client, namespace);
+    let lp = ListParams::default().labels(&format!("app=hawkeye,watcher_id={}", id));
+    let wait = async {
+        loop {
+            if pods.list(&lp).await?.items.is_empty() {
+                return Ok::<(), anyhow::Error>(());
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .map_err(|_| anyhow::anyhow!("Timeout while waiting for watcher {} to stop", id))?
+}
+
+/// Replaces the container of the watcher deployment with the current version of the worker.
+async fn patch_container(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let patch_params = PatchParams::default();
+    let spec_updated = json!({
+        "spec": {
+            "template": {
+                "spec": {
+                    "containers": templates::containers(id, watcher)
+                }
+            }
+        }
+    });
+    deployments
+        .patch(
+            &templates::deployment_name(id),
+            &patch_params,
+            &Patch::Apply(spec_updated),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Compares the stored definition of the watcher with its `Deployment`: the image, ports and
+/// environment of the worker container, whether the last change of its template was rolled out,
+/// and the definition loaded by the worker when running. Fails for the packed watchers, which
+/// have no `Deployment` of their own.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn get_watcher_drift(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Vec<Drift>> {
+    let watcher = get_watcher_config(client.clone(), namespace, id).await?;
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let deployment = deployments.get(&templates::deployment_name(id)).await?;
+    let mut drifts = Vec::new();
+
+    // Read back like the deployed container, with the defaults Kubernetes fills in
+    let desired: Container = serde_json::from_value(templates::container_spec(id, &watcher))?;
+    let desired = json!(desired);
+    let deployed = deployment
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.template.spec.as_ref())
+        .and_then(|spec| {
+            spec.containers
+                .iter()
+                .find(|c| c.name == templates::CONTAINER_NAME)
+        })
+        .map_or(serde_json::Value::Null, |container| json!(container));
+    for field in ["image", "ports"].iter() {
+        let (desired, deployed) = (desired[*field].clone(), deployed[*field].clone());
+        drift::compare(field, desired, deployed, &mut drifts);
+    }
+    drift::compare_env(&desired["env"], &deployed["env"], &mut drifts);
+
+    // The pods run an older template until the rollout is over
+    if let Some(status) = deployment.status.as_ref() {
+        drift::compare(
+            "generation",
+            json!(deployment.metadata.generation),
+            json!(status.observed_generation),
+            &mut drifts,
+        );
+        drift::compare(
+            "updated_replicas",
+            json!(status.replicas.unwrap_or(0)),
+            json!(status.updated_replicas.unwrap_or(0)),
+            &mut drifts,
+        );
+    }
+
+    // Older workers have no `config` endpoint, their definition is not compared
+    if let Some(bytes) = call_worker(client, namespace, id, "config").await? {
+        let running: Watcher = serde_json::from_slice(&bytes)?;
+        drift::compare_config(&watcher, &running, &mut drifts);
+    }
+    Ok(drifts)
+}
+
+/// Fetches the latest video frame from the worker, returns `None` when the worker has no frame.
+pub async fn get_video_frame(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Option<Bytes>> {
+    call_worker(client, namespace, id, "latest_frame").await
+}
+
+/// Fetches the Prometheus metrics exposed by the worker, in the text exposition format.
+pub async fn get_watcher_metrics(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Option<String>> {
+    let metrics = call_worker(client, namespace, id, "metrics").await?;
+    Ok(metrics.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Fetches the detection state exposed by the worker.
+pub async fn get_watcher_state(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    match call_worker(client, namespace, id, "state").await? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Fires the actions of transitions in the worker, returns their records.
+pub async fn trigger_transition(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    trigger: &TransitionTrigger,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let body = serde_json::to_vec(trigger)?;
+    let response = request_worker(
+        client,
+        namespace,
+        id,
+        reqwest::Method::POST,
+        "trigger",
+        Some(body),
+        // The worker only answers once the actions completed
+        Duration::from_secs(MAX_EXEC_TIMEOUT as u64 + *CALL_WATCHER_TIMEOUT),
+    )
+    .await?;
+    match response {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Fetches the last actions executed by the worker.
+pub async fn get_action_history(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    match call_worker(client, namespace, id, "actions/history").await? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Executes again an action of the history of the worker, returns the record of the replay.
+pub async fn replay_action(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    index: u64,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let response = request_worker(
+        client,
+        namespace,
+        id,
+        reqwest::Method::POST,
+        &format!("actions/{}/replay", index),
+        None,
+        // The worker only answers once the action completed, as long as an exec action at most
+        Duration::from_secs(MAX_EXEC_TIMEOUT as u64 + *CALL_WATCHER_TIMEOUT),
+    )
+    .await?;
+    match response {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Sends the stored definition of the watcher to its worker, which reloads its slates and
+/// transitions without restarting. Returns `false` when the worker cannot reload it.
+pub async fn reload_watcher(client: Client, namespace: &str, id: &str) -> anyhow::Result<bool> {
+    let watcher = get_watcher_config(client.clone(), namespace, id).await?;
+    let body = serde_json::to_vec(&watcher)?;
+    let reloaded = request_worker(
+        client,
+        namespace,
+        id,
+        reqwest::Method::POST,
+        "reload",
+        Some(body),
+        Duration::from_secs(*CALL_WATCHER_TIMEOUT),
+    )
+    .await?;
+    Ok(reloaded.is_some())
+}
+
+/// Changes the threshold of a slate in the worker, returns its comparison of the latest frame
+/// with the slate.
+pub async fn set_watcher_threshold(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    change: &ThresholdChange,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let body = serde_json::to_vec(change)?;
+    let response = request_worker(
+        client,
+        namespace,
+        id,
+        reqwest::Method::PUT,
+        "config/threshold",
+        Some(body),
+        Duration::from_secs(*CALL_WATCHER_TIMEOUT),
+    )
+    .await?;
+    match response {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Samples the similarities of the frames with the slates in the worker for `seconds`, returns
+/// their distribution with a suggested threshold for each slate.
+pub async fn calibrate_watcher(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    seconds: u64,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let response = request_worker(
+        client,
+        namespace,
+        id,
+        reqwest::Method::GET,
+        &format!("calibrate?duration={}", seconds),
+        None,
+        // The worker only answers once the calibration is over
+        Duration::from_secs(seconds + *CALL_WATCHER_TIMEOUT),
+    )
+    .await?;
+    match response {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Records `seconds` of the incoming video in the worker, returns the reference of the clip it
+/// uploaded to the archive of the watcher.
+pub async fn record_watcher(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    seconds: u64,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let response = request_worker(
+        client,
+        namespace,
+        id,
+        reqwest::Method::POST,
+        &format!("record?duration={}", seconds),
+        None,
+        // The worker only answers once the clip is recorded and uploaded
+        Duration::from_secs(seconds + CLIP_UPLOAD_SECONDS + *CALL_WATCHER_TIMEOUT),
+    )
+    .await?;
+    match response {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Calls an endpoint of the HTTP server of the worker, returns `None` when the worker cannot
+/// answer.
+async fn call_worker(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    path: &str,
+) -> anyhow::Result<Option<Bytes>> {
+    request_worker(
+        client,
+        namespace,
+        id,
+        reqwest::Method::GET,
+        path,
+        None,
+        Duration::from_secs(*CALL_WATCHER_TIMEOUT),
+    )
+    .await
+}
+
+/// Sends a request to the HTTP server of the worker, returns `None` when the worker cannot
+/// answer.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+async fn request_worker(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    method: reqwest::Method,
+    path: &str,
+    body: Option<Vec<u8>>,
+    timeout: Duration,
+) -> anyhow::Result<Option<Bytes>> {
+    let watcher = get_watcher_config(client.clone(), namespace, id).await?;
+    let pod = get_watcher_pod(client, namespace, id).await?;
+    let token = pod
+        .as_ref()
+        .and_then(|pod| templates::pod_subject(pod.labels()))
+        .map(|subject| auth::control_token(&subject));
+    let pod_ip = match pod.and_then(|p| p.status).and_then(|ps| ps.pod_ip) {
+        Some(ip) => ip,
+        None => {
+            tracing::debug!("Not able to get Pod IP");
+            return Ok(None);
+        }
+    };
+
+    let http_client = reqwest::Client::builder().timeout(timeout).build()?;
+    // Try for new and old ports in pod
+    for port in watcher.source.ingest_port.into_iter().chain(Some(3030)) {
+        let url = format!("http://{}:{}/{}", pod_ip, port, path);
+
+        log::info!("Calling Pod using url: {}", url);
+        let span = tracing::info_span!("call_pod", %url);
+        let mut request = http_client.request(method.clone(), url.as_str());
+        if let Some(body) = body.as_ref() {
+            request = request
+                .header("content-type", "application/json")
+                .body(body.clone());
+        }
+        for (name, value) in span.in_scope(telemetry::trace_headers) {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(id) = request_id::current() {
+            request = request.header(request_id::HEADER, id.as_str());
+        }
+        if let Some(token) = token.as_ref() {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().instrument(span).await?;
+        if let Ok(worker_response) = response.error_for_status() {
+            // reqwest and warp depend on different versions of `bytes`
+            let body = worker_response.bytes().await?;
+            return Ok(Some(Bytes::from(body.to_vec())));
+        }
+    }
+    log::error!("Error calling Pod using old and new urls");
+    Ok(None)
+}
+
+/// Streams the logs of the worker container, returns `None` when there is no Pod running.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn get_watcher_logs(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    query: &LogQuery,
+) -> anyhow::Result<Option<LogStream>> {
+    let pod_name = match get_watcher_pod(client.clone(), namespace, id).await? {
+        Some(pod) => pod.metadata.name.unwrap_or_default(),
+        None => {
+            tracing::debug!("No Pod found for this watcher: {}", id);
+            return Ok(None);
+        }
+    };
+
+    let log_params = LogParams {
+        container: Some(templates::CONTAINER_NAME.to_string()),
+        follow: query.follow,
+        since_seconds: query.since_seconds,
+        tail_lines: query.tail_lines,
+        ..LogParams::default()
+    };
+    let pods: Api<Pod> = Api::namespaced(client, namespace);
+    let stream = pods.log_stream(&pod_name, &log_params).await?;
+    Ok(Some(stream.map_err(anyhow::Error::from).boxed()))
+}
+
+/// Finds the Pod running the watcher worker, if any. The worker of a packed watcher runs in the
+/// Pod of its pack.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, watcher_id = %id))]
+pub async fn get_watcher_pod(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Option<Pod>> {
+    let pods_client: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    let lp = ListParams::default().labels(&format!("app=hawkeye,watcher_id={}", id));
+    if let Some(pod) = pods_client.list(&lp).await?.items.into_iter().next() {
+        return Ok(Some(pod));
+    }
+    // The pod of the pack of a packed watcher
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let config_map = config_maps.get(&templates::configmap_name(id)).await;
+    let pack = not_found_as_none(config_map.map_err(anyhow::Error::from))?
+        .and_then(|config_map| config_map.labels().get(templates::PACK_LABEL).cloned());
+    let pack = match pack {
+        Some(pack) => pack,
+        None => return Ok(None),
+    };
+    let lp =
+        ListParams::default().labels(&format!("app=hawkeye,{}={}", templates::PACK_LABEL, pack));
+    let pods = pods_client.list(&lp).await?;
+    Ok(pods.items.into_iter().next())
+}
+
+/// Calculates the `Status` of a Watcher from its Kubernetes objects.
+pub trait WatcherStatus {
+    fn get_watcher_status(&self) -> Status;
+}
+
+impl WatcherStatus for Deployment {
+    fn get_watcher_status(&self) -> Status {
+        let target_status = self
+            .metadata
+            .labels
+            .as_ref()
+            .map(|labels| {
+                labels
+                    .get("target_status")
+                    .map(|status| serde_json::from_str(&format!("\"{}\"", status)).ok())
+            })
+            .flatten()
+            .flatten()
+            .unwrap_or({
+                let name = self.metadata.name.as_ref().expect("Name must be present");
+                log::error!(
+                    "Deployment {} is missing required 'target_status' label",
+                    name
+                );
+                Status::Error
+            });
+
+        if let Some(status) = self.status.as_ref() {
+            calculated_status(status.available_replicas.unwrap_or(0) > 0, target_status)
+        } else {
+            Status::Error
+        }
+    }
+}
+
+/// Calculates the `Status` of a Watcher from whether its worker is running and the status it
+/// should reach.
+pub fn calculated_status(running: bool, target_status: Status) -> Status {
+    match (running, target_status) {
+        (true, Status::Running) => Status::Running,
+        (false, Status::Ready) => Status::Ready,
+        (false, Status::Running) => Status::Pending,
+        (true, Status::Ready) => Status::Pending,
+        (_, _) => Status::Error,
+    }
+}
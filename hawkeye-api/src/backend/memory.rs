@@ -0,0 +1,416 @@
+//! Keeps the watchers in memory, without running any worker.
+//!
+//! Useful to develop against the API without a Kubernetes cluster, and to test the handlers.
+//! Status changes are applied immediately, so watchers are never pending unless told so with
+//! `MemoryBackend::set_status`.
+use crate::audit::{self, AuditEntry};
+use crate::backend;
+use crate::backend::{ListQuery, LogQuery, LogStream, StatusChange, WatcherBackend, WatcherPage};
+use crate::config::INGEST_PORT_RANGE;
+use crate::drift::Drift;
+use crate::events::{self, EventQuery};
+use crate::revisions::{self, Revision};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hawkeye_core::models::{
+    Heartbeat, Source, Status, ThresholdChange, TransitionTrigger, Watcher, WatcherEvent,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use warp::hyper::body::Bytes;
+
+#[derive(Default)]
+pub struct MemoryBackend {
+    /// Watchers with their current status, sorted by ID like in the other backends.
+    watchers: Mutex<BTreeMap<String, (Watcher, Status)>>,
+    audit: Mutex<BTreeMap<String, Vec<AuditEntry>>>,
+    revisions: Mutex<BTreeMap<String, Vec<Revision>>>,
+    heartbeats: Mutex<BTreeMap<String, Heartbeat>>,
+    /// Last events of each watcher, as many as a query may return.
+    events: Mutex<BTreeMap<String, Vec<WatcherEvent>>>,
+    /// ID of the watcher holding each reserved name, with the time it reserved it.
+    names: Mutex<BTreeMap<String, (String, DateTime<Utc>)>>,
+    /// Watchers whose upgrades fail, like when their new pods never get ready.
+    failing_upgrades: Mutex<BTreeSet<String>>,
+}
+
+impl MemoryBackend {
+    /// Forces the status of a watcher, to simulate the states a real worker goes through.
+    #[cfg(test)]
+    pub fn set_status(&self, id: &str, status: Status) {
+        if let Some(entry) = self.watchers.lock().unwrap().get_mut(id) {
+            entry.1 = status;
+        }
+    }
+
+    /// Makes the upgrades of a watcher fail, to simulate the errors of Kubernetes.
+    #[cfg(test)]
+    pub fn fail_upgrades(&self, id: &str) {
+        self.failing_upgrades.lock().unwrap().insert(id.to_string());
+    }
+
+    fn change_status(&self, id: &str, target: Status) -> StatusChange {
+        let mut watchers = self.watchers.lock().unwrap();
+        let current = match watchers.get_mut(id) {
+            Some((_, status)) => status,
+            None => return StatusChange::NotFound,
+        };
+        if *current == target {
+            return StatusChange::Unchanged;
+        }
+        match *current {
+            Status::Pending => StatusChange::Busy,
+            Status::Error => StatusChange::Refused,
+            Status::Running | Status::Ready => {
+                *current = target;
+                StatusChange::Applied
+            }
+        }
+    }
+}
+
+fn has_tags(watcher: &Watcher, tags: &[(String, String)]) -> bool {
+    tags.iter().all(|(key, value)| {
+        watcher
+            .tags
+            .as_ref()
+            .and_then(|t| t.get(key))
+            .map(|v| v == value)
+            .unwrap_or(false)
+    })
+}
+
+fn with_status(watcher: &Watcher, status: Status, heartbeat: Option<&Heartbeat>) -> Watcher {
+    let mut watcher = watcher.clone();
+    watcher.status = Some(status);
+    watcher.last_heartbeat = heartbeat.cloned();
+    backend::mark_stale(&mut watcher, Utc::now());
+    watcher
+}
+
+#[async_trait]
+impl WatcherBackend for MemoryBackend {
+    async fn list_watchers(&self, query: &ListQuery) -> anyhow::Result<WatcherPage> {
+        let watchers = self.watchers.lock().unwrap();
+        let heartbeats = self.heartbeats.lock().unwrap();
+        // The continue token is the ID of the last watcher of the previous page
+        let mut matching = watchers
+            .iter()
+            .filter(|(id, _)| {
+                query
+                    .continue_token
+                    .as_ref()
+                    .map_or(true, |t| id.as_str() > t.as_str())
+            })
+            .filter(|(_, (w, _))| has_tags(w, &query.tags))
+            .filter(|(_, (w, _))| query.include_deleted || w.deleted_at.is_none())
+            .filter(|(_, (w, _))| query.name.is_none() || w.name == query.name)
+            .map(|(id, (w, status))| with_status(w, *status, heartbeats.get(id)));
+
+        let limit = query.limit.map(|l| l as usize).unwrap_or(usize::MAX);
+        let page: Vec<Watcher> = matching.by_ref().take(limit).collect();
+        let continue_token = if matching.next().is_some() {
+            page.last().and_then(|w| w.id.clone())
+        } else {
+            None
+        };
+        Ok(WatcherPage {
+            watchers: page,
+            continue_token,
+        })
+    }
+
+    async fn get_watcher(&self, id: &str) -> anyhow::Result<Option<Watcher>> {
+        let watchers = self.watchers.lock().unwrap();
+        let heartbeat = self.heartbeats.lock().unwrap().get(id).cloned();
+        Ok(watchers
+            .get(id)
+            .map(|(w, status)| with_status(w, *status, heartbeat.as_ref())))
+    }
+
+    async fn get_watcher_config(&self, id: &str) -> anyhow::Result<Option<Watcher>> {
+        let watchers = self.watchers.lock().unwrap();
+        Ok(watchers.get(id).map(|(w, _)| w.clone()))
+    }
+
+    async fn get_watcher_status(&self, id: &str) -> anyhow::Result<Option<Status>> {
+        let watchers = self.watchers.lock().unwrap();
+        Ok(watchers.get(id).map(|(_, status)| *status))
+    }
+
+    async fn create_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()> {
+        let mut watchers = self.watchers.lock().unwrap();
+        if watchers.contains_key(id) {
+            return Err(anyhow::anyhow!("Watcher {} already exists", id));
+        }
+        watchers.insert(id.to_string(), (watcher.clone(), Status::Ready));
+        Ok(())
+    }
+
+    async fn update_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()> {
+        let mut watchers = self.watchers.lock().unwrap();
+        match watchers.get_mut(id) {
+            Some(entry) => {
+                entry.0 = watcher.clone();
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("Watcher {} does not exist", id)),
+        }
+    }
+
+    async fn delete_watcher(&self, id: &str) -> anyhow::Result<bool> {
+        self.audit.lock().unwrap().remove(id);
+        self.revisions.lock().unwrap().remove(id);
+        self.events.lock().unwrap().remove(id);
+        self.heartbeats.lock().unwrap().remove(id);
+        Ok(self.watchers.lock().unwrap().remove(id).is_some())
+    }
+
+    async fn upgrade_watcher(&self, id: &str, _restart: bool) -> anyhow::Result<()> {
+        if self.failing_upgrades.lock().unwrap().contains(id) {
+            anyhow::bail!("The pods of watcher {} did not get ready", id);
+        }
+        match self.watchers.lock().unwrap().contains_key(id) {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("Watcher {} does not exist", id)),
+        }
+    }
+
+    async fn start_watcher(&self, id: &str) -> anyhow::Result<StatusChange> {
+        Ok(self.change_status(id, Status::Running))
+    }
+
+    async fn stop_watcher(
+        &self,
+        id: &str,
+        _grace_seconds: Option<u32>,
+    ) -> anyhow::Result<StatusChange> {
+        Ok(self.change_status(id, Status::Ready))
+    }
+
+    async fn allocate_ingest_port(&self, source: &Source) -> anyhow::Result<Option<u32>> {
+        let watchers = self.watchers.lock().unwrap();
+        let used_ports: Vec<u32> = watchers
+            .values()
+            .filter(|(w, _)| w.deleted_at.is_none())
+            .flat_map(|(w, _)| w.ports())
+            .collect();
+        let (first, last) = *INGEST_PORT_RANGE;
+        Ok((first..=last).find(|port| {
+            source
+                .ports_from(*port)
+                .iter()
+                .all(|(_, port)| !used_ports.contains(port))
+        }))
+    }
+
+    async fn find_port_conflict(
+        &self,
+        ports: &[u32],
+        exclude_id: Option<&str>,
+    ) -> anyhow::Result<Option<String>> {
+        let watchers = self.watchers.lock().unwrap();
+        Ok(watchers
+            .iter()
+            .find(|(id, (w, _))| {
+                w.deleted_at.is_none()
+                    && w.ports().iter().any(|port| ports.contains(port))
+                    && Some(id.as_str()) != exclude_id
+            })
+            .map(|(id, _)| id.clone()))
+    }
+
+    async fn reserve_name(&self, name: &str, id: &str) -> anyhow::Result<Option<String>> {
+        let watchers = self.watchers.lock().unwrap();
+        let mut names = self.names.lock().unwrap();
+        let now = Utc::now();
+        if let Some((holder, since)) = names.get(name) {
+            let holder_watcher = watchers.get(holder).map(|(w, _)| w);
+            if holder != id && backend::holds_name(holder_watcher, name, Some(*since), now) {
+                return Ok(Some(holder.clone()));
+            }
+        }
+        names.insert(name.to_string(), (id.to_string(), now));
+        Ok(None)
+    }
+
+    async fn release_name(&self, name: &str, id: &str) -> anyhow::Result<()> {
+        let mut names = self.names.lock().unwrap();
+        if names.get(name).map(|(holder, _)| holder.as_str()) == Some(id) {
+            names.remove(name);
+        }
+        Ok(())
+    }
+
+    async fn get_video_frame(&self, _id: &str) -> anyhow::Result<Option<Bytes>> {
+        // There is no worker capturing frames
+        Ok(None)
+    }
+
+    async fn get_watcher_metrics(&self, _id: &str) -> anyhow::Result<Option<String>> {
+        // There is no worker collecting metrics
+        Ok(None)
+    }
+
+    async fn reload_watcher(&self, _id: &str) -> anyhow::Result<bool> {
+        // There is no worker to reload
+        Ok(false)
+    }
+
+    async fn set_watcher_threshold(
+        &self,
+        _id: &str,
+        _change: &ThresholdChange,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        // There is no worker detecting slates
+        Ok(None)
+    }
+
+    async fn calibrate_watcher(
+        &self,
+        _id: &str,
+        _seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        // There is no worker detecting slates
+        Ok(None)
+    }
+
+    async fn record_watcher(
+        &self,
+        _id: &str,
+        _seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        // There is no worker receiving the video
+        Ok(None)
+    }
+
+    async fn get_watcher_state(&self, _id: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        // There is no worker detecting slates
+        Ok(None)
+    }
+
+    async fn trigger_transition(
+        &self,
+        _id: &str,
+        _trigger: &TransitionTrigger,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        // There is no worker executing actions
+        Ok(None)
+    }
+
+    async fn get_action_history(&self, _id: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        // There is no worker executing actions
+        Ok(None)
+    }
+
+    async fn replay_action(
+        &self,
+        _id: &str,
+        _index: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        // There is no worker executing actions
+        Ok(None)
+    }
+
+    async fn get_watcher_logs(
+        &self,
+        _id: &str,
+        _query: &LogQuery,
+    ) -> anyhow::Result<Option<LogStream>> {
+        // There is no worker writing logs
+        Ok(None)
+    }
+
+    async fn get_watcher_drift(&self, id: &str) -> anyhow::Result<Option<Vec<Drift>>> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(None);
+        }
+        // Nothing is deployed, the stored definition is the one running
+        Ok(Some(Vec::new()))
+    }
+
+    async fn record_audit(&self, id: &str, entry: &AuditEntry) -> anyhow::Result<()> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(());
+        }
+        let mut audit = self.audit.lock().unwrap();
+        let entries = audit.entry(id.to_string()).or_default();
+        entries.push(entry.clone());
+        let excess = entries.len().saturating_sub(audit::MAX_ENTRIES);
+        entries.drain(..excess);
+        Ok(())
+    }
+
+    async fn get_audit(&self, id: &str) -> anyhow::Result<Option<Vec<AuditEntry>>> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(None);
+        }
+        let audit = self.audit.lock().unwrap();
+        Ok(Some(audit.get(id).cloned().unwrap_or_default()))
+    }
+
+    async fn record_revision(&self, id: &str, revision: &Revision) -> anyhow::Result<Option<u64>> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(None);
+        }
+        let mut revisions = self.revisions.lock().unwrap();
+        let entries = revisions.entry(id.to_string()).or_default();
+        Ok(Some(revisions::append(entries, revision)))
+    }
+
+    async fn list_revisions(&self, id: &str) -> anyhow::Result<Option<Vec<Revision>>> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(None);
+        }
+        let revisions = self.revisions.lock().unwrap();
+        Ok(Some(revisions.get(id).cloned().unwrap_or_default()))
+    }
+
+    async fn record_heartbeat(&self, id: &str, heartbeat: &Heartbeat) -> anyhow::Result<bool> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(false);
+        }
+        let mut heartbeats = self.heartbeats.lock().unwrap();
+        heartbeats.insert(id.to_string(), heartbeat.clone());
+        Ok(true)
+    }
+
+    async fn record_events(&self, id: &str, reported: &[WatcherEvent]) -> anyhow::Result<bool> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(false);
+        }
+        let mut stored = self.events.lock().unwrap();
+        let kept = stored.entry(id.to_string()).or_default();
+        kept.extend_from_slice(reported);
+        // The workers may report late events after the recent ones
+        kept.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        let excess = kept.len().saturating_sub(events::MAX_LIMIT);
+        kept.drain(..excess);
+        Ok(true)
+    }
+
+    async fn list_events(
+        &self,
+        id: &str,
+        query: &EventQuery,
+    ) -> anyhow::Result<Option<Vec<WatcherEvent>>> {
+        if !self.watchers.lock().unwrap().contains_key(id) {
+            return Ok(None);
+        }
+        let stored = self.events.lock().unwrap();
+        Ok(Some(
+            stored
+                .get(id)
+                .into_iter()
+                .flatten()
+                .filter(|event| query.matches(event))
+                .take(query.limit)
+                .cloned()
+                .collect(),
+        ))
+    }
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+//! Runs the packed watchers side by side, in the worker of their pack.
+//!
+//! A packed watcher has no `Deployment` of its own. Its `ConfigMap` names its pack and holds the
+//! status it should reach. The `ConfigMap` of the pack holds the definitions of its watchers that
+//! should be running, which the worker of the pack reloads, so starting or stopping one of them
+//! leaves the others running.
+use crate::backend::kubernetes::{calculated_status, not_found_as_none};
+use crate::backend::StatusChange;
+use crate::config::{PACK_BITRATE, PACK_SIZE};
+use crate::templates;
+use hawkeye_core::models::{Status, Watcher};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams, PostParams};
+use kube::{Api, Client, ResourceExt};
+use lazy_static::lazy_static;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::Mutex;
+
+lazy_static! {
+    /// Held while a new packed watcher is assigned its pack and until its `ConfigMap` is created,
+    /// so concurrent creations do not overfill a pack.
+    pub static ref ASSIGNMENT: Mutex<()> = Mutex::new(());
+}
+
+/// The watchers of a pack and the total bitrate of their sources.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PackLoad {
+    pub watchers: usize,
+    pub bitrate_kbps: u32,
+}
+
+/// Picks the pack of a new watcher whose sources have `bitrate_kbps`: the pack with the least
+/// room left that still fits it, within `size` watchers and `capacity_kbps`, so the packs are
+/// filled before a new one is started.
+pub fn choose_pack(
+    loads: &BTreeMap<String, PackLoad>,
+    bitrate_kbps: u32,
+    size: usize,
+    capacity_kbps: u32,
+) -> String {
+    let best_fit = loads
+        .iter()
+        .filter(|(_, load)| {
+            load.watchers < size && load.bitrate_kbps + bitrate_kbps <= capacity_kbps
+        })
+        .max_by_key(|(_, load)| load.bitrate_kbps)
+        .map(|(pack, _)| pack.clone());
+    best_fit.unwrap_or_else(|| {
+        (1..)
+            .map(|n: usize| n.to_string())
+            .find(|name| !loads.contains_key(name))
+            .expect("There are free pack names")
+    })
+}
+
+/// Assigns a pack to a new watcher of the namespace, from the packs of its other watchers. To be
+/// called while holding `ASSIGNMENT`.
+pub async fn assign_pack(
+    client: Client,
+    namespace: &str,
+    watcher: &Watcher,
+) -> anyhow::Result<String> {
+    let lp =
+        ListParams::default().labels(&format!("app=hawkeye,watcher_id,{}", templates::PACK_LABEL));
+    let config_maps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let mut loads: BTreeMap<String, PackLoad> = BTreeMap::new();
+    for config_map in config_maps.list(&lp).await?.items {
+        let pack = match config_map.labels().get(templates::PACK_LABEL) {
+            Some(pack) => pack.clone(),
+            None => continue,
+        };
+        let member: Option<Watcher> = config_map
+            .data
+            .as_ref()
+            .and_then(|data| data.get("watcher.json"))
+            .and_then(|contents| serde_json::from_str(contents).ok());
+        let load = loads.entry(pack).or_default();
+        load.watchers += 1;
+        load.bitrate_kbps += member.map_or(0, |member| member.bitrate_kbps());
+    }
+    Ok(choose_pack(
+        &loads,
+        watcher.bitrate_kbps(),
+        *PACK_SIZE,
+        *PACK_BITRATE,
+    ))
+}
+
+/// The status a packed watcher should reach, from the label of its `ConfigMap`.
+fn target_status(config_map: &ConfigMap) -> Option<Status> {
+    let status = config_map.labels().get("target_status")?;
+    serde_json::from_value(json!(status)).ok()
+}
+
+/// Whether the pods of the pack run the watcher, once the pack is rolled out.
+fn runs_member(deployment: &Deployment, id: &str) -> bool {
+    let is_member = deployment
+        .annotations()
+        .get(templates::MEMBERS_ANNOTATION)
+        .map_or(false, |members| {
+            members.split(',').any(|member| member == id)
+        });
+    let rolled_out = deployment.status.as_ref().map_or(false, |status| {
+        status.observed_generation == deployment.metadata.generation
+            && status.updated_replicas == status.replicas
+            && status.available_replicas.unwrap_or(0) > 0
+    });
+    is_member && rolled_out
+}
+
+/// Calculates the `Status` of a packed watcher from its `ConfigMap` and the `Deployment` of its
+/// pack, if any.
+pub fn packed_status(id: &str, config_map: &ConfigMap, pack: Option<&Deployment>) -> Status {
+    match target_status(config_map) {
+        Some(target) => calculated_status(pack.map_or(false, |d| runs_member(d, id)), target),
+        None => {
+            log::error!(
+                "ConfigMap of packed watcher {} is missing required 'target_status' label",
+                id
+            );
+            Status::Error
+        }
+    }
+}
+
+/// Calculates the `Status` of the packed watchers of the namespace, from their `ConfigMap`.
+pub async fn packed_statuses(
+    client: Client,
+    namespace: &str,
+    config_maps: &[ConfigMap],
+) -> anyhow::Result<HashMap<String, Status>> {
+    let packed: Vec<&ConfigMap> = config_maps
+        .iter()
+        .filter(|c| c.labels().contains_key(templates::PACK_LABEL))
+        .collect();
+    if packed.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let lp = ListParams::default().labels(&format!("app=hawkeye,{}", templates::PACK_LABEL));
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let packs: HashMap<String, Deployment> = deployments
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|d| Some((d.labels().get(templates::PACK_LABEL)?.clone(), d)))
+        .collect();
+    Ok(packed
+        .into_iter()
+        .filter_map(|config_map| {
+            let id = config_map.labels().get("watcher_id")?;
+            let pack = packs.get(config_map.labels().get(templates::PACK_LABEL)?);
+            Some((id.clone(), packed_status(id, config_map, pack)))
+        })
+        .collect())
+}
+
+/// Loads the `ConfigMap` of a packed watcher with the `Deployment` of its pack. `None` when the
+/// watcher does not exist or is not packed.
+async fn get_member(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Option<(ConfigMap, String, Option<Deployment>)>> {
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let config_map = config_maps.get(&templates::configmap_name(id)).await;
+    let config_map = match not_found_as_none(config_map.map_err(anyhow::Error::from))? {
+        Some(config_map) => config_map,
+        None => return Ok(None),
+    };
+    let pack = match config_map.labels().get(templates::PACK_LABEL) {
+        Some(pack) => pack.clone(),
+        None => return Ok(None),
+    };
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let deployment = deployments
+        .get(&templates::pack_deployment_name(&pack))
+        .await;
+    let deployment = not_found_as_none(deployment.map_err(anyhow::Error::from))?;
+    Ok(Some((config_map, pack, deployment)))
+}
+
+/// Calculates the `Status` of a packed watcher, `None` when the watcher does not exist or is not
+/// packed.
+pub async fn get_packed_status(
+    client: Client,
+    namespace: &str,
+    id: &str,
+) -> anyhow::Result<Option<Status>> {
+    Ok(get_member(client, namespace, id)
+        .await?
+        .map(|(config_map, _, deployment)| packed_status(id, &config_map, deployment.as_ref())))
+}
+
+/// Starts or stops a packed watcher by changing the status it should reach, then rolls out its
+/// pack.
+pub async fn change_status(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    target: Status,
+) -> anyhow::Result<StatusChange> {
+    let (config_map, pack, deployment) = match get_member(client.clone(), namespace, id).await? {
+        Some(member) => member,
+        None => return Ok(StatusChange::NotFound),
+    };
+
+    // Same guards as the watchers running alone
+    let current = packed_status(id, &config_map, deployment.as_ref());
+    if current == target {
+        return Ok(StatusChange::Unchanged);
+    }
+    match current {
+        Status::Pending => return Ok(StatusChange::Busy),
+        Status::Error => return Ok(StatusChange::Refused),
+        Status::Running | Status::Ready => (),
+    }
+
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+    let status_label_json = json!({
+        "metadata": {
+            "labels": {
+                "target_status": target,
+            }
+        }
+    });
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    config_maps
+        .patch(
+            &templates::configmap_name(id),
+            &patch_params,
+            &Patch::Merge(status_label_json),
+        )
+        .await?;
+
+    sync_pack(client, namespace, &pack).await?;
+    Ok(StatusChange::Applied)
+}
+
+/// Makes the worker of the pack run its watchers that should be running, through the `ConfigMap`
+/// of the pack. The `Deployment` is only rolled out when the secrets of the watchers change, and
+/// is deleted with the `ConfigMap` once the pack has no watcher left.
+#[tracing::instrument(skip_all, fields(namespace = %namespace, pack = %pack))]
+pub async fn sync_pack(client: Client, namespace: &str, pack: &str) -> anyhow::Result<()> {
+    let lp = ListParams::default().labels(&format!(
+        "app=hawkeye,watcher_id,{}={}",
+        templates::PACK_LABEL,
+        pack
+    ));
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let member_config_maps = config_maps.list(&lp).await?.items;
+    let mut members: Vec<(Watcher, bool)> = member_config_maps
+        .iter()
+        .filter_map(|c| {
+            let data = c.data.as_ref()?;
+            let watcher = serde_json::from_str(data.get("watcher.json")?).ok()?;
+            Some((watcher, target_status(c) == Some(Status::Running)))
+        })
+        .collect();
+    members.sort_by(|(a, _), (b, _)| a.id.cmp(&b.id));
+    let running: Vec<&Watcher> = members
+        .iter()
+        .filter(|(_, running)| *running)
+        .map(|(watcher, _)| watcher)
+        .collect();
+    let members: Vec<&Watcher> = members.iter().map(|(watcher, _)| watcher).collect();
+
+    let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+    let name = templates::pack_deployment_name(pack);
+    let members_name = templates::pack_configmap_name(pack);
+    if member_config_maps.is_empty() {
+        tracing::debug!("Deleting the Deployment of the empty pack");
+        let deleted = deployments.delete(&name, &DeleteParams::default()).await;
+        not_found_as_none(deleted.map_err(anyhow::Error::from))?;
+        let deleted = config_maps
+            .delete(&members_name, &DeleteParams::default())
+            .await;
+        not_found_as_none(deleted.map_err(anyhow::Error::from))?;
+        return Ok(());
+    }
+
+    let members_config = templates::build_pack_configmap(pack, &running);
+    let existing = config_maps.get(&members_name).await;
+    match not_found_as_none(existing.map_err(anyhow::Error::from))? {
+        Some(mut existing) => {
+            existing.data = members_config.data;
+            config_maps
+                .replace(&members_name, &PostParams::default(), &existing)
+                .await?;
+        }
+        None => {
+            config_maps
+                .create(&PostParams::default(), &members_config)
+                .await?;
+        }
+    }
+
+    // The pod template is the same when only the running watchers changed, the new spec does not
+    // roll out the pack then
+    let deployment = templates::build_pack_deployment(pack, &members, &running);
+    let existing = deployments.get(&name).await;
+    match not_found_as_none(existing.map_err(anyhow::Error::from))? {
+        Some(mut existing) => {
+            existing.spec = deployment.spec;
+            existing
+                .metadata
+                .annotations
+                .get_or_insert_with(Default::default)
+                .extend(deployment.metadata.annotations.unwrap_or_default());
+            deployments
+                .replace(&name, &PostParams::default(), &existing)
+                .await?;
+        }
+        None => {
+            tracing::debug!("Creating the Deployment of the pack");
+            deployments
+                .create(&PostParams::default(), &deployment)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loads(packs: &[(&str, usize, u32)]) -> BTreeMap<String, PackLoad> {
+        packs
+            .iter()
+            .map(|(pack, watchers, bitrate_kbps)| {
+                let load = PackLoad {
+                    watchers: *watchers,
+                    bitrate_kbps: *bitrate_kbps,
+                };
+                (pack.to_string(), load)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fills_the_fullest_pack_first() {
+        let loads = loads(&[("1", 8, 8_000), ("2", 3, 3_000), ("3", 5, 5_000)]);
+
+        assert_eq!(choose_pack(&loads, 1_000, 8, 20_000), "3");
+        assert_eq!(choose_pack(&loads, 1_000, 5, 20_000), "2");
+        assert_eq!(choose_pack(&loads, 1_000, 3, 20_000), "4");
+        assert_eq!(choose_pack(&BTreeMap::new(), 1_000, 8, 20_000), "1");
+    }
+
+    #[test]
+    fn bins_the_watchers_by_bitrate() {
+        let loads = loads(&[("1", 2, 18_000), ("2", 6, 6_000), ("3", 1, 12_000)]);
+
+        // The pack with the least bandwidth left that fits the watcher
+        assert_eq!(choose_pack(&loads, 2_000, 8, 20_000), "1");
+        assert_eq!(choose_pack(&loads, 5_000, 8, 20_000), "3");
+        assert_eq!(choose_pack(&loads, 10_000, 8, 20_000), "2");
+        assert_eq!(choose_pack(&loads, 15_000, 8, 20_000), "4");
+    }
+}
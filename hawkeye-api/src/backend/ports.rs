@@ -0,0 +1,348 @@
+//! Publishes the ingest ports of all watchers on a single shared `Service`.
+//!
+//! In the shared mode the watchers don't get a `Service`/LoadBalancer each. A pre-provisioned
+//! `Service` without selector, of the same name in each namespace, receives the video feeds
+//! instead, and the `ports` reconciler keeps its ports and `Endpoints` in line with the watchers
+//! of the namespace: every ingest port is routed to the pod of the watcher owning it, the pod of
+//! its pack for a packed watcher.
+use crate::backend::kubernetes::not_found_as_none;
+use crate::config::{NAMESPACES, SHARED_SERVICE};
+use crate::templates;
+use futures::StreamExt;
+use hawkeye_core::models::Watcher;
+use k8s_openapi::api::core::v1::{ConfigMap, Endpoints, Pod, Service};
+use kube::api::{ListParams, Patch, PatchParams, PostParams};
+use kube::runtime::watcher;
+use kube::{Api, Client, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How often the ports of the shared `Service` are reconciled without any change of the
+/// watchers, in case a change was missed by the watch streams.
+const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Port of the shared `Service` while no watcher is published on it, since a `Service` needs
+/// at least one. It has no `Endpoints`, so nothing is received on it.
+const PLACEHOLDER_PORT: u32 = 1;
+
+/// Ingest ports published by the watchers: the watcher owning each port, with its protocol and
+/// the port of the pod receiving the feed.
+type PublishedPorts = BTreeMap<u32, (String, &'static str, u32)>;
+
+/// Reconciles the ports of the shared `Service` in the background, when enabled: as soon as the
+/// pods or the `ConfigMap`s of the watchers change, since the address of a worker changes every
+/// time it is started, and every `SYNC_INTERVAL`.
+pub fn spawn(client: Client) {
+    let service = match SHARED_SERVICE.as_ref() {
+        Some(service) => service.clone(),
+        None => return,
+    };
+    log::info!(
+        "Publishing the ingest ports on the shared Service {}",
+        service
+    );
+    let changed = Arc::new(Notify::new());
+    for namespace in NAMESPACES.iter() {
+        notify_changes(
+            Api::<Pod>::namespaced(client.clone(), namespace),
+            "app=hawkeye",
+            changed.clone(),
+        );
+        notify_changes(
+            Api::<ConfigMap>::namespaced(client.clone(), namespace),
+            "app=hawkeye,watcher_id",
+            changed.clone(),
+        );
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = changed.notified() => (),
+            }
+            for namespace in NAMESPACES.iter() {
+                if let Err(e) = reconcile(client.clone(), namespace, &service).await {
+                    log::error!(
+                        "Error while reconciling the ports of {} in {}: {:?}",
+                        service,
+                        namespace,
+                        e
+                    );
+                }
+            }
+        }
+    });
+}
+
+/// Notifies `changed` of every change of the objects matching the label selector. The changes
+/// made during a reconciliation pass are handled by a single next one.
+fn notify_changes<K>(api: Api<K>, label_selector: &str, changed: Arc<Notify>)
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + 'static,
+{
+    let stream = watcher(api, ListParams::default().labels(label_selector));
+    tokio::spawn(async move {
+        stream
+            .for_each(|event| {
+                match event {
+                    Ok(_) => changed.notify_one(),
+                    Err(e) => log::error!("Error while watching Kubernetes objects: {:?}", e),
+                }
+                futures::future::ready(())
+            })
+            .await;
+        log::error!("Kubernetes watch stream ended, the ports are only reconciled periodically");
+    });
+}
+
+/// Runs a single reconciliation pass of the ports and `Endpoints` of the shared `Service` of the
+/// namespace.
+pub async fn reconcile(client: Client, namespace: &str, service: &str) -> anyhow::Result<()> {
+    let lp = ListParams::default().labels("app=hawkeye,watcher_id");
+
+    let mut ports = PublishedPorts::new();
+    // Watchers of each pack of the namespace
+    let mut members: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    for config_map in config_maps.list(&lp).await?.items {
+        let labels = config_map.labels();
+        if let (Some(pack), Some(id)) =
+            (labels.get(templates::PACK_LABEL), labels.get("watcher_id"))
+        {
+            members.entry(pack.clone()).or_default().push(id.clone());
+        }
+        let watcher = config_map
+            .data
+            .as_ref()
+            .and_then(|data| data.get("watcher.json"))
+            .and_then(|contents| serde_json::from_str::<Watcher>(contents).ok());
+        if let Some(watcher) = watcher {
+            publish(&watcher, &mut ports);
+        }
+    }
+
+    // Address and name of the running pod of each watcher
+    let mut addresses: BTreeMap<String, (String, String)> = BTreeMap::new();
+    let pods: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    for pod in pods.list(&lp).await?.items {
+        if let (Some(id), Some(ip)) = (pod.labels().get("watcher_id"), running_ip(&pod)) {
+            addresses.insert(id.clone(), (ip, pod.name()));
+        }
+    }
+    let pack_lp = ListParams::default().labels(&format!("app=hawkeye,{}", templates::PACK_LABEL));
+    for pod in pods.list(&pack_lp).await?.items {
+        let pack_members = pod
+            .labels()
+            .get(templates::PACK_LABEL)
+            .and_then(|pack| members.get(pack));
+        if let (Some(ip), Some(pack_members)) = (running_ip(&pod), pack_members) {
+            for id in pack_members {
+                addresses.insert(id.clone(), (ip.clone(), pod.name()));
+            }
+        }
+    }
+
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+
+    // A JSON merge patch replaces the whole list, so the ports of the deleted watchers are
+    // removed, unlike a strategic merge patch merging the ports by their number
+    let services: Api<Service> = Api::namespaced(client.clone(), namespace);
+    let svc_patch = json!({
+        "spec": {
+            "ports": service_ports(&ports),
+        }
+    });
+    services
+        .patch(service, &patch_params, &Patch::Merge(&svc_patch))
+        .await?;
+
+    let endpoints: Api<Endpoints> = Api::namespaced(client, namespace);
+    let endpoints_patch = json!({ "subsets": endpoint_subsets(&ports, &addresses, namespace) });
+    let patched = endpoints
+        .patch(service, &patch_params, &Patch::Merge(&endpoints_patch))
+        .await
+        .map_err(anyhow::Error::from);
+    if not_found_as_none(patched)?.is_none() {
+        tracing::debug!(
+            "Creating the Endpoints of the shared Service {} in {}",
+            service,
+            namespace
+        );
+        let mut new_endpoints: Endpoints = serde_json::from_value(endpoints_patch)?;
+        new_endpoints.metadata.name = Some(service.to_string());
+        endpoints
+            .create(&PostParams::default(), &new_endpoints)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Address of the pod while it is running.
+fn running_ip(pod: &Pod) -> Option<String> {
+    pod.status
+        .as_ref()
+        .filter(|s| s.phase.as_deref() == Some("Running"))
+        .and_then(|s| s.pod_ip.clone())
+}
+
+/// Adds the ports the watcher receives its feeds on, when they are pushed to it.
+fn publish(watcher: &Watcher, ports: &mut PublishedPorts) {
+    let source = &watcher.source;
+    let id = match watcher.id.as_ref() {
+        Some(id) if source.is_pushed() => id,
+        // The workers connecting to their sender receive nothing on the port
+        _ => return,
+    };
+    for backup in watcher
+        .backup_sources
+        .iter()
+        .flatten()
+        .filter(|b| b.is_pushed())
+    {
+        for port in backup.ports() {
+            ports.insert(port, (id.clone(), "UDP", port));
+        }
+    }
+    if let Some(port) = source.ingest_port {
+        let (protocol, target_port) = templates::ingest_target(port, &source.transport);
+        // The FEC streams, second leg or RTCP of RIST
+        for (_, other) in source.ports_from(port).into_iter().skip(1) {
+            ports.insert(other, (id.clone(), "UDP", other));
+        }
+        ports.insert(port, (id.clone(), protocol, target_port));
+    }
+}
+
+/// Ports of the shared `Service`, the `PLACEHOLDER_PORT` when no watcher is published.
+fn service_ports(ports: &PublishedPorts) -> Vec<Value> {
+    if ports.is_empty() {
+        return vec![json!({
+            "name": "placeholder",
+            "protocol": "TCP",
+            "port": PLACEHOLDER_PORT,
+        })];
+    }
+    ports
+        .iter()
+        .map(|(port, (_, protocol, target_port))| {
+            json!({
+                "name": port_name(*port),
+                "protocol": protocol,
+                "port": port,
+                "targetPort": target_port,
+            })
+        })
+        .collect()
+}
+
+/// Subsets of the `Endpoints` of the shared `Service`, routing each port to the running pod of
+/// its watcher. The ports of the watchers without one are left out.
+fn endpoint_subsets(
+    ports: &PublishedPorts,
+    addresses: &BTreeMap<String, (String, String)>,
+    namespace: &str,
+) -> Vec<Value> {
+    ports
+        .iter()
+        .filter_map(|(port, (id, protocol, target_port))| {
+            let (ip, pod) = addresses.get(id)?;
+            Some(json!({
+                "addresses": [{
+                    "ip": ip,
+                    "targetRef": {
+                        "kind": "Pod",
+                        "name": pod,
+                        "namespace": namespace,
+                    },
+                }],
+                "ports": [{
+                    "name": port_name(*port),
+                    "protocol": protocol,
+                    "port": target_port,
+                }],
+            }))
+        })
+        .collect()
+}
+
+/// Names the port of the shared `Service` receiving the given ingest port.
+fn port_name(port: u32) -> String {
+    format!("ingest-{}", port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watcher(id: &str, source: Value) -> Watcher {
+        let mut watcher: Watcher =
+            serde_json::from_str(include_str!("../../../fixtures/watcher.json")).unwrap();
+        watcher.id = Some(id.to_string());
+        watcher.source = serde_json::from_value(source).unwrap();
+        watcher
+    }
+
+    #[test]
+    fn publishes_the_ports_of_the_pushed_sources() {
+        let mut ports = PublishedPorts::new();
+        publish(
+            &watcher(
+                "rtp",
+                json!({
+                    "ingest_port": 5000,
+                    "container": "mpeg-ts",
+                    "codec": "h264",
+                    "transport": {"protocol": "rtp", "fec": "1d"}
+                }),
+            ),
+            &mut ports,
+        );
+        publish(
+            &watcher(
+                "hls",
+                json!({
+                    "ingest_port": 5010,
+                    "container": "mpeg-ts",
+                    "codec": "h264",
+                    "transport": {"protocol": "hls", "url": "https://example.com/live.m3u8"}
+                }),
+            ),
+            &mut ports,
+        );
+        assert_eq!(ports.keys().collect::<Vec<_>>(), vec![&5000, &5002]);
+        assert_eq!(ports[&5002], ("rtp".to_string(), "UDP", 5002));
+
+        let mut addresses = BTreeMap::new();
+        addresses.insert(
+            "rtp".to_string(),
+            ("10.0.0.7".to_string(), "hawkeye-deployment-rtp".to_string()),
+        );
+        let subsets = endpoint_subsets(&ports, &addresses, "sports");
+        assert_eq!(subsets.len(), 2);
+        assert_eq!(subsets[0]["addresses"][0]["ip"], "10.0.0.7");
+        assert_eq!(
+            subsets[0]["addresses"][0]["targetRef"]["namespace"],
+            "sports"
+        );
+        assert_eq!(subsets[1]["ports"][0]["name"], "ingest-5002");
+
+        // The ports of the watchers without a running pod are published without endpoints
+        assert!(endpoint_subsets(&ports, &BTreeMap::new(), "sports").is_empty());
+        assert_eq!(service_ports(&ports).len(), 2);
+    }
+
+    #[test]
+    fn keeps_a_placeholder_port_without_watchers() {
+        let ports = service_ports(&PublishedPorts::new());
+        assert_eq!(ports.len(), 1);
+        assert_eq!(ports[0]["port"], PLACEHOLDER_PORT);
+        assert!(endpoint_subsets(&PublishedPorts::new(), &BTreeMap::new(), "sports").is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::backend::kubernetes::{self, WatcherStatus};
+use crate::backend::{self, packs};
+use crate::config::{NAMESPACE, NAMESPACES};
+use crate::templates;
+use chrono::Utc;
+use futures::StreamExt;
+use hawkeye_core::models::{Status, Watcher};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::ListParams;
+use kube::runtime::reflector::{self, ObjectRef, Store};
+use kube::runtime::watcher;
+use kube::{Api, Client, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// In-memory copy of the Kubernetes objects of all watchers, kept up to date by watch streams.
+///
+/// Handlers read from the cache to avoid listing objects in the API server on every request,
+/// falling back to the API server when the cache is not synced yet or misses an object.
+#[derive(Clone)]
+pub struct WatcherCache {
+    /// The objects of each of the managed namespaces.
+    namespaces: Vec<NamespaceCache>,
+}
+
+#[derive(Clone)]
+struct NamespaceCache {
+    namespace: String,
+    deployments: Store<Deployment>,
+    config_maps: Store<ConfigMap>,
+    deployments_synced: Arc<AtomicBool>,
+    config_maps_synced: Arc<AtomicBool>,
+}
+
+impl WatcherCache {
+    /// Starts watching the watcher objects in the background.
+    pub fn start(client: Client) -> Self {
+        let namespaces = NAMESPACES
+            .iter()
+            .map(|namespace| {
+                // The Deployments of the packs have no watcher_id
+                let (deployments, deployments_synced) = spawn_reflector(
+                    Api::<Deployment>::namespaced(client.clone(), namespace),
+                    "app=hawkeye",
+                );
+                let (config_maps, config_maps_synced) = spawn_reflector(
+                    Api::<ConfigMap>::namespaced(client.clone(), namespace),
+                    "app=hawkeye,watcher_id",
+                );
+                NamespaceCache {
+                    namespace: namespace.clone(),
+                    deployments,
+                    config_maps,
+                    deployments_synced,
+                    config_maps_synced,
+                }
+            })
+            .collect();
+        Self { namespaces }
+    }
+
+    /// Whether the initial list of objects was loaded into the cache.
+    pub fn is_synced(&self) -> bool {
+        self.namespaces.iter().all(|n| {
+            n.deployments_synced.load(Ordering::SeqCst)
+                && n.config_maps_synced.load(Ordering::SeqCst)
+        })
+    }
+
+    pub fn deployment(&self, namespace: &str, id: &str) -> Option<Deployment> {
+        let object = ObjectRef::new(&templates::deployment_name(id)).within(namespace);
+        self.namespaces
+            .iter()
+            .find(|n| n.namespace == namespace)
+            .and_then(|n| n.deployments.get(&object))
+    }
+
+    pub fn pack_deployment(&self, namespace: &str, pack: &str) -> Option<Deployment> {
+        let object = ObjectRef::new(&templates::pack_deployment_name(pack)).within(namespace);
+        self.namespaces
+            .iter()
+            .find(|n| n.namespace == namespace)
+            .and_then(|n| n.deployments.get(&object))
+    }
+
+    pub fn config_map(&self, namespace: &str, id: &str) -> Option<ConfigMap> {
+        let object = ObjectRef::new(&templates::configmap_name(id)).within(namespace);
+        self.namespaces
+            .iter()
+            .find(|n| n.namespace == namespace)
+            .and_then(|n| n.config_maps.get(&object))
+    }
+
+    /// Finds the namespace of a cached watcher.
+    pub fn namespace_of(&self, id: &str) -> Option<String> {
+        self.namespaces
+            .iter()
+            .find(|n| self.config_map(&n.namespace, id).is_some())
+            .map(|n| n.namespace.clone())
+    }
+
+    /// Lists the watchers having all the given labels, with their calculated status.
+    pub fn watchers(&self, labels: &[(String, String)]) -> Vec<Watcher> {
+        let now = Utc::now();
+        let mut watchers: Vec<Watcher> = self
+            .namespaces
+            .iter()
+            .flat_map(|n| n.config_maps.state())
+            .filter(|c| {
+                let config_labels = match c.metadata.labels.as_ref() {
+                    Some(l) => l,
+                    None => return false,
+                };
+                config_labels.get("app").map(String::as_str) == Some("hawkeye")
+                    && config_labels.contains_key("watcher_id")
+                    && labels
+                        .iter()
+                        .all(|(key, value)| config_labels.get(key) == Some(value))
+            })
+            .filter_map(|c| {
+                let mut watcher =
+                    serde_json::from_str::<Watcher>(c.data.as_ref()?.get("watcher.json")?).ok()?;
+                watcher.status = Some(self.status_of(&watcher, &c));
+                watcher.last_heartbeat = kubernetes::heartbeat_of(&c);
+                backend::mark_stale(&mut watcher, now);
+                Some(watcher)
+            })
+            .collect();
+
+        for watcher in watchers.iter_mut() {
+            // TODO: Comes from the service
+            watcher.source.ingest_ip = None;
+        }
+        watchers.sort_by(|a, b| a.id.cmp(&b.id));
+        watchers
+    }
+
+    /// Calculates the status of a cached watcher from its `Deployment`, or the one of its pack.
+    fn status_of(&self, watcher: &Watcher, config_map: &ConfigMap) -> Status {
+        let namespace = watcher
+            .namespace
+            .clone()
+            .unwrap_or_else(|| NAMESPACE.clone());
+        let id = match watcher.id.as_ref() {
+            Some(id) => id,
+            None => return Status::Error,
+        };
+        match config_map.labels().get(templates::PACK_LABEL) {
+            Some(pack) => {
+                let deployment = self.pack_deployment(&namespace, pack);
+                packs::packed_status(id, config_map, deployment.as_ref())
+            }
+            None => self
+                .deployment(&namespace, id)
+                .map(|d| d.get_watcher_status())
+                .unwrap_or(Status::Error),
+        }
+    }
+}
+
+/// Keeps a `Store` up to date with the objects of the watchers matching the label selector,
+/// restarting the watch stream on errors. The returned flag is set once the initial list of
+/// objects is loaded.
+fn spawn_reflector<K>(api: Api<K>, label_selector: &str) -> (Store<K>, Arc<AtomicBool>)
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+    K::DynamicType: Default + Eq + std::hash::Hash + Clone,
+{
+    let writer = reflector::store::Writer::<K>::default();
+    let store = writer.as_reader();
+    let synced = Arc::new(AtomicBool::new(false));
+
+    let lp = ListParams::default().labels(label_selector);
+    let stream = reflector::reflector(writer, watcher(api, lp));
+    let is_synced = synced.clone();
+    tokio::spawn(async move {
+        stream
+            .for_each(|event| {
+                match event {
+                    Ok(watcher::Event::Restarted(_)) => is_synced.store(true, Ordering::SeqCst),
+                    Ok(_) => (),
+                    Err(e) => log::error!("Error while watching Kubernetes objects: {:?}", e),
+                }
+                futures::future::ready(())
+            })
+            .await;
+        log::error!("Kubernetes watch stream ended, the cache is no longer updated");
+        is_synced.store(false, Ordering::SeqCst);
+    });
+
+    (store, synced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::apps::v1::DeploymentStatus;
+    use kube::runtime::reflector::store::Writer;
+
+    fn namespace_cache(
+        namespace: &str,
+        config_maps: Vec<ConfigMap>,
+        deployments: Vec<Deployment>,
+    ) -> NamespaceCache {
+        let mut config_maps_writer = Writer::<ConfigMap>::default();
+        let mut deployments_writer = Writer::<Deployment>::default();
+        let cache = NamespaceCache {
+            namespace: namespace.to_string(),
+            deployments: deployments_writer.as_reader(),
+            config_maps: config_maps_writer.as_reader(),
+            deployments_synced: Arc::new(AtomicBool::new(true)),
+            config_maps_synced: Arc::new(AtomicBool::new(true)),
+        };
+        config_maps_writer.apply_watcher_event(&watcher::Event::Restarted(config_maps));
+        deployments_writer.apply_watcher_event(&watcher::Event::Restarted(deployments));
+        cache
+    }
+
+    #[test]
+    fn finds_the_watchers_of_other_namespaces() {
+        let id = "ee21fc9a-7225-450b-a2a7-2faf914e35b8";
+        let mut watcher: Watcher =
+            serde_json::from_str(include_str!("../../fixtures/watcher.json")).unwrap();
+        watcher.namespace = Some("sports".to_string());
+        let contents = serde_json::to_string(&watcher).unwrap();
+        let mut config_map = templates::build_configmap(id, &contents, &watcher);
+        config_map.metadata.namespace = Some("sports".to_string());
+        let mut deployment = templates::build_deployment(id, &watcher);
+        deployment.metadata.namespace = Some("sports".to_string());
+        deployment.status = Some(DeploymentStatus::default());
+
+        let cache = WatcherCache {
+            namespaces: vec![
+                namespace_cache("default", Vec::new(), Vec::new()),
+                namespace_cache("sports", vec![config_map], vec![deployment]),
+            ],
+        };
+        assert!(cache.is_synced());
+        assert_eq!(cache.namespace_of(id), Some("sports".to_string()));
+        assert_eq!(cache.namespace_of("missing"), None);
+        assert!(cache.config_map("default", id).is_none());
+        assert!(cache.deployment("sports", id).is_some());
+
+        // The status comes from the Deployment of the namespace of the watcher
+        let watchers = cache.watchers(&[]);
+        assert_eq!(watchers.len(), 1);
+        assert_eq!(watchers[0].namespace.as_deref(), Some("sports"));
+        assert_eq!(watchers[0].status, Some(Status::Ready));
+    }
+}
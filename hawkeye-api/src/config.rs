@@ -1,30 +1,354 @@
+use crate::auth::{Grant, Scope};
+use hawkeye_core::models::Scheduling;
 use lazy_static::lazy_static;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use std::collections::HashMap;
 use std::iter;
 
 // Environment variable names
 const NAMESPACE_ENV: &str = "HAWKEYE_NAMESPACE";
+const NAMESPACES_ENV: &str = "HAWKEYE_NAMESPACES";
 const DOCKER_IMAGE_ENV: &str = "HAWKEYE_DOCKER_IMAGE";
+const WORKER_IMAGES_ENV: &str = "HAWKEYE_WORKER_IMAGES";
 const FIXED_TOKEN_ENV: &str = "HAWKEYE_FIXED_TOKEN";
 const CALL_WATCHER_TIMEOUT_ENV: &str = "HAWKEYE_CALL_WATCHER_TIMEOUT_TOKEN";
+const API_KEYS_ENV: &str = "HAWKEYE_API_KEYS";
+const JWKS_URL_ENV: &str = "HAWKEYE_JWKS_URL";
+const JWT_AUDIENCE_ENV: &str = "HAWKEYE_JWT_AUDIENCE";
+const JWT_ISSUER_ENV: &str = "HAWKEYE_JWT_ISSUER";
+const OIDC_ISSUER_ENV: &str = "HAWKEYE_OIDC_ISSUER";
+const OIDC_CLIENT_ID_ENV: &str = "HAWKEYE_OIDC_CLIENT_ID";
+const OIDC_CLIENT_SECRET_ENV: &str = "HAWKEYE_OIDC_CLIENT_SECRET";
+const OIDC_REDIRECT_URL_ENV: &str = "HAWKEYE_OIDC_REDIRECT_URL";
+const OIDC_GROUPS_CLAIM_ENV: &str = "HAWKEYE_OIDC_GROUPS_CLAIM";
+const OIDC_GROUP_ROLES_ENV: &str = "HAWKEYE_OIDC_GROUP_ROLES";
+const INGEST_PORT_RANGE_ENV: &str = "HAWKEYE_INGEST_PORT_RANGE";
+const RECONCILE_INTERVAL_ENV: &str = "HAWKEYE_RECONCILE_INTERVAL";
+const OPERATOR_MODE_ENV: &str = "HAWKEYE_OPERATOR_MODE";
+const BACKEND_ENV: &str = "HAWKEYE_BACKEND";
+const WORKER_SCHEDULING_ENV: &str = "HAWKEYE_WORKER_SCHEDULING";
+const WORKER_GRACE_PERIOD_ENV: &str = "HAWKEYE_WORKER_GRACE_PERIOD";
+const WORKER_SERVICE_ACCOUNT_ENV: &str = "HAWKEYE_WORKER_SERVICE_ACCOUNT";
+const EXEC_ALLOWLIST_ENV: &str = "HAWKEYE_EXEC_ALLOWLIST";
+const ACTION_SECRETS_ENV: &str = "HAWKEYE_ACTION_SECRETS";
+const STALL_TIMEOUT_ENV: &str = "HAWKEYE_STALL_TIMEOUT";
+const STALL_ACTION_ENV: &str = "HAWKEYE_STALL_ACTION";
+const POD_DISRUPTION_BUDGET_ENV: &str = "HAWKEYE_POD_DISRUPTION_BUDGET";
+const SHARED_SERVICE_ENV: &str = "HAWKEYE_SHARED_SERVICE";
+const WEBHOOK_URLS_ENV: &str = "HAWKEYE_WEBHOOK_URLS";
+const WEBHOOK_SECRET_ENV: &str = "HAWKEYE_WEBHOOK_SECRET";
+const SNS_TOPIC_ARN_ENV: &str = "HAWKEYE_SNS_TOPIC_ARN";
+const DEAD_LETTER_FILE_ENV: &str = "HAWKEYE_DEAD_LETTER_FILE";
+const OTLP_ENDPOINT_ENV: &str = "HAWKEYE_OTLP_ENDPOINT";
+const RATE_LIMIT_ENV: &str = "HAWKEYE_RATE_LIMIT";
+const RATE_LIMIT_BURST_ENV: &str = "HAWKEYE_RATE_LIMIT_BURST";
+const MAX_CONCURRENT_REQUESTS_ENV: &str = "HAWKEYE_MAX_CONCURRENT_REQUESTS";
+const TRUSTED_PROXIES_ENV: &str = "HAWKEYE_TRUSTED_PROXIES";
+const FRAME_CACHE_TTL_ENV: &str = "HAWKEYE_FRAME_CACHE_TTL";
+const METRICS_PUSH_URL_ENV: &str = "HAWKEYE_METRICS_PUSH_URL";
+const METRICS_PUSH_MODE_ENV: &str = "HAWKEYE_METRICS_PUSH_MODE";
+const METRICS_PUSH_INTERVAL_ENV: &str = "HAWKEYE_METRICS_PUSH_INTERVAL";
+const METRICS_PUSH_SECRET_ENV: &str = "HAWKEYE_METRICS_PUSH_SECRET";
+const LOG_FORMAT_ENV: &str = "HAWKEYE_LOG_FORMAT";
+const SLATE_DIR_ENV: &str = "HAWKEYE_SLATE_DIR";
+const SLATE_LIBRARY_URL_ENV: &str = "HAWKEYE_SLATE_LIBRARY_URL";
+const EVENT_STORE_ENV: &str = "HAWKEYE_EVENT_STORE";
+const WORKER_TOKEN_KEY_ENV: &str = "HAWKEYE_WORKER_TOKEN_KEY";
+const RTMP_SERVER_IMAGE_ENV: &str = "HAWKEYE_RTMP_SERVER_IMAGE";
+const RTMPS_CERTIFICATE_ENV: &str = "HAWKEYE_RTMPS_CERTIFICATE";
+const NVDEC_GPU_RESOURCE_ENV: &str = "HAWKEYE_NVDEC_GPU_RESOURCE";
+const VAAPI_GPU_RESOURCE_ENV: &str = "HAWKEYE_VAAPI_GPU_RESOURCE";
+const PACK_SIZE_ENV: &str = "HAWKEYE_PACK_SIZE";
+const PACK_BITRATE_ENV: &str = "HAWKEYE_PACK_BITRATE";
+const PACK_CPU_ENV: &str = "HAWKEYE_PACK_CPU";
+const PACK_MEMORY_ENV: &str = "HAWKEYE_PACK_MEMORY";
+const DELETED_RETENTION_ENV: &str = "HAWKEYE_DELETED_RETENTION";
 
 // Configuration defaults
 const DEFAULT_CALL_WATCHER_TIMEOUT: u64 = 2;
+const DEFAULT_INGEST_PORT_RANGE: (u32, u32) = (5000, 5999);
+const DEFAULT_RECONCILE_INTERVAL: u64 = 60;
+const DEFAULT_WORKER_GRACE_PERIOD: u32 = 30;
+const DEFAULT_RATE_LIMIT: u32 = 5;
+const DEFAULT_RATE_LIMIT_BURST: u32 = 20;
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 32;
+const DEFAULT_FRAME_CACHE_TTL: u64 = 2;
+const DEFAULT_METRICS_PUSH_MODE: &str = "pushgateway";
+const DEFAULT_METRICS_PUSH_INTERVAL: u64 = 15;
+const DEFAULT_LOG_FORMAT: &str = "text";
+const DEFAULT_STALL_ACTION: &str = "restart";
+const DEFAULT_SLATE_DIR: &str = "/var/lib/hawkeye/slates";
+const DEFAULT_RTMP_SERVER_IMAGE: &str = "tiangolo/nginx-rtmp:latest";
+const DEFAULT_NVDEC_GPU_RESOURCE: &str = "nvidia.com/gpu";
+const DEFAULT_VAAPI_GPU_RESOURCE: &str = "gpu.intel.com/i915";
+const DEFAULT_PACK_SIZE: usize = 8;
+const DEFAULT_PACK_BITRATE: u32 = 20_000;
+const DEFAULT_PACK_CPU: &str = "4";
+const DEFAULT_PACK_MEMORY: &str = "1Gi";
+const DEFAULT_DELETED_RETENTION: u64 = 7 * 24 * 3600;
 
 lazy_static! {
-    /// Kubernetes namespace where the resources are managed (created/deleted/updated)
+    /// Kubernetes namespace where the resources are managed (created/deleted/updated) when the
+    /// watcher does not select one
     pub static ref NAMESPACE: String =
         std::env::var(NAMESPACE_ENV).unwrap_or_else(|_| "default".into());
 
+    /// All the Kubernetes namespaces where watchers are managed, starting with the default one
+    pub static ref NAMESPACES: Vec<String> = {
+        let mut namespaces = vec![NAMESPACE.clone()];
+        for namespace in std::env::var(NAMESPACES_ENV).map(|val| parse_list(&val)).unwrap_or_default() {
+            if !namespaces.contains(&namespace) {
+                namespaces.push(namespace);
+            }
+        }
+        namespaces
+    };
+
     /// The docker image of the "hawkeye-worker" to be used in the K8s Deployment resource template
     pub static ref DOCKER_IMAGE: String =
         std::env::var(DOCKER_IMAGE_ENV).unwrap_or_else(|_| "hawkeye-dev:latest".into());
 
+    /// Other images of the "hawkeye-worker" watchers are allowed to run, e.g. canary versions
+    pub static ref WORKER_IMAGES: Vec<String> =
+        std::env::var(WORKER_IMAGES_ENV).map(|val| parse_list(&val)).unwrap_or_default();
+
     /// A fixed authentication token required by clients while calling the Hawkeye API
     pub static ref FIXED_TOKEN: String =
         std::env::var(FIXED_TOKEN_ENV).unwrap_or_else(|_| gen_token());
 
+    /// Static API keys accepted by the Hawkeye API and the scope each of them grants.
+    ///
+    /// The fixed token is always accepted with admin scope when configured. A random token is
+    /// only generated when no other authentication method is configured.
+    pub static ref API_KEYS: HashMap<String, Grant> = {
+        let mut keys = parse_grants(&std::env::var(API_KEYS_ENV).unwrap_or_default());
+        let no_jwt = JWKS_URL.is_none() && OIDC_ISSUER.is_none();
+        if std::env::var_os(FIXED_TOKEN_ENV).is_some() || (keys.is_empty() && no_jwt) {
+            keys.insert(FIXED_TOKEN.clone(), Grant::all(Scope::Admin));
+        }
+        keys
+    };
+
+    /// URL of the JSON Web Key Set used to validate JWT bearer tokens. JWTs are not accepted
+    /// when missing.
+    pub static ref JWKS_URL: Option<String> = std::env::var(JWKS_URL_ENV).ok();
+
+    /// Audience the JWT bearer tokens must be issued for, if any.
+    pub static ref JWT_AUDIENCE: Option<String> = std::env::var(JWT_AUDIENCE_ENV).ok();
+
+    /// Issuer of the JWT bearer tokens, if any.
+    pub static ref JWT_ISSUER: Option<String> = std::env::var(JWT_ISSUER_ENV).ok();
+
+    /// OpenID Connect provider the clients log in with, like `https://login.example.com`. Its
+    /// discovery document sets the JWKS URL when `HAWKEYE_JWKS_URL` is missing.
+    pub static ref OIDC_ISSUER: Option<String> =
+        std::env::var(OIDC_ISSUER_ENV).ok().map(|val| val.trim_end_matches('/').to_string());
+
+    /// Client of the API registered with the OIDC provider, also the audience required in the
+    /// JWT bearer tokens when `HAWKEYE_JWT_AUDIENCE` is missing.
+    pub static ref OIDC_CLIENT_ID: Option<String> = std::env::var(OIDC_CLIENT_ID_ENV).ok();
+
+    /// Secret of the client of the API, to exchange the codes of the logins.
+    pub static ref OIDC_CLIENT_SECRET: Option<String> = std::env::var(OIDC_CLIENT_SECRET_ENV).ok();
+
+    /// URL of `GET /v1/auth/callback` as reached by the browsers, registered with the provider.
+    pub static ref OIDC_REDIRECT_URL: Option<String> = std::env::var(OIDC_REDIRECT_URL_ENV).ok();
+
+    /// Claim of the JWT bearer tokens listing the groups of the client.
+    pub static ref OIDC_GROUPS_CLAIM: String =
+        std::env::var(OIDC_GROUPS_CLAIM_ENV).unwrap_or_else(|_| "groups".into());
+
+    /// Grant of the members of each group, in the format of the API keys, like
+    /// `sre:admin,sports-ops:operate;team:sports`.
+    pub static ref OIDC_GROUP_ROLES: HashMap<String, Grant> =
+        parse_grants(&std::env::var(OIDC_GROUP_ROLES_ENV).unwrap_or_default());
+
+    /// Range of ports (inclusive) used to allocate the ingest port of watchers created without one
+    pub static ref INGEST_PORT_RANGE: (u32, u32) =
+        std::env::var(INGEST_PORT_RANGE_ENV).ok().and_then(|val| parse_port_range(&val)).unwrap_or(DEFAULT_INGEST_PORT_RANGE);
+
+    /// Seconds between reconciliations of the watcher objects, `0` disables the reconciliation
+    pub static ref RECONCILE_INTERVAL: u64 =
+        std::env::var(RECONCILE_INTERVAL_ENV).ok().and_then(|val| val.parse::<u64>().ok()).unwrap_or(DEFAULT_RECONCILE_INTERVAL);
+
+    /// Whether watchers are managed with `Watcher` custom resources, reconciled by this API
+    pub static ref OPERATOR_MODE: bool =
+        std::env::var(OPERATOR_MODE_ENV).map(|val| val == "true" || val == "1").unwrap_or(false);
+
+    /// Where the watchers are running, either `kubernetes` or `memory` for local development
+    pub static ref BACKEND: String =
+        std::env::var(BACKEND_ENV).unwrap_or_else(|_| "kubernetes".into());
+
+    /// Scheduling constraints of all the worker pods, in the same JSON format as the `scheduling`
+    /// block of the watchers
+    pub static ref WORKER_SCHEDULING: Scheduling =
+        std::env::var(WORKER_SCHEDULING_ENV).ok().and_then(|val| parse_scheduling(&val)).unwrap_or_default();
+
+    /// Seconds a stopped worker has to finish the actions in progress before being killed
+    pub static ref WORKER_GRACE_PERIOD: u32 =
+        std::env::var(WORKER_GRACE_PERIOD_ENV).ok().and_then(|val| val.parse::<u32>().ok()).unwrap_or(DEFAULT_WORKER_GRACE_PERIOD);
+
+    /// Kubernetes `ServiceAccount` of the worker pods, like one bound to an IAM role with IRSA
+    /// for the AWS actions, the default one of the namespace when missing
+    pub static ref WORKER_SERVICE_ACCOUNT: Option<String> =
+        std::env::var(WORKER_SERVICE_ACCOUNT_ENV).ok().filter(|val| !val.trim().is_empty());
+
+    /// Absolute paths of the commands bundled in the worker image the `exec` actions can run,
+    /// each one followed by the only arguments allowed when limited, see
+    /// `ExecCommand::is_allowed`. No command can run when empty
+    pub static ref EXEC_ALLOWLIST: Vec<String> =
+        std::env::var(EXEC_ALLOWLIST_ENV).map(|val| parse_list(&val)).unwrap_or_default();
+
+    /// Names of the `Secret`s the HTTP actions can reference, mounted in the workers using
+    /// them, no `Secret` can be referenced when empty
+    pub static ref ACTION_SECRETS: Vec<String> =
+        std::env::var(ACTION_SECRETS_ENV).map(|val| parse_list(&val)).unwrap_or_default();
+
+    /// Seconds the pipelines of the workers produce no frame, while receiving packets, before
+    /// their watchdog recovers them. The pipelines are not watched when missing
+    pub static ref STALL_TIMEOUT: Option<u64> =
+        std::env::var(STALL_TIMEOUT_ENV).ok().and_then(|val| val.parse::<u64>().ok());
+
+    /// How the watchdog of the workers recovers a stalled pipeline: `restart` it, or `exit` so
+    /// Kubernetes restarts the worker
+    pub static ref STALL_ACTION: String =
+        std::env::var(STALL_ACTION_ENV).unwrap_or_else(|_| DEFAULT_STALL_ACTION.into());
+
+    /// Whether a `PodDisruptionBudget` protects the running workers from voluntary evictions,
+    /// like node drains
+    pub static ref POD_DISRUPTION_BUDGET: bool =
+        std::env::var(POD_DISRUPTION_BUDGET_ENV).map(|val| val == "true" || val == "1").unwrap_or(false);
+
+    /// Name of a pre-provisioned `Service` without selector, in the main namespace, receiving the
+    /// video feeds of all watchers instead of one `Service`/LoadBalancer per watcher
+    pub static ref SHARED_SERVICE: Option<String> =
+        std::env::var(SHARED_SERVICE_ENV).ok().filter(|val| !val.trim().is_empty());
+
+    /// URLs receiving the lifecycle events of the watchers
+    pub static ref WEBHOOK_URLS: Vec<String> =
+        std::env::var(WEBHOOK_URLS_ENV).map(|val| parse_list(&val)).unwrap_or_default();
+
+    /// Secret signing the lifecycle events with HMAC-SHA256, events are not signed when missing
+    pub static ref WEBHOOK_SECRET: Option<String> = std::env::var(WEBHOOK_SECRET_ENV).ok();
+
+    /// ARN of the SNS topic receiving the lifecycle events of the watchers, if any
+    pub static ref SNS_TOPIC_ARN: Option<String> = std::env::var(SNS_TOPIC_ARN_ENV).ok();
+
+    /// File where the lifecycle events that could not be delivered are appended, if any
+    pub static ref DEAD_LETTER_FILE: Option<String> = std::env::var(DEAD_LETTER_FILE_ENV).ok();
+
+    /// Endpoint of the OpenTelemetry collector receiving the traces over OTLP/gRPC, traces are
+    /// not exported when missing
+    pub static ref OTLP_ENDPOINT: Option<String> = std::env::var(OTLP_ENDPOINT_ENV).ok();
+
+    /// Format of the logs of the API and the workers: `text`, or `json` for one JSON object per
+    /// line with the fields of the spans, like the `watcher_id`
+    pub static ref LOG_FORMAT: String =
+        std::env::var(LOG_FORMAT_ENV).unwrap_or_else(|_| DEFAULT_LOG_FORMAT.into());
+
+    /// Requests per second each client can make to the expensive endpoints, like the video
+    /// frames, `0` disables the limit
+    pub static ref RATE_LIMIT: u32 =
+        std::env::var(RATE_LIMIT_ENV).ok().and_then(|val| val.parse::<u32>().ok()).unwrap_or(DEFAULT_RATE_LIMIT);
+
+    /// Requests each client can make in a burst over the rate limit
+    pub static ref RATE_LIMIT_BURST: u32 =
+        std::env::var(RATE_LIMIT_BURST_ENV).ok().and_then(|val| val.parse::<u32>().ok()).unwrap_or(DEFAULT_RATE_LIMIT_BURST);
+
+    /// Requests served at the same time by each of the expensive endpoints, for all clients
+    pub static ref MAX_CONCURRENT_REQUESTS: usize =
+        std::env::var(MAX_CONCURRENT_REQUESTS_ENV).ok().and_then(|val| val.parse::<usize>().ok()).unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+    /// Reverse proxies in front of the API, each appending the address of its peer to the
+    /// `X-Forwarded-For` header, so the clients are told apart by their address
+    pub static ref TRUSTED_PROXIES: usize =
+        std::env::var(TRUSTED_PROXIES_ENV).ok().and_then(|val| val.parse::<usize>().ok()).unwrap_or(0);
+
+    /// Seconds the latest video frame fetched from a worker is served to other requests, `0`
+    /// fetches it on every request
+    pub static ref FRAME_CACHE_TTL: u64 =
+        std::env::var(FRAME_CACHE_TTL_ENV).ok().and_then(|val| val.parse::<u64>().ok()).unwrap_or(DEFAULT_FRAME_CACHE_TTL);
+
+    /// URL the workers push their metrics to, for clusters where they cannot be scraped, the
+    /// metrics are only scraped when missing
+    pub static ref METRICS_PUSH_URL: Option<String> = std::env::var(METRICS_PUSH_URL_ENV).ok();
+
+    /// How the workers push their metrics: `pushgateway` or `remote_write`
+    pub static ref METRICS_PUSH_MODE: String =
+        std::env::var(METRICS_PUSH_MODE_ENV).unwrap_or_else(|_| DEFAULT_METRICS_PUSH_MODE.into());
+
+    /// Seconds between the pushes of the metrics of each worker
+    pub static ref METRICS_PUSH_INTERVAL: u64 =
+        std::env::var(METRICS_PUSH_INTERVAL_ENV).ok().and_then(|val| val.parse::<u64>().ok()).unwrap_or(DEFAULT_METRICS_PUSH_INTERVAL);
+
+    /// Kubernetes `Secret` with the `username` and `password`, or the `token`, the workers
+    /// authenticate with when pushing their metrics
+    pub static ref METRICS_PUSH_SECRET: Option<String> = std::env::var(METRICS_PUSH_SECRET_ENV).ok();
+
+    /// Directory where the slates uploaded to the library are stored, like a mounted
+    /// `PersistentVolumeClaim` or a bucket mounted with a CSI driver
+    pub static ref SLATE_DIR: String =
+        std::env::var(SLATE_DIR_ENV).unwrap_or_else(|_| DEFAULT_SLATE_DIR.into());
+
+    /// URL of this API as reached from the worker pods, e.g. `http://hawkeye-api:8080`, to
+    /// download the slates of the library and send the heartbeats and events. The workers cannot
+    /// load `slate://` URLs, nor send their heartbeats and events, when missing
+    pub static ref SLATE_LIBRARY_URL: Option<String> = std::env::var(SLATE_LIBRARY_URL_ENV).ok();
+
+    /// Where the events of the workers are stored: `dynamodb://<table>`,
+    /// `postgres://<user>:<password>@<host>/<database>` or `s3://<bucket>/<prefix>`. The events
+    /// are not kept when missing
+    pub static ref EVENT_STORE: Option<String> =
+        std::env::var(EVENT_STORE_ENV).ok().filter(|val| !val.trim().is_empty());
+
+    /// Key the tokens of the workers are derived from, see `auth::control_token` and
+    /// `auth::report_token`. A random key is generated when missing, so the workers must be
+    /// upgraded once the API restarts
+    pub static ref WORKER_TOKEN_KEY: String =
+        std::env::var(WORKER_TOKEN_KEY_ENV).unwrap_or_else(|_| gen_key());
+
+    /// Image of the RTMP server running next to the workers of the `rtmp` sources, accepting the
+    /// streams published to its `live` application on port 1935
+    pub static ref RTMP_SERVER_IMAGE: String =
+        std::env::var(RTMP_SERVER_IMAGE_ENV).unwrap_or_else(|_| DEFAULT_RTMP_SERVER_IMAGE.into());
+
+    /// ARN of the ACM certificate the load balancers of the RTMPS sources terminate TLS with,
+    /// RTMPS is not available when missing
+    pub static ref RTMPS_CERTIFICATE: Option<String> =
+        std::env::var(RTMPS_CERTIFICATE_ENV).ok().filter(|val| !val.trim().is_empty());
+
+    /// Extended resource of the device plugin exposing the NVIDIA GPUs of the nodes, requested
+    /// for the workers decoding with NVDEC
+    pub static ref NVDEC_GPU_RESOURCE: String =
+        std::env::var(NVDEC_GPU_RESOURCE_ENV).unwrap_or_else(|_| DEFAULT_NVDEC_GPU_RESOURCE.into());
+
+    /// Extended resource of the device plugin exposing the VAAPI GPUs of the nodes, requested
+    /// for the workers decoding with VAAPI
+    pub static ref VAAPI_GPU_RESOURCE: String =
+        std::env::var(VAAPI_GPU_RESOURCE_ENV).unwrap_or_else(|_| DEFAULT_VAAPI_GPU_RESOURCE.into());
+
+    /// Most watchers packed in the same worker
+    pub static ref PACK_SIZE: usize =
+        std::env::var(PACK_SIZE_ENV).ok().and_then(|val| val.parse::<usize>().ok()).filter(|size| *size > 0).unwrap_or(DEFAULT_PACK_SIZE);
+
+    /// Highest total bitrate of the sources of the watchers packed in the same worker, in kbps
+    pub static ref PACK_BITRATE: u32 =
+        std::env::var(PACK_BITRATE_ENV).ok().and_then(|val| val.parse::<u32>().ok()).filter(|bitrate| *bitrate > 0).unwrap_or(DEFAULT_PACK_BITRATE);
+
+    /// CPU requested, and limited to, for the worker of a pack of watchers
+    pub static ref PACK_CPU: String =
+        std::env::var(PACK_CPU_ENV).unwrap_or_else(|_| DEFAULT_PACK_CPU.into());
+
+    /// Memory requested, and limited to, for the worker of a pack of watchers
+    pub static ref PACK_MEMORY: String =
+        std::env::var(PACK_MEMORY_ENV).unwrap_or_else(|_| DEFAULT_PACK_MEMORY.into());
+
+    /// Seconds the deleted watchers are kept, and can be restored, before being purged. `0` keeps
+    /// them forever
+    pub static ref DELETED_RETENTION: u64 =
+        std::env::var(DELETED_RETENTION_ENV).ok().and_then(|val| val.parse::<u64>().ok()).unwrap_or(DEFAULT_DELETED_RETENTION);
+
     pub static ref CALL_WATCHER_TIMEOUT: u64 =
         std::env::var(CALL_WATCHER_TIMEOUT_ENV).map(|val| val.parse::<u64>()).unwrap_or_else(|_| Ok(DEFAULT_CALL_WATCHER_TIMEOUT)).unwrap_or(DEFAULT_CALL_WATCHER_TIMEOUT);
 }
@@ -47,3 +371,72 @@ fn gen_token() -> String {
     );
     random_token
 }
+
+/// Random key of the tokens of the workers, when `HAWKEYE_WORKER_TOKEN_KEY` is not present.
+fn gen_key() -> String {
+    log::warn!(
+        "Missing {}, the workers must be upgraded when the API restarts",
+        WORKER_TOKEN_KEY_ENV
+    );
+    thread_rng().sample_iter(Alphanumeric).take(32).collect()
+}
+
+/// Parses the API keys, or the groups, in the format `key1:scope,key2:scope;tag:value`, the tags
+/// following the scope limiting the key to the watchers having them. Invalid entries are ignored.
+fn parse_grants(value: &str) -> HashMap<String, Grant> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            // The key or group may contain `:`, the tags of its grant too
+            let (key_scope, tags) = entry.split_at(entry.find(';').unwrap_or(entry.len()));
+            match key_scope.rsplit_once(':') {
+                Some((key, scope)) => match format!("{}{}", scope, tags).parse::<Grant>() {
+                    Ok(grant) => Some((key.to_string(), grant)),
+                    Err(e) => {
+                        log::error!("Ignoring grant with invalid scope: {}", e);
+                        None
+                    }
+                },
+                None => {
+                    log::error!("Ignoring grant without scope, expected `key:scope`");
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Parses a comma separated list, ignoring empty entries.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses a port range in the format `first-last`.
+fn parse_port_range(value: &str) -> Option<(u32, u32)> {
+    let (first, last) = value.split_once('-')?;
+    let range = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+    if range.0 <= range.1 {
+        Some(range)
+    } else {
+        log::error!("Invalid port range: {}", value);
+        None
+    }
+}
+
+/// Parses the scheduling constraints in JSON format.
+fn parse_scheduling(value: &str) -> Option<Scheduling> {
+    match serde_json::from_str(value) {
+        Ok(scheduling) => Some(scheduling),
+        Err(e) => {
+            log::error!("Invalid worker scheduling configuration: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use hawkeye_core::models;
+use kube::CustomResource;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Declarative definition of a watcher, reconciled by the API running in operator mode.
+///
+/// The spec is the same document accepted by the REST API, so watchers can be managed with
+/// `kubectl apply` using the payloads of the API. The name of the resource is the watcher ID.
+#[derive(CustomResource, Serialize, Deserialize, Clone, Debug)]
+#[kube(
+    group = "hawkeye.io",
+    version = "v1",
+    kind = "Watcher",
+    namespaced,
+    status = "WatcherResourceStatus",
+    shortname = "hw",
+    printcolumn = r#"{"name":"Status", "type":"string", "jsonPath":".status.phase"}"#,
+    printcolumn = r#"{"name":"Port", "type":"integer", "jsonPath":".status.ingest_port"}"#
+)]
+pub struct WatcherSpec {
+    #[serde(flatten)]
+    pub watcher: models::Watcher,
+}
+
+/// The watcher model is validated by the operator, the schema only requires an object so
+/// the model does not need to be described twice.
+impl JsonSchema for WatcherSpec {
+    fn schema_name() -> String {
+        "WatcherSpec".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        serde_json::from_value(json!({
+            "type": "object",
+            "x-kubernetes-preserve-unknown-fields": true,
+        }))
+        .expect("Valid schema")
+    }
+}
+
+/// Observed state of a watcher resource, written by the operator.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct WatcherResourceStatus {
+    /// Calculated status of the watcher, same as in the REST API.
+    pub phase: Option<String>,
+    /// Ingest port of the watcher, kept when it was allocated by the operator.
+    pub ingest_port: Option<u32>,
+    /// Reason why the watcher could not be reconciled.
+    pub message: Option<String>,
+}
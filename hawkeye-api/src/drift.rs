@@ -0,0 +1,156 @@
+//! Compares the stored definition of the watchers with what their worker runs.
+//!
+//! A change of the definition reaches the worker through its `Deployment`, rolled out by
+//! Kubernetes, or through a reload of the worker. `GET /v1/watchers/{id}/diff` reports what did
+//! not, like an upgrade that never rolled out.
+use crate::audit;
+use hawkeye_core::models::Watcher;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Fields of the stored definition changed without the worker, they never drift.
+const API_FIELDS: &[&str] = &[
+    "status",
+    "status_description",
+    "suspended",
+    "deleted_at",
+    "last_heartbeat",
+    "stale",
+];
+
+/// A value deployed for the watcher that is not the one of its definition.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Drift {
+    /// What differs, like `image`, `env.HAWKEYE_LOG_FORMAT` or `config.transitions`.
+    pub field: String,
+    /// Value of the stored definition, `null` when missing.
+    pub desired: Value,
+    /// Value deployed, `null` when missing.
+    pub deployed: Value,
+}
+
+/// Adds the drift of `field` when its values differ.
+pub fn compare(field: &str, desired: Value, deployed: Value, drifts: &mut Vec<Drift>) {
+    if desired != deployed {
+        drifts.push(Drift {
+            field: field.to_string(),
+            desired,
+            deployed,
+        });
+    }
+}
+
+/// Environment variables of the workers whose values are not reported.
+const SECRET_ENV: &[&str] = &["HAWKEYE_CONTROL_TOKEN"];
+
+/// Compares the environment variables of two containers by name, as `env.<name>` fields.
+pub fn compare_env(desired: &Value, deployed: &Value, drifts: &mut Vec<Drift>) {
+    let by_name = |env: &Value| -> BTreeMap<String, Value> {
+        env.as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|var| {
+                let name = var.get("name")?.as_str()?.to_string();
+                let mut value = var.clone();
+                value.as_object_mut()?.remove("name");
+                if SECRET_ENV.contains(&name.as_str()) {
+                    // Still compared, by the digest of their value
+                    let digest = Sha256::digest(value.to_string().as_bytes());
+                    value = json!({ "sha256": hex::encode(digest) });
+                }
+                Some((name, value))
+            })
+            .collect()
+    };
+    let desired = by_name(desired);
+    let deployed = by_name(deployed);
+    let mut names: Vec<&String> = desired.keys().chain(deployed.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        compare(
+            &format!("env.{}", name),
+            desired.get(name).cloned().unwrap_or(Value::Null),
+            deployed.get(name).cloned().unwrap_or(Value::Null),
+            drifts,
+        );
+    }
+}
+
+/// Compares the definition loaded by the running worker with the stored one, as
+/// `config.<path>` fields.
+pub fn compare_config(desired: &Watcher, running: &Watcher, drifts: &mut Vec<Drift>) {
+    let comparable = |watcher: &Watcher| {
+        let mut value = json!(watcher);
+        if let Some(fields) = value.as_object_mut() {
+            for field in API_FIELDS {
+                fields.remove(*field);
+            }
+        }
+        value
+    };
+    for change in audit::diff(&comparable(running), &comparable(desired)) {
+        drifts.push(Drift {
+            field: format!("config.{}", change.path),
+            desired: change.to,
+            deployed: change.from,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_values_that_differ() {
+        let mut drifts = Vec::new();
+        compare_env(
+            &json!([
+                { "name": "HAWKEYE_LOG_FORMAT", "value": "json" },
+                { "name": "HAWKEYE_GRACE_PERIOD", "value": "30" }
+            ]),
+            &json!([
+                { "name": "HAWKEYE_GRACE_PERIOD", "value": "30" },
+                { "name": "HAWKEYE_LOG_FORMAT", "value": "text" }
+            ]),
+            &mut drifts,
+        );
+        assert_eq!(
+            drifts,
+            vec![Drift {
+                field: "env.HAWKEYE_LOG_FORMAT".to_string(),
+                desired: json!({ "value": "json" }),
+                deployed: json!({ "value": "text" }),
+            }]
+        );
+
+        let running: Watcher = serde_json::from_value(json!({
+            "slate_url": "file://./resources/slate_120px.jpg",
+            "source": {
+                "ingest_port": 5000,
+                "container": "mpeg-ts",
+                "codec": "h264",
+                "transport": { "protocol": "rtp" }
+            },
+            "transitions": []
+        }))
+        .unwrap();
+        let mut desired = running.clone();
+        desired.suspended = Some(true);
+        desired.description = Some("Sports".to_string());
+        drifts.clear();
+        compare_config(&desired, &running, &mut drifts);
+        assert_eq!(
+            drifts,
+            vec![Drift {
+                field: "config.description".to_string(),
+                desired: json!("Sports"),
+                deployed: Value::Null,
+            }]
+        );
+    }
+}
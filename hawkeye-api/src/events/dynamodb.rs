@@ -0,0 +1,150 @@
+//! Events in a DynamoDB table, with the `watcher_id` as partition key and the `sk` string as
+//! sort key. The sort key is the time of the event followed by a random suffix, so the events of
+//! the same millisecond are all kept.
+use crate::events::{timestamp, EventQuery, EventStore};
+use async_trait::async_trait;
+use hawkeye_core::models::WatcherEvent;
+use rusoto_core::Region;
+use rusoto_dynamodb::{
+    AttributeValue, BatchWriteItemInput, DynamoDb, DynamoDbClient, PutRequest, QueryInput,
+    WriteRequest,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Most items written by a `BatchWriteItem` request.
+const MAX_BATCH_WRITE: usize = 25;
+
+/// Attempts to write the items DynamoDB did not process, throttled ones included.
+const MAX_WRITE_ATTEMPTS: u32 = 5;
+
+pub struct DynamoDbStore {
+    client: DynamoDbClient,
+    table: String,
+}
+
+impl DynamoDbStore {
+    /// The table in the region of the API.
+    pub fn new(table: &str) -> Self {
+        Self {
+            client: DynamoDbClient::new(Region::default()),
+            table: table.to_string(),
+        }
+    }
+}
+
+fn string(value: String) -> AttributeValue {
+    AttributeValue {
+        s: Some(value),
+        ..AttributeValue::default()
+    }
+}
+
+fn item(watcher_id: &str, event: &WatcherEvent) -> anyhow::Result<HashMap<String, AttributeValue>> {
+    let mut item = HashMap::new();
+    item.insert("watcher_id".to_string(), string(watcher_id.to_string()));
+    item.insert(
+        "sk".to_string(),
+        string(format!("{}#{}", event.timestamp, uuid::Uuid::new_v4())),
+    );
+    item.insert("event".to_string(), string(serde_json::to_string(event)?));
+    Ok(item)
+}
+
+#[async_trait]
+impl EventStore for DynamoDbStore {
+    async fn append(&self, watcher_id: &str, events: &[WatcherEvent]) -> anyhow::Result<()> {
+        for chunk in events.chunks(MAX_BATCH_WRITE) {
+            let mut requests = chunk
+                .iter()
+                .map(|event| {
+                    Ok(WriteRequest {
+                        put_request: Some(PutRequest {
+                            item: item(watcher_id, event)?,
+                        }),
+                        ..WriteRequest::default()
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let mut attempt = 1;
+            loop {
+                let mut request_items = HashMap::new();
+                request_items.insert(self.table.clone(), requests);
+                let output = self
+                    .client
+                    .batch_write_item(BatchWriteItemInput {
+                        request_items,
+                        ..BatchWriteItemInput::default()
+                    })
+                    .await?;
+                requests = output
+                    .unprocessed_items
+                    .and_then(|mut unprocessed| unprocessed.remove(&self.table))
+                    .unwrap_or_default();
+                if requests.is_empty() {
+                    break;
+                }
+                if attempt == MAX_WRITE_ATTEMPTS {
+                    anyhow::bail!(
+                        "DynamoDB did not write {} events of watcher {}",
+                        requests.len(),
+                        watcher_id
+                    );
+                }
+                tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempt))).await;
+                attempt += 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        watcher_id: &str,
+        query: &EventQuery,
+    ) -> anyhow::Result<Vec<WatcherEvent>> {
+        let mut values = HashMap::new();
+        values.insert(":id".to_string(), string(watcher_id.to_string()));
+        values.insert(":from".to_string(), string(timestamp(&query.from)));
+        // After the random suffixes of the events of the last millisecond
+        values.insert(
+            ":to".to_string(),
+            string(format!("{}#~", timestamp(&query.to))),
+        );
+
+        let mut events = Vec::new();
+        let mut start_key = None;
+        loop {
+            let output = self
+                .client
+                .query(QueryInput {
+                    table_name: self.table.clone(),
+                    key_condition_expression: Some(
+                        "watcher_id = :id AND sk BETWEEN :from AND :to".to_string(),
+                    ),
+                    expression_attribute_values: Some(values.clone()),
+                    exclusive_start_key: start_key,
+                    ..QueryInput::default()
+                })
+                .await?;
+            for item in output.items.unwrap_or_default() {
+                let event = item
+                    .get("event")
+                    .and_then(|value| value.s.as_deref())
+                    .map(serde_json::from_str::<WatcherEvent>);
+                match event {
+                    Some(Ok(event)) if query.matches(&event) => events.push(event),
+                    Some(Ok(_)) => (),
+                    _ => log::warn!("Ignoring invalid event of watcher {}", watcher_id),
+                }
+                if events.len() == query.limit {
+                    return Ok(events);
+                }
+            }
+            start_key = output.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(events);
+            }
+        }
+    }
+}
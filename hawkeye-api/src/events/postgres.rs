@@ -0,0 +1,123 @@
+//! Events in the `hawkeye_events` table of a Postgres database, created when missing.
+use crate::events::{EventQuery, EventStore};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hawkeye_core::models::{WatcherEvent, WatcherEventKind};
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, NoTls};
+
+const CREATE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS hawkeye_events (
+        watcher_id TEXT NOT NULL,
+        timestamp TIMESTAMPTZ NOT NULL,
+        kind TEXT NOT NULL,
+        event TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS hawkeye_events_watcher_time
+        ON hawkeye_events (watcher_id, timestamp);
+";
+
+pub struct PostgresStore {
+    url: String,
+    /// Connected again when the connection is lost.
+    client: Mutex<Option<Client>>,
+}
+
+impl PostgresStore {
+    /// Connects to the database and creates the table of the events.
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let client = connect(url).await?;
+        client.batch_execute(CREATE_TABLE).await?;
+        Ok(Self {
+            url: url.to_string(),
+            client: Mutex::new(Some(client)),
+        })
+    }
+
+    async fn client(&self) -> anyhow::Result<tokio::sync::MutexGuard<'_, Option<Client>>> {
+        let mut client = self.client.lock().await;
+        if client.as_ref().map_or(true, Client::is_closed) {
+            *client = Some(connect(&self.url).await?);
+        }
+        Ok(client)
+    }
+}
+
+async fn connect(url: &str) -> anyhow::Result<Client> {
+    let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::error!("Lost the connection to the event store: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+fn kind_name(kind: WatcherEventKind) -> anyhow::Result<String> {
+    Ok(serde_json::to_value(kind)?
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+#[async_trait]
+impl EventStore for PostgresStore {
+    async fn append(&self, watcher_id: &str, events: &[WatcherEvent]) -> anyhow::Result<()> {
+        let mut guard = self.client().await?;
+        let client = guard.as_mut().expect("connected");
+        let transaction = client.transaction().await?;
+        let insert = transaction
+            .prepare(
+                "INSERT INTO hawkeye_events (watcher_id, timestamp, kind, event) \
+                 VALUES ($1, $2, $3, $4)",
+            )
+            .await?;
+        for event in events {
+            let time: DateTime<Utc> = event
+                .time()
+                .ok_or_else(|| anyhow::anyhow!("Invalid timestamp {}", event.timestamp))?;
+            transaction
+                .execute(
+                    &insert,
+                    &[
+                        &watcher_id,
+                        &time,
+                        &kind_name(event.kind)?,
+                        &serde_json::to_string(event)?,
+                    ],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        watcher_id: &str,
+        query: &EventQuery,
+    ) -> anyhow::Result<Vec<WatcherEvent>> {
+        let kind = query.kind.map(kind_name).transpose()?;
+        let guard = self.client().await?;
+        let client = guard.as_ref().expect("connected");
+        let rows = client
+            .query(
+                "SELECT event FROM hawkeye_events \
+                 WHERE watcher_id = $1 AND timestamp BETWEEN $2 AND $3 \
+                 AND ($4::TEXT IS NULL OR kind = $4) \
+                 ORDER BY timestamp LIMIT $5",
+                &[
+                    &watcher_id,
+                    &query.from,
+                    &query.to,
+                    &kind,
+                    &(query.limit as i64),
+                ],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .filter_map(|row| serde_json::from_str(row.get::<_, &str>(0)).ok())
+            .collect())
+    }
+}
@@ -0,0 +1,153 @@
+//! Events as JSON lines in an S3 bucket, one object per day for each report of a worker:
+//! `<prefix>/<watcher_id>/<YYYY-MM-DD>/<timestamp>_<uuid>.jsonl`.
+use crate::events::{EventQuery, EventStore};
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use futures::TryStreamExt;
+use hawkeye_core::models::WatcherEvent;
+use rusoto_core::Region;
+use rusoto_s3::{GetObjectRequest, ListObjectsV2Request, PutObjectRequest, S3Client, S3};
+use std::collections::BTreeMap;
+
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Store {
+    /// The events under the `bucket/prefix` location, in the region of the API.
+    pub fn new(location: &str) -> Self {
+        let (bucket, prefix) = match location.split_once('/') {
+            Some((bucket, prefix)) => (bucket, prefix.trim_matches('/')),
+            None => (location, ""),
+        };
+        Self {
+            client: S3Client::new(Region::default()),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn day_prefix(&self, watcher_id: &str, day: NaiveDate) -> String {
+        let key = format!("{}/{}/", watcher_id, day.format("%Y-%m-%d"));
+        if self.prefix.is_empty() {
+            key
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+
+    async fn keys(&self, prefix: String) -> anyhow::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut token = None;
+        loop {
+            let output = self
+                .client
+                .list_objects_v2(ListObjectsV2Request {
+                    bucket: self.bucket.clone(),
+                    prefix: Some(prefix.clone()),
+                    continuation_token: token,
+                    ..ListObjectsV2Request::default()
+                })
+                .await?;
+            keys.extend(
+                output
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| object.key),
+            );
+            token = output.next_continuation_token;
+            if token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    async fn read(&self, key: String) -> anyhow::Result<Vec<WatcherEvent>> {
+        let output = self
+            .client
+            .get_object(GetObjectRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                ..GetObjectRequest::default()
+            })
+            .await?;
+        let body = match output.body {
+            Some(body) => body.map_ok(|bytes| bytes.to_vec()).try_concat().await?,
+            None => return Ok(Vec::new()),
+        };
+        Ok(String::from_utf8_lossy(&body)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    log::warn!("Ignoring invalid event in {}: {}", key, e);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl EventStore for S3Store {
+    async fn append(&self, watcher_id: &str, events: &[WatcherEvent]) -> anyhow::Result<()> {
+        let mut days: BTreeMap<NaiveDate, Vec<&WatcherEvent>> = BTreeMap::new();
+        for event in events {
+            let time = event
+                .time()
+                .ok_or_else(|| anyhow::anyhow!("Invalid timestamp {}", event.timestamp))?;
+            days.entry(time.date().naive_utc()).or_default().push(event);
+        }
+        for (day, events) in days {
+            let mut body = String::new();
+            for event in &events {
+                body.push_str(&serde_json::to_string(event)?);
+                body.push('\n');
+            }
+            let key = format!(
+                "{}{}_{}.jsonl",
+                self.day_prefix(watcher_id, day),
+                events[0].timestamp,
+                uuid::Uuid::new_v4()
+            );
+            self.client
+                .put_object(PutObjectRequest {
+                    bucket: self.bucket.clone(),
+                    key,
+                    body: Some(body.into_bytes().into()),
+                    content_type: Some("application/x-ndjson".to_string()),
+                    ..PutObjectRequest::default()
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        watcher_id: &str,
+        query: &EventQuery,
+    ) -> anyhow::Result<Vec<WatcherEvent>> {
+        let mut events = Vec::new();
+        let mut day = query.from.date().naive_utc();
+        while day <= query.to.date().naive_utc() {
+            for key in self.keys(self.day_prefix(watcher_id, day)).await? {
+                events.extend(
+                    self.read(key)
+                        .await?
+                        .into_iter()
+                        .filter(|event| query.matches(event)),
+                );
+            }
+            day = day + Duration::days(1);
+        }
+        // The times reported by the workers sort as text
+        events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        events.truncate(query.limit);
+        Ok(events)
+    }
+}
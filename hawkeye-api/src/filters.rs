@@ -1,128 +1,832 @@
-use crate::{auth, handlers};
-use hawkeye_core::models::Watcher;
-use kube::Client;
+use crate::auth::{Grant, Scope};
+use crate::backend::Backend;
+use crate::{auth, handlers, rate_limit, slates};
+use hawkeye_core::models::{
+    Heartbeat, ModeChange, ThresholdChange, TransitionTrigger, Watcher, WatcherEvent,
+};
 use serde::Serialize;
+use warp::filters::BoxedFilter;
+use warp::http::header::RETRY_AFTER;
+use warp::http::HeaderValue;
 use warp::hyper::StatusCode;
-use warp::Filter;
+use warp::reply::Response;
+use warp::{Filter, Reply};
 
 /// API root for v1
 pub fn v1(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = std::convert::Infallible> + Clone {
-    watchers_list(client.clone())
-        .or(watcher_create(client.clone()))
-        .or(watcher_get(client.clone()))
-        .or(watcher_delete(client.clone()))
-        .or(watcher_upgrade(client.clone()))
-        .or(watcher_start(client.clone()))
-        .or(watcher_stop(client.clone()))
-        .or(watcher_video_frame(client.clone()))
-        .or(healthcheck(client))
+    watcher_routes(backend.clone())
+        .or(worker_routes(backend.clone()))
+        .or(history_routes(backend.clone()))
+        .or(service_routes(backend))
         .recover(handle_rejection)
 }
 
+/// Routes of the definitions and the lifecycle of the watchers. The groups of routes are boxed,
+/// a single chain of them nests too deep for the compiler and the stack of the tests.
+fn watcher_routes(backend: Backend) -> BoxedFilter<(Response,)> {
+    watchers_list(backend.clone())
+        .or(watcher_create(backend.clone()))
+        .or(watcher_by_name(backend.clone()))
+        .or(watcher_get(backend.clone()))
+        .or(watcher_update(backend.clone()))
+        .or(watcher_delete(backend.clone()))
+        .or(watcher_export(backend.clone()))
+        .or(watcher_import(backend.clone()))
+        .or(watcher_upgrade(backend.clone()))
+        .or(watcher_start(backend.clone()))
+        .or(watcher_stop(backend.clone()))
+        .or(watcher_suspend(backend.clone()))
+        .or(watcher_resume(backend.clone()))
+        .or(watcher_restore(backend.clone()))
+        .or(watchers_bulk_start(backend.clone()))
+        .or(watchers_bulk_stop(backend.clone()))
+        .or(watchers_bulk_upgrade(backend))
+        .map(Reply::into_response)
+        .boxed()
+}
+
+/// Routes calling the workers of the watchers.
+fn worker_routes(backend: Backend) -> BoxedFilter<(Response,)> {
+    watcher_reload(backend.clone())
+        .or(watcher_threshold(backend.clone()))
+        .or(watcher_mode(backend.clone()))
+        .or(watcher_calibrate(backend.clone()))
+        .or(watcher_record(backend.clone()))
+        .or(watcher_video_frame(backend.clone()))
+        .or(watcher_preview(backend.clone()))
+        .or(watcher_metrics(backend.clone()))
+        .or(watcher_state(backend.clone()))
+        .or(watcher_trigger(backend.clone()))
+        .or(watcher_action_history(backend.clone()))
+        .or(watcher_action_replay(backend.clone()))
+        .or(watcher_logs(backend))
+        .map(Reply::into_response)
+        .boxed()
+}
+
+/// Routes of the history of the watchers and of the reports of their workers.
+fn history_routes(backend: Backend) -> BoxedFilter<(Response,)> {
+    watcher_diff(backend.clone())
+        .or(watcher_audit(backend.clone()))
+        .or(watcher_revisions(backend.clone()))
+        .or(watcher_rollback(backend.clone()))
+        .or(watcher_heartbeat(backend.clone()))
+        .or(watcher_events_report(backend.clone()))
+        .or(watcher_events(backend.clone()))
+        .or(watcher_analytics(backend))
+        .map(Reply::into_response)
+        .boxed()
+}
+
+/// Routes of the slates, the documentation, the login and the health of the API.
+fn service_routes(backend: Backend) -> BoxedFilter<(Response,)> {
+    slate_upload()
+        .or(slates_list())
+        .or(slate_image())
+        .or(slate_delete(backend.clone()))
+        .or(openapi_spec())
+        .or(swagger_ui())
+        .or(auth_login())
+        .or(auth_callback())
+        .or(auth_token())
+        .or(healthcheck(backend.clone()))
+        .or(healthz())
+        .or(readyz(backend))
+        .map(Reply::into_response)
+        .boxed()
+}
+
 /// GET /v1/watchers
 pub fn watchers_list(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("v1" / "watchers")
-        .and(auth::verify())
+        .and(auth::grant(Scope::Read))
         .and(warp::get())
-        .and(with_client(client))
+        .and(warp::query::<handlers::ListOptions>())
+        .and(rate_limit::limit(&rate_limit::LIST_WATCHERS))
+        .and(with_backend(backend))
         .and_then(handlers::list_watchers)
 }
 
 /// POST /v1/watchers
 pub fn watcher_create(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("v1" / "watchers")
-        .and(auth::verify())
+        .and(auth::grant(Scope::Admin))
         .and(warp::post())
         .and(json_body())
-        .and(with_client(client))
+        .and(auth::actor())
+        .and(with_backend(backend))
         .and_then(handlers::create_watcher)
 }
 
+/// GET /v1/watchers/by-name/{name}
+pub fn watcher_by_name(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "watchers" / "by-name" / String)
+        .and(auth::grant(Scope::Read))
+        .and(warp::get())
+        .and(with_backend(backend))
+        .and_then(handlers::get_watcher_by_name)
+}
+
 /// GET /v1/watchers/{id}
 pub fn watcher_get(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("v1" / "watchers" / String)
-        .and(auth::verify())
-        .and(warp::get())
-        .and(with_client(client))
-        .and_then(handlers::get_watcher)
+    owned(
+        warp::path!("v1" / "watchers" / String).and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher)
+}
+
+/// PUT /v1/watchers/{id}
+pub fn watcher_update(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned_with_grant(
+        warp::path!("v1" / "watchers" / String).and(warp::put()),
+        Scope::Admin,
+        backend.clone(),
+    )
+    .and(json_body())
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::update_watcher)
+}
+
+/// GET /v1/watchers/{id}/export
+pub fn watcher_export(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "export").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::ExportOptions>())
+    .and(with_backend(backend))
+    .and_then(handlers::export_watcher)
+}
+
+/// POST /v1/watchers/import
+pub fn watcher_import(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "watchers" / "import")
+        .and(auth::grant(Scope::Admin))
+        .and(warp::post())
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::bytes())
+        .and(auth::actor())
+        .and(with_backend(backend))
+        .and_then(handlers::import_watcher)
 }
 
 /// DELETE /v1/watchers/{id}
 pub fn watcher_delete(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("v1" / "watchers" / String)
-        .and(auth::verify())
-        .and(warp::delete())
-        .and(with_client(client))
-        .and_then(handlers::delete_watcher)
+    owned(
+        warp::path!("v1" / "watchers" / String).and(warp::delete()),
+        Scope::Admin,
+        backend.clone(),
+    )
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::delete_watcher)
 }
 
 /// POST /v1/watchers/{id}/upgrade
 pub fn watcher_upgrade(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("v1" / "watchers" / String / "upgrade")
-        .and(auth::verify())
-        .and(warp::post())
-        .and(with_client(client))
-        .and_then(handlers::upgrade_watcher)
+    owned(
+        warp::path!("v1" / "watchers" / String / "upgrade").and(warp::post()),
+        Scope::Admin,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::UpgradeOptions>())
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::upgrade_watcher)
 }
 
 /// POST /v1/watchers/{id}/start
 pub fn watcher_start(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("v1" / "watchers" / String / "start")
-        .and(auth::verify())
-        .and(warp::post())
-        .and(with_client(client))
-        .and_then(handlers::start_watcher)
+    owned(
+        warp::path!("v1" / "watchers" / String / "start").and(warp::post()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::start_watcher)
 }
 
 /// POST /v1/watchers/{id}/stop
 pub fn watcher_stop(
-    client: Client,
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "stop").and(warp::post()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::StopOptions>())
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::stop_watcher)
+}
+
+/// POST /v1/watchers/{id}/suspend
+pub fn watcher_suspend(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "suspend").and(warp::post()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::suspend_watcher)
+}
+
+/// POST /v1/watchers/{id}/resume
+pub fn watcher_resume(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "resume").and(warp::post()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::resume_watcher)
+}
+
+/// POST /v1/watchers/{id}/restore
+pub fn watcher_restore(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "restore").and(warp::post()),
+        Scope::Admin,
+        backend.clone(),
+    )
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::restore_watcher)
+}
+
+/// POST /v1/watchers/{id}/reload
+pub fn watcher_reload(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "reload").and(warp::post()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::reload_watcher)
+}
+
+/// PUT /v1/watchers/{id}/config/threshold
+pub fn watcher_threshold(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "config" / "threshold").and(warp::put()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(threshold_body())
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::set_watcher_threshold)
+}
+
+/// PUT /v1/watchers/{id}/mode
+pub fn watcher_mode(
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("v1" / "watchers" / String / "stop")
-        .and(auth::verify())
+    owned(
+        warp::path!("v1" / "watchers" / String / "mode").and(warp::put()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(mode_body())
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::set_watcher_mode)
+}
+
+/// GET /v1/watchers/{id}/calibrate
+pub fn watcher_calibrate(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "calibrate").and(warp::get()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::CalibrateOptions>())
+    .and(with_backend(backend))
+    .and_then(handlers::calibrate_watcher)
+}
+
+/// POST /v1/watchers/{id}/record
+pub fn watcher_record(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "record").and(warp::post()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::RecordOptions>())
+    .and(with_backend(backend))
+    .and_then(handlers::record_watcher)
+}
+
+/// POST /v1/watchers/start
+pub fn watchers_bulk_start(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "watchers" / "start")
+        .and(auth::grant(Scope::Operate))
+        .and(warp::post())
+        .and(bulk_selector_body())
+        .and(auth::actor())
+        .and(with_backend(backend))
+        .and_then(handlers::bulk_start_watchers)
+}
+
+/// POST /v1/watchers/stop
+pub fn watchers_bulk_stop(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "watchers" / "stop")
+        .and(auth::grant(Scope::Operate))
+        .and(warp::post())
+        .and(bulk_selector_body())
+        .and(auth::actor())
+        .and(with_backend(backend))
+        .and_then(handlers::bulk_stop_watchers)
+}
+
+/// POST /v1/watchers/upgrade
+pub fn watchers_bulk_upgrade(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "watchers" / "upgrade")
+        .and(auth::grant(Scope::Admin))
         .and(warp::post())
-        .and(with_client(client))
-        .and_then(handlers::stop_watcher)
+        .and(fleet_upgrade_body())
+        .and(auth::actor())
+        .and(with_backend(backend))
+        .and_then(handlers::upgrade_watchers)
 }
 
 /// GET /v1/watchers/{id}/video-frame
 pub fn watcher_video_frame(
-    client: Client,
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "video-frame").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(warp::header::optional::<String>("if-none-match"))
+    .and(rate_limit::limit(&rate_limit::VIDEO_FRAME))
+    .and(with_backend(backend))
+    .and_then(handlers::get_video_frame)
+}
+
+/// GET /v1/watchers/{id}/preview
+pub fn watcher_preview(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "preview").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::PreviewOptions>())
+    .and(rate_limit::limit(&rate_limit::VIDEO_FRAME))
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_preview)
+}
+
+/// GET /v1/watchers/{id}/metrics
+pub fn watcher_metrics(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "metrics").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::MetricsOptions>())
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_metrics)
+}
+
+/// GET /v1/watchers/{id}/state
+pub fn watcher_state(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "state").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_state)
+}
+
+/// POST /v1/watchers/{id}/trigger
+pub fn watcher_trigger(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "trigger").and(warp::post()),
+        Scope::Operate,
+        backend.clone(),
+    )
+    .and(trigger_body())
+    .and(auth::actor())
+    .and(with_backend(backend))
+    .and_then(handlers::trigger_transition)
+}
+
+/// GET /v1/watchers/{id}/actions/history
+pub fn watcher_action_history(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "actions" / "history").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(with_backend(backend))
+    .and_then(handlers::get_action_history)
+}
+
+/// POST /v1/watchers/{id}/actions/{index}/replay
+pub fn watcher_action_replay(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "watchers" / String / "actions" / u64 / "replay")
+        .and(warp::post())
+        .and(auth::grant(Scope::Operate))
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |id: String, index: u64, grant: Grant, backend: Backend| async move {
+                check_owner(id, grant, backend)
+                    .await
+                    .map(|(id, _)| (id, index))
+            },
+        )
+        .untuple_one()
+        .and(auth::actor())
+        .and(with_backend(backend))
+        .and_then(handlers::replay_action)
+}
+
+/// GET /v1/watchers/{id}/logs
+pub fn watcher_logs(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "logs").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::LogOptions>())
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_logs)
+}
+
+/// GET /v1/watchers/{id}/diff
+pub fn watcher_diff(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "diff").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_diff)
+}
+
+/// GET /v1/watchers/{id}/audit
+pub fn watcher_audit(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "audit").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_audit)
+}
+
+/// GET /v1/watchers/{id}/revisions
+pub fn watcher_revisions(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "revisions").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_revisions)
+}
+
+/// POST /v1/watchers/{id}/rollback/{rev}
+pub fn watcher_rollback(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "watchers" / String / "rollback" / u64)
+        .and(warp::post())
+        .and(auth::grant(Scope::Admin))
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |id: String, revision: u64, grant: Grant, backend: Backend| async move {
+                check_owner(id, grant, backend)
+                    .await
+                    .map(|(id, grant)| (id, grant, revision))
+            },
+        )
+        .untuple_one()
+        .and(auth::actor())
+        .and(with_backend(backend))
+        .and_then(handlers::rollback_watcher)
+}
+
+/// POST /v1/watchers/{id}/heartbeat
+///
+/// The workers send their heartbeats with the report token of their watcher, see `reported`.
+pub fn watcher_heartbeat(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    reported(
+        warp::path!("v1" / "watchers" / String / "heartbeat").and(warp::post()),
+        backend.clone(),
+    )
+    .and(heartbeat_body())
+    .and(with_backend(backend))
+    .and_then(handlers::record_heartbeat)
+}
+
+/// POST /v1/watchers/{id}/events
+///
+/// The workers report their events with the report token of their watcher, see `reported`.
+pub fn watcher_events_report(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    reported(
+        warp::path!("v1" / "watchers" / String / "events").and(warp::post()),
+        backend.clone(),
+    )
+    .and(events_body())
+    .and(with_backend(backend))
+    .and_then(handlers::report_watcher_events)
+}
+
+/// GET /v1/watchers/{id}/events
+pub fn watcher_events(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "events").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::EventOptions>())
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_events)
+}
+
+/// GET /v1/watchers/{id}/analytics
+pub fn watcher_analytics(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    owned(
+        warp::path!("v1" / "watchers" / String / "analytics").and(warp::get()),
+        Scope::Read,
+        backend.clone(),
+    )
+    .and(warp::query::<handlers::AnalyticsOptions>())
+    .and(with_backend(backend))
+    .and_then(handlers::get_watcher_analytics)
+}
+
+/// POST /v1/slates
+pub fn slate_upload() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "slates")
+        .and(auth::verify(Scope::Admin))
+        .and(warp::post())
+        .and(warp::multipart::form().max_length(slates::MAX_SLATE_SIZE))
+        .and(auth::actor())
+        .and_then(handlers::upload_slate)
+}
+
+/// GET /v1/slates
+pub fn slates_list() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "slates")
+        .and(auth::verify(Scope::Read))
+        .and(warp::get())
+        .and_then(handlers::list_slates)
+}
+
+/// GET /v1/slates/{id}/image
+///
+/// Not authenticated, the workers download the slates of their watcher from it.
+pub fn slate_image() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "slates" / String / "image")
+        .and(warp::get())
+        .and_then(handlers::get_slate_image)
+}
+
+/// DELETE /v1/slates/{id}
+pub fn slate_delete(
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("v1" / "watchers" / String / "video-frame")
+    warp::path!("v1" / "slates" / String)
+        .and(auth::verify(Scope::Admin))
+        .and(warp::delete())
+        .and(auth::actor())
+        .and(with_backend(backend))
+        .and_then(handlers::delete_slate)
+}
+
+/// GET /v1/openapi.json
+pub fn openapi_spec() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "openapi.json")
+        .and(warp::get())
+        .and_then(handlers::openapi_spec)
+}
+
+/// GET /v1/docs/{file}
+pub fn swagger_ui() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("v1")
+        .and(warp::path("docs"))
+        .and(warp::get())
+        .and(warp::path::full())
+        .and(warp::path::tail())
+        .and_then(handlers::swagger_ui)
+}
+
+/// GET /v1/auth/login
+pub fn auth_login() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "auth" / "login")
+        .and(warp::get())
+        .and_then(handlers::login)
+}
+
+/// GET /v1/auth/callback
+pub fn auth_callback() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "auth" / "callback")
         .and(warp::get())
-        .and(with_client(client))
-        .and_then(handlers::get_video_frame)
+        .and(warp::query::<handlers::LoginCallback>())
+        .and(warp::cookie::optional(handlers::LOGIN_STATE_COOKIE))
+        .and_then(handlers::login_callback)
+}
+
+/// POST /v1/auth/token
+pub fn auth_token() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("v1" / "auth" / "token")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and_then(handlers::request_token)
 }
 
 /// GET /healthcheck
 pub fn healthcheck(
-    client: Client,
+    backend: Backend,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("healthcheck")
         .and(warp::get())
-        .and(with_client(client))
+        .and(with_backend(backend))
         .and_then(handlers::healthcheck)
 }
 
-fn with_client(
-    client: Client,
-) -> impl Filter<Extract = (Client,), Error = std::convert::Infallible> + Clone {
-    warp::any().map(move || client.clone())
+/// GET /healthz
+pub fn healthz() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("healthz")
+        .and(warp::get())
+        .and_then(handlers::healthz)
+}
+
+/// GET /readyz
+pub fn readyz(
+    backend: Backend,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path("readyz")
+        .and(warp::get())
+        .and(with_backend(backend))
+        .and_then(handlers::readyz)
+}
+
+/// The ID of the watcher of the `path`, once checked that the client is granted `required` on
+/// it, see `check_owner`. The `path` matches the method too, so the watcher is only fetched for
+/// the requests of the route.
+fn owned<F>(
+    path: F,
+    required: Scope,
+    backend: Backend,
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (String,), Error = warp::Rejection> + Clone,
+{
+    owned_with_grant(path, required, backend).map(|id: String, _: Grant| id)
+}
+
+/// Like `owned`, passing on the grant of the client for the handlers checking the tags of the
+/// definitions they store.
+fn owned_with_grant<F>(
+    path: F,
+    required: Scope,
+    backend: Backend,
+) -> impl Filter<Extract = (String, Grant), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (String,), Error = warp::Rejection> + Clone,
+{
+    path.and(auth::grant(required))
+        .and(with_backend(backend))
+        .and_then(check_owner)
+        .untuple_one()
+}
+
+/// The ID of the watcher of the `path`, for the reports of its worker: authenticated with the
+/// `auth::report_token` of the watcher, or else like `owned` with the `operate` scope.
+fn reported<F>(
+    path: F,
+    backend: Backend,
+) -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (String,), Error = warp::Rejection> + Clone + Send,
+{
+    path.clone()
+        .and(warp::header::<String>("authorization"))
+        .and_then(|id: String, auth_header: String| async move {
+            if auth::is_report_token(&id, &auth_header) {
+                Ok(id)
+            } else {
+                Err(warp::reject::custom(auth::NoAuth))
+            }
+        })
+        .or(owned(path, Scope::Operate, backend))
+        .unify()
+}
+
+/// Rejects the request when the watcher does not have the tags the grant of the client is
+/// limited to. The watchers that do not exist are left to the handlers.
+async fn check_owner(
+    id: String,
+    grant: Grant,
+    backend: Backend,
+) -> Result<(String, Grant), warp::Rejection> {
+    if !grant.is_restricted() {
+        return Ok((id, grant));
+    }
+    match backend.get_watcher_config(&id).await {
+        Ok(Some(watcher)) if !grant.covers(watcher.tags.as_ref()) => {
+            Err(warp::reject::custom(auth::Forbidden))
+        }
+        Ok(_) => Ok((id, grant)),
+        Err(e) => {
+            tracing::error!("Could not check the tags of watcher {}: {:?}", id, e);
+            Err(warp::reject::custom(auth::Forbidden))
+        }
+    }
+}
+
+fn with_backend(
+    backend: Backend,
+) -> impl Filter<Extract = (Backend,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || backend.clone())
 }
 
 fn json_body() -> impl Filter<Extract = (Watcher,), Error = warp::Rejection> + Clone {
@@ -131,6 +835,36 @@ fn json_body() -> impl Filter<Extract = (Watcher,), Error = warp::Rejection> + C
     warp::body::content_length_limit(1024 * 16).and(warp::body::json())
 }
 
+fn bulk_selector_body(
+) -> impl Filter<Extract = (handlers::BulkSelector,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn fleet_upgrade_body(
+) -> impl Filter<Extract = (handlers::FleetUpgrade,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn threshold_body() -> impl Filter<Extract = (ThresholdChange,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024).and(warp::body::json())
+}
+
+fn trigger_body() -> impl Filter<Extract = (TransitionTrigger,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024).and(warp::body::json())
+}
+
+fn mode_body() -> impl Filter<Extract = (ModeChange,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024).and(warp::body::json())
+}
+
+fn heartbeat_body() -> impl Filter<Extract = (Heartbeat,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024).and(warp::body::json())
+}
+
+fn events_body() -> impl Filter<Extract = (Vec<WatcherEvent>,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 1024).and(warp::body::json())
+}
+
 /// An API error serializable to JSON.
 #[derive(Serialize)]
 struct ErrorMessage {
@@ -142,26 +876,1114 @@ async fn handle_rejection(
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
     let message = "Error calling the API".to_string();
     let code;
+    let mut retry_after = None;
 
-    log::debug!("Rejection = {:?}", err);
+    tracing::debug!("Rejection = {:?}", err);
 
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
     } else if err.find::<auth::NoAuth>().is_some() {
         code = StatusCode::UNAUTHORIZED;
+    } else if err.find::<auth::Forbidden>().is_some() {
+        code = StatusCode::FORBIDDEN;
     } else if let Some(missing) = err.find::<warp::reject::MissingHeader>() {
         if missing.name() == "authorization" {
             code = StatusCode::UNAUTHORIZED;
         } else {
             code = StatusCode::BAD_REQUEST;
         }
+    } else if err.find::<warp::reject::InvalidQuery>().is_some() {
+        code = StatusCode::BAD_REQUEST;
     } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
         code = StatusCode::METHOD_NOT_ALLOWED;
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        code = StatusCode::PAYLOAD_TOO_LARGE;
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        code = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+    } else if let Some(limited) = err.find::<rate_limit::TooManyRequests>() {
+        code = StatusCode::TOO_MANY_REQUESTS;
+        retry_after = Some(limited.retry_after_secs());
     } else {
-        log::debug!("Unhandled rejection: {:?}", err);
+        tracing::debug!("Unhandled rejection: {:?}", err);
         code = StatusCode::INTERNAL_SERVER_ERROR;
     }
 
     let json = warp::reply::json(&ErrorMessage { message });
-    Ok(warp::reply::with_status(json, code))
+    let mut resp = warp::reply::with_status(json, code).into_response();
+    if let Some(secs) = retry_after {
+        resp.headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(secs));
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::memory::MemoryBackend;
+    use crate::backend::WatcherBackend;
+    use crate::config::FIXED_TOKEN;
+    use hawkeye_core::models::{Status, WatcherMode};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use uuid::Uuid;
+    use warp::http::Response;
+    use warp::hyper::body::Bytes;
+
+    /// A valid watcher definition, with a unique name.
+    fn watcher_payload() -> Value {
+        json!({
+            "name": format!("watcher-{}", Uuid::new_v4()),
+            "slate_url": "file://./resources/slate_120px.jpg",
+            "source": {
+                "container": "mpeg-ts",
+                "codec": "h264",
+                "transport": { "protocol": "rtp" }
+            },
+            "transitions": [
+                { "from": "content", "to": "slate", "actions": [] }
+            ]
+        })
+    }
+
+    async fn call(
+        backend: &Arc<MemoryBackend>,
+        method: &str,
+        path: &str,
+        body: Option<Value>,
+    ) -> Response<Bytes> {
+        let mut request = warp::test::request()
+            .method(method)
+            .path(path)
+            .header("authorization", format!("Bearer {}", *FIXED_TOKEN));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        request.reply(&v1(backend.clone())).await
+    }
+
+    async fn import(backend: &Arc<MemoryBackend>, definition: String) -> Response<Bytes> {
+        warp::test::request()
+            .method("POST")
+            .path("/v1/watchers/import")
+            .header("authorization", format!("Bearer {}", *FIXED_TOKEN))
+            .header("content-type", "application/yaml")
+            .body(definition)
+            .reply(&v1(backend.clone()))
+            .await
+    }
+
+    fn json(resp: &Response<Bytes>) -> Value {
+        serde_json::from_slice(resp.body()).unwrap()
+    }
+
+    async fn create(backend: &Arc<MemoryBackend>) -> String {
+        let resp = call(backend, "POST", "/v1/watchers", Some(watcher_payload())).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        json(&resp)["id"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn create_and_get_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let resp = call(&backend, "POST", "/v1/watchers", Some(watcher_payload())).await;
+
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let created = json(&resp);
+        assert_eq!(created["status"], "pending");
+        assert_eq!(created["source"]["ingest_port"], 5000);
+
+        let id = created["id"].as_str().unwrap();
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}", id), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["status"], "ready");
+    }
+
+    #[tokio::test]
+    async fn create_invalid_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["transitions"] = json!([]);
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(json(&resp)["errors"][0]["field"], "transitions");
+    }
+
+    #[tokio::test]
+    async fn create_watcher_with_unknown_image() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["worker_image"] = json!("hawkeye-worker:canary");
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(json(&resp)["errors"][0]["field"], "worker_image");
+    }
+
+    #[tokio::test]
+    async fn create_watcher_with_rtmps_source() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["source"]["container"] = json!("flv");
+        payload["source"]["transport"] = json!({"protocol": "rtmp", "stream_key": "channel-1"});
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload.clone())).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        // No certificate is configured for the load balancers
+        payload["source"]["transport"]["tls"] = json!(true);
+        payload["source"]["ingest_port"] = json!(5001);
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(json(&resp)["errors"][0]["field"], "source.transport.tls");
+    }
+
+    #[tokio::test]
+    async fn create_watcher_with_port_in_use() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let mut payload = watcher_payload();
+        payload["source"]["ingest_port"] = json!(5000);
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(json(&resp)["watcher_id"], id.as_str());
+    }
+
+    #[tokio::test]
+    async fn create_watcher_with_fec_ports() {
+        let backend = Arc::new(MemoryBackend::default());
+        create(&backend).await;
+        let mut payload = watcher_payload();
+        payload["source"]["transport"] = json!({"protocol": "rtp", "fec": "2d"});
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let created = json(&resp);
+        assert_eq!(created["source"]["ingest_port"], 5001);
+
+        // Receiving the row FEC stream of the other watcher
+        let mut payload = watcher_payload();
+        payload["source"]["ingest_port"] = json!(5005);
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(json(&resp)["watcher_id"], created["id"]);
+    }
+
+    #[tokio::test]
+    async fn create_watcher_with_backup_sources() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["backup_sources"] = json!([{
+            "ingest_port": 5010,
+            "container": "mpeg-ts",
+            "codec": "h264",
+            "transport": {"protocol": "rtp"}
+        }]);
+        payload["failover"] = json!({
+            "duration": 3,
+            "transitions": [{"from": "content", "to": "backup", "actions": []}]
+        });
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let created = json(&resp);
+        assert_eq!(created["source"]["ingest_port"], 5000);
+
+        // Receiving the backup of the other watcher
+        let mut payload = watcher_payload();
+        payload["source"]["ingest_port"] = json!(5010);
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(json(&resp)["watcher_id"], created["id"]);
+    }
+
+    #[tokio::test]
+    async fn create_watcher_with_unlisted_secret() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        // No `Secret` is made for the actions
+        payload["stream_loss"] = json!({
+            "duration": 3,
+            "transitions": [{"from": "content", "to": "lost", "actions": [{
+                "type": "http_call", "method": "POST", "url": "http://noc/lost",
+                "authorization": {"secretRef": "noc/token"}
+            }]}]
+        });
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(json(&resp)["errors"][0]["field"], "actions");
+    }
+
+    #[tokio::test]
+    async fn create_watcher_with_disallowed_command() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["backup_sources"] = json!([{
+            "ingest_port": 5010,
+            "container": "mpeg-ts",
+            "codec": "h264",
+            "transport": {"protocol": "rtp"}
+        }]);
+        // No command is allowed by the API
+        payload["failover"] = json!({
+            "duration": 3,
+            "transitions": [{"from": "content", "to": "backup", "actions": [{
+                "type": "exec", "command": "/opt/hawkeye/scripts/notify.sh"
+            }]}]
+        });
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            json(&resp)["errors"][0]["field"],
+            "failover.transitions[0].actions[0].command"
+        );
+    }
+
+    #[tokio::test]
+    async fn start_and_stop_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["message"], "Watcher is starting");
+
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["message"], "Watcher is already running");
+
+        let resp = call(&backend, "POST", &format!("/v1/watchers/{}/stop", id), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["message"], "Watcher is stopping");
+        assert_eq!(
+            backend.get_watcher_status(&id).await.unwrap(),
+            Some(Status::Ready)
+        );
+    }
+
+    #[tokio::test]
+    async fn reload_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/reload", id);
+
+        let resp = call(&backend, "POST", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        // There is no worker in the memory backend
+        let resp = call(&backend, "POST", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn set_watcher_threshold() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/config/threshold", id);
+
+        let resp = call(&backend, "PUT", &path, Some(json!({"threshold": 0.5}))).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        let resp = call(
+            &backend,
+            "PUT",
+            &path,
+            Some(json!({"slate_id": "unknown", "threshold": 0.5})),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // There is no worker in the memory backend
+        let resp = call(&backend, "PUT", &path, Some(json!({"threshold": 0.5}))).await;
+        assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn set_watcher_mode() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/mode", id);
+
+        let resp = call(&backend, "PUT", &path, Some(json!({"mode": "monitor"}))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let watcher = backend.get_watcher_config(&id).await.unwrap().unwrap();
+        assert_eq!(watcher.mode, Some(WatcherMode::Monitor));
+
+        let resp = call(
+            &backend,
+            "PUT",
+            "/v1/watchers/unknown/mode",
+            Some(json!({"mode": "active"})),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn calibrate_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/calibrate", id);
+
+        let resp = call(&backend, "GET", &format!("{}?duration=601", path), None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = call(&backend, "GET", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        // There is no worker in the memory backend
+        let resp = call(&backend, "GET", &format!("{}?duration=5", path), None).await;
+        assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn record_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/record", id);
+
+        let resp = call(&backend, "POST", &format!("{}?duration=121", path), None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = call(&backend, "POST", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+
+        let mut payload = watcher_payload();
+        payload["archive"] = json!({"url": "s3://incidents/hawkeye"});
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        let id = json(&resp)["id"].as_str().unwrap().to_string();
+        let path = format!("/v1/watchers/{}/record", id);
+
+        let resp = call(&backend, "POST", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        // There is no worker in the memory backend
+        let resp = call(&backend, "POST", &format!("{}?duration=5", path), None).await;
+        assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn watcher_heartbeat() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        backend.set_status(&id, Status::Running);
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}", id), None).await;
+        assert_eq!(json(&resp)["last_heartbeat"], Value::Null);
+        assert_eq!(json(&resp)["stale"], Value::Null);
+
+        let heartbeat = json!({
+            "timestamp": "2021-12-01T23:00:00.000Z",
+            "frames_processed": 7200,
+            "mode": "content",
+            "version": "0.1.0"
+        });
+        let path = format!("/v1/watchers/{}/heartbeat", id);
+        let resp = call(&backend, "POST", &path, Some(heartbeat.clone())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}", id), None).await;
+        let watcher = json(&resp);
+        assert_eq!(watcher["last_heartbeat"]["frames_processed"], 7200);
+        // Timestamped by the API when received
+        assert_ne!(
+            watcher["last_heartbeat"]["timestamp"],
+            heartbeat["timestamp"]
+        );
+        assert_eq!(watcher["stale"], false);
+
+        let resp = call(
+            &backend,
+            "POST",
+            "/v1/watchers/missing/heartbeat",
+            Some(heartbeat),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn report_and_query_events() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/events", id);
+        let events = json!([
+            {
+                "timestamp": "2021-12-01T23:00:05.250Z",
+                "kind": "transition",
+                "slate_id": "default",
+                "from": "content",
+                "to": "slate"
+            },
+            {
+                "timestamp": "2021-12-01T23:00:05.400Z",
+                "kind": "action",
+                "slate_id": "default",
+                "from": "content",
+                "to": "slate",
+                "action": "http_call POST https://ads.example.com/break",
+                "success": true,
+                "latency_ms": 150
+            }
+        ]);
+        let resp = call(&backend, "POST", &path, Some(events)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call(
+            &backend,
+            "POST",
+            "/v1/watchers/missing/events",
+            Some(json!([])),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let range = "from=2021-12-01T00:00:00Z&to=2021-12-02T00:00:00Z";
+        let resp = call(&backend, "GET", &format!("{}?{}", path, range), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp).as_array().unwrap().len(), 2);
+
+        let query = format!("{}?{}&kind=action", path, range);
+        let resp = call(&backend, "GET", &query, None).await;
+        assert_eq!(json(&resp)[0]["latency_ms"], 150);
+        assert_eq!(json(&resp).as_array().unwrap().len(), 1);
+
+        // Nothing happened in the last day
+        let resp = call(&backend, "GET", &path, None).await;
+        assert_eq!(json(&resp), json!([]));
+
+        let query = format!("{}?from=2021-10-01T00:00:00Z&to=2021-12-02T00:00:00Z", path);
+        let resp = call(&backend, "GET", &query, None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = call(&backend, "GET", &format!("{}?from=yesterday", path), None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn watcher_analytics() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let events = json!([
+            {"timestamp": "2021-12-01T10:00:00.000Z", "kind": "detection",
+             "from": "content", "to": "slate"},
+            {"timestamp": "2021-12-01T10:00:00.200Z", "kind": "action", "success": false},
+            {"timestamp": "2021-12-01T10:00:30.000Z", "kind": "detection",
+             "from": "slate", "to": "content"}
+        ]);
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/events", id),
+            Some(events),
+        )
+        .await;
+
+        let path = format!("/v1/watchers/{}/analytics", id);
+        let query = format!("{}?from=2021-12-01&to=2021-12-02", path);
+        let resp = call(&backend, "GET", &query, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let analytics = json(&resp);
+        assert_eq!(analytics["days"].as_array().unwrap().len(), 2);
+        assert_eq!(analytics["days"][0]["slate_seconds"], 30.0);
+        assert_eq!(analytics["days"][0]["action_failure_rate"], 1.0);
+        assert_eq!(analytics["days"][1]["slate_periods"], 0);
+
+        let query = format!("{}?from=2021-10-01&to=2021-12-02", path);
+        let resp = call(&backend, "GET", &query, None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = call(&backend, "GET", "/v1/watchers/missing/analytics", None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn trigger_transition() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/trigger", id);
+        let trigger = json!({"from": "content", "to": "slate"});
+
+        let resp = call(&backend, "POST", &path, Some(trigger.clone())).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        let resp = call(
+            &backend,
+            "POST",
+            &path,
+            Some(json!({"slate_id": "unknown", "from": "content", "to": "slate"})),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // There is no worker in the memory backend
+        let resp = call(&backend, "POST", &path, Some(trigger)).await;
+        assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn replay_action() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let history = format!("/v1/watchers/{}/actions/history", id);
+        let replay = format!("/v1/watchers/{}/actions/3/replay", id);
+
+        let resp = call(&backend, "GET", &history, None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+        let resp = call(&backend, "POST", &replay, None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        // There is no worker in the memory backend
+        let resp = call(&backend, "GET", &history, None).await;
+        assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+        let resp = call(&backend, "POST", &replay, None).await;
+        assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn start_pending_or_failed_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+
+        backend.set_status(&id, Status::Pending);
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        backend.set_status(&id, Status::Error);
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn stop_watcher_with_grace_period() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        backend.set_status(&id, Status::Running);
+        let path = format!("/v1/watchers/{}/stop", id);
+
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("{}?grace_seconds=3600", path),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("{}?grace_seconds=10", path),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn suspend_and_resume_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        backend.set_status(&id, Status::Running);
+
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/suspend", id),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}", id), None).await;
+        assert_eq!(json(&resp)["status"], "ready");
+        assert_eq!(json(&resp)["suspended"], true);
+
+        let start = format!("/v1/watchers/{}/start", id);
+        let resp = call(&backend, "POST", &start, None).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        let resp = call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/resume", id),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call(&backend, "POST", &start, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn audit_log_records_changes() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}", id), None).await;
+        let mut payload = watcher_payload();
+        payload["name"] = json(&resp)["name"].clone();
+        payload["source"]["ingest_port"] = json!(5001);
+        let resp = call(
+            &backend,
+            "PUT",
+            &format!("/v1/watchers/{}", id),
+            Some(payload),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        call(
+            &backend,
+            "POST",
+            &format!("/v1/watchers/{}/start", id),
+            None,
+        )
+        .await;
+
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}/audit", id), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let entries = json(&resp);
+        let actions: Vec<&str> = entries
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["action"].as_str().unwrap())
+            .collect();
+        assert_eq!(actions, vec!["create", "update", "start"]);
+        assert!(entries[0]["actor"]
+            .as_str()
+            .unwrap()
+            .starts_with("api-key:"));
+        assert_eq!(
+            entries[1]["changes"],
+            json!([{ "path": "source.ingest_port", "from": 5000, "to": 5001 }])
+        );
+    }
+
+    #[tokio::test]
+    async fn upgrade_running_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}/upgrade", id);
+
+        backend.set_status(&id, Status::Running);
+        let resp = call(&backend, "POST", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = call(&backend, "POST", &format!("{}?restart=true", path), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["status"], "running");
+
+        backend.set_status(&id, Status::Ready);
+        let resp = call(&backend, "POST", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn upgrade_watchers_pauses_on_error() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut ids = vec![create(&backend).await, create(&backend).await];
+        ids.sort();
+        backend.set_status(&ids[0], Status::Running);
+
+        // A watcher that cannot be upgraded without a restart does not pause the rollout
+        let resp = call(&backend, "POST", "/v1/watchers/upgrade", Some(json!({}))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = json(&resp);
+        assert_eq!(body["results"][0]["status"], 400);
+        assert_eq!(body["results"][1]["status"], 200);
+        assert_eq!(body["skipped"], json!([]));
+
+        backend.set_status(&ids[0], Status::Ready);
+        backend.fail_upgrades(&ids[0]);
+        let resp = call(&backend, "POST", "/v1/watchers/upgrade", Some(json!({}))).await;
+        let body = json(&resp);
+        assert_eq!(body["results"][0]["status"], 500);
+        assert_eq!(body["skipped"], json!([ids[1]]));
+
+        let strategy = json!({ "strategy": { "pause_on_error": false } });
+        let resp = call(&backend, "POST", "/v1/watchers/upgrade", Some(strategy)).await;
+        let body = json(&resp);
+        assert_eq!(body["results"][1]["status"], 200);
+        assert_eq!(body["skipped"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn update_watcher_keeps_ingest_port() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let mut payload = watcher_payload();
+        payload["description"] = json!("Updated");
+        let resp = call(
+            &backend,
+            "PUT",
+            &format!("/v1/watchers/{}", id),
+            Some(payload),
+        )
+        .await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let updated = json(&resp);
+        assert_eq!(updated["description"], "Updated");
+        assert_eq!(updated["source"]["ingest_port"], 5000);
+    }
+
+    #[tokio::test]
+    async fn get_watcher_by_name() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}", id), None).await;
+        let name = json(&resp)["name"].as_str().unwrap().to_string();
+
+        let resp = call(
+            &backend,
+            "GET",
+            &format!("/v1/watchers/by-name/{}", name),
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["id"], id.as_str());
+        let resp = call(&backend, "GET", "/v1/watchers/by-name/unknown", None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let mut payload = watcher_payload();
+        payload["name"] = json!(name);
+        payload["source"]["ingest_port"] = json!(5002);
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload.clone())).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(json(&resp)["watcher_id"], id.as_str());
+
+        payload.as_object_mut().unwrap().remove("name");
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(json(&resp)["errors"][0]["field"], "name");
+    }
+
+    #[tokio::test]
+    async fn names_are_reserved() {
+        let backend = Arc::new(MemoryBackend::default());
+        // A watcher being created holds its name before it exists
+        assert_eq!(backend.reserve_name("channel-1", "a").await.unwrap(), None);
+        assert_eq!(
+            backend.reserve_name("channel-1", "b").await.unwrap(),
+            Some("a".to_string())
+        );
+        let mut payload = watcher_payload();
+        payload["name"] = json!("channel-1");
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload.clone())).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(json(&resp)["watcher_id"], "a");
+        backend.release_name("channel-1", "a").await.unwrap();
+
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload.clone())).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let id = json(&resp)["id"].as_str().unwrap().to_string();
+        let path = format!("/v1/watchers/{}", id);
+
+        // Renamed, the watcher gives up its previous name
+        let mut renamed = payload.clone();
+        renamed["name"] = json!("channel-2");
+        let resp = call(&backend, "PUT", &path, Some(renamed)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        payload["source"]["ingest_port"] = json!(5002);
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn import_watcher_by_name() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["name"] = json!("channel-1");
+        payload["tags"] = json!({"region": "eu"});
+        let definition = serde_yaml::to_string(&payload).unwrap();
+
+        let resp = import(&backend, definition.clone()).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let id = json(&resp)["id"].as_str().unwrap().to_string();
+
+        let path = format!("/v1/watchers/{}/export?format=yaml", id);
+        let resp = call(&backend, "GET", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "application/yaml");
+        let exported: Value = serde_yaml::from_slice(resp.body()).unwrap();
+        assert_eq!(exported["name"], "channel-1");
+        assert_eq!(exported["tags"]["region"], "eu");
+        assert!(exported.get("id").is_none());
+        assert!(exported.get("status").is_none());
+
+        // Importing the export again changes nothing
+        let resp = import(&backend, String::from_utf8(resp.body().to_vec()).unwrap()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["id"], id.as_str());
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}/audit", id), None).await;
+        assert_eq!(json(&resp).as_array().unwrap().len(), 1);
+
+        payload["description"] = json!("Updated");
+        let resp = import(&backend, serde_yaml::to_string(&payload).unwrap()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["id"], id.as_str());
+        assert_eq!(json(&resp)["description"], "Updated");
+
+        let resp = import(&backend, definition.replace("name: channel-1\n", "")).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn update_pending_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        backend.set_status(&id, Status::Pending);
+        let path = format!("/v1/watchers/{}", id);
+        let resp = call(&backend, "PUT", &path, Some(watcher_payload())).await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn delete_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}", id);
+
+        let resp = call(&backend, "DELETE", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call(&backend, "DELETE", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = call(&backend, "GET", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn restore_deleted_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}", id);
+        call(&backend, "POST", &format!("{}/start", path), None).await;
+
+        let resp = call(&backend, "DELETE", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            backend.get_watcher_status(&id).await.unwrap(),
+            Some(Status::Ready)
+        );
+        let resp = call(&backend, "GET", "/v1/watchers", None).await;
+        assert_eq!(json(&resp), json!([]));
+        let resp = call(&backend, "GET", "/v1/watchers?include_deleted=true", None).await;
+        assert!(json(&resp)[0]["deleted_at"].is_string());
+        let resp = call(&backend, "POST", &format!("{}/start", path), None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = call(&backend, "POST", &format!("{}/restore", path), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call(&backend, "GET", &path, None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["status"], "ready");
+        assert!(json(&resp).get("deleted_at").is_none());
+    }
+
+    #[tokio::test]
+    async fn deleted_watcher_releases_its_ports() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        let path = format!("/v1/watchers/{}", id);
+        call(&backend, "DELETE", &path, None).await;
+
+        let resp = call(&backend, "POST", "/v1/watchers", Some(watcher_payload())).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let other_id = json(&resp)["id"].as_str().unwrap().to_string();
+        assert_eq!(json(&resp)["source"]["ingest_port"], 5000);
+        let resp = call(&backend, "GET", "/v1/watchers?limit=1", None).await;
+        assert_eq!(json(&resp)[0]["id"], other_id.as_str());
+
+        let resp = call(&backend, "POST", &format!("{}/restore", path), None).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(json(&resp)["watcher_id"], other_id.as_str());
+    }
+
+    #[tokio::test]
+    async fn diff_of_memory_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+
+        let resp = call(&backend, "GET", &format!("/v1/watchers/{}/diff", id), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp), json!({ "drift": false, "differences": [] }));
+        let resp = call(&backend, "GET", "/v1/watchers/missing/diff", None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn login_needs_oidc() {
+        let backend = Arc::new(MemoryBackend::default());
+        let resp = call(&backend, "GET", "/v1/auth/login", None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_IMPLEMENTED);
+
+        // Without the state cookie of the login
+        let resp = call(
+            &backend,
+            "GET",
+            "/v1/auth/callback?code=abc&state=def",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn rollback_to_revision() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["description"] = json!("Good");
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload.clone())).await;
+        let path = format!("/v1/watchers/{}", json(&resp)["id"].as_str().unwrap());
+        payload["description"] = json!("Bad");
+        call(&backend, "PUT", &path, Some(payload)).await;
+
+        let resp = call(&backend, "GET", &format!("{}/revisions", path), None).await;
+        let revisions = json(&resp);
+        assert_eq!(revisions[0]["revision"], 1);
+        assert_eq!(revisions[0]["action"], "create");
+        assert_eq!(revisions[1]["watcher"]["description"], "Bad");
+
+        let resp = call(&backend, "POST", &format!("{}/rollback/1", path), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(&resp)["description"], "Good");
+        let resp = call(&backend, "GET", &format!("{}/revisions", path), None).await;
+        assert_eq!(json(&resp)[2]["action"], "rollback");
+        let resp = call(&backend, "POST", &format!("{}/rollback/9", path), None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn list_watchers_in_pages() {
+        let backend = Arc::new(MemoryBackend::default());
+        for _ in 0..3 {
+            create(&backend).await;
+        }
+
+        let resp = call(&backend, "GET", "/v1/watchers?limit=2", None).await;
+        assert_eq!(json(&resp).as_array().unwrap().len(), 2);
+        let token = resp.headers()["x-continue-token"].to_str().unwrap();
+
+        let path = format!("/v1/watchers?limit=2&continue={}", token);
+        let resp = call(&backend, "GET", &path, None).await;
+        assert_eq!(json(&resp).as_array().unwrap().len(), 1);
+        assert!(resp.headers().get("x-continue-token").is_none());
+    }
+
+    #[tokio::test]
+    async fn delete_slate_used_by_watcher() {
+        let backend = Arc::new(MemoryBackend::default());
+        let mut payload = watcher_payload();
+        payload["slate_url"] = json!("slate://0c6f1a52-3b1e-4d0e-a1a4-5d2f0e9c7b11");
+        let resp = call(&backend, "POST", "/v1/watchers", Some(payload)).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let resp = call(
+            &backend,
+            "DELETE",
+            "/v1/slates/0c6f1a52-3b1e-4d0e-a1a4-5d2f0e9c7b11",
+            None,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn request_without_token() {
+        let backend = Arc::new(MemoryBackend::default());
+        let resp = warp::test::request()
+            .path("/v1/watchers")
+            .reply(&v1(backend))
+            .await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn frames_without_token() {
+        let backend = Arc::new(MemoryBackend::default());
+        let id = create(&backend).await;
+        for path in ["video-frame", "preview"].iter() {
+            let resp = warp::test::request()
+                .path(&format!("/v1/watchers/{}/{}", id, path))
+                .reply(&v1(backend.clone()))
+                .await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn probes_without_token() {
+        let backend = Arc::new(MemoryBackend::default());
+        for path in ["/healthz", "/readyz"].iter() {
+            let resp = warp::test::request()
+                .path(path)
+                .reply(&v1(backend.clone()))
+                .await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+    }
 }
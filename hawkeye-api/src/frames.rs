@@ -0,0 +1,158 @@
+//! Keeps the latest video frame fetched from each worker for a short time, so clients polling
+//! the frames do not call the worker pod on every request.
+//!
+//! Concurrent requests for the frame of the same watcher wait for a single call to the worker.
+//! Each frame gets an `ETag`, letting clients revalidate it with `If-None-Match`. The previews
+//! streamed to the clients read the frames from the cache too.
+use crate::backend::Backend;
+use crate::config::FRAME_CACHE_TTL;
+use futures::{stream, Stream};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::hyper::body::Bytes;
+
+lazy_static! {
+    static ref FRAMES: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<Frame>>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// A video frame captured by the worker.
+#[derive(Clone)]
+pub struct Frame {
+    pub image: Bytes,
+    /// Quoted strong entity tag of the image.
+    pub etag: String,
+    fetched_at: Instant,
+}
+
+impl Frame {
+    fn new(image: Bytes) -> Self {
+        let digest = hex::encode(Sha256::digest(&image));
+        Frame {
+            etag: format!("\"{}\"", &digest[..32]),
+            image,
+            fetched_at: Instant::now(),
+        }
+    }
+
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        self.fetched_at.elapsed() < ttl
+    }
+}
+
+/// Latest video frame of the watcher, fetched from the worker unless a fresh one is cached.
+pub async fn latest(backend: &Backend, id: &str) -> anyhow::Result<Option<Frame>> {
+    let ttl = Duration::from_secs(*FRAME_CACHE_TTL);
+    if ttl == Duration::from_secs(0) {
+        return Ok(backend.get_video_frame(id).await?.map(Frame::new));
+    }
+
+    let slot = slot(id, ttl);
+    let mut cached = slot.lock().await;
+    if let Some(frame) = cached.as_ref().filter(|frame| frame.is_fresh(ttl)) {
+        return Ok(Some(frame.clone()));
+    }
+    let frame = backend.get_video_frame(id).await?.map(Frame::new);
+    *cached = frame.clone();
+    Ok(frame)
+}
+
+/// Boundary between the frames of the multipart preview stream.
+pub const PREVIEW_BOUNDARY: &str = "hawkeye-frame";
+
+/// Streams the frames of the watcher as the parts of a `multipart/x-mixed-replace` body, checking
+/// for a new frame every `period`. The stream ends when the worker has no frame to serve.
+pub fn preview(
+    backend: Backend,
+    id: String,
+    period: Duration,
+) -> impl Stream<Item = anyhow::Result<Bytes>> {
+    let ticks = tokio::time::interval(period);
+    stream::unfold(
+        (backend, id, ticks, None),
+        |(backend, id, mut ticks, last_etag): (_, _, _, Option<String>)| async move {
+            loop {
+                ticks.tick().await;
+                match latest(&backend, &id).await {
+                    // Unchanged frames are not sent again, clients keep showing the last part
+                    Ok(Some(frame)) if last_etag.as_ref() == Some(&frame.etag) => continue,
+                    Ok(Some(frame)) => {
+                        let part = preview_part(&frame);
+                        return Some((Ok(part), (backend, id, ticks, Some(frame.etag))));
+                    }
+                    Ok(None) => return None,
+                    Err(e) => {
+                        log::error!("Could not get the video frame of watcher {}: {:?}", id, e);
+                        return None;
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Encodes the frame as one part of the preview stream.
+fn preview_part(frame: &Frame) -> Bytes {
+    let header = format!(
+        "--{}\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+        PREVIEW_BOUNDARY,
+        frame.image.len()
+    );
+    let mut part = Vec::with_capacity(header.len() + frame.image.len() + 2);
+    part.extend_from_slice(header.as_bytes());
+    part.extend_from_slice(&frame.image);
+    part.extend_from_slice(b"\r\n");
+    Bytes::from(part)
+}
+
+/// Whether the `If-None-Match` header sent by the client matches the entity tag.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Cache entry of the watcher, the stale entries of other watchers are dropped when a new one
+/// is added.
+fn slot(id: &str, ttl: Duration) -> Arc<tokio::sync::Mutex<Option<Frame>>> {
+    let mut frames = FRAMES.lock().unwrap();
+    if !frames.contains_key(id) {
+        frames.retain(|_, slot| match slot.try_lock() {
+            Ok(cached) => cached.as_ref().map_or(false, |frame| frame.is_fresh(ttl)),
+            Err(_) => true,
+        });
+    }
+    frames.entry(id.to_string()).or_default().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_if_none_match_header() {
+        let frame = Frame::new(Bytes::from_static(b"png"));
+        assert!(etag_matches(&frame.etag, &frame.etag));
+        assert!(etag_matches(
+            &format!("\"other\", W/{}", frame.etag),
+            &frame.etag
+        ));
+        assert!(etag_matches("*", &frame.etag));
+        assert!(!etag_matches("\"other\"", &frame.etag));
+    }
+
+    #[test]
+    fn encodes_preview_parts() {
+        let frame = Frame::new(Bytes::from_static(b"png"));
+        assert_eq!(
+            preview_part(&frame),
+            Bytes::from_static(
+                b"--hawkeye-frame\r\nContent-Type: image/png\r\nContent-Length: 3\r\n\r\npng\r\n"
+            )
+        );
+    }
+}
@@ -1,560 +1,3023 @@
-use crate::config::{CALL_WATCHER_TIMEOUT, NAMESPACE};
-use crate::templates;
-use crate::templates::container_spec;
-use hawkeye_core::models::{Status, Watcher};
-use k8s_openapi::api::apps::v1::Deployment;
-use k8s_openapi::api::core::v1::{ConfigMap, Pod, Service};
-use kube::api::{DeleteParams, ListParams, Patch, PatchParams, PostParams};
-use kube::{Api, Client};
+use crate::analytics::{self, Analytics};
+use crate::audit;
+use crate::auth::Grant;
+use crate::backend::{Backend, ListQuery, LogQuery, StatusChange};
+use crate::config::{
+    ACTION_SECRETS, DOCKER_IMAGE, EXEC_ALLOWLIST, NAMESPACE, NAMESPACES, OPERATOR_MODE,
+    PACK_BITRATE, RTMPS_CERTIFICATE, SHARED_SERVICE, WORKER_GRACE_PERIOD, WORKER_IMAGES,
+};
+use crate::events::{self, EventQuery};
+use crate::frames;
+use crate::metrics;
+use crate::oidc::{self, ClientCredentials};
+use crate::openapi;
+use crate::rate_limit;
+use crate::request_id;
+use crate::revisions;
+use crate::scheduler;
+use crate::slates;
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use futures::future::join_all;
+use futures::StreamExt;
+use hawkeye_core::models::{
+    Action, Heartbeat, ModeChange, Protocol, Status, ThresholdChange, TransitionTrigger,
+    ValidationErrors, Watcher, WatcherEvent, WatcherEventKind, DEFAULT_CALIBRATION_SECONDS,
+    DEFAULT_RECORDING_SECONDS, MAX_CALIBRATION_SECONDS, MAX_RECORDING_SECONDS,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
 use std::convert::Infallible;
-use std::time::Duration;
 use uuid::Uuid;
-use warp::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use warp::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, LOCATION, SET_COOKIE};
 use warp::http::{HeaderValue, StatusCode};
+use warp::hyper::body::Bytes;
 use warp::hyper::Body;
+use warp::multipart::FormData;
+use warp::path::{FullPath, Tail};
 use warp::reply;
+use warp::Reply;
 
-pub async fn list_watchers(client: Client) -> Result<impl warp::Reply, Infallible> {
-    let lp = ListParams::default()
-        .labels("app=hawkeye,watcher_id")
-        .timeout(10);
+/// Query parameters accepted while listing watchers.
+#[derive(Deserialize, Debug, Default)]
+pub struct ListOptions {
+    /// Maximum number of watchers returned in a single page.
+    pub limit: Option<u32>,
+    /// Token returned in the `X-Continue-Token` header to fetch the next page.
+    #[serde(rename = "continue")]
+    pub continue_token: Option<String>,
+    /// Only return watchers in this status.
+    pub status: Option<Status>,
+    /// Comma separated list of `key:value` tags the watchers must have.
+    pub tag: Option<String>,
+    /// Also return the deleted watchers that were not purged yet.
+    pub include_deleted: Option<bool>,
+}
+
+/// Parses a comma separated list of `key:value` tags the watchers must have.
+fn parse_tags(tags: Option<&str>) -> Result<Vec<(String, String)>, String> {
+    let mut parsed = Vec::new();
+    for tag in tags.iter().flat_map(|t| t.split(',')) {
+        match tag.split_once(':') {
+            Some((key, value)) if !key.is_empty() => {
+                parsed.push((key.to_string(), value.to_string()))
+            }
+            _ => return Err(format!("Invalid tag filter '{}', expected key:value", tag)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Reply used when the client is not granted the watchers of the request.
+fn forbidden(message: String) -> reply::WithStatus<reply::Json> {
+    reply::with_status(
+        reply::json(&json!({ "message": message })),
+        StatusCode::FORBIDDEN,
+    )
+}
+
+/// Rejects the definition of a watcher that would leave the tags the grant of the client is
+/// limited to.
+fn check_grant_tags(grant: &Grant, watcher: &Watcher) -> Option<reply::WithStatus<reply::Json>> {
+    if grant.covers(watcher.tags.as_ref()) {
+        return None;
+    }
+    let tags: Vec<String> = grant
+        .tags
+        .iter()
+        .map(|(key, value)| format!("{}:{}", key, value))
+        .collect();
+    Some(forbidden(format!(
+        "The watcher needs the tags {}",
+        tags.join(",")
+    )))
+}
+
+/// Rejects the IDs of a bulk request that are not all in reach of the client.
+async fn check_grant_ids(
+    backend: &Backend,
+    grant: &Grant,
+    ids: &[String],
+) -> Option<reply::WithStatus<reply::Json>> {
+    if !grant.is_restricted() {
+        return None;
+    }
+    let owned = match list_live_ids(backend, &grant.tags).await {
+        Ok(owned) => owned,
+        Err(e) => return Some(backend_error(e)),
+    };
+    let others: Vec<&String> = ids.iter().filter(|id| !owned.contains(id)).collect();
+    if others.is_empty() {
+        return None;
+    }
+    Some(forbidden(format!(
+        "Not granted on watchers {}",
+        others
+            .iter()
+            .map(|id| id.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    )))
+}
+
+/// Reply used when the backend fails to complete a request.
+fn backend_error(e: anyhow::Error) -> reply::WithStatus<reply::Json> {
+    let msg: String = format!("Error while calling Kubernetes API: {:?}", e);
+    log::error!(
+        "{} (request_id={})",
+        msg,
+        request_id::current().unwrap_or_default()
+    );
+    reply::with_status(
+        reply::json(&json!({ "message": msg })),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+}
+
+fn not_found() -> reply::WithStatus<reply::Json> {
+    reply::with_status(reply::json(&json!({})), StatusCode::NOT_FOUND)
+}
+
+/// Reply used when the events are asked for without an event store configured.
+fn events_error(e: anyhow::Error) -> reply::WithStatus<reply::Json> {
+    if e.is::<events::NotConfigured>() {
+        return reply::with_status(
+            reply::json(&json!({ "message": e.to_string() })),
+            StatusCode::NOT_IMPLEMENTED,
+        );
+    }
+    backend_error(e)
+}
+
+/// List all watchers.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers",
+    params(
+        (
+            "limit" = Option<u32>,
+            query,
+            description = "Maximum number of watchers to return in a single page."
+        ),
+        (
+            "continue" = Option<String>,
+            query,
+            description = "Token from the `X-Continue-Token` header of the previous page."
+        ),
+        (
+            "status" = Option<String>,
+            query,
+            description = "Only return watchers in this status. Filtered pages may contain \
+                less watchers than `limit`."
+        ),
+        (
+            "tag" = Option<String>,
+            query,
+            description = "Comma separated list of `key:value` tags the watchers must have."
+        ),
+        (
+            "include_deleted" = Option<bool>,
+            query,
+            description = "Also return the deleted watchers that were not purged yet."
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Successfull response.",
+            body = [Watcher],
+            headers((
+                "X-Continue-Token" = String,
+                description = "Present when there are more watchers to list, use it as the \
+                    `continue` parameter."
+            ))
+        ),
+        (
+            status = 429,
+            description = "The client is over the rate limit, or too many requests are being \
+                served.",
+            headers(("Retry-After" = u64, description = "Seconds to wait before calling again."))
+        ),
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn list_watchers(
+    grant: Grant,
+    options: ListOptions,
+    _permit: rate_limit::Permit,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut tags = match parse_tags(options.tag.as_deref()) {
+        Ok(tags) => tags,
+        Err(message) => {
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+
+    // Only the watchers in reach of the client are listed
+    tags.extend(grant.tags);
+    let query = ListQuery {
+        limit: options.limit,
+        continue_token: options.continue_token,
+        tags,
+        name: None,
+        include_deleted: options.include_deleted == Some(true),
+    };
+    let mut page = match backend.list_watchers(&query).await {
+        Ok(page) => page,
+        Err(e) => return Ok(backend_error(e).into_response()),
+    };
+
+    // The status is calculated, so it cannot be part of the query. A filtered page may contain
+    // less watchers than the requested limit.
+    if let Some(status) = options.status {
+        page.watchers.retain(|w| w.status == Some(status));
+    }
+
+    let mut resp = reply::json(&page.watchers).into_response();
+    if let Some(token) = page.continue_token {
+        if let Ok(value) = HeaderValue::from_str(&token) {
+            resp.headers_mut().insert("x-continue-token", value);
+        }
+    }
+    Ok(resp)
+}
+
+/// Create a new Watcher.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers",
+    request_body = Watcher,
+    responses(
+        (status = 201, description = "Successfull response.", body = Watcher),
+        (
+            status = 409,
+            description = "The ingest port, or the name, is already used by another Watcher.",
+            body = Conflict
+        ),
+        (status = 422, description = "The Watcher definition is not valid.", body = Invalid),
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn create_watcher(
+    grant: Grant,
+    mut watcher: Watcher,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.create_watcher: {:?}", watcher);
+
+    watcher.namespace.get_or_insert_with(|| NAMESPACE.clone());
+    if watcher.source.ingest_port.is_none() {
+        match backend.allocate_ingest_port(&watcher.source).await {
+            Ok(Some(port)) => {
+                tracing::debug!("Allocated ingest port {}", port);
+                watcher.source.ingest_port = Some(port);
+            }
+            Ok(None) => {
+                return Ok(reply::with_status(
+                    reply::json(&json!({
+                        "message": "No ingest port available in the configured range"
+                    })),
+                    StatusCode::CONFLICT,
+                ))
+            }
+            Err(e) => return Ok(backend_error(e)),
+        }
+    }
+
+    if let Err(errors) = validate_watcher(&watcher) {
+        return Ok(validation_failed(errors));
+    }
+    if let Some(reply) = check_grant_tags(&grant, &watcher) {
+        return Ok(reply);
+    }
+    if let Some(reply) = check_port_conflict(&backend, &watcher, None).await {
+        return Ok(reply);
+    }
+    let new_id = Uuid::new_v4().to_string();
+    if let Some(reply) = check_name_conflict(&backend, &watcher, &new_id).await {
+        return Ok(reply);
+    }
+
+    watcher.id = Some(new_id.clone());
+    watcher.suspended = None;
+    watcher.deleted_at = None;
+    // The backend assigns the pack of the packed watchers
+    watcher.pack = None;
+    watcher.last_heartbeat = None;
+    watcher.stale = None;
+
+    if let Err(e) = backend.create_watcher(&new_id, &watcher).await {
+        return Ok(backend_error(e));
+    }
+    let changes = audit::diff(&json!({}), &json!(watcher));
+    audit::record(&backend, &new_id, &actor, "create", changes).await;
+    revisions::record(&backend, &new_id, &actor, "create", &definition(&watcher)).await;
+
+    watcher.status = Some(Status::Pending);
+    watcher.source.ingest_ip = None;
+
+    Ok(reply::with_status(
+        reply::json(&watcher),
+        StatusCode::CREATED,
+    ))
+}
+
+/// Replace the definition of an existing Watcher.
+///
+/// The ingest IP of the Watcher is preserved. A running Watcher is restarted to pick up the new
+/// configuration, unless only its slates and transitions changed, which its worker reloads.
+#[utoipa::path(
+    put,
+    path = "/v1/watchers/{watcher_id}",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    request_body = Watcher,
+    responses(
+        (status = 200, description = "Successfull response.", body = Watcher),
+        (
+            status = 409,
+            description = "The ingest port, or the name, is already used by another Watcher.",
+            body = Conflict
+        ),
+        (status = 422, description = "The Watcher definition is not valid.", body = Invalid),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn update_watcher(
+    id: String,
+    grant: Grant,
+    watcher: Watcher,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.update_watcher: {} {:?}", id, watcher);
+    Ok(replace_watcher(&id, &grant, watcher, &actor, "update", &backend).await)
+}
+
+/// Replaces the definition of the watcher, recording the change as `action`.
+async fn replace_watcher(
+    id: &str,
+    grant: &Grant,
+    mut watcher: Watcher,
+    actor: &str,
+    action: &str,
+    backend: &Backend,
+) -> reply::WithStatus<reply::Json> {
+    let current = match get_live_config(backend, id).await {
+        Ok(Some(current)) => current,
+        Ok(None) => return not_found(),
+        Err(e) => return backend_error(e),
+    };
+    // Keep the current ingest port when it is not part of the payload
+    if watcher.source.ingest_port.is_none() {
+        watcher.source.ingest_port = current.source.ingest_port;
+    }
+    // Moving a watcher in or out of a pack needs to create it again
+    watcher.packed = current.packed;
+    watcher.pack = current.pack.clone();
+
+    if let Err(errors) = validate_watcher(&watcher) {
+        return validation_failed(errors);
+    }
+    if let Some(reply) = check_grant_tags(grant, &watcher) {
+        return reply;
+    }
+    if let Some(reply) = check_port_conflict(backend, &watcher, Some(id)).await {
+        return reply;
+    }
+    if let Some(reply) = check_name_conflict(backend, &watcher, id).await {
+        return reply;
+    }
+
+    let watcher_status = match backend.get_watcher_status(id).await {
+        Ok(Some(status)) => status,
+        Ok(None) => return not_found(),
+        Err(e) => return backend_error(e),
+    };
+    if watcher_status == Status::Pending {
+        return reply::with_status(
+            reply::json(&json!({
+                "message": "Watcher is currently updating"
+            })),
+            StatusCode::CONFLICT,
+        );
+    }
+
+    // Fields managed by the API are never taken from the payload
+    watcher.id = Some(id.to_string());
+    watcher.status = None;
+    watcher.status_description = None;
+    watcher.source.ingest_ip = None;
+    watcher.suspended = current.suspended;
+    watcher.deleted_at = None;
+    watcher.namespace = current.namespace.clone();
+    watcher.last_heartbeat = None;
+    watcher.stale = None;
+
+    if let Err(e) = backend.update_watcher(id, &watcher).await {
+        return backend_error(e);
+    }
+    if current.name != watcher.name {
+        release_name(backend, current.name.as_deref(), id).await;
+    }
+    let changes = audit::diff(&json!(current), &json!(watcher));
+    audit::record(backend, id, actor, action, changes).await;
+    revisions::record(backend, id, actor, action, &definition(&watcher)).await;
+
+    watcher.status = Some(watcher_status);
+    reply::with_status(reply::json(&watcher), StatusCode::OK)
+}
+
+/// Returns the revisions of the definition of a Watcher, oldest first.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/revisions",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "The revisions of the Watcher.", body = [Revision]),
+        (status = 404, description = "The Watcher does not exist."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_revisions(
+    id: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    match backend.list_revisions(&id).await {
+        Ok(Some(revisions)) => Ok(reply::with_status(reply::json(&revisions), StatusCode::OK)),
+        Ok(None) => Ok(not_found()),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Replace the definition of a Watcher with the one of a past revision, like `update_watcher`
+/// does. The rollback is stored as a new revision, so it can be rolled back too.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/rollback/{revision}",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        ("revision" = i64, path, description = "Number of the revision to roll back to."),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The Watcher with the definition of the revision.",
+            body = Watcher
+        ),
+        (
+            status = 404,
+            description = "The Watcher does not exist, or the revision is no longer kept."
+        ),
+        (
+            status = 409,
+            description = "The ingest port, or the name, is already used by another Watcher.",
+            body = Conflict
+        ),
+        (status = 422, description = "The Watcher definition is not valid.", body = Invalid),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn rollback_watcher(
+    id: String,
+    grant: Grant,
+    revision: u64,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.rollback_watcher: {} to revision {}", id, revision);
+    let revisions = match backend.list_revisions(&id).await {
+        Ok(Some(revisions)) => revisions,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
+    };
+    let watcher = match revisions.into_iter().find(|r| r.revision == revision) {
+        Some(found) => found.watcher,
+        None => {
+            return Ok(reply::with_status(
+                reply::json(&json!({
+                    "message": format!("Revision {} of the watcher is not kept", revision)
+                })),
+                StatusCode::NOT_FOUND,
+            ))
+        }
+    };
+    Ok(replace_watcher(&id, &grant, watcher, &actor, "rollback", &backend).await)
+}
+
+/// Reserves the name of the watcher, rejecting the request when it is already the one of another
+/// watcher, the names keying the imports. Checked last, the reservation being kept.
+async fn check_name_conflict(
+    backend: &Backend,
+    watcher: &Watcher,
+    id: &str,
+) -> Option<reply::WithStatus<reply::Json>> {
+    let name = watcher.name.as_deref()?;
+    match backend.reserve_name(name, id).await {
+        Ok(Some(other_id)) => Some(reply::with_status(
+            reply::json(&json!({
+                "message": format!("Name {} is already used by watcher {}", name, other_id),
+                "watcher_id": other_id,
+            })),
+            StatusCode::CONFLICT,
+        )),
+        Ok(None) => None,
+        Err(e) => Some(backend_error(e)),
+    }
+}
+
+/// Releases the name the watcher gave up. A failure is only logged, the reservation of a name
+/// the watcher does not have anymore being taken over by the next watcher wanting it.
+async fn release_name(backend: &Backend, name: Option<&str>, id: &str) {
+    if let Some(name) = name {
+        if let Err(e) = backend.release_name(name, id).await {
+            log::warn!(
+                "Could not release the name {} of watcher {}: {:?}",
+                name,
+                id,
+                e
+            );
+        }
+    }
+}
+
+/// The watcher named `name`, if any. The deleted watchers give up their name.
+async fn find_by_name(backend: &Backend, name: &str) -> anyhow::Result<Option<Watcher>> {
+    let query = ListQuery {
+        name: Some(name.to_string()),
+        ..ListQuery::default()
+    };
+    let page = backend.list_watchers(&query).await?;
+    Ok(page.watchers.into_iter().next())
+}
+
+/// The definition of the watcher, without the fields managed by the API, as exported and
+/// imported.
+fn definition(watcher: &Watcher) -> Watcher {
+    let mut definition = watcher.clone();
+    definition.id = None;
+    definition.status = None;
+    definition.status_description = None;
+    definition.suspended = None;
+    definition.deleted_at = None;
+    definition.pack = None;
+    definition.last_heartbeat = None;
+    definition.stale = None;
+    definition.source.ingest_ip = None;
+    definition
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportOptions {
+    /// `yaml` or `json`, the default.
+    pub format: Option<String>,
+}
+
+/// Returns the definition of a Watcher in a canonical form, its keys sorted, suited to store it
+/// in Git and import it again with `import_watcher`.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/export",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        ("format" = Option<String>, query, description = "`yaml` for YAML, JSON otherwise."),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The definition of the Watcher, in YAML with `format=yaml`.",
+            body = Watcher
+        ),
+        (status = 400, description = "Unsupported format."),
+        (status = 404, description = "Watcher not found."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn export_watcher(
+    id: String,
+    options: ExportOptions,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let watcher = match get_live_config(&backend, &id).await {
+        Ok(Some(watcher)) => watcher,
+        Ok(None) => return Ok(not_found().into_response()),
+        Err(e) => return Ok(backend_error(e).into_response()),
+    };
+    // The maps of the values are sorted by key
+    let value = json!(definition(&watcher));
+    let (body, content_type) = match options.format.as_deref() {
+        Some("yaml") => (
+            serde_yaml::to_string(&value).unwrap_or_default(),
+            "application/yaml",
+        ),
+        None | Some("json") => (
+            serde_json::to_string_pretty(&value).unwrap_or_default() + "\n",
+            "application/json",
+        ),
+        Some(format) => {
+            let message = format!("Unsupported format {}, expected yaml or json", format);
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+    Ok(reply::with_header(body, CONTENT_TYPE, content_type).into_response())
+}
+
+/// Creates or updates the Watcher with the `name` of the definition, in YAML or JSON as its
+/// content type tells. Importing the same definition again leaves the watcher unchanged.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/import",
+    request_body(
+        content = Watcher,
+        description = "The definition, in YAML with the `application/yaml` content type."
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The Watcher with this name was updated, or already had this definition.",
+            body = Watcher
+        ),
+        (status = 201, description = "The Watcher was created.", body = Watcher),
+        (status = 400, description = "The definition cannot be parsed."),
+        (
+            status = 409,
+            description = "The ingest port, or the name, is already used by another Watcher.",
+            body = Conflict
+        ),
+        (status = 422, description = "The definition has no name, or is not valid."),
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn import_watcher(
+    grant: Grant,
+    content_type: Option<String>,
+    body: Bytes,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let is_yaml = content_type.map_or(false, |t| t.contains("yaml"));
+    let parsed = if is_yaml {
+        serde_yaml::from_slice::<Watcher>(&body).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_slice::<Watcher>(&body).map_err(|e| e.to_string())
+    };
+    let mut watcher = match parsed {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": format!("Invalid Watcher definition: {}", e) })),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+    let name = match watcher.name.clone() {
+        Some(name) => name,
+        None => {
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": "The imported watchers need a name" })),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+            .into_response())
+        }
+    };
+    let current = match find_by_name(&backend, &name).await {
+        Ok(Some(current)) => current,
+        Ok(None) => {
+            tracing::debug!("v1.import_watcher: creating {}", name);
+            return Ok(create_watcher(grant, watcher, actor, backend)
+                .await?
+                .into_response());
+        }
+        Err(e) => return Ok(backend_error(e).into_response()),
+    };
+    if !grant.covers(current.tags.as_ref()) {
+        let message = format!("Not granted on the watcher named {}", name);
+        return Ok(forbidden(message).into_response());
+    }
+    let id = current.id.clone().unwrap_or_default();
+    let config = match backend.get_watcher_config(&id).await {
+        Ok(Some(config)) => config,
+        Ok(None) => return Ok(not_found().into_response()),
+        Err(e) => return Ok(backend_error(e).into_response()),
+    };
+    // Fill in what the API assigned, like `update_watcher` does, before comparing
+    if watcher.source.ingest_port.is_none() {
+        watcher.source.ingest_port = config.source.ingest_port;
+    }
+    watcher.namespace = config.namespace.clone();
+    watcher.packed = config.packed;
+    if definition(&watcher) == definition(&config) {
+        tracing::debug!("v1.import_watcher: {} is unchanged", name);
+        return Ok(reply::with_status(reply::json(&current), StatusCode::OK).into_response());
+    }
+    tracing::debug!("v1.import_watcher: updating {} ({})", name, id);
+    Ok(update_watcher(id, grant, watcher, actor, backend)
+        .await?
+        .into_response())
+}
+
+/// Rejects the request when the ingest port, or another port of the source, is already used by
+/// another watcher, since they would be competing for the same video stream.
+async fn check_port_conflict(
+    backend: &Backend,
+    watcher: &Watcher,
+    exclude_id: Option<&str>,
+) -> Option<reply::WithStatus<reply::Json>> {
+    let ports = watcher.ports();
+    match backend.find_port_conflict(&ports, exclude_id).await {
+        Ok(None) => None,
+        Ok(Some(watcher_id)) => Some(reply::with_status(
+            reply::json(&json!({
+                "message": format!("Ports {:?} of the sources are already used by watcher {}", ports, watcher_id),
+                "watcher_id": watcher_id,
+            })),
+            StatusCode::CONFLICT,
+        )),
+        Err(e) => Some(backend_error(e)),
+    }
+}
+
+/// Validates the `Watcher` definition, including the settings only this API can check.
+pub fn validate_watcher(watcher: &Watcher) -> Result<(), ValidationErrors> {
+    let mut errors = watcher.validate().err().unwrap_or_default();
+    if watcher.name.is_none() {
+        errors.add("name", "A name is required");
+    }
+    if let Some(schedule) = watcher.schedule.as_ref() {
+        scheduler::validate(schedule, &mut errors);
+    }
+    if let Some(namespace) = watcher.namespace.as_deref() {
+        if !NAMESPACES.iter().any(|n| n == namespace) {
+            errors.add(
+                "namespace",
+                format!("Namespace {} is not managed by the API", namespace),
+            );
+        }
+    }
+    if let Some(image) = watcher.worker_image.as_deref() {
+        if image != DOCKER_IMAGE.as_str() && !WORKER_IMAGES.iter().any(|i| i == image) {
+            errors.add(
+                "worker_image",
+                format!("Image {} is not allowed by the API", image),
+            );
+        }
+    }
+    if watcher.is_packed() && *OPERATOR_MODE {
+        errors.add(
+            "packed",
+            "The operator runs each watcher in its own worker, it cannot pack them",
+        );
+    }
+    if watcher.is_packed() && watcher.bitrate_kbps() > *PACK_BITRATE {
+        errors.add(
+            "packed",
+            format!(
+                "The bitrate of the sources is above the {} kbps of a pack",
+                *PACK_BITRATE
+            ),
+        );
+    }
+    if let Protocol::Rtmp {
+        tls: Some(true), ..
+    } = watcher.source.transport
+    {
+        if RTMPS_CERTIFICATE.is_none() || SHARED_SERVICE.is_some() {
+            errors.add(
+                "source.transport.tls",
+                "RTMPS needs a certificate for the load balancer of the watcher",
+            );
+        }
+    }
+    for (field, action) in watcher.actions() {
+        if let Action::Exec(command) = action {
+            if !command.is_allowed(&EXEC_ALLOWLIST) {
+                errors.add(
+                    format!("{}.command", field),
+                    format!(
+                        "Command {} is not allowed by the API with these arguments",
+                        command.command
+                    ),
+                );
+            }
+        }
+    }
+    // The workers send the referenced credentials to the URL of the actions, so only the
+    // `Secret`s made for the actions can be referenced
+    for name in watcher.secret_names() {
+        if !ACTION_SECRETS.iter().any(|allowed| allowed == name) {
+            errors.add(
+                "actions",
+                format!("Secret {} is not allowed by the API", name),
+            );
+        }
+    }
+    errors.into_result()
+}
+
+/// Reply used when the `Watcher` in the payload is not valid, listing the problems per field.
+fn validation_failed(errors: ValidationErrors) -> reply::WithStatus<reply::Json> {
+    reply::with_status(
+        reply::json(&json!({
+            "message": "Invalid Watcher definition",
+            "errors": errors.errors,
+        })),
+        StatusCode::UNPROCESSABLE_ENTITY,
+    )
+}
+
+/// Query parameters accepted while upgrading a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct UpgradeOptions {
+    /// Restart a running watcher to upgrade it, instead of refusing the upgrade.
+    #[serde(default)]
+    pub restart: bool,
+}
+
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn upgrade_watcher(
+    id: String,
+    options: UpgradeOptions,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.upgrade_watcher: {} {:?}", id, options);
+    let (body, code) = upgrade(&backend, &id, options.restart, &actor).await;
+    Ok(reply::with_status(reply::json(&body), code))
+}
+
+/// Upgrades a single watcher, returning the body and HTTP status code of the reply.
+async fn upgrade(
+    backend: &Backend,
+    id: &str,
+    restart: bool,
+    actor: &str,
+) -> (serde_json::Value, StatusCode) {
+    let failed = |e: anyhow::Error| {
+        let msg = format!("Error while calling Kubernetes API: {:?}", e);
+        log::error!("{}", msg);
+        (json!({ "message": msg }), StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    let watcher_status = match backend.get_watcher_status(id).await {
+        Ok(Some(status)) => status,
+        Ok(None) => return (json!({}), StatusCode::NOT_FOUND),
+        Err(e) => return failed(e),
+    };
+
+    // We use the stored definition as source of truth for what are the watchers we have
+    let mut watcher = match get_live_config(backend, id).await {
+        Ok(Some(w)) => w,
+        Ok(None) => return (json!({}), StatusCode::NOT_FOUND),
+        Err(e) => return failed(e),
+    };
+
+    let restart = match (watcher_status, restart) {
+        (Status::Ready, _) => false,
+        (Status::Running, true) => true,
+        (_, _) => {
+            return (
+                json!({"message": "The Watcher must be stopped before the upgrade can be applied"}),
+                StatusCode::BAD_REQUEST,
+            )
+        }
+    };
+    watcher.status = Some(watcher_status);
+
+    match backend.upgrade_watcher(id, restart).await {
+        Ok(_) => {
+            audit::record(backend, id, actor, "upgrade", Vec::new()).await;
+            (json!(watcher), StatusCode::OK)
+        }
+        Err(e) => failed(e),
+    }
+}
+
+/// Selects the watchers of a fleet upgrade and how the upgrade is rolled out.
+#[derive(Deserialize, JsonSchema, Debug, Default)]
+pub struct FleetUpgrade {
+    /// Watchers to upgrade, all of them when missing.
+    pub ids: Option<Vec<String>>,
+    /// Comma separated list of `key:value` tags the upgraded watchers must have.
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub strategy: RolloutStrategy,
+    /// Restart the running watchers to upgrade them, see `UpgradeOptions`.
+    #[serde(default)]
+    pub restart: bool,
+}
+
+/// How a fleet upgrade is rolled out.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(default)]
+pub struct RolloutStrategy {
+    /// Number of watchers upgraded at the same time, `1` upgrades them sequentially.
+    pub batch_size: usize,
+    /// Stop the rollout after a batch where the upgrade of any of the watchers failed, with an
+    /// error of Kubernetes or pods that did not get ready. The watchers that could not be
+    /// upgraded, like the running ones without `restart`, do not stop it.
+    pub pause_on_error: bool,
+}
+
+impl Default for RolloutStrategy {
+    fn default() -> Self {
+        RolloutStrategy {
+            batch_size: 1,
+            pause_on_error: true,
+        }
+    }
+}
+
+/// Upgrade many Watchers to the current version of the worker, in batches.
+///
+/// The watchers not upgraded because the rollout was paused are listed as `skipped`.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/upgrade",
+    request_body = FleetUpgrade,
+    responses(
+        (status = 200, description = "Result of the upgrade for each Watcher.", body = BulkResults),
+        (status = 400, description = "Invalid selector or strategy."),
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn upgrade_watchers(
+    grant: Grant,
+    upgrade_request: FleetUpgrade,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.upgrade_watchers: {:?}", upgrade_request);
+
+    let mut tags = match parse_tags(upgrade_request.tag.as_deref()) {
+        Ok(tags) => tags,
+        Err(message) => {
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+    if upgrade_request.ids.is_none() {
+        // Only the watchers in reach of the client are upgraded
+        tags.extend(grant.tags.iter().cloned());
+    }
+    let ids = match (upgrade_request.ids, tags.is_empty()) {
+        (Some(ids), true) => {
+            if let Some(reply) = check_grant_ids(&backend, &grant, &ids).await {
+                return Ok(reply);
+            }
+            ids
+        }
+        (None, _) => match list_live_ids(&backend, &tags).await {
+            Ok(ids) => ids,
+            Err(e) => return Ok(backend_error(e)),
+        },
+        (Some(_), false) => {
+            return Ok(reply::with_status(
+                reply::json(&json!({
+                    "message": "Only one of `ids` or `tag` can be provided"
+                })),
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+    let strategy = upgrade_request.strategy;
+    let restart = upgrade_request.restart;
+    if strategy.batch_size == 0 {
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": "The batch size must be at least 1" })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let mut results = Vec::new();
+    let mut batches = ids.chunks(strategy.batch_size);
+    for batch in batches.by_ref() {
+        let calls = batch.iter().map(|id| {
+            let backend = backend.clone();
+            let actor = &actor;
+            async move {
+                let (body, code) = upgrade(&backend, id, restart, actor).await;
+                json!({
+                    "id": id,
+                    "status": code.as_u16(),
+                    "message": body.get("message"),
+                })
+            }
+        });
+        let batch_results = join_all(calls).await;
+        // Only the failed upgrades pause the rollout, with a 5xx, not the watchers left as they
+        // were
+        let failed = batch_results
+            .iter()
+            .any(|r| r["status"].as_u64().map_or(false, |status| status >= 500));
+        results.extend(batch_results);
+        if failed && strategy.pause_on_error {
+            log::warn!("Pausing the upgrade of the watchers after a failure");
+            break;
+        }
+    }
+    let skipped: Vec<&String> = batches.flatten().collect();
+
+    Ok(reply::with_status(
+        reply::json(&json!({ "results": results, "skipped": skipped })),
+        StatusCode::OK,
+    ))
+}
+
+/// Get a Watcher.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "Successfull response.", body = Watcher),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher(id: String, backend: Backend) -> Result<impl warp::Reply, Infallible> {
+    match backend.get_watcher(&id).await {
+        Ok(Some(w)) if w.deleted_at.is_none() => {
+            Ok(reply::with_status(reply::json(&w), StatusCode::OK))
+        }
+        Ok(_) => Ok(not_found()),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Gets the Watcher by its unique name rather than its ID.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/by-name/{name}",
+    params(
+        ("name" = String, path, description = "Unique name of the watcher."),
+    ),
+    responses(
+        (status = 200, description = "Successfull response.", body = Watcher),
+        (status = 404, description = "No Watcher has this name."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_name = %name))]
+pub async fn get_watcher_by_name(
+    name: String,
+    grant: Grant,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    match find_by_name(&backend, &name).await {
+        // Like the watchers that do not exist, so the names of the other teams are not revealed
+        Ok(Some(w)) if !grant.covers(w.tags.as_ref()) => Ok(not_found().into_response()),
+        Ok(Some(w)) => Ok(get_watcher(w.id.unwrap_or_default(), backend)
+            .await?
+            .into_response()),
+        Ok(None) => Ok(not_found().into_response()),
+        Err(e) => Ok(backend_error(e).into_response()),
+    }
+}
+
+/// Lists the IDs of the watchers having all the given tags, but the deleted ones.
+async fn list_live_ids(
+    backend: &Backend,
+    tags: &[(String, String)],
+) -> anyhow::Result<Vec<String>> {
+    let mut query = ListQuery {
+        tags: tags.to_vec(),
+        ..ListQuery::default()
+    };
+    let mut ids = Vec::new();
+    loop {
+        let page = backend.list_watchers(&query).await?;
+        ids.extend(page.watchers.into_iter().filter_map(|w| w.id));
+        match page.continue_token {
+            Some(token) => query.continue_token = Some(token),
+            None => return Ok(ids),
+        }
+    }
+}
+
+/// Loads the definition of the watcher, `None` when it does not exist or is deleted.
+async fn get_live_config(backend: &Backend, id: &str) -> anyhow::Result<Option<Watcher>> {
+    Ok(backend
+        .get_watcher_config(id)
+        .await?
+        .filter(|w| w.deleted_at.is_none()))
+}
+
+/// Checks the watcher is running before calling its worker, returns the status code of the
+/// reply otherwise.
+async fn check_running(backend: &Backend, id: &str) -> Option<StatusCode> {
+    match backend.get_watcher_status(id).await {
+        Ok(Some(Status::Running)) => None,
+        Ok(Some(_)) => {
+            tracing::debug!("Watcher is not running...");
+            Some(StatusCode::NOT_ACCEPTABLE)
+        }
+        Ok(None) => {
+            tracing::debug!("Watcher not found: {}", id);
+            Some(StatusCode::NOT_FOUND)
+        }
+        Err(e) => {
+            log::error!("Error while calling Kubernetes API: {:?}", e);
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Latest video frame.
+///
+/// Expose the latest video frame the Watcher has captured.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/video-frame",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        (
+            "If-None-Match" = Option<String>,
+            header,
+            description = "ETag of a frame the client already has."
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The image bytes",
+            body = String,
+            content_type = "image/png",
+            headers((
+                "ETag" = String,
+                description = "Entity tag of the frame, to revalidate it with `If-None-Match`."
+            ))
+        ),
+        (
+            status = 304,
+            description = "The frame has not changed since the one with the ETag sent in \
+                `If-None-Match`."
+        ),
+        (
+            status = 429,
+            description = "The client is over the rate limit, or too many requests are being \
+                served.",
+            headers(("Retry-After" = u64, description = "Seconds to wait before calling again."))
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_video_frame(
+    id: String,
+    if_none_match: Option<String>,
+    _permit: rate_limit::Permit,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut resp = warp::reply::Response::new(Body::empty());
+    if let Some(code) = check_running(&backend, &id).await {
+        *resp.status_mut() = code;
+        return Ok(resp);
+    }
+
+    match frames::latest(&backend, &id).await {
+        Ok(Some(frame)) => {
+            let headers = resp.headers_mut();
+            // Clients may keep the frame, but must revalidate it with its ETag
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+            if let Ok(value) = HeaderValue::from_str(&frame.etag) {
+                headers.insert(ETAG, value);
+            }
+            match if_none_match {
+                Some(tags) if frames::etag_matches(&tags, &frame.etag) => {
+                    *resp.status_mut() = StatusCode::NOT_MODIFIED;
+                }
+                _ => {
+                    headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+                    *resp.body_mut() = Body::from(frame.image);
+                }
+            }
+        }
+        Ok(None) => {
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+        Err(e) => {
+            log::error!("Could not get the video frame of watcher {}: {:?}", id, e);
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+    }
+    Ok(resp)
+}
+
+/// Query parameters accepted while streaming the preview of a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct PreviewOptions {
+    /// Frames per second, from 1 to `MAX_PREVIEW_FPS`.
+    pub fps: Option<u32>,
+}
+
+/// Highest rate of the preview streams, they are meant for thumbnails and not for playback.
+const MAX_PREVIEW_FPS: u32 = 5;
+
+/// Streams the frames of the Watcher worker as a multipart MJPEG-like preview, which browsers
+/// can show in an `<img>` element.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/preview",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        ("fps" = Option<u32>, query, description = "Frames per second, from 1 to 5."),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The stream of PNG images, one per part.",
+            body = String,
+            content_type = "multipart/x-mixed-replace"
+        ),
+        (
+            status = 429,
+            description = "The client is over the rate limit, or too many requests are being \
+                served.",
+            headers(("Retry-After" = u64, description = "Seconds to wait before calling again."))
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_preview(
+    id: String,
+    options: PreviewOptions,
+    permit: rate_limit::Permit,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut resp = warp::reply::Response::new(Body::empty());
+    if let Some(code) = check_running(&backend, &id).await {
+        *resp.status_mut() = code;
+        return Ok(resp);
+    }
+
+    let fps = options.fps.unwrap_or(1).clamp(1, MAX_PREVIEW_FPS);
+    let period = std::time::Duration::from_secs(1) / fps;
+    let content_type = format!(
+        "multipart/x-mixed-replace; boundary={}",
+        frames::PREVIEW_BOUNDARY
+    );
+    let headers = resp.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&content_type) {
+        headers.insert(CONTENT_TYPE, value);
+    }
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    // The stream holds the slot of the request until the client goes away
+    let parts = frames::preview(backend, id, period).map(move |part| {
+        let _slot = &permit;
+        part
+    });
+    *resp.body_mut() = Body::wrap_stream(parts);
+    Ok(resp)
+}
+
+/// Query parameters accepted while reading the metrics of a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct MetricsOptions {
+    /// `json` for a summary of the detection stats, the Prometheus metrics otherwise.
+    pub format: Option<String>,
+}
+
+/// Returns the Prometheus metrics of the Watcher worker, or a summary of them.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/metrics",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        (
+            "format" = Option<String>,
+            query,
+            description = "Use `json` for a summary of the metrics."
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The Prometheus metrics of the worker, or their `MetricsSummary` \
+                with `format=json`.",
+            body = String,
+            content_type = "text/plain"
+        ),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 406, description = "The Watcher is not running."),
+        (status = 417, description = "The worker did not return its metrics."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_metrics(
+    id: String,
+    options: MetricsOptions,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut resp = warp::reply::Response::new(Body::empty());
+    if let Some(code) = check_running(&backend, &id).await {
+        *resp.status_mut() = code;
+        return Ok(resp);
+    }
+
+    match backend.get_watcher_metrics(&id).await {
+        Ok(Some(contents)) => {
+            if options.format.as_deref() == Some("json") {
+                return Ok(reply::json(&metrics::summarize(&contents)).into_response());
+            }
+            let headers = resp.headers_mut();
+            headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+            *resp.body_mut() = Body::from(contents);
+        }
+        Ok(None) => {
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+        Err(e) => {
+            log::error!("Could not get the metrics of watcher {}: {:?}", id, e);
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+    }
+    Ok(resp)
+}
+
+/// Returns what the Watcher worker is detecting: the video mode, the similarity with the slates
+/// and the actions executed.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/state",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "The detection state of the worker.", body = DetectionState),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 406, description = "The Watcher is not running."),
+        (status = 417, description = "The worker did not return its state."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_state(
+    id: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut resp = warp::reply::Response::new(Body::empty());
+    if let Some(code) = check_running(&backend, &id).await {
+        *resp.status_mut() = code;
+        return Ok(resp);
+    }
+
+    match backend.get_watcher_state(&id).await {
+        Ok(Some(state)) => return Ok(reply::json(&state).into_response()),
+        Ok(None) => {
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+        Err(e) => {
+            log::error!("Could not get the state of watcher {}: {:?}", id, e);
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+    }
+    Ok(resp)
+}
+
+/// Fire the actions of the transitions of a slate or trigger of a running Watcher, whatever its
+/// frames show, like when the detection misses a slate. Replies with the records of the actions
+/// once they completed.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/trigger",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    request_body = TransitionTrigger,
+    responses(
+        (
+            status = 200,
+            description = "The actions were executed, successfully or not.",
+            body = [ActionRecord]
+        ),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 406, description = "The Watcher is not running."),
+        (status = 422, description = "The Watcher has no such slate, trigger or transition."),
+        (
+            status = 417,
+            description = "The worker could not trigger the transition, its logs tell why."
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn trigger_transition(
+    id: String,
+    trigger: TransitionTrigger,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Some(code) = check_running(&backend, &id).await {
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Only the transitions of running watchers can be triggered"
+            })),
+            code,
+        ));
+    }
+    let watcher = match backend.get_watcher_config(&id).await {
+        Ok(Some(watcher)) => watcher,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
+    };
+    if let Err(errors) = trigger.validate(&watcher) {
+        return Ok(validation_failed(errors));
+    }
+    match backend.trigger_transition(&id, &trigger).await {
+        Ok(Some(records)) => {
+            log::info!(
+                "Transition from {} to {} of {} of watcher {} triggered by {}",
+                trigger.from,
+                trigger.to,
+                trigger.slate_id(),
+                id,
+                actor
+            );
+            Ok(reply::with_status(reply::json(&records), StatusCode::OK))
+        }
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "The worker could not trigger the transition, see its logs"
+            })),
+            StatusCode::EXPECTATION_FAILED,
+        )),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Returns the last actions executed by the Watcher worker, oldest first.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/actions/history",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "The actions executed by the worker.", body = [ActionRecord]),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 406, description = "The Watcher is not running."),
+        (status = 417, description = "The worker did not return its actions."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_action_history(
+    id: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut resp = warp::reply::Response::new(Body::empty());
+    if let Some(code) = check_running(&backend, &id).await {
+        *resp.status_mut() = code;
+        return Ok(resp);
+    }
 
-    // Get all K8S deployments we know, we want to return the status of each watcher
-    let deployments_client: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-    let deployments = deployments_client.list(&lp).await.unwrap();
-    let mut deployments_index = HashMap::new();
-    for deploy in deployments.items {
-        if let Some(watcher_id) = deploy.metadata.labels.as_ref().unwrap().get("watcher_id") {
-            deployments_index.insert(watcher_id.clone(), deploy.get_watcher_status());
+    match backend.get_action_history(&id).await {
+        Ok(Some(actions)) => return Ok(reply::json(&actions).into_response()),
+        Ok(None) => {
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+        Err(e) => {
+            log::error!("Could not get the actions of watcher {}: {:?}", id, e);
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
         }
     }
+    Ok(resp)
+}
 
-    let config_maps_client: Api<ConfigMap> = Api::namespaced(client.clone(), &NAMESPACE);
-    let config_maps = config_maps_client.list(&lp).await.unwrap();
+/// Executes again an action of the history of a running Watcher, like one that failed, and
+/// replies with the record of the replay once it completed.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/actions/{index}/replay",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        ("index" = usize, path, description = "Index of the action in the history."),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The action was executed, successfully or not.",
+            body = ActionRecord
+        ),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 406, description = "The Watcher is not running."),
+        (
+            status = 417,
+            description = "The action is no longer in the history, or the worker could not \
+                replay it."
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn replay_action(
+    id: String,
+    index: u64,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Some(code) = check_running(&backend, &id).await {
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Only the actions of running watchers can be replayed"
+            })),
+            code,
+        ));
+    }
+    match backend.replay_action(&id, index).await {
+        Ok(Some(record)) => {
+            log::info!("Action {} of watcher {} replayed by {}", index, id, actor);
+            Ok(reply::with_status(reply::json(&record), StatusCode::OK))
+        }
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "The worker could not replay the action, it may no longer be in its history"
+            })),
+            StatusCode::EXPECTATION_FAILED,
+        )),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Query parameters accepted while reading the logs of a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct LogOptions {
+    /// Keep the connection open, streaming new log lines as they are written.
+    pub follow: Option<bool>,
+    /// Only return logs newer than this number of seconds.
+    pub since: Option<i64>,
+    /// Only return this number of lines from the end of the logs.
+    pub tail: Option<i64>,
+}
 
-    let mut watchers: Vec<Watcher> = Vec::new();
-    for config in config_maps.items {
-        let data = config.data.unwrap();
-        let mut watcher: Watcher = serde_json::from_str(data.get("watcher.json").unwrap()).unwrap();
-        let calculated_status = if let Some(status) =
-            deployments_index.get(watcher.id.as_ref().unwrap_or(&"undefined".to_string()))
-        {
-            *status
-        } else {
-            Status::Error
-        };
-        watcher.status = Some(calculated_status);
-        // TODO: Comes from the service
-        watcher.source.ingest_ip = None;
-        watchers.push(watcher);
+/// Returns the logs of the Watcher worker container, optionally following new lines.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/logs",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        ("follow" = Option<bool>, query, description = "Keep streaming new log lines."),
+        (
+            "since" = Option<u32>,
+            query,
+            description = "Only return logs newer than this number of seconds."
+        ),
+        (
+            "tail" = Option<u32>,
+            query,
+            description = "Only return this number of lines from the end of the logs."
+        ),
+    ),
+    responses(
+        (status = 200, description = "The log lines.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The Watcher is not running."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_logs(
+    id: String,
+    options: LogOptions,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut resp = warp::reply::Response::new(Body::empty());
+
+    let query = LogQuery {
+        follow: options.follow.unwrap_or(false),
+        since_seconds: options.since,
+        tail_lines: options.tail,
+    };
+    match backend.get_watcher_logs(&id, &query).await {
+        Ok(Some(stream)) => {
+            let headers = resp.headers_mut();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+            *resp.body_mut() = Body::wrap_stream(stream);
+        }
+        Ok(None) => {
+            *resp.status_mut() = StatusCode::NOT_FOUND;
+        }
+        Err(e) => {
+            log::error!("Could not read logs of watcher {}: {:?}", id, e);
+            *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+        }
+    }
+    Ok(resp)
+}
+
+/// Start a Watcher worker.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/start",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "Watcher is starting.", body = Message),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn start_watcher(
+    id: String,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let result = start(&backend, &id).await;
+    if let Ok(StatusChange::Applied) = result {
+        audit::record(&backend, &id, &actor, "start", Vec::new()).await;
+    }
+    let (message, code) = status_change_reply(result, Status::Running);
+    Ok(reply::with_status(reply::json(&message), code))
+}
+
+/// Reload the slates and the transitions of a running Watcher without restarting its worker,
+/// keeping the state of the stream.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/reload",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "Watcher is reloaded."),
+        (status = 406, description = "The Watcher is not running."),
+        (status = 417, description = "The worker could not reload the Watcher, its logs tell why."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn reload_watcher(
+    id: String,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Some(code) = check_running(&backend, &id).await {
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Only running watchers can be reloaded"
+            })),
+            code,
+        ));
+    }
+    match backend.reload_watcher(&id).await {
+        Ok(true) => {
+            audit::record(&backend, &id, &actor, "reload", Vec::new()).await;
+            Ok(reply::with_status(
+                reply::json(&json!({
+                    "message": "Watcher has been reloaded"
+                })),
+                StatusCode::OK,
+            ))
+        }
+        Ok(false) => Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "The worker could not reload the watcher, see its logs"
+            })),
+            StatusCode::EXPECTATION_FAILED,
+        )),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Change the threshold of a slate of a running Watcher, returning how its latest frame compares
+/// with the slate. The watcher definition is not changed, so the threshold is lost on the next
+/// reload or restart of the worker.
+#[utoipa::path(
+    put,
+    path = "/v1/watchers/{watcher_id}/config/threshold",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    request_body = ThresholdChange,
+    responses(
+        (
+            status = 200,
+            description = "Comparison of the latest frame with the slate under the new threshold.",
+            body = ThresholdResult
+        ),
+        (status = 406, description = "The Watcher is not running."),
+        (status = 422, description = "The slate does not exist or the threshold is not valid."),
+        (
+            status = 417,
+            description = "The worker could not change the threshold, its logs tell why."
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn set_watcher_threshold(
+    id: String,
+    change: ThresholdChange,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Some(code) = check_running(&backend, &id).await {
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Only the thresholds of running watchers can be changed"
+            })),
+            code,
+        ));
+    }
+    let watcher = match backend.get_watcher_config(&id).await {
+        Ok(Some(watcher)) => watcher,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
+    };
+    if let Err(errors) = change.validate(&watcher) {
+        return Ok(validation_failed(errors));
+    }
+    match backend.set_watcher_threshold(&id, &change).await {
+        Ok(Some(result)) => {
+            log::info!(
+                "Threshold of slate {} of watcher {} set to {} by {}",
+                change.slate_id(),
+                id,
+                change.threshold,
+                actor
+            );
+            Ok(reply::with_status(reply::json(&result), StatusCode::OK))
+        }
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "The worker could not change the threshold, see its logs"
+            })),
+            StatusCode::EXPECTATION_FAILED,
+        )),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Switch a Watcher between executing its actions and only monitoring its transitions. A
+/// running worker applies the change without restarting.
+#[utoipa::path(
+    put,
+    path = "/v1/watchers/{watcher_id}/mode",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    request_body = ModeChange,
+    responses(
+        (status = 200, description = "The mode was changed.", body = ModeChange),
+        (status = 404, description = "The Watcher does not exist."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn set_watcher_mode(
+    id: String,
+    change: ModeChange,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let current = match get_live_config(&backend, &id).await {
+        Ok(Some(watcher)) => watcher,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
+    };
+    let mut watcher = current.clone();
+    watcher.mode = Some(change.mode);
+    if let Err(e) = backend.update_watcher(&id, &watcher).await {
+        return Ok(backend_error(e));
+    }
+    let changes = audit::diff(&json!(current), &json!(watcher));
+    audit::record(&backend, &id, &actor, "update", changes).await;
+    revisions::record(&backend, &id, &actor, "update", &definition(&watcher)).await;
+    Ok(reply::with_status(reply::json(&change), StatusCode::OK))
+}
+
+/// Query parameters accepted while calibrating a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct CalibrateOptions {
+    /// Seconds the frames are sampled for.
+    pub duration: Option<u64>,
+}
+
+/// Sample the similarities of the frames of a running Watcher with its slates, returning their
+/// distribution with a suggested threshold for each slate. Replies once the sampling is over.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/calibrate",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        ("duration" = Option<u32>, query, description = "Seconds the frames are sampled for."),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Distribution of the similarities with each slate.",
+            body = Calibration
+        ),
+        (status = 400, description = "The duration is not valid."),
+        (status = 406, description = "The Watcher is not running."),
+        (
+            status = 417,
+            description = "The worker could not calibrate the slates, or is already \
+                calibrating them."
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn calibrate_watcher(
+    id: String,
+    options: CalibrateOptions,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let seconds = options.duration.unwrap_or(DEFAULT_CALIBRATION_SECONDS);
+    if seconds == 0 || seconds > MAX_CALIBRATION_SECONDS {
+        let message = format!(
+            "The duration must be between 1 and {} seconds",
+            MAX_CALIBRATION_SECONDS
+        );
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": message })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if let Some(code) = check_running(&backend, &id).await {
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Only running watchers can be calibrated"
+            })),
+            code,
+        ));
+    }
+    match backend.calibrate_watcher(&id, seconds).await {
+        Ok(Some(calibration)) => Ok(reply::with_status(
+            reply::json(&calibration),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "The worker could not calibrate the slates, see its logs"
+            })),
+            StatusCode::EXPECTATION_FAILED,
+        )),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Query parameters accepted while recording a clip of a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct RecordOptions {
+    /// Seconds of the incoming video recorded.
+    pub duration: Option<u64>,
+}
+
+/// Record a clip of the incoming video of a running Watcher as a transport stream, uploaded to
+/// the archive of the Watcher. Replies with the URL of the clip once it is uploaded.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/record",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        ("duration" = Option<u32>, query, description = "Seconds of video recorded."),
+    ),
+    responses(
+        (status = 200, description = "The clip uploaded to the archive.", body = Clip),
+        (status = 400, description = "The duration is not valid."),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 406, description = "The Watcher is not running."),
+        (status = 412, description = "The Watcher has no archive."),
+        (
+            status = 417,
+            description = "The worker could not record or upload the clip, or is already \
+                recording one."
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn record_watcher(
+    id: String,
+    options: RecordOptions,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let seconds = options.duration.unwrap_or(DEFAULT_RECORDING_SECONDS);
+    if seconds == 0 || seconds > MAX_RECORDING_SECONDS {
+        let message = format!(
+            "The duration must be between 1 and {} seconds",
+            MAX_RECORDING_SECONDS
+        );
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": message })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    match backend.get_watcher_config(&id).await {
+        Ok(Some(watcher)) if watcher.archive.is_none() => {
+            return Ok(reply::with_status(
+                reply::json(&json!({
+                    "message": "The watcher needs an archive to upload the clip to"
+                })),
+                StatusCode::PRECONDITION_FAILED,
+            ))
+        }
+        Ok(Some(_)) => (),
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
+    }
+    if let Some(code) = check_running(&backend, &id).await {
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Only running watchers can be recorded"
+            })),
+            code,
+        ));
+    }
+    match backend.record_watcher(&id, seconds).await {
+        Ok(Some(clip)) => Ok(reply::with_status(reply::json(&clip), StatusCode::OK)),
+        Ok(None) => Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "The worker could not record the clip, see its logs"
+            })),
+            StatusCode::EXPECTATION_FAILED,
+        )),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Starts the watcher unless it is suspended.
+async fn start(backend: &Backend, id: &str) -> anyhow::Result<StatusChange> {
+    match get_live_config(backend, id).await? {
+        Some(watcher) if watcher.suspended == Some(true) => Ok(StatusChange::Suspended),
+        Some(_) => backend.start_watcher(id).await,
+        None => Ok(StatusChange::NotFound),
+    }
+}
+
+/// Stop a Watcher and keep it stopped until it is resumed, whatever starts watchers.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/suspend",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "Watcher is suspended."),
+        (status = 409, description = "Watcher is currently updating."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn suspend_watcher(
+    id: String,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.suspend_watcher: {}", id);
+    let mut watcher = match get_live_config(&backend, &id).await {
+        Ok(Some(w)) => w,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
+    };
+
+    // Watchers in error are not running, they can be suspended as they are
+    match backend.stop_watcher(&id, None).await {
+        Ok(StatusChange::Applied) | Ok(StatusChange::Unchanged) | Ok(StatusChange::Refused) => (),
+        result => {
+            let (message, code) = status_change_reply(result, Status::Ready);
+            return Ok(reply::with_status(reply::json(&message), code));
+        }
+    }
+
+    watcher.suspended = Some(true);
+    if let Err(e) = backend.update_watcher(&id, &watcher).await {
+        return Ok(backend_error(e));
+    }
+    audit::record(&backend, &id, &actor, "suspend", Vec::new()).await;
+    Ok(reply::with_status(
+        reply::json(&json!({ "message": "Watcher is suspended" })),
+        StatusCode::OK,
+    ))
+}
+
+/// Lift the suspension of a Watcher, leaving it stopped.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/resume",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "Watcher is resumed."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn resume_watcher(
+    id: String,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.resume_watcher: {}", id);
+    let mut watcher = match get_live_config(&backend, &id).await {
+        Ok(Some(w)) => w,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
+    };
+
+    if watcher.suspended != Some(true) {
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": "Watcher is not suspended" })),
+            StatusCode::OK,
+        ));
+    }
+    watcher.suspended = None;
+    if let Err(e) = backend.update_watcher(&id, &watcher).await {
+        return Ok(backend_error(e));
+    }
+    audit::record(&backend, &id, &actor, "resume", Vec::new()).await;
+    Ok(reply::with_status(
+        reply::json(&json!({ "message": "Watcher is resumed" })),
+        StatusCode::OK,
+    ))
+}
+
+/// Query parameters accepted while stopping a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct StopOptions {
+    /// Seconds the worker has to finish the actions in progress, up to the grace period
+    /// configured for the workers.
+    pub grace_seconds: Option<u32>,
+}
+
+/// Stop a Watcher worker.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/stop",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        (
+            "grace_seconds" = Option<u32>,
+            query,
+            description = "Seconds the worker has to finish the actions in progress, it cannot \
+                be longer than the grace period configured in the API."
+        ),
+    ),
+    responses(
+        (status = 200, description = "Watcher is stopping.", body = Message),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn stop_watcher(
+    id: String,
+    options: StopOptions,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Some(grace_seconds) = options.grace_seconds {
+        if grace_seconds > *WORKER_GRACE_PERIOD {
+            let message = format!(
+                "The grace period cannot be longer than {} seconds",
+                *WORKER_GRACE_PERIOD
+            );
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    }
+    let result = backend.stop_watcher(&id, options.grace_seconds).await;
+    if let Ok(StatusChange::Applied) = result {
+        audit::record(&backend, &id, &actor, "stop", Vec::new()).await;
     }
+    let (message, code) = status_change_reply(result, Status::Ready);
+    Ok(reply::with_status(reply::json(&message), code))
+}
 
-    Ok(warp::reply::json(&watchers))
+/// Selects the watchers affected by a bulk operation, either by ID or by tags.
+#[derive(Deserialize, JsonSchema, Debug)]
+pub struct BulkSelector {
+    pub ids: Option<Vec<String>>,
+    /// Comma separated list of `key:value` tags the watchers must have.
+    pub tag: Option<String>,
 }
 
-pub async fn create_watcher(
-    mut watcher: Watcher,
-    client: Client,
+/// Start many Watchers at once, see `start_watcher`.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/start",
+    request_body = BulkSelector,
+    responses(
+        (
+            status = 200,
+            description = "Result of the operation for each Watcher.",
+            body = BulkResults
+        ),
+    )
+)]
+pub async fn bulk_start_watchers(
+    grant: Grant,
+    selector: BulkSelector,
+    actor: String,
+    backend: Backend,
 ) -> Result<impl warp::Reply, Infallible> {
-    log::debug!("v1.create_watcher: {:?}", watcher);
-
-    let new_id = Uuid::new_v4().to_string();
-    watcher.id = Some(new_id.clone());
-    let pp = PostParams::default();
-
-    // 1. Create ConfigMap
-    log::debug!("Creating ConfigMap instance");
-    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), &NAMESPACE);
-    let config_file_contents = serde_json::to_string(&watcher).unwrap();
-    let config = templates::build_configmap(&new_id, &config_file_contents);
-    // TODO: Handle errors
-    let _ = config_maps.create(&pp, &config).await.unwrap();
-
-    // 2. Create Deployment with replicas=0
-    log::debug!("Creating Deployment instance");
-    let deployments: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-    let deploy = templates::build_deployment(&new_id, watcher.source.ingest_port);
-    // TODO: Handle errors
-    let _ = deployments.create(&pp, &deploy).await.unwrap();
-
-    // 3. Create Service/LoadBalancer
-    log::debug!("Creating Service instance");
-    let services: Api<Service> = Api::namespaced(client.clone(), &NAMESPACE);
-    let svc = templates::build_service(&new_id, watcher.source.ingest_port);
-    // TODO: Handle errors
-    let _ = services.create(&pp, &svc).await.unwrap();
-
-    watcher.status = Some(Status::Pending);
-    watcher.source.ingest_ip = None;
+    bulk_change_status(grant, selector, actor, backend, Status::Running).await
+}
 
-    Ok(reply::with_status(
-        reply::json(&watcher),
-        StatusCode::CREATED,
-    ))
+/// Stop many Watchers at once, see `stop_watcher`.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/stop",
+    request_body = BulkSelector,
+    responses(
+        (
+            status = 200,
+            description = "Result of the operation for each Watcher.",
+            body = BulkResults
+        ),
+    )
+)]
+pub async fn bulk_stop_watchers(
+    grant: Grant,
+    selector: BulkSelector,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    bulk_change_status(grant, selector, actor, backend, Status::Ready).await
 }
 
-pub async fn upgrade_watcher(id: String, client: Client) -> Result<impl warp::Reply, Infallible> {
-    log::debug!("v1.upgrade_watcher: {}", id);
-    let deployments: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-    let deployment = match deployments.get(&templates::deployment_name(&id)).await {
-        Ok(d) => d,
-        Err(_) => {
-            return Ok(reply::with_status(
-                reply::json(&json!({})),
-                StatusCode::NOT_FOUND,
-            ))
-        }
-    };
+#[tracing::instrument(skip_all)]
+async fn bulk_change_status(
+    grant: Grant,
+    selector: BulkSelector,
+    actor: String,
+    backend: Backend,
+    target: Status,
+) -> Result<reply::WithStatus<reply::Json>, Infallible> {
+    tracing::debug!("v1.bulk_change_status: {:?} -> {:?}", selector, target);
 
-    // We use the ConfigMap as source of truth for what are the watchers we have
-    let config_maps_client: Api<ConfigMap> = Api::namespaced(client.clone(), &NAMESPACE);
-    let config_map = match config_maps_client
-        .get(&templates::configmap_name(&id))
-        .await
-    {
-        Ok(c) => c,
-        Err(_) => {
+    let ids = match (selector.ids, selector.tag) {
+        (Some(ids), None) => {
+            if let Some(reply) = check_grant_ids(&backend, &grant, &ids).await {
+                return Ok(reply);
+            }
+            ids
+        }
+        (None, Some(tag)) => {
+            let mut tags = match parse_tags(Some(&tag)) {
+                Ok(tags) => tags,
+                Err(message) => {
+                    return Ok(reply::with_status(
+                        reply::json(&json!({ "message": message })),
+                        StatusCode::BAD_REQUEST,
+                    ))
+                }
+            };
+            tags.extend(grant.tags);
+            match list_live_ids(&backend, &tags).await {
+                Ok(ids) => ids,
+                Err(e) => return Ok(backend_error(e)),
+            }
+        }
+        (_, _) => {
             return Ok(reply::with_status(
-                reply::json(&json!({})),
-                StatusCode::NOT_FOUND,
+                reply::json(&json!({
+                    "message": "Either `ids` or `tag` must be provided"
+                })),
+                StatusCode::BAD_REQUEST,
             ))
         }
     };
 
-    let mut watcher: Watcher =
-        serde_json::from_str(config_map.data.unwrap().get("watcher.json").unwrap()).unwrap();
-    let watcher_status = deployment.get_watcher_status();
-    if watcher_status != Status::Ready {
-        return Ok(reply::with_status(
-            reply::json(
-                &json!({"message": "The Watcher must be stopped before the upgrade can be applied"}),
-            ),
-            StatusCode::BAD_REQUEST,
-        ));
-    }
-    watcher.status = Some(watcher_status);
-
-    let patch_params = PatchParams::default();
-    let spec_updated = json!({
-        "spec": {
-            "template": {
-                "spec": {
-                    "containers": [
-                        container_spec(&id, watcher.source.ingest_port)
-                    ]
-                }
+    let calls = ids.iter().map(|id| {
+        let backend = backend.clone();
+        let actor = &actor;
+        async move {
+            let (result, action) = match target {
+                Status::Running => (start(&backend, id).await, "start"),
+                _ => (backend.stop_watcher(id, None).await, "stop"),
+            };
+            if let Ok(StatusChange::Applied) = result {
+                audit::record(&backend, id, actor, action, Vec::new()).await;
             }
+            let (message, code) = status_change_reply(result, target);
+            json!({
+                "id": id,
+                "status": code.as_u16(),
+                "message": message.get("message"),
+            })
         }
     });
+    let results = join_all(calls).await;
 
-    match deployments
-        .patch(
-            deployment.metadata.name.as_ref().unwrap(),
-            &patch_params,
-            &Patch::Apply(spec_updated),
-        )
-        .await
-    {
-        Ok(_) => Ok(reply::with_status(reply::json(&watcher), StatusCode::OK)),
+    Ok(reply::with_status(
+        reply::json(&json!({ "results": results })),
+        StatusCode::OK,
+    ))
+}
+
+/// Translates the result of a change of status into the message and HTTP status code returned
+/// to the client.
+fn status_change_reply(
+    result: anyhow::Result<StatusChange>,
+    target: Status,
+) -> (serde_json::Value, StatusCode) {
+    let (action, verb) = match target {
+        Status::Running => ("starting", "running"),
+        _ => ("stopping", "stopped"),
+    };
+    match result {
+        Ok(StatusChange::Applied) => (
+            json!({ "message": format!("Watcher is {}", action) }),
+            StatusCode::OK,
+        ),
+        // No op, already in the target status!
+        Ok(StatusChange::Unchanged) => (
+            json!({ "message": format!("Watcher is already {}", verb) }),
+            StatusCode::OK,
+        ),
+        // No op, committing other changes.
+        Ok(StatusChange::Busy) => (
+            json!({ "message": "Watcher is currently updating" }),
+            StatusCode::CONFLICT,
+        ),
+        Ok(StatusChange::Refused) => (
+            json!({
+                "message": format!("Watcher in error state cannot be set to {}", verb)
+            }),
+            StatusCode::NOT_ACCEPTABLE,
+        ),
+        Ok(StatusChange::Suspended) => (
+            json!({ "message": "Watcher is suspended, it must be resumed first" }),
+            StatusCode::CONFLICT,
+        ),
+        Ok(StatusChange::NotFound) => (json!({}), StatusCode::NOT_FOUND),
         Err(e) => {
             let msg: String = format!("Error while calling Kubernetes API: {:?}", e);
             log::error!("{}", msg);
-            let error_body = json!({ "message": msg });
-            return Ok(reply::with_status(
-                reply::json(&error_body),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ));
+            (json!({ "message": msg }), StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-pub async fn get_watcher(id: String, client: Client) -> Result<impl warp::Reply, Infallible> {
-    let deployments_client: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-    // TODO: searching for a deployment could be a filter in this route
-    let deployment = match deployments_client
-        .get(&templates::deployment_name(&id))
-        .await
-    {
-        Ok(d) => d,
-        Err(_) => {
+/// Delete a Watcher, stopping it and keeping its definition so it can be restored until it is
+/// purged, once `DELETED_RETENTION` passed.
+#[utoipa::path(
+    delete,
+    path = "/v1/watchers/{watcher_id}",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "Successfull executed operation.", body = Message),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn delete_watcher(
+    id: String,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut watcher = match get_live_config(&backend, &id).await {
+        Ok(Some(w)) => w,
+        Ok(None) => {
             return Ok(reply::with_status(
-                reply::json(&json!({})),
+                reply::json(&json!({
+                    "message": "Watcher does not exist"
+                })),
                 StatusCode::NOT_FOUND,
             ))
         }
+        Err(e) => return Ok(backend_error(e)),
     };
 
-    // We use the ConfigMap as source of truth for what are the watchers we have
-    let config_maps_client: Api<ConfigMap> = Api::namespaced(client.clone(), &NAMESPACE);
-    let config_map = match config_maps_client
-        .get(&templates::configmap_name(&id))
-        .await
-    {
-        Ok(c) => c,
-        Err(_) => {
-            return Ok(reply::with_status(
-                reply::json(&json!({})),
-                StatusCode::NOT_FOUND,
-            ))
+    // Watchers in error are not running, they can be deleted as they are
+    match backend.stop_watcher(&id, None).await {
+        Ok(StatusChange::Applied) | Ok(StatusChange::Unchanged) | Ok(StatusChange::Refused) => (),
+        result => {
+            let (message, code) = status_change_reply(result, Status::Ready);
+            return Ok(reply::with_status(reply::json(&message), code));
         }
+    }
+
+    watcher.deleted_at = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+    if let Err(e) = backend.update_watcher(&id, &watcher).await {
+        return Ok(backend_error(e));
+    }
+    release_name(&backend, watcher.name.as_deref(), &id).await;
+    audit::record(&backend, &id, &actor, "delete", Vec::new()).await;
+    Ok(reply::with_status(
+        reply::json(&json!({
+            "message": "Watcher has been deleted"
+        })),
+        StatusCode::OK,
+    ))
+}
+
+/// Restore a deleted Watcher that was not purged yet, leaving it stopped.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/restore",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "Watcher is restored."),
+        (status = 404, description = "Watcher not found, or already purged."),
+        (
+            status = 409,
+            description = "The ingest port, or the name, is already used by another Watcher.",
+            body = Conflict
+        ),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn restore_watcher(
+    id: String,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    tracing::debug!("v1.restore_watcher: {}", id);
+    let mut watcher = match backend.get_watcher_config(&id).await {
+        Ok(Some(w)) => w,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
     };
 
-    let mut w: Watcher =
-        serde_json::from_str(config_map.data.unwrap().get("watcher.json").unwrap()).unwrap();
-    w.status = Some(deployment.get_watcher_status());
-
-    w.status_description = if let Some(Status::Pending) = w.status.as_ref() {
-        // Load more information why it's in pending status
-        // We get the reason the container is waiting, if available
-        let pods_client: Api<Pod> = Api::namespaced(client.clone(), &NAMESPACE);
-        let lp = ListParams::default().labels(&format!("app=hawkeye,watcher_id={}", id));
-        let pods = pods_client.list(&lp).await.unwrap();
-        let status_description = pods
-            .items
-            .first()
-            .map(|p| p.status.as_ref())
-            .flatten()
-            .map(|ps| ps.container_statuses.as_ref())
-            .flatten()
-            .map(|css| css.first())
-            .flatten()
-            .map(|cs| cs.state.as_ref())
-            .flatten()
-            .map(|cs| cs.waiting.as_ref())
-            .flatten()
-            .map(|csw| csw.message.clone())
-            .flatten();
-        log::debug!(
-            "Additional information for the Pending status: {:?}",
-            status_description.as_ref()
-        );
-        status_description
-    } else {
-        None
-    };
-
-    // Comes from the service
-    w.source.ingest_ip = if w.status != Some(Status::Error) {
-        log::debug!("Getting ingest_ip from Service's LoadBalancer");
-        let services: Api<Service> = Api::namespaced(client.clone(), &NAMESPACE);
-        let service = services
-            .get_status(&templates::service_name(&id))
-            .await
-            .unwrap();
-        service
-            .status
-            .as_ref()
-            .map(|s| s.load_balancer.as_ref())
-            .flatten()
-            .map(|lbs| lbs.ingress.as_ref())
-            .flatten()
-            .map(|lbs| lbs.first())
-            .flatten()
-            .map(|lb| lb.clone().hostname.or(lb.clone().ip))
-            .flatten()
-    } else {
-        None
+    if watcher.deleted_at.is_none() {
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": "Watcher is not deleted" })),
+            StatusCode::OK,
+        ));
+    }
+    watcher.deleted_at = None;
+    // Another watcher may have taken its ports or name since it was deleted
+    if let Some(reply) = check_port_conflict(&backend, &watcher, Some(&id)).await {
+        return Ok(reply);
+    }
+    if let Some(reply) = check_name_conflict(&backend, &watcher, &id).await {
+        return Ok(reply);
+    }
+    if let Err(e) = backend.update_watcher(&id, &watcher).await {
+        return Ok(backend_error(e));
+    }
+    audit::record(&backend, &id, &actor, "restore", Vec::new()).await;
+    Ok(reply::with_status(
+        reply::json(&json!({ "message": "Watcher is restored" })),
+        StatusCode::OK,
+    ))
+}
+
+/// Returns what the deployed Watcher runs that is not in its stored definition, like an upgrade
+/// that did not roll out or a change the worker did not reload.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/diff",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The differences between the definition and the deployed Watcher.",
+            body = Differences
+        ),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 406, description = "The Watcher is packed, it has no Deployment of its own."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_diff(
+    id: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let watcher = match get_live_config(&backend, &id).await {
+        Ok(Some(watcher)) => watcher,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(backend_error(e)),
     };
+    if watcher.pack.is_some() {
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Packed watchers have no deployment of their own to compare"
+            })),
+            StatusCode::NOT_ACCEPTABLE,
+        ));
+    }
+    match backend.get_watcher_drift(&id).await {
+        Ok(Some(drifts)) => Ok(reply::with_status(
+            reply::json(&json!({
+                "drift": !drifts.is_empty(),
+                "differences": drifts,
+            })),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(not_found()),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
 
-    Ok(reply::with_status(reply::json(&w), StatusCode::OK))
+/// Returns the audit log of a Watcher, oldest entries first.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/audit",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    responses(
+        (status = 200, description = "The audit log of the Watcher.", body = [AuditEntry]),
+        (status = 404, description = "The Watcher does not exist."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_audit(
+    id: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    match backend.get_audit(&id).await {
+        Ok(Some(entries)) => Ok(reply::with_status(reply::json(&entries), StatusCode::OK)),
+        Ok(None) => Ok(not_found()),
+        Err(e) => Ok(backend_error(e)),
+    }
 }
 
-pub async fn get_video_frame(id: String, client: Client) -> Result<impl warp::Reply, Infallible> {
-    let mut resp = warp::reply::Response::new(Body::empty());
+/// Keeps the heartbeat sent by the worker of a Watcher, timestamped with the time of the API.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/heartbeat",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    request_body = Heartbeat,
+    responses(
+        (status = 200, description = "The heartbeat is recorded.", body = Heartbeat),
+        (status = 404, description = "The Watcher does not exist."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn record_heartbeat(
+    id: String,
+    mut heartbeat: Heartbeat,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    heartbeat.timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    match backend.record_heartbeat(&id, &heartbeat).await {
+        Ok(true) => Ok(reply::with_status(reply::json(&heartbeat), StatusCode::OK)),
+        Ok(false) => Ok(not_found()),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
 
-    // We use the ConfigMap as source of truth for what are the watchers we have
-    let config_maps_client: Api<ConfigMap> = Api::namespaced(client.clone(), &NAMESPACE);
-    let config_map = match config_maps_client
-        .get(&templates::configmap_name(&id))
-        .await
-    {
-        Ok(c) => c,
-        Err(_) => {
-            log::debug!("ConfigMap object not found for this watcher: {}", id);
-            *resp.status_mut() = StatusCode::NOT_FOUND;
-            return Ok(resp);
-        }
+/// Stores the events reported by the worker of a Watcher.
+#[utoipa::path(
+    post,
+    path = "/v1/watchers/{watcher_id}/events",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+    ),
+    request_body = [WatcherEvent],
+    responses(
+        (status = 200, description = "The events are stored.", body = Recorded),
+        (status = 400, description = "Too many events, or an event with an invalid timestamp."),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 501, description = "No event store is configured."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn report_watcher_events(
+    id: String,
+    reported: Vec<WatcherEvent>,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    if reported.len() > events::MAX_REPORTED {
+        let message = format!(
+            "At most {} events can be reported at once",
+            events::MAX_REPORTED
+        );
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": message })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if let Some(event) = reported.iter().find(|event| event.time().is_none()) {
+        let message = format!("Invalid timestamp {}", event.timestamp);
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": message })),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    match backend.record_events(&id, &reported).await {
+        Ok(true) => Ok(reply::with_status(
+            reply::json(&json!({ "recorded": reported.len() })),
+            StatusCode::OK,
+        )),
+        Ok(false) => Ok(not_found()),
+        Err(e) => Ok(events_error(e)),
+    }
+}
+
+/// Query parameters accepted while listing the events of a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct EventOptions {
+    /// RFC 3339 time of the oldest events returned, a day before `to` by default.
+    pub from: Option<String>,
+    /// RFC 3339 time of the newest events returned, now by default.
+    pub to: Option<String>,
+    /// Only return the events of this kind.
+    pub kind: Option<WatcherEventKind>,
+    /// Maximum number of events returned.
+    pub limit: Option<usize>,
+}
+
+/// The query of the events selected by the options, at most `events::MAX_RANGE_DAYS` long.
+fn event_query(options: EventOptions, now: DateTime<Utc>) -> Result<EventQuery, String> {
+    let parse = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|_| format!("Invalid time {}, expected an RFC 3339 time", time))
     };
-    let watcher: Watcher =
-        serde_json::from_str(config_map.data.unwrap().get("watcher.json").unwrap()).unwrap();
+    let to = options.to.as_deref().map(parse).transpose()?.unwrap_or(now);
+    let from = match options.from.as_deref() {
+        Some(from) => parse(from)?,
+        None => to - Duration::days(1),
+    };
+    if from > to {
+        return Err("The start of the range is after its end".to_string());
+    }
+    if to - from > Duration::days(events::MAX_RANGE_DAYS) {
+        return Err(format!(
+            "The range cannot be longer than {} days",
+            events::MAX_RANGE_DAYS
+        ));
+    }
+    let limit = options.limit.unwrap_or(events::DEFAULT_LIMIT);
+    if limit == 0 || limit > events::MAX_LIMIT {
+        return Err(format!(
+            "The limit must be between 1 and {}",
+            events::MAX_LIMIT
+        ));
+    }
+    Ok(EventQuery {
+        from,
+        to,
+        kind: options.kind,
+        limit,
+    })
+}
 
-    let deployments_client: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-    let deployment = match deployments_client
-        .get(&templates::deployment_name(&id))
-        .await
-    {
-        Ok(d) => d,
-        Err(_) => {
-            *resp.status_mut() = StatusCode::NOT_FOUND;
-            return Ok(resp);
+/// Returns the events reported by the worker of a Watcher, oldest first.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/events",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        (
+            "from" = Option<String>,
+            query,
+            description = "Oldest events returned, a day before `to` by default."
+        ),
+        (
+            "to" = Option<String>,
+            query,
+            description = "Newest events returned, now by default. At most 31 days after `from`."
+        ),
+        (
+            "kind" = Option<String>,
+            query,
+            description = "Only return the events of this kind, see `WatcherEventKind`."
+        ),
+        (
+            "limit" = Option<u32>,
+            query,
+            description = "Maximum number of events returned, from 1 to 10000, 1000 by default."
+        ),
+    ),
+    responses(
+        (status = 200, description = "The events of the period.", body = [WatcherEvent]),
+        (status = 400, description = "Invalid period or limit."),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 501, description = "No event store is configured."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_events(
+    id: String,
+    options: EventOptions,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let query = match event_query(options, Utc::now()) {
+        Ok(query) => query,
+        Err(message) => {
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
+            ))
         }
     };
-    if Status::Running != deployment.get_watcher_status() {
-        log::debug!("Watcher is not running...");
-        *resp.status_mut() = StatusCode::NOT_ACCEPTABLE;
-        return Ok(resp);
+    match backend.list_events(&id, &query).await {
+        Ok(Some(events)) => Ok(reply::with_status(reply::json(&events), StatusCode::OK)),
+        Ok(None) => Ok(not_found()),
+        Err(e) => Ok(events_error(e)),
     }
-    let pods_client: Api<Pod> = Api::namespaced(client.clone(), &NAMESPACE);
-    let lp = ListParams::default().labels(&format!("app=hawkeye,watcher_id={}", id));
-    let pods = pods_client.list(&lp).await.unwrap();
-    if let Some(pod_ip) = pods
-        .items
-        .first()
-        .map(|p| p.status.as_ref())
-        .flatten()
-        .map(|ps| ps.pod_ip.clone())
-        .flatten()
-    {
-        let http_client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(*CALL_WATCHER_TIMEOUT))
-            .build()
-            .unwrap();
-        // Try for new and old ports in pod
-        for port in vec![watcher.source.ingest_port, 3030] {
-            let url = format!("http://{}:{}/latest_frame", pod_ip, port);
-
-            log::info!("Calling Pod using url: {}", url);
-            let response = match http_client.get(url.as_str()).send().await {
-                Ok(r) => r,
-                Err(error) => {
-                    log::error!("Could not call {} endpoint: {:?}", url, error);
-                    *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
-                    return Ok(resp);
-                }
-            };
-
-            match response.error_for_status() {
-                Ok(image_response) => {
-                    let headers = resp.headers_mut();
-                    headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
-                    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+}
 
-                    let image_bytes = image_response.bytes().await.unwrap();
-                    *resp.body_mut() = Body::from(image_bytes.to_vec());
+/// Query parameters accepted while aggregating the events of a watcher.
+#[derive(Deserialize, Debug, Default)]
+pub struct AnalyticsOptions {
+    /// First day aggregated, as `YYYY-MM-DD` in UTC.
+    pub from: Option<String>,
+    /// Last day aggregated, today by default.
+    pub to: Option<String>,
+}
 
-                    return Ok(resp);
-                }
-                Err(_) => {
-                    continue;
-                }
-            }
-        }
-        log::error!("Error calling Pod using old and new urls");
-        *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
-    } else {
-        log::debug!("Not able to get Pod IP");
-        *resp.status_mut() = StatusCode::EXPECTATION_FAILED;
+/// The days selected by the options, at most `events::MAX_RANGE_DAYS`.
+fn analytics_days(
+    options: &AnalyticsOptions,
+    today: NaiveDate,
+) -> Result<(NaiveDate, NaiveDate), String> {
+    let parse = |day: &str| {
+        NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .map_err(|_| format!("Invalid day {}, expected YYYY-MM-DD", day))
+    };
+    let last_day = options
+        .to
+        .as_deref()
+        .map(parse)
+        .transpose()?
+        .unwrap_or(today);
+    let first_day = match options.from.as_deref() {
+        Some(from) => parse(from)?,
+        None => last_day - Duration::days(analytics::DEFAULT_DAYS - 1),
+    };
+    if first_day > last_day {
+        return Err("The first day is after the last one".to_string());
     }
-    Ok(resp)
+    if (last_day - first_day).num_days() >= events::MAX_RANGE_DAYS {
+        return Err(format!(
+            "At most {} days can be aggregated",
+            events::MAX_RANGE_DAYS
+        ));
+    }
+    Ok((first_day, last_day))
 }
 
-/// Start a Watcher worker by making sure there's a positive replica count for the Kubernetes
-/// deployment.
-pub async fn start_watcher(id: String, client: Client) -> Result<impl warp::Reply, Infallible> {
-    let deployments_client: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-
-    // Get the Kubernetes deployment for the Watcher.
-    // TODO: probably better to just get the scale
-    let deployment = match deployments_client
-        .get(&templates::deployment_name(&id))
-        .await
-    {
-        Ok(d) => d,
-        Err(_) => {
+/// Returns the daily aggregates of the events of a Watcher: its time on slate, transitions and
+/// failed actions.
+#[utoipa::path(
+    get,
+    path = "/v1/watchers/{watcher_id}/analytics",
+    params(
+        ("watcher_id" = String, path, description = "The Watcher ID."),
+        (
+            "from" = Option<String>,
+            query,
+            description = "First day aggregated, 6 days before `to` by default."
+        ),
+        (
+            "to" = Option<String>,
+            query,
+            description = "Last day aggregated, today by default. At most 31 days are aggregated."
+        ),
+    ),
+    responses(
+        (status = 200, description = "The aggregates of each day.", body = Analytics),
+        (status = 400, description = "Invalid days."),
+        (status = 404, description = "The Watcher does not exist."),
+        (status = 501, description = "No event store is configured."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(watcher_id = %id))]
+pub async fn get_watcher_analytics(
+    id: String,
+    options: AnalyticsOptions,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    let now = Utc::now();
+    let (first_day, last_day) = match analytics_days(&options, now.date().naive_utc()) {
+        Ok(days) => days,
+        Err(message) => {
             return Ok(reply::with_status(
-                reply::json(&json!({})),
-                StatusCode::NOT_FOUND,
+                reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
             ))
         }
     };
+    let query = EventQuery {
+        from: DateTime::from_utc(first_day.and_hms(0, 0, 0), Utc),
+        to: DateTime::from_utc(last_day.and_hms_milli(23, 59, 59, 999), Utc),
+        kind: None,
+        limit: analytics::MAX_EVENTS + 1,
+    };
+    let mut events = match backend.list_events(&id, &query).await {
+        Ok(Some(events)) => events,
+        Ok(None) => return Ok(not_found()),
+        Err(e) => return Ok(events_error(e)),
+    };
+    let truncated = events.len() > analytics::MAX_EVENTS;
+    events.truncate(analytics::MAX_EVENTS);
+    let analytics = Analytics {
+        from: first_day.format("%Y-%m-%d").to_string(),
+        to: last_day.format("%Y-%m-%d").to_string(),
+        days: analytics::daily(&events, first_day, last_day, now),
+        truncated,
+    };
+    Ok(reply::with_status(reply::json(&analytics), StatusCode::OK))
+}
 
-    // Actions and guards based on the current Watcher status.
-    match deployment.get_watcher_status() {
-        Status::Running => Ok(reply::with_status(
-            // No op, already running!
-            reply::json(&json!({
-                "message": "Watcher is already running"
-            })),
-            StatusCode::OK,
-        )),
-        Status::Pending => Ok(reply::with_status(
-            // No op, committing other changes.
-            reply::json(&json!({
-                "message": "Watcher is currently updating"
-            })),
-            StatusCode::CONFLICT,
-        )),
-        Status::Ready => {
-            // Start Watcher by setting Kubernetes deployment replicas=1
-            let mut patch_params = PatchParams::default();
-            patch_params.field_manager = Some("hawkeye_api".to_string());
-
-            // Set Kubernetes deployment replica=1 via patch.
-            let deployment_scale_json = json!({
-                "apiVersion": "autoscaling/v1",
-                "spec": { "replicas": 1 },
-            });
-            deployments_client
-                .patch_scale(
-                    deployment.metadata.name.as_ref().unwrap(),
-                    &patch_params,
-                    &Patch::Merge(&deployment_scale_json),
-                )
-                .await
-                .unwrap();
-
-            // Update the status of the Watcher to indicate it should be running.
-            let status_label_json = json!({
-                "apiVersion": "apps/v1",
-                "metadata": {
-                    "labels": {
-                        "target_status": Status::Running,
-                    }
-                }
-            });
-            deployments_client
-                .patch(
-                    deployment.metadata.name.as_ref().unwrap(),
-                    &patch_params,
-                    &Patch::Merge(status_label_json),
-                )
-                .await
-                .unwrap();
-
-            Ok(reply::with_status(
-                reply::json(&json!({
-                    "message": "Watcher is starting"
-                })),
-                StatusCode::OK,
+/// Adds the uploaded slate to the library, watchers can use it as soon as this returns.
+#[utoipa::path(
+    post,
+    path = "/v1/slates",
+    request_body(content = SlateUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "The slate was added to the library.", body = SlateInfo),
+        (status = 400, description = "The upload has no file or its type is not supported."),
+        (status = 413, description = "The slate is larger than 10MB."),
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn upload_slate(form: FormData, actor: String) -> Result<impl warp::Reply, Infallible> {
+    let upload = match slates::Upload::read(form).await {
+        Ok(upload) => upload,
+        Err(e) => {
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": e.to_string() })),
+                StatusCode::BAD_REQUEST,
             ))
         }
-        Status::Error => Ok(reply::with_status(
-            reply::json(&json!({
-                "message": "Watcher in error state cannot be set to running"
-            })),
-            StatusCode::NOT_ACCEPTABLE,
-        )),
+    };
+    match slates::library().save(upload).await {
+        Ok(info) => {
+            log::info!("Slate {} uploaded by {}", info.id, actor);
+            Ok(reply::with_status(reply::json(&info), StatusCode::CREATED))
+        }
+        Err(e) => Ok(backend_error(e)),
     }
 }
 
-/// Stop a Watcher worker by making sure there's a replica count of 0 for the Kubernetes
-/// deployment.
-pub async fn stop_watcher(id: String, client: Client) -> Result<impl warp::Reply, Infallible> {
-    let deployments_client: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-    // TODO: probably better to just get the scale
-    let deployment = match deployments_client
-        .get(&templates::deployment_name(&id))
-        .await
-    {
-        Ok(d) => d,
-        Err(_) => {
-            return Ok(reply::with_status(
-                reply::json(&json!({})),
-                StatusCode::NOT_FOUND,
-            ))
+/// List the slates of the library.
+#[utoipa::path(
+    get,
+    path = "/v1/slates",
+    responses(
+        (
+            status = 200,
+            description = "The slates of the library, oldest first.",
+            body = [SlateInfo]
+        ),
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn list_slates() -> Result<impl warp::Reply, Infallible> {
+    match slates::library().list().await {
+        Ok(slates) => Ok(reply::with_status(reply::json(&slates), StatusCode::OK)),
+        Err(e) => Ok(backend_error(e)),
+    }
+}
+
+/// Serves the image of a slate, as the workers download it.
+#[utoipa::path(
+    get,
+    path = "/v1/slates/{slate_id}/image",
+    params(
+        ("slate_id" = String, path, description = "The slate ID."),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The image or video of the slate, with the content type it was \
+                uploaded with."
+        ),
+        (status = 404, description = "The slate does not exist."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(slate_id = %id))]
+pub async fn get_slate_image(id: String) -> Result<impl warp::Reply, Infallible> {
+    match slates::library().get(&id).await {
+        Ok(Some((info, image))) => {
+            let mut resp = warp::reply::Response::new(Body::from(image));
+            if let Ok(content_type) = HeaderValue::from_str(&info.content_type) {
+                resp.headers_mut().insert(CONTENT_TYPE, content_type);
+            }
+            Ok(resp)
         }
+        Ok(None) => Ok(not_found().into_response()),
+        Err(e) => Ok(backend_error(e).into_response()),
+    }
+}
+
+/// Deletes a slate of the library, unless a watcher still uses it.
+#[utoipa::path(
+    delete,
+    path = "/v1/slates/{slate_id}",
+    params(
+        ("slate_id" = String, path, description = "The slate ID."),
+    ),
+    responses(
+        (status = 200, description = "The slate was deleted."),
+        (status = 404, description = "The slate does not exist."),
+        (status = 409, description = "Watchers still use the slate."),
+    )
+)]
+#[tracing::instrument(skip_all, fields(slate_id = %id))]
+pub async fn delete_slate(
+    id: String,
+    actor: String,
+    backend: Backend,
+) -> Result<impl warp::Reply, Infallible> {
+    // A deleted watcher still uses its slates once restored
+    let query = ListQuery {
+        include_deleted: true,
+        ..ListQuery::default()
     };
-    // TODO: Set target_status to Ready
-    match deployment.get_watcher_status() {
-        Status::Ready => Ok(reply::with_status(
-            reply::json(&json!({
-                "message": "Watcher is already stopped"
-            })),
-            StatusCode::OK,
-        )),
-        Status::Pending => Ok(reply::with_status(
+    let page = match backend.list_watchers(&query).await {
+        Ok(page) => page,
+        Err(e) => return Ok(backend_error(e)),
+    };
+    let used_by: Vec<String> = page
+        .watchers
+        .iter()
+        .filter(|w| slates::is_used_by(w, &id))
+        .filter_map(|w| w.id.clone())
+        .collect();
+    if !used_by.is_empty() {
+        return Ok(reply::with_status(
             reply::json(&json!({
-                "message": "Watcher is currently updating"
+                "message": format!("Slate is used by the watchers {}", used_by.join(", "))
             })),
             StatusCode::CONFLICT,
-        )),
-        Status::Running => {
-            // Stop watcher / replicas to 0
-            let mut patch_params = PatchParams::default();
-            patch_params.field_manager = Some("hawkeye_api".to_string());
-
-            let deployment_scale_json = json!({
-                "apiVersion": "autoscaling/v1",
-                "spec": { "replicas": 0 },
-            });
-            deployments_client
-                .patch_scale(
-                    deployment.metadata.name.as_ref().unwrap(),
-                    &patch_params,
-                    &Patch::Merge(&deployment_scale_json),
-                )
-                .await
-                .unwrap();
-
-            // Update the status of the Watcher to indicate it should be running.
-            let status_label_json = json!({
-                "apiVersion": "apps/v1",
-                "metadata": {
-                    "labels": {
-                        "target_status": Status::Ready,
-                    }
-                }
-            });
-            deployments_client
-                .patch(
-                    deployment.metadata.name.as_ref().unwrap(),
-                    &patch_params,
-                    &Patch::Merge(status_label_json),
-                )
-                .await
-                .unwrap();
+        ));
+    }
 
+    match slates::library().delete(&id).await {
+        Ok(true) => {
+            log::info!("Slate {} deleted by {}", id, actor);
             Ok(reply::with_status(
                 reply::json(&json!({
-                    "message": "Watcher is stopping"
+                    "message": "Slate has been deleted"
                 })),
                 StatusCode::OK,
             ))
         }
-        Status::Error => Ok(reply::with_status(
+        Ok(false) => Ok(reply::with_status(
             reply::json(&json!({
-                "message": "Watcher in error state cannot be set to stopped"
+                "message": "Slate does not exist"
             })),
-            StatusCode::NOT_ACCEPTABLE,
+            StatusCode::NOT_FOUND,
         )),
+        Err(e) => Ok(backend_error(e)),
     }
 }
 
-pub async fn delete_watcher(id: String, client: Client) -> Result<impl warp::Reply, Infallible> {
-    let dp = DeleteParams::default();
+/// Serves the OpenAPI document of this API as JSON.
+#[utoipa::path(
+    get,
+    path = "/v1/openapi.json",
+    responses(
+        (status = 200, description = "The OpenAPI document."),
+    )
+)]
+pub async fn openapi_spec() -> Result<impl warp::Reply, Infallible> {
+    Ok(reply::json(&*openapi::SPEC))
+}
 
-    let deployments_client: Api<Deployment> = Api::namespaced(client.clone(), &NAMESPACE);
-    let _ = deployments_client
-        .delete(&templates::deployment_name(&id), &dp)
-        .await;
+/// Serves the Swagger UI to browse the OpenAPI document, from `/v1/docs/` so its page finds its
+/// assets.
+pub async fn swagger_ui(full_path: FullPath, tail: Tail) -> Result<impl warp::Reply, Infallible> {
+    if !full_path.as_str().ends_with('/') && tail.as_str().is_empty() {
+        let redirect = reply::with_status(reply::reply(), StatusCode::FOUND);
+        return Ok(reply::with_header(redirect, LOCATION, "/v1/docs/").into_response());
+    }
+    match openapi::swagger_ui_file(tail.as_str()) {
+        Ok(Some(file)) => {
+            let mut resp = warp::reply::Response::new(Body::from(file.bytes.into_owned()));
+            if let Ok(value) = HeaderValue::from_str(&file.content_type) {
+                resp.headers_mut().insert(CONTENT_TYPE, value);
+            }
+            Ok(resp)
+        }
+        Ok(None) => Ok(not_found().into_response()),
+        Err(e) => Ok(backend_error(e).into_response()),
+    }
+}
 
-    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), &NAMESPACE);
-    let _ = config_maps
-        .delete(&templates::configmap_name(&id), &dp)
-        .await;
+/// Cookie holding the `state` of a login, compared by its callback so the codes of the logins
+/// started elsewhere are refused.
+pub const LOGIN_STATE_COOKIE: &str = "hawkeye_oidc_state";
 
-    let services: Api<Service> = Api::namespaced(client, &NAMESPACE);
-    match services.delete(&templates::service_name(&id), &dp).await {
-        Ok(_) => Ok(reply::with_status(
-            reply::json(&json!({
-                "message": "Watcher has been deleted"
-            })),
-            StatusCode::OK,
-        )),
-        Err(_) => Ok(reply::with_status(
-            reply::json(&json!({
-                "message": "Watcher does not exist"
-            })),
-            StatusCode::NOT_FOUND,
-        )),
+/// Query of the OIDC provider coming back to `GET /v1/auth/callback`.
+#[derive(Deserialize, Debug)]
+pub struct LoginCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    /// Set by the provider when the login failed.
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Reply used when a login fails, or when OIDC is not configured.
+fn oidc_error(e: anyhow::Error) -> reply::WithStatus<reply::Json> {
+    if e.is::<oidc::NotConfigured>() {
+        return reply::with_status(
+            reply::json(&json!({ "message": e.to_string() })),
+            StatusCode::NOT_IMPLEMENTED,
+        );
+    }
+    log::warn!("OIDC login failed: {:?}", e);
+    reply::with_status(
+        reply::json(&json!({ "message": format!("Login failed: {}", e) })),
+        StatusCode::UNAUTHORIZED,
+    )
+}
+
+/// Redirects the browser to the OIDC provider to log in.
+#[utoipa::path(
+    get,
+    path = "/v1/auth/login",
+    responses(
+        (status = 302, description = "Redirect to the login page of the provider."),
+        (status = 501, description = "OIDC is not configured."),
+    )
+)]
+pub async fn login() -> Result<impl warp::Reply, Infallible> {
+    let state = Uuid::new_v4().to_string();
+    let url = match oidc::login_url(&state).await {
+        Ok(url) => url,
+        Err(e) => return Ok(oidc_error(e).into_response()),
+    };
+    let cookie = format!(
+        "{}={}; Path=/v1/auth; Max-Age=600; HttpOnly; Secure; SameSite=Lax",
+        LOGIN_STATE_COOKIE, state
+    );
+    let redirect = reply::with_status(reply::reply(), StatusCode::FOUND);
+    let redirect = reply::with_header(redirect, LOCATION, url);
+    Ok(reply::with_header(redirect, SET_COOKIE, cookie).into_response())
+}
+
+/// Exchanges the code of the login for the tokens of the person, once the provider redirected
+/// the browser back.
+#[utoipa::path(
+    get,
+    path = "/v1/auth/callback",
+    params(
+        ("code" = Option<String>, query, description = "Code of the login, given by the provider."),
+        (
+            "state" = Option<String>,
+            query,
+            description = "Must match the state of the login started by this browser."
+        ),
+    ),
+    responses(
+        (status = 200, description = "Tokens of the person.", body = Tokens),
+        (status = 400, description = "The login was not started by this browser."),
+        (status = 401, description = "The login failed."),
+        (status = 501, description = "OIDC is not configured."),
+    )
+)]
+pub async fn login_callback(
+    callback: LoginCallback,
+    expected_state: Option<String>,
+) -> Result<impl warp::Reply, Infallible> {
+    if let Some(error) = callback.error {
+        let description = callback.error_description.unwrap_or_default();
+        return Ok(oidc_error(anyhow::anyhow!("{} {}", error, description)));
+    }
+    let code = match callback.code {
+        Some(code) if callback.state.is_some() && callback.state == expected_state => code,
+        _ => {
+            return Ok(reply::with_status(
+                reply::json(&json!({ "message": "Unknown login, start it again" })),
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+    match oidc::exchange_code(&code).await {
+        Ok(tokens) => Ok(reply::with_status(reply::json(&tokens), StatusCode::OK)),
+        Err(e) => Ok(oidc_error(e)),
+    }
+}
+
+/// Exchanges the credentials of a service for its token, with the client credentials flow.
+#[utoipa::path(
+    post,
+    path = "/v1/auth/token",
+    request_body = ClientCredentials,
+    responses(
+        (status = 200, description = "Token of the service.", body = Tokens),
+        (status = 401, description = "The provider refused the credentials."),
+        (status = 501, description = "OIDC is not configured."),
+    )
+)]
+pub async fn request_token(credentials: ClientCredentials) -> Result<impl warp::Reply, Infallible> {
+    match oidc::client_credentials(&credentials).await {
+        Ok(tokens) => Ok(reply::with_status(reply::json(&tokens), StatusCode::OK)),
+        Err(e) => Ok(oidc_error(e)),
     }
 }
 
-pub async fn healthcheck(client: Client) -> Result<impl warp::Reply, Infallible> {
-    match client.apiserver_version().await {
-        Ok(_info) => Ok(reply::with_status(
+pub async fn healthcheck(backend: Backend) -> Result<impl warp::Reply, Infallible> {
+    match backend.healthcheck().await {
+        Ok(_) => Ok(reply::with_status(
             reply::json(&json!({
                 "message": "All good! 🎉",
             })),
@@ -572,47 +3035,31 @@ pub async fn healthcheck(client: Client) -> Result<impl warp::Reply, Infallible>
     }
 }
 
-trait WatcherStatus {
-    fn get_watcher_status(&self) -> Status;
+/// Liveness probe: the process is able to serve requests, without calling Kubernetes, so the API
+/// is not restarted while the API server is unreachable.
+pub async fn healthz() -> Result<impl warp::Reply, Infallible> {
+    Ok(reply::json(&json!({ "message": "Alive" })))
 }
 
-impl WatcherStatus for Deployment {
-    fn get_watcher_status(&self) -> Status {
-        let target_status = self
-            .metadata
-            .labels
-            .as_ref()
-            .map(|labels| {
-                labels
-                    .get("target_status")
-                    .map(|status| serde_json::from_str(&format!("\"{}\"", status)).ok())
-            })
-            .flatten()
-            .flatten()
-            .unwrap_or({
-                let name = self.metadata.name.as_ref().expect("Name must be present");
-                log::error!(
-                    "Deployment {} is missing required 'target_status' label",
-                    name
-                );
-                Status::Error
-            });
-
-        if let Some(status) = self.status.as_ref() {
-            let deploy_status = if status.available_replicas.unwrap_or(0) > 0 {
-                Status::Running
-            } else {
-                Status::Ready
-            };
-            match (deploy_status, target_status) {
-                (Status::Running, Status::Running) => Status::Running,
-                (Status::Ready, Status::Ready) => Status::Ready,
-                (Status::Ready, Status::Running) => Status::Pending,
-                (Status::Running, Status::Ready) => Status::Pending,
-                (_, _) => Status::Error,
-            }
-        } else {
-            Status::Error
-        }
+/// Readiness probe: Kubernetes can be reached and the cache of the watchers is loaded.
+pub async fn readyz(backend: Backend) -> Result<impl warp::Reply, Infallible> {
+    if let Err(err) = backend.healthcheck().await {
+        log::warn!("Not ready, cannot communicate with K8s API: {:?}", err);
+        return Ok(reply::with_status(
+            reply::json(&json!({
+                "message": "Not able to communicate with the Kubernetes API Server.",
+            })),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    }
+    if !backend.is_synced() {
+        return Ok(reply::with_status(
+            reply::json(&json!({ "message": "The cache of the watchers is not loaded yet." })),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
     }
+    Ok(reply::with_status(
+        reply::json(&json!({ "message": "Ready" })),
+        StatusCode::OK,
+    ))
 }
@@ -1,12 +1,37 @@
+mod analytics;
+mod audit;
 mod auth;
+mod backend;
+mod cache;
 mod config;
+mod crd;
+mod drift;
+mod events;
 mod filters;
+mod frames;
 mod handlers;
+mod metrics;
+mod notifications;
+mod oidc;
+mod openapi;
+mod operator;
+mod purge;
+mod rate_limit;
+mod reconciler;
+mod request_id;
+mod revisions;
+mod scheduler;
+mod slates;
+mod telemetry;
 mod templates;
 
 use hawkeye_core::utils::maybe_bootstrap_sentry;
-use kube::Client;
+use kube::{Client, CustomResourceExt};
+use std::convert::Infallible;
 use std::env;
+use std::sync::Arc;
+use warp::hyper::service::{make_service_fn, service_fn};
+use warp::hyper::Server;
 use warp::Filter;
 
 #[tokio::main]
@@ -19,17 +44,71 @@ async fn main() -> anyhow::Result<()> {
 
     // `sentry_client` must be in scope in main() to stay alive and functional.
     let sentry_client = maybe_bootstrap_sentry();
-    if sentry_client.is_none() {
+    let json_logs = config::LOG_FORMAT.as_str() == "json";
+    if config::OTLP_ENDPOINT.is_some() || json_logs {
+        telemetry::init(
+            config::OTLP_ENDPOINT.as_deref(),
+            json_logs,
+            sentry_client.is_none(),
+        )?;
+    } else if sentry_client.is_none() {
         pretty_env_logger::init();
     }
 
-    let client = Client::try_default().await?;
+    // Prints the definition of the `Watcher` resource, to be applied before using operator mode
+    if env::args().nth(1).as_deref() == Some("crd") {
+        println!("{}", serde_yaml::to_string(&crd::Watcher::crd())?);
+        return Ok(());
+    }
+
+    let backend: backend::Backend = if config::BACKEND.as_str() == "memory" {
+        log::warn!("Using the in-memory backend, watchers are not persisted and never run");
+        Arc::new(backend::memory::MemoryBackend::default())
+    } else {
+        let client = Client::try_default().await?;
+
+        reconciler::spawn(client.clone());
+        backend::ports::spawn(client.clone());
+        if *config::OPERATOR_MODE {
+            for namespace in config::NAMESPACES.iter() {
+                tokio::spawn(operator::run(client.clone(), namespace.clone()));
+            }
+        }
+
+        let mut kubernetes = backend::kubernetes::KubernetesBackend::new(client);
+        if let Some(url) = config::EVENT_STORE.as_ref() {
+            kubernetes = kubernetes.with_event_store(events::open(url).await?);
+        }
+        Arc::new(kubernetes)
+    };
+    let backend = if notifications::enabled() {
+        notifications::spawn(backend)
+    } else {
+        backend
+    };
+    scheduler::spawn(backend.clone());
+    purge::spawn(backend.clone());
+    let v1 = filters::v1(backend);
+    let routes = v1
+        .with(warp::log::custom(request_id::access_log))
+        .with(warp::trace::request());
 
-    let v1 = filters::v1(client);
-    let routes = v1.with(warp::log("watchers"));
+    // Served with hyper directly, so each request runs in the scope of its request ID
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |_| {
+        let service = service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                request_id::serve(service.clone(), request)
+            }))
+        }
+    });
 
     log::info!("Running API at 0.0.0.0:8080 ..");
-    warp::serve(routes).run(([0, 0, 0, 0], 8080)).await;
+    Server::bind(&([0, 0, 0, 0], 8080).into())
+        .serve(make_service)
+        .await?;
+    telemetry::shutdown();
 
     Ok(())
 }
@@ -0,0 +1,120 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Detection stats of a watcher, summarized from the Prometheus metrics of its worker.
+#[derive(Serialize, JsonSchema, Debug, Default, PartialEq)]
+pub struct MetricsSummary {
+    /// Number of times a slate image was found in the stream.
+    pub slate_found: u64,
+    /// Number of times the content was found in the stream.
+    pub content_found: u64,
+    pub similarity_executions: u64,
+    pub similarity_execution_seconds: Latency,
+    pub frame_processing_seconds: Latency,
+    pub http_calls: HttpCalls,
+}
+
+/// Processing latency, from a Prometheus histogram.
+#[derive(Serialize, JsonSchema, Debug, Default, PartialEq)]
+pub struct Latency {
+    pub count: u64,
+    /// Total seconds spent.
+    pub sum: f64,
+    /// Average seconds spent, missing before the first measure.
+    pub average: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Default, PartialEq)]
+pub struct HttpCalls {
+    pub success: u64,
+    pub error: u64,
+    pub retried: u64,
+    pub retries_exhausted: u64,
+}
+
+/// Summarizes the metrics in the Prometheus text exposition format. Unknown metrics are ignored.
+pub fn summarize(contents: &str) -> MetricsSummary {
+    let samples = parse_samples(contents);
+    let counter = |name: &str| samples.get(name).copied().unwrap_or(0.0) as u64;
+    let latency = |name: &str| {
+        let count = counter(&format!("{}_count", name));
+        let sum = samples
+            .get(&format!("{}_sum", name))
+            .copied()
+            .unwrap_or(0.0);
+        Latency {
+            count,
+            sum,
+            average: if count > 0 {
+                Some(sum / count as f64)
+            } else {
+                None
+            },
+        }
+    };
+
+    MetricsSummary {
+        slate_found: counter("slate_found_in_stream"),
+        content_found: counter("content_found_in_stream"),
+        similarity_executions: counter("similarity_execution"),
+        similarity_execution_seconds: latency("similarity_execution_seconds"),
+        frame_processing_seconds: latency("frame_processing_seconds"),
+        http_calls: HttpCalls {
+            success: counter("http_call_success"),
+            error: counter("http_call_error"),
+            retried: counter("http_call_retried"),
+            retries_exhausted: counter("http_call_retries_exhausted"),
+        },
+    }
+}
+
+/// Reads the value of each sample by metric name, adding up the samples with different labels.
+fn parse_samples(contents: &str) -> HashMap<String, f64> {
+    let mut samples = HashMap::new();
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (series, value) = match line.rsplit_once(char::is_whitespace) {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let name = series.split('{').next().unwrap_or(series).trim();
+        if let Ok(value) = value.parse::<f64>() {
+            *samples.entry(name.to_string()).or_insert(0.0) += value;
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_worker_metrics() {
+        let contents = r#"
+# HELP slate_found_in_stream Number of times a slate image was found in the stream
+# TYPE slate_found_in_stream counter
+slate_found_in_stream 3
+content_found_in_stream 2
+similarity_execution 10
+frame_processing_seconds_bucket{le="0.005"} 4
+frame_processing_seconds_bucket{le="+Inf"} 10
+frame_processing_seconds_sum 0.5
+frame_processing_seconds_count 10
+http_call_success 1
+"#;
+        let summary = summarize(contents);
+
+        assert_eq!(summary.slate_found, 3);
+        assert_eq!(summary.content_found, 2);
+        assert_eq!(summary.similarity_executions, 10);
+        assert_eq!(summary.frame_processing_seconds.count, 10);
+        assert_eq!(summary.frame_processing_seconds.average, Some(0.05));
+        assert_eq!(summary.similarity_execution_seconds.average, None);
+        assert_eq!(summary.http_calls.success, 1);
+        assert_eq!(summary.http_calls.error, 0);
+    }
+}
@@ -0,0 +1,482 @@
+//! Notifies downstream automation of the lifecycle changes of the watchers.
+//!
+//! Events are sent as JSON to the configured webhook URLs and SNS topic. The body is signed with
+//! HMAC-SHA256 when a secret is configured. Deliveries are retried with an exponential backoff,
+//! events that could not be delivered are written to the dead-letter log.
+use crate::audit::AuditEntry;
+use crate::backend::{
+    Backend, ListQuery, LogQuery, LogStream, StatusChange, WatcherBackend, WatcherPage,
+};
+use crate::config::{DEAD_LETTER_FILE, SNS_TOPIC_ARN, WEBHOOK_SECRET, WEBHOOK_URLS};
+use crate::drift::Drift;
+use crate::events::EventQuery;
+use crate::request_id;
+use crate::revisions::Revision;
+use async_trait::async_trait;
+use chrono::Utc;
+use hawkeye_core::models::{
+    Heartbeat, Source, Status, ThresholdChange, TransitionTrigger, Watcher, WatcherEvent,
+};
+use hmac::{Hmac, Mac};
+use rusoto_core::Region;
+use rusoto_sns::{MessageAttributeValue, PublishInput, Sns, SnsClient};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use warp::hyper::body::Bytes;
+
+/// Number of attempts made to deliver an event to each destination.
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled after every failed attempt.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the watchers are checked for errors.
+const ERROR_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const SIGNATURE_HEADER: &str = "X-Hawkeye-Signature";
+
+/// Lifecycle change of a watcher.
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Created,
+    Started,
+    Stopped,
+    Errored,
+    Deleted,
+    UpgradeApplied,
+}
+
+/// Body of the notifications.
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    /// Unique ID of the event, to deduplicate retried deliveries.
+    pub id: String,
+    pub event: EventKind,
+    pub watcher_id: String,
+    /// RFC 3339 time of the change.
+    pub timestamp: String,
+    /// ID of the API request that made the change, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Whether any destination is configured for the notifications.
+pub fn enabled() -> bool {
+    !WEBHOOK_URLS.is_empty() || SNS_TOPIC_ARN.is_some()
+}
+
+/// Wraps the backend to notify the lifecycle changes applied through it, and starts checking the
+/// watchers for errors in the background.
+pub fn spawn(backend: Backend) -> Backend {
+    let inner = backend.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ERROR_CHECK_INTERVAL);
+        let mut statuses: HashMap<String, Status> = HashMap::new();
+        loop {
+            interval.tick().await;
+            match inner.list_watchers(&ListQuery::default()).await {
+                Ok(page) => statuses = notify_errors(&statuses, page.watchers),
+                Err(e) => log::error!("Could not check the watchers for errors: {:?}", e),
+            }
+        }
+    });
+    Arc::new(NotifyingBackend { inner: backend })
+}
+
+/// Notifies the watchers that moved to the error status since the previous check, and returns
+/// the current statuses.
+fn notify_errors(
+    previous: &HashMap<String, Status>,
+    watchers: Vec<Watcher>,
+) -> HashMap<String, Status> {
+    let mut statuses = HashMap::new();
+    for watcher in watchers {
+        if let (Some(id), Some(status)) = (watcher.id, watcher.status) {
+            match previous.get(&id) {
+                Some(before) if status == Status::Error && *before != Status::Error => {
+                    notify(EventKind::Errored, &id)
+                }
+                _ => (),
+            }
+            statuses.insert(id, status);
+        }
+    }
+    statuses
+}
+
+/// Sends the event to all destinations in the background.
+pub fn notify(kind: EventKind, watcher_id: &str) {
+    let event = Event {
+        id: uuid::Uuid::new_v4().to_string(),
+        event: kind,
+        watcher_id: watcher_id.to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        request_id: request_id::current(),
+    };
+    tokio::spawn(deliver(event));
+}
+
+async fn deliver(event: Event) {
+    let body = match serde_json::to_string(&event) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Could not serialize event {:?}: {:?}", event, e);
+            return;
+        }
+    };
+    let signature = WEBHOOK_SECRET.as_deref().map(|secret| sign(secret, &body));
+    let request_id = event.request_id.as_deref();
+
+    for url in WEBHOOK_URLS.iter() {
+        let result =
+            with_retries(|| post_webhook(url, &body, signature.as_deref(), request_id)).await;
+        if let Err(e) = result {
+            dead_letter(url, &body, e).await;
+        }
+    }
+    if let Some(topic_arn) = SNS_TOPIC_ARN.as_ref() {
+        let result = with_retries(|| publish_sns(topic_arn, &body, signature.as_deref())).await;
+        if let Err(e) = result {
+            dead_letter(topic_arn, &body, e).await;
+        }
+    }
+}
+
+/// Runs the delivery until it succeeds, at most `MAX_ATTEMPTS` times.
+async fn with_retries<F, Fut>(mut delivery: F) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match delivery().await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= MAX_ATTEMPTS => return Err(e),
+            Err(e) => log::warn!("Delivery attempt {} failed: {:?}", attempt, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+async fn post_webhook(
+    url: &str,
+    body: &str,
+    signature: Option<&str>,
+    request_id: Option<&str>,
+) -> anyhow::Result<()> {
+    let http_client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()?;
+    let mut request = http_client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string());
+    if let Some(signature) = signature {
+        request = request.header(SIGNATURE_HEADER, signature);
+    }
+    if let Some(request_id) = request_id {
+        request = request.header(request_id::HEADER, request_id);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+async fn publish_sns(topic_arn: &str, body: &str, signature: Option<&str>) -> anyhow::Result<()> {
+    let sns = SnsClient::new(Region::default());
+    let message_attributes = signature.map(|signature| {
+        let mut attributes = HashMap::new();
+        attributes.insert(
+            "signature".to_string(),
+            MessageAttributeValue {
+                data_type: "String".to_string(),
+                string_value: Some(signature.to_string()),
+                ..Default::default()
+            },
+        );
+        attributes
+    });
+    sns.publish(PublishInput {
+        topic_arn: Some(topic_arn.to_string()),
+        message: body.to_string(),
+        message_attributes,
+        ..Default::default()
+    })
+    .await?;
+    Ok(())
+}
+
+/// Records an event that could not be delivered, in the log and in the dead-letter file when
+/// configured.
+async fn dead_letter(destination: &str, body: &str, error: anyhow::Error) {
+    log::error!(
+        "Could not deliver event to {}, giving up: {} ({:?})",
+        destination,
+        body,
+        error
+    );
+    let path = match DEAD_LETTER_FILE.as_ref() {
+        Some(path) => path,
+        None => return,
+    };
+    let line = serde_json::json!({
+        "destination": destination,
+        "error": error.to_string(),
+        "event": serde_json::from_str::<serde_json::Value>(body).unwrap_or_default(),
+    });
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(format!("{}\n", line).as_bytes()).await
+    };
+    if let Err(e) = written.await {
+        log::error!("Could not write to the dead-letter file {}: {:?}", path, e);
+    }
+}
+
+/// Signs the body with HMAC-SHA256, in the format `sha256=<hex digest>`.
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether the watcher is already deleted, its deletion notified.
+async fn is_deleted(backend: &Backend, id: &str) -> anyhow::Result<bool> {
+    Ok(backend
+        .get_watcher_config(id)
+        .await?
+        .map_or(false, |w| w.deleted_at.is_some()))
+}
+
+/// Notifies the changes successfully applied by the wrapped backend.
+struct NotifyingBackend {
+    inner: Backend,
+}
+
+#[async_trait]
+impl WatcherBackend for NotifyingBackend {
+    async fn list_watchers(&self, query: &ListQuery) -> anyhow::Result<WatcherPage> {
+        self.inner.list_watchers(query).await
+    }
+
+    async fn get_watcher(&self, id: &str) -> anyhow::Result<Option<Watcher>> {
+        self.inner.get_watcher(id).await
+    }
+
+    async fn get_watcher_config(&self, id: &str) -> anyhow::Result<Option<Watcher>> {
+        self.inner.get_watcher_config(id).await
+    }
+
+    async fn get_watcher_status(&self, id: &str) -> anyhow::Result<Option<Status>> {
+        self.inner.get_watcher_status(id).await
+    }
+
+    async fn create_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()> {
+        self.inner.create_watcher(id, watcher).await?;
+        notify(EventKind::Created, id);
+        Ok(())
+    }
+
+    async fn update_watcher(&self, id: &str, watcher: &Watcher) -> anyhow::Result<()> {
+        let was_deleted = is_deleted(&self.inner, id).await?;
+        self.inner.update_watcher(id, watcher).await?;
+        // The watchers are deleted softly, by an update
+        if watcher.deleted_at.is_some() && !was_deleted {
+            notify(EventKind::Deleted, id);
+        }
+        Ok(())
+    }
+
+    async fn delete_watcher(&self, id: &str) -> anyhow::Result<bool> {
+        // The purge of a deleted watcher was already notified
+        let was_deleted = is_deleted(&self.inner, id).await?;
+        let deleted = self.inner.delete_watcher(id).await?;
+        if deleted && !was_deleted {
+            notify(EventKind::Deleted, id);
+        }
+        Ok(deleted)
+    }
+
+    async fn upgrade_watcher(&self, id: &str, restart: bool) -> anyhow::Result<()> {
+        self.inner.upgrade_watcher(id, restart).await?;
+        notify(EventKind::UpgradeApplied, id);
+        Ok(())
+    }
+
+    async fn start_watcher(&self, id: &str) -> anyhow::Result<StatusChange> {
+        let change = self.inner.start_watcher(id).await?;
+        if change == StatusChange::Applied {
+            notify(EventKind::Started, id);
+        }
+        Ok(change)
+    }
+
+    async fn stop_watcher(
+        &self,
+        id: &str,
+        grace_seconds: Option<u32>,
+    ) -> anyhow::Result<StatusChange> {
+        let change = self.inner.stop_watcher(id, grace_seconds).await?;
+        if change == StatusChange::Applied {
+            notify(EventKind::Stopped, id);
+        }
+        Ok(change)
+    }
+
+    async fn allocate_ingest_port(&self, source: &Source) -> anyhow::Result<Option<u32>> {
+        self.inner.allocate_ingest_port(source).await
+    }
+
+    async fn find_port_conflict(
+        &self,
+        ports: &[u32],
+        exclude_id: Option<&str>,
+    ) -> anyhow::Result<Option<String>> {
+        self.inner.find_port_conflict(ports, exclude_id).await
+    }
+
+    async fn reserve_name(&self, name: &str, id: &str) -> anyhow::Result<Option<String>> {
+        self.inner.reserve_name(name, id).await
+    }
+
+    async fn release_name(&self, name: &str, id: &str) -> anyhow::Result<()> {
+        self.inner.release_name(name, id).await
+    }
+
+    async fn get_video_frame(&self, id: &str) -> anyhow::Result<Option<Bytes>> {
+        self.inner.get_video_frame(id).await
+    }
+
+    async fn get_watcher_metrics(&self, id: &str) -> anyhow::Result<Option<String>> {
+        self.inner.get_watcher_metrics(id).await
+    }
+
+    async fn reload_watcher(&self, id: &str) -> anyhow::Result<bool> {
+        self.inner.reload_watcher(id).await
+    }
+
+    async fn set_watcher_threshold(
+        &self,
+        id: &str,
+        change: &ThresholdChange,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        self.inner.set_watcher_threshold(id, change).await
+    }
+
+    async fn calibrate_watcher(
+        &self,
+        id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        self.inner.calibrate_watcher(id, seconds).await
+    }
+
+    async fn record_watcher(
+        &self,
+        id: &str,
+        seconds: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        self.inner.record_watcher(id, seconds).await
+    }
+
+    async fn get_watcher_state(&self, id: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        self.inner.get_watcher_state(id).await
+    }
+
+    async fn trigger_transition(
+        &self,
+        id: &str,
+        trigger: &TransitionTrigger,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        self.inner.trigger_transition(id, trigger).await
+    }
+
+    async fn get_action_history(&self, id: &str) -> anyhow::Result<Option<serde_json::Value>> {
+        self.inner.get_action_history(id).await
+    }
+
+    async fn replay_action(
+        &self,
+        id: &str,
+        index: u64,
+    ) -> anyhow::Result<Option<serde_json::Value>> {
+        self.inner.replay_action(id, index).await
+    }
+
+    async fn get_watcher_logs(
+        &self,
+        id: &str,
+        query: &LogQuery,
+    ) -> anyhow::Result<Option<LogStream>> {
+        self.inner.get_watcher_logs(id, query).await
+    }
+
+    async fn get_watcher_drift(&self, id: &str) -> anyhow::Result<Option<Vec<Drift>>> {
+        self.inner.get_watcher_drift(id).await
+    }
+
+    async fn record_audit(&self, id: &str, entry: &AuditEntry) -> anyhow::Result<()> {
+        self.inner.record_audit(id, entry).await
+    }
+
+    async fn get_audit(&self, id: &str) -> anyhow::Result<Option<Vec<AuditEntry>>> {
+        self.inner.get_audit(id).await
+    }
+
+    async fn record_revision(&self, id: &str, revision: &Revision) -> anyhow::Result<Option<u64>> {
+        self.inner.record_revision(id, revision).await
+    }
+
+    async fn list_revisions(&self, id: &str) -> anyhow::Result<Option<Vec<Revision>>> {
+        self.inner.list_revisions(id).await
+    }
+
+    async fn record_heartbeat(&self, id: &str, heartbeat: &Heartbeat) -> anyhow::Result<bool> {
+        self.inner.record_heartbeat(id, heartbeat).await
+    }
+
+    async fn record_events(&self, id: &str, events: &[WatcherEvent]) -> anyhow::Result<bool> {
+        self.inner.record_events(id, events).await
+    }
+
+    async fn list_events(
+        &self,
+        id: &str,
+        query: &EventQuery,
+    ) -> anyhow::Result<Option<Vec<WatcherEvent>>> {
+        self.inner.list_events(id, query).await
+    }
+
+    async fn healthcheck(&self) -> anyhow::Result<()> {
+        self.inner.healthcheck().await
+    }
+
+    fn is_synced(&self) -> bool {
+        self.inner.is_synced()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_body_with_hmac_sha256() {
+        assert_eq!(
+            sign("key", "The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+}
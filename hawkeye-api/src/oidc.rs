@@ -0,0 +1,222 @@
+//! Login of the clients with an OpenID Connect provider, so the API sits behind the single
+//! sign-on of the company.
+//!
+//! People log in with the authorization code flow, through `GET /v1/auth/login`, and services
+//! with the client credentials flow, through `POST /v1/auth/token`. Both get the tokens of the
+//! provider, sent as bearer tokens to the API and validated like any JWT, see `auth`.
+use crate::auth::Grant;
+use crate::config;
+use lazy_static::lazy_static;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+lazy_static! {
+    static ref DISCOVERY: RwLock<Option<Discovery>> = RwLock::new(None);
+}
+
+/// Error of the requests made while OIDC is not configured.
+#[derive(Debug)]
+pub struct NotConfigured;
+
+impl std::fmt::Display for NotConfigured {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OIDC is not configured")
+    }
+}
+
+impl std::error::Error for NotConfigured {}
+
+/// Endpoints of the provider, from its discovery document.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Tokens issued by the provider.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Tokens {
+    /// Bearer token of the API.
+    pub access_token: String,
+    /// Identity of the person logged in, missing for the client credentials.
+    pub id_token: Option<String>,
+    pub token_type: String,
+    /// Seconds the access token is valid for.
+    pub expires_in: Option<u64>,
+}
+
+/// Client credentials exchanged for a token, by `POST /v1/auth/token`.
+#[derive(Deserialize, JsonSchema, Debug, Clone)]
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Space separated scopes requested, like `admin`.
+    pub scope: Option<String>,
+}
+
+/// Endpoints of the provider of `HAWKEYE_OIDC_ISSUER`, fetched once from
+/// `/.well-known/openid-configuration`.
+pub async fn discovery() -> anyhow::Result<Discovery> {
+    if let Some(discovery) = DISCOVERY.read().await.as_ref() {
+        return Ok(discovery.clone());
+    }
+    let issuer = config::OIDC_ISSUER.as_ref().ok_or(NotConfigured)?;
+    let url = format!("{}/.well-known/openid-configuration", issuer);
+    tracing::debug!("Fetching OIDC discovery document from {}", url);
+    let discovery: Discovery = reqwest::get(url.as_str()).await?.json().await?;
+    if discovery.issuer.trim_end_matches('/') != issuer.as_str() {
+        anyhow::bail!("Discovery document of issuer {}", discovery.issuer);
+    }
+    *DISCOVERY.write().await = Some(discovery.clone());
+    Ok(discovery)
+}
+
+/// URL of the provider where the browser logs in, coming back to the redirect URL with the
+/// code and `state`.
+pub async fn login_url(state: &str) -> anyhow::Result<String> {
+    let (client_id, redirect_url) = match (
+        config::OIDC_CLIENT_ID.as_ref(),
+        config::OIDC_REDIRECT_URL.as_ref(),
+    ) {
+        (Some(client_id), Some(redirect_url)) => (client_id, redirect_url),
+        _ => return Err(NotConfigured.into()),
+    };
+    let discovery = discovery().await?;
+    authorization_url(&discovery, client_id, redirect_url, state)
+}
+
+fn authorization_url(
+    discovery: &Discovery,
+    client_id: &str,
+    redirect_url: &str,
+    state: &str,
+) -> anyhow::Result<String> {
+    let url = Url::parse_with_params(
+        &discovery.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", client_id),
+            ("redirect_uri", redirect_url),
+            ("scope", "openid profile email"),
+            ("state", state),
+        ],
+    )?;
+    Ok(url.as_str().to_string())
+}
+
+/// Exchanges the code of a login for the tokens of the person.
+pub async fn exchange_code(code: &str) -> anyhow::Result<Tokens> {
+    let (client_id, client_secret, redirect_url) = match (
+        config::OIDC_CLIENT_ID.as_ref(),
+        config::OIDC_CLIENT_SECRET.as_ref(),
+        config::OIDC_REDIRECT_URL.as_ref(),
+    ) {
+        (Some(id), Some(secret), Some(url)) => (id, secret, url),
+        _ => return Err(NotConfigured.into()),
+    };
+    request_tokens(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_url),
+        ("client_id", client_id),
+        ("client_secret", client_secret),
+    ])
+    .await
+}
+
+/// Exchanges the credentials of a service for its token.
+pub async fn client_credentials(credentials: &ClientCredentials) -> anyhow::Result<Tokens> {
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", credentials.client_id.as_str()),
+        ("client_secret", credentials.client_secret.as_str()),
+    ];
+    if let Some(scope) = credentials.scope.as_deref() {
+        form.push(("scope", scope));
+    }
+    request_tokens(&form).await
+}
+
+async fn request_tokens(form: &[(&str, &str)]) -> anyhow::Result<Tokens> {
+    let discovery = discovery().await?;
+    let response = reqwest::Client::new()
+        .post(discovery.token_endpoint.as_str())
+        .form(form)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        // The reply of the provider is safe to log, it has no token
+        let status = response.status();
+        let reply = response.text().await.unwrap_or_default();
+        anyhow::bail!("Token request replied {}: {}", status, reply);
+    }
+    Ok(response.json().await?)
+}
+
+/// Grant of the client given the groups of its token, the one of the group with the highest
+/// scope.
+pub fn group_grant(roles: &HashMap<String, Grant>, groups: &[String]) -> Option<Grant> {
+    best_grant(groups.iter().filter_map(|group| roles.get(group).cloned()))
+}
+
+/// The grant with the highest scope, the ones on every watcher before the ones limited to some
+/// tags.
+pub fn best_grant<I: IntoIterator<Item = Grant>>(grants: I) -> Option<Grant> {
+    grants
+        .into_iter()
+        .max_by_key(|grant| (grant.scope, !grant.is_restricted()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Scope;
+
+    #[test]
+    fn maps_the_groups_to_their_grant() {
+        let mut roles = HashMap::new();
+        roles.insert("viewers".to_string(), Grant::all(Scope::Read));
+        roles.insert(
+            "sports-ops".to_string(),
+            "operate;team:sports".parse().unwrap(),
+        );
+        roles.insert("news-ops".to_string(), "operate;team:news".parse().unwrap());
+
+        let groups = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(group_grant(&roles, &groups(&["marketing"])), None);
+        assert_eq!(
+            group_grant(&roles, &groups(&["viewers", "sports-ops"])),
+            Some("operate;team:sports".parse().unwrap())
+        );
+        roles.insert("sre".to_string(), Grant::all(Scope::Operate));
+        assert_eq!(
+            group_grant(&roles, &groups(&["sports-ops", "sre"])),
+            Some(Grant::all(Scope::Operate))
+        );
+
+        let discovery = Discovery {
+            issuer: "https://login.example.com".to_string(),
+            authorization_endpoint: "https://login.example.com/authorize".to_string(),
+            token_endpoint: "https://login.example.com/token".to_string(),
+            jwks_uri: "https://login.example.com/keys".to_string(),
+        };
+        let url = authorization_url(
+            &discovery,
+            "hawkeye",
+            "https://hawkeye.example.com/v1/auth/callback",
+            "abc",
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "https://login.example.com/authorize?response_type=code&client_id=hawkeye\
+             &redirect_uri=https%3A%2F%2Fhawkeye.example.com%2Fv1%2Fauth%2Fcallback\
+             &scope=openid+profile+email&state=abc"
+        );
+    }
+}
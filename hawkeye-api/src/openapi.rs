@@ -0,0 +1,227 @@
+//! The OpenAPI document of the API, generated from the `utoipa::path` annotations of the
+//! handlers and the JSON schemas of the types they take and return.
+//!
+//! The schemas come from the `JsonSchema` derive of the types, their doc comments being the
+//! descriptions, so they follow the models. The replies built with `json!` by the handlers are
+//! described by the types of this module.
+use crate::analytics::Analytics;
+use crate::audit::AuditEntry;
+use crate::drift::Drift;
+use crate::handlers::{self, BulkSelector, FleetUpgrade};
+use crate::metrics::MetricsSummary;
+use crate::oidc::{ClientCredentials, Tokens};
+use crate::revisions::Revision;
+use crate::slates::SlateInfo;
+use hawkeye_core::models::{
+    FieldError, Heartbeat, ModeChange, ThresholdChange, TransitionTrigger, Watcher, WatcherEvent,
+    WatcherEventKind,
+};
+use lazy_static::lazy_static;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::{Config, SwaggerFile};
+
+/// Schemas of the documents of the worker, which the API passes on without a type of its own.
+const WORKER_SCHEMAS_YAML: &str = include_str!("../worker-schemas.yaml");
+
+#[derive(OpenApi)]
+#[openapi(handlers(
+    handlers::list_watchers,
+    handlers::create_watcher,
+    handlers::get_watcher,
+    handlers::update_watcher,
+    handlers::delete_watcher,
+    handlers::get_watcher_by_name,
+    handlers::export_watcher,
+    handlers::import_watcher,
+    handlers::start_watcher,
+    handlers::stop_watcher,
+    handlers::suspend_watcher,
+    handlers::resume_watcher,
+    handlers::restore_watcher,
+    handlers::reload_watcher,
+    handlers::set_watcher_threshold,
+    handlers::set_watcher_mode,
+    handlers::calibrate_watcher,
+    handlers::record_watcher,
+    handlers::bulk_start_watchers,
+    handlers::bulk_stop_watchers,
+    handlers::upgrade_watchers,
+    handlers::get_watcher_logs,
+    handlers::get_watcher_metrics,
+    handlers::get_watcher_state,
+    handlers::trigger_transition,
+    handlers::get_action_history,
+    handlers::replay_action,
+    handlers::get_watcher_diff,
+    handlers::get_watcher_audit,
+    handlers::get_watcher_revisions,
+    handlers::rollback_watcher,
+    handlers::record_heartbeat,
+    handlers::get_watcher_events,
+    handlers::report_watcher_events,
+    handlers::get_watcher_analytics,
+    handlers::list_slates,
+    handlers::upload_slate,
+    handlers::delete_slate,
+    handlers::get_slate_image,
+    handlers::openapi_spec,
+    handlers::login,
+    handlers::login_callback,
+    handlers::request_token,
+    handlers::get_video_frame,
+    handlers::get_watcher_preview,
+))]
+struct ApiDoc;
+
+/// Reply of the calls that only describe what they did.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct Message {
+    message: String,
+}
+
+/// The ingest port, or the name, is already used by another watcher.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct Conflict {
+    message: String,
+    /// ID of the watcher using the ingest port or the name.
+    watcher_id: Option<String>,
+}
+
+/// The watcher definition is not valid.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct Invalid {
+    message: String,
+    errors: Vec<FieldError>,
+}
+
+/// Result of a bulk operation for each watcher.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct BulkResults {
+    results: Vec<BulkResult>,
+    /// Watchers left out of a fleet upgrade once it was paused.
+    skipped: Option<Vec<String>>,
+}
+
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct BulkResult {
+    id: String,
+    /// HTTP status code of the operation on this watcher.
+    status: u16,
+    message: Option<String>,
+}
+
+/// Differences between the definition of the watcher and what is deployed.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct Differences {
+    /// Whether there is any difference.
+    drift: bool,
+    differences: Vec<Drift>,
+}
+
+/// Events stored by a report of the worker.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct Recorded {
+    recorded: usize,
+}
+
+/// Slate uploaded to the library, as `multipart/form-data`.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct SlateUpload {
+    /// PNG or JPEG image, or MP4 or Matroska video of the slate.
+    #[schemars(schema_with = "binary")]
+    file: String,
+    description: Option<String>,
+}
+
+fn binary(gen: &mut SchemaGenerator) -> schemars::schema::Schema {
+    let mut schema = String::json_schema(gen).into_object();
+    schema.format = Some("binary".to_string());
+    schema.into()
+}
+
+lazy_static! {
+    /// The OpenAPI document as JSON, generated only once.
+    pub static ref SPEC: Value = spec();
+
+    static ref SWAGGER_UI_CONFIG: Arc<Config<'static>> =
+        Arc::new(Config::from("/v1/openapi.json"));
+}
+
+/// The document of the annotated handlers, with the schemas of the types they reference.
+fn spec() -> Value {
+    let mut spec =
+        serde_json::to_value(ApiDoc::openapi()).expect("The OpenAPI document is valid JSON");
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    gen.subschema_for::<Watcher>();
+    gen.subschema_for::<Message>();
+    gen.subschema_for::<Conflict>();
+    gen.subschema_for::<Invalid>();
+    gen.subschema_for::<BulkSelector>();
+    gen.subschema_for::<BulkResults>();
+    gen.subschema_for::<FleetUpgrade>();
+    gen.subschema_for::<ThresholdChange>();
+    gen.subschema_for::<ModeChange>();
+    gen.subschema_for::<TransitionTrigger>();
+    gen.subschema_for::<MetricsSummary>();
+    gen.subschema_for::<Differences>();
+    gen.subschema_for::<AuditEntry>();
+    gen.subschema_for::<Revision>();
+    gen.subschema_for::<Heartbeat>();
+    gen.subschema_for::<WatcherEvent>();
+    gen.subschema_for::<WatcherEventKind>();
+    gen.subschema_for::<Recorded>();
+    gen.subschema_for::<Analytics>();
+    gen.subschema_for::<SlateInfo>();
+    gen.subschema_for::<SlateUpload>();
+    gen.subschema_for::<Tokens>();
+    gen.subschema_for::<ClientCredentials>();
+
+    let mut schemas: Map<String, Value> = serde_yaml::from_str(WORKER_SCHEMAS_YAML)
+        .expect("worker-schemas.yaml must be a map of schemas");
+    for (name, schema) in gen.take_definitions() {
+        let schema = serde_json::to_value(schema).expect("The schemas are valid JSON");
+        schemas.insert(name, schema);
+    }
+    spec["components"]["schemas"] = Value::Object(schemas);
+    spec
+}
+
+/// File of the Swagger UI at `path`, relative to `/v1/docs/`, the assets being embedded in the
+/// API so the page works without reaching a CDN.
+pub fn swagger_ui_file(path: &str) -> anyhow::Result<Option<SwaggerFile<'static>>> {
+    utoipa_swagger_ui::serve(path, SWAGGER_UI_CONFIG.clone())
+        .map_err(|e| anyhow::anyhow!("Could not serve the Swagger UI: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_is_valid_openapi_document() {
+        assert!(SPEC["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(SPEC["paths"]["/v1/watchers"]["get"].is_object());
+        assert!(SPEC["paths"]["/v1/watchers/{watcher_id}/preview"]["get"].is_object());
+
+        // Every schema referenced by the document is generated
+        let document = SPEC.to_string();
+        let schemas = SPEC["components"]["schemas"].as_object().unwrap();
+        for reference in document.split("\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "Missing schema {}", name);
+        }
+        assert!(schemas["Watcher"]["properties"]["source"].is_object());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::backend::kubernetes::{self, WatcherStatus};
+use crate::config::NAMESPACE;
+use crate::crd::{self, WatcherResourceStatus};
+use crate::handlers;
+use crate::templates;
+use futures::StreamExt;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::api::{ListParams, Patch, PatchParams};
+use kube::runtime::controller::{Context, Controller, ReconcilerAction};
+use kube::{Api, Client, Resource, ResourceExt};
+use serde_json::json;
+use std::fmt;
+use std::time::Duration;
+
+/// How often the watcher resources are reconciled when nothing changes.
+const REQUEUE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How long to wait before retrying a watcher resource that failed to reconcile.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Reconciles the `Watcher` custom resources into the `ConfigMap`, `Deployment` and `Service`
+/// objects of each watcher.
+///
+/// The objects are owned by the resource, so Kubernetes deletes them with the resource. A
+/// controller runs for each of the namespaces managed by the API.
+pub async fn run(client: Client, namespace: String) {
+    log::info!(
+        "Running in operator mode, watching Watcher resources in {}",
+        namespace
+    );
+    let watchers: Api<crd::Watcher> = Api::namespaced(client.clone(), &namespace);
+    Controller::new(watchers, ListParams::default())
+        .owns(
+            Api::<Deployment>::namespaced(client.clone(), &namespace),
+            ListParams::default().labels("app=hawkeye,watcher_id"),
+        )
+        .run(reconcile, error_policy, Context::new(client))
+        .for_each(|result| async move {
+            match result {
+                Ok((obj, _)) => tracing::debug!("Reconciled watcher {}", obj.name),
+                Err(e) => log::error!("Error while reconciling watcher resource: {:?}", e),
+            }
+        })
+        .await;
+}
+
+async fn reconcile(
+    resource: crd::Watcher,
+    ctx: Context<Client>,
+) -> Result<ReconcilerAction, ReconcileError> {
+    let client = ctx.get_ref().clone();
+    let id = resource.name();
+    let namespace = resource.namespace().unwrap_or_else(|| NAMESPACE.clone());
+    let previous = resource.status.clone().unwrap_or_default();
+
+    let mut watcher = resource.spec.watcher.clone();
+    watcher.id = Some(id.clone());
+    // The name of the resource is already unique
+    watcher.name.get_or_insert_with(|| id.clone());
+    watcher.status = None;
+    watcher.status_description = None;
+    watcher.last_heartbeat = None;
+    watcher.stale = None;
+    watcher.source.ingest_ip = None;
+    watcher.namespace = Some(namespace.clone());
+    if watcher.source.ingest_port.is_none() {
+        watcher.source.ingest_port = match previous.ingest_port {
+            Some(port) => Some(port),
+            None => kubernetes::allocate_ingest_port(client.clone(), &watcher.source).await?,
+        };
+    }
+
+    if let Err(errors) = handlers::validate_watcher(&watcher) {
+        let status = WatcherResourceStatus {
+            phase: None,
+            ingest_port: previous.ingest_port,
+            message: Some(errors.to_string()),
+        };
+        update_status(client, &namespace, &id, &status).await?;
+        // Nothing to do until the resource is changed
+        return Ok(ReconcilerAction {
+            requeue_after: None,
+        });
+    }
+    let ingest_port = watcher
+        .source
+        .ingest_port
+        .expect("Validated watchers have an ingest port");
+    if let Some(other) =
+        kubernetes::find_port_conflict(client.clone(), &watcher.ports(), Some(&id)).await?
+    {
+        let status = WatcherResourceStatus {
+            phase: None,
+            ingest_port: previous.ingest_port,
+            message: Some(format!(
+                "Ingest port, or another port of the source, is already used by watcher {}",
+                other
+            )),
+        };
+        update_status(client, &namespace, &id, &status).await?;
+        return Ok(ReconcilerAction {
+            requeue_after: Some(RETRY_INTERVAL),
+        });
+    }
+
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
+    match kubernetes::get_watcher_config(client.clone(), &namespace, &id).await {
+        Ok(current) if current == watcher => (),
+        Ok(_) => {
+            log::info!("Updating watcher {} from its resource", id);
+            kubernetes::update_watcher(client.clone(), &namespace, &id, &watcher).await?;
+            let deployment = deployments.get(&templates::deployment_name(&id)).await?;
+            if deployment.get_watcher_status() == hawkeye_core::models::Status::Running {
+                kubernetes::restart_watcher(client.clone(), &namespace, &id).await?;
+            }
+        }
+        Err(_) => {
+            log::info!("Creating watcher {} from its resource", id);
+            kubernetes::create_watcher(client.clone(), &namespace, &id, &watcher).await?;
+        }
+    }
+
+    if let Some(owner) = owner_reference(&resource) {
+        kubernetes::set_watcher_owner(client.clone(), &namespace, &id, owner).await?;
+    }
+
+    let phase = deployments
+        .get(&templates::deployment_name(&id))
+        .await
+        .ok()
+        .map(|d| d.get_watcher_status())
+        .and_then(|status| serde_json::to_value(status).ok())
+        .and_then(|value| value.as_str().map(String::from));
+    let status = WatcherResourceStatus {
+        phase,
+        ingest_port: Some(ingest_port),
+        message: None,
+    };
+    update_status(client, &namespace, &id, &status).await?;
+
+    Ok(ReconcilerAction {
+        requeue_after: Some(REQUEUE_INTERVAL),
+    })
+}
+
+fn error_policy(error: &ReconcileError, _ctx: Context<Client>) -> ReconcilerAction {
+    log::warn!("Failed to reconcile watcher resource: {}", error);
+    ReconcilerAction {
+        requeue_after: Some(RETRY_INTERVAL),
+    }
+}
+
+/// Reference to the `resource` as the controller of the objects of its watcher, so they are
+/// garbage collected when it is deleted.
+fn owner_reference(resource: &crd::Watcher) -> Option<OwnerReference> {
+    Some(OwnerReference {
+        api_version: crd::Watcher::api_version(&()).to_string(),
+        kind: crd::Watcher::kind(&()).to_string(),
+        name: resource.metadata.name.clone()?,
+        uid: resource.metadata.uid.clone()?,
+        controller: Some(true),
+        block_owner_deletion: None,
+    })
+}
+
+async fn update_status(
+    client: Client,
+    namespace: &str,
+    id: &str,
+    status: &WatcherResourceStatus,
+) -> anyhow::Result<()> {
+    let mut patch_params = PatchParams::default();
+    patch_params.field_manager = Some("hawkeye_api".to_string());
+    let watchers: Api<crd::Watcher> = Api::namespaced(client, namespace);
+    watchers
+        .patch_status(
+            id,
+            &patch_params,
+            &Patch::Merge(&json!({ "status": status })),
+        )
+        .await?;
+    Ok(())
+}
+
+/// The controller requires an error implementing `std::error::Error`, which `anyhow` does not.
+#[derive(Debug)]
+pub struct ReconcileError(anyhow::Error);
+
+impl fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl std::error::Error for ReconcileError {}
+
+impl From<anyhow::Error> for ReconcileError {
+    fn from(e: anyhow::Error) -> Self {
+        ReconcileError(e)
+    }
+}
+
+impl From<kube::Error> for ReconcileError {
+    fn from(e: kube::Error) -> Self {
+        ReconcileError(e.into())
+    }
+}
@@ -0,0 +1,103 @@
+use crate::backend::{Backend, ListQuery};
+use crate::config::DELETED_RETENTION;
+use chrono::{DateTime, Duration, Utc};
+
+/// How often the deleted watchers are checked.
+const TICK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Purges the deleted watchers in the background once they were kept for `DELETED_RETENTION`,
+/// they cannot be restored afterwards.
+pub fn spawn(backend: Backend) {
+    if *DELETED_RETENTION == 0 {
+        log::info!("Purge of deleted watchers is disabled");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let retention = Duration::seconds(*DELETED_RETENTION as i64);
+            if let Err(e) = purge_deleted(&backend, Utc::now() - retention).await {
+                log::error!("Error while purging the deleted watchers: {:?}", e);
+            }
+        }
+    });
+}
+
+/// Deletes for good the watchers deleted before `deleted_before`.
+pub async fn purge_deleted(backend: &Backend, deleted_before: DateTime<Utc>) -> anyhow::Result<()> {
+    let mut query = ListQuery {
+        include_deleted: true,
+        ..ListQuery::default()
+    };
+    loop {
+        let page = backend.list_watchers(&query).await?;
+        for watcher in page.watchers {
+            let (id, deleted_at) = match (watcher.id.as_deref(), watcher.deleted_at.as_deref()) {
+                (Some(id), Some(deleted_at)) => (id, deleted_at),
+                _ => continue,
+            };
+            match DateTime::parse_from_rfc3339(deleted_at) {
+                Ok(time) if time.with_timezone(&Utc) < deleted_before => (),
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("Invalid deletion time of watcher {}: {}", id, e);
+                    continue;
+                }
+            }
+            match backend.delete_watcher(id).await {
+                Ok(_) => log::info!("Purged watcher {} deleted at {}", id, deleted_at),
+                Err(e) => log::error!("Could not purge watcher {}: {:?}", id, e),
+            }
+        }
+        match page.continue_token {
+            Some(token) => query.continue_token = Some(token),
+            None => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::memory::MemoryBackend;
+    use crate::backend::WatcherBackend;
+    use chrono::TimeZone;
+    use hawkeye_core::models::Watcher;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn purges_the_watchers_deleted_before() {
+        let memory = Arc::new(MemoryBackend::default());
+        for (id, deleted_at) in [
+            ("kept", None),
+            ("old", Some("2021-12-01T10:00:00Z")),
+            ("recent", Some("2021-12-08T10:00:00Z")),
+        ]
+        .iter()
+        {
+            let watcher: Watcher = serde_json::from_value(json!({
+                "id": id,
+                "deleted_at": deleted_at,
+                "slate_url": "file://./resources/slate_120px.jpg",
+                "source": {
+                    "container": "mpeg-ts",
+                    "codec": "h264",
+                    "transport": { "protocol": "rtp" }
+                },
+                "transitions": []
+            }))
+            .unwrap();
+            memory.create_watcher(id, &watcher).await.unwrap();
+        }
+
+        let backend: Backend = memory.clone();
+        purge_deleted(&backend, Utc.ymd(2021, 12, 5).and_hms(0, 0, 0))
+            .await
+            .unwrap();
+        assert!(memory.get_watcher_config("kept").await.unwrap().is_some());
+        assert!(memory.get_watcher_config("old").await.unwrap().is_none());
+        assert!(memory.get_watcher_config("recent").await.unwrap().is_some());
+    }
+}
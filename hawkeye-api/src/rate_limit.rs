@@ -0,0 +1,230 @@
+//! Protects the expensive endpoints from clients polling them too often, like a dashboard
+//! showing the video frames of many watchers.
+//!
+//! Each endpoint limits the requests of every client with the generic cell rate algorithm (GCRA),
+//! and caps the requests served at the same time. Requests over the limits are rejected with
+//! `429 Too Many Requests` and a `Retry-After` header.
+//!
+//! Clients are told apart by the subject of their valid token, or else by their address, the one
+//! seen by the first of the `HAWKEYE_TRUSTED_PROXIES` in front of the API.
+use crate::auth;
+use crate::config::{MAX_CONCURRENT_REQUESTS, RATE_LIMIT, RATE_LIMIT_BURST, TRUSTED_PROXIES};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use warp::Filter;
+
+/// Clients tracked before the ones that are within their limits again are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+lazy_static! {
+    /// Limits `GET /v1/watchers/{id}/video-frame`, which calls the worker pod.
+    pub static ref VIDEO_FRAME: Limiter =
+        Limiter::new(*RATE_LIMIT, *RATE_LIMIT_BURST, *MAX_CONCURRENT_REQUESTS);
+
+    /// Limits `GET /v1/watchers`, which lists the Kubernetes objects of the watchers.
+    pub static ref LIST_WATCHERS: Limiter =
+        Limiter::new(*RATE_LIMIT, *RATE_LIMIT_BURST, *MAX_CONCURRENT_REQUESTS);
+}
+
+/// Rate limit of each client and concurrency cap of one endpoint.
+pub struct Limiter {
+    /// Time between requests of a client at the sustained rate, no limit when zero.
+    interval: Duration,
+    /// How far ahead of the sustained rate a client can go.
+    tolerance: Duration,
+    /// Time at which each client is back to its sustained rate, see GCRA.
+    clients: Mutex<HashMap<String, Instant>>,
+    concurrency: Arc<Semaphore>,
+}
+
+impl Limiter {
+    /// Allows `rate` requests per second to each client, with bursts of up to `burst` requests,
+    /// and `max_concurrent` requests being served at the same time.
+    pub fn new(rate: u32, burst: u32, max_concurrent: usize) -> Self {
+        let interval = if rate == 0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs(1) / rate
+        };
+        Limiter {
+            interval,
+            tolerance: interval * burst.saturating_sub(1),
+            clients: Mutex::new(HashMap::new()),
+            concurrency: Arc::new(Semaphore::new(max_concurrent)),
+        }
+    }
+
+    /// Counts a request of the client, returning how long it has to wait when over its limit.
+    fn check(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        if self.interval == Duration::from_secs(0) {
+            return Ok(());
+        }
+        let mut clients = self.clients.lock().unwrap();
+        let arrival = clients.get(client).copied().unwrap_or(now).max(now);
+        let ahead = arrival.duration_since(now);
+        if ahead > self.tolerance {
+            return Err(ahead - self.tolerance);
+        }
+        if clients.len() >= MAX_TRACKED_CLIENTS {
+            clients.retain(|_, arrival| *arrival > now);
+        }
+        clients.insert(client.to_string(), arrival + self.interval);
+        Ok(())
+    }
+
+    /// Takes one of the slots to serve a request, if any is free.
+    fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.concurrency.clone().try_acquire_owned().ok()
+    }
+}
+
+/// Slot taken while serving a request, released when dropped.
+pub struct Permit {
+    _slot: OwnedSemaphorePermit,
+}
+
+/// The client must wait before calling the endpoint again.
+#[derive(Debug)]
+pub struct TooManyRequests {
+    pub retry_after: Duration,
+}
+
+impl warp::reject::Reject for TooManyRequests {}
+
+impl TooManyRequests {
+    /// Value of the `Retry-After` header, in whole seconds.
+    pub fn retry_after_secs(&self) -> u64 {
+        let secs = self.retry_after.as_secs();
+        if self.retry_after.subsec_nanos() > 0 || secs == 0 {
+            secs + 1
+        } else {
+            secs
+        }
+    }
+}
+
+/// Requires the client to be within the limits of the endpoint. The returned permit must be
+/// kept until the request is served.
+pub fn limit(
+    limiter: &'static Limiter,
+) -> impl Filter<Extract = (Permit,), Error = warp::Rejection> + Clone {
+    client_key().and_then(move |client: String| async move {
+        if let Err(retry_after) = limiter.check(&client, Instant::now()) {
+            return Err(warp::reject::custom(TooManyRequests { retry_after }));
+        }
+        match limiter.acquire() {
+            Some(slot) => Ok(Permit { _slot: slot }),
+            None => Err(warp::reject::custom(TooManyRequests {
+                retry_after: Duration::from_secs(1),
+            })),
+        }
+    })
+}
+
+/// Identifies the client by the subject of its token once verified, or by its address for the
+/// clients without a valid token, so made up tokens do not get a limit of their own.
+fn client_key() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .and(warp::addr::remote())
+        .then(
+            |auth: Option<String>, forwarded: Option<String>, remote: Option<SocketAddr>| async move {
+                if let Some(auth) = auth {
+                    if let Some(subject) = auth::verified_subject(&auth).await {
+                        return format!("subject:{}", subject);
+                    }
+                }
+                let remote = remote.map(|addr| addr.ip());
+                let address = client_address(forwarded.as_deref(), remote, *TRUSTED_PROXIES);
+                format!("address:{}", address.unwrap_or_default())
+            },
+        )
+}
+
+/// Address of the client behind `trusted_proxies` reverse proxies. Each proxy appends the address
+/// of its peer to `X-Forwarded-For`, so the entries before the ones of the proxies are set by the
+/// client and ignored.
+fn client_address(
+    forwarded: Option<&str>,
+    remote: Option<IpAddr>,
+    trusted_proxies: usize,
+) -> Option<String> {
+    if trusted_proxies == 0 {
+        return remote.map(|ip| ip.to_string());
+    }
+    let entries: Vec<&str> = forwarded
+        .map(|val| {
+            val.split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    // Requests reaching the API past fewer proxies keep the farthest address known
+    entries
+        .iter()
+        .rev()
+        .nth(trusted_proxies - 1)
+        .or_else(|| entries.first())
+        .map(|entry| entry.to_string())
+        .or_else(|| remote.map(|ip| ip.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_each_client_after_burst() {
+        let limiter = Limiter::new(2, 3, 1);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check("dashboard", now).is_ok());
+        }
+        assert_eq!(
+            limiter.check("dashboard", now),
+            Err(Duration::from_millis(500))
+        );
+        assert!(limiter.check("other", now).is_ok());
+        assert!(limiter
+            .check("dashboard", now + Duration::from_millis(500))
+            .is_ok());
+
+        let permit = limiter.acquire();
+        assert!(permit.is_some());
+        assert!(limiter.acquire().is_none());
+        drop(permit);
+        assert!(limiter.acquire().is_some());
+    }
+
+    #[test]
+    fn ignores_the_addresses_forwarded_by_the_client() {
+        let remote: Option<IpAddr> = Some("10.0.0.2".parse().unwrap());
+        let forwarded = Some("1.1.1.1, 203.0.113.7, 10.0.0.1");
+
+        assert_eq!(
+            client_address(forwarded, remote, 0),
+            Some("10.0.0.2".to_string())
+        );
+        assert_eq!(
+            client_address(forwarded, remote, 1),
+            Some("10.0.0.1".to_string())
+        );
+        assert_eq!(
+            client_address(forwarded, remote, 2),
+            Some("203.0.113.7".to_string())
+        );
+        assert_eq!(
+            client_address(Some("203.0.113.7"), remote, 2),
+            Some("203.0.113.7".to_string())
+        );
+        assert_eq!(
+            client_address(None, remote, 1),
+            Some("10.0.0.2".to_string())
+        );
+    }
+}
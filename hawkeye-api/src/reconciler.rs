@@ -0,0 +1,271 @@
+use crate::backend::kubernetes;
+use crate::config::{NAMESPACES, POD_DISRUPTION_BUDGET, RECONCILE_INTERVAL, SHARED_SERVICE};
+use crate::templates;
+use hawkeye_core::models::Watcher;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::{ConfigMap, Service};
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::chrono::{DateTime, Duration as ChronoDuration, Utc};
+use kube::api::ListParams;
+use kube::{Api, Client, Resource, ResourceExt};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Seconds the reconciliation leaves a watcher being created alone. Beyond, its creation is taken
+/// as interrupted, like by a restart of the API, and its missing objects are recreated.
+const CREATION_TIMEOUT: i64 = 300;
+
+/// Periodically reconciles the Kubernetes objects of all watchers in the background.
+///
+/// The watchers are created with three sequential calls to the Kubernetes API, any of them can
+/// fail. The `ConfigMap` is the source of truth, missing `Deployment`, `Service` and
+/// `PodDisruptionBudget` objects are recreated from it. Objects that don't match the watcher
+/// definition are reported as drift. The watchers being created or deleted are left alone, their
+/// objects being missing for a while.
+pub fn spawn(client: Client) {
+    if *RECONCILE_INTERVAL == 0 {
+        log::info!("Reconciliation of watchers is disabled");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(*RECONCILE_INTERVAL));
+        loop {
+            interval.tick().await;
+            for namespace in NAMESPACES.iter() {
+                if let Err(e) = reconcile(client.clone(), namespace).await {
+                    log::error!("Error while reconciling watchers in {}: {:?}", namespace, e);
+                }
+            }
+        }
+    });
+}
+
+/// Runs a single reconciliation pass over all watchers of the namespace.
+pub async fn reconcile(client: Client, namespace: &str) -> anyhow::Result<()> {
+    tracing::debug!("Reconciling watchers in {}", namespace);
+    let lp = ListParams::default().labels("app=hawkeye,watcher_id");
+
+    let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let mut watchers: Vec<Watcher> = Vec::new();
+    let mut known_ids: HashSet<String> = HashSet::new();
+    for config_map in config_maps.list(&lp).await?.items {
+        if is_busy(&config_map, Utc::now()) {
+            tracing::debug!(
+                "Skipping watcher {} being created or deleted",
+                config_map.name()
+            );
+            known_ids.extend(config_map.labels().get("watcher_id").cloned());
+            continue;
+        }
+        let watcher = config_map
+            .data
+            .as_ref()
+            .and_then(|data| data.get("watcher.json"))
+            .and_then(|contents| serde_json::from_str::<Watcher>(contents).ok());
+        watchers.extend(watcher);
+    }
+
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let deployments_index: HashMap<String, Deployment> = deployments
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|d| Some((d.labels().get("watcher_id")?.clone(), d)))
+        .collect();
+
+    let services: Api<Service> = Api::namespaced(client.clone(), namespace);
+    let services_index: HashMap<String, Service> = services
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|s| Some((s.labels().get("watcher_id")?.clone(), s)))
+        .collect();
+
+    let pdbs: Api<PodDisruptionBudget> = Api::namespaced(client.clone(), namespace);
+    let pdb_ids: HashSet<String> = if *POD_DISRUPTION_BUDGET {
+        pdbs.list(&lp)
+            .await?
+            .items
+            .into_iter()
+            .filter_map(|p| p.labels().get("watcher_id").cloned())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    for watcher in watchers.iter() {
+        let (id, ingest_port) = match (watcher.id.as_ref(), watcher.source.ingest_port) {
+            (Some(id), Some(port)) => (id, port),
+            (_, _) => {
+                log::warn!("Skipping invalid watcher definition: {:?}", watcher.id);
+                continue;
+            }
+        };
+        known_ids.insert(id.clone());
+
+        match deployments_index.get(id) {
+            // The pack runs the watcher, it is rolled out when the watcher is started
+            _ if watcher.is_packed() => (),
+            Some(deploy) => {
+                if !deployment_ports(deploy).contains(&ingest_port) {
+                    log::warn!(
+                        "Drift detected: Deployment of watcher {} does not expose port {}",
+                        id,
+                        ingest_port
+                    );
+                }
+            }
+            None => {
+                log::warn!("Recreating missing Deployment of watcher {}", id);
+                if let Err(e) =
+                    kubernetes::create_deployment(client.clone(), namespace, id, watcher).await
+                {
+                    log::error!(
+                        "Could not recreate the Deployment of watcher {}: {:?}",
+                        id,
+                        e
+                    );
+                }
+            }
+        }
+
+        match services_index.get(id) {
+            // The ports reconciler publishes the port on the shared Service
+            _ if SHARED_SERVICE.is_some() => (),
+            // The worker connects to the sender
+            Some(_) if !watcher.source.is_pushed() => {
+                log::warn!(
+                    "Drift detected: watcher {} connects to its sender but has a Service",
+                    id
+                );
+            }
+            None if !watcher.source.is_pushed() => (),
+            Some(svc) => {
+                let exposed = service_ports(svc);
+                let pushed = watcher.sources().into_iter().filter(|s| s.is_pushed());
+                for port in pushed.flat_map(|source| source.ports()) {
+                    if !exposed.contains(&port) {
+                        log::warn!(
+                            "Drift detected: Service of watcher {} does not expose port {}",
+                            id,
+                            port
+                        );
+                    }
+                }
+            }
+            None => {
+                log::warn!("Recreating missing Service of watcher {}", id);
+                if let Err(e) =
+                    kubernetes::create_service(client.clone(), namespace, id, watcher).await
+                {
+                    log::error!("Could not recreate the Service of watcher {}: {:?}", id, e);
+                }
+            }
+        }
+
+        if *POD_DISRUPTION_BUDGET && !watcher.is_packed() && !pdb_ids.contains(id) {
+            log::warn!("Recreating missing PodDisruptionBudget of watcher {}", id);
+            // The other watchers are still reconciled
+            if let Err(e) = kubernetes::create_pdb(client.clone(), namespace, id).await {
+                log::error!(
+                    "Could not recreate the PodDisruptionBudget of watcher {}: {:?}",
+                    id,
+                    e
+                );
+            }
+        }
+    }
+
+    let deployments = deployments_index
+        .iter()
+        .filter(|(_, d)| !is_deleting(*d))
+        .map(|(id, _)| id);
+    let services = services_index
+        .iter()
+        .filter(|(_, s)| !is_deleting(*s))
+        .map(|(id, _)| id);
+    for id in deployments.chain(services) {
+        if !known_ids.contains(id) {
+            log::warn!(
+                "Drift detected: watcher {} has objects but no ConfigMap, it must be deleted",
+                id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the watcher of the `ConfigMap` is being deleted, or created for less than
+/// `CREATION_TIMEOUT`, see `templates::CREATING_ANNOTATION`.
+fn is_busy(config_map: &ConfigMap, now: DateTime<Utc>) -> bool {
+    let creating_since = config_map
+        .annotations()
+        .get(templates::CREATING_ANNOTATION)
+        .and_then(|since| DateTime::parse_from_rfc3339(since).ok());
+    is_deleting(config_map)
+        || creating_since.map_or(false, |since| {
+            now.signed_duration_since(since) < ChronoDuration::seconds(CREATION_TIMEOUT)
+        })
+}
+
+fn is_deleting<K: Resource>(object: &K) -> bool {
+    object.meta().deletion_timestamp.is_some()
+}
+
+fn deployment_ports(deploy: &Deployment) -> Vec<u32> {
+    deploy
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.template.spec.as_ref())
+        .map(|pod| pod.containers.iter())
+        .into_iter()
+        .flatten()
+        .flat_map(|container| container.ports.iter().flatten())
+        .map(|port| port.container_port as u32)
+        .collect()
+}
+
+fn service_ports(svc: &Service) -> Vec<u32> {
+    svc.spec
+        .as_ref()
+        .and_then(|spec| spec.ports.as_ref())
+        .into_iter()
+        .flatten()
+        .map(|port| port.port as u32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+
+    #[test]
+    fn leaves_the_watchers_being_created_or_deleted_alone() {
+        let now = Utc::now();
+        let mut config_map = ConfigMap::default();
+        assert!(!is_busy(&config_map, now));
+
+        config_map.metadata.annotations = Some(
+            vec![(
+                templates::CREATING_ANNOTATION.to_string(),
+                (now - ChronoDuration::seconds(10)).to_rfc3339(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert!(is_busy(&config_map, now));
+        // The creation was interrupted
+        assert!(!is_busy(
+            &config_map,
+            now + ChronoDuration::seconds(CREATION_TIMEOUT)
+        ));
+
+        config_map.metadata.annotations = None;
+        config_map.metadata.deletion_timestamp = Some(Time(now));
+        assert!(is_busy(&config_map, now));
+    }
+}
@@ -0,0 +1,91 @@
+//! Identifies each request with the `X-Request-Id` header, so a single user action can be traced
+//! from the API to the worker pods and the notifications.
+//!
+//! The ID sent by the client is kept when valid, a new one is generated otherwise. It is returned
+//! in the response, and available to the code serving the request with `current`.
+use std::future::Future;
+use tracing::Instrument;
+use uuid::Uuid;
+use warp::http::{HeaderValue, Request, Response};
+use warp::hyper::service::Service;
+use warp::hyper::Body;
+
+pub const HEADER: &str = "x-request-id";
+
+/// Longest request ID accepted from the clients.
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// ID of the request being served by the current task, if any.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Runs the future as part of the request with the given ID.
+pub fn scope<F: Future>(id: String, future: F) -> impl Future<Output = F::Output> {
+    REQUEST_ID.scope(id, future)
+}
+
+/// Serves the request with the service, identifying it with its request ID.
+pub async fn serve<S>(mut service: S, request: Request<Body>) -> Result<Response<Body>, S::Error>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+{
+    let id = request
+        .headers()
+        .get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid(value))
+        .map(String::from)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!("request", request_id = %id);
+    // The service is called in the scope too, filters may run as soon as it is called
+    let call = async move { service.call(request).await };
+    let mut response = scope(id.clone(), call.instrument(span)).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    Ok(response)
+}
+
+/// Writes the access log of the API, like `warp::log` does, with the ID of the request.
+pub fn access_log(info: warp::log::Info) {
+    log::info!(
+        target: "watchers",
+        "\"{} {} {:?}\" {} \"{}\" {:?} request_id={}",
+        info.method(),
+        info.path(),
+        info.version(),
+        info.status().as_u16(),
+        info.user_agent().unwrap_or("-"),
+        info.elapsed(),
+        current().unwrap_or_default(),
+    );
+}
+
+/// Only short printable IDs are accepted, since they end up in the logs and in other requests.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_client_ids() {
+        assert!(is_valid("4f1c2b7e-0d3a-4d6e-9a51-6f0b8a1e2c3d"));
+        assert!(is_valid("dashboard:1234"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("id with spaces"));
+        assert!(!is_valid(&"a".repeat(MAX_LENGTH + 1)));
+    }
+}
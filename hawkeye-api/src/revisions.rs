@@ -0,0 +1,58 @@
+//! Keeps the past definitions of the watchers, to roll them back.
+//!
+//! Every change of a watcher definition is stored by the backend as a numbered revision, listed
+//! with `GET /v1/watchers/{id}/revisions` and applied again with
+//! `POST /v1/watchers/{id}/rollback/{rev}`.
+use crate::backend::Backend;
+use chrono::Utc;
+use hawkeye_core::models::Watcher;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Revisions kept for each watcher, the older ones are dropped.
+pub const MAX_REVISIONS: usize = 20;
+
+/// A definition of the watcher, as stored by a change.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Revision {
+    /// Number of the revision, the first definition of the watcher being `1`.
+    pub revision: u64,
+    /// RFC 3339 time of the change.
+    pub timestamp: String,
+    /// Identity of the client, see `auth::actor`.
+    pub actor: String,
+    /// Call that made the change, like `update` or `rollback`.
+    pub action: String,
+    /// Definition of the watcher, without the fields managed by the API.
+    pub watcher: Watcher,
+}
+
+/// Stores the definition of the watcher after a change. Failing to store it does not fail the
+/// change, it is still in the audit log.
+pub async fn record(backend: &Backend, id: &str, actor: &str, action: &str, watcher: &Watcher) {
+    let revision = Revision {
+        // Numbered by the backend
+        revision: 0,
+        timestamp: Utc::now().to_rfc3339(),
+        actor: actor.to_string(),
+        action: action.to_string(),
+        watcher: watcher.clone(),
+    };
+    match backend.record_revision(id, &revision).await {
+        Ok(Some(number)) => log::debug!("Stored revision {} of watcher {}", number, id),
+        Ok(None) => (),
+        Err(e) => log::error!("Could not store the revision of watcher {}: {:?}", id, e),
+    }
+}
+
+/// Numbers the revision after the last one, dropping the oldest ones beyond `MAX_REVISIONS`.
+pub fn append(revisions: &mut Vec<Revision>, revision: &Revision) -> u64 {
+    let number = revisions.last().map_or(1, |last| last.revision + 1);
+    revisions.push(Revision {
+        revision: number,
+        ..revision.clone()
+    });
+    let excess = revisions.len().saturating_sub(MAX_REVISIONS);
+    revisions.drain(..excess);
+    number
+}
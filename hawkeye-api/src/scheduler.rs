@@ -0,0 +1,148 @@
+use crate::backend::{Backend, ListQuery, StatusChange};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use hawkeye_core::models::{self, Schedule, Status, ValidationErrors};
+use std::time::Duration;
+
+/// How often the schedules of the watchers are checked.
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Starts and stops the watchers with a `schedule` in the background.
+///
+/// Every tick applies the start and stop times that passed since the previous tick, so a time
+/// missed while the API was not running is not applied later. Suspended and deleted watchers are
+/// left alone.
+pub fn spawn(backend: Backend) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        let mut last_tick = Utc::now();
+        loop {
+            interval.tick().await;
+            let now = Utc::now();
+            if let Err(e) = apply_schedules(&backend, last_tick, now).await {
+                log::error!("Error while applying the schedules of watchers: {:?}", e);
+            }
+            last_tick = now;
+        }
+    });
+}
+
+/// Starts or stops the watchers whose schedule fired between `since` and `until`.
+pub async fn apply_schedules(
+    backend: &Backend,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let page = backend.list_watchers(&ListQuery::default()).await?;
+    for watcher in page.watchers {
+        let (id, schedule) = match (watcher.id.as_deref(), watcher.schedule.as_ref()) {
+            (Some(id), Some(schedule)) => (id, schedule),
+            _ => continue,
+        };
+        if watcher.suspended == Some(true) || watcher.deleted_at.is_some() {
+            continue;
+        }
+        let target = match due_status(schedule, since, until) {
+            Ok(Some(target)) => target,
+            Ok(None) => continue,
+            Err(e) => {
+                log::warn!("Ignoring the schedule of watcher {}: {}", id, e);
+                continue;
+            }
+        };
+
+        let result = match target {
+            Status::Running => backend.start_watcher(id).await,
+            _ => backend.stop_watcher(id, None).await,
+        };
+        match result {
+            Ok(StatusChange::Applied) => log::info!("Scheduled {:?} of watcher {}", target, id),
+            Ok(change) => tracing::debug!("Scheduled {:?} of watcher {}: {:?}", target, id, change),
+            Err(e) => log::error!("Could not apply the schedule of watcher {}: {:?}", id, e),
+        }
+    }
+    Ok(())
+}
+
+/// Finds the status the watcher must reach when its schedule fired between `since` and `until`.
+/// When both the start and the stop times passed, the latest one wins.
+fn due_status(
+    schedule: &Schedule,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> anyhow::Result<Option<Status>> {
+    let tz = timezone(schedule)?;
+    let last_start = last_fired(schedule.start.as_deref(), tz, since, until)?;
+    let last_stop = last_fired(schedule.stop.as_deref(), tz, since, until)?;
+    Ok(match (last_start, last_stop) {
+        (Some(start), Some(stop)) if start > stop => Some(Status::Running),
+        (_, Some(_)) => Some(Status::Ready),
+        (Some(_), None) => Some(Status::Running),
+        (None, None) => None,
+    })
+}
+
+/// Finds the last time the cron expression fired between `since` (excluded) and `until`.
+fn last_fired(
+    expression: Option<&str>,
+    tz: Tz,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> anyhow::Result<Option<DateTime<Utc>>> {
+    let expression = match expression {
+        Some(expression) => expression,
+        None => return Ok(None),
+    };
+    Ok(parse_cron(expression)?
+        .after(&since.with_timezone(&tz))
+        .map(|t| t.with_timezone(&Utc))
+        .take_while(|t| *t <= until)
+        .last())
+}
+
+fn parse_cron(expression: &str) -> anyhow::Result<cron::Schedule> {
+    models::parse_cron(expression).map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+fn timezone(schedule: &Schedule) -> anyhow::Result<Tz> {
+    models::parse_timezone(schedule.timezone.as_deref()).map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// Checks the cron expressions and the time zone of the schedule.
+pub fn validate(schedule: &Schedule, errors: &mut ValidationErrors) {
+    for (field, expression) in [("start", &schedule.start), ("stop", &schedule.stop)].iter() {
+        if let Some(Err(e)) = expression.as_deref().map(parse_cron) {
+            errors.add(format!("schedule.{}", field), e.to_string());
+        }
+    }
+    if let Err(e) = timezone(schedule) {
+        errors.add("schedule.timezone", e.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn latest_scheduled_time_wins() {
+        let schedule = Schedule {
+            start: Some("0 18 * * *".to_string()),
+            stop: Some("30 18 * * *".to_string()),
+            timezone: Some("America/New_York".to_string()),
+        };
+        let at = |h, m| Utc.ymd(2021, 12, 1).and_hms(h, m, 0);
+
+        // 18:00 in New York is 23:00 UTC in December
+        assert_eq!(due_status(&schedule, at(22, 0), at(22, 59)).unwrap(), None);
+        assert_eq!(
+            due_status(&schedule, at(22, 59), at(23, 0)).unwrap(),
+            Some(Status::Running)
+        );
+        assert_eq!(
+            due_status(&schedule, at(22, 59), at(23, 45)).unwrap(),
+            Some(Status::Ready)
+        );
+    }
+}
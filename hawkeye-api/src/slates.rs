@@ -0,0 +1,249 @@
+//! Library of slate images uploaded to the API, stored in a directory like a mounted PVC.
+//!
+//! Watchers reference the slates of the library as `slate://<id>` instead of an external URL,
+//! and the workers download them from the API when they start.
+use crate::config::SLATE_DIR;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use hawkeye_core::models::Watcher;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use warp::hyper::body::Buf;
+use warp::multipart::{FormData, Part};
+
+/// Scheme of the URLs referencing a slate of the library.
+pub const SCHEME: &str = "slate://";
+
+/// Largest slate image accepted, short videos included.
+pub const MAX_SLATE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Formats the workers can load a slate from.
+const CONTENT_TYPES: [&str; 4] = ["image/png", "image/jpeg", "video/mp4", "video/x-matroska"];
+
+/// Description of a slate of the library.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct SlateInfo {
+    pub id: String,
+    pub description: Option<String>,
+    pub content_type: String,
+    /// Size of the image in bytes.
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    /// URL of the slate in the watchers.
+    pub url: String,
+}
+
+/// Problems with an uploaded slate, reported to the client.
+#[derive(Debug, PartialEq)]
+pub enum UploadError {
+    MissingFile,
+    UnsupportedType(String),
+    Invalid(String),
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::MissingFile => write!(f, "The slate must be uploaded in the file field"),
+            UploadError::UnsupportedType(content_type) => write!(
+                f,
+                "Unsupported slate type {}, expected one of {}",
+                content_type,
+                CONTENT_TYPES.join(", ")
+            ),
+            UploadError::Invalid(message) => write!(f, "Invalid upload: {}", message),
+        }
+    }
+}
+
+/// A slate read from a multipart upload, with the image in the `file` field and an optional
+/// `description` field.
+pub struct Upload {
+    pub description: Option<String>,
+    pub content_type: String,
+    pub image: Vec<u8>,
+}
+
+impl Upload {
+    pub async fn read(form: FormData) -> Result<Self, UploadError> {
+        let parts: Vec<Part> = form
+            .try_collect()
+            .await
+            .map_err(|e| UploadError::Invalid(e.to_string()))?;
+        let mut description = None;
+        let mut file = None;
+        for part in parts {
+            match part.name() {
+                "description" => {
+                    description = Some(String::from_utf8_lossy(&read(part).await?).into())
+                }
+                "file" => {
+                    let content_type = part
+                        .content_type()
+                        .unwrap_or("application/octet-stream")
+                        .to_string();
+                    file = Some((content_type, read(part).await?));
+                }
+                _ => (),
+            }
+        }
+
+        let (content_type, image) = file.ok_or(UploadError::MissingFile)?;
+        if !CONTENT_TYPES.contains(&content_type.as_str()) {
+            return Err(UploadError::UnsupportedType(content_type));
+        }
+        if image.is_empty() {
+            return Err(UploadError::Invalid("The slate is empty".to_string()));
+        }
+        Ok(Self {
+            description,
+            content_type,
+            image,
+        })
+    }
+}
+
+async fn read(part: Part) -> Result<Vec<u8>, UploadError> {
+    part.stream()
+        .try_fold(Vec::new(), |mut contents, buf| async move {
+            contents.extend_from_slice(buf.chunk());
+            Ok(contents)
+        })
+        .await
+        .map_err(|e| UploadError::Invalid(e.to_string()))
+}
+
+/// Slates stored in a directory, each image next to its description.
+pub struct Library {
+    dir: PathBuf,
+}
+
+impl Library {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub async fn save(&self, upload: Upload) -> anyhow::Result<SlateInfo> {
+        let id = Uuid::new_v4().to_string();
+        let info = SlateInfo {
+            url: format!("{}{}", SCHEME, id),
+            id,
+            description: upload.description,
+            content_type: upload.content_type,
+            size: upload.image.len() as u64,
+            created_at: Utc::now(),
+        };
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.image_path(&info.id), &upload.image).await?;
+        // Written last, so slates are only listed once their image is complete
+        tokio::fs::write(self.info_path(&info.id), serde_json::to_vec(&info)?).await?;
+        Ok(info)
+    }
+
+    /// All the slates of the library, oldest first.
+    pub async fn list(&self) -> anyhow::Result<Vec<SlateInfo>> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut slates = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let contents = tokio::fs::read(&path).await?;
+            slates.push(serde_json::from_slice::<SlateInfo>(&contents)?);
+        }
+        slates.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        Ok(slates)
+    }
+
+    pub async fn get(&self, id: &str) -> anyhow::Result<Option<(SlateInfo, Vec<u8>)>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        let info = match tokio::fs::read(self.info_path(id)).await {
+            Ok(contents) => serde_json::from_slice::<SlateInfo>(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let image = tokio::fs::read(self.image_path(id)).await?;
+        Ok(Some((info, image)))
+    }
+
+    /// Deletes the slate, returns `false` when it does not exist.
+    pub async fn delete(&self, id: &str) -> anyhow::Result<bool> {
+        if !is_valid_id(id) {
+            return Ok(false);
+        }
+        match tokio::fs::remove_file(self.info_path(id)).await {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        tokio::fs::remove_file(self.image_path(id)).await?;
+        Ok(true)
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn image_path(&self, id: &str) -> PathBuf {
+        self.dir.join(Path::new(id))
+    }
+}
+
+/// The library configured in the API.
+pub fn library() -> Library {
+    Library::new(SLATE_DIR.as_str())
+}
+
+/// Whether any slate of the watcher is the slate of the library with this ID.
+pub fn is_used_by(watcher: &Watcher, id: &str) -> bool {
+    let url = format!("{}{}", SCHEME, id);
+    watcher.all_slates().iter().any(|slate| slate.url == url)
+}
+
+/// IDs are generated by the API, anything else cannot be a file of the library.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_slates() {
+        let dir = std::env::temp_dir().join(format!("hawkeye-slates-{}", Uuid::new_v4()));
+        let library = Library::new(&dir);
+        assert_eq!(library.list().await.unwrap(), Vec::new());
+
+        let info = library
+            .save(Upload {
+                description: Some("Network slate".to_string()),
+                content_type: "image/png".to_string(),
+                image: vec![1, 2, 3],
+            })
+            .await
+            .unwrap();
+        assert_eq!(info.url, format!("slate://{}", info.id));
+        assert_eq!(library.list().await.unwrap(), vec![info.clone()]);
+        assert_eq!(
+            library.get(&info.id).await.unwrap(),
+            Some((info.clone(), vec![1, 2, 3]))
+        );
+        assert_eq!(library.get("../secrets").await.unwrap(), None);
+
+        assert!(library.delete(&info.id).await.unwrap());
+        assert!(!library.delete(&info.id).await.unwrap());
+        assert_eq!(library.get(&info.id).await.unwrap(), None);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,64 @@
+//! Exports the traces of the API with OpenTelemetry.
+//!
+//! Every request gets a span, and the handlers, the calls made to Kubernetes and to the worker
+//! pods are nested in it, carrying the `watcher_id` they work on. The log records are also
+//! emitted as events of the current span, unless another logger like Sentry's is installed, and
+//! can be printed as JSON objects holding the fields of their spans.
+use opentelemetry::global;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use std::collections::HashMap;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::EnvFilter;
+
+/// Installs the OTLP exporter sending the traces to the collector at `endpoint`, if any, and
+/// prints the logs like `pretty_env_logger` does, or as JSON objects when `json_logs` is set. The
+/// log records are captured when `capture_logs` is set.
+pub fn init(endpoint: Option<&str>, json_logs: bool, capture_logs: bool) -> anyhow::Result<()> {
+    let tracer = match endpoint {
+        Some(endpoint) => {
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            Some(
+                opentelemetry_otlp::new_pipeline()
+                    .tracing()
+                    .with_exporter(
+                        opentelemetry_otlp::new_exporter()
+                            .tonic()
+                            .with_endpoint(endpoint),
+                    )
+                    .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                        KeyValue::new("service.name", "hawkeye-api"),
+                    ])))
+                    .install_batch(opentelemetry::runtime::Tokio)?,
+            )
+        }
+        None => None,
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with((!json_logs).then(tracing_subscriber::fmt::layer))
+        .with(json_logs.then(|| tracing_subscriber::fmt::layer().json().flatten_event(true)))
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)));
+    tracing::subscriber::set_global_default(subscriber)?;
+    if capture_logs {
+        tracing_log::LogTracer::init()?;
+    }
+    Ok(())
+}
+
+/// Sends the spans not exported yet, before the process exits.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Builds the `traceparent` headers continuing the current span in the called service.
+pub fn trace_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+    headers
+}
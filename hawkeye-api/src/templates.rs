@@ -1,25 +1,173 @@
-use crate::config::DOCKER_IMAGE;
-use hawkeye_core::models::Status;
+use crate::auth;
+use crate::config::{
+    ACTION_SECRETS, DOCKER_IMAGE, EVENT_STORE, EXEC_ALLOWLIST, LOG_FORMAT, METRICS_PUSH_INTERVAL,
+    METRICS_PUSH_MODE, METRICS_PUSH_SECRET, METRICS_PUSH_URL, NVDEC_GPU_RESOURCE, OTLP_ENDPOINT,
+    PACK_CPU, PACK_MEMORY, RTMPS_CERTIFICATE, RTMP_SERVER_IMAGE, SLATE_LIBRARY_URL, STALL_ACTION,
+    STALL_TIMEOUT, VAAPI_GPU_RESOURCE, WORKER_GRACE_PERIOD, WORKER_SCHEDULING,
+    WORKER_SERVICE_ACCOUNT,
+};
+use hawkeye_core::models::{
+    Decoder, NodeRequirement, Protocol, ResourceQuantities, Scheduling, Status, Watcher,
+    PACK_METRICS_PORT, RTMP_SERVER_PORT, SECRETS_DIR,
+};
 use k8s_openapi::api::apps::v1::Deployment;
 use k8s_openapi::api::core::v1::{ConfigMap, Service};
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
 use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+/// Prefix of the labels holding the `Watcher` tags, so tags can be used in label selectors.
+pub const TAG_LABEL_PREFIX: &str = "tags.hawkeye/";
+
+/// Annotation of the `ConfigMap` holding the audit log of the watcher, as a JSON list.
+pub const AUDIT_ANNOTATION: &str = "hawkeye/audit";
+
+/// Annotation of the `ConfigMap` holding the last heartbeat of the worker of the watcher.
+pub const HEARTBEAT_ANNOTATION: &str = "hawkeye/heartbeat";
+
+/// Annotation of the `ConfigMap` of a watcher whose other objects are being created, with the
+/// time the creation started. The reconciliation leaves the watcher alone meanwhile.
+pub const CREATING_ANNOTATION: &str = "hawkeye/creating";
+
+/// Label of the objects of a pack of watchers, and of the `ConfigMap` of its watchers.
+pub const PACK_LABEL: &str = "pack";
+
+/// Subject of the control token of the worker of a watcher, see `auth::control_token`.
+pub fn watcher_subject(watcher_id: &str) -> String {
+    format!("watcher/{}", watcher_id)
+}
+
+/// Subject of the control token of the worker of a pack.
+pub fn pack_subject(pack: &str) -> String {
+    format!("pack/{}", pack)
+}
+
+/// Subject of the control token of a worker pod, from its labels: its watcher, or its pack.
+pub fn pod_subject(labels: &BTreeMap<String, String>) -> Option<String> {
+    match (labels.get("watcher_id"), labels.get(PACK_LABEL)) {
+        (Some(watcher_id), _) => Some(watcher_subject(watcher_id)),
+        (None, Some(pack)) => Some(pack_subject(pack)),
+        (None, None) => None,
+    }
+}
+
+/// Builds the label key used to store a `Watcher` tag.
+pub fn tag_label(tag: &str) -> String {
+    format!("{}{}", TAG_LABEL_PREFIX, tag)
+}
 
 /// Builds an idempotent name for the `ConfigMap` based on the `watcher_id`.
 pub fn configmap_name(watcher_id: &str) -> String {
     format!("hawkeye-config-{}", watcher_id)
 }
 
-/// Builds a `ConfigMap` in the format expected to run the hawkeye-worker.
-pub fn build_configmap(watcher_id: &str, contents: &str) -> ConfigMap {
+/// Label of the `ConfigMap` of the deleted watchers, so they are left out of the listings
+/// until purged.
+pub const DELETED_LABEL: &str = "hawkeye/deleted";
+
+/// Label of the `ConfigMap` holding the name of the watcher, so it is found by name with a
+/// label selector. The names are valid label values.
+pub const NAME_LABEL: &str = "hawkeye/name";
+
+/// Builds the labels of the `ConfigMap`, including the name and the tags of the `Watcher`.
+pub fn configmap_labels(watcher_id: &str, watcher: &Watcher) -> HashMap<String, String> {
+    let mut labels = HashMap::new();
+    labels.insert("app".to_string(), "hawkeye".to_string());
+    labels.insert("watcher_id".to_string(), watcher_id.to_string());
+    if let Some(name) = watcher.name.as_ref() {
+        labels.insert(NAME_LABEL.to_string(), name.clone());
+    }
+    for (tag, value) in watcher.tags.iter().flatten() {
+        labels.insert(tag_label(tag), value.clone());
+    }
+    labels
+}
+
+/// Name of the `ConfigMap` reserving the name of a watcher. The name is hashed, since the
+/// watcher names are not all valid object names.
+pub fn name_reservation_name(name: &str) -> String {
+    let digest = hex::encode(Sha256::digest(name.as_bytes()));
+    format!("hawkeye-name-{}", &digest[..40])
+}
+
+/// Key of the ID of the watcher holding the name in its reservation.
+pub const RESERVATION_WATCHER_KEY: &str = "watcher_id";
+
+/// Key of the RFC 3339 time the name was reserved at in its reservation.
+pub const RESERVATION_TIME_KEY: &str = "reserved_at";
+
+/// Builds the `ConfigMap` reserving the name for the watcher. Created once per name, so the API
+/// server refuses a second watcher with the same name.
+pub fn build_name_reservation(name: &str, watcher_id: &str, reserved_at: &str) -> ConfigMap {
     serde_json::from_value(json!({
         "apiVersion": "v1",
         "kind": "ConfigMap",
         "metadata": {
-            "name": configmap_name(watcher_id),
+            "name": name_reservation_name(name),
+            "labels": {
+                "app": "hawkeye-name",
+            },
+        },
+        "data": {
+            "name": name,
+            (RESERVATION_WATCHER_KEY): watcher_id,
+            (RESERVATION_TIME_KEY): reserved_at,
+        }
+    }))
+    .unwrap()
+}
+
+/// Name of the `ConfigMap` holding the revisions of the watcher definition.
+pub fn revisions_name(watcher_id: &str) -> String {
+    format!("hawkeye-revisions-{}", watcher_id)
+}
+
+/// Key of the revisions in their `ConfigMap`, as a JSON list.
+pub const REVISIONS_KEY: &str = "revisions.json";
+
+/// Builds the `ConfigMap` holding the revisions of the watcher. It is owned by the `ConfigMap` of
+/// the watcher, so it is garbage collected with it, and has no `watcher_id` label so it is never
+/// taken for the one of a watcher.
+pub fn build_revisions_configmap(watcher_id: &str, contents: &str, owner: &ConfigMap) -> ConfigMap {
+    serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": {
+            "name": revisions_name(watcher_id),
             "labels": {
                 "app": "hawkeye",
-                "watcher_id": watcher_id,
-            }
+                "revisions_of": watcher_id,
+            },
+            "ownerReferences": [{
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "name": configmap_name(watcher_id),
+                "uid": owner.metadata.uid,
+            }],
+        },
+        "data": {
+            (REVISIONS_KEY): contents,
+        }
+    }))
+    .unwrap()
+}
+
+/// Builds a `ConfigMap` in the format expected to run the hawkeye-worker. The `ConfigMap` of a
+/// packed watcher also holds the status it should reach, its pack having no `Deployment` of its
+/// own.
+pub fn build_configmap(watcher_id: &str, contents: &str, watcher: &Watcher) -> ConfigMap {
+    let mut labels = json!(configmap_labels(watcher_id, watcher));
+    if let Some(pack) = watcher.pack.as_deref() {
+        labels[PACK_LABEL] = json!(pack);
+        labels["target_status"] = json!(Status::Ready);
+    }
+    serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": {
+            "name": configmap_name(watcher_id),
+            "labels": labels,
         },
         "data": {
             "log_level": "INFO",
@@ -35,8 +183,27 @@ pub fn deployment_name(watcher_id: &str) -> String {
 }
 
 /// Builds a `Deployment` configured to run the hawkeye-worker process.
-pub fn build_deployment(watcher_id: &str, ingest_port: u32) -> Deployment {
+pub fn build_deployment(watcher_id: &str, watcher: &Watcher) -> Deployment {
+    let ingest_port = watcher
+        .source
+        .ingest_port
+        .expect("Validated watchers have an ingest port");
     let metric_port_str = ingest_port.to_string();
+    // Joining a multicast group needs the network of the node, the group is not routed to pods
+    let host_network = watcher.source.multicast.is_some();
+    let mut volumes = vec![json!({
+        "name": "config",
+        "configMap": {
+            "name": configmap_name(watcher_id),
+            "items": [
+                {
+                    "key": "watcher.json",
+                    "path": "watcher.json"
+                }
+            ]
+        }
+    })];
+    volumes.extend(secret_volumes(&watcher.secret_names()));
     serde_json::from_value(json!({
         "apiVersion": "apps/v1",
         "kind": "Deployment",
@@ -71,27 +238,163 @@ pub fn build_deployment(watcher_id: &str, ingest_port: u32) -> Deployment {
                         "prometheus.io/path": "metrics",
                     }
                 },
+                "spec": {
+                    "hostNetwork": host_network,
+                    "dnsPolicy": "Default",
+                    "restartPolicy": "Always",
+                    "terminationGracePeriodSeconds": *WORKER_GRACE_PERIOD,
+                    "serviceAccountName": *WORKER_SERVICE_ACCOUNT,
+                    "nodeSelector": node_selector(&WORKER_SCHEDULING, watcher.scheduling.as_ref()),
+                    "tolerations": tolerations(&WORKER_SCHEDULING, watcher.scheduling.as_ref()),
+                    "affinity": affinity(&WORKER_SCHEDULING, watcher.scheduling.as_ref()),
+                    "containers": containers(watcher_id, watcher),
+                    "volumes": volumes,
+                }
+            }
+        }
+    }))
+    .unwrap()
+}
+
+/// Builds an idempotent name for the `Deployment` of a pack of watchers.
+pub fn pack_deployment_name(pack: &str) -> String {
+    format!("hawkeye-pack-{}", pack)
+}
+
+/// Annotation of the `Deployment` of a pack naming the watchers its pods run, comma separated.
+/// Not a label of the pods, so starting or stopping a watcher does not roll out the pack.
+pub const MEMBERS_ANNOTATION: &str = "hawkeye/members";
+
+/// Directory of the pods of a pack holding the configurations of the watchers they run.
+const PACK_MEMBERS_DIR: &str = "/config/members";
+
+/// Builds an idempotent name for the `ConfigMap` of the watchers a pack runs.
+pub fn pack_configmap_name(pack: &str) -> String {
+    format!("hawkeye-pack-members-{}", pack)
+}
+
+/// Builds the `ConfigMap` holding the definitions of the watchers the pack runs, `<id>.json`
+/// each, with the report token of each watcher in `<id>.token`. The worker of the pack reloads
+/// it, running the watchers added and stopping the ones removed while the others keep running.
+pub fn build_pack_configmap(pack: &str, running: &[&Watcher]) -> ConfigMap {
+    let mut data = BTreeMap::new();
+    for watcher in running {
+        let id = match watcher.id.as_deref() {
+            Some(id) => id,
+            None => continue,
+        };
+        if let Ok(definition) = serde_json::to_string(watcher) {
+            data.insert(format!("{}.json", id), definition);
+            data.insert(format!("{}.token", id), auth::report_token(id));
+        }
+    }
+    serde_json::from_value(json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": {
+            "name": pack_configmap_name(pack),
+            "labels": {
+                "app": "hawkeye",
+                PACK_LABEL: pack,
+            }
+        },
+        "data": data,
+    }))
+    .unwrap()
+}
+
+/// Builds the `Deployment` of a pack of watchers, whose single worker runs the ones of
+/// `running`, from the `ConfigMap` of the pack. The pod template only depends on the secrets of
+/// the `members`, so it is not rolled out when they are started or stopped, and the pack is
+/// stopped when none of them runs.
+pub fn build_pack_deployment(pack: &str, members: &[&Watcher], running: &[&Watcher]) -> Deployment {
+    let metrics_port = PACK_METRICS_PORT.to_string();
+    let labels = json!({
+        "app": "hawkeye",
+        PACK_LABEL: pack,
+        "prometheus.io/port": metrics_port,
+        "prometheus.io/scrape": "true",
+        "prometheus.io/path": "metrics",
+    });
+    let running_ids: Vec<&str> = running.iter().filter_map(|w| w.id.as_deref()).collect();
+    let mut mounts = vec![json!({
+        "mountPath": PACK_MEMBERS_DIR,
+        "name": "members",
+        "readOnly": true
+    })];
+    let mut volumes = vec![json!({
+        "name": "members",
+        "configMap": {
+            "name": pack_configmap_name(pack),
+        }
+    })];
+    // The ports of the watchers are not declared, the Services target them by number
+    let ports = vec![json!({
+        "name": "metrics",
+        "containerPort": PACK_METRICS_PORT,
+        "protocol": "TCP"
+    })];
+    let mut secrets: Vec<&str> = members.iter().flat_map(|w| w.secret_names()).collect();
+    secrets.sort_unstable();
+    secrets.dedup();
+    mounts.extend(secret_mounts(&secrets));
+    volumes.extend(secret_volumes(&secrets));
+    let resources = json!({"cpu": PACK_CPU.as_str(), "memory": PACK_MEMORY.as_str()});
+    let replicas = if running.is_empty() { 0 } else { 1 };
+    serde_json::from_value(json!({
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "metadata": {
+            "name": pack_deployment_name(pack),
+            "labels": {
+                "app": "hawkeye",
+                PACK_LABEL: pack,
+            },
+            "annotations": {
+                MEMBERS_ANNOTATION: running_ids.join(","),
+            }
+        },
+        "spec": {
+            "replicas": replicas,
+            "selector": {
+                "matchLabels": {
+                    "app": "hawkeye",
+                    PACK_LABEL: pack,
+                }
+            },
+            "template": {
+                "metadata": {
+                    "annotations": {
+                        "prometheus.io/port": metrics_port,
+                        "prometheus.io/scrape": "true",
+                        "prometheus.io/path": "metrics",
+                    },
+                    "labels": labels,
+                },
                 "spec": {
                     "dnsPolicy": "Default",
                     "restartPolicy": "Always",
-                    "terminationGracePeriodSeconds": 5,
+                    "terminationGracePeriodSeconds": *WORKER_GRACE_PERIOD,
+                    "serviceAccountName": *WORKER_SERVICE_ACCOUNT,
+                    "nodeSelector": node_selector(&WORKER_SCHEDULING, None),
+                    "tolerations": tolerations(&WORKER_SCHEDULING, None),
+                    "affinity": affinity(&WORKER_SCHEDULING, None),
                     "containers": [
-                        container_spec(watcher_id, ingest_port)
-                    ],
-                    "volumes": [
                         {
-                            "name": "config",
-                            "configMap": {
-                                "name": configmap_name(watcher_id),
-                                "items": [
-                                    {
-                                        "key": "watcher.json",
-                                        "path": "watcher.json"
-                                    }
-                                ]
-                            }
+                            "name": CONTAINER_NAME,
+                            "imagePullPolicy": "IfNotPresent",
+                            "image": DOCKER_IMAGE.as_str(),
+                            "args": ["pack", PACK_MEMBERS_DIR],
+                            "env": worker_env(None, &pack_subject(pack)),
+                            "resources": {
+                                "limits": resources,
+                                "requests": resources,
+                            },
+                            "ports": ports,
+                            "volumeMounts": mounts,
                         }
-                    ]
+                    ],
+                    "volumes": volumes,
                 }
             }
         }
@@ -99,35 +402,181 @@ pub fn build_deployment(watcher_id: &str, ingest_port: u32) -> Deployment {
     .unwrap()
 }
 
+/// Volumes of the `Secret`s referenced by the actions, optional so a missing `Secret` only fails
+/// the actions using it.
+fn secret_volumes(names: &[&str]) -> Vec<serde_json::Value> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            json!({
+                "name": format!("secret-{}", i),
+                "secret": {
+                    "secretName": name,
+                    "optional": true
+                }
+            })
+        })
+        .collect()
+}
+
+/// Mounts of the `secret_volumes` in the worker, where it reads them when the actions run.
+fn secret_mounts(names: &[&str]) -> Vec<serde_json::Value> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            json!({
+                "mountPath": format!("{}/{}", SECRETS_DIR, name),
+                "name": format!("secret-{}", i),
+                "readOnly": true
+            })
+        })
+        .collect()
+}
+
+/// Merges the node labels required by the API and the `Watcher`, the `Watcher` wins on conflicts.
+fn node_selector(
+    global: &Scheduling,
+    watcher: Option<&Scheduling>,
+) -> Option<HashMap<String, String>> {
+    let selectors = [Some(global), watcher];
+    let mut labels = HashMap::new();
+    for selector in selectors
+        .iter()
+        .flatten()
+        .filter_map(|s| s.node_selector.as_ref())
+    {
+        labels.extend(selector.clone());
+    }
+    if labels.is_empty() {
+        None
+    } else {
+        Some(labels)
+    }
+}
+
+/// Combines the tolerations of the API and the `Watcher`.
+fn tolerations(global: &Scheduling, watcher: Option<&Scheduling>) -> Option<serde_json::Value> {
+    let tolerations: Vec<serde_json::Value> = [Some(global), watcher]
+        .iter()
+        .flatten()
+        .filter_map(|s| s.tolerations.as_ref())
+        .flatten()
+        .map(|t| {
+            json!({
+                "key": t.key,
+                "operator": t.operator,
+                "value": t.value,
+                "effect": t.effect,
+                "tolerationSeconds": t.toleration_seconds,
+            })
+        })
+        .collect();
+    if tolerations.is_empty() {
+        None
+    } else {
+        Some(json!(tolerations))
+    }
+}
+
+/// Builds the node affinity of the worker, the affinity of the `Watcher` replaces the one of
+/// the API.
+fn affinity(global: &Scheduling, watcher: Option<&Scheduling>) -> Option<serde_json::Value> {
+    let affinity = watcher
+        .and_then(|s| s.affinity.as_ref())
+        .or_else(|| global.affinity.as_ref())?;
+    let term = |requirements: &Vec<NodeRequirement>| {
+        let expressions: Vec<serde_json::Value> = requirements
+            .iter()
+            .map(|r| json!({ "key": r.key, "operator": r.operator, "values": r.values }))
+            .collect();
+        json!({ "matchExpressions": expressions })
+    };
+    Some(json!({
+        "nodeAffinity": {
+            "requiredDuringSchedulingIgnoredDuringExecution": affinity.required.as_ref().map(|r| {
+                json!({ "nodeSelectorTerms": [term(r)] })
+            }),
+            "preferredDuringSchedulingIgnoredDuringExecution": affinity.preferred.as_ref().map(|r| {
+                json!([{ "weight": 1, "preference": term(r) }])
+            }),
+        }
+    }))
+}
+
+/// Name of the container running the hawkeye-worker in the watcher Pod.
+pub const CONTAINER_NAME: &str = "hawkeye-app";
+
+/// Resources of the worker container when not set in the `Watcher`.
+const DEFAULT_REQUESTS: (&str, &str) = ("1150m", "50Mi");
+const DEFAULT_LIMITS: (&str, &str) = ("2000m", "100Mi");
+
+/// Name of the container running the RTMP server of the `rtmp` sources in the watcher Pod.
+const RTMP_SERVER_CONTAINER_NAME: &str = "rtmp-server";
+
+/// The containers of the watcher Pod: the worker, and the RTMP server the encoder publishes to
+/// for the `rtmp` sources.
+pub fn containers(watcher_id: &str, watcher: &Watcher) -> Vec<serde_json::Value> {
+    let mut containers = vec![container_spec(watcher_id, watcher)];
+    if let Protocol::Rtmp { .. } = watcher.source.transport {
+        containers.push(json!({
+            "name": RTMP_SERVER_CONTAINER_NAME,
+            "imagePullPolicy": "IfNotPresent",
+            "image": RTMP_SERVER_IMAGE.as_str(),
+            "ports": [
+                {
+                    "name": "rtmp",
+                    "containerPort": RTMP_SERVER_PORT,
+                    "protocol": "TCP"
+                }
+            ]
+        }));
+    }
+    containers
+}
+
 /// Returns a fragment of the container specification
-pub fn container_spec(watcher_id: &str, ingest_port: u32) -> serde_json::Value {
+pub fn container_spec(watcher_id: &str, watcher: &Watcher) -> serde_json::Value {
+    let ingest_port = watcher
+        .source
+        .ingest_port
+        .expect("Validated watchers have an ingest port");
+    let resources = watcher.resources.as_ref();
+    let mut limits = quantities(resources.and_then(|r| r.limits.as_ref()), DEFAULT_LIMITS);
+    let mut env = worker_env(Some(watcher_id), &watcher_subject(watcher_id));
+    if let Some(gpus) = resources.and_then(|r| r.gpus) {
+        match watcher.decoder() {
+            Decoder::Nvdec => {
+                limits[NVDEC_GPU_RESOURCE.as_str()] = json!(gpus);
+                // The NVIDIA container runtime only mounts the libraries of NVDEC when asked to
+                env.push(
+                    json!({"name": "NVIDIA_DRIVER_CAPABILITIES", "value": "compute,utility,video"}),
+                );
+            }
+            Decoder::Vaapi => limits[VAAPI_GPU_RESOURCE.as_str()] = json!(gpus),
+            // Validated watchers only request GPUs for the hardware decoders
+            _ => (),
+        }
+    }
+    let mut mounts = vec![json!({
+        "mountPath": "/config",
+        "name": "config",
+        "readOnly": true
+    })];
+    mounts.extend(secret_mounts(&watcher.secret_names()));
     json!({
-        "name": "hawkeye-app",
+        "name": CONTAINER_NAME,
         "imagePullPolicy": "IfNotPresent",
-        "image": DOCKER_IMAGE.as_str(),
+        "image": watcher.worker_image.as_deref().unwrap_or_else(|| DOCKER_IMAGE.as_str()),
         "args": [
             "/config/watcher.json"
         ],
-        "env": [
-            {
-                "name": "RUST_LOG",
-                "valueFrom": {
-                    "configMapKeyRef": {
-                        "name": configmap_name(watcher_id),
-                        "key": "log_level"
-                    }
-                }
-            }
-        ],
+        "env": env,
         "resources": {
-            "limits": {
-                "cpu": "2000m",
-                "memory": "100Mi"
-            },
-            "requests": {
-                "cpu": "1150m",
-                "memory": "50Mi"
-            }
+            // Kubernetes requests the same number of GPUs as their limit
+            "limits": limits,
+            "requests": quantities(resources.and_then(|r| r.requests.as_ref()), DEFAULT_REQUESTS),
         },
         "ports": [
             {
@@ -139,13 +588,110 @@ pub fn container_spec(watcher_id: &str, ingest_port: u32) -> serde_json::Value {
                 "protocol": "TCP"
             }
         ],
-        "volumeMounts": [
-            {
-                "mountPath": "/config",
-                "name": "config",
-                "readOnly": true
+        "volumeMounts": mounts,
+        // The worker serves its probes with its metrics, on the ingest port
+        "startupProbe": {
+            "httpGet": {"path": "/healthz", "port": ingest_port},
+            "periodSeconds": 10,
+            "failureThreshold": 30
+        },
+        "livenessProbe": {
+            "httpGet": {"path": "/healthz", "port": ingest_port},
+            "periodSeconds": 10,
+            "failureThreshold": 3
+        },
+        "readinessProbe": {
+            "httpGet": {"path": "/readyz", "port": ingest_port},
+            "periodSeconds": 5,
+            "failureThreshold": 2
+        }
+    })
+}
+
+/// Environment of the worker: its log level and format, where it downloads the slates of the
+/// library from and sends its heartbeats and events to, and where it exports its traces and
+/// pushes its metrics when configured. The worker of a pack, without a watcher, logs at the
+/// `INFO` level, and its watchers get their report token from the `ConfigMap` of the pack. The
+/// API authenticates with the control token of the `subject` of the worker.
+fn worker_env(watcher_id: Option<&str>, subject: &str) -> Vec<serde_json::Value> {
+    let mut env = vec![match watcher_id {
+        Some(watcher_id) => json!({
+            "name": "RUST_LOG",
+            "valueFrom": {
+                "configMapKeyRef": {
+                    "name": configmap_name(watcher_id),
+                    "key": "log_level"
+                }
             }
-        ]
+        }),
+        None => json!({"name": "RUST_LOG", "value": "INFO"}),
+    }];
+    env.push(json!({"name": "HAWKEYE_LOG_FORMAT", "value": LOG_FORMAT.as_str()}));
+    env.push(json!({"name": "HAWKEYE_GRACE_PERIOD", "value": WORKER_GRACE_PERIOD.to_string()}));
+    env.push(json!({"name": "HAWKEYE_CONTROL_TOKEN", "value": auth::control_token(subject)}));
+    if let Some(library_url) = SLATE_LIBRARY_URL.as_ref() {
+        env.push(json!({"name": "HAWKEYE_SLATE_LIBRARY_URL", "value": library_url}));
+        env.push(json!({"name": "HAWKEYE_HEARTBEAT_URL", "value": library_url}));
+        if EVENT_STORE.is_some() {
+            env.push(json!({"name": "HAWKEYE_EVENTS_URL", "value": library_url}));
+        }
+        if let Some(watcher_id) = watcher_id {
+            env.push(json!({"name": "HAWKEYE_API_TOKEN", "value": auth::report_token(watcher_id)}));
+        }
+    }
+    if let Some(endpoint) = OTLP_ENDPOINT.as_ref() {
+        env.push(json!({"name": "HAWKEYE_OTLP_ENDPOINT", "value": endpoint}));
+    }
+    if !EXEC_ALLOWLIST.is_empty() {
+        env.push(json!({"name": "HAWKEYE_EXEC_ALLOWLIST", "value": EXEC_ALLOWLIST.join(",")}));
+    }
+    if !ACTION_SECRETS.is_empty() {
+        env.push(json!({"name": "HAWKEYE_ACTION_SECRETS", "value": ACTION_SECRETS.join(",")}));
+    }
+    if let Some(timeout) = *STALL_TIMEOUT {
+        env.push(json!({"name": "HAWKEYE_STALL_TIMEOUT", "value": timeout.to_string()}));
+        env.push(json!({"name": "HAWKEYE_STALL_ACTION", "value": STALL_ACTION.as_str()}));
+    }
+    let push_url = match METRICS_PUSH_URL.as_ref() {
+        Some(push_url) => push_url,
+        None => return env,
+    };
+    env.push(json!({"name": "HAWKEYE_METRICS_PUSH_URL", "value": push_url}));
+    env.push(json!({"name": "HAWKEYE_METRICS_PUSH_MODE", "value": METRICS_PUSH_MODE.as_str()}));
+    env.push(json!({
+        "name": "HAWKEYE_METRICS_PUSH_INTERVAL",
+        "value": METRICS_PUSH_INTERVAL.to_string()
+    }));
+    if let Some(secret) = METRICS_PUSH_SECRET.as_ref() {
+        for (name, key) in &[
+            ("HAWKEYE_METRICS_PUSH_USERNAME", "username"),
+            ("HAWKEYE_METRICS_PUSH_PASSWORD", "password"),
+            ("HAWKEYE_METRICS_PUSH_TOKEN", "token"),
+        ] {
+            env.push(json!({
+                "name": name,
+                "valueFrom": {
+                    "secretKeyRef": {
+                        "name": secret,
+                        "key": key,
+                        "optional": true
+                    }
+                }
+            }));
+        }
+    }
+    env
+}
+
+/// Fills the quantities missing in the `Watcher` with the defaults.
+fn quantities(
+    quantities: Option<&ResourceQuantities>,
+    defaults: (&str, &str),
+) -> serde_json::Value {
+    let (cpu, memory) = defaults;
+    json!({
+        "cpu": quantities.and_then(|q| q.cpu.as_deref()).unwrap_or(cpu),
+        "memory": quantities.and_then(|q| q.memory.as_deref()).unwrap_or(memory),
     })
 }
 
@@ -154,8 +700,74 @@ pub fn service_name(watcher_id: &str) -> String {
     format!("hawkeye-vid-svc-{}", watcher_id)
 }
 
+/// Name of the `Service` port receiving the video feed with the transport protocol.
+fn ingest_port_name(transport: &Protocol) -> &'static str {
+    match transport {
+        Protocol::Srt { .. } => "srt",
+        Protocol::Rtmp { .. } => "rtmp",
+        _ => "video-feed",
+    }
+}
+
+/// Protocol of the `Service` port receiving the video feed, and port of the Pod it reaches.
+pub fn ingest_target(ingest_port: u32, transport: &Protocol) -> (&'static str, u32) {
+    match transport {
+        // The RTMP server receives the feed, the worker serves its metrics on the ingest port
+        Protocol::Rtmp { .. } => ("TCP", RTMP_SERVER_PORT),
+        _ => ("UDP", ingest_port),
+    }
+}
+
+/// Annotations of the load balancer terminating the TLS of the RTMPS sources, null for the other
+/// sources so they are removed when the `Service` is patched.
+pub fn tls_annotations(transport: &Protocol) -> serde_json::Value {
+    let certificate = match transport {
+        Protocol::Rtmp {
+            tls: Some(true), ..
+        } => RTMPS_CERTIFICATE.as_deref(),
+        _ => None,
+    };
+    json!({
+        "service.beta.kubernetes.io/aws-load-balancer-ssl-cert": certificate,
+        "service.beta.kubernetes.io/aws-load-balancer-ssl-ports": certificate.map(|_| "rtmp"),
+    })
+}
+
 /// Builds a `Service` in the format expected to expose the hawkeye-worker.
-pub fn build_service(watcher_id: &str, ingest_port: u32) -> Service {
+pub fn build_service(watcher_id: &str, ingest_port: u32, watcher: &Watcher) -> Service {
+    let source = &watcher.source;
+    let transport = &source.transport;
+    let (protocol, target_port) = ingest_target(ingest_port, transport);
+    let mut ports = vec![json!({
+        "name": ingest_port_name(transport),
+        "protocol": protocol,
+        "port": ingest_port,
+        "targetPort": target_port
+    })];
+    // The FEC streams, second leg or RTCP of RIST
+    for (name, port) in source.ports_from(ingest_port).into_iter().skip(1) {
+        ports.push(json!({
+            "name": name,
+            "protocol": "UDP",
+            "port": port,
+            "targetPort": port
+        }));
+    }
+    ports.extend(backup_ports(watcher));
+    let mut annotations = json!({
+        // "external-dns.alpha.kubernetes.io/hostname": "",
+        "service.beta.kubernetes.io/aws-load-balancer-type": "nlb"
+    });
+    if let Some(tls) = tls_annotations(transport).as_object() {
+        for (key, value) in tls.iter().filter(|(_, value)| !value.is_null()) {
+            annotations[key] = value.clone();
+        }
+    }
+    // The feeds of a packed watcher reach the pod of its pack
+    let selector = match watcher.pack.as_deref() {
+        Some(pack) => json!({"app": "hawkeye", PACK_LABEL: pack}),
+        None => json!({"app": "hawkeye", "watcher_id": watcher_id}),
+    };
     serde_json::from_value(json!({
         "apiVersion": "v1",
         "kind": "Service",
@@ -165,26 +777,69 @@ pub fn build_service(watcher_id: &str, ingest_port: u32) -> Service {
                 "app": "hawkeye",
                 "watcher_id": watcher_id,
             },
-            "annotations": {
-                // "external-dns.alpha.kubernetes.io/hostname": "",
-                "service.beta.kubernetes.io/aws-load-balancer-type": "nlb"
-            }
+            "annotations": annotations,
         },
         "spec": {
             "type": "LoadBalancer",
             "externalTrafficPolicy": "Cluster",
-            "selector": {
+            // A worker is only ready once it receives frames, the feed reaches it before
+            "publishNotReadyAddresses": true,
+            "selector": selector,
+            "ports": ports,
+        }
+    }))
+    .unwrap()
+}
+
+/// The UDP ports of the backup sources sent to the worker, named after the position of the
+/// backup, like `b1-ingest`, within the 15 characters of the port names.
+fn backup_ports(watcher: &Watcher) -> Vec<serde_json::Value> {
+    let mut ports = Vec::new();
+    for (i, backup) in watcher.backup_sources.iter().flatten().enumerate() {
+        if !backup.is_pushed() {
+            continue;
+        }
+        let ingest_port = backup
+            .ingest_port
+            .expect("Validated backup sources have an ingest port");
+        for (name, port) in backup.ports_from(ingest_port) {
+            ports.push(json!({
+                "name": format!("b{}-{}", i + 1, name),
+                "protocol": "UDP",
+                "port": port,
+                "targetPort": port
+            }));
+        }
+    }
+    ports
+}
+
+/// Builds an idempotent name for the `PodDisruptionBudget` based on the `watcher_id`.
+pub fn pdb_name(watcher_id: &str) -> String {
+    format!("hawkeye-pdb-{}", watcher_id)
+}
+
+/// Builds a `PodDisruptionBudget` keeping the running worker from being evicted, so node drains
+/// wait for the watcher to be stopped.
+pub fn build_pdb(watcher_id: &str) -> PodDisruptionBudget {
+    serde_json::from_value(json!({
+        "apiVersion": "policy/v1",
+        "kind": "PodDisruptionBudget",
+        "metadata": {
+            "name": pdb_name(watcher_id),
+            "labels": {
                 "app": "hawkeye",
                 "watcher_id": watcher_id,
-            },
-            "ports": [
-                {
-                    "name": "video-feed",
-                    "protocol": "UDP",
-                    "port": ingest_port,
-                    "targetPort": ingest_port
+            }
+        },
+        "spec": {
+            "minAvailable": 1,
+            "selector": {
+                "matchLabels": {
+                    "app": "hawkeye",
+                    "watcher_id": watcher_id,
                 }
-            ]
+            }
         }
     }))
     .unwrap()
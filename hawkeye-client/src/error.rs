@@ -0,0 +1,63 @@
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent, or its reply read.
+    Http(reqwest::Error),
+    /// The API refused the request, with the message of its reply.
+    Api { status: StatusCode, message: String },
+}
+
+impl Error {
+    /// Whether the watcher, slate or action of the request does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::Api { status, .. } if *status == StatusCode::NOT_FOUND)
+    }
+
+    /// The error of a reply without a success status, the API replying `{"message": ...}`.
+    pub(crate) async fn from_response(response: Response) -> Self {
+        #[derive(Deserialize)]
+        struct Message {
+            message: String,
+        }
+
+        let status = response.status();
+        let message = match response.text().await {
+            Ok(text) => serde_json::from_str::<Message>(&text)
+                .map(|m| m.message)
+                .unwrap_or(text),
+            Err(e) => e.to_string(),
+        };
+        Error::Api { status, message }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "Request to the Hawkeye API failed: {}", e),
+            Error::Api { status, message } => {
+                write!(f, "Hawkeye API replied {}: {}", status, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Api { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
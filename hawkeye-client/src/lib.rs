@@ -0,0 +1,648 @@
+//! Client of the Hawkeye API, so other services manage their watchers without writing the HTTP
+//! requests themselves. The models are the ones of `hawkeye-core`, shared with the API.
+//!
+//! ```no_run
+//! # async fn run() -> hawkeye_client::Result<()> {
+//! use hawkeye_client::{Client, ListOptions};
+//!
+//! let client = Client::new("https://hawkeye.example.com").with_token("my-api-key");
+//! let page = client.list_watchers(&ListOptions::default()).await?;
+//! for watcher in page.watchers {
+//!     if let Some(id) = watcher.id.as_deref() {
+//!         client.start_watcher(id).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+mod error;
+mod retry;
+
+pub use crate::error::{Error, Result};
+pub use hawkeye_core::models;
+pub use hawkeye_core::models::{
+    Heartbeat, ModeChange, RetryPolicy, Status, ThresholdChange, TransitionTrigger, Watcher,
+    WatcherEvent, WatcherEventKind,
+};
+
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+use log::warn;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Method, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Characters encoded in the path segments, all but the ones of the watcher names.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.');
+
+/// Query of `Client::list_watchers`.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ListOptions {
+    /// Maximum number of watchers returned in a single page.
+    pub limit: Option<u32>,
+    /// `WatcherPage::continue_token` of the previous page.
+    #[serde(rename = "continue")]
+    pub continue_token: Option<String>,
+    pub status: Option<Status>,
+    /// Comma separated list of `key:value` tags the watchers must have.
+    pub tag: Option<String>,
+    /// Also list the deleted watchers that were not purged yet.
+    pub include_deleted: Option<bool>,
+}
+
+/// A page of watchers, the next one being fetched with its `continue_token`.
+#[derive(Debug, Clone)]
+pub struct WatcherPage {
+    pub watchers: Vec<Watcher>,
+    /// `None` on the last page.
+    pub continue_token: Option<String>,
+}
+
+/// Watchers started or stopped at once, by ID or by tags.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct BulkSelector {
+    pub ids: Option<Vec<String>>,
+    /// Comma separated list of `key:value` tags the watchers must have.
+    pub tag: Option<String>,
+}
+
+/// Body of `Client::upgrade_watchers`.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct FleetUpgrade {
+    /// Watchers to upgrade, all of them when missing.
+    pub ids: Option<Vec<String>>,
+    /// Comma separated list of `key:value` tags the upgraded watchers must have.
+    pub tag: Option<String>,
+    /// Number of watchers upgraded at the same time, 1 by default.
+    pub batch_size: Option<usize>,
+    /// Stop the rollout after a batch where any of the watchers failed to upgrade.
+    pub pause_on_error: Option<bool>,
+    /// Restart the running watchers to upgrade them.
+    pub restart: bool,
+}
+
+/// Query of `Client::logs` and `Client::follow_logs`.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct LogOptions {
+    /// Only return logs newer than this number of seconds.
+    pub since: Option<i64>,
+    /// Only return this number of lines from the end of the logs.
+    pub tail: Option<i64>,
+}
+
+/// Query of `Client::events`.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct EventOptions {
+    /// RFC 3339 time of the oldest events returned, a day before `to` by default.
+    pub from: Option<String>,
+    /// RFC 3339 time of the newest events returned, now by default.
+    pub to: Option<String>,
+    pub kind: Option<WatcherEventKind>,
+    /// Maximum number of events returned.
+    pub limit: Option<usize>,
+}
+
+/// Client of the Hawkeye API. Every request is retried as its `RetryPolicy` sets when it can be
+/// sent again safely: the rate limited requests, waiting for their `Retry-After`, the requests
+/// that did not reach the API, and the ones that can be applied twice when the API was
+/// unavailable.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    /// Client of the API served at `base_url`, like `https://hawkeye.example.com`.
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            retry: retry::default_policy(),
+        }
+    }
+
+    /// Authenticates the requests with an API key, or a JWT.
+    pub fn with_token<S: Into<String>>(mut self, token: S) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sends the requests with this client, to set its timeouts, proxies or certificates.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Sends the request built by `build` until it succeeds, or it cannot be retried. The
+    /// request is built again for every attempt, as the multipart bodies cannot be cloned.
+    async fn send<F>(&self, method: Method, path: &str, build: F) -> Result<Response>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let url = format!("{}{}", self.base_url, path);
+        let mut retries = 0;
+        loop {
+            let mut request = self.http.request(method.clone(), &url);
+            if let Some(token) = self.token.as_ref() {
+                request = request.bearer_auth(token);
+            }
+            let can_retry = retries + 1 < self.retry.max_attempts() as u32;
+            let wait = match build(request).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response)
+                    if can_retry && retry::is_retryable_status(&method, response.status()) =>
+                {
+                    warn!("{} {} replied {}", method, path, response.status());
+                    retry::backoff(&self.retry, retries + 1, Some(&response))
+                }
+                Ok(response) => return Err(Error::from_response(response).await),
+                Err(e) if can_retry && retry::is_retryable_error(&method, &e) => {
+                    warn!("{} {} failed: {}", method, path, e);
+                    retry::backoff(&self.retry, retries + 1, None)
+                }
+                Err(e) => return Err(e.into()),
+            };
+            retries += 1;
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Ok(self.send(Method::GET, path, |r| r).await?.json().await?)
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Ok(self.send(Method::POST, path, |r| r).await?.json().await?)
+    }
+
+    async fn send_json<B, T>(&self, method: Method, path: &str, body: &B) -> Result<T>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        Ok(self
+            .send(method, path, |r| r.json(body))
+            .await?
+            .json()
+            .await?)
+    }
+
+    /// GET /v1/watchers
+    pub async fn list_watchers(&self, options: &ListOptions) -> Result<WatcherPage> {
+        let response = self
+            .send(Method::GET, "/v1/watchers", |r| r.query(options))
+            .await?;
+        let continue_token = response
+            .headers()
+            .get("x-continue-token")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        Ok(WatcherPage {
+            watchers: response.json().await?,
+            continue_token,
+        })
+    }
+
+    /// POST /v1/watchers, returning the watcher with its ID.
+    pub async fn create_watcher(&self, watcher: &Watcher) -> Result<Watcher> {
+        self.send_json(Method::POST, "/v1/watchers", watcher).await
+    }
+
+    /// GET /v1/watchers/{id}
+    pub async fn get_watcher(&self, id: &str) -> Result<Watcher> {
+        self.get(&format!("/v1/watchers/{}", id)).await
+    }
+
+    /// GET /v1/watchers/by-name/{name}
+    pub async fn get_watcher_by_name(&self, name: &str) -> Result<Watcher> {
+        let name = utf8_percent_encode(name, PATH_SEGMENT);
+        self.get(&format!("/v1/watchers/by-name/{}", name)).await
+    }
+
+    /// PUT /v1/watchers/{id}
+    pub async fn update_watcher(&self, id: &str, watcher: &Watcher) -> Result<Watcher> {
+        self.send_json(Method::PUT, &format!("/v1/watchers/{}", id), watcher)
+            .await
+    }
+
+    /// DELETE /v1/watchers/{id}
+    pub async fn delete_watcher(&self, id: &str) -> Result<()> {
+        self.send(Method::DELETE, &format!("/v1/watchers/{}", id), |r| r)
+            .await?;
+        Ok(())
+    }
+
+    /// GET /v1/watchers/{id}/export?format=yaml, the definition of the watcher to store in Git.
+    pub async fn export_watcher(&self, id: &str) -> Result<String> {
+        let path = format!("/v1/watchers/{}/export", id);
+        Ok(self
+            .send(Method::GET, &path, |r| r.query(&[("format", "yaml")]))
+            .await?
+            .text()
+            .await?)
+    }
+
+    /// POST /v1/watchers/import, creating or updating the watcher with the name of the YAML
+    /// `definition`.
+    pub async fn import_watcher(&self, definition: &str) -> Result<Watcher> {
+        Ok(self
+            .send(Method::POST, "/v1/watchers/import", |r| {
+                r.header(CONTENT_TYPE, "application/yaml")
+                    .body(definition.to_string())
+            })
+            .await?
+            .json()
+            .await?)
+    }
+
+    /// POST /v1/watchers/{id}/upgrade
+    pub async fn upgrade_watcher(&self, id: &str, restart: bool) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/upgrade", id);
+        let response = self
+            .send(Method::POST, &path, |r| r.query(&[("restart", restart)]))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// POST /v1/watchers/{id}/start
+    pub async fn start_watcher(&self, id: &str) -> Result<Value> {
+        self.post(&format!("/v1/watchers/{}/start", id)).await
+    }
+
+    /// POST /v1/watchers/{id}/stop, the worker having `grace_seconds` to finish the actions in
+    /// progress.
+    pub async fn stop_watcher(&self, id: &str, grace_seconds: Option<u32>) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/stop", id);
+        let response = self
+            .send(Method::POST, &path, |r| match grace_seconds {
+                Some(seconds) => r.query(&[("grace_seconds", seconds)]),
+                None => r,
+            })
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// POST /v1/watchers/{id}/suspend
+    pub async fn suspend_watcher(&self, id: &str) -> Result<Value> {
+        self.post(&format!("/v1/watchers/{}/suspend", id)).await
+    }
+
+    /// POST /v1/watchers/{id}/resume
+    pub async fn resume_watcher(&self, id: &str) -> Result<Value> {
+        self.post(&format!("/v1/watchers/{}/resume", id)).await
+    }
+
+    /// POST /v1/watchers/{id}/restore
+    pub async fn restore_watcher(&self, id: &str) -> Result<Value> {
+        self.post(&format!("/v1/watchers/{}/restore", id)).await
+    }
+
+    /// POST /v1/watchers/{id}/reload
+    pub async fn reload_watcher(&self, id: &str) -> Result<Value> {
+        self.post(&format!("/v1/watchers/{}/reload", id)).await
+    }
+
+    /// PUT /v1/watchers/{id}/config/threshold
+    pub async fn set_threshold(&self, id: &str, change: &ThresholdChange) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/config/threshold", id);
+        self.send_json(Method::PUT, &path, change).await
+    }
+
+    /// PUT /v1/watchers/{id}/mode
+    pub async fn set_mode(&self, id: &str, change: &ModeChange) -> Result<Value> {
+        self.send_json(Method::PUT, &format!("/v1/watchers/{}/mode", id), change)
+            .await
+    }
+
+    /// GET /v1/watchers/{id}/calibrate, replying once the frames were sampled for `duration`
+    /// seconds.
+    pub async fn calibrate_watcher(&self, id: &str, duration: Option<u64>) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/calibrate", id);
+        let response = self
+            .send(Method::GET, &path, |r| r.query(&[("duration", duration)]))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// POST /v1/watchers/{id}/record, replying with the URL of the clip once it is uploaded.
+    pub async fn record_watcher(&self, id: &str, duration: Option<u64>) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/record", id);
+        let response = self
+            .send(Method::POST, &path, |r| r.query(&[("duration", duration)]))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// POST /v1/watchers/start
+    pub async fn start_watchers(&self, selector: &BulkSelector) -> Result<Value> {
+        self.send_json(Method::POST, "/v1/watchers/start", selector)
+            .await
+    }
+
+    /// POST /v1/watchers/stop
+    pub async fn stop_watchers(&self, selector: &BulkSelector) -> Result<Value> {
+        self.send_json(Method::POST, "/v1/watchers/stop", selector)
+            .await
+    }
+
+    /// POST /v1/watchers/upgrade
+    pub async fn upgrade_watchers(&self, upgrade: &FleetUpgrade) -> Result<Value> {
+        let body = serde_json::json!({
+            "ids": upgrade.ids,
+            "tag": upgrade.tag,
+            "strategy": {
+                "batch_size": upgrade.batch_size.unwrap_or(1),
+                "pause_on_error": upgrade.pause_on_error.unwrap_or(false),
+            },
+            "restart": upgrade.restart,
+        });
+        self.send_json(Method::POST, "/v1/watchers/upgrade", &body)
+            .await
+    }
+
+    /// GET /v1/watchers/{id}/video-frame, the last frame of the worker as a PNG image.
+    pub async fn video_frame(&self, id: &str) -> Result<Bytes> {
+        let path = format!("/v1/watchers/{}/video-frame", id);
+        Ok(self.send(Method::GET, &path, |r| r).await?.bytes().await?)
+    }
+
+    /// GET /v1/watchers/{id}/preview, the multipart stream of the frames of the worker.
+    pub async fn preview(
+        &self,
+        id: &str,
+        fps: Option<u32>,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        let path = format!("/v1/watchers/{}/preview", id);
+        let response = self
+            .send(Method::GET, &path, |r| r.query(&[("fps", fps)]))
+            .await?;
+        Ok(response.bytes_stream().map_err(Error::from))
+    }
+
+    /// GET /v1/watchers/{id}/metrics, the Prometheus metrics of the worker.
+    pub async fn metrics(&self, id: &str) -> Result<String> {
+        let path = format!("/v1/watchers/{}/metrics", id);
+        Ok(self.send(Method::GET, &path, |r| r).await?.text().await?)
+    }
+
+    /// GET /v1/watchers/{id}/metrics?format=json, the summary of the detection stats.
+    pub async fn metrics_summary(&self, id: &str) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/metrics", id);
+        let response = self
+            .send(Method::GET, &path, |r| r.query(&[("format", "json")]))
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// GET /v1/watchers/{id}/state
+    pub async fn state(&self, id: &str) -> Result<Value> {
+        self.get(&format!("/v1/watchers/{}/state", id)).await
+    }
+
+    /// POST /v1/watchers/{id}/trigger
+    pub async fn trigger_transition(&self, id: &str, trigger: &TransitionTrigger) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/trigger", id);
+        self.send_json(Method::POST, &path, trigger).await
+    }
+
+    /// GET /v1/watchers/{id}/actions/history
+    pub async fn action_history(&self, id: &str) -> Result<Value> {
+        self.get(&format!("/v1/watchers/{}/actions/history", id))
+            .await
+    }
+
+    /// POST /v1/watchers/{id}/actions/{index}/replay
+    pub async fn replay_action(&self, id: &str, index: u64) -> Result<Value> {
+        self.post(&format!("/v1/watchers/{}/actions/{}/replay", id, index))
+            .await
+    }
+
+    /// GET /v1/watchers/{id}/logs
+    pub async fn logs(&self, id: &str, options: &LogOptions) -> Result<String> {
+        let path = format!("/v1/watchers/{}/logs", id);
+        let response = self.send(Method::GET, &path, |r| r.query(options)).await?;
+        Ok(response.text().await?)
+    }
+
+    /// GET /v1/watchers/{id}/logs?follow=true, the new lines being streamed as they are written.
+    pub async fn follow_logs(
+        &self,
+        id: &str,
+        options: &LogOptions,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        let path = format!("/v1/watchers/{}/logs", id);
+        let response = self
+            .send(Method::GET, &path, |r| {
+                r.query(options).query(&[("follow", true)])
+            })
+            .await?;
+        Ok(response.bytes_stream().map_err(Error::from))
+    }
+
+    /// GET /v1/watchers/{id}/diff
+    pub async fn diff_watcher(&self, id: &str) -> Result<Value> {
+        self.get(&format!("/v1/watchers/{}/diff", id)).await
+    }
+
+    /// GET /v1/watchers/{id}/audit
+    pub async fn audit(&self, id: &str) -> Result<Value> {
+        self.get(&format!("/v1/watchers/{}/audit", id)).await
+    }
+
+    /// GET /v1/watchers/{id}/revisions
+    pub async fn revisions(&self, id: &str) -> Result<Value> {
+        self.get(&format!("/v1/watchers/{}/revisions", id)).await
+    }
+
+    /// POST /v1/watchers/{id}/rollback/{rev}
+    pub async fn rollback_watcher(&self, id: &str, revision: u64) -> Result<Watcher> {
+        self.post(&format!("/v1/watchers/{}/rollback/{}", id, revision))
+            .await
+    }
+
+    /// POST /v1/watchers/{id}/heartbeat
+    pub async fn send_heartbeat(&self, id: &str, heartbeat: &Heartbeat) -> Result<Heartbeat> {
+        let path = format!("/v1/watchers/{}/heartbeat", id);
+        self.send_json(Method::POST, &path, heartbeat).await
+    }
+
+    /// POST /v1/watchers/{id}/events
+    pub async fn report_events(&self, id: &str, events: &[WatcherEvent]) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/events", id);
+        self.send_json(Method::POST, &path, events).await
+    }
+
+    /// GET /v1/watchers/{id}/events
+    pub async fn events(&self, id: &str, options: &EventOptions) -> Result<Vec<WatcherEvent>> {
+        let path = format!("/v1/watchers/{}/events", id);
+        let response = self.send(Method::GET, &path, |r| r.query(options)).await?;
+        Ok(response.json().await?)
+    }
+
+    /// GET /v1/watchers/{id}/analytics, for the days from `from` to `to`, as `YYYY-MM-DD`.
+    pub async fn analytics(&self, id: &str, from: Option<&str>, to: Option<&str>) -> Result<Value> {
+        let path = format!("/v1/watchers/{}/analytics", id);
+        let response = self
+            .send(Method::GET, &path, |r| {
+                r.query(&[("from", from), ("to", to)])
+            })
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// POST /v1/slates, returning the description of the slate with its `slate://` URL.
+    pub async fn upload_slate(
+        &self,
+        image: Vec<u8>,
+        content_type: &str,
+        description: Option<&str>,
+    ) -> Result<Value> {
+        // Checked once, so the parts of the attempts are built without errors
+        Part::bytes(Vec::new()).mime_str(content_type)?;
+        let response = self
+            .send(Method::POST, "/v1/slates", |r| {
+                let file = Part::bytes(image.clone())
+                    .file_name("slate")
+                    .mime_str(content_type)
+                    .expect("Checked content type");
+                let mut form = Form::new().part("file", file);
+                if let Some(description) = description {
+                    form = form.text("description", description.to_string());
+                }
+                r.multipart(form)
+            })
+            .await?;
+        Ok(response.json().await?)
+    }
+
+    /// GET /v1/slates
+    pub async fn list_slates(&self) -> Result<Value> {
+        self.get("/v1/slates").await
+    }
+
+    /// GET /v1/slates/{id}/image
+    pub async fn slate_image(&self, id: &str) -> Result<Bytes> {
+        let path = format!("/v1/slates/{}/image", id);
+        Ok(self.send(Method::GET, &path, |r| r).await?.bytes().await?)
+    }
+
+    /// DELETE /v1/slates/{id}
+    pub async fn delete_slate(&self, id: &str) -> Result<()> {
+        self.send(Method::DELETE, &format!("/v1/slates/{}", id), |r| r)
+            .await?;
+        Ok(())
+    }
+
+    /// POST /v1/auth/token, authenticating the requests of the client with the token of a
+    /// service registered with the OIDC provider of the API. The token is not renewed, log in
+    /// again once it expires.
+    pub async fn login_client_credentials(
+        mut self,
+        client_id: &str,
+        client_secret: &str,
+        scope: Option<&str>,
+    ) -> Result<Self> {
+        let credentials = serde_json::json!({
+            "client_id": client_id,
+            "client_secret": client_secret,
+            "scope": scope,
+        });
+        let tokens: Value = self
+            .send_json(Method::POST, "/v1/auth/token", &credentials)
+            .await?;
+        self.token = tokens["access_token"].as_str().map(String::from);
+        Ok(self)
+    }
+
+    /// GET /v1/openapi.json
+    pub async fn openapi_spec(&self) -> Result<Value> {
+        self.get("/v1/openapi.json").await
+    }
+
+    /// GET /readyz, failing while the API cannot reach its backend.
+    pub async fn readyz(&self) -> Result<()> {
+        self.send(Method::GET, "/readyz", |r| r).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::mock;
+
+    #[tokio::test]
+    async fn retries_the_unavailable_api() {
+        let unavailable = mock("GET", "/v1/watchers/abc/state")
+            .with_status(503)
+            .with_body(r#"{"message": "Try again"}"#)
+            .expect(2)
+            .create();
+        let client = Client::new(mockito::server_url())
+            .with_token("secret")
+            .with_retry(RetryPolicy {
+                max_attempts: Some(2),
+                backoff_ms: Some(1),
+                ..RetryPolicy::default()
+            });
+        match client.state("abc").await {
+            Err(Error::Api { status, message }) => {
+                assert_eq!(status.as_u16(), 503);
+                assert_eq!(message, "Try again");
+            }
+            other => panic!("Unexpected reply {:?}", other),
+        }
+        unavailable.assert();
+
+        let not_found = mock("POST", "/v1/watchers/abc/start")
+            .match_header("authorization", "Bearer secret")
+            .with_status(404)
+            .with_body(r#"{"message": "Watcher does not exist"}"#)
+            .expect(1)
+            .create();
+        assert!(client
+            .start_watcher("abc")
+            .await
+            .unwrap_err()
+            .is_not_found());
+        not_found.assert();
+    }
+
+    #[tokio::test]
+    async fn gets_the_watcher_by_name() {
+        let found = mock("GET", "/v1/watchers/by-name/lyon-bayern")
+            .with_status(200)
+            .with_body(include_str!("../../fixtures/watcher.json"))
+            .expect(1)
+            .create();
+        let client = Client::new(mockito::server_url());
+        let watcher = client.get_watcher_by_name("lyon-bayern").await.unwrap();
+        assert_eq!(watcher.name.as_deref(), Some("lyon-bayern"));
+        found.assert();
+
+        let not_found = mock("GET", "/v1/watchers/by-name/channel%201")
+            .with_status(404)
+            .with_body(r#"{"message": "Watcher does not exist"}"#)
+            .expect(1)
+            .create();
+        assert!(client
+            .get_watcher_by_name("channel 1")
+            .await
+            .unwrap_err()
+            .is_not_found());
+        not_found.assert();
+    }
+}
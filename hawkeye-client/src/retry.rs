@@ -0,0 +1,88 @@
+use hawkeye_core::models::RetryPolicy;
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, Response, StatusCode};
+use std::time::Duration;
+
+/// Retries of the requests when none is set with `Client::with_retry`: 3 retries, waiting 200ms
+/// before the first one, up to 5s.
+pub fn default_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: Some(4),
+        backoff_ms: Some(200),
+        max_backoff_ms: Some(5000),
+        jitter: Some(0.2),
+        ..RetryPolicy::default()
+    }
+}
+
+/// Whether the request can be sent again after this error: the connection errors happen before
+/// the API receives it, the timeouts only for the requests that can be applied twice.
+pub fn is_retryable_error(method: &Method, error: &reqwest::Error) -> bool {
+    error.is_connect() || (error.is_timeout() && is_idempotent(method))
+}
+
+/// Whether the request can be sent again after this reply: the rate limited requests were not
+/// handled, the ones of an unavailable API only when they can be applied twice.
+pub fn is_retryable_status(method: &Method, status: StatusCode) -> bool {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            is_idempotent(method)
+        }
+        _ => false,
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    *method != Method::POST && *method != Method::PATCH
+}
+
+/// Wait before the retry, the first one being `1`: the `Retry-After` of the reply when set, the
+/// backoff of the policy with a random part of its jitter otherwise.
+pub fn backoff(policy: &RetryPolicy, retry: u32, response: Option<&Response>) -> Duration {
+    let retry_after = response
+        .and_then(|response| response.headers().get(RETRY_AFTER))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs);
+    if let Some(retry_after) = retry_after {
+        return retry_after;
+    }
+    let backoff_ms = policy.backoff_ms(retry) as f64;
+    let jitter = match policy.jitter {
+        Some(jitter) if jitter > 0.0 => rand::thread_rng().gen_range(-jitter..=jitter),
+        _ => 0.0,
+    };
+    Duration::from_millis((backoff_ms * (1.0 + jitter)).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_the_requests_not_applied() {
+        assert!(is_retryable_status(
+            &Method::POST,
+            StatusCode::TOO_MANY_REQUESTS
+        ));
+        assert!(is_retryable_status(
+            &Method::GET,
+            StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(!is_retryable_status(
+            &Method::POST,
+            StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert!(!is_retryable_status(&Method::GET, StatusCode::NOT_FOUND));
+
+        let policy = RetryPolicy {
+            jitter: None,
+            ..default_policy()
+        };
+        assert_eq!(backoff(&policy, 1, None), Duration::from_millis(200));
+        assert_eq!(backoff(&policy, 3, None), Duration::from_millis(800));
+        assert_eq!(backoff(&policy, 10, None), Duration::from_secs(5));
+    }
+}
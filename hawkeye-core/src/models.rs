@@ -1,110 +1,2838 @@
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
 use color_eyre::{eyre::eyre, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::collections::HashMap;
+use std::str::FromStr;
+
+/// ID of the slate of `Watcher::slate_url`.
+pub const DEFAULT_SLATE_ID: &str = "default";
+
+/// Highest dissimilarity (DSSIM) of a frame matching a slate without a `threshold`.
+pub const DEFAULT_SLATE_THRESHOLD: f64 = 0.9;
+
+/// Highest share of the bits of the perceptual hashes differing between a frame matching a slate
+/// and the slate, for slates without a `threshold`.
+pub const DEFAULT_HASH_THRESHOLD: f64 = 0.1;
+
+/// Highest `(1 - SSIM) / 2` or `(1 - NCC) / 2` of a frame matching a slate without a `threshold`.
+pub const DEFAULT_CORRELATION_THRESHOLD: f64 = 0.1;
+
+/// Highest share of the characters of a text pattern differing from the text read on a frame
+/// matching an `ocr` slate without a `threshold`.
+pub const DEFAULT_TEXT_THRESHOLD: f64 = 0.2;
+
+/// IDs of the black and freeze triggers in the actions and the metrics, reserved like the ID of
+/// the default slate.
+pub const BLACK_TRIGGER_ID: &str = "black";
+pub const FREEZE_TRIGGER_ID: &str = "freeze";
+pub const FAILOVER_TRIGGER_ID: &str = "failover";
+pub const STREAM_LOSS_TRIGGER_ID: &str = "stream_loss";
+
+/// Seconds the frames must stay black or frozen before a trigger without `duration` fires.
+pub const DEFAULT_TRIGGER_SECONDS: f64 = 2.0;
+/// Frames analyzed per second, when missing in the `Analysis` of the watcher.
+pub const DEFAULT_ANALYSIS_FPS: f64 = 10.0;
+/// Highest number of frames analyzed per second.
+pub const MAX_ANALYSIS_FPS: f64 = 30.0;
+/// Seconds without frames before failing over to the next source, longer than the triggers to
+/// let the pipelines start.
+pub const DEFAULT_FAILOVER_SECONDS: f64 = 5.0;
+
+/// Highest mean difference of the luma of consecutive frozen frames, for a freeze trigger without
+/// `threshold`.
+pub const DEFAULT_FREEZE_THRESHOLD: f64 = 0.005;
+
+/// Milliseconds before the actions of a transition without `cooldown_ms` can run again.
+pub const DEFAULT_COOLDOWN_MS: u64 = 5000;
+
+/// Loudness, in dBFS, under which the audio of a transition condition without
+/// `silence_threshold` is silent.
+pub const DEFAULT_SILENCE_DBFS: f64 = -50.0;
+
+/// Seconds a worker samples the similarities of the frames when calibrating the slates.
+pub const DEFAULT_CALIBRATION_SECONDS: u64 = 60;
+
+/// Longest calibration of the slates, in seconds.
+pub const MAX_CALIBRATION_SECONDS: u64 = 600;
+
+/// Seconds of the incoming video a worker records in a clip.
+pub const DEFAULT_RECORDING_SECONDS: u64 = 30;
+
+/// Longest clip of the incoming video, in seconds. The worker holds the clip in memory until it
+/// is uploaded.
+pub const MAX_RECORDING_SECONDS: u64 = 120;
+
+/// Milliseconds before the first retry of an action without `backoff_ms`.
+pub const DEFAULT_RETRY_BACKOFF_MS: u64 = 500;
+
+/// Longest wait between two attempts of an action without `max_backoff_ms`, in milliseconds.
+pub const DEFAULT_MAX_BACKOFF_MS: u64 = 30_000;
+
+/// Seconds a command run by an `exec` action without `timeout` can last.
+pub const DEFAULT_EXEC_TIMEOUT: u32 = 10;
+
+/// Longest timeout of the commands run by the `exec` actions, in seconds.
+pub const MAX_EXEC_TIMEOUT: u32 = 300;
+
+/// Port of the RTMP server running next to the worker, receiving the streams of the `rtmp`
+/// sources.
+pub const RTMP_SERVER_PORT: u32 = 1935;
+
+/// Port of the worker running the watchers of a pack, serving the metrics of all of them.
+pub const PACK_METRICS_PORT: u32 = 9102;
+
+/// Bitrate assumed for the sources not setting theirs, in kbps, the one of an SD channel.
+pub const DEFAULT_BITRATE_KBPS: u32 = 2_500;
+
+/// Most seconds of thumbnails archived with the frame of a transition, the worker keeping one
+/// thumbnail per second for a minute.
+pub const MAX_ARCHIVE_THUMBNAIL_SECONDS: u32 = 60;
+
+/// Most seconds of video kept on each side of a transition for its clip, the worker holding them
+/// in memory.
+pub const MAX_CAPTURE_SECONDS: u32 = 60;
+
+/// Seconds of video recorded after a transition for its clip, when the capture does not set them.
+pub const DEFAULT_CAPTURE_AFTER_SECONDS: u32 = 10;
+
+/// Seconds between the heartbeats of a worker.
+pub const HEARTBEAT_INTERVAL_SECONDS: u64 = 30;
+
+/// Seconds without a heartbeat after which a running watcher is reported as stale.
+pub const HEARTBEAT_STALE_SECONDS: i64 = 90;
+
+/// Where slates can be loaded from, `slate://<id>` being a slate of the library of the API.
+const SLATE_URL_SCHEMES: &[&str] = &["http://", "https://", "file://", "slate://"];
+
+/// Buckets the frames of the transitions can be archived to, on S3 or GCS.
+const ARCHIVE_URL_SCHEMES: &[&str] = &["s3://", "gs://"];
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
 pub struct Watcher {
     pub id: Option<String>,
+    /// Unique, human readable name of the watcher, which `POST /v1/watchers/import` creates or
+    /// updates the watcher by. The API requires it, the watchers created before it may not have
+    /// one.
+    pub name: Option<String>,
     pub description: Option<String>,
     pub slate_url: String,
+    /// Other slates the frames are compared with, besides the one of `slate_url`.
+    pub slates: Option<Vec<Slate>>,
     pub status: Option<Status>,
     pub status_description: Option<String>,
     pub source: Source,
+    /// Sources the worker switches to, in order, when the frames of the current source stop,
+    /// see `failover`.
+    pub backup_sources: Option<Vec<Source>>,
     pub transitions: Vec<Transition>,
+    pub tags: Option<HashMap<String, String>>,
+    pub resources: Option<Resources>,
+    /// Rate and resolution of the frames analyzed, lower ones using less CPU.
+    pub analysis: Option<Analysis>,
+    pub scheduling: Option<Scheduling>,
+    /// Image of the hawkeye-worker running this watcher, the image configured in the API is used
+    /// when missing.
+    pub worker_image: Option<String>,
+    /// Set by the API while the watcher is suspended, suspended watchers cannot be started.
+    pub suspended: Option<bool>,
+    /// Set by the API when the watcher is deleted, as an RFC 3339 timestamp. Deleted watchers are
+    /// stopped and can be restored until they are purged.
+    pub deleted_at: Option<String>,
+    pub schedule: Option<Schedule>,
+    /// Kubernetes namespace of the watcher, it cannot be changed once created.
+    pub namespace: Option<String>,
+    /// Runs the watcher in a worker shared with other packed watchers, for low-bitrate channels
+    /// like SD ones. It cannot be changed once created.
+    pub packed: Option<bool>,
+    /// Set by the API, the pack of watchers whose worker runs this packed watcher.
+    pub pack: Option<String>,
+    /// Transitions between the content and sustained black frames.
+    pub black: Option<Trigger>,
+    /// Transitions between the content and frozen video.
+    pub freeze: Option<Trigger>,
+    /// Seconds without frames before switching to the next of the `backup_sources`, and the
+    /// transitions between the primary source, `content`, and the `backup` ones.
+    pub failover: Option<Trigger>,
+    /// Transitions between the content and the stream lost, without frames, from `content` to
+    /// `lost` when the stream is lost and back when it recovers.
+    pub stream_loss: Option<Trigger>,
+    /// States the stream goes through, running actions when entering and leaving them, besides
+    /// the transitions between the video modes.
+    pub state_machine: Option<StateMachine>,
+    /// Whether the worker executes the actions, `active` when missing.
+    pub mode: Option<WatcherMode>,
+    /// Bucket the frames are uploaded to when a transition fires, for the review of incidents.
+    pub archive: Option<FrameArchive>,
+    /// Clips of the video around the transitions, uploaded to the `archive`.
+    pub capture: Option<ClipCapture>,
+    /// Set by the API, the last heartbeat sent by the worker.
+    pub last_heartbeat: Option<Heartbeat>,
+    /// Set by the API when the watcher is running but its worker stopped sending heartbeats for
+    /// `HEARTBEAT_STALE_SECONDS`, like when its pipeline is stuck.
+    pub stale: Option<bool>,
+}
+
+impl Watcher {
+    pub fn is_valid(&self) -> Result<()> {
+        self.validate().map_err(|errors| eyre!("{}", errors))
+    }
+
+    /// Checks the whole `Watcher` definition, collecting every problem found instead of
+    /// stopping at the first one.
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        if let Some(name) = self.name.as_deref() {
+            if name.is_empty() || !is_valid_label(name) {
+                errors.add(
+                    "name",
+                    "The name must be up to 63 letters, digits, '-', '_' or '.', starting and ending with a letter or digit",
+                );
+            }
+        }
+
+        if !is_valid_url(&self.slate_url, SLATE_URL_SCHEMES) {
+            errors.add(
+                "slate_url",
+                format!("{} not recognized as a valid URL!", self.slate_url),
+            );
+        }
+
+        self.source.validate("source", &mut errors);
+        self.validate_backup_sources(&mut errors);
+
+        // The actions of a watcher with a state machine can all be the ones of its states
+        if self.state_machine.is_none() || !self.transitions.is_empty() {
+            validate_transitions("transitions", &self.transitions, SLATE_MODES, &mut errors);
+        }
+
+        for (i, slate) in self.slates.iter().flatten().enumerate() {
+            let field = format!("slates[{}]", i);
+            if !is_valid_label(&slate.id) || slate.id.is_empty() {
+                errors.add(format!("{}.id", field), "Invalid slate ID");
+            } else if [
+                DEFAULT_SLATE_ID,
+                BLACK_TRIGGER_ID,
+                FREEZE_TRIGGER_ID,
+                FAILOVER_TRIGGER_ID,
+                STREAM_LOSS_TRIGGER_ID,
+            ]
+            .contains(&slate.id.as_str())
+                || self
+                    .slates
+                    .iter()
+                    .flatten()
+                    .take(i)
+                    .any(|s| s.id == slate.id)
+            {
+                errors.add(
+                    format!("{}.id", field),
+                    format!("Slate ID {} is already used", slate.id),
+                );
+            }
+            if !is_valid_url(&slate.url, SLATE_URL_SCHEMES) {
+                errors.add(
+                    format!("{}.url", field),
+                    format!("{} not recognized as a valid URL!", slate.url),
+                );
+            }
+            if let Some(threshold) = slate.threshold {
+                if !threshold.is_finite() || threshold < 0.0 {
+                    errors.add(
+                        format!("{}.threshold", field),
+                        "Threshold must be a positive number",
+                    );
+                } else if slate.detector().is_normalized() && threshold > 1.0 {
+                    errors.add(
+                        format!("{}.threshold", field),
+                        "Threshold of this detector must be at most 1",
+                    );
+                }
+            }
+            if let Some(region) = slate.region.as_ref() {
+                region.validate(&format!("{}.region", field), &mut errors);
+            }
+            if let Some(text) = slate.text.as_ref() {
+                if slate.detector() != Detector::Ocr {
+                    errors.add(
+                        format!("{}.text", field),
+                        "Text is only matched by the ocr detector",
+                    );
+                } else if text.is_empty() || text.iter().any(|pattern| pattern.trim().is_empty()) {
+                    errors.add(format!("{}.text", field), "Text patterns cannot be empty");
+                }
+            }
+            if let Some(transitions) = slate.transitions.as_ref() {
+                validate_transitions(
+                    &format!("{}.transitions", field),
+                    transitions,
+                    SLATE_MODES,
+                    &mut errors,
+                );
+            }
+        }
+
+        if let Some(black) = self.black.as_ref() {
+            black.validate("black", VideoMode::Black, &mut errors);
+        }
+        if let Some(freeze) = self.freeze.as_ref() {
+            freeze.validate("freeze", VideoMode::Frozen, &mut errors);
+        }
+        if let Some(failover) = self.failover.as_ref() {
+            failover.validate("failover", VideoMode::Backup, &mut errors);
+        }
+        if let Some(stream_loss) = self.stream_loss.as_ref() {
+            stream_loss.validate("stream_loss", VideoMode::Lost, &mut errors);
+        }
+        if let Some(machine) = self.state_machine.as_ref() {
+            machine.validate("state_machine", &mut errors);
+        }
+
+        for (tag, value) in self.tags.iter().flatten() {
+            if !is_valid_label(tag) || tag.is_empty() {
+                errors.add(format!("tags.{}", tag), "Invalid tag name");
+            } else if !is_valid_label(value) {
+                errors.add(format!("tags.{}", tag), "Invalid tag value");
+            }
+        }
+
+        if let Some(resources) = self.resources.as_ref() {
+            resources.validate(&mut errors);
+        }
+        if let Some(analysis) = self.analysis.as_ref() {
+            analysis.validate(&mut errors);
+        }
+        if let Some(gpus) = self.resources.as_ref().and_then(|r| r.gpus) {
+            if gpus == 0 {
+                errors.add("resources.gpus", "At least 1 GPU must be requested");
+            } else if !self.decoder().is_gpu() {
+                errors.add(
+                    "resources.gpus",
+                    "GPUs are only requested for the vaapi and nvdec decoders",
+                );
+            }
+        }
+        if let Some(scheduling) = self.scheduling.as_ref() {
+            scheduling.validate(&mut errors);
+        }
+        if let Some(archive) = self.archive.as_ref() {
+            archive.validate(&mut errors);
+        }
+        if let Some(capture) = self.capture.as_ref() {
+            capture.validate(&mut errors);
+            if self.archive.is_none() {
+                errors.add(
+                    "capture",
+                    "Clips are uploaded to the archive, which is missing",
+                );
+            }
+            // The video of the pulled sources is decoded right away, the worker cannot keep it
+            if self
+                .sources()
+                .iter()
+                .any(|source| source.pull_url().is_some())
+            {
+                errors.add(
+                    "capture",
+                    "Clips cannot be recorded from the sources pulled over HLS or DASH",
+                );
+            }
+        }
+        if self.is_packed() {
+            self.validate_packed(&mut errors);
+        }
+        if let Some(schedule) = self.schedule.as_ref() {
+            if schedule.start.is_none() && schedule.stop.is_none() {
+                errors.add("schedule", "At least one of start or stop must be defined");
+            }
+        }
+        if let Some(image) = self.worker_image.as_deref() {
+            if image.trim().is_empty() || image.contains(char::is_whitespace) {
+                errors.add("worker_image", "Invalid image name");
+            }
+        }
+
+        errors.into_result()
+    }
+
+    /// All the slates the frames are compared with, starting with the one of `slate_url`, each
+    /// with the transitions it triggers.
+    pub fn all_slates(&self) -> Vec<Slate> {
+        let default = Slate {
+            id: DEFAULT_SLATE_ID.to_string(),
+            url: self.slate_url.clone(),
+            threshold: None,
+            detector: None,
+            region: None,
+            text: None,
+            transitions: Some(self.transitions.clone()),
+        };
+        let others = self.slates.iter().flatten().map(|slate| Slate {
+            transitions: Some(
+                slate
+                    .transitions
+                    .clone()
+                    .unwrap_or_else(|| self.transitions.clone()),
+            ),
+            ..slate.clone()
+        });
+        std::iter::once(default).chain(others).collect()
+    }
+
+    /// Every action of the watcher, with the field of its definition.
+    pub fn actions(&self) -> Vec<(String, &Action)> {
+        let mut actions = Vec::new();
+        transition_actions("transitions", &self.transitions, &mut actions);
+        for (i, slate) in self.slates.iter().flatten().enumerate() {
+            if let Some(transitions) = slate.transitions.as_ref() {
+                let field = format!("slates[{}].transitions", i);
+                transition_actions(&field, transitions, &mut actions);
+            }
+        }
+        for (id, trigger) in self.triggers() {
+            let field = format!("{}.transitions", id);
+            transition_actions(&field, &trigger.transitions, &mut actions);
+        }
+        for (i, state) in self
+            .state_machine
+            .iter()
+            .flat_map(|m| &m.states)
+            .enumerate()
+        {
+            let field = format!("state_machine.states[{}]", i);
+            for (name, state_actions) in
+                [("on_enter", &state.on_enter), ("on_exit", &state.on_exit)]
+            {
+                for (j, action) in state_actions.iter().enumerate() {
+                    actions.push((format!("{}.{}[{}]", field, name, j), action));
+                }
+            }
+            for (j, transition) in state.transitions.iter().enumerate() {
+                for (k, action) in transition.actions.iter().enumerate() {
+                    let field = format!("{}.transitions[{}].actions[{}]", field, j, k);
+                    actions.push((field, action));
+                }
+            }
+        }
+        actions
+    }
+
+    /// Names of the `Secret`s referenced by the HTTP calls of the watcher, sorted.
+    pub fn secret_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .actions()
+            .into_iter()
+            .flat_map(|(_, action)| match action {
+                Action::HttpCall(call)
+                | Action::Scte35(Scte35Signal {
+                    destination: Scte35Destination::Http(call),
+                    ..
+                }) => call.secret_names(),
+                _ => Vec::new(),
+            })
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// The black, freeze, failover and stream loss triggers of the watcher, with their IDs.
+    pub fn triggers(&self) -> Vec<(&'static str, &Trigger)> {
+        let black = self.black.as_ref().map(|black| (BLACK_TRIGGER_ID, black));
+        let freeze = self
+            .freeze
+            .as_ref()
+            .map(|freeze| (FREEZE_TRIGGER_ID, freeze));
+        let failover = self
+            .failover
+            .as_ref()
+            .map(|failover| (FAILOVER_TRIGGER_ID, failover));
+        let stream_loss = self
+            .stream_loss
+            .as_ref()
+            .map(|stream_loss| (STREAM_LOSS_TRIGGER_ID, stream_loss));
+        black
+            .into_iter()
+            .chain(freeze)
+            .chain(failover)
+            .chain(stream_loss)
+            .collect()
+    }
+
+    pub fn is_packed(&self) -> bool {
+        self.packed.unwrap_or_default()
+    }
+
+    /// Bitrate of the source and the backup sources received by the worker, in kbps.
+    pub fn bitrate_kbps(&self) -> u32 {
+        self.sources()
+            .iter()
+            .map(|source| source.bitrate_kbps.unwrap_or(DEFAULT_BITRATE_KBPS))
+            .sum()
+    }
+
+    /// The worker of a pack runs the watchers side by side with the same resources, scheduling
+    /// and image, and without the RTMP server or the network of the node of some sources.
+    fn validate_packed(&self, errors: &mut ValidationErrors) {
+        let sources = self.sources();
+        if sources
+            .iter()
+            .any(|source| matches!(source.transport, Protocol::Rtmp { .. }))
+        {
+            errors.add("packed", "RTMP sources cannot be packed");
+        }
+        if sources.iter().any(|source| source.multicast.is_some()) {
+            errors.add("packed", "Multicast sources cannot be packed");
+        }
+        if self.resources.is_some() {
+            errors.add("packed", "Packed watchers use the resources of their pack");
+        }
+        if self.scheduling.is_some() {
+            errors.add("packed", "Packed watchers use the scheduling of their pack");
+        }
+        if self.worker_image.is_some() {
+            errors.add("packed", "Packed watchers run the image of the API");
+        }
+    }
+
+    /// Decoder of the H.264 frames of the sources.
+    pub fn decoder(&self) -> Decoder {
+        self.analysis
+            .as_ref()
+            .and_then(|analysis| analysis.decoder)
+            .unwrap_or_default()
+    }
+
+    /// Seconds without frames before the worker fails over to the next source.
+    pub fn failover_seconds(&self) -> f64 {
+        self.failover
+            .as_ref()
+            .and_then(|failover| failover.duration)
+            .unwrap_or(DEFAULT_FAILOVER_SECONDS)
+    }
+
+    /// The source, then the backup sources in the order the worker fails over to them.
+    pub fn sources(&self) -> Vec<&Source> {
+        std::iter::once(&self.source)
+            .chain(self.backup_sources.iter().flatten())
+            .collect()
+    }
+
+    /// The ports of the worker receiving the source and the backup sources.
+    pub fn ports(&self) -> Vec<u32> {
+        self.sources()
+            .into_iter()
+            .flat_map(|source| source.ports())
+            .collect()
+    }
+
+    /// The backup sources are received by the same worker, on their own ingest ports, with the
+    /// network and the load balancer of the source.
+    fn validate_backup_sources(&self, errors: &mut ValidationErrors) {
+        for (i, backup) in self.backup_sources.iter().flatten().enumerate() {
+            let field = format!("backup_sources[{}]", i);
+            backup.validate(&field, errors);
+            if backup.ingest_port.is_some() && backup.ingest_port == self.source.ingest_port {
+                errors.add(
+                    format!("{}.ingest_port", field),
+                    "Backup sources need an ingest port of their own",
+                );
+            }
+            if let Protocol::Rtmp { .. } = backup.transport {
+                errors.add(
+                    format!("{}.transport", field),
+                    "Only the source can be received with RTMP",
+                );
+            }
+            if backup.multicast.is_some() {
+                errors.add(
+                    format!("{}.multicast", field),
+                    "Only the source can be multicast",
+                );
+            }
+            if backup.is_pushed() && !self.source.is_pushed() {
+                errors.add(
+                    format!("{}.transport", field),
+                    "Backup sources sent to the worker need a source sent to it too",
+                );
+            }
+        }
+        // The ports shared within a source are reported by its own validation
+        let ports: Vec<Vec<u32>> = self.sources().iter().map(|source| source.ports()).collect();
+        if (1..ports.len()).any(|i| {
+            ports[i]
+                .iter()
+                .any(|port| ports[..i].iter().flatten().any(|p| p == port))
+        }) {
+            errors.add(
+                "backup_sources",
+                "The sources cannot share their ingest port, or another of their ports",
+            );
+        }
+        if self.failover.is_some() && self.backup_sources.iter().flatten().next().is_none() {
+            errors.add("failover", "Failing over needs backup sources");
+        }
+    }
+
+    /// Whether the worker must restart to apply the changes from the `previous` definition. The
+    /// slates and the transitions are reloaded by the running worker, and the name, the
+    /// description and the tags are not used by it. The `Secret`s of the actions are mounted in
+    /// the pod of the worker, so referencing other ones needs a restart.
+    pub fn requires_restart(&self, previous: &Watcher) -> bool {
+        let reloaded = Watcher {
+            name: previous.name.clone(),
+            description: previous.description.clone(),
+            mode: previous.mode,
+            slate_url: previous.slate_url.clone(),
+            slates: previous.slates.clone(),
+            transitions: previous.transitions.clone(),
+            tags: previous.tags.clone(),
+            ..self.clone()
+        };
+        reloaded != *previous || self.secret_names() != previous.secret_names()
+    }
+}
+
+fn transition_actions<'a>(
+    field: &str,
+    transitions: &'a [Transition],
+    actions: &mut Vec<(String, &'a Action)>,
+) {
+    for (i, transition) in transitions.iter().enumerate() {
+        for (j, action) in transition.actions.iter().enumerate() {
+            actions.push((format!("{}[{}].actions[{}]", field, i, j), action));
+        }
+    }
+}
+
+/// Video modes of the transitions of the slates.
+const SLATE_MODES: &[VideoMode] = &[VideoMode::Content, VideoMode::Slate];
+
+fn validate_transitions(
+    field: &str,
+    transitions: &[Transition],
+    modes: &[VideoMode],
+    errors: &mut ValidationErrors,
+) {
+    if transitions.is_empty() {
+        errors.add(field, "At least one transition must be defined");
+    }
+    for (i, transition) in transitions.iter().enumerate() {
+        let field = format!("{}[{}]", field, i);
+        if transition.from == transition.to {
+            errors.add(&field, "Transition must be between different video modes");
+        } else if !modes.contains(&transition.from) || !modes.contains(&transition.to) {
+            errors.add(
+                &field,
+                format!(
+                    "Transition must be between the {} video modes",
+                    modes
+                        .iter()
+                        .map(|mode| mode.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" and ")
+                ),
+            );
+        }
+        if transitions[..i]
+            .iter()
+            .any(|t| t.from == transition.from && t.to == transition.to)
+        {
+            errors.add(
+                &field,
+                "Overlaps with a previous transition between the same video modes",
+            );
+        }
+        for (j, action) in transition.actions.iter().enumerate() {
+            action.validate(&format!("{}.actions[{}]", field, j), errors);
+        }
+        if let Some(audio) = &transition.audio {
+            audio.validate(&format!("{}.audio", field), errors);
+        }
+        if let Some(condition) = &transition.condition {
+            if transition.audio.is_some() {
+                errors.add(
+                    format!("{}.audio", field),
+                    "The audio of a transition with a condition is part of the condition",
+                );
+            }
+            condition.validate(&format!("{}.condition", field), errors);
+        }
+        if let Some(windows) = &transition.active {
+            if windows.is_empty() {
+                errors.add(
+                    format!("{}.active", field),
+                    "At least one window must be defined",
+                );
+            }
+            for (j, window) in windows.iter().enumerate() {
+                window.validate(&format!("{}.active[{}]", field, j), errors);
+            }
+        }
+        if let Some(hysteresis) = &transition.hysteresis {
+            hysteresis.validate(&format!("{}.hysteresis", field), errors);
+        }
+    }
+}
+
+/// A problem found in a specific field of a `Watcher` definition.
+#[derive(Serialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// All the problems found while validating a `Watcher` definition.
+#[derive(Serialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn add<F: Into<String>, M: Into<String>>(&mut self, field: F, message: M) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<String> = self
+            .errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect();
+        write!(f, "{}", messages.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+fn is_valid_url(url: &str, schemes: &[&str]) -> bool {
+    schemes.iter().any(|scheme| {
+        url.strip_prefix(scheme)
+            .map(|rest| !rest.is_empty() && !rest.contains(char::is_whitespace))
+            .unwrap_or(false)
+    })
+}
+
+/// Tags are stored as Kubernetes labels, so they must follow the same syntax.
+fn is_valid_label(value: &str) -> bool {
+    value.len() <= 63
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && value
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_alphanumeric())
+            .unwrap_or(true)
+        && value
+            .chars()
+            .last()
+            .map(|c| c.is_ascii_alphanumeric())
+            .unwrap_or(true)
+}
+
+/// Compute resources of the worker running the `Watcher`, the defaults of the API are used for
+/// anything missing.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Resources {
+    pub requests: Option<ResourceQuantities>,
+    pub limits: Option<ResourceQuantities>,
+    /// GPUs of the hardware decoder of the analysis, see `Decoder`.
+    pub gpus: Option<u32>,
+}
+
+/// How the worker samples the decoded frames before comparing them with the slates.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    /// Frames analyzed per second, `DEFAULT_ANALYSIS_FPS` when missing.
+    pub fps: Option<f64>,
+    /// Height, in lines, the larger frames are downscaled to right after being decoded, like
+    /// `480`. The frames are compared with the slates at the size of the slates in any case.
+    pub max_resolution: Option<u32>,
+    /// Decoder of the H.264 frames, `Decoder::Software` when missing.
+    pub decoder: Option<Decoder>,
+}
+
+/// Decoder of the H.264 frames. The hardware decoders are used when the worker finds them, the
+/// worker falls back to the software decoder otherwise.
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Decoder {
+    Software,
+    /// VAAPI, on Intel and AMD GPUs.
+    Vaapi,
+    /// NVDEC, on NVIDIA GPUs.
+    Nvdec,
+    /// NVDEC, then VAAPI, whichever the worker finds first.
+    Auto,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::Software
+    }
+}
+
+impl Decoder {
+    /// Whether the decoder runs on a given kind of GPU, which can be requested for the worker.
+    pub fn is_gpu(&self) -> bool {
+        matches!(self, Decoder::Vaapi | Decoder::Nvdec)
+    }
+}
+
+// The rate is validated to be a finite number
+impl Eq for Analysis {}
+
+/// Archives the frame the worker analyzed when a transition fires, with the thumbnails of the
+/// seconds before it. The URLs of the uploaded images are given to the actions of the transition.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameArchive {
+    /// Bucket and prefix of the images, like `s3://incidents/hawkeye` or `gs://incidents`. GCS is
+    /// reached through its S3 compatible API, with HMAC keys.
+    pub url: String,
+    /// Region of the S3 bucket, the one of the worker when missing.
+    pub region: Option<String>,
+    /// Seconds before the transition whose thumbnails are also uploaded, one per second, none
+    /// when missing.
+    pub thumbnail_seconds: Option<u32>,
+}
+
+impl FrameArchive {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        let has_bucket = ARCHIVE_URL_SCHEMES
+            .iter()
+            .filter_map(|scheme| self.url.strip_prefix(scheme))
+            .any(|rest| !rest.starts_with('/'));
+        if !is_valid_url(&self.url, ARCHIVE_URL_SCHEMES) || !has_bucket {
+            errors.add(
+                "archive.url",
+                format!("{} is not a s3:// or gs:// bucket URL", self.url),
+            );
+        }
+        if let Some(seconds) = self.thumbnail_seconds {
+            if seconds > MAX_ARCHIVE_THUMBNAIL_SECONDS {
+                errors.add(
+                    "archive.thumbnail_seconds",
+                    format!(
+                        "At most {} seconds of thumbnails are kept",
+                        MAX_ARCHIVE_THUMBNAIL_SECONDS
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Keeps the last seconds of the incoming video in the worker, as received, so the clip around a
+/// transition is uploaded to the archive when it fires. Its URL is given to the actions.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClipCapture {
+    /// Seconds of video kept before the transitions.
+    pub buffer_seconds: u32,
+    /// Seconds of video recorded after a transition before uploading its clip, 10 when missing.
+    pub after_seconds: Option<u32>,
+}
+
+impl ClipCapture {
+    pub fn after_seconds(&self) -> u32 {
+        self.after_seconds.unwrap_or(DEFAULT_CAPTURE_AFTER_SECONDS)
+    }
+
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if self.buffer_seconds == 0 || self.buffer_seconds > MAX_CAPTURE_SECONDS {
+            errors.add(
+                "capture.buffer_seconds",
+                format!(
+                    "The buffer must keep between 1 and {} seconds",
+                    MAX_CAPTURE_SECONDS
+                ),
+            );
+        }
+        if self.after_seconds() > MAX_CAPTURE_SECONDS {
+            errors.add(
+                "capture.after_seconds",
+                format!(
+                    "At most {} seconds are recorded after a transition",
+                    MAX_CAPTURE_SECONDS
+                ),
+            );
+        }
+    }
+}
+
+impl Analysis {
+    pub fn fps(&self) -> f64 {
+        self.fps.unwrap_or(DEFAULT_ANALYSIS_FPS)
+    }
+
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if let Some(fps) = self.fps {
+            if !fps.is_finite() || fps <= 0.0 || fps > MAX_ANALYSIS_FPS {
+                errors.add(
+                    "analysis.fps",
+                    format!(
+                        "Frames per second must be above 0 and at most {}",
+                        MAX_ANALYSIS_FPS
+                    ),
+                );
+            }
+        }
+        if let Some(lines) = self.max_resolution {
+            if !(120..=4320).contains(&lines) {
+                errors.add(
+                    "analysis.max_resolution",
+                    "Resolution must be between 120 and 4320 lines",
+                );
+            }
+        }
+    }
+}
+
+/// Quantities in the Kubernetes format, like `500m` CPU or `256Mi` of memory.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResourceQuantities {
+    pub cpu: Option<String>,
+    pub memory: Option<String>,
+}
+
+impl Resources {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        for (kind, quantities) in [("requests", &self.requests), ("limits", &self.limits)].iter() {
+            let values = quantities
+                .iter()
+                .flat_map(|q| vec![("cpu", &q.cpu), ("memory", &q.memory)]);
+            for (name, value) in values {
+                if let Some(value) = value {
+                    if !is_valid_quantity(value) {
+                        errors.add(
+                            format!("resources.{}.{}", kind, name),
+                            format!("{} is not a valid quantity", value),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Constraints on the nodes where the worker of the `Watcher` can run, added to the ones
+/// configured in the API.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Scheduling {
+    /// Labels the node must have.
+    pub node_selector: Option<HashMap<String, String>>,
+    pub tolerations: Option<Vec<Toleration>>,
+    pub affinity: Option<NodeAffinity>,
+}
+
+/// Starts and stops the watcher at fixed times, in the cron format.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Schedule {
+    /// When the watcher is started, e.g. `0 18 * * MON-FRI`.
+    pub start: Option<String>,
+    /// When the watcher is stopped.
+    pub stop: Option<String>,
+    /// IANA name of the time zone of the cron expressions, UTC when missing.
+    pub timezone: Option<String>,
+}
+
+/// Allows the worker to run on nodes with matching taints.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Toleration {
+    pub key: Option<String>,
+    /// `Equal` (default) or `Exists`.
+    pub operator: Option<String>,
+    pub value: Option<String>,
+    /// `NoSchedule`, `PreferNoSchedule` or `NoExecute`, all effects when missing.
+    pub effect: Option<String>,
+    pub toleration_seconds: Option<i64>,
+}
+
+/// Node affinity of the worker, each list of requirements must all match the node labels.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct NodeAffinity {
+    /// The worker only runs on nodes matching these requirements.
+    pub required: Option<Vec<NodeRequirement>>,
+    /// The worker runs on nodes matching these requirements when possible.
+    pub preferred: Option<Vec<NodeRequirement>>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct NodeRequirement {
+    pub key: String,
+    /// `In`, `NotIn`, `Exists`, `DoesNotExist`, `Gt` or `Lt`.
+    pub operator: String,
+    pub values: Option<Vec<String>>,
+}
+
+impl Scheduling {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        for (i, toleration) in self.tolerations.iter().flatten().enumerate() {
+            let field = format!("scheduling.tolerations[{}]", i);
+            if let Some(operator) = toleration.operator.as_deref() {
+                if !["Equal", "Exists"].contains(&operator) {
+                    errors.add(
+                        format!("{}.operator", field),
+                        format!("Unknown operator {}", operator),
+                    );
+                }
+            }
+            if let Some(effect) = toleration.effect.as_deref() {
+                if !["NoSchedule", "PreferNoSchedule", "NoExecute"].contains(&effect) {
+                    errors.add(
+                        format!("{}.effect", field),
+                        format!("Unknown effect {}", effect),
+                    );
+                }
+            }
+        }
+
+        let affinity = self.affinity.as_ref();
+        let requirements = [
+            ("required", affinity.and_then(|a| a.required.as_ref())),
+            ("preferred", affinity.and_then(|a| a.preferred.as_ref())),
+        ];
+        for (kind, requirements) in requirements.iter() {
+            for (i, requirement) in requirements.iter().copied().flatten().enumerate() {
+                let operators = ["In", "NotIn", "Exists", "DoesNotExist", "Gt", "Lt"];
+                if !operators.contains(&requirement.operator.as_str()) {
+                    errors.add(
+                        format!("scheduling.affinity.{}[{}].operator", kind, i),
+                        format!("Unknown operator {}", requirement.operator),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Checks the value is a number followed by one of the suffixes accepted by Kubernetes.
+fn is_valid_quantity(value: &str) -> bool {
+    const SUFFIXES: [&str; 14] = [
+        "Ki", "Mi", "Gi", "Ti", "Pi", "Ei", "m", "k", "M", "G", "T", "P", "E", "",
+    ];
+    SUFFIXES.iter().any(|suffix| {
+        value
+            .strip_suffix(suffix)
+            .map(|number| {
+                !number.is_empty()
+                    && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+                    && number.matches('.').count() <= 1
+                    && number.chars().any(|c| c.is_ascii_digit())
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// What the worker of a watcher does with the transitions it detects.
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatcherMode {
+    /// Executes the actions of the transitions.
+    Active,
+    /// Detects the transitions, reporting them in the metrics, state and events of the worker,
+    /// without executing their actions. Meant to trial the detection on a live channel.
+    Monitor,
+}
+
+impl Default for WatcherMode {
+    fn default() -> Self {
+        WatcherMode::Active
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Running,
+    Pending,
+    Ready,
+    Error,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct Source {
+    pub ingest_ip: Option<String>,
+    /// Allocated by the API when missing in the creation payload.
+    pub ingest_port: Option<u32>,
+    pub container: Container,
+    pub codec: Codec,
+    pub transport: Protocol,
+    /// Multicast group the worker joins to receive the `rtp` or `udp` stream, on the ingest
+    /// port, instead of the stream being sent to the worker.
+    pub multicast: Option<Multicast>,
+    /// Bitrate of the stream in kbps, `DEFAULT_BITRATE_KBPS` when missing. The packed watchers
+    /// are packed by the bitrate of their sources.
+    pub bitrate_kbps: Option<u32>,
+}
+
+impl Source {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        match self.ingest_port {
+            Some(port) if port <= 1024 || port >= 60_000 => errors.add(
+                format!("{}.ingest_port", field),
+                format!(
+                    "Source port {} is not in within the valid range (1024-60000)",
+                    port
+                ),
+            ),
+            Some(_) => (),
+            None => errors.add(format!("{}.ingest_port", field), "Source port is required"),
+        }
+        if self.bitrate_kbps == Some(0) {
+            errors.add(
+                format!("{}.bitrate_kbps", field),
+                "The bitrate must be positive",
+            );
+        }
+        if let Protocol::Rtp {
+            fec,
+            second_leg_port,
+        } = &self.transport
+        {
+            if (fec.is_some() || second_leg_port.is_some()) && self.container != Container::MpegTs {
+                errors.add(
+                    format!("{}.container", field),
+                    "FEC and second legs are only available for MPEG-TS streams",
+                );
+            }
+            if let (Some(port), Some(ingest_port)) = (second_leg_port, self.ingest_port) {
+                let taken = self
+                    .ports_from(ingest_port)
+                    .iter()
+                    .any(|(name, p)| *name != "second-leg" && p == port);
+                if *port <= 1024 || *port >= 60_000 || taken {
+                    errors.add(
+                        format!("{}.transport.second_leg_port", field),
+                        format!(
+                            "Port {} of the second leg must be in the valid range (1024-60000) and not used by the first one",
+                            port
+                        ),
+                    );
+                }
+            }
+        }
+        if let Some(multicast) = &self.multicast {
+            multicast.validate(&format!("{}.multicast", field), errors);
+            match &self.transport {
+                Protocol::Rtp {
+                    fec: None,
+                    second_leg_port: None,
+                }
+                | Protocol::Udp => (),
+                _ => errors.add(
+                    format!("{}.multicast", field),
+                    "Only RTP sources without FEC or second leg, and UDP sources, can be multicast",
+                ),
+            }
+        }
+        if self.transport == Protocol::Udp && self.container != Container::MpegTs {
+            errors.add(
+                format!("{}.container", field),
+                "UDP sources carry MPEG-TS streams",
+            );
+        }
+        if let Protocol::Rist { .. } = &self.transport {
+            if self.container != Container::MpegTs {
+                errors.add(
+                    format!("{}.container", field),
+                    "RIST sources carry MPEG-TS streams",
+                );
+            }
+            // The RTCP of RIST uses the next port
+            if self.ingest_port.map_or(false, |port| port % 2 != 0) {
+                errors.add(
+                    format!("{}.ingest_port", field),
+                    "The ingest port of RIST sources must be even",
+                );
+            }
+        }
+        if let Protocol::Srt {
+            mode,
+            address,
+            passphrase,
+            ..
+        } = &self.transport
+        {
+            if self.container != Container::MpegTs {
+                errors.add(
+                    format!("{}.container", field),
+                    "SRT sources carry MPEG-TS streams",
+                );
+            }
+            match (mode.unwrap_or_default(), address) {
+                (SrtMode::Caller, None) => errors.add(
+                    format!("{}.transport.address", field),
+                    "The address of the sender is required in the caller mode",
+                ),
+                (SrtMode::Listener, Some(_)) => errors.add(
+                    format!("{}.transport.address", field),
+                    "Only the caller mode connects to an address",
+                ),
+                (SrtMode::Caller, Some(address)) if !is_valid_host_port(address) => errors.add(
+                    format!("{}.transport.address", field),
+                    "The address of the sender must be a hostname or an IP address, and a port",
+                ),
+                (_, _) => (),
+            }
+            if let Some(passphrase) = passphrase {
+                let printable = passphrase
+                    .chars()
+                    .all(|c| c.is_ascii_graphic() && c != '"' && c != '\\');
+                if !(10..=79).contains(&passphrase.len()) || !printable {
+                    errors.add(
+                        format!("{}.transport.passphrase", field),
+                        "The passphrase must be 10 to 79 printable characters, without quotes or backslashes",
+                    );
+                }
+            }
+        }
+        if let Some(url) = self.pull_url() {
+            let http = url.starts_with("http://") || url.starts_with("https://");
+            if !http || url.contains(|c: char| c == '"' || c.is_whitespace()) {
+                errors.add(
+                    format!("{}.transport.url", field),
+                    "The URL of the playlist or manifest must be an HTTP(S) URL",
+                );
+            }
+        }
+        if let Protocol::Rtmp { stream_key, .. } = &self.transport {
+            if self.container != Container::Flv {
+                errors.add(
+                    format!("{}.container", field),
+                    "RTMP sources carry FLV streams",
+                );
+            }
+            let valid = stream_key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if stream_key.is_empty() || !valid {
+                errors.add(
+                    format!("{}.transport.stream_key", field),
+                    "The stream key must be made of letters, digits, '-' and '_'",
+                );
+            }
+        }
+    }
+
+    /// Whether the sender pushes the stream to the ingest port of the worker, which then needs a
+    /// `Service` receiving it, rather than the worker connecting to the sender.
+    pub fn is_pushed(&self) -> bool {
+        if self.multicast.is_some() {
+            return false;
+        }
+        match &self.transport {
+            Protocol::Rtp { .. }
+            | Protocol::Udp
+            | Protocol::Rist { .. }
+            | Protocol::Rtmp { .. } => true,
+            Protocol::Srt { mode, .. } => mode.unwrap_or_default() == SrtMode::Listener,
+            Protocol::Hls { .. } | Protocol::Dash { .. } => false,
+        }
+    }
+
+    /// The ports of the worker receiving the source on the ingest port, named after what they
+    /// receive: the ingest port, also serving the metrics, then the ports of the FEC streams, of
+    /// the second leg or of the RTCP of RIST.
+    pub fn ports_from(&self, ingest_port: u32) -> Vec<(&'static str, u32)> {
+        let mut ports = vec![("ingest", ingest_port)];
+        match &self.transport {
+            Protocol::Rtp {
+                fec,
+                second_leg_port,
+            } => {
+                // SMPTE 2022-1 sends the FEC streams 2 and 4 ports above the media
+                if fec.is_some() {
+                    ports.push(("fec-columns", ingest_port + 2));
+                }
+                if *fec == Some(Fec::TwoDimensions) {
+                    ports.push(("fec-rows", ingest_port + 4));
+                }
+                if let Some(port) = second_leg_port {
+                    ports.push(("second-leg", *port));
+                }
+            }
+            Protocol::Rist { .. } => ports.push(("rist-rtcp", ingest_port + 1)),
+            _ => (),
+        }
+        ports
+    }
+
+    /// The ports of `ports_from` the ingest port, none when missing.
+    pub fn ports(&self) -> Vec<u32> {
+        self.ingest_port
+            .map(|ingest_port| self.ports_from(ingest_port))
+            .into_iter()
+            .flatten()
+            .map(|(_, port)| port)
+            .collect()
+    }
+
+    /// URL of the playlist or manifest the worker pulls the stream from, for the HLS and DASH
+    /// sources.
+    pub fn pull_url(&self) -> Option<&str> {
+        match &self.transport {
+            Protocol::Hls { url, .. } | Protocol::Dash { url, .. } => Some(url.as_str()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Container {
+    RawVideo,
+    MpegTs,
+    Fmp4,
+    Flv,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    H264,
+    H265,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "protocol", rename_all = "lowercase")]
+pub enum Protocol {
+    Rtp {
+        /// SMPTE 2022-1 forward error correction of the lost packets, from the FEC streams sent
+        /// 2, and 4 for the rows, ports above the ingest port.
+        fec: Option<Fec>,
+        /// Port receiving the second leg of a SMPTE 2022-7 stream, merged seamlessly with the
+        /// first one.
+        second_leg_port: Option<u32>,
+    },
+    /// MPEG-TS in UDP datagrams, without RTP, like most multicast streams.
+    Udp,
+    /// Reliable Internet Stream Transport, carrying an MPEG-TS stream, the RTCP using the port
+    /// after the ingest port.
+    Rist {
+        /// Time given to receive the lost packets again, 1000 milliseconds when missing.
+        buffer_ms: Option<u32>,
+    },
+    /// Secure Reliable Transport, carrying an MPEG-TS stream.
+    Srt {
+        /// `listener` when missing.
+        mode: Option<SrtMode>,
+        /// `host:port` of the sender the worker connects to, in the `caller` mode.
+        address: Option<String>,
+        /// Key of the encrypted streams, 10 to 79 characters.
+        passphrase: Option<String>,
+        /// Time given to recover the lost packets, 120 milliseconds when missing.
+        latency_ms: Option<u32>,
+    },
+    /// Real-Time Messaging Protocol, carrying an FLV stream the encoder publishes to
+    /// `rtmp://<ingest_ip>:<ingest_port>/live/<stream_key>`.
+    Rtmp {
+        /// Only the stream published with this key is watched.
+        stream_key: String,
+        /// Whether the encoder publishes over RTMPS, the load balancer terminating TLS.
+        tls: Option<bool>,
+    },
+    /// HTTP Live Streaming, the worker polling the playlist for the segments at the live edge.
+    /// The `container` and `codec` are detected.
+    Hls {
+        url: String,
+        /// Highest bitrate of the variant watched, in kbps, the lowest variant when missing.
+        max_bitrate_kbps: Option<u32>,
+    },
+    /// MPEG-DASH, the worker polling the manifest for the segments at the live edge. The
+    /// `container` and `codec` are detected.
+    Dash {
+        url: String,
+        /// Highest bitrate of the representation watched, in kbps, the lowest one when missing.
+        max_bitrate_kbps: Option<u32>,
+    },
+}
+
+/// Multicast group of a source, the worker running with the network of its node to join it.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct Multicast {
+    /// Address of the group, like `239.1.1.1`.
+    pub group: String,
+    /// Address of the sender, to join the group with source-specific multicast (SSM).
+    pub source_address: Option<String>,
+    /// Network interface of the node joining the group, like `eth1`, picked by the routes of the
+    /// node when missing.
+    pub interface: Option<String>,
+}
+
+impl Multicast {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        let group = self.group.parse::<std::net::IpAddr>();
+        if !group.map_or(false, |group| group.is_multicast()) {
+            errors.add(
+                format!("{}.group", field),
+                format!("{} is not a multicast address", self.group),
+            );
+        }
+        if let Some(address) = self.source_address.as_deref() {
+            if address.parse::<std::net::IpAddr>().is_err() {
+                errors.add(
+                    format!("{}.source_address", field),
+                    format!("{} is not an IP address", address),
+                );
+            }
+        }
+        if let Some(interface) = self.interface.as_deref() {
+            let valid = interface
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_');
+            if interface.is_empty() || interface.len() > 15 || !valid {
+                errors.add(
+                    format!("{}.interface", field),
+                    format!("{} is not a network interface name", interface),
+                );
+            }
+        }
+    }
+}
+
+/// FEC streams of SMPTE 2022-1.
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Fec {
+    /// Column FEC stream only.
+    #[serde(rename = "1d")]
+    OneDimension,
+    /// Column and row FEC streams.
+    #[serde(rename = "2d")]
+    TwoDimensions,
+}
+
+/// Which side of the SRT connection the worker is.
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SrtMode {
+    /// Waits for the sender on the ingest port.
+    Listener,
+    /// Connects to the sender.
+    Caller,
+}
+
+impl Default for SrtMode {
+    fn default() -> Self {
+        SrtMode::Listener
+    }
+}
+
+/// A slate image the frames are compared with, triggering its own transitions.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Slate {
+    /// Identifies the slate in the metrics and the state of the worker.
+    pub id: String,
+    pub url: String,
+    /// Highest dissimilarity of a frame matching the slate, as measured by the `detector`, its
+    /// default threshold when missing.
+    pub threshold: Option<f64>,
+    /// Algorithm comparing the frames with the slate, `dssim` when missing.
+    pub detector: Option<Detector>,
+    /// Area of the frames and of the slate compared, ignoring the tickers, clocks or logos
+    /// outside of the slate art. The whole frame when missing.
+    pub region: Option<Region>,
+    /// Text read on the frames showing the slate by the `ocr` detector, like `BE RIGHT BACK`,
+    /// matching any of them. The text read on the slate image when missing.
+    pub text: Option<Vec<String>>,
+    /// Transitions between the content and this slate, the `transitions` of the watcher when
+    /// missing.
+    pub transitions: Option<Vec<Transition>>,
+}
+
+// Thresholds are validated to be finite numbers
+impl Eq for Slate {}
+
+impl Slate {
+    pub fn detector(&self) -> Detector {
+        self.detector.unwrap_or_default()
+    }
+
+    /// The `threshold` of the slate, or the default one of its detector.
+    pub fn threshold_or_default(&self) -> f64 {
+        self.threshold
+            .unwrap_or_else(|| self.detector().default_threshold())
+    }
+}
+
+/// Area of an image, in percent of its width and height.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Region {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        let values = [self.x, self.y, self.width, self.height];
+        if values
+            .iter()
+            .any(|v| !v.is_finite() || *v < 0.0 || *v > 100.0)
+        {
+            errors.add(
+                field,
+                "Region must be in percent of the frame, between 0 and 100",
+            );
+        } else if self.width == 0.0 || self.height == 0.0 {
+            errors.add(field, "Region must not be empty");
+        } else if self.x + self.width > 100.0 || self.y + self.height > 100.0 {
+            errors.add(field, "Region must be within the frame");
+        }
+    }
+
+    /// Position and size in pixels of the region of an image of the given size, at least one
+    /// pixel wide and high.
+    pub fn to_pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let scale = |percent: f64, size: u32| (percent * f64::from(size) / 100.0).round() as u32;
+        let x = scale(self.x, width).min(width.saturating_sub(1));
+        let y = scale(self.y, height).min(height.saturating_sub(1));
+        let region_width = scale(self.width, width).max(1).min(width - x);
+        let region_height = scale(self.height, height).max(1).min(height - y);
+        (x, y, region_width, region_height)
+    }
+}
+
+/// Algorithm comparing the frames with a slate.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Detector {
+    /// Structural dissimilarity of the images, the most accurate.
+    Dssim,
+    /// Share of the bits differing between the perceptual hashes (DCT) of the images, much
+    /// cheaper to compare.
+    Phash,
+    /// Share of the bits differing between the difference hashes of the images, the cheapest.
+    Dhash,
+    /// `(1 - SSIM) / 2` of the luma of the images, cheaper than DSSIM which also compares the
+    /// colors at several scales.
+    Ssim,
+    /// `(1 - NCC) / 2`, from the normalized cross-correlation of the luma of the images, which
+    /// ignores the brightness and the contrast of the stream.
+    Ncc,
+    /// Share of the characters of the `text` of the slate differing from the text read on the
+    /// frames, for slates whose art changes but not their message. Only available in workers
+    /// built with the `ocr` feature.
+    Ocr,
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Detector::Dssim
+    }
+}
+
+impl Detector {
+    pub fn default_threshold(self) -> f64 {
+        match self {
+            Detector::Dssim => DEFAULT_SLATE_THRESHOLD,
+            Detector::Phash | Detector::Dhash => DEFAULT_HASH_THRESHOLD,
+            Detector::Ssim | Detector::Ncc => DEFAULT_CORRELATION_THRESHOLD,
+            Detector::Ocr => DEFAULT_TEXT_THRESHOLD,
+        }
+    }
+
+    /// Whether the similarities are between `0` and `1`, so thresholds above `1` match
+    /// everything.
+    pub fn is_normalized(self) -> bool {
+        self != Detector::Dssim
+    }
+}
+
+/// New mode of a watcher, applied to its worker without restarting it.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct ModeChange {
+    pub mode: WatcherMode,
+}
+
+/// Transitions of a slate, or of the black or freeze trigger, whose actions are fired on a
+/// running worker whatever its frames show.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct TransitionTrigger {
+    /// The `default` slate when missing.
+    pub slate_id: Option<String>,
+    pub from: VideoMode,
+    pub to: VideoMode,
+}
+
+impl TransitionTrigger {
+    pub fn slate_id(&self) -> &str {
+        self.slate_id.as_deref().unwrap_or(DEFAULT_SLATE_ID)
+    }
+
+    /// The transitions of the slate or trigger from `from` to `to`, `None` when the watcher has
+    /// no such slate or trigger.
+    pub fn transitions(&self, watcher: &Watcher) -> Option<Vec<Transition>> {
+        let transitions = match watcher
+            .triggers()
+            .into_iter()
+            .find(|(id, _)| *id == self.slate_id())
+        {
+            Some((_, trigger)) => trigger.transitions.clone(),
+            None => watcher
+                .all_slates()
+                .into_iter()
+                .find(|slate| slate.id == self.slate_id())?
+                .transitions
+                .unwrap_or_default(),
+        };
+        Some(
+            transitions
+                .into_iter()
+                .filter(|transition| transition.from == self.from && transition.to == self.to)
+                .collect(),
+        )
+    }
+
+    /// Checks the slate or trigger is one of the watcher, with a transition to fire.
+    pub fn validate(&self, watcher: &Watcher) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        match self.transitions(watcher) {
+            None => errors.add(
+                "slate_id",
+                format!("Watcher has no slate or trigger {}", self.slate_id()),
+            ),
+            Some(transitions) if transitions.is_empty() => errors.add(
+                "to",
+                format!(
+                    "{} has no transition from {} to {}",
+                    self.slate_id(),
+                    self.from,
+                    self.to
+                ),
+            ),
+            Some(_) => (),
+        }
+        errors.into_result()
+    }
+}
+
+/// Sent by a running worker every `HEARTBEAT_INTERVAL_SECONDS`, as long as its pipeline is
+/// processing the incoming video.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct Heartbeat {
+    /// RFC 3339 time the heartbeat was received by the API, so the clock of the worker does not
+    /// matter.
+    pub timestamp: String,
+    /// Frames analyzed since the worker started.
+    pub frames_processed: u64,
+    /// Video mode of the last frames, missing until the first frame is analyzed.
+    pub mode: Option<VideoMode>,
+    /// Version of the worker.
+    pub version: String,
+}
+
+impl Heartbeat {
+    /// Whether the heartbeat is older than `HEARTBEAT_STALE_SECONDS`, or has an invalid time.
+    pub fn is_stale(&self, now: DateTime<Utc>) -> bool {
+        DateTime::parse_from_rfc3339(&self.timestamp).map_or(true, |time| {
+            now.signed_duration_since(time).num_seconds() > HEARTBEAT_STALE_SECONDS
+        })
+    }
+}
+
+/// What a worker reported to the event store of the API.
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatcherEventKind {
+    /// The stream switched to another video mode, like from the content to a slate.
+    Detection,
+    /// A slate, or trigger, switched between its video modes.
+    Transition,
+    /// An action was executed, or replayed.
+    Action,
+    /// The frame pipeline stalled, and the worker restarted it or exited.
+    Stall,
+    /// The worker stopped, `from` being the video mode it detected last.
+    Shutdown,
+}
+
+/// Something a worker detected or did, kept by the event store of the API so the history of a
+/// watcher can be queried.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct WatcherEvent {
+    /// RFC 3339 time, in UTC with milliseconds, so the times of the events sort as text.
+    pub timestamp: String,
+    pub kind: WatcherEventKind,
+    /// Slate, or trigger, of the event. The matching slate of the detections of a slate.
+    pub slate_id: Option<String>,
+    pub from: Option<VideoMode>,
+    pub to: Option<VideoMode>,
+    /// Description of the action, or its type, for the `action` events. How the worker
+    /// recovered, `restart` or `exit`, for the `stall` events.
+    pub action: Option<String>,
+    pub success: Option<bool>,
+    pub error: Option<String>,
+    /// Milliseconds the action took, all its attempts included.
+    pub latency_ms: Option<u64>,
+}
+
+impl WatcherEvent {
+    /// Time of the event, `None` when the worker reported an invalid one.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .map(|time| time.with_timezone(&Utc))
+    }
+}
+
+/// Threshold of a slate tuned on a running worker, without changing the watcher definition.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ThresholdChange {
+    /// The `default` slate when missing.
+    pub slate_id: Option<String>,
+    pub threshold: f64,
+}
+
+impl ThresholdChange {
+    pub fn slate_id(&self) -> &str {
+        self.slate_id.as_deref().unwrap_or(DEFAULT_SLATE_ID)
+    }
+
+    /// Checks the slate is one of the watcher and the threshold is valid.
+    pub fn validate(&self, watcher: &Watcher) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        let slates = watcher.all_slates();
+        let slate = slates.iter().find(|slate| slate.id == self.slate_id());
+        if slate.is_none() {
+            errors.add(
+                "slate_id",
+                format!("Watcher has no slate {}", self.slate_id()),
+            );
+        }
+        if !self.threshold.is_finite() || self.threshold < 0.0 {
+            errors.add("threshold", "Threshold must be a positive number");
+        } else if slate.map_or(false, |slate| slate.detector().is_normalized())
+            && self.threshold > 1.0
+        {
+            errors.add("threshold", "Threshold of this detector must be at most 1");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub from: VideoMode,
+    pub to: VideoMode,
+    pub actions: Vec<Action>,
+    /// Combined with the video mode to tell when the stream is in the `from` or `to` mode, only
+    /// the video mode counts when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioCondition>,
+    /// Tells when the stream is in the mode other than `content` of the transition, replacing
+    /// the video mode and the `audio` condition.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hysteresis: Option<Hysteresis>,
+    /// Milliseconds the stream must stay in the `to` mode before the actions run, right away
+    /// when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_match_duration_ms: Option<u64>,
+    /// Milliseconds before the actions can run again, `DEFAULT_COOLDOWN_MS` when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_ms: Option<u64>,
+    /// Times the actions can run, any of the windows containing the time of the transition,
+    /// always when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<Vec<ActiveWindow>>,
+}
+
+// The silence thresholds are validated to be finite
+impl Eq for Transition {}
+
+/// Composite condition of a transition, evaluated on every frame.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// A slate matches the frame, the slate of the transition, or the default slate in a state
+    /// machine, when `slate_id` is missing.
+    Slate {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        slate_id: Option<String>,
+    },
+    /// The frame is black.
+    Black,
+    /// The audio is quieter than the `threshold`, in dBFS, `DEFAULT_SILENCE_DBFS` when missing.
+    /// Streams without audio are silent.
+    Silence {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        threshold: Option<f64>,
+    },
+    /// The time of day, in UTC, is between `start` and `end` (`HH:MM`), the window spanning
+    /// midnight when it ends before it starts.
+    TimeWindow { start: String, end: String },
+    /// All the conditions are met.
+    All { conditions: Vec<Condition> },
+    /// Any of the conditions is met.
+    Any { conditions: Vec<Condition> },
+    /// At least `count` of the conditions are met.
+    AtLeast {
+        count: usize,
+        conditions: Vec<Condition>,
+    },
+    /// The condition is not met.
+    Not { condition: Box<Condition> },
+}
+
+impl Condition {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        match self {
+            Condition::Slate { slate_id } => {
+                if slate_id.as_deref().map_or(false, |id| id.is_empty()) {
+                    errors.add(format!("{}.slate_id", field), "Invalid slate ID");
+                }
+            }
+            Condition::Black => {}
+            Condition::Silence { threshold } => {
+                if threshold.map_or(false, |threshold| !threshold.is_finite() || threshold > 0.0) {
+                    errors.add(
+                        format!("{}.threshold", field),
+                        "Silence threshold must be a loudness in dBFS, at most 0",
+                    );
+                }
+            }
+            Condition::TimeWindow { start, end } => {
+                for (name, time) in [("start", start), ("end", end)] {
+                    if minutes_of_day(time).is_none() {
+                        errors.add(
+                            format!("{}.{}", field, name),
+                            "Time must be a time of day like 23:30",
+                        );
+                    }
+                }
+            }
+            Condition::All { conditions } | Condition::Any { conditions } => {
+                Condition::validate_all(field, conditions, errors);
+            }
+            Condition::AtLeast { count, conditions } => {
+                if *count == 0 || *count > conditions.len() {
+                    errors.add(
+                        format!("{}.count", field),
+                        "Count must be between 1 and the number of conditions",
+                    );
+                }
+                Condition::validate_all(field, conditions, errors);
+            }
+            Condition::Not { condition } => {
+                condition.validate(&format!("{}.condition", field), errors);
+            }
+        }
+    }
+
+    fn validate_all(field: &str, conditions: &[Condition], errors: &mut ValidationErrors) {
+        if conditions.is_empty() {
+            errors.add(
+                format!("{}.conditions", field),
+                "At least one condition must be defined",
+            );
+        }
+        for (i, condition) in conditions.iter().enumerate() {
+            condition.validate(&format!("{}.conditions[{}]", field, i), errors);
+        }
+    }
+}
+
+/// Minutes since midnight of a `HH:MM` time of day.
+pub fn minutes_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours < 24 && minutes < 60 {
+        Some(hours * 60 + minutes)
+    } else {
+        None
+    }
+}
+
+/// Whether the minute of the day is in the window from `start` (included) to `end` (excluded),
+/// which spans midnight when it ends before it starts.
+pub fn in_daily_window(start: u32, end: u32, minute: u32) -> bool {
+    if start <= end {
+        (start..end).contains(&minute)
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+/// Parses a cron expression, in the standard format with five fields or with seconds first. The
+/// days of the week are numbered like in the standard format, from 0 for Sunday, 7 being Sunday
+/// too.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule> {
+    let mut fields: Vec<String> = expression.split_whitespace().map(String::from).collect();
+    if fields.len() == 5 {
+        fields.insert(0, "0".to_string());
+    }
+    // The `cron` crate numbers the days from 1 for Sunday, the names mean the same in both
+    if let Some(days) = fields.get_mut(5) {
+        *days = day_of_week_names(days)
+            .map_err(|e| eyre!("Invalid cron expression '{}': {}", expression, e))?;
+    }
+    cron::Schedule::from_str(&fields.join(" "))
+        .map_err(|e| eyre!("Invalid cron expression '{}': {}", expression, e))
+}
+
+/// Names of the days of the week, from Sunday.
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Replaces the numbered days of the day of week field by their names: the lists, ranges and
+/// steps with numbers are expanded to the list of the names of their days.
+fn day_of_week_names(field: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    for part in field.split(',') {
+        if !part.chars().any(|c| c.is_ascii_digit()) {
+            parts.push(part.to_string());
+            continue;
+        }
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().unwrap_or(0)),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(eyre!("Invalid step of the days of the week: {}", part));
+        }
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (0, 6),
+            Some((start, end)) => (day_of_week(start)?, day_of_week(end)?),
+            // A day with a step runs to the end of the week
+            None if part.contains('/') => (day_of_week(range)?, 6),
+            None => (day_of_week(range)?, day_of_week(range)?),
+        };
+        if start > end {
+            return Err(eyre!("Invalid range of the days of the week: {}", part));
+        }
+        let days: Vec<&str> = (start..=end)
+            .step_by(step)
+            .map(|day| WEEKDAYS[day % 7])
+            .collect();
+        parts.push(days.join(","));
+    }
+    Ok(parts.join(","))
 }
 
-impl Watcher {
-    pub fn is_valid(&self) -> Result<()> {
-        if self.slate_url.starts_with("http://")
-            || self.slate_url.starts_with("https://")
-            || self.slate_url.starts_with("file://")
-        {
-            Ok(self.source.is_valid()?)
-        } else {
-            Err(eyre!("{} not recognized as a valid URL!", self.slate_url))
+/// Number of a day of the week, from 0 for Sunday to 7 for Sunday again, or its name.
+fn day_of_week(day: &str) -> Result<usize> {
+    let number = match day.parse::<usize>() {
+        Ok(number) => Some(number).filter(|number| *number <= 7),
+        Err(_) => WEEKDAYS
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(day)),
+    };
+    number.ok_or_else(|| eyre!("Invalid day of the week: {}", day))
+}
+
+/// Parses the IANA name of a time zone, UTC when missing.
+pub fn parse_timezone(name: Option<&str>) -> Result<Tz> {
+    let name = name.unwrap_or("UTC");
+    name.parse::<Tz>()
+        .map_err(|e| eyre!("Invalid time zone '{}': {}", name, e))
+}
+
+/// Times a transition is active, either a daily range or the minutes of a cron expression.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ActiveWindow {
+    /// Start of the daily range, `HH:MM`.
+    pub start: Option<String>,
+    /// End of the daily range, which spans midnight when it ends before it starts.
+    pub end: Option<String>,
+    /// Minutes the transition is active, e.g. `* 6-21 * * MON-FRI`.
+    pub cron: Option<String>,
+    /// IANA name of the time zone of the range or the cron expression, UTC when missing.
+    pub timezone: Option<String>,
+}
+
+impl ActiveWindow {
+    /// Whether the window contains the given time, never for windows that are not valid.
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        let local = match parse_timezone(self.timezone.as_deref()) {
+            Ok(tz) => time.with_timezone(&tz),
+            Err(_) => return false,
+        };
+        if let Some(expression) = self.cron.as_deref() {
+            // The expression fires at the start of the minutes it contains
+            let minute = local.with_second(0).and_then(|t| t.with_nanosecond(0));
+            return match (parse_cron(expression), minute) {
+                (Ok(schedule), Some(minute)) => {
+                    schedule
+                        .after(&(minute - chrono::Duration::seconds(1)))
+                        .next()
+                        == Some(minute)
+                }
+                _ => false,
+            };
+        }
+        let range = (
+            self.start.as_deref().and_then(minutes_of_day),
+            self.end.as_deref().and_then(minutes_of_day),
+        );
+        match range {
+            (Some(start), Some(end)) => {
+                in_daily_window(start, end, local.hour() * 60 + local.minute())
+            }
+            _ => false,
         }
     }
-}
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum Status {
-    Running,
-    Pending,
-    Ready,
-    Error,
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        match (&self.start, &self.end, &self.cron) {
+            (Some(start), Some(end), None) => {
+                for (name, time) in [("start", start), ("end", end)] {
+                    if minutes_of_day(time).is_none() {
+                        errors.add(
+                            format!("{}.{}", field, name),
+                            "Time must be a time of day like 23:30",
+                        );
+                    }
+                }
+            }
+            (None, None, Some(expression)) => {
+                if let Err(e) = parse_cron(expression) {
+                    errors.add(format!("{}.cron", field), e.to_string());
+                }
+            }
+            _ => errors.add(
+                field,
+                "Window must be either a daily range with a start and an end, or a cron expression",
+            ),
+        }
+        if let Err(e) = parse_timezone(self.timezone.as_deref()) {
+            errors.add(format!("{}.timezone", field), e.to_string());
+        }
+    }
 }
 
+/// Consecutive frames needed to change the mode of a transition, so a noisy feed does not flap
+/// between the modes.
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
-pub struct Source {
-    pub ingest_ip: Option<String>,
-    pub ingest_port: u32,
-    pub container: Container,
-    pub codec: Codec,
-    pub transport: Protocol,
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct Hysteresis {
+    /// Frames in the mode other than `content` before entering it, `1` when missing.
+    pub enter_frames: Option<u32>,
+    /// Frames in the `content` mode before going back to it, `1` when missing.
+    pub exit_frames: Option<u32>,
 }
 
-impl Source {
-    fn is_valid(&self) -> Result<()> {
-        if self.ingest_port > 1024 && self.ingest_port < 60_000 {
-            Ok(())
-        } else {
-            Err(eyre!(
-                "Source port {} is not in within the valid range (1024-60000)",
-                self.ingest_port
-            ))
+impl Hysteresis {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        for (name, frames) in [
+            ("enter_frames", self.enter_frames),
+            ("exit_frames", self.exit_frames),
+        ] {
+            if frames == Some(0) {
+                errors.add(
+                    format!("{}.{}", field, name),
+                    "At least one frame is needed to change the mode",
+                );
+            }
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
-#[serde(rename_all = "kebab-case")]
-pub enum Container {
-    RawVideo,
-    MpegTs,
-    Fmp4,
+/// Condition on the audio of the stream, combined with the video mode of a transition.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct AudioCondition {
+    pub audio: AudioMode,
+    #[serde(default)]
+    pub combine: Combine,
+    /// Loudness, in dBFS, under which the audio is silent, `DEFAULT_SILENCE_DBFS` when missing.
+    pub silence_threshold: Option<f64>,
+}
+
+impl AudioCondition {
+    pub fn silence_threshold(&self) -> f64 {
+        self.silence_threshold.unwrap_or(DEFAULT_SILENCE_DBFS)
+    }
+
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        if let Some(threshold) = self.silence_threshold {
+            if !threshold.is_finite() || threshold > 0.0 {
+                errors.add(
+                    format!("{}.silence_threshold", field),
+                    "Silence threshold must be a loudness in dBFS, at most 0",
+                );
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
-pub enum Codec {
-    H264,
-    H265,
+pub enum AudioMode {
+    Silent,
+    Audible,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
-#[serde(tag = "protocol", rename_all = "lowercase")]
-pub enum Protocol {
-    Rtp,
+/// How an `AudioCondition` is combined with the video mode: the stream is in the mode other than
+/// `content` when both the video and the audio show it (`and`), or when either does (`or`).
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Combine {
+    And,
+    Or,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
-pub struct Transition {
-    pub from: VideoMode,
-    pub to: VideoMode,
-    pub actions: Vec<Action>,
+impl Default for Combine {
+    fn default() -> Self {
+        Combine::And
+    }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum VideoMode {
     Slate,
     Content,
+    /// Sustained black frames, see `Watcher::black`.
+    Black,
+    /// Frozen video, see `Watcher::freeze`.
+    Frozen,
+    /// Frames of a backup source, see `Watcher::failover`.
+    Backup,
+    /// No frame received, see `Watcher::stream_loss`.
+    Lost,
+}
+
+impl std::fmt::Display for VideoMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            VideoMode::Slate => "slate",
+            VideoMode::Content => "content",
+            VideoMode::Black => "black",
+            VideoMode::Frozen => "frozen",
+            VideoMode::Backup => "backup",
+            VideoMode::Lost => "lost",
+        };
+        write!(f, "{}", name)
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+/// Black or frozen frames lasting long enough to trigger their own transitions, between the
+/// `content` and the `black` or `frozen` video modes.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Trigger {
+    /// Seconds the frames must stay black or frozen, `DEFAULT_TRIGGER_SECONDS` when missing.
+    pub duration: Option<f64>,
+    /// Highest mean difference of the luma of consecutive frames still frozen, between `0` and
+    /// `1`, `DEFAULT_FREEZE_THRESHOLD` when missing. Not used to detect black frames.
+    pub threshold: Option<f64>,
+    pub transitions: Vec<Transition>,
+}
+
+// Durations and thresholds are validated to be finite numbers
+impl Eq for Trigger {}
+
+impl Trigger {
+    pub fn duration(&self) -> f64 {
+        self.duration.unwrap_or(DEFAULT_TRIGGER_SECONDS)
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold.unwrap_or(DEFAULT_FREEZE_THRESHOLD)
+    }
+
+    fn validate(&self, field: &str, mode: VideoMode, errors: &mut ValidationErrors) {
+        if let Some(duration) = self.duration {
+            if !duration.is_finite() || duration < 0.0 {
+                errors.add(
+                    format!("{}.duration", field),
+                    "Duration must be a positive number of seconds",
+                );
+            }
+        }
+        if let Some(threshold) = self.threshold {
+            if !threshold.is_finite() || !(0.0..=1.0).contains(&threshold) {
+                errors.add(
+                    format!("{}.threshold", field),
+                    "Threshold must be between 0 and 1",
+                );
+            }
+        }
+        validate_transitions(
+            &format!("{}.transitions", field),
+            &self.transitions,
+            &[VideoMode::Content, mode],
+            errors,
+        );
+    }
+}
+
+/// Finite-state machine of a watcher, moving between named states on the conditions met by the
+/// frames or once a state lasted long enough.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct StateMachine {
+    /// Name of the state the stream starts in, its entry actions are not run.
+    pub initial: String,
+    pub states: Vec<MachineState>,
+}
+
+impl StateMachine {
+    pub fn state(&self, name: &str) -> Option<&MachineState> {
+        self.states.iter().find(|state| state.name == name)
+    }
+
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        if self.states.is_empty() {
+            errors.add(
+                format!("{}.states", field),
+                "At least one state must be defined",
+            );
+        }
+        if self.state(&self.initial).is_none() {
+            errors.add(
+                format!("{}.initial", field),
+                format!("Unknown state {}", self.initial),
+            );
+        }
+        for (i, state) in self.states.iter().enumerate() {
+            let field = format!("{}.states[{}]", field, i);
+            if !is_valid_label(&state.name) || state.name.is_empty() {
+                errors.add(format!("{}.name", field), "Invalid state name");
+            } else if self.states[..i].iter().any(|s| s.name == state.name) {
+                errors.add(
+                    format!("{}.name", field),
+                    format!("State name {} is already used", state.name),
+                );
+            }
+            for (name, actions) in [("on_enter", &state.on_enter), ("on_exit", &state.on_exit)] {
+                for (j, action) in actions.iter().enumerate() {
+                    action.validate(&format!("{}.{}[{}]", field, name, j), errors);
+                }
+            }
+            for (j, transition) in state.transitions.iter().enumerate() {
+                let field = format!("{}.transitions[{}]", field, j);
+                self.validate_target(&field, &state.name, &transition.to, errors);
+                transition.when.validate(&format!("{}.when", field), errors);
+                for (k, action) in transition.actions.iter().enumerate() {
+                    action.validate(&format!("{}.actions[{}]", field, k), errors);
+                }
+            }
+            if let Some(timeout) = state.timeout.as_ref() {
+                let field = format!("{}.timeout", field);
+                if !timeout.seconds.is_finite() || timeout.seconds <= 0.0 {
+                    errors.add(
+                        format!("{}.seconds", field),
+                        "Timeout must be a positive number of seconds",
+                    );
+                }
+                self.validate_target(&field, &state.name, &timeout.to, errors);
+            }
+        }
+    }
+
+    fn validate_target(&self, field: &str, from: &str, to: &str, errors: &mut ValidationErrors) {
+        if to == from {
+            errors.add(
+                format!("{}.to", field),
+                "Transition must be between different states",
+            );
+        } else if self.state(to).is_none() {
+            errors.add(format!("{}.to", field), format!("Unknown state {}", to));
+        }
+    }
+}
+
+/// State of a `StateMachine`.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct MachineState {
+    pub name: String,
+    /// Run when the stream enters the state.
+    #[serde(default)]
+    pub on_enter: Vec<Action>,
+    /// Run when the stream leaves the state, before the actions of the transition.
+    #[serde(default)]
+    pub on_exit: Vec<Action>,
+    /// Checked in order on every frame, the first one whose condition is met is taken.
+    #[serde(default)]
+    pub transitions: Vec<StateTransition>,
+    /// Leaves the state once it lasted long enough, when none of the transitions was taken.
+    pub timeout: Option<StateTimeout>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct StateTransition {
+    /// Name of the state entered.
+    pub to: String,
+    pub when: Condition,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+}
+
+// The silence thresholds are validated to be finite
+impl Eq for StateTransition {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct StateTimeout {
+    pub seconds: f64,
+    /// Name of the state entered.
+    pub to: String,
+}
+
+// The seconds are validated to be finite
+impl Eq for StateTimeout {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Action {
     HttpCall(HttpCall),
+    MediaLive(MediaLiveCall),
+    Sqs(SqsMessage),
+    Sns(SnsMessage),
+    Kafka(KafkaMessage),
+    Scte35(Scte35Signal),
+    Exec(ExecCommand),
 
     // #[cfg(test)]
     #[serde(skip_serializing, skip_deserializing)]
     FakeAction(FakeAction),
 }
 
+impl Action {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        match self {
+            Action::HttpCall(call) => {
+                if !is_valid_url(&call.url, &["http://", "https://"]) {
+                    errors.add(
+                        format!("{}.url", field),
+                        format!("{} not recognized as a valid URL!", call.url),
+                    );
+                }
+                call.validate_authorization(field, errors);
+            }
+            Action::MediaLive(call) => call.validate(field, errors),
+            Action::Sqs(message) => {
+                if !is_valid_url(&message.queue_url, &["https://"]) {
+                    errors.add(
+                        format!("{}.queue_url", field),
+                        format!("{} not recognized as a valid URL!", message.queue_url),
+                    );
+                }
+                validate_region(field, message.region.as_deref(), errors);
+            }
+            Action::Sns(message) => {
+                if !message.topic_arn.starts_with("arn:")
+                    || message.topic_arn.split(':').count() != 6
+                {
+                    errors.add(
+                        format!("{}.topic_arn", field),
+                        "Topic must be the ARN of an SNS topic",
+                    );
+                }
+                validate_region(field, message.region.as_deref(), errors);
+            }
+            Action::Kafka(message) => message.validate(field, errors),
+            Action::Scte35(signal) => signal.validate(field, errors),
+            Action::Exec(command) => command.validate(field, errors),
+            Action::FakeAction(_) => (),
+        }
+        if let Some(retry) = self.retry_policy() {
+            retry.validate(&format!("{}.retry", field), errors);
+        }
+    }
+
+    /// The `retry` of the action, if any, or the policy of its `retries`.
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        let (retry, retries) = match self {
+            Action::HttpCall(call) => (&call.retry, call.retries),
+            Action::MediaLive(call) => (&call.retry, call.retries),
+            Action::Sqs(message) => (&message.retry, message.retries),
+            Action::Sns(message) => (&message.retry, message.retries),
+            Action::Kafka(message) => (&message.retry, message.retries),
+            Action::Scte35(Scte35Signal {
+                destination: Scte35Destination::Http(call),
+                ..
+            }) => (&call.retry, call.retries),
+            Action::Scte35(_) | Action::Exec(_) | Action::FakeAction(_) => return None,
+        };
+        Some(
+            retry
+                .clone()
+                .unwrap_or_else(|| RetryPolicy::from_retries(retries)),
+        )
+    }
+}
+
+/// How an action is attempted again when it fails, waiting longer after every attempt, and
+/// paused after too many failures.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one, 1 when missing.
+    pub max_attempts: Option<u8>,
+    /// Milliseconds before the first retry, doubled for every other one,
+    /// `DEFAULT_RETRY_BACKOFF_MS` when missing.
+    pub backoff_ms: Option<u64>,
+    /// Longest wait between two attempts, in milliseconds, `DEFAULT_MAX_BACKOFF_MS` when missing.
+    pub max_backoff_ms: Option<u64>,
+    /// Fraction of the wait randomly added or removed, from 0 to 1, none when missing.
+    pub jitter: Option<f64>,
+    /// Milliseconds each attempt can last, the timeout of the action when missing.
+    pub attempt_timeout_ms: Option<u64>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+}
+
+// The jitter is validated to be finite
+impl Eq for RetryPolicy {}
+
+impl RetryPolicy {
+    /// Attempts `retries` times again right away, the former behavior of the actions.
+    pub fn from_retries(retries: Option<u8>) -> Self {
+        Self {
+            max_attempts: Some(retries.unwrap_or(0).saturating_add(1)),
+            backoff_ms: Some(0),
+            ..Self::default()
+        }
+    }
+
+    pub fn max_attempts(&self) -> u8 {
+        self.max_attempts.unwrap_or(1)
+    }
+
+    /// Milliseconds to wait before the retry, the first one being `1`, without the jitter.
+    pub fn backoff_ms(&self, retry: u32) -> u64 {
+        let backoff = self.backoff_ms.unwrap_or(DEFAULT_RETRY_BACKOFF_MS);
+        let max = self.max_backoff_ms.unwrap_or(DEFAULT_MAX_BACKOFF_MS);
+        backoff
+            .saturating_mul(1 << retry.saturating_sub(1).min(32))
+            .min(max)
+    }
+
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        if self.max_attempts == Some(0) {
+            errors.add(
+                format!("{}.max_attempts", field),
+                "At least one attempt must be made",
+            );
+        }
+        if self
+            .jitter
+            .map_or(false, |j| !j.is_finite() || !(0.0..=1.0).contains(&j))
+        {
+            errors.add(
+                format!("{}.jitter", field),
+                "Jitter must be between 0 and 1",
+            );
+        }
+        if self.attempt_timeout_ms == Some(0) {
+            errors.add(
+                format!("{}.attempt_timeout_ms", field),
+                "Timeout must be a positive number of milliseconds",
+            );
+        }
+        if let Some(breaker) = self.circuit_breaker.as_ref() {
+            if breaker.failures == 0 {
+                errors.add(
+                    format!("{}.circuit_breaker.failures", field),
+                    "At least one failure must open the circuit",
+                );
+            }
+            if !breaker.pause_seconds.is_finite() || breaker.pause_seconds <= 0.0 {
+                errors.add(
+                    format!("{}.circuit_breaker.pause_seconds", field),
+                    "Pause must be a positive number of seconds",
+                );
+            }
+        }
+    }
+}
+
+/// Pauses an action once its executions failed `failures` times in a row, every attempt
+/// failing, the transitions being ignored for `pause_seconds`.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct CircuitBreaker {
+    pub failures: u32,
+    pub pause_seconds: f64,
+}
+
+// The pause is validated to be finite
+impl Eq for CircuitBreaker {}
+
+/// Schedules an action to run right away on an AWS Elemental MediaLive channel, with the
+/// credentials of the service account of the worker.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct MediaLiveCall {
+    pub channel_id: String,
+    /// AWS region of the channel, the region of the worker when missing.
+    pub region: Option<String>,
+    pub description: Option<String>,
+    pub schedule_action: MediaLiveScheduleAction,
+    /// Retries after the first attempt, without waiting, replaced by `retry`.
+    pub retries: Option<u8>,
+    pub retry: Option<RetryPolicy>,
+}
+
+impl MediaLiveCall {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        if self.channel_id.is_empty() || !self.channel_id.chars().all(|c| c.is_ascii_digit()) {
+            errors.add(
+                format!("{}.channel_id", field),
+                "Channel ID must be the numeric ID of a MediaLive channel",
+            );
+        }
+        validate_region(field, self.region.as_deref(), errors);
+        match &self.schedule_action {
+            MediaLiveScheduleAction::SpliceInsert { duration, .. } => {
+                if duration.map_or(false, |d| !d.is_finite() || d <= 0.0) {
+                    errors.add(
+                        format!("{}.schedule_action.duration", field),
+                        "Duration must be a positive number of seconds",
+                    );
+                }
+            }
+            MediaLiveScheduleAction::ReturnToNetwork { .. } => {}
+            MediaLiveScheduleAction::InputSwitch { input_attachment } => {
+                if input_attachment.trim().is_empty() {
+                    errors.add(
+                        format!("{}.schedule_action.input_attachment", field),
+                        "Input attachment name cannot be empty",
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn validate_region(field: &str, region: Option<&str>, errors: &mut ValidationErrors) {
+    if let Some(region) = region {
+        if region.is_empty()
+            || !region
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            errors.add(format!("{}.region", field), "Invalid AWS region");
+        }
+    }
+}
+
+/// Sends a message to an AWS SQS queue. The `{{name}}` variables of the message are replaced
+/// like in the HTTP calls.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct SqsMessage {
+    pub queue_url: String,
+    /// AWS region of the queue, the region of the worker when missing.
+    pub region: Option<String>,
+    pub description: Option<String>,
+    /// Body of the message, a JSON object with the variables when missing.
+    pub message: Option<String>,
+    /// Group of the messages of a FIFO queue.
+    pub message_group_id: Option<String>,
+    /// Retries after the first attempt, without waiting, replaced by `retry`.
+    pub retries: Option<u8>,
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Publishes a message to an AWS SNS topic. The `{{name}}` variables of the subject and the
+/// message are replaced like in the HTTP calls.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct SnsMessage {
+    pub topic_arn: String,
+    /// AWS region of the topic, the region of the worker when missing.
+    pub region: Option<String>,
+    pub description: Option<String>,
+    pub subject: Option<String>,
+    /// Body of the message, a JSON object with the variables when missing.
+    pub message: Option<String>,
+    /// Retries after the first attempt, without waiting, replaced by `retry`.
+    pub retries: Option<u8>,
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Publishes a record to a Kafka topic. The `{{name}}` variables of the key and the payload are
+/// replaced like in the HTTP calls.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct KafkaMessage {
+    /// Bootstrap brokers, `host:port`.
+    pub brokers: Vec<String>,
+    pub topic: String,
+    pub description: Option<String>,
+    /// Key of the record, choosing its partition, none when missing.
+    pub key: Option<String>,
+    /// Value of the record, a JSON object with the variables when missing.
+    pub payload: Option<String>,
+    /// Retries after the first attempt, without waiting, replaced by `retry`.
+    pub retries: Option<u8>,
+    pub retry: Option<RetryPolicy>,
+}
+
+impl KafkaMessage {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        if self.brokers.is_empty() {
+            errors.add(
+                format!("{}.brokers", field),
+                "At least one broker must be defined",
+            );
+        }
+        for (i, broker) in self.brokers.iter().enumerate() {
+            let valid = broker.rsplit_once(':').map_or(false, |(host, port)| {
+                !host.is_empty() && port.parse::<u16>().is_ok()
+            });
+            if !valid {
+                errors.add(
+                    format!("{}.brokers[{}]", field, i),
+                    "Broker must be a host and a port, like kafka:9092",
+                );
+            }
+        }
+        // Names Kafka allows for the topics
+        if self.topic.is_empty()
+            || self.topic.len() > 249
+            || !self
+                .topic
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+        {
+            errors.add(format!("{}.topic", field), "Invalid topic name");
+        }
+    }
+}
+
+/// Sends a SCTE-35 splice insert, a `splice_info_section` starting right away, to a packager API
+/// or a muxer.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Scte35Signal {
+    pub description: Option<String>,
+    /// `splice_insert` when missing.
+    pub command: Option<Scte35Command>,
+    /// Generated for a `splice_insert` and the last one started for a `return_to_network` when
+    /// missing.
+    pub splice_event_id: Option<u32>,
+    /// Seconds of the ad break of a `splice_insert`, until a `return_to_network` when missing.
+    pub duration: Option<f64>,
+    pub destination: Scte35Destination,
+}
+
+// The durations are validated to be finite
+impl Eq for Scte35Signal {}
+
+impl Scte35Signal {
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        if self.duration.map_or(false, |d| !d.is_finite() || d <= 0.0) {
+            errors.add(
+                format!("{}.duration", field),
+                "Duration must be a positive number of seconds",
+            );
+        }
+        match &self.destination {
+            Scte35Destination::Http(call) => {
+                if !is_valid_url(&call.url, &["http://", "https://"]) {
+                    errors.add(
+                        format!("{}.destination.url", field),
+                        format!("{} not recognized as a valid URL!", call.url),
+                    );
+                }
+                call.validate_authorization(&format!("{}.destination", field), errors);
+            }
+            Scte35Destination::Udp { address } => {
+                let valid = address.rsplit_once(':').map_or(false, |(host, port)| {
+                    !host.is_empty() && port.parse::<u16>().map_or(false, |port| port > 0)
+                });
+                if !valid {
+                    errors.add(
+                        format!("{}.destination.address", field),
+                        "Address must be a host and a port, like muxer:5500",
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scte35Command {
+    /// Starts an ad break, leaving the network.
+    SpliceInsert,
+    /// Ends an ad break.
+    ReturnToNetwork,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Scte35Destination {
+    /// Calls a packager API, the `{{scte35}}` variable of the body being the base64 encoded
+    /// section. The body is the section alone when missing.
+    Http(HttpCall),
+    /// Sends the binary section in a UDP datagram to a muxer.
+    Udp { address: String },
+}
+
+/// Environment variables the `env` of the exec actions cannot set: the ones of the worker, and
+/// the ones changing what the dynamic loader, the shells and the interpreters run.
+const RESERVED_ENV: &[&str] = &[
+    "PATH",
+    "IFS",
+    "ENV",
+    "BASH_ENV",
+    "SHELLOPTS",
+    "PS4",
+    "PYTHONPATH",
+    "PYTHONSTARTUP",
+    "PERL5LIB",
+    "PERL5OPT",
+    "RUBYOPT",
+    "NODE_OPTIONS",
+    "GCONV_PATH",
+];
+
+/// Prefixes of the environment variables the `env` of the exec actions cannot set.
+const RESERVED_ENV_PREFIXES: &[&str] = &["HAWKEYE_", "LD_", "DYLD_", "BASH_FUNC_"];
+
+/// Runs a command bundled in the worker image, without a shell, for the integrations without an
+/// HTTP API. The `{{name}}` variables of the arguments and the environment are replaced like in
+/// the HTTP calls.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct ExecCommand {
+    /// Absolute path of the command, which the allowlist of the API and the worker must contain.
+    pub command: String,
+    pub args: Option<Vec<String>>,
+    pub description: Option<String>,
+    /// Environment of the command, besides `PATH`, the `HAWKEYE_*` variables and the other
+    /// `RESERVED_ENV`.
+    pub env: Option<HashMap<String, String>>,
+    /// Seconds the command can run before being killed, `DEFAULT_EXEC_TIMEOUT` when missing.
+    pub timeout: Option<u32>,
+}
+
+impl ExecCommand {
+    pub fn timeout(&self) -> u32 {
+        self.timeout.unwrap_or(DEFAULT_EXEC_TIMEOUT)
+    }
+
+    fn validate(&self, field: &str, errors: &mut ValidationErrors) {
+        if !self.command.starts_with('/')
+            || self.command.contains(char::is_whitespace)
+            || self.command.split('/').any(|part| part == "..")
+        {
+            errors.add(
+                format!("{}.command", field),
+                "Command must be an absolute path",
+            );
+        }
+        if !(1..=MAX_EXEC_TIMEOUT).contains(&self.timeout()) {
+            errors.add(
+                format!("{}.timeout", field),
+                format!("Timeout must be between 1 and {} seconds", MAX_EXEC_TIMEOUT),
+            );
+        }
+        for name in self.env.iter().flat_map(HashMap::keys) {
+            let valid = !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                errors.add(
+                    format!("{}.env.{}", field, name),
+                    "Invalid environment variable name",
+                );
+            } else if RESERVED_ENV.contains(&name.as_str())
+                || RESERVED_ENV_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+            {
+                errors.add(
+                    format!("{}.env.{}", field, name),
+                    "Environment variable reserved by the worker",
+                );
+            }
+        }
+    }
+
+    /// Whether an entry of the allowlist allows the command with its arguments. An entry is the
+    /// path of the command, allowing any arguments, or the path followed by the only arguments
+    /// allowed, separated by spaces, `*` allowing any value of an argument.
+    pub fn is_allowed(&self, allowlist: &[String]) -> bool {
+        let args = self.args.as_deref().unwrap_or_default();
+        allowlist.iter().any(|allowed| {
+            let mut parts = allowed.split_whitespace();
+            if parts.next() != Some(self.command.as_str()) {
+                return false;
+            }
+            let allowed_args: Vec<&str> = parts.collect();
+            allowed_args.is_empty()
+                || (allowed_args.len() == args.len()
+                    && allowed_args
+                        .iter()
+                        .zip(args)
+                        .all(|(allowed, arg)| *allowed == "*" || allowed == arg))
+        })
+    }
+}
+
+/// Action of the schedule of a MediaLive channel.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MediaLiveScheduleAction {
+    /// Starts an ad break with a SCTE-35 splice insert, lasting `duration` seconds, or until a
+    /// `return_to_network` when missing. The splice event ID is generated when missing.
+    SpliceInsert {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        splice_event_id: Option<u32>,
+    },
+    /// Ends an ad break, the last one started by the worker when `splice_event_id` is missing.
+    ReturnToNetwork {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        splice_event_id: Option<u32>,
+    },
+    /// Switches the channel to another of its inputs, by the name of its attachment.
+    InputSwitch { input_attachment: String },
+}
+
+// The durations are validated to be finite
+impl Eq for MediaLiveScheduleAction {}
+
 // #[cfg(test)]
 #[derive(Clone, Debug)]
 pub struct FakeAction {
@@ -139,19 +2867,40 @@ impl FakeAction {
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
 pub struct HttpCall {
     pub method: HttpMethod,
     pub url: String,
     pub description: Option<String>,
     pub authorization: Option<HttpAuth>,
+    /// Headers of the call, without credentials: those are taken from a `Secret`, by the
+    /// `authorization` or the `secret_headers`.
     pub headers: Option<HashMap<String, String>>,
+    /// Headers whose value is in the key of a Kubernetes `Secret`, as `name/key`, like an
+    /// `X-Api-Key`. The worker reads them like the `authorization`.
+    pub secret_headers: Option<HashMap<String, String>>,
     pub body: Option<String>,
+    /// Retries after the first attempt, without waiting, replaced by `retry`.
     pub retries: Option<u8>,
+    pub retry: Option<RetryPolicy>,
     pub timeout: Option<u32>,
+    pub signing: Option<HttpSigning>,
+}
+
+/// Signs the HTTP call with HMAC-SHA256, so its receiver checks it comes from the watcher: the
+/// signature covers the timestamp and the random nonce sent with the call, to refuse the old or
+/// replayed calls, and its body.
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
+pub struct HttpSigning {
+    /// Key of a Kubernetes `Secret` holding the key of the signature, as `name/key`.
+    #[serde(rename = "secretRef")]
+    pub secret_ref: String,
+    /// Header of the signature, `X-Hawkeye-Signature` when missing.
+    pub header: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, JsonSchema, Copy, Clone, Debug, Eq, PartialEq)]
 pub enum HttpMethod {
     POST,
     GET,
@@ -169,18 +2918,164 @@ impl ToString for HttpMethod {
             HttpMethod::PATCH => "PATCH".to_string(),
             HttpMethod::DELETE => "DELETE".to_string(),
         }
-    }
+    }
+}
+
+impl HttpCall {
+    fn validate_authorization(&self, field: &str, errors: &mut ValidationErrors) {
+        if let Some(HttpAuth::SecretRef(reference)) = self.authorization.as_ref() {
+            if !is_valid_secret_ref(reference) {
+                errors.add(
+                    format!("{}.authorization.secretRef", field),
+                    "The reference must be the name of a Secret and one of its keys, like name/key",
+                );
+            }
+        }
+        let mut headers: Vec<&String> = self.headers.iter().flat_map(|h| h.keys()).collect();
+        headers.sort_unstable();
+        for name in headers
+            .into_iter()
+            .filter(|name| is_credential_header(name))
+        {
+            errors.add(
+                format!("{}.headers.{}", field, name),
+                "Credentials must be taken from a Secret, with the authorization or the \
+                 secret_headers of the call",
+            );
+        }
+        let mut secret_headers: Vec<(&String, &String)> =
+            self.secret_headers.iter().flatten().collect();
+        secret_headers.sort_unstable();
+        for (name, reference) in secret_headers {
+            if !is_valid_secret_ref(reference) {
+                errors.add(
+                    format!("{}.secret_headers.{}", field, name),
+                    "The reference must be the name of a Secret and one of its keys, like name/key",
+                );
+            }
+        }
+        if let Some(signing) = self.signing.as_ref() {
+            if !is_valid_secret_ref(&signing.secret_ref) {
+                errors.add(
+                    format!("{}.signing.secretRef", field),
+                    "The reference must be the name of a Secret and one of its keys, like name/key",
+                );
+            }
+            let valid_header = signing.header.as_deref().map_or(true, |header| {
+                !header.is_empty()
+                    && header
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            });
+            if !valid_header {
+                errors.add(
+                    format!("{}.signing.header", field),
+                    "The header must be made of letters, digits, '-' or '_'",
+                );
+            }
+        }
+    }
+
+    /// Names of the `Secret`s of the authorization, the secret headers and the signature of the
+    /// call.
+    pub fn secret_names(&self) -> Vec<&str> {
+        let authorization = match self.authorization.as_ref() {
+            Some(HttpAuth::SecretRef(reference)) => Some(reference.as_str()),
+            _ => None,
+        };
+        let headers = self.secret_headers.iter().flat_map(|h| h.values());
+        let signing = self.signing.as_ref().map(|s| s.secret_ref.as_str());
+        authorization
+            .into_iter()
+            .chain(headers.map(String::as_str))
+            .chain(signing)
+            .filter_map(|reference| reference.split_once('/'))
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+/// Whether the header carries credentials, which are not stored in plain text with the watcher.
+fn is_credential_header(name: &str) -> bool {
+    let name = name.to_lowercase();
+    matches!(
+        name.as_str(),
+        "authorization" | "proxy-authorization" | "cookie"
+    ) || ["token", "secret", "password", "api-key", "apikey"]
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+/// Whether the value references the key of a `Secret`, as `name/key`.
+fn is_valid_secret_ref(reference: &str) -> bool {
+    reference.split_once('/').map_or(false, |(name, key)| {
+        is_valid_secret_name(name)
+            && !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    })
+}
+
+/// Whether the value is a `host:port` address, the host being a hostname, an IPv4 address or an
+/// IPv6 address in brackets. It is put in the pipeline of the worker, so nothing else is allowed.
+fn is_valid_host_port(address: &str) -> bool {
+    let (host, port) = match address.rsplit_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let valid_port = port.chars().all(|c| c.is_ascii_digit())
+        && port.parse::<u16>().map(|port| port > 0).unwrap_or(false);
+    let valid_host = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(ipv6) => ipv6.parse::<std::net::Ipv6Addr>().is_ok(),
+        None => {
+            host.parse::<std::net::Ipv4Addr>().is_ok()
+                || (!host.is_empty()
+                    && host.len() <= 253
+                    && host.split('.').all(|label| {
+                        !label.is_empty()
+                            && label.len() <= 63
+                            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                            && !label.starts_with('-')
+                            && !label.ends_with('-')
+                    }))
+        }
+    };
+    valid_port && valid_host
+}
+
+/// Whether the value is the name of a Kubernetes object, a DNS subdomain.
+fn is_valid_secret_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name
+            .split('.')
+            .all(|part| !part.is_empty() && is_valid_label(part) && !part.contains('_'))
+        && name == name.to_lowercase()
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+/// Directory where the `Secret`s referenced by the actions are mounted in the worker, one
+/// directory for each of them with a file for each key.
+pub const SECRETS_DIR: &str = "/secrets";
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum HttpAuth {
-    Basic { username: String, password: String },
+    Basic {
+        username: String,
+        password: String,
+    },
+    /// Value of the `Authorization` header, like `Bearer <token>`, in the key of a Kubernetes
+    /// `Secret`, as `name/key`. The API mounts the `Secret` in the worker, which reads it when
+    /// the action runs, so the credentials are not stored in the definition of the watcher.
+    #[serde(rename = "secretRef")]
+    SecretRef(String),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
     use pretty_assertions::assert_eq;
     use std::fs::File;
     use std::io::Read;
@@ -188,17 +3083,22 @@ mod tests {
     fn get_watcher() -> Watcher {
         Watcher {
             id: Some("ee21fc9a-7225-450b-a2a7-2faf914e35b8".to_string()),
+            name: Some("lyon-bayern".to_string()),
             description: Some("UEFA 2020 - Lyon vs. Bayern".to_string()),
             slate_url: "file://./resources/slate_120px.jpg".to_string(),
+            slates: None,
             status: Some(Status::Running),
             status_description: None,
             source: Source {
                 ingest_ip: None,
-                ingest_port: 5000,
+                ingest_port: Some(5000),
                 container: Container::MpegTs,
                 codec: Codec::H264,
-                transport: Protocol::Rtp
+                transport: Protocol::Rtp { fec: None, second_leg_port: None },
+                multicast: None,
+                bitrate_kbps: None
             },
+            backup_sources: None,
             transitions: vec![
                 Transition {
                     from: VideoMode::Content,
@@ -213,11 +3113,20 @@ mod tests {
                                 password: "something".to_string()
                             }),
                             headers: Some([("Content-Type", "application/json")].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<String, String>>()),
+                            secret_headers: None,
                             body: Some("{\"duration\":300}".to_string()),
                             retries: Some(3),
+                            retry: None,
                             timeout: Some(10),
+                            signing: None,
                         })
-                    ]
+                    ],
+                    audio: None,
+                    condition: None,
+                    hysteresis: None,
+                    min_match_duration_ms: None,
+                    cooldown_ms: None,
+                    active: None,
                 },
                 Transition {
                     from: VideoMode::Slate,
@@ -232,13 +3141,43 @@ mod tests {
                                 password: "something".to_string()
                             }),
                             headers: None,
+                            secret_headers: None,
                             body: None,
                             retries: None,
+                            retry: None,
                             timeout: Some(10),
+                            signing: None,
                         })
-                    ]
+                    ],
+                    audio: None,
+                    condition: None,
+                    hysteresis: None,
+                    min_match_duration_ms: None,
+                    cooldown_ms: None,
+                    active: None,
                 }
-            ]
+            ],
+            tags: None,
+            resources: None,
+            analysis: None,
+            scheduling: None,
+            worker_image: None,
+            suspended: None,
+            deleted_at: None,
+            schedule: None,
+            namespace: None,
+            packed: None,
+            pack: None,
+            black: None,
+            freeze: None,
+            failover: None,
+            stream_loss: None,
+            state_machine: None,
+            mode: None,
+            archive: None,
+            capture: None,
+            last_heartbeat: None,
+            stale: None,
         }
     }
 
@@ -249,6 +3188,74 @@ mod tests {
 
         w.slate_url = String::from("something else");
         assert!(w.is_valid().is_err());
+
+        w.slate_url = String::from("slate://4b2e8b5c-0d6e-4c8a-9f3a-1f2d3c4b5a69");
+        assert!(w.is_valid().is_ok());
+    }
+
+    #[test]
+    fn slate_changes_do_not_require_restart() {
+        let previous = get_watcher();
+        let mut w = previous.clone();
+        w.slate_url = String::from("slate://4b2e8b5c-0d6e-4c8a-9f3a-1f2d3c4b5a69");
+        w.transitions.clear();
+        w.mode = Some(WatcherMode::Monitor);
+        assert!(!w.requires_restart(&previous));
+
+        let mut w = previous.clone();
+        if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+            call.authorization = Some(HttpAuth::SecretRef("ad-server/token".to_string()));
+        }
+        assert!(w.requires_restart(&previous));
+
+        w.source.ingest_port = Some(5001);
+        assert!(w.requires_restart(&previous));
+    }
+
+    #[test]
+    fn check_threshold_change() {
+        let w = get_watcher();
+        let change = ThresholdChange {
+            slate_id: None,
+            threshold: 0.5,
+        };
+        assert!(change.validate(&w).is_ok());
+
+        let change = ThresholdChange {
+            slate_id: Some("network".to_string()),
+            threshold: -1.0,
+        };
+        let errors = change.validate(&w).unwrap_err();
+        assert_eq!(errors.errors[0].field, "slate_id");
+        assert_eq!(errors.errors[1].field, "threshold");
+    }
+
+    #[test]
+    fn check_transition_trigger() {
+        let w = get_watcher();
+        let trigger = TransitionTrigger {
+            slate_id: None,
+            from: VideoMode::Content,
+            to: VideoMode::Slate,
+        };
+        assert!(trigger.validate(&w).is_ok());
+        assert_eq!(trigger.transitions(&w).unwrap()[0].actions.len(), 1);
+
+        let trigger = TransitionTrigger {
+            slate_id: None,
+            from: VideoMode::Content,
+            to: VideoMode::Black,
+        };
+        let errors = trigger.validate(&w).unwrap_err();
+        assert_eq!(errors.errors[0].field, "to");
+
+        let trigger = TransitionTrigger {
+            slate_id: Some("black".to_string()),
+            from: VideoMode::Content,
+            to: VideoMode::Black,
+        };
+        let errors = trigger.validate(&w).unwrap_err();
+        assert_eq!(errors.errors[0].field, "slate_id");
     }
 
     #[test]
@@ -256,10 +3263,1150 @@ mod tests {
         let mut w = get_watcher();
         assert!(w.is_valid().is_ok());
 
-        w.source.ingest_port = 1000;
+        w.source.ingest_port = Some(1000);
         assert!(w.is_valid().is_err());
     }
 
+    #[test]
+    fn check_transitions_are_not_empty() {
+        let mut w = get_watcher();
+        w.transitions.clear();
+
+        let errors = w.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "transitions");
+    }
+
+    #[test]
+    fn check_name_is_readable() {
+        let mut w = get_watcher();
+        w.name = Some("sports-east.1".to_string());
+        assert!(w.validate().is_ok());
+
+        for name in &["", "Sports East", "-sports", "sports/east"] {
+            w.name = Some(name.to_string());
+            let errors = w.validate().unwrap_err();
+            assert_eq!(errors.errors[0].field, "name");
+        }
+    }
+
+    #[test]
+    fn check_transitions_do_not_overlap() {
+        let mut w = get_watcher();
+        w.transitions.push(w.transitions[0].clone());
+
+        let errors = w.validate().unwrap_err();
+        assert_eq!(errors.errors.len(), 1);
+        assert_eq!(errors.errors[0].field, "transitions[2]");
+    }
+
+    #[test]
+    fn check_action_url_is_url() {
+        let mut w = get_watcher();
+        if let Action::HttpCall(call) = &mut w.transitions[1].actions[0] {
+            call.url = String::from("non-existent.cbs.com/ad-break");
+        }
+
+        let errors = w.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "transitions[1].actions[0].url");
+    }
+
+    #[test]
+    fn check_action_secret_refs() {
+        let mut w = get_watcher();
+        let reference: HttpAuth =
+            serde_json::from_str(r#"{"secretRef": "ad-server.credentials/token"}"#).unwrap();
+        assert_eq!(
+            reference,
+            HttpAuth::SecretRef("ad-server.credentials/token".to_string())
+        );
+        for (i, transition) in w.transitions.iter_mut().enumerate() {
+            if let Action::HttpCall(call) = &mut transition.actions[0] {
+                call.authorization = Some(HttpAuth::SecretRef(format!("ad-server/token-{}", i)));
+            }
+        }
+        assert!(w.validate().is_ok());
+        assert_eq!(w.secret_names(), vec!["ad-server"]);
+
+        if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+            call.signing = Some(HttpSigning {
+                secret_ref: "webhooks/hmac".to_string(),
+                header: Some("X-Signature".to_string()),
+            });
+        }
+        assert!(w.validate().is_ok());
+        assert_eq!(w.secret_names(), vec!["ad-server", "webhooks"]);
+        if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+            call.signing = Some(HttpSigning {
+                secret_ref: "webhooks".to_string(),
+                header: Some("X Signature".to_string()),
+            });
+        }
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[0].actions[0].signing.secretRef",
+                "transitions[0].actions[0].signing.header"
+            ]
+        );
+        if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+            call.signing = None;
+        }
+
+        for reference in &[
+            "ad-server",
+            "Ad-Server/token",
+            "ad-server/",
+            "ad_server/token",
+        ] {
+            if let Action::HttpCall(call) = &mut w.transitions[1].actions[0] {
+                call.authorization = Some(HttpAuth::SecretRef(reference.to_string()));
+            }
+            let errors = w.validate().unwrap_err();
+            assert_eq!(
+                errors.errors[0].field,
+                "transitions[1].actions[0].authorization.secretRef"
+            );
+        }
+    }
+
+    #[test]
+    fn check_trigger_secret_refs() {
+        let mut w = get_watcher();
+        w.backup_sources = serde_json::from_value(serde_json::json!([
+            {"ingest_port": 5010, "container": "mpeg-ts", "codec": "h264", "transport": {"protocol": "rtp"}}
+        ]))
+        .unwrap();
+        w.failover = serde_json::from_value(serde_json::json!({
+            "duration": 5,
+            "transitions": [{"from": "content", "to": "backup", "actions": [{
+                "type": "http_call", "method": "POST", "url": "http://noc/failover",
+                "authorization": {"secretRef": "noc/token"}
+            }]}]
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert_eq!(w.secret_names(), vec!["noc"]);
+        let actions = w.actions();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[2].0, "failover.transitions[0].actions[0]");
+    }
+
+    #[test]
+    fn check_stream_loss_actions() {
+        let mut w = get_watcher();
+        w.stream_loss = serde_json::from_value(serde_json::json!({
+            "duration": 3,
+            "transitions": [
+                {"from": "content", "to": "lost", "actions": [
+                    {"type": "exec", "command": "/opt/hawkeye/scripts/page.sh"}
+                ]},
+                {"from": "lost", "to": "content", "actions": [{
+                    "type": "http_call", "method": "POST", "url": "http://noc/recovered",
+                    "authorization": {"secretRef": "noc/token"}
+                }]}
+            ]
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert_eq!(w.secret_names(), vec!["noc"]);
+        let fields: Vec<String> = w.actions().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[0].actions[0]",
+                "transitions[1].actions[0]",
+                "stream_loss.transitions[0].actions[0]",
+                "stream_loss.transitions[1].actions[0]"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_action_credential_headers() {
+        let mut w = get_watcher();
+        let header = |name: &str, value: &str| {
+            [(name.to_string(), value.to_string())]
+                .iter()
+                .cloned()
+                .collect::<HashMap<String, String>>()
+        };
+        for name in &["Authorization", "Cookie", "X-Api-Key", "X-Auth-Token"] {
+            if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+                call.headers = Some(header(name, "abc"));
+            }
+            let errors = w.validate().unwrap_err();
+            assert_eq!(
+                errors.errors[0].field,
+                format!("transitions[0].actions[0].headers.{}", name)
+            );
+        }
+
+        if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+            call.headers = Some(header("Content-Type", "application/json"));
+            call.secret_headers = Some(header("X-Api-Key", "ad-server/api-key"));
+        }
+        assert!(w.validate().is_ok());
+        assert_eq!(w.secret_names(), vec!["ad-server"]);
+
+        if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+            call.secret_headers = Some(header("X-Api-Key", "abc"));
+        }
+        let errors = w.validate().unwrap_err();
+        assert_eq!(
+            errors.errors[0].field,
+            "transitions[0].actions[0].secret_headers.X-Api-Key"
+        );
+    }
+
+    #[test]
+    fn check_slates() {
+        let mut w = get_watcher();
+        let slate = Slate {
+            id: "network".to_string(),
+            url: "https://example.com/network.jpg".to_string(),
+            threshold: Some(0.5),
+            detector: None,
+            region: None,
+            text: None,
+            transitions: None,
+        };
+        w.slates = Some(vec![
+            slate.clone(),
+            Slate {
+                threshold: Some(-1.0),
+                ..slate.clone()
+            },
+            Slate {
+                id: "sports".to_string(),
+                threshold: Some(2.0),
+                detector: Some(Detector::Phash),
+                region: Some(Region {
+                    x: 50.0,
+                    y: 0.0,
+                    width: 60.0,
+                    height: 100.0,
+                }),
+                ..slate.clone()
+            },
+            Slate {
+                id: DEFAULT_SLATE_ID.to_string(),
+                transitions: Some(vec![]),
+                ..slate.clone()
+            },
+            Slate {
+                id: "brb".to_string(),
+                text: Some(vec!["BE RIGHT BACK".to_string()]),
+                ..slate.clone()
+            },
+            Slate {
+                id: "message".to_string(),
+                detector: Some(Detector::Ocr),
+                text: Some(vec![" ".to_string()]),
+                ..slate
+            },
+        ]);
+
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "slates[1].id",
+                "slates[1].threshold",
+                "slates[2].threshold",
+                "slates[2].region",
+                "slates[3].id",
+                "slates[3].transitions",
+                "slates[4].text",
+                "slates[5].text"
+            ]
+        );
+
+        w.slates.as_mut().unwrap().truncate(1);
+        let slates = w.all_slates();
+        assert_eq!(slates.len(), 2);
+        assert_eq!(slates[0].id, DEFAULT_SLATE_ID);
+        assert_eq!(slates[1].transitions.as_ref(), Some(&w.transitions));
+    }
+
+    #[test]
+    fn check_triggers() {
+        let mut w = get_watcher();
+        w.black = Some(Trigger {
+            duration: Some(1.5),
+            threshold: None,
+            transitions: vec![Transition {
+                from: VideoMode::Content,
+                to: VideoMode::Black,
+                actions: vec![],
+                audio: None,
+                condition: None,
+                hysteresis: None,
+                min_match_duration_ms: None,
+                cooldown_ms: None,
+                active: None,
+            }],
+        });
+        assert!(w.validate().is_ok());
+
+        w.freeze = Some(Trigger {
+            duration: Some(-1.0),
+            threshold: Some(2.0),
+            transitions: vec![Transition {
+                from: VideoMode::Content,
+                to: VideoMode::Black,
+                actions: vec![],
+                audio: None,
+                condition: None,
+                hysteresis: None,
+                min_match_duration_ms: None,
+                cooldown_ms: None,
+                active: None,
+            }],
+        });
+        w.transitions[0].to = VideoMode::Frozen;
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[0]",
+                "freeze.duration",
+                "freeze.threshold",
+                "freeze.transitions[0]"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_conditions() {
+        let mut w = get_watcher();
+        let condition: Condition = serde_json::from_value(serde_json::json!({
+            "type": "all",
+            "conditions": [
+                {"type": "slate"},
+                {"type": "at_least", "count": 1, "conditions": [
+                    {"type": "silence", "threshold": -60},
+                    {"type": "time_window", "start": "22:00", "end": "06:00"}
+                ]}
+            ]
+        }))
+        .unwrap();
+        w.transitions[0].condition = Some(condition);
+        w.transitions[0].hysteresis = Some(Hysteresis {
+            enter_frames: Some(5),
+            exit_frames: None,
+        });
+        assert!(w.validate().is_ok());
+
+        w.transitions[1].condition = Some(Condition::AtLeast {
+            count: 2,
+            conditions: vec![
+                Condition::Black,
+                Condition::TimeWindow {
+                    start: "24:00".to_string(),
+                    end: "6:00".to_string(),
+                },
+            ],
+        });
+        w.transitions[1].hysteresis = Some(Hysteresis {
+            enter_frames: Some(0),
+            exit_frames: None,
+        });
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[1].condition.conditions[1].start",
+                "transitions[1].condition.conditions[1].end",
+                "transitions[1].hysteresis.enter_frames"
+            ]
+        );
+        assert_eq!(minutes_of_day("23:30"), Some(1410));
+    }
+
+    #[test]
+    fn check_active_windows() {
+        let mut w = get_watcher();
+        let overnight = ActiveWindow {
+            start: Some("22:00".to_string()),
+            end: Some("06:00".to_string()),
+            timezone: Some("America/New_York".to_string()),
+            ..ActiveWindow::default()
+        };
+        let weekdays = ActiveWindow {
+            cron: Some("* 9-17 * * MON-FRI".to_string()),
+            ..ActiveWindow::default()
+        };
+        w.transitions[0].active = Some(vec![overnight.clone(), weekdays.clone()]);
+        assert!(w.validate().is_ok());
+
+        // Wednesday 1 December 2021, New York being 5 hours behind UTC
+        let at = |h, m| Utc.ymd(2021, 12, 1).and_hms(h, m, 30);
+        assert!(overnight.contains(at(3, 0)));
+        assert!(overnight.contains(at(10, 59)));
+        assert!(!overnight.contains(at(11, 0)));
+        assert!(weekdays.contains(at(9, 0)));
+        assert!(weekdays.contains(at(17, 59)));
+        assert!(!weekdays.contains(at(18, 0)));
+        assert!(!weekdays.contains(Utc.ymd(2021, 12, 4).and_hms(12, 0, 0)));
+
+        w.transitions[1].active = Some(vec![
+            ActiveWindow {
+                cron: Some("every minute".to_string()),
+                timezone: Some("Mars/Olympus".to_string()),
+                ..ActiveWindow::default()
+            },
+            ActiveWindow {
+                start: Some("22:00".to_string()),
+                ..weekdays
+            },
+        ]);
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[1].active[0].cron",
+                "transitions[1].active[0].timezone",
+                "transitions[1].active[1]"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_cron_days_of_week() {
+        use chrono::Datelike;
+        use chrono::Weekday::{self, Fri, Mon, Sat, Sun, Thu, Tue, Wed};
+
+        // The days of the week from Sunday 5 December 2021
+        let days = |expression: &str| {
+            let sunday = Utc.ymd(2021, 12, 5).and_hms(0, 0, 0);
+            parse_cron(expression)
+                .unwrap()
+                .after(&(sunday - chrono::Duration::seconds(1)))
+                .take_while(|t| *t < sunday + chrono::Duration::days(7))
+                .map(|t| t.weekday())
+                .collect::<Vec<Weekday>>()
+        };
+        assert_eq!(days("0 12 * * 0"), vec![Sun]);
+        assert_eq!(days("0 12 * * 7"), vec![Sun]);
+        assert_eq!(days("0 12 * * 1-5"), vec![Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(days("0 12 * * MON-FRI"), vec![Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(days("0 12 * * 5-7"), vec![Sun, Fri, Sat]);
+        assert_eq!(days("0 12 * * 1,WED,5"), vec![Mon, Wed, Fri]);
+        assert_eq!(days("0 12 * * */2"), vec![Sun, Tue, Thu, Sat]);
+        assert_eq!(days("0 12 * * 1/2"), vec![Mon, Wed, Fri]);
+        assert_eq!(days("0 0 12 * * 6"), vec![Sat]);
+
+        for expression in &["0 12 * * 8", "0 12 * * 5-1", "0 12 * * */0", "0 12 * * 1-x"] {
+            assert!(parse_cron(expression).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn check_media_live_actions() {
+        let mut w = get_watcher();
+        let action: Action = serde_json::from_value(serde_json::json!({
+            "type": "media_live",
+            "channel_id": "1234567",
+            "region": "us-west-2",
+            "schedule_action": {"type": "splice_insert", "duration": 120}
+        }))
+        .unwrap();
+        w.transitions[0].actions.push(action);
+        assert!(w.validate().is_ok());
+
+        w.transitions[1]
+            .actions
+            .push(Action::MediaLive(MediaLiveCall {
+                channel_id: "my-channel".to_string(),
+                region: None,
+                description: None,
+                schedule_action: MediaLiveScheduleAction::InputSwitch {
+                    input_attachment: " ".to_string(),
+                },
+                retries: None,
+                retry: None,
+            }));
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[1].actions[1].channel_id",
+                "transitions[1].actions[1].schedule_action.input_attachment"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_message_actions() {
+        let mut w = get_watcher();
+        let actions: Vec<Action> = serde_json::from_value(serde_json::json!([
+            {"type": "sqs", "queue_url": "https://sqs.us-east-1.amazonaws.com/123456789012/slates"},
+            {"type": "sns", "topic_arn": "arn:aws:sns:us-east-1:123456789012:slates", "subject": "Slate {{slate_id}}"},
+            {"type": "kafka", "brokers": ["kafka-0:9092", "kafka-1:9092"], "topic": "slates", "key": "{{watcher_id}}"}
+        ]))
+        .unwrap();
+        w.transitions[0].actions.extend(actions);
+        assert!(w.validate().is_ok());
+
+        w.transitions[1].actions = serde_json::from_value(serde_json::json!([
+            {"type": "sqs", "queue_url": "slates", "region": "us east"},
+            {"type": "sns", "topic_arn": "slates"},
+            {"type": "kafka", "brokers": ["kafka"], "topic": "slates/events"}
+        ]))
+        .unwrap();
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[1].actions[0].queue_url",
+                "transitions[1].actions[0].region",
+                "transitions[1].actions[1].topic_arn",
+                "transitions[1].actions[2].brokers[0]",
+                "transitions[1].actions[2].topic"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_scte35_actions() {
+        let mut w = get_watcher();
+        let actions: Vec<Action> = serde_json::from_value(serde_json::json!([
+            {"type": "scte35", "duration": 30, "destination": {
+                "type": "http", "method": "POST", "url": "http://packager/cues",
+                "body": "{\"cue\": \"{{scte35}}\"}"
+            }},
+            {"type": "scte35", "command": "return_to_network", "destination": {
+                "type": "udp", "address": "muxer:5500"
+            }}
+        ]))
+        .unwrap();
+        w.transitions[0].actions.extend(actions);
+        assert!(w.validate().is_ok());
+
+        w.transitions[1].actions = serde_json::from_value(serde_json::json!([
+            {"type": "scte35", "duration": 0, "destination": {
+                "type": "http", "method": "POST", "url": "packager/cues"
+            }},
+            {"type": "scte35", "destination": {"type": "udp", "address": "muxer"}}
+        ]))
+        .unwrap();
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[1].actions[0].duration",
+                "transitions[1].actions[0].destination.url",
+                "transitions[1].actions[1].destination.address"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_exec_actions() {
+        let mut w = get_watcher();
+        let action: Action = serde_json::from_value(serde_json::json!({
+            "type": "exec",
+            "command": "/opt/hawkeye/scripts/notify.sh",
+            "args": ["--slate", "{{slate_id}}"],
+            "env": {"CHANNEL": "{{watcher_id}}"},
+            "timeout": 30
+        }))
+        .unwrap();
+        w.transitions[0].actions.push(action);
+        assert!(w.validate().is_ok());
+        let actions = w.actions();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[1].0, "transitions[0].actions[1]");
+
+        w.transitions[1].actions = serde_json::from_value(serde_json::json!([
+            {"type": "exec", "command": "/opt/hawkeye/../../bin/sh", "timeout": 0},
+            {"type": "exec", "command": "notify.sh", "env": {"PATH": "/tmp"}},
+            {"type": "exec", "command": "/bin/true", "env": {"LD_PRELOAD": "/tmp/a.so"}},
+            {"type": "exec", "command": "/bin/true", "env": {"BASH_ENV": "/tmp/a"}}
+        ]))
+        .unwrap();
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[1].actions[0].command",
+                "transitions[1].actions[0].timeout",
+                "transitions[1].actions[1].command",
+                "transitions[1].actions[1].env.PATH",
+                "transitions[1].actions[2].env.LD_PRELOAD",
+                "transitions[1].actions[3].env.BASH_ENV"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_exec_allowlist() {
+        let command: ExecCommand = serde_json::from_value(serde_json::json!({
+            "command": "/opt/hawkeye/scripts/notify.sh",
+            "args": ["--slate", "{{slate_id}}"]
+        }))
+        .unwrap();
+        let allowlist = |entries: &[&str]| -> Vec<String> {
+            entries.iter().map(|entry| entry.to_string()).collect()
+        };
+        assert!(command.is_allowed(&allowlist(&["/opt/hawkeye/scripts/notify.sh"])));
+        assert!(command.is_allowed(&allowlist(&["/opt/hawkeye/scripts/notify.sh --slate *"])));
+        assert!(command.is_allowed(&allowlist(&[
+            "/opt/hawkeye/scripts/notify.sh --slate network",
+            "/opt/hawkeye/scripts/notify.sh --slate {{slate_id}}"
+        ])));
+        assert!(!command.is_allowed(&allowlist(&["/opt/hawkeye/scripts/notify.sh --slate"])));
+        assert!(!command.is_allowed(&allowlist(&["/opt/hawkeye/scripts/notify.sh --channel *"])));
+        assert!(!command.is_allowed(&allowlist(&["/opt/hawkeye/scripts/notify"])));
+        assert!(!command.is_allowed(&[]));
+    }
+
+    #[test]
+    fn check_retry_policies() {
+        let mut w = get_watcher();
+        if let Action::HttpCall(call) = &mut w.transitions[0].actions[0] {
+            call.retry = Some(
+                serde_json::from_value(serde_json::json!({
+                    "max_attempts": 5,
+                    "backoff_ms": 1000,
+                    "max_backoff_ms": 5000,
+                    "jitter": 0.2,
+                    "circuit_breaker": {"failures": 3, "pause_seconds": 60}
+                }))
+                .unwrap(),
+            );
+        }
+        assert!(w.validate().is_ok());
+        let retry = w.transitions[0].actions[0].retry_policy().unwrap();
+        assert_eq!(retry.max_attempts(), 5);
+        let backoffs: Vec<u64> = (1..=4).map(|retry_n| retry.backoff_ms(retry_n)).collect();
+        assert_eq!(backoffs, vec![1000, 2000, 4000, 5000]);
+        // The former `retries` are attempted right away
+        let retry = w.transitions[1].actions[0].retry_policy().unwrap();
+        assert_eq!(retry.max_attempts(), 1);
+        assert_eq!(retry.backoff_ms(1), 0);
+
+        if let Action::HttpCall(call) = &mut w.transitions[1].actions[0] {
+            call.retry = Some(
+                serde_json::from_value(serde_json::json!({
+                    "max_attempts": 0,
+                    "jitter": 2,
+                    "circuit_breaker": {"failures": 0, "pause_seconds": 60}
+                }))
+                .unwrap(),
+            );
+        }
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "transitions[1].actions[0].retry.max_attempts",
+                "transitions[1].actions[0].retry.jitter",
+                "transitions[1].actions[0].retry.circuit_breaker.failures"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_state_machine() {
+        let mut w = get_watcher();
+        let machine: StateMachine = serde_json::from_value(serde_json::json!({
+            "initial": "content",
+            "states": [
+                {"name": "content", "transitions": [
+                    {"to": "slate", "when": {"type": "slate"}}
+                ]},
+                {"name": "slate", "transitions": [
+                    {"to": "preroll", "when": {"type": "not", "condition": {"type": "slate"}}}
+                ]},
+                {"name": "preroll", "timeout": {"seconds": 10, "to": "content"}}
+            ]
+        }))
+        .unwrap();
+        w.state_machine = Some(machine);
+        w.transitions.clear();
+        assert!(w.validate().is_ok());
+
+        let machine = w.state_machine.as_mut().unwrap();
+        machine.initial = "live".to_string();
+        machine.states[1].transitions[0].to = "slate".to_string();
+        machine.states[2].name = "content".to_string();
+        machine.states[2].timeout = Some(StateTimeout {
+            seconds: 0.0,
+            to: "content".to_string(),
+        });
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "state_machine.initial",
+                "state_machine.states[1].transitions[0].to",
+                "state_machine.states[2].name",
+                "state_machine.states[2].timeout.seconds",
+                "state_machine.states[2].timeout.to"
+            ]
+        );
+
+        w.state_machine = None;
+        assert!(w.validate().is_err());
+    }
+
+    #[test]
+    fn region_to_pixels() {
+        let region = Region {
+            x: 10.0,
+            y: 50.0,
+            width: 50.0,
+            height: 50.0,
+        };
+        assert_eq!(region.to_pixels(213, 120), (21, 60, 107, 60));
+
+        let tiny = Region {
+            x: 100.0,
+            y: 0.0,
+            width: 0.1,
+            height: 0.1,
+        };
+        assert_eq!(tiny.to_pixels(213, 120), (212, 0, 1, 1));
+    }
+
+    #[test]
+    fn check_resources_are_quantities() {
+        let mut w = get_watcher();
+        w.resources = Some(Resources {
+            requests: Some(ResourceQuantities {
+                cpu: Some("500m".to_string()),
+                memory: Some("256Mi".to_string()),
+            }),
+            limits: Some(ResourceQuantities {
+                cpu: Some("2.5".to_string()),
+                memory: Some("lots".to_string()),
+            }),
+            gpus: None,
+        });
+
+        let errors = w.validate().unwrap_err();
+        assert_eq!(errors.errors.len(), 1);
+        assert_eq!(errors.errors[0].field, "resources.limits.memory");
+    }
+
+    #[test]
+    fn check_scheduling_operators() {
+        let mut w = get_watcher();
+        w.scheduling = Some(Scheduling {
+            node_selector: None,
+            tolerations: Some(vec![Toleration {
+                key: Some("multicast".to_string()),
+                operator: Some("Exists".to_string()),
+                effect: Some("NoWhere".to_string()),
+                ..Toleration::default()
+            }]),
+            affinity: Some(NodeAffinity {
+                required: Some(vec![NodeRequirement {
+                    key: "gpu".to_string(),
+                    operator: "Has".to_string(),
+                    values: None,
+                }]),
+                preferred: None,
+            }),
+        });
+
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "scheduling.tolerations[0].effect",
+                "scheduling.affinity.required[0].operator"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_fec_and_rist_sources() {
+        let mut w = get_watcher();
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "rtp",
+            "fec": "2d",
+            "second_leg_port": 5010
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert_eq!(w.source.ports(), vec![5000, 5002, 5004, 5010]);
+
+        w.source.transport = Protocol::Rtp {
+            fec: Some(Fec::OneDimension),
+            second_leg_port: Some(5002),
+        };
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["source.transport.second_leg_port"]);
+
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "rist",
+            "buffer_ms": 500
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert_eq!(w.source.ports(), vec![5000, 5001]);
+        w.source.ingest_port = Some(5001);
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["source.ingest_port"]);
+    }
+
+    #[test]
+    fn check_multicast_sources() {
+        let mut w = get_watcher();
+        w.source.transport = Protocol::Udp;
+        w.source.multicast = serde_json::from_value(serde_json::json!({
+            "group": "232.1.1.1",
+            "source_address": "10.0.0.12",
+            "interface": "eth1"
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert!(!w.source.is_pushed());
+
+        w.source.transport = Protocol::Rist { buffer_ms: None };
+        w.source.multicast = Some(Multicast {
+            group: "10.0.0.1".to_string(),
+            source_address: Some("sender".to_string()),
+            interface: Some("eth1; reboot".to_string()),
+        });
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "source.multicast.group",
+                "source.multicast.source_address",
+                "source.multicast.interface",
+                "source.multicast"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_backup_sources() {
+        let mut w = get_watcher();
+        w.backup_sources = serde_json::from_value(serde_json::json!([
+            {"ingest_port": 5010, "container": "mpeg-ts", "codec": "h264", "transport": {"protocol": "rtp"}},
+            {"ingest_port": 5020, "container": "mpeg-ts", "codec": "h264", "transport": {"protocol": "srt", "mode": "caller", "address": "backup.example.com:9000"}}
+        ]))
+        .unwrap();
+        w.failover = serde_json::from_value(serde_json::json!({
+            "duration": 5,
+            "transitions": [{"from": "content", "to": "backup", "actions": []}]
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert_eq!(w.ports(), vec![5000, 5010, 5020]);
+        assert_eq!(w.triggers()[0].0, FAILOVER_TRIGGER_ID);
+
+        let backups = w.backup_sources.as_mut().unwrap();
+        backups[0].ingest_port = Some(5020);
+        backups[1].transport = Protocol::Rtmp {
+            stream_key: "backup".to_string(),
+            tls: None,
+        };
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "backup_sources[1].container",
+                "backup_sources[1].transport",
+                "backup_sources"
+            ]
+        );
+
+        w.backup_sources = None;
+        let errors = w.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "failover");
+    }
+
+    #[test]
+    fn check_analysis() {
+        let mut w = get_watcher();
+        w.analysis = Some(Analysis {
+            fps: Some(2.0),
+            max_resolution: Some(480),
+            decoder: None,
+        });
+        assert!(w.validate().is_ok());
+
+        w.analysis = Some(Analysis {
+            fps: Some(60.0),
+            max_resolution: Some(60),
+            decoder: None,
+        });
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["analysis.fps", "analysis.max_resolution"]);
+    }
+
+    #[test]
+    fn check_packed_watchers() {
+        let mut w = get_watcher();
+        w.packed = Some(true);
+        assert!(w.validate().is_ok());
+        assert_eq!(w.bitrate_kbps(), DEFAULT_BITRATE_KBPS);
+        w.source.bitrate_kbps = Some(800);
+        assert_eq!(w.bitrate_kbps(), 800);
+        w.source.bitrate_kbps = Some(0);
+        assert_eq!(
+            w.validate().unwrap_err().errors[0].field,
+            "source.bitrate_kbps"
+        );
+        w.source.bitrate_kbps = None;
+
+        w.worker_image = Some("hawkeye-worker:canary".to_string());
+        w.resources = Some(Resources::default());
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["packed", "packed"]);
+    }
+
+    #[test]
+    fn check_frame_archive() {
+        let mut w = get_watcher();
+        w.archive = Some(FrameArchive {
+            url: "gs://incidents/hawkeye".to_string(),
+            region: None,
+            thumbnail_seconds: Some(10),
+        });
+        assert!(w.validate().is_ok());
+
+        w.archive = Some(FrameArchive {
+            url: "https://incidents.s3.amazonaws.com".to_string(),
+            region: None,
+            thumbnail_seconds: Some(120),
+        });
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["archive.url", "archive.thumbnail_seconds"]);
+    }
+
+    #[test]
+    fn check_clip_capture() {
+        let mut w = get_watcher();
+        w.capture = Some(ClipCapture {
+            buffer_seconds: 20,
+            after_seconds: None,
+        });
+        let errors = w.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "capture");
+
+        w.archive = Some(FrameArchive {
+            url: "s3://incidents".to_string(),
+            region: None,
+            thumbnail_seconds: None,
+        });
+        assert!(w.validate().is_ok());
+
+        w.capture = Some(ClipCapture {
+            buffer_seconds: 0,
+            after_seconds: Some(90),
+        });
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["capture.buffer_seconds", "capture.after_seconds"]
+        );
+
+        w.capture = Some(ClipCapture {
+            buffer_seconds: 20,
+            after_seconds: None,
+        });
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "hls",
+            "url": "https://cdn.example.com/live/master.m3u8"
+        }))
+        .unwrap();
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["capture"]);
+    }
+
+    #[test]
+    fn heartbeats_go_stale() {
+        let mut heartbeat = Heartbeat {
+            timestamp: "2021-12-01T23:00:00Z".to_string(),
+            frames_processed: 7200,
+            mode: Some(VideoMode::Content),
+            version: "0.1.0".to_string(),
+        };
+        let now = Utc.ymd(2021, 12, 1).and_hms(23, 1, 30);
+        assert!(!heartbeat.is_stale(now));
+        assert!(heartbeat.is_stale(now + chrono::Duration::seconds(1)));
+
+        heartbeat.timestamp = "now".to_string();
+        assert!(heartbeat.is_stale(now));
+    }
+
+    #[test]
+    fn check_gpus_need_a_hardware_decoder() {
+        let mut w = get_watcher();
+        w.resources = Some(Resources {
+            gpus: Some(1),
+            ..Resources::default()
+        });
+        let errors = w.validate().unwrap_err();
+        assert_eq!(errors.errors[0].field, "resources.gpus");
+
+        w.analysis = Some(Analysis {
+            decoder: Some(Decoder::Auto),
+            ..Analysis::default()
+        });
+        assert!(w.validate().is_err());
+
+        w.analysis = Some(Analysis {
+            decoder: Some(Decoder::Nvdec),
+            ..Analysis::default()
+        });
+        assert!(w.validate().is_ok());
+        assert_eq!(w.decoder(), Decoder::Nvdec);
+    }
+
+    #[test]
+    fn check_srt_sources() {
+        let mut w = get_watcher();
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "srt",
+            "passphrase": "0123456789abcdef",
+            "latency_ms": 500
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert!(w.source.is_pushed());
+
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "srt",
+            "mode": "caller",
+            "address": "encoder.example.com:9000"
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert!(!w.source.is_pushed());
+
+        for address in [
+            "10.0.0.1:9000",
+            "[::1]:9000",
+            "encoder.example.com",
+            "encoder.example.com:0",
+            "encoder:9000?mode=listener",
+            "a\" ! filesink location=/tmp/x \":9000",
+        ] {
+            w.source.transport = serde_json::from_value(serde_json::json!({
+                "protocol": "srt",
+                "mode": "caller",
+                "address": address
+            }))
+            .unwrap();
+            let valid = address == "10.0.0.1:9000" || address == "[::1]:9000";
+            assert_eq!(w.validate().is_ok(), valid, "{}", address);
+        }
+
+        w.source.container = Container::RawVideo;
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "srt",
+            "mode": "caller",
+            "passphrase": "short"
+        }))
+        .unwrap();
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "source.container",
+                "source.transport.address",
+                "source.transport.passphrase"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_rtmp_sources() {
+        let mut w = get_watcher();
+        w.source.container = Container::Flv;
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "rtmp",
+            "stream_key": "channel-1_main",
+            "tls": true
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert!(w.source.is_pushed());
+
+        w.source.container = Container::MpegTs;
+        w.source.transport = Protocol::Rtmp {
+            stream_key: "../live".to_string(),
+            tls: None,
+        };
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["source.container", "source.transport.stream_key"]
+        );
+    }
+
+    #[test]
+    fn check_pull_sources() {
+        let mut w = get_watcher();
+        w.source.transport = serde_json::from_value(serde_json::json!({
+            "protocol": "hls",
+            "url": "https://cdn.example.com/channel-1/index.m3u8"
+        }))
+        .unwrap();
+        assert!(w.validate().is_ok());
+        assert!(!w.source.is_pushed());
+        assert_eq!(
+            w.source.pull_url(),
+            Some("https://cdn.example.com/channel-1/index.m3u8")
+        );
+
+        w.source.transport = Protocol::Dash {
+            url: "ftp://cdn.example.com/channel-1/manifest.mpd".to_string(),
+            max_bitrate_kbps: Some(800),
+        };
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["source.transport.url"]);
+    }
+
+    #[test]
+    fn check_all_errors_are_reported() {
+        let mut w = get_watcher();
+        w.slate_url = String::from("something else");
+        w.source.ingest_port = Some(80);
+        w.tags = Some(
+            [("team", "sports!")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        );
+
+        let errors = w.validate().unwrap_err();
+        let fields: Vec<&str> = errors.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["slate_url", "source.ingest_port", "tags.team"]);
+    }
+
     #[test]
     fn deserialize_as_expected() {
         let mut fixture = File::open("../fixtures/watcher.json").expect("Fixture was not found!");
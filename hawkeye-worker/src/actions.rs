@@ -1,33 +1,182 @@
-use crate::metrics::{
-    HTTP_CALL_DURATION, HTTP_CALL_ERROR_COUNTER, HTTP_CALL_RETRIED_COUNT,
-    HTTP_CALL_RETRIES_EXHAUSTED_COUNT, HTTP_CALL_SUCCESS_COUNTER,
-};
+use crate::archive;
+use crate::audio;
+use crate::aws;
+use crate::conditions::{self, Facts, Settling};
+use crate::events;
+use crate::exec;
+use crate::kafka;
+use crate::machine::Machine;
+use crate::metrics::{transition_label, METRICS};
+use crate::retry;
+use crate::scte35;
+use crate::signing;
+use crate::state::{self, ActionRecord, STATE};
+use crate::template::{self, Variables};
 use crate::video_stream::Event;
+use chrono::Utc;
+use color_eyre::eyre::WrapErr;
 use color_eyre::Result;
 use crossbeam::channel::Receiver;
-use hawkeye_core::models::{self, Action, HttpAuth, HttpCall, VideoMode};
+use hawkeye_core::models::{
+    self, Action, ActiveWindow, AudioCondition, CircuitBreaker, Combine, Condition, HttpAuth,
+    HttpCall, Hysteresis, VideoMode, WatcherMode, BLACK_TRIGGER_ID, DEFAULT_COOLDOWN_MS,
+    DEFAULT_SLATE_ID, FAILOVER_TRIGGER_ID, FREEZE_TRIGGER_ID, SECRETS_DIR, STREAM_LOSS_TRIGGER_ID,
+};
 use log::{debug, error, info, warn};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::Path;
 use std::time::Duration;
+use tracing::field::Empty;
+use tracing::{info_span, Span};
 
 #[cfg(test)]
 use sn_fake_clock::FakeClock as Instant;
 #[cfg(not(test))]
 use std::time::Instant;
 
+thread_local! {
+    /// Status of the last response of an HTTP call made on the thread.
+    static LAST_STATUS: Cell<Option<u16>> = Cell::new(None);
+}
+
 /// Abstracts execution call for every action type.
-trait ActionExecution {
-    fn execute(&mut self) -> Result<()>;
+///
+/// The `labels` are the values of the `transition` and `action_type` labels of the metrics, and
+/// the `variables` are rendered in the action.
+pub(crate) trait ActionExecution {
+    fn execute(&mut self, labels: &[&str], variables: &Variables) -> Result<()>;
 }
 
 impl ActionExecution for Action {
-    fn execute(&mut self) -> Result<()> {
+    fn execute(&mut self, labels: &[&str], variables: &Variables) -> Result<()> {
         match self {
-            Action::HttpCall(a) => a.execute(),
+            Action::HttpCall(a) => a.execute(labels, variables),
+            Action::MediaLive(a) => aws::call_media_live(a, labels, variables),
+            Action::Sqs(a) => aws::send_sqs(a, labels, variables),
+            Action::Sns(a) => aws::publish_sns(a, labels, variables),
+            Action::Kafka(a) => kafka::publish(a, labels, variables),
+            Action::Scte35(a) => scte35::send(a, labels, variables),
+            Action::Exec(a) => exec::run(a, variables),
             Action::FakeAction(a) => a.execute(),
         }
     }
 }
 
+/// Outcome of an action, with what the history of the worker state keeps about it.
+struct Execution {
+    result: Result<()>,
+    status_code: Option<u16>,
+    latency_ms: u64,
+    retries: u32,
+}
+
+impl Execution {
+    /// Executes the action, measuring how long its attempts took.
+    fn measure(action: &mut Action, labels: &[&str], variables: &Variables) -> Self {
+        // Left over by an action whose execution panicked
+        LAST_STATUS.with(Cell::take);
+        retry::take_retries();
+        let started_ms = state::now_ms();
+        let result = action.execute(labels, variables);
+        Self {
+            result,
+            status_code: LAST_STATUS.with(Cell::take),
+            latency_ms: state::now_ms().saturating_sub(started_ms),
+            retries: retry::take_retries(),
+        }
+    }
+
+    fn record(&self, slate_id: &str, transition: &Transition, action: &Action) -> ActionRecord {
+        ActionRecord {
+            index: 0,
+            timestamp_ms: state::now_ms(),
+            slate_id: slate_id.to_string(),
+            from: transition.0,
+            to: transition.1,
+            action: action_name(action),
+            success: self.result.is_ok(),
+            error: self.result.as_ref().err().map(|err| format!("{:#}", err)),
+            status_code: self.status_code,
+            latency_ms: self.latency_ms,
+            retries: self.retries,
+            replay_of: None,
+            definition: Some(action.clone()),
+        }
+    }
+}
+
+/// Executes again the action of the history with the index, with the variables of the present
+/// time, and records it. `None` when the history no longer has the action.
+pub fn replay(index: u64) -> Option<ActionRecord> {
+    let original = STATE.lock().unwrap().action(index).cloned()?;
+    let mut action = original.definition?;
+    let transition = Transition(original.from, original.to);
+    info!(
+        "Replaying {} of {} on {}",
+        original.action,
+        original.slate_id,
+        transition_label(original.from, original.to)
+    );
+    let mut record = execute_now(&mut action, &original.slate_id, &transition);
+    record.replay_of = Some(index);
+    Some(record_action(record))
+}
+
+/// Executes the actions of the transitions of the slate or trigger right away, whatever the
+/// frames show and the mode of the watcher, and records them.
+pub fn fire(slate_id: &str, transitions: &[models::Transition]) -> Vec<ActionRecord> {
+    let mut records = Vec::new();
+    for definition in transitions {
+        let transition = Transition(definition.from, definition.to);
+        for action in definition.actions.iter() {
+            let mut action = action.clone();
+            info!(
+                "Firing {} of {} on {}",
+                action_name(&action),
+                slate_id,
+                transition_label(definition.from, definition.to)
+            );
+            let record = execute_now(&mut action, slate_id, &transition);
+            records.push(record_action(record));
+        }
+    }
+    records
+}
+
+/// Adds the action to the history of the worker state, and reports it to the event store.
+fn record_action(record: ActionRecord) -> ActionRecord {
+    events::report(events::action(&record));
+    STATE.lock().unwrap().record_action(record)
+}
+
+/// Executes the action out of the flow of the frames, as if the transition of the slate
+/// happened.
+fn execute_now(action: &mut Action, slate_id: &str, transition: &Transition) -> ActionRecord {
+    let transition_label = transition_label(transition.0, transition.1);
+    let labels = [transition_label.as_str(), action_type(action)];
+    let variables = Variables::new(slate_id).with_capture(archive::capture(slate_id, labels[0]));
+    let execution = Execution::measure(action, &labels, &variables);
+    let result_label = if execution.result.is_ok() {
+        "success"
+    } else {
+        "error"
+    };
+    METRICS
+        .action_executions
+        .with_label_values(&[labels[0], labels[1], result_label])
+        .inc();
+    if let Err(err) = &execution.result {
+        error!(
+            "Error while executing {} on {}: {:#}",
+            action_name(action),
+            transition_label,
+            err
+        );
+    }
+    execution.record(slate_id, transition, action)
+}
+
 /// Represents a sequence of video modes.
 #[derive(Clone, Eq, PartialEq)]
 pub struct Transition(VideoMode, VideoMode);
@@ -38,70 +187,404 @@ pub struct Transition(VideoMode, VideoMode);
 pub struct ActionExecutor {
     transition: Transition,
     action: Action,
+    /// Combined with the video mode, see `combined_mode`.
+    audio: Option<AudioCondition>,
+    /// Replaces the video mode and the audio condition, see `frame_mode`.
+    condition: Option<Condition>,
+    settling: Settling,
+    /// How long the stream must stay in the `to` mode before the action runs.
+    min_match_duration: Duration,
+    /// When the stream entered the `to` mode, until the action runs or the mode changes.
+    matched_since: Option<Instant>,
+    /// How long after a call the action cannot run again.
+    cooldown: Duration,
+    /// Times the action can run, always when empty.
+    active: Vec<ActiveWindow>,
     last_mode: Option<VideoMode>,
     last_call: Option<Instant>,
+    /// Pauses the action after repeated failures, see `record_result`.
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Executions failed in a row, since the last success or pause.
+    failures: u32,
+    paused_at: Option<Instant>,
+    /// Counts the transitions without executing the action, see `WatcherMode::Monitor`.
+    monitor: bool,
+    /// Slate, or black or freeze trigger, whose transitions trigger the action.
+    slate_id: String,
+    /// Values of the `transition` and `action_type` labels of the metrics.
+    labels: [String; 2],
 }
 
 impl ActionExecutor {
     /// Creates a new `ActionExecutor` instance
     pub fn new(transition: Transition, action: Action) -> Self {
+        let labels = [
+            transition_label(transition.0, transition.1),
+            action_type(&action).to_string(),
+        ];
         Self {
             transition,
             action,
+            audio: None,
+            condition: None,
+            settling: Settling::new(None),
+            min_match_duration: Duration::from_millis(0),
+            matched_since: None,
+            cooldown: Duration::from_millis(DEFAULT_COOLDOWN_MS),
+            active: Vec::new(),
             last_mode: None,
             last_call: None,
+            circuit_breaker: None,
+            failures: 0,
+            paused_at: None,
+            monitor: false,
+            slate_id: DEFAULT_SLATE_ID.to_string(),
+            labels,
+        }
+    }
+
+    /// Triggers the action with the transitions of the given slate.
+    pub fn for_slate(mut self, slate_id: &str) -> Self {
+        self.slate_id = slate_id.to_string();
+        self
+    }
+
+    /// Requires the audio of the stream to meet the condition, combined with the video mode.
+    pub fn with_audio(mut self, audio: Option<AudioCondition>) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    /// Tells the mode of the stream with a composite condition, changing the mode once it lasted
+    /// the frames of the hysteresis.
+    pub fn with_condition(
+        mut self,
+        condition: Option<Condition>,
+        hysteresis: Option<&Hysteresis>,
+    ) -> Self {
+        self.condition = condition;
+        self.settling = Settling::new(hysteresis);
+        self
+    }
+
+    /// Runs the action once the stream stayed in the `to` mode for `min_match_duration`, and
+    /// not again before the `cooldown`.
+    pub fn with_timing(mut self, min_match_duration: Duration, cooldown: Duration) -> Self {
+        self.min_match_duration = min_match_duration;
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Only runs the action within the windows, the transitions outside of them being ignored.
+    pub fn with_active(mut self, active: Option<Vec<ActiveWindow>>) -> Self {
+        self.active = active.unwrap_or_default();
+        self
+    }
+
+    /// Pauses the action once its executions failed too many times in a row.
+    pub fn with_circuit_breaker(mut self, circuit_breaker: Option<CircuitBreaker>) -> Self {
+        self.circuit_breaker = circuit_breaker;
+        self
+    }
+
+    /// Mode of the stream for the transition on a frame, from its condition or from the mode of
+    /// its slate, once settled. `None` when the frame has no mode for its slate, like the black
+    /// frames.
+    pub fn frame_mode(
+        &mut self,
+        modes: &[(String, VideoMode)],
+        facts: &Facts,
+    ) -> Option<VideoMode> {
+        let mode = match &self.condition {
+            Some(condition) if conditions::is_met(condition, &self.slate_id, facts) => {
+                self.detected_mode()
+            }
+            Some(_) => VideoMode::Content,
+            None => {
+                let (_, mode) = modes.iter().find(|(id, _)| *id == self.slate_id)?;
+                self.combined_mode(*mode, facts.loudness)
+            }
+        };
+        Some(self.settling.settle(mode))
+    }
+
+    /// The mode other than `content` of the transition.
+    fn detected_mode(&self) -> VideoMode {
+        if self.transition.1 == VideoMode::Content {
+            self.transition.0
+        } else {
+            self.transition.1
+        }
+    }
+
+    /// Mode of the stream for the transition, the video mode combined with the audio condition.
+    /// The mode other than `content` of the transition is detected when both the video and the
+    /// audio show it, or either of them, the stream showing the content otherwise.
+    pub fn combined_mode(&self, mode: VideoMode, loudness: Option<f64>) -> VideoMode {
+        let condition = match &self.audio {
+            Some(condition) => condition,
+            None => return mode,
+        };
+        let detected = self.detected_mode();
+        let video = mode == detected;
+        let audio = audio::matches(condition, loudness);
+        let combined = match condition.combine {
+            Combine::And => video && audio,
+            Combine::Or => video || audio,
+        };
+        if combined {
+            detected
+        } else {
+            VideoMode::Content
         }
     }
 
     // Manage the execution of an action based on the provided video mode.
     pub fn execute(&mut self, mode: VideoMode) {
-        if let Some(result) = self.call_action(mode) {
-            match result {
+        if let Some((span, execution)) = self.call_action(mode) {
+            // Logs the result with the fields of the action
+            let _enter = span.enter();
+            let result_label = if execution.result.is_ok() {
+                "success"
+            } else {
+                "error"
+            };
+            METRICS
+                .action_executions
+                .with_label_values(&[&self.labels[0], &self.labels[1], result_label])
+                .inc();
+            let record = execution.record(&self.slate_id, &self.transition, &self.action);
+            self.record_result(execution.result.is_ok());
+            match execution.result {
                 Ok(_) => self.last_call = Some(Instant::now()),
-                Err(err) => error!(
-                    "Error while processing action in mode {:?}: {:#}",
-                    mode, err
-                ),
+                Err(err) => {
+                    error!(
+                        "Error while processing action in mode {:?}: {:#}",
+                        mode, err
+                    );
+                }
             }
+            record_action(record);
         }
         self.last_mode = Some(mode);
     }
 
     /// Executes the action if the video mode matches the transition and if the action is
-    /// allowed to run.
-    fn call_action(&mut self, mode: VideoMode) -> Option<Result<()>> {
-        self.last_mode.and_then(|last_mode| {
-            if Transition(last_mode, mode) == self.transition && self.allowed_to_run() {
-                Some(self.action.execute())
-            } else {
-                None
+    /// allowed to run, within a span describing the action.
+    fn call_action(&mut self, mode: VideoMode) -> Option<(Span, Execution)> {
+        if !self.is_due(mode) || !self.is_active() || !self.allowed_to_run() {
+            return None;
+        }
+        if self.is_paused() {
+            warn!(
+                "Skipping {} on {}, paused by its circuit breaker",
+                action_name(&self.action),
+                self.labels[0]
+            );
+            return None;
+        }
+        if self.monitor {
+            info!(
+                "Monitoring, not executing {} on {}",
+                action_name(&self.action),
+                self.labels[0]
+            );
+            METRICS
+                .action_executions
+                .with_label_values(&[&self.labels[0], &self.labels[1], "suppressed"])
+                .inc();
+            // Keeps the cooldown the action would have had
+            self.last_call = Some(Instant::now());
+            return None;
+        }
+        let labels = [self.labels[0].as_str(), self.labels[1].as_str()];
+        let span = info_span!(
+            "action",
+            slate_id = %self.slate_id,
+            transition = labels[0],
+            action_type = labels[1],
+            action = %action_name(&self.action),
+            success = Empty,
+        );
+        let variables = Variables::new(&self.slate_id)
+            .with_capture(archive::capture(&self.slate_id, labels[0]));
+        let action = &mut self.action;
+        let execution = span.in_scope(|| Execution::measure(action, &labels, &variables));
+        span.record("success", &execution.result.is_ok());
+        Some((span, execution))
+    }
+
+    /// Whether the circuit breaker paused the action, and the pause is not over.
+    fn is_paused(&self) -> bool {
+        match (&self.circuit_breaker, &self.paused_at) {
+            (Some(breaker), Some(paused_at)) => {
+                paused_at.elapsed() < Duration::from_secs_f64(breaker.pause_seconds)
             }
-        })
+            _ => false,
+        }
+    }
+
+    /// Counts the failures in a row, pausing the action once they reach the ones of the circuit
+    /// breaker.
+    fn record_result(&mut self, success: bool) {
+        let breaker = match &self.circuit_breaker {
+            Some(breaker) => breaker,
+            None => return,
+        };
+        if success {
+            self.failures = 0;
+            return;
+        }
+        self.failures += 1;
+        if self.failures >= breaker.failures {
+            error!(
+                "Pausing {} on {} for {}s after {} failures in a row",
+                action_name(&self.action),
+                self.labels[0],
+                breaker.pause_seconds,
+                self.failures
+            );
+            METRICS
+                .circuit_breaker_opened
+                .with_label_values(&[&self.labels[0], &self.labels[1]])
+                .inc();
+            self.failures = 0;
+            self.paused_at = Some(Instant::now());
+        }
+    }
+
+    /// Whether the transition happened and the stream stayed in the `to` mode long enough. The
+    /// transition is forgotten when the mode changes before.
+    fn is_due(&mut self, mode: VideoMode) -> bool {
+        let transitioned = self.last_mode.map_or(false, |last_mode| {
+            Transition(last_mode, mode) == self.transition
+        });
+        if transitioned {
+            self.matched_since = Some(Instant::now());
+        } else if mode != self.transition.1 {
+            self.matched_since = None;
+        }
+        let due = self
+            .matched_since
+            .as_ref()
+            .map_or(false, |since| since.elapsed() >= self.min_match_duration);
+        if due {
+            self.matched_since = None;
+        }
+        due
     }
 
     /// Check if the action is allowed to run within the timeframe it was called.
     ///
     /// We need to limit the action frequency since the source of video mode does not guarantee the
     /// ordering of events.
+    fn is_active(&self) -> bool {
+        let now = Utc::now();
+        self.active.is_empty() || self.active.iter().any(|window| window.contains(now))
+    }
+
     fn allowed_to_run(&self) -> bool {
         match &self.last_call {
             None => true,
-            Some(last_call) => last_call.elapsed() > Duration::from_secs(5),
+            Some(last_call) => last_call.elapsed() > self.cooldown,
         }
     }
 }
 
+/// Names the action in the history of the worker state.
+fn action_name(action: &Action) -> String {
+    match action {
+        Action::HttpCall(call) => call
+            .description
+            .clone()
+            .unwrap_or_else(|| action_type(action).to_string()),
+        Action::MediaLive(call) => call
+            .description
+            .clone()
+            .unwrap_or_else(|| action_type(action).to_string()),
+        Action::Sqs(message) => message
+            .description
+            .clone()
+            .unwrap_or_else(|| action_type(action).to_string()),
+        Action::Sns(message) => message
+            .description
+            .clone()
+            .unwrap_or_else(|| action_type(action).to_string()),
+        Action::Kafka(message) => message
+            .description
+            .clone()
+            .unwrap_or_else(|| action_type(action).to_string()),
+        Action::Scte35(signal) => signal
+            .description
+            .clone()
+            .unwrap_or_else(|| action_type(action).to_string()),
+        Action::Exec(command) => command
+            .description
+            .clone()
+            .unwrap_or_else(|| command.command.clone()),
+        Action::FakeAction(_) => action_type(action).to_string(),
+    }
+}
+
+/// Value of the `action_type` label of the metrics.
+pub(crate) fn action_type(action: &Action) -> &'static str {
+    match action {
+        Action::HttpCall(_) => "http_call",
+        Action::MediaLive(_) => "media_live",
+        Action::Sqs(_) => "sqs",
+        Action::Sns(_) => "sns",
+        Action::Kafka(_) => "kafka",
+        Action::Scte35(_) => "scte35",
+        Action::Exec(_) => "exec",
+        Action::FakeAction(_) => "fake_action",
+    }
+}
+
 // TODO: Delete this type
 pub(crate) struct Executors(pub(crate) Vec<ActionExecutor>);
 
+impl Executors {
+    /// Executors of the actions of all the transitions of the slate.
+    pub(crate) fn for_slate(slate: &models::Slate) -> Self {
+        Self::for_transitions(&slate.id, slate.transitions.as_deref().unwrap_or_default())
+    }
+
+    /// Executors of the actions of the transitions of a slate or of a trigger, by its ID.
+    pub(crate) fn for_transitions(id: &str, transitions: &[models::Transition]) -> Self {
+        let mut executors = Vec::new();
+        for transition in transitions {
+            let Executors(execs) = transition.clone().into();
+            executors.extend(execs.into_iter().map(|exec| exec.for_slate(id)));
+        }
+        Self(executors)
+    }
+}
+
 impl From<models::Transition> for Executors {
     fn from(transition: models::Transition) -> Self {
         let target_transition = Transition(transition.from, transition.to);
+        let audio = transition.audio;
+        let condition = transition.condition;
+        let hysteresis = transition.hysteresis;
+        let active = transition.active;
+        let min_match_duration =
+            Duration::from_millis(transition.min_match_duration_ms.unwrap_or(0));
+        let cooldown = Duration::from_millis(transition.cooldown_ms.unwrap_or(DEFAULT_COOLDOWN_MS));
         Self(
             transition
                 .actions
                 .into_iter()
-                .map(|action| ActionExecutor::new(target_transition.clone(), action))
+                .map(|action| {
+                    let circuit_breaker = action
+                        .retry_policy()
+                        .and_then(|policy| policy.circuit_breaker);
+                    ActionExecutor::new(target_transition.clone(), action)
+                        .with_circuit_breaker(circuit_breaker)
+                        .with_audio(audio.clone())
+                        .with_condition(condition.clone(), hysteresis.as_ref())
+                        .with_timing(min_match_duration, cooldown)
+                        .with_active(active.clone())
+                })
                 .collect(),
         )
     }
@@ -110,6 +593,10 @@ impl From<models::Transition> for Executors {
 pub struct Runtime {
     receiver: Receiver<Event>,
     actions: Vec<ActionExecutor>,
+    machine: Option<Machine>,
+    mode: WatcherMode,
+    /// Last video mode of each slate and trigger, for the events of their transitions.
+    modes: HashMap<String, VideoMode>,
 }
 
 impl Runtime {
@@ -117,57 +604,184 @@ impl Runtime {
         Runtime {
             receiver,
             actions: processors,
+            machine: None,
+            mode: WatcherMode::Active,
+            modes: HashMap::new(),
+        }
+    }
+
+    /// Also runs the state machine of the watcher on the frames.
+    pub fn with_machine(mut self, machine: Option<Machine>) -> Self {
+        self.machine = machine;
+        self.set_mode(self.mode);
+        self
+    }
+
+    /// Only counts the actions of the transitions in the `Monitor` mode.
+    pub fn with_mode(mut self, mode: WatcherMode) -> Self {
+        self.set_mode(mode);
+        self
+    }
+
+    fn set_mode(&mut self, mode: WatcherMode) {
+        if mode != self.mode {
+            info!("Switching to the {:?} mode", mode);
         }
+        self.mode = mode;
+        let monitor = mode == WatcherMode::Monitor;
+        for p in self.actions.iter_mut() {
+            p.monitor = monitor;
+        }
+        if let Some(machine) = self.machine.as_mut() {
+            machine.monitor = monitor;
+        }
+        STATE.lock().unwrap().watcher_mode = mode;
     }
 
     pub fn run_blocking(&mut self) -> Result<()> {
         loop {
             match self.receiver.recv()? {
                 Event::Terminate => break,
-                Event::Mode(mode) => {
+                Event::Modes(modes, facts, frame) => {
+                    let _enter = info_span!(parent: &frame, "transition_evaluation").entered();
+                    // The stream shows a slate when any of them matches, the triggers do not
+                    // change its mode and black frames only carry theirs
+                    let slate_modes: Vec<VideoMode> = modes
+                        .iter()
+                        .filter(|(id, _)| !is_trigger(id))
+                        .map(|(_, mode)| *mode)
+                        .collect();
+                    if !slate_modes.is_empty() {
+                        let mode = slate_modes
+                            .into_iter()
+                            .find(|mode| *mode == VideoMode::Slate)
+                            .unwrap_or(VideoMode::Content);
+                        if let Some(from) = STATE.lock().unwrap().record_mode(mode) {
+                            let slate_id = modes
+                                .iter()
+                                .find(|(id, mode)| !is_trigger(id) && *mode == VideoMode::Slate)
+                                .map(|(id, _)| id.as_str());
+                            events::report(events::detection(slate_id, from, mode));
+                        }
+                    }
+                    self.report_transitions(&modes);
                     for p in self.actions.iter_mut() {
-                        p.execute(mode);
+                        if let Some(mode) = p.frame_mode(&modes, &facts) {
+                            p.execute(mode);
+                        }
+                    }
+                    if let Some(machine) = self.machine.as_mut() {
+                        machine.update(&facts);
                     }
                 }
+                Event::Reload(slates, mode) => {
+                    self.reload(&slates);
+                    self.set_mode(mode);
+                }
             }
         }
         Ok(())
     }
-}
 
-impl ActionExecution for HttpCall {
-    fn execute(&mut self) -> Result<()> {
-        let mut tries = 0;
-        loop {
-            match try_call(&self) {
-                Ok(_) => break,
-                Err(err) => {
-                    HTTP_CALL_RETRIED_COUNT.inc();
-                    tries += 1;
-                    if tries >= self.retries.unwrap_or(0) {
-                        HTTP_CALL_RETRIES_EXHAUSTED_COUNT.inc();
-                        return Err(err);
-                    }
+    /// Reports the slates and triggers whose video mode changed since the last frame.
+    fn report_transitions(&mut self, modes: &[(String, VideoMode)]) {
+        for (id, mode) in modes {
+            if let Some(last) = self.modes.insert(id.clone(), *mode) {
+                if last != *mode {
+                    events::report(events::transition(id, last, *mode));
                 }
             }
         }
-        Ok(())
+    }
+
+    /// Replaces the executors with the ones of the reloaded slates. Each one starts from the last
+    /// mode of its slate, so the reload neither triggers nor misses a transition. The executors of
+    /// the triggers are kept, changing them restarts the worker.
+    fn reload(&mut self, slates: &[models::Slate]) {
+        let mut actions = Vec::new();
+        for slate in slates {
+            let last_mode = self
+                .actions
+                .iter()
+                .find(|p| p.slate_id == slate.id)
+                .and_then(|p| p.last_mode);
+            let Executors(execs) = Executors::for_slate(slate);
+            actions.extend(execs.into_iter().map(|mut exec| {
+                exec.last_mode = last_mode;
+                exec
+            }));
+        }
+        info!("Reloaded {} actions", actions.len());
+        actions.extend(self.actions.drain(..).filter(|p| is_trigger(&p.slate_id)));
+        self.actions = actions;
     }
 }
 
-fn try_call(call: &HttpCall) -> Result<()> {
-    let timer = HTTP_CALL_DURATION.start_timer();
+/// Whether the ID is the one of a trigger, like black or freeze, rather than of a slate.
+fn is_trigger(id: &str) -> bool {
+    [
+        BLACK_TRIGGER_ID,
+        FREEZE_TRIGGER_ID,
+        FAILOVER_TRIGGER_ID,
+        STREAM_LOSS_TRIGGER_ID,
+    ]
+    .contains(&id)
+}
+
+impl ActionExecution for HttpCall {
+    fn execute(&mut self, labels: &[&str], variables: &Variables) -> Result<()> {
+        let call = template::render_call(self, variables);
+        let policy = retry::policy(self.retry.as_ref(), self.retries);
+        retry::run(&policy, &METRICS.http_call_retried, labels, |timeout| {
+            try_call(&call, labels, timeout)
+        })
+        .map_err(|err| {
+            METRICS
+                .http_call_retries_exhausted
+                .with_label_values(labels)
+                .inc();
+            err
+        })
+    }
+}
+
+/// Reads the value of a `name/key` reference to a `Secret` mounted in `dir`.
+fn read_secret(dir: &Path, reference: &str) -> Result<String> {
+    let path = dir.join(reference);
+    let value = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Could not read the secret {}", reference))?;
+    Ok(value.trim_end().to_string())
+}
+
+/// Calls the API once, the `timeout` of the attempt replacing the one of the call.
+fn try_call(call: &HttpCall, labels: &[&str], timeout: Option<Duration>) -> Result<()> {
+    let timer = METRICS
+        .http_call_duration
+        .with_label_values(labels)
+        .start_timer();
     let method = call.method.to_string();
     let mut request = ureq::request(&method, call.url.as_str());
 
     request.timeout_connect(500);
 
-    if let Some(HttpAuth::Basic { username, password }) = &call.authorization {
-        request.auth(username, password);
+    match &call.authorization {
+        Some(HttpAuth::Basic { username, password }) => {
+            request.auth(username, password);
+        }
+        Some(HttpAuth::SecretRef(reference)) => {
+            // Read for every attempt, so the rotated credentials are used without a restart
+            let authorization = read_secret(Path::new(SECRETS_DIR), reference)?;
+            request.set("Authorization", &authorization);
+        }
+        None => (),
     }
 
-    if let Some(timeout) = &call.timeout {
-        request.timeout(Duration::from_secs(*timeout as u64));
+    let timeout = timeout.or_else(|| {
+        call.timeout
+            .map(|seconds| Duration::from_secs(seconds as u64))
+    });
+    if let Some(timeout) = timeout {
+        request.timeout(timeout);
     }
 
     if let Some(headers) = &call.headers {
@@ -175,19 +789,37 @@ fn try_call(call: &HttpCall) -> Result<()> {
             request.set(k, v);
         }
     }
+    if let Some(headers) = &call.secret_headers {
+        for (name, reference) in headers.iter() {
+            let value = read_secret(Path::new(SECRETS_DIR), reference)?;
+            request.set(name, &value);
+        }
+    }
+
+    // Signed for every attempt, so the receiver does not take a retry for a replay
+    if let Some(signing) = &call.signing {
+        let key = read_secret(Path::new(SECRETS_DIR), &signing.secret_ref)?;
+        let body = call.body.as_deref().unwrap_or_default();
+        for (name, value) in signing::headers(&key, signing.header.as_deref(), body) {
+            request.set(&name, &value);
+        }
+    }
 
     let response = match call.body.as_ref() {
         Some(data) => request.send_string(data),
         None => request.call(),
     };
+    if !response.synthetic() {
+        LAST_STATUS.with(|status| status.set(Some(response.status())));
+    }
     if response.ok() {
-        HTTP_CALL_SUCCESS_COUNTER.inc();
+        METRICS.http_call_success.with_label_values(labels).inc();
         debug!(
             "Successfully called backend API {}",
             response.into_string()?
         );
     } else {
-        HTTP_CALL_ERROR_COUNTER.inc();
+        METRICS.http_call_error.with_label_values(labels).inc();
         warn!(
             "Error while calling backend API ({}): {}",
             response.status(),
@@ -241,6 +873,58 @@ mod tests {
         assert_eq!(called.load(Ordering::SeqCst), true);
     }
 
+    #[test]
+    fn replays_action_of_history() {
+        let called = Arc::new(AtomicBool::new(false));
+        let mut action = Action::FakeAction(FakeAction {
+            called: called.clone(),
+            execute_returns: Some(Err(())),
+        });
+        let transition = Transition(VideoMode::Content, VideoMode::Slate);
+        let execution = Execution::measure(
+            &mut action,
+            &["content_to_slate", "fake_action"],
+            &Variables::new("default"),
+        );
+        assert!(execution.result.is_err());
+        let record = execution.record("default", &transition, &action);
+        let index = STATE.lock().unwrap().record_action(record).index;
+        called.store(false, Ordering::SeqCst);
+
+        let replayed = replay(index).unwrap();
+        assert_eq!(called.load(Ordering::SeqCst), true);
+        assert_eq!(replayed.replay_of, Some(index));
+        assert!(replayed.index > index);
+        assert_eq!(replayed.success, false);
+        assert_eq!(replayed.retries, 0);
+        assert!(replay(u64::MAX).is_none());
+    }
+
+    #[test]
+    fn fires_actions_of_transitions() {
+        let called = Arc::new(AtomicBool::new(false));
+        let transition = models::Transition {
+            from: VideoMode::Content,
+            to: VideoMode::Slate,
+            actions: vec![Action::FakeAction(FakeAction {
+                called: called.clone(),
+                execute_returns: Some(Ok(())),
+            })],
+            audio: None,
+            condition: None,
+            hysteresis: None,
+            min_match_duration_ms: None,
+            cooldown_ms: None,
+            active: None,
+        };
+
+        let records = fire("network", &[transition]);
+        assert_eq!(called.load(Ordering::SeqCst), true);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].slate_id, "network");
+        assert_eq!(records[0].success, true);
+    }
+
     #[test]
     fn executor_slate_action_cannot_be_called_twice_in_short_timeframe() {
         let called = Arc::new(AtomicBool::new(false));
@@ -289,6 +973,115 @@ mod tests {
         assert_eq!(called.load(Ordering::SeqCst), true);
     }
 
+    #[test]
+    fn executor_waits_for_min_match_duration() {
+        let called = Arc::new(AtomicBool::new(false));
+        let mut executor = ActionExecutor::new(
+            Transition(VideoMode::Content, VideoMode::Slate),
+            Action::FakeAction(FakeAction {
+                called: called.clone(),
+                execute_returns: Some(Ok(())),
+            }),
+        )
+        .with_timing(Duration::from_millis(500), Duration::from_secs(60));
+        executor.execute(VideoMode::Content);
+        executor.execute(VideoMode::Slate);
+        sleep(Duration::from_millis(300));
+        // The slate did not last long enough
+        executor.execute(VideoMode::Content);
+        sleep(Duration::from_millis(300));
+        executor.execute(VideoMode::Content);
+        assert_eq!(called.load(Ordering::SeqCst), false);
+
+        executor.execute(VideoMode::Slate);
+        sleep(Duration::from_millis(300));
+        executor.execute(VideoMode::Slate);
+        assert_eq!(called.load(Ordering::SeqCst), false);
+        sleep(Duration::from_millis(300));
+        executor.execute(VideoMode::Slate);
+        assert_eq!(called.load(Ordering::SeqCst), true);
+
+        // Not again within the cooldown
+        called.store(false, Ordering::SeqCst);
+        sleep(Duration::from_secs(30));
+        executor.execute(VideoMode::Content);
+        executor.execute(VideoMode::Slate);
+        sleep(Duration::from_secs(1));
+        executor.execute(VideoMode::Slate);
+        assert_eq!(called.load(Ordering::SeqCst), false);
+    }
+
+    #[test]
+    fn executor_pauses_action_failing_repeatedly() {
+        let called = Arc::new(AtomicBool::new(false));
+        let mut executor = ActionExecutor::new(
+            Transition(VideoMode::Content, VideoMode::Slate),
+            Action::FakeAction(FakeAction {
+                called: called.clone(),
+                execute_returns: Some(Err(())),
+            }),
+        )
+        .with_timing(Duration::from_millis(0), Duration::from_millis(0))
+        .with_circuit_breaker(Some(CircuitBreaker {
+            failures: 2,
+            pause_seconds: 60.0,
+        }));
+        for _ in 0..2 {
+            called.store(false, Ordering::SeqCst);
+            executor.execute(VideoMode::Content);
+            executor.execute(VideoMode::Slate);
+            assert_eq!(called.load(Ordering::SeqCst), true);
+        }
+
+        called.store(false, Ordering::SeqCst);
+        executor.execute(VideoMode::Content);
+        executor.execute(VideoMode::Slate);
+        assert_eq!(called.load(Ordering::SeqCst), false);
+
+        sleep(Duration::from_secs(61));
+        executor.execute(VideoMode::Content);
+        executor.execute(VideoMode::Slate);
+        assert_eq!(called.load(Ordering::SeqCst), true);
+    }
+
+    #[test]
+    fn executor_only_runs_within_active_windows() {
+        let called = Arc::new(AtomicBool::new(false));
+        let executor = |called: &Arc<AtomicBool>, window: ActiveWindow| {
+            ActionExecutor::new(
+                Transition(VideoMode::Content, VideoMode::Slate),
+                Action::FakeAction(FakeAction {
+                    called: called.clone(),
+                    execute_returns: Some(Ok(())),
+                }),
+            )
+            .with_active(Some(vec![window]))
+        };
+        // Empty daily range, never active
+        let mut inactive = executor(
+            &called,
+            ActiveWindow {
+                start: Some("10:00".to_string()),
+                end: Some("10:00".to_string()),
+                ..ActiveWindow::default()
+            },
+        );
+        inactive.execute(VideoMode::Content);
+        inactive.execute(VideoMode::Slate);
+        assert_eq!(called.load(Ordering::SeqCst), false);
+
+        let mut active = executor(
+            &called,
+            ActiveWindow {
+                cron: Some("* * * * *".to_string()),
+                ..ActiveWindow::default()
+            },
+        );
+        active.execute(VideoMode::Content);
+        active.execute(VideoMode::Slate);
+        assert_eq!(called.load(Ordering::SeqCst), true);
+    }
+
     #[test]
     fn executor_slate_action_cannot_be_called_twice_if_no_mode_change() {
         let called = Arc::new(AtomicBool::new(false));
@@ -331,7 +1124,12 @@ mod tests {
 
         let (s, r) = unbounded();
         // Pile up some events for the runtime to consume
-        s.send(Event::Mode(VideoMode::Slate)).unwrap();
+        s.send(Event::Modes(
+            vec![(DEFAULT_SLATE_ID.to_string(), VideoMode::Slate)],
+            Facts::default(),
+            Span::none(),
+        ))
+        .unwrap();
         s.send(Event::Terminate).unwrap();
 
         let mut runtime = Runtime::new(r, vec![executor]);
@@ -341,10 +1139,132 @@ mod tests {
         assert_eq!(called.load(Ordering::SeqCst), true);
     }
 
+    #[test]
+    fn runtime_calls_action_executor_of_matching_slate() {
+        let called = Arc::new(AtomicBool::new(false));
+        let other_called = Arc::new(AtomicBool::new(false));
+        let executor = |called: &Arc<AtomicBool>, slate_id: &str| {
+            let mut executor = ActionExecutor::new(
+                Transition(VideoMode::Content, VideoMode::Slate),
+                Action::FakeAction(FakeAction {
+                    called: called.clone(),
+                    execute_returns: Some(Ok(())),
+                }),
+            )
+            .for_slate(slate_id);
+            executor.execute(VideoMode::Content);
+            executor
+        };
+        let executors = vec![
+            executor(&called, "network"),
+            executor(&other_called, DEFAULT_SLATE_ID),
+        ];
+
+        let (s, r) = unbounded();
+        s.send(Event::Modes(
+            vec![
+                (DEFAULT_SLATE_ID.to_string(), VideoMode::Content),
+                ("network".to_string(), VideoMode::Slate),
+            ],
+            Facts::default(),
+            Span::none(),
+        ))
+        .unwrap();
+        s.send(Event::Terminate).unwrap();
+
+        let mut runtime = Runtime::new(r, executors);
+        runtime.run_blocking().expect("Should run successfully!");
+
+        assert_eq!(called.load(Ordering::SeqCst), true);
+        assert_eq!(other_called.load(Ordering::SeqCst), false);
+    }
+
+    #[test]
+    fn runtime_reloads_executors() {
+        let called = Arc::new(AtomicBool::new(false));
+        let mut executor = ActionExecutor::new(
+            Transition(VideoMode::Slate, VideoMode::Content),
+            Action::FakeAction(FakeAction {
+                called: Arc::new(AtomicBool::new(false)),
+                execute_returns: Some(Ok(())),
+            }),
+        )
+        .for_slate("network");
+        executor.execute(VideoMode::Content);
+
+        let (s, r) = unbounded();
+        s.send(Event::Reload(
+            vec![models::Slate {
+                id: "network".to_string(),
+                url: "file://./resources/slate_120px.jpg".to_string(),
+                threshold: None,
+                detector: None,
+                region: None,
+                text: None,
+                transitions: Some(vec![models::Transition {
+                    from: VideoMode::Content,
+                    to: VideoMode::Slate,
+                    actions: vec![Action::FakeAction(FakeAction {
+                        called: called.clone(),
+                        execute_returns: Some(Ok(())),
+                    })],
+                    audio: None,
+                    condition: None,
+                    hysteresis: None,
+                    min_match_duration_ms: None,
+                    cooldown_ms: None,
+                    active: None,
+                }]),
+            }],
+            WatcherMode::Active,
+        ))
+        .unwrap();
+        s.send(Event::Modes(
+            vec![("network".to_string(), VideoMode::Slate)],
+            Facts::default(),
+            Span::none(),
+        ))
+        .unwrap();
+        s.send(Event::Terminate).unwrap();
+
+        let mut runtime = Runtime::new(r, vec![executor]);
+        runtime.run_blocking().expect("Should run successfully!");
+
+        assert_eq!(called.load(Ordering::SeqCst), true);
+    }
+
+    #[test]
+    fn runtime_does_not_execute_actions_in_monitor_mode() {
+        let called = Arc::new(AtomicBool::new(false));
+        let executor = ActionExecutor::new(
+            Transition(VideoMode::Content, VideoMode::Slate),
+            Action::FakeAction(FakeAction {
+                called: called.clone(),
+                execute_returns: Some(Ok(())),
+            }),
+        );
+
+        let (s, r) = unbounded();
+        for mode in [VideoMode::Content, VideoMode::Slate] {
+            s.send(Event::Modes(
+                vec![(DEFAULT_SLATE_ID.to_string(), mode)],
+                Facts::default(),
+                Span::none(),
+            ))
+            .unwrap();
+        }
+        s.send(Event::Terminate).unwrap();
+
+        let mut runtime = Runtime::new(r, vec![executor]).with_mode(WatcherMode::Monitor);
+        runtime.run_blocking().expect("Should run successfully!");
+
+        assert_eq!(called.load(Ordering::SeqCst), false);
+    }
+
     #[test]
     fn action_http_call_performs_request() {
-        let path = "/do-something";
-        let req_body = "{\"duration\":20}";
+        let path = "/do-something/network";
+        let req_body = "{\"duration\":20,\"watcher\":\"\"}";
 
         let server = mock("POST", path)
             .match_body(req_body)
@@ -355,7 +1275,7 @@ mod tests {
 
         let mut action = HttpCall {
             method: HttpMethod::POST,
-            url: format!("{}{}", server_url(), path),
+            url: format!("{}/do-something/{{{{slate_id}}}}", server_url()),
             description: None,
             authorization: Some(HttpAuth::Basic {
                 username: "user".to_string(),
@@ -367,15 +1287,73 @@ mod tests {
                     .map(|(k, v)| (k.to_string(), v.to_string()))
                     .collect::<HashMap<String, String>>(),
             ),
-            body: Some(req_body.to_string()),
+            secret_headers: None,
+            body: Some("{\"duration\":20,\"watcher\":\"{{watcher_id}}\"}".to_string()),
             retries: None,
+            retry: None,
             timeout: None,
+            signing: None,
         };
 
-        action.execute().expect("Should execute successfully!");
+        action
+            .execute(
+                &["content_to_slate", "http_call"],
+                &Variables::new("network"),
+            )
+            .expect("Should execute successfully!");
         assert!(server.matched());
     }
 
+    #[test]
+    fn reads_the_mounted_secrets() {
+        let dir = std::env::temp_dir().join("hawkeye-secrets");
+        std::fs::create_dir_all(dir.join("ad-server")).unwrap();
+        std::fs::write(dir.join("ad-server").join("token"), "Bearer abc\n").unwrap();
+
+        assert_eq!(read_secret(&dir, "ad-server/token").unwrap(), "Bearer abc");
+        assert!(read_secret(&dir, "ad-server/missing").is_err());
+    }
+
+    #[test]
+    fn combines_video_mode_with_audio() {
+        let executor = |combine| {
+            ActionExecutor::new(
+                Transition(VideoMode::Content, VideoMode::Slate),
+                Action::FakeAction(FakeAction {
+                    called: Arc::new(AtomicBool::new(false)),
+                    execute_returns: Some(Ok(())),
+                }),
+            )
+            .with_audio(Some(AudioCondition {
+                audio: models::AudioMode::Silent,
+                combine,
+                silence_threshold: None,
+            }))
+        };
+
+        let and = executor(Combine::And);
+        assert_eq!(and.combined_mode(VideoMode::Slate, None), VideoMode::Slate);
+        assert_eq!(
+            and.combined_mode(VideoMode::Slate, Some(-10.0)),
+            VideoMode::Content
+        );
+        assert_eq!(
+            and.combined_mode(VideoMode::Content, None),
+            VideoMode::Content
+        );
+
+        let or = executor(Combine::Or);
+        assert_eq!(or.combined_mode(VideoMode::Content, None), VideoMode::Slate);
+        assert_eq!(
+            or.combined_mode(VideoMode::Slate, Some(-10.0)),
+            VideoMode::Slate
+        );
+        assert_eq!(
+            or.combined_mode(VideoMode::Content, Some(-10.0)),
+            VideoMode::Content
+        );
+    }
+
     #[test]
     fn build_executor_from_models() {
         let transition = models::Transition {
@@ -396,10 +1374,19 @@ mod tests {
                         .map(|(k, v)| (k.to_string(), v.to_string()))
                         .collect::<HashMap<String, String>>(),
                 ),
+                secret_headers: None,
                 body: Some("{\"duration\":320}".to_string()),
                 retries: Some(3),
+                retry: None,
                 timeout: Some(10),
+                signing: None,
             })],
+            audio: None,
+            condition: None,
+            hysteresis: None,
+            min_match_duration_ms: None,
+            cooldown_ms: None,
+            active: None,
         };
 
         let _executors: Executors = transition.into();
@@ -0,0 +1,323 @@
+//! Offline analysis of a capture, to tune the slates and triggers of a watcher: its frames are
+//! decoded as fast as possible, compared with the slates like in `process_frames`, and every
+//! transition is reported with its position in the capture. No action is executed and no server
+//! is started.
+use crate::config::AnalyzeConfig;
+use crate::img_detector::{is_similar, Slate, SlateDetector};
+use crate::reload;
+use crate::triggers::FrameTriggers;
+use crate::video_stream::analysis_branch;
+use crate::watcher_file;
+use color_eyre::{eyre::eyre, Result};
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use hawkeye_core::models::{VideoMode, Watcher};
+use log::info;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub input: String,
+    pub frames: u64,
+    /// Position of the last frame analyzed.
+    pub duration_seconds: f64,
+    pub slates: Vec<SlateSummary>,
+    pub transitions: Vec<Transition>,
+}
+
+/// How close the frames came to a slate, to pick its threshold.
+#[derive(Serialize, Debug)]
+pub struct SlateSummary {
+    pub slate_id: String,
+    pub threshold: f64,
+    /// Lowest similarity of the frames, the closest one to the slate. Missing when every frame
+    /// was black.
+    pub best_similarity: Option<f64>,
+    pub matched_frames: u64,
+}
+
+/// A slate, or trigger, of the watcher switching modes.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Transition {
+    /// Position of the first frame in the new mode, from the first frame of the capture.
+    pub seconds: f64,
+    /// ID of the slate, or trigger.
+    pub id: String,
+    pub from: VideoMode,
+    pub to: VideoMode,
+    /// Similarity of the frame with the slate, missing for the triggers.
+    pub similarity: Option<f64>,
+    pub threshold: Option<f64>,
+}
+
+/// Runs `hawkeye-worker analyze`, writing the report to `--report`, or the standard output.
+pub fn run(config: &AnalyzeConfig) -> Result<()> {
+    let watcher = watcher_file::load(&config.config)?;
+    watcher.is_valid()?;
+    gst::init()?;
+    let slates = reload::load_slates(&watcher.all_slates(), config.slate_library_url.as_deref())?;
+    let mut analyzer = Analyzer::new(&watcher, slates)?;
+
+    info!("Analyzing {}..", config.input.display());
+    let started = Instant::now();
+    for frame in Capture::open(&config.input, &watcher)? {
+        let (seconds, image) = frame?;
+        analyzer.analyze(seconds, &image)?;
+    }
+    let report = analyzer.report(&config.input);
+    info!(
+        "Analyzed {} frames in {:?}, {} transitions",
+        report.frames,
+        started.elapsed(),
+        report.transitions.len()
+    );
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match config.report.as_ref() {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// The frames of a capture with their position in seconds, pulled from the pipeline as fast as
+/// they are decoded instead of at the rate of the capture.
+pub struct Capture {
+    pipeline: gst::Pipeline,
+    appsink: gst_app::AppSink,
+    /// Timestamp of the first frame, the captures of live streams not starting at 0.
+    first_pts: Option<u64>,
+}
+
+impl Capture {
+    pub fn open(path: &Path, watcher: &Watcher) -> Result<Self> {
+        let path =
+            fs::canonicalize(path).map_err(|e| eyre!("Cannot read {}: {}", path.display(), e))?;
+        // The appsink blocks the pipeline until its frames are pulled, so none is dropped
+        let description = format!(
+            "uridecodebin uri=\"file://{}\" name=src src. ! audio/x-raw ! fakesink sync=false src. ! video/x-raw ! queue ! {} ! pngenc snapshot=false ! appsink name=sink sync=false max-buffers=2",
+            path.display(),
+            analysis_branch(watcher.analysis.as_ref())
+        );
+        let pipeline = gst::parse_launch(&description)?
+            .downcast::<gst::Pipeline>()
+            .map_err(|_| eyre!("Expected a gst::Pipeline"))?;
+        let appsink = pipeline
+            .by_name("sink")
+            .and_then(|sink| sink.downcast::<gst_app::AppSink>().ok())
+            .ok_or_else(|| eyre!("Sink element not found"))?;
+        pipeline.set_state(gst::State::Playing)?;
+        Ok(Self {
+            pipeline,
+            appsink,
+            first_pts: None,
+        })
+    }
+
+    /// The error that stopped the pipeline, if any, once no more sample can be pulled.
+    fn error(&self) -> Option<color_eyre::Report> {
+        let bus = self.pipeline.bus()?;
+        let msg = bus.pop_filtered(&[gst::MessageType::Error])?;
+        match msg.view() {
+            gst::MessageView::Error(err) => {
+                Some(eyre!("Cannot decode the capture: {}", err.error()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for Capture {
+    type Item = Result<(f64, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Fails at the end of the capture, or when the pipeline stopped on an error
+        let sample = match self.appsink.pull_sample() {
+            Ok(sample) => sample,
+            Err(_) => return self.error().map(Err),
+        };
+        let buffer = sample.buffer()?;
+        let pts = buffer.pts().map_or(0, |pts| pts.nseconds());
+        let first_pts = *self.first_pts.get_or_insert(pts);
+        let seconds = pts.saturating_sub(first_pts) as f64 / 1e9;
+        let map = match buffer.map_readable() {
+            Ok(map) => map,
+            Err(_) => return Some(Err(eyre!("Failed to map buffer readable"))),
+        };
+        Some(Ok((seconds, map.to_vec())))
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+/// Detects the modes of the slates and triggers of the watcher on each frame, and collects their
+/// transitions.
+pub struct Analyzer {
+    black_detector: SlateDetector,
+    slates: Vec<Slate>,
+    triggers: FrameTriggers,
+    /// Stands for the first frame, so the durations of the triggers follow the capture.
+    start: Instant,
+    modes: HashMap<String, VideoMode>,
+    summaries: Vec<SlateSummary>,
+    transitions: Vec<Transition>,
+    frames: u64,
+    last_seconds: f64,
+}
+
+impl Analyzer {
+    pub fn new(watcher: &Watcher, slates: Vec<Slate>) -> Result<Self> {
+        let black_image = include_bytes!("../../resources/black_120px.jpg");
+        let summaries = slates
+            .iter()
+            .map(|slate| SlateSummary {
+                slate_id: slate.id.clone(),
+                threshold: slate.threshold,
+                best_similarity: None,
+                matched_frames: 0,
+            })
+            .collect();
+        Ok(Self {
+            black_detector: SlateDetector::new(black_image)?,
+            slates,
+            triggers: FrameTriggers::new(watcher),
+            start: Instant::now(),
+            modes: HashMap::new(),
+            summaries,
+            transitions: Vec::new(),
+            frames: 0,
+            last_seconds: 0.0,
+        })
+    }
+
+    pub fn analyze(&mut self, seconds: f64, image: &[u8]) -> Result<()> {
+        let frame = self.black_detector.decode(image)?;
+        let is_black = self.black_detector.is_match(&frame);
+        // The slates keep their mode on the black frames, like in `process_frames`
+        let mut modes = Vec::new();
+        if !is_black {
+            for (slate, summary) in self.slates.iter().zip(self.summaries.iter_mut()) {
+                let cropped = slate.region.as_ref().map(|region| frame.crop(region));
+                let similarity = slate
+                    .detector
+                    .similarity(cropped.as_ref().unwrap_or(&frame));
+                let is_match = is_similar(similarity, slate.threshold);
+                summary.best_similarity = Some(
+                    summary
+                        .best_similarity
+                        .map_or(similarity, |best| best.min(similarity)),
+                );
+                let mode = if is_match {
+                    summary.matched_frames += 1;
+                    VideoMode::Slate
+                } else {
+                    VideoMode::Content
+                };
+                modes.push((slate.id.clone(), mode, Some((similarity, slate.threshold))));
+            }
+        }
+        let now = self.start + Duration::from_secs_f64(seconds);
+        for (id, mode) in self.triggers.update(frame, is_black, now) {
+            modes.push((id, mode, None));
+        }
+
+        for (id, mode, score) in modes {
+            // The first frame only sets the initial modes
+            match self.modes.insert(id.clone(), mode) {
+                Some(from) if from != mode => self.transitions.push(Transition {
+                    seconds,
+                    id,
+                    from,
+                    to: mode,
+                    similarity: score.map(|(similarity, _)| similarity),
+                    threshold: score.map(|(_, threshold)| threshold),
+                }),
+                _ => (),
+            }
+        }
+        self.frames += 1;
+        self.last_seconds = seconds;
+        Ok(())
+    }
+
+    pub fn report(self, input: &Path) -> Report {
+        Report {
+            input: input.display().to_string(),
+            frames: self.frames,
+            duration_seconds: self.last_seconds,
+            slates: self.summaries,
+            transitions: self.transitions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hawkeye_core::models::Detector;
+    use std::path::PathBuf;
+
+    #[test]
+    fn reports_the_transitions_with_their_position() {
+        let watcher: Watcher = serde_json::from_value(serde_json::json!({
+            "slate_url": "file://../resources/slate_120px.jpg",
+            "source": {
+                "ingest_port": 5000,
+                "container": "mpeg-ts",
+                "codec": "h264",
+                "transport": {"protocol": "rtp"}
+            },
+            "transitions": [],
+            "black": {"duration": 1, "transitions": []}
+        }))
+        .unwrap();
+        let slate = std::fs::read("../resources/slate_120px.jpg").unwrap();
+        let slates = vec![Slate {
+            id: "slate".to_string(),
+            detector: crate::img_detector::new_detector(Detector::Dssim, &slate, None).unwrap(),
+            threshold: 0.1,
+            region: None,
+        }];
+        let mut analyzer = Analyzer::new(&watcher, slates).unwrap();
+        let frames = [
+            "../resources/non-slate_120px.jpg",
+            "../resources/slate_120px.jpg",
+            "../resources/black_120px.jpg",
+            "../resources/black_120px.jpg",
+            "../resources/non-slate_120px.jpg",
+        ];
+        for (i, path) in frames.iter().enumerate() {
+            let image = std::fs::read(path).unwrap();
+            analyzer.analyze(i as f64, &image).unwrap();
+        }
+
+        let report = analyzer.report(&PathBuf::from("capture.ts"));
+        assert_eq!(report.frames, 5);
+        assert_eq!(report.duration_seconds, 4.0);
+        assert_eq!(report.slates[0].matched_frames, 1);
+        assert!(is_similar(report.slates[0].best_similarity.unwrap(), 0.1));
+        let transitions: Vec<(f64, &str, VideoMode, VideoMode)> = report
+            .transitions
+            .iter()
+            .map(|t| (t.seconds, t.id.as_str(), t.from, t.to))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (1.0, "slate", VideoMode::Content, VideoMode::Slate),
+                (3.0, "black", VideoMode::Content, VideoMode::Black),
+                (4.0, "slate", VideoMode::Slate, VideoMode::Content),
+                (4.0, "black", VideoMode::Black, VideoMode::Content),
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::video_stream::Detection;
+use color_eyre::Result;
+use image::{ImageOutputFormat, Rgba, RgbaImage};
+use std::cmp::Ordering;
+
+const MATCH_COLOR: Rgba<u8> = Rgba([0, 220, 0, 255]);
+const NO_MATCH_COLOR: Rgba<u8> = Rgba([230, 0, 0, 255]);
+const TEXT_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const TEXT_BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// Pixels of each dot of the font.
+const TEXT_SCALE: u32 = 2;
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// Glyphs of a 3x5 pixel font, enough to write the annotations without loading a font file.
+/// Characters without a glyph are left blank.
+const GLYPHS: [(char, [&str; 5]); 41] = [
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["###", "..#", "###", "#..", "###"]),
+    ('3', ["###", "..#", "###", "..#", "###"]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "###", "..#", "###"]),
+    ('6', ["###", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", "..#", "..#", "..#"]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "###"]),
+    ('A', [".#.", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', [".##", "#..", "#..", "#..", ".##"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', [".##", "#..", "#.#", "#.#", ".##"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", ".#."]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('P', ["##.", "#.#", "##.", "#..", "#.."]),
+    ('Q', [".#.", "#.#", "#.#", "##.", ".##"]),
+    ('R', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('S', [".##", "#..", ".#.", "..#", "##."]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    (':', ["...", ".#.", "...", ".#.", "..."]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('_', ["...", "...", "...", "...", "###"]),
+    ('/', ["..#", "..#", ".#.", "#..", "#.."]),
+];
+
+/// Draws the result of the detection over the frame: the analyzed region of the matching slate,
+/// or of the closest one, in green when it matches and in red otherwise, and its similarity
+/// score.
+pub fn annotate(png: &[u8], detection: &Detection) -> Result<Vec<u8>> {
+    let mut img = image::load_from_memory(png)?.to_rgba8();
+
+    let matched = detection.matched();
+    let closest = matched.or_else(|| {
+        detection
+            .slates
+            .iter()
+            .filter(|slate| slate.similarity.is_some())
+            .min_by(|a, b| {
+                a.similarity
+                    .partial_cmp(&b.similarity)
+                    .unwrap_or(Ordering::Equal)
+            })
+    });
+    let color = if matched.is_some() {
+        MATCH_COLOR
+    } else {
+        NO_MATCH_COLOR
+    };
+    let (width, height) = img.dimensions();
+    let (x, y, region_width, region_height) = closest
+        .and_then(|slate| slate.region.as_ref())
+        .map_or((0, 0, width, height), |region| {
+            region.to_pixels(width, height)
+        });
+    draw_rectangle(&mut img, x, y, region_width, region_height, color);
+
+    let mode = match matched {
+        _ if detection.is_black => "BLACK".to_string(),
+        Some(slate) => format!("SLATE {}", slate.slate_id),
+        None => "CONTENT".to_string(),
+    };
+    let score = match closest.and_then(|slate| slate.similarity) {
+        Some(similarity) => format!("DSSIM {:.3}", similarity),
+        None => "DSSIM -".to_string(),
+    };
+    draw_text(&mut img, 3, 3, &mode);
+    draw_text(&mut img, 3, 3 + (GLYPH_HEIGHT + 2) * TEXT_SCALE, &score);
+
+    let mut annotated = Vec::new();
+    image::DynamicImage::ImageRgba8(img).write_to(&mut annotated, ImageOutputFormat::Png)?;
+    Ok(annotated)
+}
+
+/// Draws the outline of the rectangle, clipped to the image.
+fn draw_rectangle(img: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    for dx in 0..width {
+        put_pixel(img, x + dx, y, color);
+        put_pixel(img, x + dx, y + height - 1, color);
+    }
+    for dy in 0..height {
+        put_pixel(img, x, y + dy, color);
+        put_pixel(img, x + width - 1, y + dy, color);
+    }
+}
+
+/// Writes the text over a dark background, so it is readable over any frame.
+fn draw_text(img: &mut RgbaImage, x: u32, y: u32, text: &str) {
+    let advance = (GLYPH_WIDTH + 1) * TEXT_SCALE;
+    let chars = text.chars().count() as u32;
+    for dy in 0..(GLYPH_HEIGHT + 2) * TEXT_SCALE {
+        for dx in 0..chars * advance + TEXT_SCALE {
+            put_pixel(img, x + dx, y + dy, TEXT_BACKGROUND);
+        }
+    }
+
+    for (i, c) in text.chars().enumerate() {
+        let glyph = match GLYPHS
+            .iter()
+            .find(|(glyph, _)| *glyph == c.to_ascii_uppercase())
+        {
+            Some((_, rows)) => rows,
+            None => continue,
+        };
+        let glyph_x = x + TEXT_SCALE + i as u32 * advance;
+        let glyph_y = y + TEXT_SCALE;
+        for (row, dots) in glyph.iter().enumerate() {
+            for (col, dot) in dots.chars().enumerate() {
+                if dot != '#' {
+                    continue;
+                }
+                for sy in 0..TEXT_SCALE {
+                    for sx in 0..TEXT_SCALE {
+                        put_pixel(
+                            img,
+                            glyph_x + col as u32 * TEXT_SCALE + sx,
+                            glyph_y + row as u32 * TEXT_SCALE + sy,
+                            TEXT_COLOR,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn put_pixel(img: &mut RgbaImage, x: u32, y: u32, color: Rgba<u8>) {
+    if x < img.width() && y < img.height() {
+        img.put_pixel(x, y, color);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::video_stream::SlateMatch;
+    use hawkeye_core::models::Region;
+    use std::fs::File;
+    use std::io::Read;
+
+    #[test]
+    fn annotates_the_frame() {
+        let mut slate =
+            File::open("../resources/slate_120px.jpg").expect("Missing file in resources folder");
+        let mut buffer = Vec::new();
+        slate
+            .read_to_end(&mut buffer)
+            .expect("Failed to write to buffer");
+        let detection = Detection {
+            is_black: false,
+            slates: vec![SlateMatch {
+                slate_id: "default".to_string(),
+                similarity: Some(0.012),
+                is_match: true,
+                region: None,
+            }],
+        };
+
+        let annotated = annotate(&buffer, &detection).unwrap();
+        let img = image::load_from_memory(&annotated).unwrap().to_rgba8();
+        let original = image::load_from_memory(&buffer).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), original.dimensions());
+        assert_eq!(*img.get_pixel(0, 0), MATCH_COLOR);
+        assert_eq!(*img.get_pixel(3, 3), TEXT_BACKGROUND);
+    }
+
+    #[test]
+    fn annotates_the_region() {
+        let mut slate =
+            File::open("../resources/slate_120px.jpg").expect("Missing file in resources folder");
+        let mut buffer = Vec::new();
+        slate
+            .read_to_end(&mut buffer)
+            .expect("Failed to write to buffer");
+        let detection = Detection {
+            is_black: false,
+            slates: vec![SlateMatch {
+                slate_id: "default".to_string(),
+                similarity: Some(0.5),
+                is_match: false,
+                region: Some(Region {
+                    x: 50.0,
+                    y: 50.0,
+                    width: 50.0,
+                    height: 50.0,
+                }),
+            }],
+        };
+
+        let annotated = annotate(&buffer, &detection).unwrap();
+        let img = image::load_from_memory(&annotated).unwrap().to_rgba8();
+        let (width, height) = img.dimensions();
+        assert_eq!(*img.get_pixel(width - 1, height - 1), NO_MATCH_COLOR);
+        assert_ne!(*img.get_pixel(0, height - 1), NO_MATCH_COLOR);
+    }
+}
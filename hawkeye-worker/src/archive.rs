@@ -0,0 +1,339 @@
+use crate::aws;
+use crate::metrics::METRICS;
+use crate::preview::{self, FRAME_HISTORY};
+use crate::record;
+use crate::video_stream::LATEST_FRAME;
+use chrono::{DateTime, Utc};
+use color_eyre::Result;
+use hawkeye_core::models::{ClipCapture, FrameArchive};
+use lazy_static::lazy_static;
+use log::{debug, error};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Endpoint of the S3 compatible API of GCS.
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// Timeout of the upload of each image.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout of the upload of a clip of the incoming video.
+pub const CLIP_UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The actions of a transition becoming due on the same frame share its images, when they are
+/// captured closer than this.
+const SHARED_CAPTURE: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    static ref ARCHIVE: Mutex<Option<Archive>> = Mutex::new(None);
+}
+
+/// Bucket of the archive, from its URL.
+#[derive(Clone, Debug, PartialEq)]
+struct Bucket {
+    name: String,
+    /// Prefix of the keys, without leading nor trailing slash.
+    prefix: String,
+    /// S3 compatible API of the bucket, S3 itself when missing.
+    endpoint: Option<&'static str>,
+    region: Option<String>,
+}
+
+impl Bucket {
+    fn parse(archive: &FrameArchive) -> Option<Self> {
+        let (rest, endpoint) = match archive.url.strip_prefix("s3://") {
+            Some(rest) => (rest, None),
+            None => (archive.url.strip_prefix("gs://")?, Some(GCS_ENDPOINT)),
+        };
+        let (name, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        Some(Self {
+            name: name.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            endpoint,
+            region: archive.region.clone(),
+        })
+    }
+
+    /// Key of an image of the transition of the slate or trigger, grouped by watcher and day.
+    fn key(&self, watcher_id: &str, transition_id: &str, at: DateTime<Utc>, name: &str) -> String {
+        self.prefixed(format!(
+            "{}/{}/{}_{}/{}",
+            watcher_id,
+            at.format("%Y-%m-%d"),
+            at.format("%Y%m%dT%H%M%S%.3fZ"),
+            transition_id,
+            name
+        ))
+    }
+
+    /// Key of a clip of the incoming video, next to the transitions of the day.
+    fn clip_key(&self, watcher_id: &str, at: DateTime<Utc>) -> String {
+        self.prefixed(format!(
+            "{}/{}/{}_clip.ts",
+            watcher_id,
+            at.format("%Y-%m-%d"),
+            at.format("%Y%m%dT%H%M%S%.3fZ")
+        ))
+    }
+
+    fn prefixed(&self, key: String) -> String {
+        if self.prefix.is_empty() {
+            key
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+
+    /// HTTPS URL of the object.
+    fn url(&self, key: &str) -> String {
+        match (self.endpoint, self.region.as_deref()) {
+            (Some(endpoint), _) => format!("{}/{}/{}", endpoint, self.name, key),
+            (None, Some(region)) => {
+                format!("https://{}.s3.{}.amazonaws.com/{}", self.name, region, key)
+            }
+            (None, None) => format!("https://{}.s3.amazonaws.com/{}", self.name, key),
+        }
+    }
+}
+
+/// Images of a transition, given to its actions. Empty when the watcher has no archive.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capture {
+    pub frame_url: Option<String>,
+    /// Thumbnails of the seconds before the transition, oldest first.
+    pub thumbnail_urls: Vec<String>,
+    /// Clip of the video around the transition, uploaded once its end is recorded.
+    pub clip_url: Option<String>,
+}
+
+struct Archive {
+    watcher_id: String,
+    bucket: Bucket,
+    thumbnail_seconds: u32,
+    clip: Option<ClipCapture>,
+    /// Transition of the last capture, with when it was captured.
+    last: Option<(String, Instant, Capture)>,
+}
+
+/// Archives the frames of the transitions of the watcher, when it has an archive, with the clips
+/// of the video around them when it captures them.
+pub fn configure(archive: Option<&FrameArchive>, capture: Option<&ClipCapture>, watcher_id: &str) {
+    let archive = archive.and_then(|archive| {
+        Some(Archive {
+            watcher_id: watcher_id.to_string(),
+            bucket: Bucket::parse(archive)?,
+            thumbnail_seconds: archive.thumbnail_seconds.unwrap_or(0),
+            clip: capture.cloned(),
+            last: None,
+        })
+    });
+    // The video after the transition is kept until its clip is uploaded
+    record::keep_last(
+        archive
+            .as_ref()
+            .and_then(|archive| archive.clip.as_ref())
+            .map(|clip| Duration::from_secs((clip.buffer_seconds + clip.after_seconds()) as u64)),
+    );
+    *ARCHIVE.lock().unwrap() = archive;
+}
+
+/// Archives the latest frame, with the thumbnails of the seconds before it, for the transition
+/// of the slate or trigger. Their URLs are returned right away, the images being uploaded in the
+/// background.
+pub fn capture(slate_id: &str, transition: &str) -> Capture {
+    let mut archive = ARCHIVE.lock().unwrap();
+    let archive = match archive.as_mut() {
+        Some(archive) => archive,
+        None => return Capture::default(),
+    };
+    let transition_id = format!("{}_{}", slate_id, transition);
+    if let Some((last_id, captured_at, capture)) = archive.last.as_ref() {
+        if *last_id == transition_id && captured_at.elapsed() < SHARED_CAPTURE {
+            return capture.clone();
+        }
+    }
+    // Nothing to archive before the first frame
+    let frame = match (*LATEST_FRAME.read()).clone() {
+        Some(frame) => frame,
+        None => return Capture::default(),
+    };
+
+    let now = SystemTime::now();
+    let at = DateTime::<Utc>::from(now);
+    let key = |name: &str| {
+        archive
+            .bucket
+            .key(&archive.watcher_id, &transition_id, at, name)
+    };
+    let mut images = vec![(key("frame.jpg"), frame)];
+    if archive.thumbnail_seconds > 0 {
+        let period = Duration::from_secs(archive.thumbnail_seconds as u64);
+        let history = FRAME_HISTORY.lock().unwrap();
+        for thumbnail in history.last(period, now) {
+            // The one of the last second is the frame itself
+            let age = now
+                .duration_since(thumbnail.captured_at)
+                .map_or(0, |age| age.as_secs());
+            if age > 0 {
+                let name = format!("thumbnail-{}s.jpg", age);
+                images.push((key(&name), thumbnail.image.clone()));
+            }
+        }
+    }
+
+    let clip_key = match archive.clip.as_ref() {
+        Some(clip) if record::is_recordable() => Some((key("clip.ts"), clip.clone())),
+        _ => None,
+    };
+    let capture = Capture {
+        frame_url: Some(archive.bucket.url(&images[0].0)),
+        thumbnail_urls: images[1..]
+            .iter()
+            .map(|(key, _)| archive.bucket.url(key))
+            .collect(),
+        clip_url: clip_key.as_ref().map(|(key, _)| archive.bucket.url(key)),
+    };
+    let bucket = archive.bucket.clone();
+    if let Some((key, clip)) = clip_key {
+        let bucket = bucket.clone();
+        thread::spawn(move || upload_transition_clip(&bucket, &key, &clip));
+    }
+    thread::spawn(move || upload(&bucket, images));
+    archive.last = Some((transition_id, Instant::now(), capture.clone()));
+    capture
+}
+
+/// Uploads a clip of the incoming video, recorded as a transport stream started at `at`, returns
+/// its URL. `None` when the watcher has no archive.
+pub fn upload_clip(clip: Vec<u8>, at: DateTime<Utc>) -> Option<Result<String>> {
+    let (watcher_id, bucket) = {
+        let archive = ARCHIVE.lock().unwrap();
+        let archive = archive.as_ref()?;
+        (archive.watcher_id.clone(), archive.bucket.clone())
+    };
+    let key = bucket.clip_key(&watcher_id, at);
+    let uploaded = aws::put_object(
+        bucket.region.as_deref(),
+        bucket.endpoint,
+        &bucket.name,
+        &key,
+        clip,
+        "video/mp2t",
+        CLIP_UPLOAD_TIMEOUT,
+    );
+    let result = if uploaded.is_ok() { "success" } else { "error" };
+    METRICS.archive_uploads.with_label_values(&[result]).inc();
+    Some(uploaded.map(|_| bucket.url(&key)))
+}
+
+/// Waits for the seconds after the transition, then uploads the video kept around it.
+fn upload_transition_clip(bucket: &Bucket, key: &str, clip: &ClipCapture) {
+    let after = Duration::from_secs(clip.after_seconds() as u64);
+    thread::sleep(after);
+    let video = record::last(Duration::from_secs(clip.buffer_seconds as u64) + after);
+    if video.is_empty() {
+        error!("Could not archive {}: no video was kept", key);
+        METRICS.archive_uploads.with_label_values(&["error"]).inc();
+        return;
+    }
+    let uploaded = aws::put_object(
+        bucket.region.as_deref(),
+        bucket.endpoint,
+        &bucket.name,
+        key,
+        video,
+        "video/mp2t",
+        CLIP_UPLOAD_TIMEOUT,
+    );
+    match uploaded {
+        Ok(_) => {
+            debug!("Archived {}", key);
+            METRICS
+                .archive_uploads
+                .with_label_values(&["success"])
+                .inc();
+        }
+        Err(err) => {
+            error!("Could not archive {}: {:#}", key, err);
+            METRICS.archive_uploads.with_label_values(&["error"]).inc();
+        }
+    }
+}
+
+/// Uploads the frames, captured as PNG images, as JPEG images.
+fn upload(bucket: &Bucket, images: Vec<(String, Vec<u8>)>) {
+    for (key, png) in images {
+        let uploaded = preview::to_jpeg(&png).and_then(|jpeg| {
+            aws::put_object(
+                bucket.region.as_deref(),
+                bucket.endpoint,
+                &bucket.name,
+                &key,
+                jpeg,
+                "image/jpeg",
+                UPLOAD_TIMEOUT,
+            )
+        });
+        match uploaded {
+            Ok(_) => {
+                debug!("Archived {}", key);
+                METRICS
+                    .archive_uploads
+                    .with_label_values(&["success"])
+                    .inc();
+            }
+            Err(err) => {
+                error!("Could not archive {}: {:#}", key, err);
+                METRICS.archive_uploads.with_label_values(&["error"]).inc();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn builds_the_urls_of_the_archived_frames() {
+        let at = Utc.ymd(2021, 12, 1).and_hms_milli(23, 0, 5, 250);
+        let s3 = Bucket::parse(&FrameArchive {
+            url: "s3://incidents/hawkeye/".to_string(),
+            region: Some("us-east-1".to_string()),
+            thumbnail_seconds: None,
+        })
+        .unwrap();
+        let key = s3.key("channel-1", "default_content_to_slate", at, "frame.jpg");
+        assert_eq!(
+            key,
+            "hawkeye/channel-1/2021-12-01/20211201T230005.250Z_default_content_to_slate/frame.jpg"
+        );
+        assert_eq!(
+            s3.url(&key),
+            format!("https://incidents.s3.us-east-1.amazonaws.com/{}", key)
+        );
+
+        let gcs = Bucket::parse(&FrameArchive {
+            url: "gs://incidents".to_string(),
+            region: None,
+            thumbnail_seconds: None,
+        })
+        .unwrap();
+        let key = gcs.key(
+            "channel-1",
+            "black_content_to_black",
+            at,
+            "thumbnail-3s.jpg",
+        );
+        assert_eq!(
+            gcs.url(&key),
+            "https://storage.googleapis.com/incidents/channel-1/2021-12-01/20211201T230005.250Z_black_content_to_black/thumbnail-3s.jpg"
+        );
+        assert_eq!(
+            gcs.clip_key("channel-1", at),
+            "channel-1/2021-12-01/20211201T230005.250Z_clip.ts"
+        );
+    }
+}
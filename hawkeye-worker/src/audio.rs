@@ -0,0 +1,91 @@
+use crate::metrics::METRICS;
+use crate::state::STATE;
+use gstreamer as gst;
+use hawkeye_core::models::{AudioCondition, AudioMode};
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Branch of the pipeline measuring the loudness of the audio of the stream, posting a `level`
+/// message on the bus twice a second.
+pub const AUDIO_BRANCH: &str = "queue ! capsfilter caps=\"audio/mpeg; audio/x-ac3; audio/x-eac3\" ! decodebin ! audioconvert ! level interval=500000000 ! fakesink sync=false";
+
+/// Branch of the pipeline measuring the loudness of the audio already decoded, like the one of
+/// `uridecodebin`.
+pub const RAW_AUDIO_BRANCH: &str =
+    "queue ! audioconvert ! level interval=500000000 ! fakesink sync=false";
+
+/// Age of the last measure after which the stream is considered without audio.
+const STALE_AFTER: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    static ref LOUDNESS: Mutex<Option<(f64, Instant)>> = Mutex::new(None);
+}
+
+/// Loudness of the loudest channel of a `level` message, in dBFS.
+pub fn level_loudness(structure: &gst::StructureRef) -> Option<f64> {
+    if structure.name() != "level" {
+        return None;
+    }
+    let rms = structure.get::<glib::ValueArray>("rms").ok()?;
+    rms.iter()
+        .filter_map(|channel| channel.get::<f64>().ok())
+        .fold(None, |loudest: Option<f64>, channel| {
+            Some(loudest.map_or(channel, |loudest| loudest.max(channel)))
+        })
+}
+
+pub fn record_loudness(loudness: f64) {
+    *LOUDNESS.lock().unwrap() = Some((loudness, Instant::now()));
+    METRICS.audio_loudness.set(loudness);
+    STATE.lock().unwrap().loudness = Some(loudness);
+}
+
+/// Loudness of the audio in dBFS, missing when the stream has no audio or it stopped.
+pub fn loudness() -> Option<f64> {
+    let latest = *LOUDNESS.lock().unwrap();
+    latest
+        .filter(|(_, measured)| measured.elapsed() < STALE_AFTER)
+        .map(|(loudness, _)| loudness)
+}
+
+/// Whether the audio, of the given loudness, meets the condition. Streams without audio are
+/// silent.
+pub fn matches(condition: &AudioCondition, loudness: Option<f64>) -> bool {
+    let silent = is_silent(loudness, condition.silence_threshold());
+    match condition.audio {
+        AudioMode::Silent => silent,
+        AudioMode::Audible => !silent,
+    }
+}
+
+/// Whether the audio, of the given loudness, is quieter than the threshold in dBFS. Streams
+/// without audio are silent.
+pub fn is_silent(loudness: Option<f64>, threshold: f64) -> bool {
+    loudness.map_or(true, |loudness| loudness < threshold)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hawkeye_core::models::Combine;
+
+    #[test]
+    fn compares_loudness_with_silence_threshold() {
+        let silent = AudioCondition {
+            audio: AudioMode::Silent,
+            combine: Combine::And,
+            silence_threshold: Some(-40.0),
+        };
+        assert!(matches(&silent, Some(-60.0)));
+        assert!(!matches(&silent, Some(-20.0)));
+        assert!(matches(&silent, None));
+
+        let audible = AudioCondition {
+            audio: AudioMode::Audible,
+            ..silent
+        };
+        assert!(!matches(&audible, Some(-60.0)));
+        assert!(matches(&audible, Some(-20.0)));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::metrics::METRICS;
+use crate::retry;
+use crate::scte35::{self, LAST_SPLICE_EVENT_ID};
+use crate::template::{self, Variables};
+use async_trait::async_trait;
+use color_eyre::{eyre::eyre, Result};
+use hawkeye_core::models::{
+    MediaLiveCall, MediaLiveScheduleAction, RetryPolicy, SnsMessage, SqsMessage,
+};
+use log::info;
+use rusoto_core::credential::{
+    AutoRefreshingProvider, AwsCredentials, ChainProvider, CredentialsError, ProvideAwsCredentials,
+};
+use rusoto_core::{HttpClient, Region};
+use rusoto_medialive::{
+    BatchScheduleActionCreateRequest, BatchUpdateScheduleRequest,
+    ImmediateModeScheduleActionStartSettings, InputSwitchScheduleActionSettings, MediaLive,
+    MediaLiveClient, ScheduleAction, ScheduleActionSettings, ScheduleActionStartSettings,
+    Scte35ReturnToNetworkScheduleActionSettings, Scte35SpliceInsertScheduleActionSettings,
+};
+use rusoto_s3::{PutObjectRequest, S3Client, S3};
+use rusoto_sns::{PublishInput, Sns, SnsClient};
+use rusoto_sqs::{SendMessageRequest, Sqs, SqsClient};
+use rusoto_sts::WebIdentityProvider;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Credentials of the AWS calls, from the web identity token Kubernetes mounts for the service
+/// account of the pod (IRSA), or from the environment, profile and instance metadata otherwise.
+enum Credentials {
+    WebIdentity(AutoRefreshingProvider<WebIdentityProvider>),
+    Chain(ChainProvider),
+}
+
+impl Credentials {
+    fn new() -> Result<Self> {
+        if std::env::var_os("AWS_WEB_IDENTITY_TOKEN_FILE").is_some() {
+            let provider = AutoRefreshingProvider::new(WebIdentityProvider::from_k8s_env())?;
+            Ok(Credentials::WebIdentity(provider))
+        } else {
+            Ok(Credentials::Chain(ChainProvider::new()))
+        }
+    }
+}
+
+#[async_trait]
+impl ProvideAwsCredentials for Credentials {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        match self {
+            Credentials::WebIdentity(provider) => provider.credentials().await,
+            Credentials::Chain(provider) => provider.credentials().await,
+        }
+    }
+}
+
+/// The region of the action, or the one of the worker, from `AWS_DEFAULT_REGION` or
+/// `AWS_REGION`.
+fn region(name: Option<&str>) -> Result<Region> {
+    match name {
+        Some(name) => Region::from_str(name).map_err(|e| eyre!("Invalid AWS region: {}", e)),
+        None => Ok(Region::default()),
+    }
+}
+
+/// Runs an AWS call from the thread of the actions, which is not part of a Tokio runtime,
+/// giving up after the timeout, if any.
+fn block_on<F: Future>(future: F, timeout: Option<Duration>) -> Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    match timeout {
+        Some(timeout) => runtime
+            .block_on(tokio::time::timeout(timeout, future))
+            .map_err(|_| eyre!("AWS call timed out after {:?}", timeout)),
+        None => Ok(runtime.block_on(future)),
+    }
+}
+
+/// Runs the call until it succeeds, following the retry policy of the action.
+pub fn with_retries<F>(policy: &RetryPolicy, labels: &[&str], mut call: F) -> Result<()>
+where
+    F: FnMut(Option<Duration>) -> Result<()>,
+{
+    retry::run(policy, &METRICS.aws_call_retried, labels, |timeout| {
+        let _timer = METRICS
+            .aws_call_duration
+            .with_label_values(labels)
+            .start_timer();
+        call(timeout)
+    })
+    .map_err(|err| {
+        METRICS.aws_call_error.with_label_values(labels).inc();
+        err
+    })
+}
+
+pub fn call_media_live(call: &MediaLiveCall, labels: &[&str], variables: &Variables) -> Result<()> {
+    let client = MediaLiveClient::new_with(
+        HttpClient::new()?,
+        Credentials::new()?,
+        region(call.region.as_deref())?,
+    );
+    let action = schedule_action(&call.schedule_action, variables);
+    let policy = retry::policy(call.retry.as_ref(), call.retries);
+    with_retries(&policy, labels, |timeout| {
+        let request = BatchUpdateScheduleRequest {
+            channel_id: call.channel_id.clone(),
+            creates: Some(BatchScheduleActionCreateRequest {
+                schedule_actions: vec![action.clone()],
+            }),
+            ..Default::default()
+        };
+        block_on(client.batch_update_schedule(request), timeout)?
+            .map_err(|e| eyre!("MediaLive channel {}: {}", call.channel_id, e))?;
+        Ok(())
+    })?;
+    if let MediaLiveScheduleAction::SpliceInsert { .. } = call.schedule_action {
+        *LAST_SPLICE_EVENT_ID.lock().unwrap() = splice_event_id(&action);
+    }
+    info!(
+        "Scheduled {} on MediaLive channel {}",
+        action.action_name, call.channel_id
+    );
+    Ok(())
+}
+
+pub fn send_sqs(message: &SqsMessage, labels: &[&str], variables: &Variables) -> Result<()> {
+    let client = SqsClient::new_with(
+        HttpClient::new()?,
+        Credentials::new()?,
+        region(message.region.as_deref())?,
+    );
+    let body = template::render_message(message.message.as_deref(), variables);
+    let group_id = message
+        .message_group_id
+        .as_deref()
+        .map(|group_id| template::render(group_id, variables));
+    // FIFO queues without content-based deduplication need an ID, unique to the transition
+    let deduplication_id = group_id.as_ref().map(|_| {
+        format!(
+            "{}-{}-{}",
+            variables.watcher_id,
+            variables.slate_id,
+            variables.timestamp.timestamp_millis()
+        )
+    });
+    let policy = retry::policy(message.retry.as_ref(), message.retries);
+    with_retries(&policy, labels, |timeout| {
+        let request = SendMessageRequest {
+            queue_url: message.queue_url.clone(),
+            message_body: body.clone(),
+            message_group_id: group_id.clone(),
+            message_deduplication_id: deduplication_id.clone(),
+            ..Default::default()
+        };
+        block_on(client.send_message(request), timeout)?
+            .map_err(|e| eyre!("SQS queue {}: {}", message.queue_url, e))?;
+        Ok(())
+    })?;
+    info!("Sent message to SQS queue {}", message.queue_url);
+    Ok(())
+}
+
+pub fn publish_sns(message: &SnsMessage, labels: &[&str], variables: &Variables) -> Result<()> {
+    let client = SnsClient::new_with(
+        HttpClient::new()?,
+        Credentials::new()?,
+        region(message.region.as_deref())?,
+    );
+    let body = template::render_message(message.message.as_deref(), variables);
+    let subject = message
+        .subject
+        .as_deref()
+        .map(|subject| template::render(subject, variables));
+    let policy = retry::policy(message.retry.as_ref(), message.retries);
+    with_retries(&policy, labels, |timeout| {
+        let request = PublishInput {
+            topic_arn: Some(message.topic_arn.clone()),
+            message: body.clone(),
+            subject: subject.clone(),
+            ..Default::default()
+        };
+        block_on(client.publish(request), timeout)?
+            .map_err(|e| eyre!("SNS topic {}: {}", message.topic_arn, e))?;
+        Ok(())
+    })?;
+    info!("Published message to SNS topic {}", message.topic_arn);
+    Ok(())
+}
+
+/// Uploads an object to the bucket, on S3 or on the S3 compatible API at the `endpoint`.
+pub fn put_object(
+    region_name: Option<&str>,
+    endpoint: Option<&str>,
+    bucket: &str,
+    key: &str,
+    body: Vec<u8>,
+    content_type: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let region = match endpoint {
+        Some(endpoint) => Region::Custom {
+            name: region_name.unwrap_or("auto").to_string(),
+            endpoint: endpoint.to_string(),
+        },
+        None => region(region_name)?,
+    };
+    let client = S3Client::new_with(HttpClient::new()?, Credentials::new()?, region);
+    let request = PutObjectRequest {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        body: Some(body.into()),
+        content_type: Some(content_type.to_string()),
+        ..Default::default()
+    };
+    block_on(client.put_object(request), Some(timeout))?
+        .map_err(|e| eyre!("Bucket {}: {}", bucket, e))?;
+    Ok(())
+}
+
+/// The schedule action starting right away, named after the watcher, the slate and the time.
+fn schedule_action(action: &MediaLiveScheduleAction, variables: &Variables) -> ScheduleAction {
+    let timestamp = variables.timestamp.timestamp_millis();
+    let settings = match action {
+        MediaLiveScheduleAction::SpliceInsert {
+            duration,
+            splice_event_id,
+        } => ScheduleActionSettings {
+            scte_35_splice_insert_settings: Some(Scte35SpliceInsertScheduleActionSettings {
+                duration: duration
+                    .map(|seconds| (seconds * scte35::TICKS_PER_SECOND).round() as i64),
+                splice_event_id: splice_event_id.unwrap_or((timestamp / 1000) as u32).into(),
+            }),
+            ..Default::default()
+        },
+        MediaLiveScheduleAction::ReturnToNetwork { splice_event_id } => ScheduleActionSettings {
+            scte_35_return_to_network_settings: Some(Scte35ReturnToNetworkScheduleActionSettings {
+                splice_event_id: splice_event_id
+                    .or(*LAST_SPLICE_EVENT_ID.lock().unwrap())
+                    .unwrap_or_default()
+                    .into(),
+            }),
+            ..Default::default()
+        },
+        MediaLiveScheduleAction::InputSwitch { input_attachment } => ScheduleActionSettings {
+            input_switch_settings: Some(InputSwitchScheduleActionSettings {
+                input_attachment_name_reference: input_attachment.clone(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    };
+    ScheduleAction {
+        action_name: format!(
+            "hawkeye-{}-{}-{}",
+            variables.watcher_id, variables.slate_id, timestamp
+        ),
+        schedule_action_settings: settings,
+        schedule_action_start_settings: ScheduleActionStartSettings {
+            immediate_mode_schedule_action_start_settings: Some(
+                ImmediateModeScheduleActionStartSettings {},
+            ),
+            ..Default::default()
+        },
+    }
+}
+
+fn splice_event_id(action: &ScheduleAction) -> Option<u32> {
+    action
+        .schedule_action_settings
+        .scte_35_splice_insert_settings
+        .as_ref()
+        .map(|settings| settings.splice_event_id as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn schedules_splice_insert_right_away() {
+        let variables = Variables {
+            watcher_id: "channel-1".to_string(),
+            slate_id: "network".to_string(),
+            timestamp: Utc.timestamp_millis(1_638_399_600_250),
+            stream_time: None,
+            frame_url: None,
+            thumbnail_urls: Vec::new(),
+            clip_url: None,
+        };
+        let action = schedule_action(
+            &MediaLiveScheduleAction::SpliceInsert {
+                duration: Some(30.0),
+                splice_event_id: None,
+            },
+            &variables,
+        );
+
+        assert_eq!(
+            action.action_name,
+            "hawkeye-channel-1-network-1638399600250"
+        );
+        let settings = action
+            .schedule_action_settings
+            .scte_35_splice_insert_settings;
+        assert_eq!(
+            settings,
+            Some(Scte35SpliceInsertScheduleActionSettings {
+                duration: Some(2_700_000),
+                splice_event_id: 1_638_399_600,
+            })
+        );
+        assert!(action
+            .schedule_action_start_settings
+            .immediate_mode_schedule_action_start_settings
+            .is_some());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::state::STATE;
+use crate::video_stream::Detection;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Number of bins of the histograms of the similarities.
+const BINS: usize = 20;
+
+lazy_static! {
+    static ref CALIBRATION: Mutex<Option<Calibration>> = Mutex::new(None);
+}
+
+/// Similarities of the frames with each slate, sampled while calibrating.
+#[derive(Default, Debug)]
+pub struct Calibration {
+    samples: BTreeMap<String, Vec<f64>>,
+}
+
+/// Distribution of the similarities of the frames with a slate.
+#[derive(Serialize, Debug)]
+pub struct SlateCalibration {
+    pub slate_id: String,
+    /// Frames compared with the slate, black frames are not.
+    pub samples: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Current threshold of the slate.
+    pub threshold: f64,
+    /// Threshold best separating the frames showing the slate from the others, missing when
+    /// all the frames were alike.
+    pub suggested_threshold: Option<f64>,
+    pub histogram: Vec<Bin>,
+}
+
+/// Number of frames with a similarity in `[from, to)`, the last bin includes `to`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Bin {
+    pub from: f64,
+    pub to: f64,
+    pub count: usize,
+}
+
+impl Calibration {
+    pub fn record(&mut self, detection: &Detection) {
+        for slate in detection.slates.iter() {
+            if let Some(similarity) = slate.similarity {
+                self.samples
+                    .entry(slate.slate_id.clone())
+                    .or_default()
+                    .push(similarity);
+            }
+        }
+    }
+
+    /// Distribution of the similarities with each slate the worker compares the frames with.
+    pub fn report(&self) -> Vec<SlateCalibration> {
+        let slates = STATE.lock().unwrap().slates.clone();
+        slates
+            .into_iter()
+            .map(|slate| {
+                let samples = self
+                    .samples
+                    .get(&slate.slate_id)
+                    .cloned()
+                    .unwrap_or_default();
+                calibrate(slate.slate_id, slate.threshold, samples)
+            })
+            .collect()
+    }
+}
+
+/// Samples the similarities of the frames until the returned guard is finished or dropped,
+/// returns `None` when a calibration is already running.
+pub fn start() -> Option<Running> {
+    let mut calibration = CALIBRATION.lock().unwrap();
+    if calibration.is_some() {
+        return None;
+    }
+    *calibration = Some(Calibration::default());
+    Some(Running)
+}
+
+/// Records the similarities of a frame when calibrating.
+pub fn record(detection: &Detection) {
+    if let Some(calibration) = CALIBRATION.lock().unwrap().as_mut() {
+        calibration.record(detection);
+    }
+}
+
+/// A running calibration, stopped when dropped so an abandoned request does not block the next
+/// calibrations.
+pub struct Running;
+
+impl Running {
+    pub fn finish(self) -> Calibration {
+        CALIBRATION.lock().unwrap().take().unwrap_or_default()
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        CALIBRATION.lock().unwrap().take();
+    }
+}
+
+fn calibrate(slate_id: String, threshold: f64, mut samples: Vec<f64>) -> SlateCalibration {
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    SlateCalibration {
+        slate_id,
+        samples: samples.len(),
+        min: samples.first().copied(),
+        max: samples.last().copied(),
+        threshold,
+        suggested_threshold: suggest_threshold(&samples),
+        histogram: histogram(&samples),
+    }
+}
+
+/// Bins of the sorted similarities, from `0` to the highest one.
+fn histogram(sorted: &[f64]) -> Vec<Bin> {
+    let max = match sorted.last() {
+        Some(max) if *max > 0.0 => *max,
+        Some(_) => {
+            return vec![Bin {
+                from: 0.0,
+                to: 0.0,
+                count: sorted.len(),
+            }]
+        }
+        None => return Vec::new(),
+    };
+    let width = max / BINS as f64;
+    let mut bins: Vec<Bin> = (0..BINS)
+        .map(|i| Bin {
+            from: width * i as f64,
+            to: width * (i + 1) as f64,
+            count: 0,
+        })
+        .collect();
+    for similarity in sorted {
+        let index = ((similarity / width) as usize).min(BINS - 1);
+        bins[index].count += 1;
+    }
+    bins
+}
+
+/// Splits the sorted similarities in the two groups differing the most (Otsu's method), the
+/// frames showing the slate and the others, and suggests the middle of the gap between them.
+fn suggest_threshold(sorted: &[f64]) -> Option<f64> {
+    let count = sorted.len() as f64;
+    let total: f64 = sorted.iter().sum();
+    let mut below = 0.0;
+    let mut best: Option<(f64, f64)> = None;
+    for (i, pair) in sorted.windows(2).enumerate() {
+        below += pair[0];
+        if pair[0] == pair[1] {
+            continue;
+        }
+        let below_count = (i + 1) as f64;
+        let above_count = count - below_count;
+        let mean_below = below / below_count;
+        let mean_above = (total - below) / above_count;
+        let variance = below_count * above_count * (mean_above - mean_below).powi(2);
+        if best.map_or(true, |(best_variance, _)| variance > best_variance) {
+            best = Some((variance, (pair[0] + pair[1]) / 2.0));
+        }
+    }
+    best.map(|(_, threshold)| threshold)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suggests_threshold_between_slate_and_content() {
+        let mut samples = vec![0.01, 0.02, 0.015, 0.3, 0.35, 0.4, 0.32];
+        samples.extend(vec![0.31; 10]);
+        let calibration = calibrate("default".to_string(), 0.015, samples);
+
+        assert_eq!(calibration.samples, 17);
+        assert_eq!(calibration.min, Some(0.01));
+        assert_eq!(calibration.max, Some(0.4));
+        let suggested = calibration.suggested_threshold.unwrap();
+        assert!((suggested - 0.16).abs() < 1e-9);
+        assert_eq!(calibration.histogram.len(), BINS);
+        assert_eq!(
+            calibration
+                .histogram
+                .iter()
+                .map(|bin| bin.count)
+                .sum::<usize>(),
+            17
+        );
+        assert_eq!(calibration.histogram[BINS - 1].count, 1);
+    }
+
+    #[test]
+    fn no_suggestion_for_identical_frames() {
+        let calibration = calibrate("default".to_string(), 0.015, vec![0.0; 5]);
+        assert_eq!(calibration.suggested_threshold, None);
+        assert_eq!(
+            calibration.histogram,
+            vec![Bin {
+                from: 0.0,
+                to: 0.0,
+                count: 5
+            }]
+        );
+    }
+}
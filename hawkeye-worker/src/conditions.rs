@@ -0,0 +1,178 @@
+use crate::audio;
+use crate::video_stream::Detection;
+use hawkeye_core::models::{
+    in_daily_window, minutes_of_day, Condition, Hysteresis, VideoMode, DEFAULT_SILENCE_DBFS,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a frame shows, the conditions of the transitions are evaluated on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Facts {
+    /// Slates matching the frame.
+    pub matched: Vec<String>,
+    pub is_black: bool,
+    /// See `audio::loudness`.
+    pub loudness: Option<f64>,
+    /// Minutes since midnight, in UTC.
+    pub minute_of_day: u32,
+}
+
+impl Facts {
+    pub fn new(detection: &Detection, loudness: Option<f64>, now: SystemTime) -> Self {
+        let minutes = now
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() / 60)
+            .unwrap_or_default();
+        Self {
+            matched: detection
+                .slates
+                .iter()
+                .filter(|slate| slate.is_match)
+                .map(|slate| slate.slate_id.clone())
+                .collect(),
+            is_black: detection.is_black,
+            loudness,
+            minute_of_day: (minutes % (24 * 60)) as u32,
+        }
+    }
+}
+
+/// Whether the frame meets the condition of a transition of the given slate.
+pub fn is_met(condition: &Condition, slate_id: &str, facts: &Facts) -> bool {
+    match condition {
+        Condition::Slate { slate_id: id } => {
+            let id = id.as_deref().unwrap_or(slate_id);
+            facts.matched.iter().any(|matched| matched == id)
+        }
+        Condition::Black => facts.is_black,
+        Condition::Silence { threshold } => {
+            audio::is_silent(facts.loudness, threshold.unwrap_or(DEFAULT_SILENCE_DBFS))
+        }
+        Condition::TimeWindow { start, end } => {
+            match (minutes_of_day(start), minutes_of_day(end)) {
+                (Some(start), Some(end)) => in_daily_window(start, end, facts.minute_of_day),
+                _ => false,
+            }
+        }
+        Condition::All { conditions } => conditions
+            .iter()
+            .all(|condition| is_met(condition, slate_id, facts)),
+        Condition::Any { conditions } => conditions
+            .iter()
+            .any(|condition| is_met(condition, slate_id, facts)),
+        Condition::AtLeast { count, conditions } => {
+            conditions
+                .iter()
+                .filter(|condition| is_met(condition, slate_id, facts))
+                .count()
+                >= *count
+        }
+        Condition::Not { condition } => !is_met(condition, slate_id, facts),
+    }
+}
+
+/// Mode of a transition, only changing once the new mode lasted the frames of its hysteresis.
+pub struct Settling {
+    enter_frames: u32,
+    exit_frames: u32,
+    settled: Option<VideoMode>,
+    /// Mode differing from the settled one, with the consecutive frames it lasted.
+    pending: Option<(VideoMode, u32)>,
+}
+
+impl Settling {
+    pub fn new(hysteresis: Option<&Hysteresis>) -> Self {
+        Self {
+            enter_frames: hysteresis.and_then(|h| h.enter_frames).unwrap_or(1),
+            exit_frames: hysteresis.and_then(|h| h.exit_frames).unwrap_or(1),
+            settled: None,
+            pending: None,
+        }
+    }
+
+    /// The settled mode, given the mode of the latest frame.
+    pub fn settle(&mut self, mode: VideoMode) -> VideoMode {
+        let settled = *self.settled.get_or_insert(mode);
+        if mode == settled {
+            self.pending = None;
+            return settled;
+        }
+        let frames = match self.pending {
+            Some((pending, frames)) if pending == mode => frames + 1,
+            _ => 1,
+        };
+        let needed = if mode == VideoMode::Content {
+            self.exit_frames
+        } else {
+            self.enter_frames
+        };
+        if frames >= needed {
+            self.settled = Some(mode);
+            self.pending = None;
+            mode
+        } else {
+            self.pending = Some((mode, frames));
+            settled
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn evaluates_composite_conditions() {
+        let condition: Condition = serde_json::from_value(serde_json::json!({
+            "type": "all",
+            "conditions": [
+                {"type": "slate"},
+                {"type": "at_least", "count": 2, "conditions": [
+                    {"type": "silence"},
+                    {"type": "black"},
+                    {"type": "time_window", "start": "22:00", "end": "06:00"}
+                ]}
+            ]
+        }))
+        .unwrap();
+        let facts = Facts {
+            matched: vec!["network".to_string()],
+            is_black: false,
+            loudness: Some(-70.0),
+            minute_of_day: 23 * 60,
+        };
+
+        assert!(is_met(&condition, "network", &facts));
+        assert!(!is_met(&condition, "default", &facts));
+        let noon = Facts {
+            minute_of_day: 12 * 60,
+            ..facts.clone()
+        };
+        assert!(!is_met(&condition, "network", &noon));
+        let loud = Facts {
+            loudness: Some(-10.0),
+            ..facts
+        };
+        assert!(!is_met(&condition, "network", &loud));
+    }
+
+    #[test]
+    fn settles_after_hysteresis_frames() {
+        use VideoMode::{Content, Slate};
+        let mut settling = Settling::new(Some(&Hysteresis {
+            enter_frames: Some(3),
+            exit_frames: Some(2),
+        }));
+        let modes: Vec<VideoMode> = [
+            Content, Slate, Slate, Content, Slate, Slate, Slate, Content, Content,
+        ]
+        .iter()
+        .map(|mode| settling.settle(*mode))
+        .collect();
+
+        assert_eq!(
+            modes,
+            vec![Content, Content, Content, Content, Content, Content, Slate, Slate, Content]
+        );
+    }
+}
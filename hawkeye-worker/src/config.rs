@@ -1,13 +1,207 @@
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
 
+/// Arguments of `hawkeye-worker analyze`, see `analyze::run`.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "analyze",
+    about = "Reports the transitions of the slates and triggers of a watcher in a capture."
+)]
+pub struct AnalyzeConfig {
+    /// Capture analyzed, in any container and codec GStreamer decodes
+    #[structopt(long, parse(from_os_str))]
+    pub input: PathBuf,
+
+    /// Path to the watcher configuration, in JSON or YAML
+    #[structopt(long, parse(from_os_str))]
+    pub config: PathBuf,
+
+    /// Path the JSON report is written to, the standard output when missing
+    #[structopt(long, parse(from_os_str))]
+    pub report: Option<PathBuf>,
+
+    /// URL of the API serving the slates referenced as `slate://<id>`
+    #[structopt(long, env = "HAWKEYE_SLATE_LIBRARY_URL")]
+    pub slate_library_url: Option<String>,
+}
+
+/// Arguments of `hawkeye-worker pack`, see `pack::run`.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "pack",
+    about = "Runs the watchers of a pack side by side, as their configurations come and go."
+)]
+pub struct PackConfig {
+    /// Directory of the configurations of the watchers of the pack to run, `<id>.json` each,
+    /// reloaded while the pack runs
+    #[structopt(parse(from_os_str))]
+    pub members_dir: PathBuf,
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "video-slate-detector",
     about = "Detects slate image and triggers URL request."
 )]
 pub struct AppConfig {
-    // Path to the watcher configuration
+    // Path to the watcher configuration, in JSON or YAML
     #[structopt(parse(from_os_str))]
     pub watcher_path: PathBuf,
+
+    /// Receives the frames of a `file://` capture or an `rtsp://` stream instead of the source of
+    /// the watcher, to run the worker outside Kubernetes. `test://` generates them on a schedule,
+    /// like `test://content=10,default=5`, see `TestSource`
+    #[structopt(long)]
+    pub source: Option<String>,
+
+    /// Plays the `--source` again once it ended, like a capture in a loop
+    #[structopt(long = "loop")]
+    pub loop_source: bool,
+
+    /// Pushes the metrics to this URL, for workers that cannot be scraped
+    #[structopt(long, env = "HAWKEYE_METRICS_PUSH_URL")]
+    pub metrics_push_url: Option<String>,
+
+    /// How the metrics are pushed: `pushgateway` or `remote_write`
+    #[structopt(long, env = "HAWKEYE_METRICS_PUSH_MODE", default_value = "pushgateway")]
+    pub metrics_push_mode: PushMode,
+
+    /// Seconds between pushes of the metrics
+    #[structopt(long, env = "HAWKEYE_METRICS_PUSH_INTERVAL", default_value = "15")]
+    pub metrics_push_interval: u64,
+
+    /// Username of the basic authentication of the push URL
+    #[structopt(long, env = "HAWKEYE_METRICS_PUSH_USERNAME")]
+    pub metrics_push_username: Option<String>,
+
+    /// Password of the basic authentication of the push URL
+    #[structopt(long, env = "HAWKEYE_METRICS_PUSH_PASSWORD", hide_env_values = true)]
+    pub metrics_push_password: Option<String>,
+
+    /// Bearer token sent to the push URL, instead of the basic authentication
+    #[structopt(long, env = "HAWKEYE_METRICS_PUSH_TOKEN", hide_env_values = true)]
+    pub metrics_push_token: Option<String>,
+
+    /// URL of the API serving the slates referenced as `slate://<id>`
+    #[structopt(long, env = "HAWKEYE_SLATE_LIBRARY_URL")]
+    pub slate_library_url: Option<String>,
+
+    /// URL of the API the events of the watcher are reported to, for its event store
+    #[structopt(long, env = "HAWKEYE_EVENTS_URL")]
+    pub events_url: Option<String>,
+
+    /// URL of the API the heartbeats of the worker are sent to
+    #[structopt(long, env = "HAWKEYE_HEARTBEAT_URL")]
+    pub heartbeat_url: Option<String>,
+
+    /// Bearer token of the events and heartbeats sent to the API, the report token of the watcher
+    /// issued by the API
+    #[structopt(long, env = "HAWKEYE_API_TOKEN", hide_env_values = true)]
+    pub api_token: Option<String>,
+
+    /// OpenTelemetry collector receiving the traces of the frame pipeline over OTLP/gRPC
+    #[structopt(long, env = "HAWKEYE_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Format of the logs: `text`, or `json` for one JSON object per line
+    #[structopt(long, env = "HAWKEYE_LOG_FORMAT", default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Commands the `exec` actions can run, comma separated absolute paths, each one followed by
+    /// the only arguments allowed when limited
+    #[structopt(long, env = "HAWKEYE_EXEC_ALLOWLIST", use_delimiter = true)]
+    pub exec_allowlist: Vec<String>,
+
+    /// Kubernetes `Secret`s the HTTP actions of a reloaded definition can reference, comma
+    /// separated names. None when missing
+    #[structopt(long, env = "HAWKEYE_ACTION_SECRETS", use_delimiter = true)]
+    pub action_secrets: Vec<String>,
+
+    /// Bearer token of the API, required by the endpoints changing the detection of the worker.
+    /// They are disabled when missing
+    #[structopt(long, env = "HAWKEYE_CONTROL_TOKEN", hide_env_values = true)]
+    pub control_token: Option<String>,
+
+    /// Seconds without a frame, while the input receives packets, before the pipeline is
+    /// stalled. The pipeline is not watched when missing
+    #[structopt(long, env = "HAWKEYE_STALL_TIMEOUT")]
+    pub stall_timeout: Option<u64>,
+
+    /// Seconds without a frame before the worker is not ready, see `/readyz`
+    #[structopt(long, env = "HAWKEYE_READY_TIMEOUT", default_value = "10")]
+    pub ready_timeout: u64,
+
+    /// Seconds the worker has to stop once asked to, before it is killed. Matches the
+    /// `terminationGracePeriodSeconds` of its pod
+    #[structopt(long, env = "HAWKEYE_GRACE_PERIOD", default_value = "30")]
+    pub grace_period: u64,
+
+    /// What the watchdog does when the pipeline stalls: `restart` the pipeline, or `exit` so the
+    /// worker is restarted
+    #[structopt(long, env = "HAWKEYE_STALL_ACTION", default_value = "restart")]
+    pub stall_action: StallAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StallAction {
+    Restart,
+    Exit,
+}
+
+impl StallAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StallAction::Restart => "restart",
+            StallAction::Exit => "exit",
+        }
+    }
+}
+
+impl FromStr for StallAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restart" => Ok(StallAction::Restart),
+            "exit" => Ok(StallAction::Exit),
+            _ => Err(format!("Unknown stall action: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PushMode {
+    Pushgateway,
+    RemoteWrite,
+}
+
+impl FromStr for PushMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pushgateway" => Ok(PushMode::Pushgateway),
+            "remote_write" => Ok(PushMode::RemoteWrite),
+            _ => Err(format!("Unknown metrics push mode: {}", s)),
+        }
+    }
 }
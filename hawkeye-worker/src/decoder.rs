@@ -0,0 +1,123 @@
+use gst::prelude::*;
+use gstreamer as gst;
+use hawkeye_core::models::Decoder;
+use log::{info, warn};
+
+/// Name of the decoder in the pipelines, its warnings being counted as decode errors.
+const DECODER_NAME: &str = "decoder";
+
+const SOFTWARE_DECODER: &str = "avdec_h264";
+const VAAPI_DECODER: &str = "vaapih264dec";
+const NVDEC_DECODER: &str = "nvh264dec";
+
+/// The H.264 decoders tried for the decoder of the analysis, in order. The software decoder comes
+/// last, always.
+fn candidates(decoder: Decoder) -> &'static [&'static str] {
+    match decoder {
+        Decoder::Software => &[SOFTWARE_DECODER],
+        Decoder::Vaapi => &[VAAPI_DECODER, SOFTWARE_DECODER],
+        Decoder::Nvdec => &[NVDEC_DECODER, SOFTWARE_DECODER],
+        Decoder::Auto => &[NVDEC_DECODER, VAAPI_DECODER, SOFTWARE_DECODER],
+    }
+}
+
+/// The first decoder available, the software one when none is.
+fn select(decoder: Decoder, is_available: impl Fn(&str) -> bool) -> &'static str {
+    candidates(decoder)
+        .iter()
+        .copied()
+        .find(|name| is_available(name))
+        .unwrap_or(SOFTWARE_DECODER)
+}
+
+/// The plugins of the hardware decoders only register their elements when they find a device
+/// they can decode with, so a missing GPU falls back to the software decoder.
+fn is_available(name: &str) -> bool {
+    gst::ElementFactory::find(name).is_some()
+}
+
+/// The decoder to use, logging when the hardware decoder of the watcher is not available.
+fn available(decoder: Decoder) -> &'static str {
+    let name = select(decoder, is_available);
+    if decoder != Decoder::Software && name == SOFTWARE_DECODER {
+        warn!(
+            "No hardware decoder found for {:?}, falling back to {}",
+            decoder, SOFTWARE_DECODER
+        );
+    } else {
+        info!("Decoding the frames with {}", name);
+    }
+    name
+}
+
+/// The elements decoding the H.264 frames. The hardware decoders download their frames to the
+/// system memory, where the rest of the pipeline reads them.
+pub fn h264_decoder(decoder: Decoder) -> String {
+    describe(available(decoder))
+}
+
+fn describe(name: &str) -> String {
+    if name == SOFTWARE_DECODER {
+        format!("{} name={}", name, DECODER_NAME)
+    } else {
+        format!("{} name={} ! video/x-raw", name, DECODER_NAME)
+    }
+}
+
+/// Makes `uridecodebin` pick the decoder of the watcher for the HLS and DASH sources, by ranking
+/// the hardware decoder above the software one.
+pub fn prefer(decoder: Decoder) {
+    let name = available(decoder);
+    if name == SOFTWARE_DECODER {
+        return;
+    }
+    if let Some(factory) = gst::ElementFactory::find(name) {
+        factory.set_rank(gst::Rank::Primary);
+    }
+    if let Some(factory) = gst::ElementFactory::find(SOFTWARE_DECODER) {
+        factory.set_rank(gst::Rank::Secondary);
+    }
+}
+
+/// Whether the element, from its path in the pipeline, is one of the decoders, named or plugged
+/// by `uridecodebin`.
+pub fn is_decoder(path: &str) -> bool {
+    path.ends_with(&format!("/{}", DECODER_NAME))
+        || candidates(Decoder::Auto)
+            .iter()
+            .any(|name| path.contains(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_the_software_decoder() {
+        let nothing = |_: &str| false;
+        let nvdec = |name: &str| name == NVDEC_DECODER;
+        let vaapi = |name: &str| name == VAAPI_DECODER;
+
+        assert_eq!(select(Decoder::Vaapi, nothing), SOFTWARE_DECODER);
+        assert_eq!(select(Decoder::Vaapi, nvdec), SOFTWARE_DECODER);
+        assert_eq!(select(Decoder::Vaapi, vaapi), VAAPI_DECODER);
+        assert_eq!(select(Decoder::Nvdec, nvdec), NVDEC_DECODER);
+        assert_eq!(select(Decoder::Auto, vaapi), VAAPI_DECODER);
+        assert_eq!(select(Decoder::Software, nvdec), SOFTWARE_DECODER);
+
+        assert_eq!(describe(SOFTWARE_DECODER), "avdec_h264 name=decoder");
+        assert_eq!(
+            describe(NVDEC_DECODER),
+            "nvh264dec name=decoder ! video/x-raw"
+        );
+    }
+
+    #[test]
+    fn finds_the_decoders_of_the_warnings() {
+        assert!(is_decoder("/GstPipeline:pipeline0/GstAvdec_h264:decoder"));
+        assert!(is_decoder(
+            "/GstPipeline:pipeline0/GstURIDecodeBin:src/GstDecodeBin:decodebin0/nvh264dec0"
+        ));
+        assert!(!is_decoder("/GstPipeline:pipeline0/GstTSDemux:demux"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::config::StallAction;
+use crate::metrics::METRICS;
+use crate::state::ActionRecord;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use color_eyre::{eyre::eyre, Result};
+use crossbeam::channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use hawkeye_core::models::{VideoMode, WatcherEvent, WatcherEventKind};
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Events waiting to be reported, the new ones are dropped when the API is not keeping up.
+const MAX_PENDING: usize = 10_000;
+
+/// Most events reported in one request.
+const MAX_BATCH: usize = 500;
+
+/// How long the events wait to be reported together.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+const REPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref SENDER: Mutex<Option<Sender<WatcherEvent>>> = Mutex::new(None);
+    /// Disconnected once the reporter ended, after sending the pending events.
+    static ref REPORTED: Mutex<Option<Receiver<()>>> = Mutex::new(None);
+}
+
+/// Reports the events of the worker to the event store of the API at `api_url`, in the
+/// background.
+pub fn spawn(api_url: &str, token: Option<String>, watcher_id: &str) {
+    let url = format!(
+        "{}/v1/watchers/{}/events",
+        api_url.trim_end_matches('/'),
+        watcher_id
+    );
+    log::info!("Reporting the events to {}", url);
+    let (sender, receiver) = bounded(MAX_PENDING);
+    *SENDER.lock().unwrap() = Some(sender);
+    let (reporting, reported) = bounded(0);
+    *REPORTED.lock().unwrap() = Some(reported);
+    thread::spawn(move || {
+        let _reporting: Sender<()> = reporting;
+        run(&url, token.as_deref(), receiver)
+    });
+}
+
+/// Sends the pending events, waiting at most `timeout`, before the worker exits. The events
+/// queued afterwards are not reported.
+pub fn flush(timeout: Duration) {
+    SENDER.lock().unwrap().take();
+    if let Some(reported) = REPORTED.lock().unwrap().take() {
+        let _ = reported.recv_timeout(timeout);
+    }
+}
+
+/// Queues the event to be reported, when the events are reported.
+pub fn report(event: WatcherEvent) {
+    if let Some(sender) = SENDER.lock().unwrap().as_ref() {
+        if sender.try_send(event).is_err() {
+            METRICS
+                .events_reported
+                .with_label_values(&["dropped"])
+                .inc();
+        }
+    }
+}
+
+/// The stream switched to `to`, showing the slate when it is one.
+pub fn detection(slate_id: Option<&str>, from: VideoMode, to: VideoMode) -> WatcherEvent {
+    WatcherEvent {
+        slate_id: slate_id.map(String::from),
+        from: Some(from),
+        to: Some(to),
+        ..event(WatcherEventKind::Detection, Utc::now().timestamp_millis())
+    }
+}
+
+/// The slate or trigger switched from a video mode to another.
+pub fn transition(slate_id: &str, from: VideoMode, to: VideoMode) -> WatcherEvent {
+    WatcherEvent {
+        slate_id: Some(slate_id.to_string()),
+        from: Some(from),
+        to: Some(to),
+        ..event(WatcherEventKind::Transition, Utc::now().timestamp_millis())
+    }
+}
+
+/// The watchdog found the pipeline stalled, and restarted it or is exiting.
+pub fn stall(action: StallAction) -> WatcherEvent {
+    WatcherEvent {
+        action: Some(action.as_str().to_string()),
+        ..event(WatcherEventKind::Stall, Utc::now().timestamp_millis())
+    }
+}
+
+/// The worker stopped, after detecting the video mode `from` last.
+pub fn shutdown(from: Option<VideoMode>) -> WatcherEvent {
+    WatcherEvent {
+        from,
+        ..event(WatcherEventKind::Shutdown, Utc::now().timestamp_millis())
+    }
+}
+
+/// The action recorded in the history of the worker.
+pub fn action(record: &ActionRecord) -> WatcherEvent {
+    WatcherEvent {
+        slate_id: Some(record.slate_id.clone()),
+        from: Some(record.from),
+        to: Some(record.to),
+        action: Some(record.action.clone()),
+        success: Some(record.success),
+        error: record.error.clone(),
+        latency_ms: Some(record.latency_ms),
+        ..event(WatcherEventKind::Action, record.timestamp_ms as i64)
+    }
+}
+
+fn event(kind: WatcherEventKind, timestamp_ms: i64) -> WatcherEvent {
+    WatcherEvent {
+        timestamp: Utc
+            .timestamp_millis(timestamp_ms)
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+        kind,
+        slate_id: None,
+        from: None,
+        to: None,
+        action: None,
+        success: None,
+        error: None,
+        latency_ms: None,
+    }
+}
+
+/// Sends the events in batches, the failed batches being sent again with the next events.
+fn run(url: &str, token: Option<&str>, receiver: Receiver<WatcherEvent>) {
+    let mut pending: Vec<WatcherEvent> = Vec::new();
+    loop {
+        let deadline = Instant::now() + FLUSH_INTERVAL;
+        while pending.len() < MAX_BATCH {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => pending.push(event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return send_pending(url, token, &pending),
+            }
+        }
+        if pending.is_empty() {
+            continue;
+        }
+        let batch_size = pending.len().min(MAX_BATCH);
+        match send(url, token, &pending[..batch_size]) {
+            Ok(_) => {
+                METRICS
+                    .events_reported
+                    .with_label_values(&["success"])
+                    .inc_by(batch_size as u64);
+                pending.drain(..batch_size);
+            }
+            Err(err) => {
+                log::warn!("Could not report {} events: {:#}", batch_size, err);
+                // Keeps the latest events when the API stays unreachable
+                let excess = pending.len().saturating_sub(MAX_PENDING);
+                METRICS
+                    .events_reported
+                    .with_label_values(&["dropped"])
+                    .inc_by(excess as u64);
+                pending.drain(..excess);
+            }
+        }
+    }
+}
+
+/// Sends the pending events once, when the worker exits.
+fn send_pending(url: &str, token: Option<&str>, pending: &[WatcherEvent]) {
+    for batch in pending.chunks(MAX_BATCH) {
+        if let Err(err) = send(url, token, batch) {
+            log::warn!("Could not report {} events: {:#}", pending.len(), err);
+            return;
+        }
+        METRICS
+            .events_reported
+            .with_label_values(&["success"])
+            .inc_by(batch.len() as u64);
+    }
+}
+
+fn send(url: &str, token: Option<&str>, events: &[WatcherEvent]) -> Result<()> {
+    let mut request = ureq::post(url);
+    request.timeout(REPORT_TIMEOUT);
+    if let Some(token) = token {
+        request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let response = request
+        .set("Content-Type", "application/json")
+        .send_string(&serde_json::to_string(events)?);
+    if response.error() {
+        return Err(eyre!("HTTP error ({}) from {}", response.status(), url));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn describes_the_actions_executed() {
+        let record = ActionRecord {
+            index: 3,
+            timestamp_ms: 1_638_399_605_250,
+            slate_id: "network".to_string(),
+            from: VideoMode::Content,
+            to: VideoMode::Slate,
+            action: "http_call POST https://ads.example.com/break".to_string(),
+            success: false,
+            error: Some("HTTP error 503".to_string()),
+            status_code: Some(503),
+            latency_ms: 120,
+            retries: 2,
+            replay_of: None,
+            definition: None,
+        };
+        let event = action(&record);
+
+        assert_eq!(event.timestamp, "2021-12-01T23:00:05.250Z");
+        assert_eq!(event.kind, WatcherEventKind::Action);
+        assert_eq!(event.slate_id.as_deref(), Some("network"));
+        assert_eq!(event.success, Some(false));
+        assert_eq!(event.latency_ms, Some(120));
+        assert_eq!(
+            serde_json::to_value(&transition("black", VideoMode::Content, VideoMode::Black))
+                .unwrap()["kind"],
+            "transition"
+        );
+    }
+}
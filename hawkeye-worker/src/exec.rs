@@ -0,0 +1,159 @@
+use crate::template::{self, Variables};
+use chrono::SecondsFormat;
+use color_eyre::{eyre::eyre, Result};
+use hawkeye_core::models::ExecCommand;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bytes of the output of a command logged, the rest is dropped.
+const MAX_OUTPUT_BYTES: u64 = 64 * 1024;
+
+/// `PATH` of the commands, their environment being cleared.
+const COMMAND_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+lazy_static! {
+    static ref ALLOWLIST: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Commands the actions can run, set once when the worker starts.
+pub fn set_allowlist(allowlist: &[String]) {
+    *ALLOWLIST.lock().unwrap() = allowlist.to_vec();
+}
+
+pub fn is_allowed(command: &ExecCommand) -> bool {
+    command.is_allowed(&ALLOWLIST.lock().unwrap())
+}
+
+/// Runs the command with only the configured environment, in `/tmp` and without input, killing
+/// it once its timeout is over. Its output is logged when it completes.
+pub fn run(command: &ExecCommand, variables: &Variables) -> Result<()> {
+    if !is_allowed(command) {
+        return Err(eyre!(
+            "Command {} is not allowed by HAWKEYE_EXEC_ALLOWLIST",
+            command.command
+        ));
+    }
+    let args: Vec<String> = command
+        .args
+        .iter()
+        .flatten()
+        .map(|arg| template::render(arg, variables))
+        .collect();
+    let mut process = Command::new(&command.command);
+    process
+        .args(&args)
+        .env_clear()
+        .env("PATH", COMMAND_PATH)
+        .env("HAWKEYE_WATCHER_ID", &variables.watcher_id)
+        .env("HAWKEYE_SLATE_ID", &variables.slate_id)
+        .env(
+            "HAWKEYE_TIMESTAMP",
+            variables
+                .timestamp
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .current_dir("/tmp")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    for (name, value) in command.env.iter().flatten() {
+        process.env(name, template::render(value, variables));
+    }
+
+    let mut child = process
+        .spawn()
+        .map_err(|e| eyre!("Command {}: {}", command.command, e))?;
+    // Read on their own threads, so a full pipe does not block the command
+    let stdout = child.stdout.take().map(read_output);
+    let stderr = child.stderr.take().map(read_output);
+
+    let timeout = Duration::from_secs(command.timeout() as u64);
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if started.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    let output = |reader: Option<thread::JoinHandle<String>>| {
+        reader
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default()
+    };
+    for line in output(stdout).lines() {
+        info!("{}: {}", command.command, line);
+    }
+    for line in output(stderr).lines() {
+        warn!("{}: {}", command.command, line);
+    }
+    match status {
+        Some(status) if status.success() => Ok(()),
+        Some(status) => Err(eyre!("Command {} failed: {}", command.command, status)),
+        None => Err(eyre!(
+            "Command {} killed after {} seconds",
+            command.command,
+            timeout.as_secs()
+        )),
+    }
+}
+
+fn read_output<R: Read + Send + 'static>(output: R) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let mut output = output;
+        let _ = output
+            .by_ref()
+            .take(MAX_OUTPUT_BYTES)
+            .read_to_end(&mut bytes);
+        // Drain the rest so the command does not block on a full pipe
+        let _ = std::io::copy(&mut output, &mut std::io::sink());
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(command: &str, args: &[&str], timeout: u32) -> ExecCommand {
+        ExecCommand {
+            command: command.to_string(),
+            args: Some(args.iter().map(|arg| arg.to_string()).collect()),
+            description: None,
+            env: None,
+            timeout: Some(timeout),
+        }
+    }
+
+    #[test]
+    fn runs_allowed_commands_until_their_timeout() {
+        set_allowlist(&["/bin/sh".to_string()]);
+        let variables = Variables::new("network");
+
+        assert!(run(
+            &command(
+                "/bin/sh",
+                &["-c", "test \"$HAWKEYE_SLATE_ID\" = network"],
+                5
+            ),
+            &variables
+        )
+        .is_ok());
+        assert!(run(&command("/bin/sh", &["-c", "exit 3"], 5), &variables).is_err());
+        let started = Instant::now();
+        assert!(run(&command("/bin/sh", &["-c", "sleep 10"], 1), &variables).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(run(&command("/bin/echo", &["{{slate_id}}"], 5), &variables).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Regression tests of the detection, run with `cargo test --features video-tests`: each case of
+//! `fixtures/golden` describes a short clip, rendered from the images of `resources` as a
+//! MPEG-TS stream, with the transitions its watcher must report. The clips are analyzed like
+//! `hawkeye-worker analyze` does, so a change of the detectors, triggers or pipelines shows up as
+//! transitions moving, appearing or disappearing, or as scores out of their tolerance.
+use crate::analyze::{Analyzer, Capture, Report};
+use crate::reload;
+use color_eyre::{eyre::eyre, Result};
+use gst::prelude::*;
+use gstreamer as gst;
+use hawkeye_core::models::{VideoMode, Watcher};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CASES_DIR: &str = "../fixtures/golden";
+const IMAGES_DIR: &str = "../resources";
+
+/// Rate of the rendered clips, the default rate of the analysis so every frame is analyzed.
+const CLIP_FPS: u32 = 10;
+
+/// How long a clip has to be rendered.
+const RENDER_TIMEOUT_SECONDS: u64 = 60;
+
+/// A clip and the timeline of the transitions expected from it.
+#[derive(Deserialize)]
+struct Case {
+    description: String,
+    /// Images of the clip, one after the other.
+    segments: Vec<Segment>,
+    watcher: Watcher,
+    tolerance: Tolerance,
+    transitions: Vec<ExpectedTransition>,
+}
+
+#[derive(Deserialize)]
+struct Segment {
+    /// Name of the image in `resources`.
+    image: String,
+    seconds: u32,
+}
+
+#[derive(Deserialize)]
+struct Tolerance {
+    /// Distance between the expected position of a transition and the reported one.
+    seconds: f64,
+    /// Distance between the expected similarity of a transition and the reported one.
+    similarity: f64,
+}
+
+#[derive(Deserialize)]
+struct ExpectedTransition {
+    seconds: f64,
+    id: String,
+    from: VideoMode,
+    to: VideoMode,
+    /// Only checked when set, the triggers not having any.
+    similarity: Option<f64>,
+}
+
+/// Renders the segments of the case into a clip at `path`, the images being encoded like the
+/// sources of the watchers would be.
+fn render(case: &Case, path: &Path) -> Result<()> {
+    let mut description = format!(
+        "concat name=c ! videoconvert ! avenc_mpeg2video bitrate=2000000 ! mpegvideoparse ! mpegtsmux ! filesink location=\"{}\"",
+        path.display()
+    );
+    for segment in &case.segments {
+        let image = fs::canonicalize(Path::new(IMAGES_DIR).join(&segment.image))?;
+        description.push_str(&format!(
+            " filesrc location=\"{}\" ! decodebin ! videoconvert ! videoscale ! video/x-raw,width=320,height=180 ! imagefreeze num-buffers={} ! video/x-raw,framerate={}/1 ! c.",
+            image.display(),
+            segment.seconds * CLIP_FPS,
+            CLIP_FPS
+        ));
+    }
+    let pipeline = gst::parse_launch(&description)?;
+    let bus = pipeline
+        .bus()
+        .ok_or_else(|| eyre!("Pipeline without bus"))?;
+    pipeline.set_state(gst::State::Playing)?;
+    let msg = bus.timed_pop_filtered(
+        gst::ClockTime::from_seconds(RENDER_TIMEOUT_SECONDS),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    );
+    pipeline.set_state(gst::State::Null)?;
+    match msg.as_ref().map(|msg| msg.view()) {
+        Some(gst::MessageView::Eos(_)) => Ok(()),
+        Some(gst::MessageView::Error(err)) => Err(eyre!("Cannot render: {}", err.error())),
+        _ => Err(eyre!("Not rendered within {}s", RENDER_TIMEOUT_SECONDS)),
+    }
+}
+
+fn analyze(case: &Case, clip: &Path) -> Result<Report> {
+    let slates = reload::load_slates(&case.watcher.all_slates(), None)?;
+    let mut analyzer = Analyzer::new(&case.watcher, slates)?;
+    for frame in Capture::open(clip, &case.watcher)? {
+        let (seconds, image) = frame?;
+        analyzer.analyze(seconds, &image)?;
+    }
+    Ok(analyzer.report(clip))
+}
+
+/// The differences between the expected timeline and the reported one.
+fn compare(case: &Case, report: &Report) -> Vec<String> {
+    let tolerance = &case.tolerance;
+    if report.transitions.len() != case.transitions.len() {
+        return vec![format!(
+            "expected {} transitions, got {:?}",
+            case.transitions.len(),
+            report.transitions
+        )];
+    }
+    let mut differences = Vec::new();
+    for (expected, actual) in case.transitions.iter().zip(&report.transitions) {
+        if (&expected.id, expected.from, expected.to) != (&actual.id, actual.from, actual.to) {
+            differences.push(format!(
+                "expected {} from {:?} to {:?}, got {:?}",
+                expected.id, expected.from, expected.to, actual
+            ));
+            continue;
+        }
+        if (expected.seconds - actual.seconds).abs() > tolerance.seconds {
+            differences.push(format!(
+                "expected {} at {}s, got {}s",
+                expected.id, expected.seconds, actual.seconds
+            ));
+        }
+        if let Some(similarity) = expected.similarity {
+            match actual.similarity {
+                Some(reported) if (reported - similarity).abs() <= tolerance.similarity => (),
+                reported => differences.push(format!(
+                    "expected {} at {}s with a similarity of {}, got {:?}",
+                    expected.id, expected.seconds, similarity, reported
+                )),
+            }
+        }
+    }
+    differences
+}
+
+#[test]
+fn matches_the_golden_timelines() {
+    gst::init().unwrap();
+    let mut paths: Vec<PathBuf> = fs::read_dir(CASES_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "No golden case in {}", CASES_DIR);
+
+    let mut failures = Vec::new();
+    for path in paths {
+        let case: Case = serde_json::from_str(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("Invalid golden case {}: {}", path.display(), e));
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let clip = std::env::temp_dir().join(format!("hawkeye-golden-{}.ts", name));
+        let report = render(&case, &clip).and_then(|_| analyze(&case, &clip));
+        let _ = fs::remove_file(&clip);
+        match report {
+            Ok(report) => failures.extend(
+                compare(&case, &report)
+                    .into_iter()
+                    .map(|difference| format!("{} ({}): {}", name, case.description, difference)),
+            ),
+            Err(e) => failures.push(format!("{} ({}): {}", name, case.description, e)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
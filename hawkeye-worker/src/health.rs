@@ -0,0 +1,138 @@
+use crate::metrics::METRICS;
+use crate::state::STATE;
+use gst::prelude::*;
+use gstreamer as gst;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Element of the pipeline after the input, its buffers being counted as the packets received.
+pub const PACKETS_PROBE: &str = "identity name=packets silent=true";
+
+/// Element of the pipeline after the decoder, the PTS of its frames being checked for
+/// discontinuities. Its frames are counted as the packets when the pipeline has no
+/// `PACKETS_PROBE`, like the ones of the HLS and DASH sources.
+pub const FRAMES_PROBE: &str = "identity name=frames silent=true";
+
+/// Largest gap between the PTS of consecutive frames still continuous.
+const MAX_PTS_GAP: Duration = Duration::from_secs(1);
+
+/// PTS of the previous frame, when there is none yet.
+const NO_PTS: u64 = u64::MAX;
+
+lazy_static! {
+    /// When the pipeline produced the last frame.
+    static ref LAST_FRAME: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Counts the packets and checks the PTS of the frames going through the probes of the pipeline.
+pub fn add_probes(pipeline: &gst::Pipeline) {
+    let packets = pipeline
+        .by_name("packets")
+        .or_else(|| pipeline.by_name("frames"))
+        .and_then(|element| element.static_pad("src"));
+    if let Some(pad) = packets {
+        pad.add_probe(gst::PadProbeType::BUFFER, |_, _| {
+            METRICS.input_packets.inc();
+            gst::PadProbeReturn::Ok
+        });
+    }
+    if let Some(pad) = pipeline
+        .by_name("frames")
+        .and_then(|element| element.static_pad("src"))
+    {
+        let previous = AtomicU64::new(NO_PTS);
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(gst::PadProbeData::Buffer(buffer)) = &info.data {
+                if let Some(pts) = buffer.pts() {
+                    let pts = pts.nseconds();
+                    let previous = previous.swap(pts, Ordering::Relaxed);
+                    if previous != NO_PTS && is_discontinuity(previous, pts) {
+                        record_discontinuity();
+                    }
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+}
+
+/// Whether the PTS went back, or jumped ahead, from the one of the previous frame.
+fn is_discontinuity(previous_ns: u64, pts_ns: u64) -> bool {
+    pts_ns < previous_ns || pts_ns - previous_ns > MAX_PTS_GAP.as_nanos() as u64
+}
+
+fn record_discontinuity() {
+    METRICS.input_pts_discontinuities.inc();
+    STATE.lock().unwrap().input.pts_discontinuities += 1;
+}
+
+/// Records a frame produced by the pipeline, for the readiness of the worker.
+pub fn record_frame(now: Instant) {
+    *LAST_FRAME.lock().unwrap() = Some(now);
+}
+
+/// Whether the pipeline produced a frame within `timeout`.
+pub fn is_receiving_frames(now: Instant, timeout: Duration) -> bool {
+    LAST_FRAME
+        .lock()
+        .unwrap()
+        .map_or(false, |last| now.saturating_duration_since(last) < timeout)
+}
+
+/// Records a warning of the decoder, which drops the frames it cannot decode.
+pub fn record_decode_error() {
+    METRICS.input_decode_errors.inc();
+    STATE.lock().unwrap().input.decode_errors += 1;
+}
+
+/// Packets received per second, sampled at most once a second.
+pub struct PacketRate {
+    packets: u64,
+    sampled_at: Instant,
+}
+
+impl PacketRate {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            packets: METRICS.input_packets.get(),
+            sampled_at: now,
+        }
+    }
+
+    pub fn sample(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.sampled_at);
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let packets = METRICS.input_packets.get();
+        let rate = (packets - self.packets) as f64 / elapsed.as_secs_f64();
+        STATE.lock().unwrap().input.packets_per_second = rate;
+        self.packets = packets;
+        self.sampled_at = now;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_pts_discontinuities() {
+        // 10 frames per second
+        assert!(!is_discontinuity(1_000_000_000, 1_100_000_000));
+        assert!(!is_discontinuity(1_000_000_000, 2_000_000_000));
+        assert!(is_discontinuity(1_000_000_000, 2_100_000_000));
+        assert!(is_discontinuity(1_000_000_000, 900_000_000));
+    }
+
+    #[test]
+    fn ready_while_receiving_frames() {
+        let timeout = Duration::from_secs(10);
+        record_frame(Instant::now());
+        let now = Instant::now();
+        assert!(is_receiving_frames(now, timeout));
+        assert!(!is_receiving_frames(now + timeout, timeout));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::state::STATE;
+use chrono::{SecondsFormat, Utc};
+use color_eyre::{eyre::eyre, Result};
+use hawkeye_core::models::{Heartbeat, HEARTBEAT_INTERVAL_SECONDS};
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(HEARTBEAT_INTERVAL_SECONDS);
+
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// When the frame pipeline last went through its loop, with or without a frame.
+    static ref LAST_ITERATION: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Called by the frame pipeline on each iteration, the heartbeats stop when it is stuck.
+pub fn alive() {
+    *LAST_ITERATION.lock().unwrap() = Some(Instant::now());
+}
+
+/// Whether the frame pipeline went through its loop since the last heartbeat was due.
+pub fn is_alive(now: Instant) -> bool {
+    LAST_ITERATION.lock().unwrap().map_or(false, |last| {
+        now.saturating_duration_since(last) < HEARTBEAT_INTERVAL
+    })
+}
+
+/// Sends the heartbeats of the worker to the API at `api_url` in the background, as long as the
+/// frame pipeline is running.
+pub fn spawn(api_url: &str, token: Option<String>, watcher_id: &str) {
+    let url = format!(
+        "{}/v1/watchers/{}/heartbeat",
+        api_url.trim_end_matches('/'),
+        watcher_id
+    );
+    log::info!("Sending the heartbeats to {}", url);
+    thread::spawn(move || loop {
+        thread::sleep(HEARTBEAT_INTERVAL);
+        if !is_alive(Instant::now()) {
+            log::warn!("The frame pipeline is stuck, skipping the heartbeat");
+            continue;
+        }
+        if let Err(err) = send(&url, token.as_deref(), &heartbeat()) {
+            log::warn!("Could not send the heartbeat: {:#}", err);
+        }
+    });
+}
+
+fn heartbeat() -> Heartbeat {
+    let state = STATE.lock().unwrap();
+    Heartbeat {
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        frames_processed: state.frames_processed,
+        mode: state.mode,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+fn send(url: &str, token: Option<&str>, heartbeat: &Heartbeat) -> Result<()> {
+    let mut request = ureq::post(url);
+    request.timeout(HEARTBEAT_TIMEOUT);
+    if let Some(token) = token {
+        request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let response = request
+        .set("Content-Type", "application/json")
+        .send_string(&serde_json::to_string(heartbeat)?);
+    if response.error() {
+        return Err(eyre!("HTTP error ({}) from {}", response.status(), url));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skips_the_heartbeats_of_a_stuck_pipeline() {
+        alive();
+        let now = Instant::now();
+        assert!(is_alive(now));
+        assert!(!is_alive(now + HEARTBEAT_INTERVAL));
+    }
+}
@@ -1,8 +1,119 @@
+use crate::ocr::TextDetector;
 use color_eyre::Result;
 use dssim::{DssimImage, ToRGBAPLU, RGBAPLU};
+use hawkeye_core::models::{Detector, Region, DEFAULT_SLATE_THRESHOLD};
+use image::GrayImage;
 use imgref::{Img, ImgVec};
 use load_image::{Image, ImageData};
+use std::cell::{Cell, RefCell};
 
+/// Size of the luma grid compared by the SSIM and NCC detectors.
+const GRID: (usize, usize) = (128, 72);
+/// Side of the blocks of the grid whose SSIM is averaged.
+const SSIM_BLOCK: usize = 8;
+
+/// A slate of the watcher, compared with every frame.
+pub struct Slate {
+    pub id: String,
+    pub detector: Box<dyn FrameDetector>,
+    /// See `is_similar`.
+    pub threshold: f64,
+    /// Area of the frames compared with the slate, the whole frame when missing.
+    pub region: Option<Region>,
+}
+
+/// Compares the frames with a slate image, each slate picking its implementation with its
+/// `detector` to trade accuracy for CPU.
+pub trait FrameDetector: Send {
+    /// Dissimilarity between the frame and the slate, `0` when they are identical.
+    fn similarity(&self, frame: &DecodedFrame) -> f64;
+}
+
+/// The implementation of the `detector` of a slate, comparing the frames with the `region` of
+/// the slate image.
+pub fn new_detector(
+    detector: Detector,
+    slate: &[u8],
+    region: Option<&Region>,
+) -> Result<Box<dyn FrameDetector>> {
+    let mut bitmap = load_data(slate)?;
+    if let Some(region) = region {
+        bitmap = crop(&bitmap, region);
+    }
+    Ok(match detector {
+        Detector::Dssim => Box::new(SlateDetector::from_bitmap(&bitmap)?),
+        Detector::Phash | Detector::Dhash => Box::new(HashDetector {
+            detector,
+            hash: hash(detector, &Luma::new(&bitmap)),
+        }),
+        Detector::Ssim => Box::new(SsimDetector(Luma::new(&bitmap).grid())),
+        Detector::Ncc => Box::new(NccDetector(Luma::new(&bitmap).grid())),
+        Detector::Ocr => Box::new(TextDetector::from_slate(&Luma::new(&bitmap).grayscale())?),
+    })
+}
+
+/// A frame decoded once, so it can be compared with several images.
+pub struct DecodedFrame {
+    bitmap: ImgVec<RGBAPLU>,
+    // Prepared on the first comparison with a DSSIM slate, for the cropped frames
+    image: RefCell<Option<DssimImage<f32>>>,
+    luma: Luma,
+    // Computed on the first comparison with a slate using them
+    phash: Cell<Option<u64>>,
+    dhash: Cell<Option<u64>>,
+}
+
+impl DecodedFrame {
+    fn new(bitmap: ImgVec<RGBAPLU>, image: Option<DssimImage<f32>>) -> Self {
+        Self {
+            luma: Luma::new(&bitmap),
+            bitmap,
+            image: RefCell::new(image),
+            phash: Cell::new(None),
+            dhash: Cell::new(None),
+        }
+    }
+
+    /// Mean difference of the luma of the frames, between `0` and `1`, `1` when their sizes
+    /// differ.
+    pub fn difference(&self, other: &DecodedFrame) -> f64 {
+        if (self.luma.width, self.luma.height) != (other.luma.width, other.luma.height) {
+            return 1.0;
+        }
+        let total: f64 = self
+            .luma
+            .values
+            .iter()
+            .zip(other.luma.values.iter())
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        total / self.luma.values.len().max(1) as f64
+    }
+
+    /// The frame in shades of gray, to read its text.
+    pub fn grayscale(&self) -> GrayImage {
+        self.luma.grayscale()
+    }
+
+    /// The region of the frame, to compare with a slate cropped the same way.
+    pub fn crop(&self, region: &Region) -> DecodedFrame {
+        DecodedFrame::new(crop(&self.bitmap, region), None)
+    }
+
+    fn hash(&self, detector: Detector) -> u64 {
+        let cache = match detector {
+            Detector::Dhash => &self.dhash,
+            _ => &self.phash,
+        };
+        cache.get().unwrap_or_else(|| {
+            let computed = hash(detector, &self.luma);
+            cache.set(Some(computed));
+            computed
+        })
+    }
+}
+
+/// Structural dissimilarity (DSSIM) of the images, in color and at several scales.
 pub struct SlateDetector {
     slate: DssimImage<f32>,
     similarity_algorithm: dssim::Dssim,
@@ -10,9 +121,14 @@ pub struct SlateDetector {
 
 impl SlateDetector {
     pub fn new(slate: &[u8]) -> Result<Self> {
+        Self::from_bitmap(&load_data(slate)?)
+    }
+
+    fn from_bitmap(bitmap: &ImgVec<RGBAPLU>) -> Result<Self> {
         let similarity_algorithm = dssim::Dssim::new();
-        let slate_img = load_data(slate)?;
-        let slate = similarity_algorithm.create_image(&slate_img).unwrap();
+        let slate = similarity_algorithm
+            .create_image(bitmap)
+            .ok_or_else(|| color_eyre::eyre::eyre!("Could not prepare the slate for comparison"))?;
 
         Ok(Self {
             slate,
@@ -20,18 +136,79 @@ impl SlateDetector {
         })
     }
 
-    pub fn is_match(&self, image_buffer: &[u8]) -> bool {
-        let frame_img = load_data(image_buffer).unwrap();
-        let frame = self.similarity_algorithm.create_image(&frame_img).unwrap();
+    /// Whether the frame matches the image with the default threshold.
+    pub fn is_match(&self, frame: &DecodedFrame) -> bool {
+        is_similar(self.similarity(frame), DEFAULT_SLATE_THRESHOLD)
+    }
 
-        let (res, _) = self.similarity_algorithm.compare(&self.slate, frame);
-        let val: f64 = res.into();
-        let val = (val * 1000f64) as u32;
+    /// Decodes the frame, which can then be compared with any detector.
+    pub fn decode(&self, image_buffer: &[u8]) -> Result<DecodedFrame> {
+        let bitmap = load_data(image_buffer)?;
+        let image = self
+            .similarity_algorithm
+            .create_image(&bitmap)
+            .ok_or_else(|| color_eyre::eyre::eyre!("Could not prepare the frame for comparison"))?;
+        Ok(DecodedFrame::new(bitmap, Some(image)))
+    }
+}
 
-        val <= 900u32
+impl FrameDetector for SlateDetector {
+    fn similarity(&self, frame: &DecodedFrame) -> f64 {
+        let mut image = frame.image.borrow_mut();
+        if image.is_none() {
+            *image = self.similarity_algorithm.create_image(&frame.bitmap);
+        }
+        match image.as_ref() {
+            Some(image) => {
+                let (res, _) = self.similarity_algorithm.compare(&self.slate, image);
+                res.into()
+            }
+            // Regions too small to be compared never match
+            None => f64::INFINITY,
+        }
     }
 }
 
+/// Share of the bits differing between the hashes of the images, the hash of the slate being
+/// computed once when it is loaded.
+struct HashDetector {
+    detector: Detector,
+    hash: u64,
+}
+
+impl FrameDetector for HashDetector {
+    fn similarity(&self, frame: &DecodedFrame) -> f64 {
+        f64::from((frame.hash(self.detector) ^ self.hash).count_ones()) / 64.0
+    }
+}
+
+/// `(1 - SSIM) / 2` of the luma of the images, cheaper than DSSIM as it ignores the colors and
+/// compares a single scale.
+struct SsimDetector(Vec<f64>);
+
+impl FrameDetector for SsimDetector {
+    fn similarity(&self, frame: &DecodedFrame) -> f64 {
+        (1.0 - ssim(&self.0, &frame.luma.grid())) / 2.0
+    }
+}
+
+/// `(1 - NCC) / 2`, from the normalized cross-correlation of the luma of the images, like the
+/// normed correlation coefficient template matching of OpenCV. Insensitive to the brightness and
+/// the contrast of the stream.
+struct NccDetector(Vec<f64>);
+
+impl FrameDetector for NccDetector {
+    fn similarity(&self, frame: &DecodedFrame) -> f64 {
+        (1.0 - ncc(&self.0, &frame.luma.grid())) / 2.0
+    }
+}
+
+/// Whether the frame matches the slate, given the result of `FrameDetector::similarity` and the
+/// highest dissimilarity accepted.
+pub fn is_similar(similarity: f64, threshold: f64) -> bool {
+    similarity <= threshold
+}
+
 fn load_data(data: &[u8]) -> Result<ImgVec<RGBAPLU>> {
     let img = load_image::load_data(data)?;
     Ok(match_img_bitmap(img))
@@ -50,6 +227,181 @@ fn match_img_bitmap(img: Image) -> ImgVec<RGBAPLU> {
     }
 }
 
+fn crop(bitmap: &ImgVec<RGBAPLU>, region: &Region) -> ImgVec<RGBAPLU> {
+    let (x, y, width, height) = region.to_pixels(bitmap.width() as u32, bitmap.height() as u32);
+    let (x, y, width, height) = (x as usize, y as usize, width as usize, height as usize);
+    let pixels = bitmap.sub_image(x, y, width, height).pixels().collect();
+    Img::new(pixels, width, height)
+}
+
+/// Luma of the pixels of an image, row by row.
+struct Luma {
+    width: usize,
+    height: usize,
+    values: Vec<f64>,
+}
+
+impl Luma {
+    fn new(bitmap: &ImgVec<RGBAPLU>) -> Self {
+        let values = bitmap
+            .pixels()
+            .map(|pixel| f64::from(0.299 * pixel.r + 0.587 * pixel.g + 0.114 * pixel.b))
+            .collect();
+        Self {
+            width: bitmap.width(),
+            height: bitmap.height(),
+            values,
+        }
+    }
+
+    /// 8-bit image of the luma, gamma encoded like the decoded images.
+    fn grayscale(&self) -> GrayImage {
+        let pixels = self
+            .values
+            .iter()
+            .map(|value| (value.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0).round() as u8)
+            .collect();
+        GrayImage::from_raw(self.width as u32, self.height as u32, pixels)
+            .expect("One value per pixel")
+    }
+
+    /// Luma averaged over the areas of a `width` x `height` grid.
+    fn shrink(&self, width: usize, height: usize) -> Vec<f64> {
+        let mut sums = vec![0.0; width * height];
+        let mut counts = vec![0usize; width * height];
+        for (i, value) in self.values.iter().enumerate() {
+            let (x, y) = (i % self.width, i / self.width);
+            let cell = (y * height / self.height) * width + x * width / self.width;
+            sums[cell] += value;
+            counts[cell] += 1;
+        }
+        sums.iter()
+            .zip(counts)
+            .map(|(sum, count)| if count > 0 { sum / count as f64 } else { 0.0 })
+            .collect()
+    }
+
+    /// See `GRID`.
+    fn grid(&self) -> Vec<f64> {
+        self.shrink(GRID.0, GRID.1)
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Mean SSIM of the blocks of two grids.
+fn ssim(a: &[f64], b: &[f64]) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    let (width, height) = GRID;
+    let mut total = 0.0;
+    let mut blocks = 0;
+    for block_y in (0..height).step_by(SSIM_BLOCK) {
+        for block_x in (0..width).step_by(SSIM_BLOCK) {
+            let cells: Vec<usize> = (block_y..(block_y + SSIM_BLOCK).min(height))
+                .flat_map(|y| {
+                    (block_x..(block_x + SSIM_BLOCK).min(width)).map(move |x| y * width + x)
+                })
+                .collect();
+            let block_a: Vec<f64> = cells.iter().map(|cell| a[*cell]).collect();
+            let block_b: Vec<f64> = cells.iter().map(|cell| b[*cell]).collect();
+            let (mean_a, mean_b) = (mean(&block_a), mean(&block_b));
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for (value_a, value_b) in block_a.iter().zip(block_b.iter()) {
+                var_a += (value_a - mean_a).powi(2);
+                var_b += (value_b - mean_b).powi(2);
+                covariance += (value_a - mean_a) * (value_b - mean_b);
+            }
+            let n = cells.len() as f64;
+            let (var_a, var_b, covariance) = (var_a / n, var_b / n, covariance / n);
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a.powi(2) + mean_b.powi(2) + C1) * (var_a + var_b + C2));
+            blocks += 1;
+        }
+    }
+    total / f64::from(blocks)
+}
+
+/// Normalized cross-correlation of two grids, between `-1` and `1` when they are identical.
+fn ncc(a: &[f64], b: &[f64]) -> f64 {
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+    for (value_a, value_b) in a.iter().zip(b.iter()) {
+        var_a += (value_a - mean_a).powi(2);
+        var_b += (value_b - mean_b).powi(2);
+        covariance += (value_a - mean_a) * (value_b - mean_b);
+    }
+    let norm = (var_a * var_b).sqrt();
+    if norm > f64::EPSILON {
+        covariance / norm
+    } else if var_a <= f64::EPSILON && var_b <= f64::EPSILON {
+        // Both images are uniform, they only correlate with the same brightness
+        if (mean_a - mean_b).abs() < 0.01 {
+            1.0
+        } else {
+            0.0
+        }
+    } else {
+        0.0
+    }
+}
+
+fn hash(detector: Detector, luma: &Luma) -> u64 {
+    match detector {
+        Detector::Dhash => dhash(luma),
+        _ => phash(luma),
+    }
+}
+
+/// Perceptual hash: the lowest 8x8 frequencies of the DCT of the 32x32 luma, each bit telling
+/// whether a frequency is above their median.
+fn phash(luma: &Luma) -> u64 {
+    const SIZE: usize = 32;
+    const FREQUENCIES: usize = 8;
+    let luma = luma.shrink(SIZE, SIZE);
+    let dct = |u: usize, i: usize| {
+        (std::f64::consts::PI * u as f64 * (2 * i + 1) as f64 / (2 * SIZE) as f64).cos()
+    };
+    // Separable DCT-II, limited to the frequencies kept
+    let mut rows = vec![0.0; SIZE * FREQUENCIES];
+    for y in 0..SIZE {
+        for u in 0..FREQUENCIES {
+            rows[y * FREQUENCIES + u] = (0..SIZE).map(|x| luma[y * SIZE + x] * dct(u, x)).sum();
+        }
+    }
+    let mut coefficients = Vec::with_capacity(FREQUENCIES * FREQUENCIES);
+    for v in 0..FREQUENCIES {
+        for u in 0..FREQUENCIES {
+            coefficients.push(
+                (0..SIZE)
+                    .map(|y| rows[y * FREQUENCIES + u] * dct(v, y))
+                    .sum::<f64>(),
+            );
+        }
+    }
+    // The first coefficient is the average luma, which would skew the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let median = sorted[sorted.len() / 2];
+    to_bits(coefficients.iter().map(|c| *c > median))
+}
+
+/// Difference hash: each bit telling whether the luma decreases between two neighbours of the
+/// 9x8 luma.
+fn dhash(luma: &Luma) -> u64 {
+    let luma = luma.shrink(9, 8);
+    to_bits(
+        luma.chunks(9)
+            .flat_map(|row| row.windows(2).map(|pair| pair[0] > pair[1])),
+    )
+}
+
+fn to_bits(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0, |hash, bit| (hash << 1) | u64::from(bit))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -76,7 +428,9 @@ mod test {
         let detector = SlateDetector::new(buffer.as_slice()).unwrap();
         let slate_img = read_bytes("../resources/slate_120px.jpg");
 
-        assert!(detector.is_match(slate_img.as_slice()));
+        let frame = detector.decode(slate_img.as_slice()).unwrap();
+
+        assert!(detector.is_match(&frame));
     }
 
     #[test]
@@ -90,6 +444,52 @@ mod test {
         let detector = SlateDetector::new(buffer.as_slice()).unwrap();
         let frame_img = read_bytes("../resources/non-slate_120px.jpg");
 
-        assert_eq!(detector.is_match(frame_img.as_slice()), false);
+        let frame = detector.decode(frame_img.as_slice()).unwrap();
+
+        assert_eq!(detector.is_match(&frame), false);
+    }
+
+    #[test]
+    fn compare_with_detectors() {
+        let slate = read_bytes("../resources/slate_120px.jpg");
+        let decoder = SlateDetector::new(&slate).unwrap();
+        let same = decoder.decode(&slate).unwrap();
+        let other = decoder
+            .decode(&read_bytes("../resources/non-slate_120px.jpg"))
+            .unwrap();
+
+        for detector in [
+            Detector::Phash,
+            Detector::Dhash,
+            Detector::Ssim,
+            Detector::Ncc,
+        ] {
+            let comparator = new_detector(detector, &slate, None).unwrap();
+            assert!(comparator.similarity(&same) < 1e-9);
+            assert!(!is_similar(
+                comparator.similarity(&other),
+                detector.default_threshold()
+            ));
+        }
+    }
+
+    #[test]
+    fn compare_regions() {
+        let slate = read_bytes("../resources/slate_120px.jpg");
+        let decoder = SlateDetector::new(&slate).unwrap();
+        let frame = decoder.decode(&slate).unwrap();
+        let region = Region {
+            x: 25.0,
+            y: 25.0,
+            width: 50.0,
+            height: 50.0,
+        };
+
+        let cropped = frame.crop(&region);
+        assert_eq!((cropped.bitmap.width(), cropped.bitmap.height()), (107, 60));
+        for detector in [Detector::Dssim, Detector::Ssim] {
+            let comparator = new_detector(detector, &slate, Some(&region)).unwrap();
+            assert!(comparator.similarity(&cropped) < 1e-9);
+        }
     }
 }
@@ -0,0 +1,49 @@
+use crate::metrics::METRICS;
+use crate::retry;
+use crate::template::{self, Variables};
+use color_eyre::{eyre::eyre, Result};
+use hawkeye_core::models::KafkaMessage;
+use log::info;
+use std::time::Duration;
+
+// The worker module shares the name of the crate
+use ::kafka::producer::{Producer, Record, RequiredAcks};
+
+/// How long the brokers have to acknowledge a record.
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publishes the record once the leader of its partition has written it, following the retry
+/// policy of the action.
+pub fn publish(message: &KafkaMessage, labels: &[&str], variables: &Variables) -> Result<()> {
+    let key = message
+        .key
+        .as_deref()
+        .map(|key| template::render(key, variables));
+    let payload = template::render_message(message.payload.as_deref(), variables);
+    let policy = retry::policy(message.retry.as_ref(), message.retries);
+    retry::run(&policy, &METRICS.kafka_publish_retried, labels, |timeout| {
+        try_publish(message, key.as_deref(), &payload, timeout)
+    })?;
+    info!("Published record to Kafka topic {}", message.topic);
+    Ok(())
+}
+
+/// Publishes the record once, the `timeout` of the attempt replacing the one of the
+/// acknowledgement.
+fn try_publish(
+    message: &KafkaMessage,
+    key: Option<&str>,
+    payload: &str,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let mut producer = Producer::from_hosts(message.brokers.clone())
+        .with_ack_timeout(timeout.unwrap_or(ACK_TIMEOUT))
+        .with_required_acks(RequiredAcks::One)
+        .create()
+        .map_err(|e| eyre!("Kafka brokers {}: {}", message.brokers.join(","), e))?;
+    let result = match key {
+        Some(key) => producer.send(&Record::from_key_value(&message.topic, key, payload)),
+        None => producer.send(&Record::from_value(&message.topic, payload)),
+    };
+    result.map_err(|e| eyre!("Kafka topic {}: {}", message.topic, e))
+}
@@ -0,0 +1,190 @@
+use crate::actions::{action_type, ActionExecution};
+use crate::conditions::{self, Facts};
+use crate::metrics::METRICS;
+use crate::state::STATE;
+use crate::template::Variables;
+use hawkeye_core::models::{Action, StateMachine, DEFAULT_SLATE_ID};
+use log::{error, info};
+use std::time::Duration;
+
+#[cfg(test)]
+use sn_fake_clock::FakeClock as Instant;
+#[cfg(not(test))]
+use std::time::Instant;
+
+/// Runs the `StateMachine` of a watcher on the frames of the stream.
+pub struct Machine {
+    definition: StateMachine,
+    /// Index of the current state in the definition.
+    current: usize,
+    entered_at: Instant,
+    /// Counts the actions without executing them, see `WatcherMode::Monitor`.
+    pub(crate) monitor: bool,
+}
+
+impl Machine {
+    pub fn new(definition: StateMachine) -> Self {
+        let current = definition
+            .states
+            .iter()
+            .position(|state| state.name == definition.initial)
+            .unwrap_or_default();
+        STATE.lock().unwrap().machine_state = Some(definition.states[current].name.clone());
+        Self {
+            definition,
+            current,
+            entered_at: Instant::now(),
+            monitor: false,
+        }
+    }
+
+    pub fn state(&self) -> &str {
+        &self.definition.states[self.current].name
+    }
+
+    /// Takes the first transition of the current state met by the frame, or its timeout once
+    /// the state lasted long enough.
+    pub fn update(&mut self, facts: &Facts) {
+        let state = &self.definition.states[self.current];
+        let transition = state
+            .transitions
+            .iter()
+            .position(|transition| conditions::is_met(&transition.when, DEFAULT_SLATE_ID, facts));
+        let to = match (transition, &state.timeout) {
+            (Some(i), _) => &state.transitions[i].to,
+            (None, Some(timeout))
+                if self.entered_at.elapsed() >= Duration::from_secs_f64(timeout.seconds) =>
+            {
+                &timeout.to
+            }
+            _ => return,
+        };
+        if let Some(next) = self.definition.states.iter().position(|s| s.name == *to) {
+            let slate_id = facts
+                .matched
+                .first()
+                .map(String::as_str)
+                .unwrap_or_default();
+            self.enter(next, transition, &Variables::new(slate_id));
+        }
+    }
+
+    /// Runs the exit actions of the current state, the actions of the transition taken, if any,
+    /// and the entry actions of the next state. The slate of their variables is the first one
+    /// matching the frame.
+    fn enter(&mut self, next: usize, transition: Option<usize>, variables: &Variables) {
+        let from = self.state().to_string();
+        let to = self.definition.states[next].name.clone();
+        info!("Moving from state {} to state {}", from, to);
+        let monitor = self.monitor;
+        let states = &mut self.definition.states;
+        run_actions(
+            &mut states[self.current].on_exit,
+            &format!("exit_{}", from),
+            variables,
+            monitor,
+        );
+        if let Some(i) = transition {
+            run_actions(
+                &mut states[self.current].transitions[i].actions,
+                &format!("{}_to_{}", from, to),
+                variables,
+                monitor,
+            );
+        }
+        run_actions(
+            &mut states[next].on_enter,
+            &format!("enter_{}", to),
+            variables,
+            monitor,
+        );
+        self.current = next;
+        self.entered_at = Instant::now();
+        STATE.lock().unwrap().machine_state = Some(to);
+    }
+}
+
+/// Runs the actions one after the other, the `transition` labeling their metrics. The actions
+/// are only counted when the watcher is monitoring.
+fn run_actions(actions: &mut [Action], transition: &str, variables: &Variables, monitor: bool) {
+    for action in actions.iter_mut() {
+        let labels = [transition, action_type(action)];
+        if monitor {
+            info!(
+                "Monitoring, not executing a {} on {}",
+                labels[1], transition
+            );
+            METRICS
+                .action_executions
+                .with_label_values(&[labels[0], labels[1], "suppressed"])
+                .inc();
+            continue;
+        }
+        let result = action.execute(&labels, variables);
+        let result_label = if result.is_ok() { "success" } else { "error" };
+        METRICS
+            .action_executions
+            .with_label_values(&[labels[0], labels[1], result_label])
+            .inc();
+        if let Err(err) = result {
+            error!("Error while running an action on {}: {:#}", transition, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hawkeye_core::models::FakeAction;
+    use sn_fake_clock::FakeClock;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn moves_through_states_and_runs_their_actions() {
+        let entered = Arc::new(AtomicBool::new(false));
+        let mut definition: StateMachine = serde_json::from_value(serde_json::json!({
+            "initial": "content",
+            "states": [
+                {"name": "content", "transitions": [
+                    {"to": "slate", "when": {"type": "slate"}}
+                ]},
+                {"name": "slate", "transitions": [
+                    {"to": "preroll", "when": {"type": "not", "condition": {"type": "slate"}}}
+                ]},
+                {"name": "preroll", "timeout": {"seconds": 10, "to": "content"}}
+            ]
+        }))
+        .unwrap();
+        definition.states[2]
+            .on_enter
+            .push(Action::FakeAction(FakeAction {
+                called: entered.clone(),
+                execute_returns: Some(Ok(())),
+            }));
+        let mut machine = Machine::new(definition);
+        let slate = Facts {
+            matched: vec![DEFAULT_SLATE_ID.to_string()],
+            ..Facts::default()
+        };
+        let content = Facts::default();
+
+        machine.update(&content);
+        assert_eq!(machine.state(), "content");
+        machine.update(&slate);
+        assert_eq!(machine.state(), "slate");
+        machine.update(&slate);
+        assert_eq!(machine.state(), "slate");
+        assert_eq!(entered.load(Ordering::SeqCst), false);
+
+        machine.update(&content);
+        assert_eq!(machine.state(), "preroll");
+        assert_eq!(entered.load(Ordering::SeqCst), true);
+        FakeClock::advance_time(5_000);
+        machine.update(&content);
+        assert_eq!(machine.state(), "preroll");
+        FakeClock::advance_time(5_000);
+        machine.update(&content);
+        assert_eq!(machine.state(), "content");
+    }
+}
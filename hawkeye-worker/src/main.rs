@@ -1,25 +1,63 @@
 mod actions;
+mod analyze;
+mod annotate;
+mod archive;
+mod audio;
+mod aws;
+mod calibration;
+mod conditions;
 mod config;
+mod decoder;
+mod events;
+mod exec;
+#[cfg(all(test, feature = "video-tests"))]
+mod golden;
+mod health;
+mod heartbeat;
 mod img_detector;
+mod kafka;
+mod machine;
 mod metrics;
+mod ocr;
+mod pack;
+mod preview;
+mod push;
+mod record;
+mod reload;
+mod retry;
+mod scte35;
+mod shutdown;
+mod signing;
 mod slate;
+mod state;
+mod telemetry;
+mod template;
+mod test_source;
+mod triggers;
 mod video_stream;
+mod watchdog;
+mod watcher_file;
 
 use crate::actions::{ActionExecutor, Executors};
-use crate::config::AppConfig;
-use crate::img_detector::SlateDetector;
+use crate::config::{AnalyzeConfig, AppConfig, LogFormat, PackConfig};
+use crate::machine::Machine;
 use crate::metrics::run_metrics_service;
-use crate::video_stream::{process_frames, RtpServer};
+use crate::push::PushTarget;
+use crate::reload::Reloader;
+use crate::shutdown::Task;
+use crate::test_source::{TestSource, TEST_SCHEME};
+use crate::triggers::FrameTriggers;
+use crate::video_stream::{process_frames, FailoverStream, LocalStream, SourceStream};
+use crate::watchdog::Watchdog;
 use color_eyre::Result;
 use crossbeam::channel::unbounded;
 use gstreamer as gst;
-use hawkeye_core::models::Watcher;
+use hawkeye_core::models::{Action, Watcher};
 use hawkeye_core::utils::maybe_bootstrap_sentry;
-use log::info;
-use std::fs::File;
-use std::sync::atomic::{AtomicBool, Ordering};
+use log::{info, warn};
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
-use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 
 fn main() -> Result<()> {
@@ -27,31 +65,97 @@ fn main() -> Result<()> {
 
     // `sentry_client` must be in scope in main() to stay alive and functional.
     let sentry_client = maybe_bootstrap_sentry();
-    if sentry_client.is_none() {
-        pretty_env_logger::init();
-    }
 
+    // `hawkeye-worker analyze`, the watcher path being the first argument otherwise
+    if std::env::args().nth(1).as_deref() == Some("analyze") {
+        if sentry_client.is_none() {
+            pretty_env_logger::init();
+        }
+        return analyze::run(&AnalyzeConfig::from_iter(std::env::args().skip(1)));
+    }
+    // `hawkeye-worker pack`, run by the pods of the packs
+    if std::env::args().nth(1).as_deref() == Some("pack") {
+        if sentry_client.is_none() {
+            pretty_env_logger::init();
+        }
+        return pack::run(&PackConfig::from_iter(std::env::args().skip(1)));
+    }
     let config: AppConfig = AppConfig::from_args();
-    let watcher_config = File::open(config.watcher_path)?;
-    let watcher: Watcher = serde_json::from_reader(watcher_config)?;
+    let watcher = watcher_file::load(&config.watcher_path)?;
     watcher
         .is_valid()
         .expect("Invalid configuration for Watcher");
+    let ingest_port = watcher
+        .source
+        .ingest_port
+        .expect("Validated watchers have an ingest port");
+    let watcher_id = watcher
+        .id
+        .clone()
+        .unwrap_or_else(|| ingest_port.to_string());
+
+    // Sends the pending spans when dropped, at the end of main()
+    let json_logs = config.log_format == LogFormat::Json;
+    let _telemetry = if config.otlp_endpoint.is_some() || json_logs {
+        Some(telemetry::init(
+            config.otlp_endpoint.as_deref(),
+            json_logs,
+            &watcher_id,
+            sentry_client.is_none(),
+        )?)
+    } else {
+        if sentry_client.is_none() {
+            pretty_env_logger::init();
+        }
+        None
+    };
+    template::set_watcher_id(&watcher_id);
+    archive::configure(
+        watcher.archive.as_ref(),
+        watcher.capture.as_ref(),
+        &watcher_id,
+    );
+    exec::set_allowlist(&config.exec_allowlist);
+    for (field, action) in watcher.actions() {
+        if let Action::Exec(command) = action {
+            if !exec::is_allowed(command) {
+                warn!(
+                    "Command {} of {} is not allowed, the action will fail",
+                    command.command, field
+                );
+            }
+        }
+    }
+    let watcher_span = telemetry::watcher_span(&watcher_id);
+    let _enter = watcher_span.enter();
 
     info!("Initializing GStreamer..");
     gst::init().expect("Could not initialize GStreamer!");
 
     let (sender, receiver) = unbounded();
 
+    let slates = watcher.all_slates();
+
     info!("Loading executors..");
     let mut executors: Vec<ActionExecutor> = Vec::new();
-    for transition in watcher.transitions.iter() {
-        let mut execs: Executors = transition.clone().into();
+    for slate in slates.iter() {
+        let mut execs = Executors::for_slate(slate);
+        executors.append(&mut execs.0);
+    }
+    for (id, trigger) in watcher.triggers() {
+        let mut execs = Executors::for_transitions(id, &trigger.transitions);
         executors.append(&mut execs.0);
     }
 
-    thread::spawn(move || {
-        let mut runtime = actions::Runtime::new(receiver, executors);
+    let machine = watcher.state_machine.clone().map(Machine::new);
+    let mode = watcher.mode.unwrap_or_default();
+
+    let actions_span = watcher_span.clone();
+    let actions_runtime = Task::spawn(move || {
+        let _enter = actions_span.enter();
+        let mut runtime = actions::Runtime::new(receiver, executors)
+            .with_machine(machine)
+            .with_mode(mode);
 
         info!("Starting actions runtime..");
         runtime
@@ -60,28 +164,134 @@ fn main() -> Result<()> {
     });
 
     // starts metrics web app
-    let metrics_port = watcher.source.ingest_port as u16;
-    thread::spawn(move || run_metrics_service(metrics_port));
+    let (reload_sender, reload_receiver) = unbounded();
+    let reloader = Reloader::new(
+        config.watcher_path.clone(),
+        config.slate_library_url.clone(),
+        watcher.clone(),
+        reload_sender,
+    )
+    .with_action_secrets(config.action_secrets.clone());
+    let metrics_port = ingest_port as u16;
+    let (stop_metrics, metrics_stopped) = tokio::sync::oneshot::channel();
+    let ready_timeout = Duration::from_secs(config.ready_timeout);
+    let control_token = config.control_token.clone();
+    let metrics_service = Task::spawn(move || {
+        run_metrics_service(
+            metrics_port,
+            reloader,
+            control_token,
+            ready_timeout,
+            metrics_stopped,
+        )
+    });
+    if let Some(target) = PushTarget::from_config(&config, &watcher_id) {
+        push::spawn(target);
+    }
+    if let Some(events_url) = config.events_url.as_ref() {
+        events::spawn(events_url, config.api_token.clone(), &watcher_id);
+    }
+    if let Some(heartbeat_url) = config.heartbeat_url.as_ref() {
+        heartbeat::spawn(heartbeat_url, config.api_token.clone(), &watcher_id);
+    }
 
     let running = Arc::new(AtomicBool::new(true));
+    shutdown::install(running.clone(), Duration::from_secs(config.grace_period));
 
-    let r = running.clone();
-    ctrlc::set_handler(move || {
-        r.store(false, Ordering::SeqCst);
-    })
-    .expect("Error setting termination handler");
+    let detectors = reload::load_slates(&slates, config.slate_library_url.as_deref())?;
+    state::STATE.lock().unwrap().set_slates(&slates);
+    log::info!("Starting pipeline on port {}", ingest_port);
 
-    let detector = SlateDetector::new(&slate::load_img(watcher.slate_url.as_str())?)?;
-    log::info!(
-        "Starting pipeline at rtp://0.0.0.0:{}",
-        watcher.source.ingest_port
-    );
+    let local_frames: Option<Box<dyn Fn() -> Frames>> = match config.source.as_deref() {
+        Some(url) if url.starts_with(TEST_SCHEME) => {
+            let test_source = TestSource::new(
+                &url[TEST_SCHEME.len()..],
+                &watcher,
+                config.slate_library_url.as_deref(),
+            )?
+            .with_analysis(watcher.analysis.clone());
+            Some(Box::new(move || {
+                Box::new(test_source.clone().into_iter()) as Frames
+            }))
+        }
+        Some(url) => {
+            let local_stream =
+                LocalStream::new(url, config.loop_source)?.with_analysis(watcher.analysis.clone());
+            Some(Box::new(move || {
+                Box::new(local_stream.clone().into_iter()) as Frames
+            }))
+        }
+        None => None,
+    };
+    let sources = watcher.clone();
+    let start_frames = move || match local_frames.as_ref() {
+        Some(start) => start(),
+        None => frames(ingest_port, &sources),
+    };
+    let frames: Frames = match config.stall_timeout {
+        Some(timeout) => Box::new(Watchdog::new(
+            start_frames,
+            Duration::from_secs(timeout),
+            config.stall_action,
+        )),
+        None => start_frames(),
+    };
 
-    let server = RtpServer::new(
-        watcher.source.ingest_port,
-        watcher.source.container,
-        watcher.source.codec,
-    );
+    process_frames(
+        frames,
+        detectors,
+        FrameTriggers::new(&watcher),
+        &watcher_id,
+        running,
+        reload_receiver,
+        sender,
+    )?;
+
+    // Let the actions of the last transitions complete, so a stop does not leave the downstream
+    // channel in the middle of a transition
+    info!("Waiting for the actions in progress..");
+    match actions_runtime.wait(shutdown::remaining()) {
+        Some(Ok(())) => (),
+        Some(Err(_)) => {
+            log::error!("Actions runtime panicked while finishing the actions in progress")
+        }
+        None => log::error!("The actions in progress did not complete within the grace period"),
+    }
+    let last_mode = state::STATE.lock().unwrap().mode;
+    events::report(events::shutdown(last_mode));
+    events::flush(shutdown::FINAL_REPORT_TIMEOUT);
 
-    process_frames(server.into_iter(), detector, running, sender)
+    info!("Stopping the metrics server..");
+    let _ = stop_metrics.send(());
+    if metrics_service
+        .wait(Some(shutdown::METRICS_SHUTDOWN_TIMEOUT))
+        .is_none()
+    {
+        warn!("The metrics server did not stop in time");
+    }
+    Ok(())
+}
+
+type Frames = Box<dyn Iterator<Item = Result<Option<Vec<u8>>>>>;
+
+/// Starts the pipelines of the source of the watcher, and of its backup sources.
+fn frames(ingest_port: u32, watcher: &Watcher) -> Frames {
+    let source = SourceStream::new(ingest_port, watcher.source.clone())
+        .with_analysis(watcher.analysis.clone());
+    match watcher.backup_sources.as_ref() {
+        Some(backups) if !backups.is_empty() => {
+            let mut sources = vec![source];
+            for backup in backups {
+                let port = backup
+                    .ingest_port
+                    .expect("Validated backup sources have an ingest port");
+                sources.push(
+                    SourceStream::new(port, backup.clone()).with_analysis(watcher.analysis.clone()),
+                );
+            }
+            let timeout = Duration::from_secs_f64(watcher.failover_seconds());
+            Box::new(FailoverStream::new(sources, timeout).into_iter())
+        }
+        _ => Box::new(source.into_iter()),
+    }
 }
@@ -1,65 +1,300 @@
-use crate::video_stream;
+use crate::img_detector::is_similar;
+use crate::reload::{ReloadError, Reloader};
+use crate::{actions, annotate, archive, calibration, preview, record, state, video_stream};
+use crate::{health, heartbeat};
+use hawkeye_core::models::{
+    ThresholdChange, TransitionTrigger, VideoMode, DEFAULT_CALIBRATION_SECONDS,
+    DEFAULT_RECORDING_SECONDS, MAX_CALIBRATION_SECONDS, MAX_RECORDING_SECONDS,
+};
 use lazy_static::lazy_static;
 use log::debug;
+use prometheus::proto::MetricFamily;
 use prometheus::{self, Encoder, TextEncoder};
-use prometheus::{register_histogram, register_int_counter, Histogram, IntCounter};
+use prometheus::{
+    Gauge, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::runtime::Builder;
+use tokio::sync::oneshot;
+use warp::hyper::body::Bytes;
 use warp::hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
 use warp::hyper::{Body, StatusCode};
 use warp::reply::Response;
-use warp::Filter;
+use warp::{Filter, Reply};
 
 lazy_static! {
-    pub static ref FOUND_SLATE_COUNTER: IntCounter = register_int_counter!(
-        "slate_found_in_stream",
-        "Number of times a slate image was found in the stream"
-    )
-    .unwrap();
-    pub static ref FOUND_CONTENT_COUNTER: IntCounter = register_int_counter!(
-        "content_found_in_stream",
-        "Number of times the content was found in the stream"
-    )
-    .unwrap();
-    pub static ref SIMILARITY_EXECUTION_COUNTER: IntCounter = register_int_counter!(
-        "similarity_execution",
-        "Number of times we searched for slate in the stream"
-    )
-    .unwrap();
-    pub static ref SIMILARITY_EXECUTION_DURATION: Histogram = register_histogram!(
-        "similarity_execution_seconds",
-        "Seconds it took to execute the similarity algorithm"
-    )
-    .unwrap();
-    pub static ref FRAME_PROCESSING_DURATION: Histogram = register_histogram!(
-        "frame_processing_seconds",
-        "Seconds it took to execute the whole frame processing block"
-    )
-    .unwrap();
-    pub static ref HTTP_CALL_DURATION: Histogram = register_histogram!(
-        "http_call_action_execution_seconds",
-        "Seconds it took to execute the HTTP call"
-    )
-    .unwrap();
-    pub static ref HTTP_CALL_SUCCESS_COUNTER: IntCounter = register_int_counter!(
-        "http_call_success",
-        "Number of times the HTTP call executed successfully"
-    )
-    .unwrap();
-    pub static ref HTTP_CALL_ERROR_COUNTER: IntCounter = register_int_counter!(
-        "http_call_error",
-        "Number of times the HTTP call returned an HTTP error status code"
-    )
-    .unwrap();
-    pub static ref HTTP_CALL_RETRIED_COUNT: IntCounter = register_int_counter!(
-        "http_call_retried",
-        "Number of times the HTTP call was retried"
-    )
-    .unwrap();
-    pub static ref HTTP_CALL_RETRIES_EXHAUSTED_COUNT: IntCounter = register_int_counter!(
-        "http_call_retries_exhausted",
-        "Number of times the HTTP action has exhausted all the retries"
-    )
-    .unwrap();
+    pub static ref METRICS: Metrics = Metrics::new().expect("Invalid metric definitions");
+}
+
+/// Metric families of the worker, labeled by `slate_id`, and by `transition` and `action_type`
+/// for the actions, exposed in the `/metrics` endpoint.
+pub struct Metrics {
+    registry: Registry,
+    pub slate_found: IntCounterVec,
+    pub content_found: IntCounter,
+    pub black_detected: IntCounter,
+    pub freeze_detected: IntCounter,
+    pub audio_loudness: Gauge,
+    pub similarity_executions: IntCounterVec,
+    pub similarity_duration: HistogramVec,
+    pub frame_processing_duration: Histogram,
+    /// Also labeled by `result`, `success` or `error`, or `suppressed` when monitoring.
+    pub action_executions: IntCounterVec,
+    pub http_call_duration: HistogramVec,
+    pub http_call_success: IntCounterVec,
+    pub http_call_error: IntCounterVec,
+    pub http_call_retried: IntCounterVec,
+    pub http_call_retries_exhausted: IntCounterVec,
+    pub aws_call_duration: HistogramVec,
+    pub aws_call_error: IntCounterVec,
+    pub aws_call_retried: IntCounterVec,
+    pub kafka_publish_retried: IntCounterVec,
+    pub circuit_breaker_opened: IntCounterVec,
+    /// Labeled by `result`, `success` or `error`.
+    pub archive_uploads: IntCounterVec,
+    /// Labeled by `result`, `success` or `dropped`.
+    pub events_reported: IntCounterVec,
+    /// Labeled by the `from` and `to` indexes of the sources, `0` being the source of the watcher.
+    pub source_failovers: IntCounterVec,
+    pub active_source: IntGauge,
+    pub stream_lost: IntCounter,
+    /// Labeled by the `action` of the watchdog, `restart` or `exit`.
+    pub pipeline_stalls: IntCounterVec,
+    pub input_packets: IntCounter,
+    pub input_decode_errors: IntCounter,
+    pub input_pts_discontinuities: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> prometheus::Result<Self> {
+        let slate = &["slate_id"];
+        let action = &["transition", "action_type"];
+        let metrics = Self {
+            registry: Registry::new(),
+            slate_found: IntCounterVec::new(
+                Opts::new(
+                    "slate_found_in_stream",
+                    "Number of times a slate image was found in the stream",
+                ),
+                slate,
+            )?,
+            content_found: IntCounter::new(
+                "content_found_in_stream",
+                "Number of times the content was found in the stream",
+            )?,
+            black_detected: IntCounter::new(
+                "black_detected_in_stream",
+                "Number of times the stream stayed black long enough to trigger the black transitions",
+            )?,
+            freeze_detected: IntCounter::new(
+                "freeze_detected_in_stream",
+                "Number of times the stream froze long enough to trigger the freeze transitions",
+            )?,
+            audio_loudness: Gauge::new(
+                "audio_loudness_dbfs",
+                "Loudness of the loudest channel of the audio of the stream, in dBFS",
+            )?,
+            similarity_executions: IntCounterVec::new(
+                Opts::new(
+                    "similarity_execution",
+                    "Number of times we searched for slate in the stream",
+                ),
+                slate,
+            )?,
+            similarity_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "similarity_execution_seconds",
+                    "Seconds it took to execute the similarity algorithm",
+                ),
+                slate,
+            )?,
+            frame_processing_duration: Histogram::with_opts(HistogramOpts::new(
+                "frame_processing_seconds",
+                "Seconds it took to execute the whole frame processing block",
+            ))?,
+            action_executions: IntCounterVec::new(
+                Opts::new(
+                    "action_execution",
+                    "Number of times an action was executed, by result",
+                ),
+                &["transition", "action_type", "result"],
+            )?,
+            http_call_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_call_action_execution_seconds",
+                    "Seconds it took to execute the HTTP call",
+                ),
+                action,
+            )?,
+            http_call_success: IntCounterVec::new(
+                Opts::new(
+                    "http_call_success",
+                    "Number of times the HTTP call executed successfully",
+                ),
+                action,
+            )?,
+            http_call_error: IntCounterVec::new(
+                Opts::new(
+                    "http_call_error",
+                    "Number of times the HTTP call returned an HTTP error status code",
+                ),
+                action,
+            )?,
+            http_call_retried: IntCounterVec::new(
+                Opts::new(
+                    "http_call_retried",
+                    "Number of times the HTTP call was retried",
+                ),
+                action,
+            )?,
+            http_call_retries_exhausted: IntCounterVec::new(
+                Opts::new(
+                    "http_call_retries_exhausted",
+                    "Number of times the HTTP action has exhausted all the retries",
+                ),
+                action,
+            )?,
+            aws_call_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "aws_call_action_execution_seconds",
+                    "Seconds it took to execute each attempt of the AWS call",
+                ),
+                action,
+            )?,
+            aws_call_error: IntCounterVec::new(
+                Opts::new(
+                    "aws_call_error",
+                    "Number of times the AWS call failed after all its retries",
+                ),
+                action,
+            )?,
+            aws_call_retried: IntCounterVec::new(
+                Opts::new(
+                    "aws_call_retried",
+                    "Number of times the AWS call was retried",
+                ),
+                action,
+            )?,
+            kafka_publish_retried: IntCounterVec::new(
+                Opts::new(
+                    "kafka_publish_retried",
+                    "Number of times the Kafka publish was retried",
+                ),
+                action,
+            )?,
+            circuit_breaker_opened: IntCounterVec::new(
+                Opts::new(
+                    "action_circuit_breaker_opened",
+                    "Number of times the action was paused after failing repeatedly",
+                ),
+                action,
+            )?,
+            archive_uploads: IntCounterVec::new(
+                Opts::new(
+                    "archive_uploads",
+                    "Number of frames of the transitions uploaded to the archive",
+                ),
+                &["result"],
+            )?,
+            events_reported: IntCounterVec::new(
+                Opts::new(
+                    "events_reported",
+                    "Number of events reported to the event store of the API",
+                ),
+                &["result"],
+            )?,
+            source_failovers: IntCounterVec::new(
+                Opts::new(
+                    "source_failover",
+                    "Number of times the worker switched to the next source after the frames stopped",
+                ),
+                &["from", "to"],
+            )?,
+            active_source: IntGauge::new(
+                "active_source",
+                "Index of the source of the frames, 0 for the source and from 1 for the backup sources",
+            )?,
+            stream_lost: IntCounter::new(
+                "stream_lost",
+                "Number of times no frame was received long enough to trigger the stream loss transitions",
+            )?,
+            pipeline_stalls: IntCounterVec::new(
+                Opts::new(
+                    "pipeline_stalls",
+                    "Number of times the pipeline produced no frame while receiving packets, until the watchdog recovered it",
+                ),
+                &["action"],
+            )?,
+            input_packets: IntCounter::new(
+                "input_packets_received",
+                "Number of packets received from the source, or frames decoded for the HLS and DASH sources",
+            )?,
+            input_decode_errors: IntCounter::new(
+                "input_decode_errors",
+                "Number of warnings of the decoder of the frames",
+            )?,
+            input_pts_discontinuities: IntCounter::new(
+                "input_pts_discontinuities",
+                "Number of times the PTS of the decoded frames went back or jumped ahead",
+            )?,
+        };
+
+        let registry = &metrics.registry;
+        registry.register(Box::new(metrics.slate_found.clone()))?;
+        registry.register(Box::new(metrics.content_found.clone()))?;
+        registry.register(Box::new(metrics.black_detected.clone()))?;
+        registry.register(Box::new(metrics.freeze_detected.clone()))?;
+        registry.register(Box::new(metrics.audio_loudness.clone()))?;
+        registry.register(Box::new(metrics.similarity_executions.clone()))?;
+        registry.register(Box::new(metrics.similarity_duration.clone()))?;
+        registry.register(Box::new(metrics.frame_processing_duration.clone()))?;
+        registry.register(Box::new(metrics.action_executions.clone()))?;
+        registry.register(Box::new(metrics.http_call_duration.clone()))?;
+        registry.register(Box::new(metrics.http_call_success.clone()))?;
+        registry.register(Box::new(metrics.http_call_error.clone()))?;
+        registry.register(Box::new(metrics.http_call_retried.clone()))?;
+        registry.register(Box::new(metrics.http_call_retries_exhausted.clone()))?;
+        registry.register(Box::new(metrics.aws_call_duration.clone()))?;
+        registry.register(Box::new(metrics.aws_call_error.clone()))?;
+        registry.register(Box::new(metrics.aws_call_retried.clone()))?;
+        registry.register(Box::new(metrics.kafka_publish_retried.clone()))?;
+        registry.register(Box::new(metrics.circuit_breaker_opened.clone()))?;
+        registry.register(Box::new(metrics.archive_uploads.clone()))?;
+        registry.register(Box::new(metrics.events_reported.clone()))?;
+        registry.register(Box::new(metrics.source_failovers.clone()))?;
+        registry.register(Box::new(metrics.active_source.clone()))?;
+        registry.register(Box::new(metrics.stream_lost.clone()))?;
+        registry.register(Box::new(metrics.pipeline_stalls.clone()))?;
+        registry.register(Box::new(metrics.input_packets.clone()))?;
+        registry.register(Box::new(metrics.input_decode_errors.clone()))?;
+        registry.register(Box::new(metrics.input_pts_discontinuities.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Current value of all the metrics of the worker.
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+}
+
+/// Value of the `transition` label, like `content_to_slate`.
+pub fn transition_label(from: VideoMode, to: VideoMode) -> String {
+    format!("{}_to_{}", mode_label(from), mode_label(to))
+}
+
+fn mode_label(mode: VideoMode) -> &'static str {
+    match mode {
+        VideoMode::Slate => "slate",
+        VideoMode::Content => "content",
+        VideoMode::Black => "black",
+        VideoMode::Frozen => "frozen",
+        VideoMode::Backup => "backup",
+        VideoMode::Lost => "lost",
+    }
 }
 
 fn get_metric_contents() -> String {
@@ -67,19 +302,30 @@ fn get_metric_contents() -> String {
     let mut buffer = Vec::new();
     let encoder = TextEncoder::new();
 
-    let metric_families = prometheus::gather();
+    let metric_families = METRICS.gather();
     encoder.encode(&metric_families, &mut buffer).unwrap();
 
     String::from_utf8(buffer).unwrap()
 }
 
-fn latest_frame() -> impl warp::Reply {
+/// The latest frame, with the result of its detection drawn over it when `annotate=true`.
+fn latest_frame(query: HashMap<String, String>) -> impl warp::Reply {
     let image = video_stream::LATEST_FRAME.read();
     let image_png = HeaderValue::from_static("image/png");
     let no_store = HeaderValue::from_static("no-store");
+    let annotate = query.get("annotate").map(String::as_str) == Some("true");
+    let detection = (*video_stream::LATEST_DETECTION.read()).clone();
     let response = match &*image {
         Some(image) => {
-            let mut res = Response::new(image.clone().into());
+            let contents = match detection {
+                Some(detection) if annotate => annotate::annotate(image, &detection)
+                    .unwrap_or_else(|err| {
+                        log::error!("Could not annotate the latest frame: {:?}", err);
+                        image.clone()
+                    }),
+                _ => image.clone(),
+            };
+            let mut res = Response::new(contents.into());
             let headers = res.headers_mut();
             headers.insert(CONTENT_TYPE, image_png);
             headers.insert(CACHE_CONTROL, no_store);
@@ -98,17 +344,515 @@ fn latest_frame() -> impl warp::Reply {
     Ok(response)
 }
 
-pub fn run_metrics_service(metrics_port: u16) {
+fn preview() -> impl warp::Reply {
+    let mut res = Response::new(Body::wrap_stream(preview::preview_stream()));
+    let headers = res.headers_mut();
+    if let Ok(content_type) = HeaderValue::from_str(&preview::preview_content_type()) {
+        headers.insert(CONTENT_TYPE, content_type);
+    }
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
+
+/// Frames of the last `last` seconds, 10 by default, as base64 encoded PNG images.
+fn frame_history(query: HashMap<String, String>) -> impl warp::Reply {
+    let seconds = query
+        .get("last")
+        .and_then(|last| last.parse::<u64>().ok())
+        .unwrap_or(10);
+    let history = preview::FRAME_HISTORY.lock().unwrap();
+    let frames: Vec<_> = history
+        .last(Duration::from_secs(seconds), SystemTime::now())
+        .map(|frame| {
+            let timestamp = frame
+                .captured_at
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis())
+                .unwrap_or_default();
+            json!({
+                "timestamp_ms": timestamp as u64,
+                "image": base64::encode(&frame.image),
+            })
+        })
+        .collect();
+    let mut res = warp::reply::json(&frames).into_response();
+    res.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
+
+/// What the worker is detecting, see `DetectionState`.
+fn detection_state() -> impl warp::Reply {
+    let state = state::STATE.lock().unwrap().clone();
+    let mut res = warp::reply::json(&state).into_response();
+    res.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
+
+/// Last actions executed, oldest first, see `ActionRecord`.
+fn action_history() -> impl warp::Reply {
+    let actions = state::STATE.lock().unwrap().actions.clone();
+    let mut res = warp::reply::json(&actions).into_response();
+    res.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
+
+/// Executes again an action of the history, replying with the record of the replay.
+async fn replay_action(index: u64) -> Result<impl warp::Reply, Infallible> {
+    // The actions block until they complete
+    let result = tokio::task::spawn_blocking(move || actions::replay(index)).await;
+    Ok(match result {
+        Ok(Some(record)) => warp::reply::with_status(warp::reply::json(&record), StatusCode::OK),
+        Ok(None) => warp::reply::with_status(
+            warp::reply::json(&json!({
+                "message": format!("No action {} in the history", index)
+            })),
+            StatusCode::NOT_FOUND,
+        ),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&json!({ "message": e.to_string() })),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    })
+}
+
+/// Fires the actions of the transitions of a slate or trigger, replying with their records once
+/// they completed.
+async fn trigger(
+    reloader: Reloader,
+    trigger: TransitionTrigger,
+) -> Result<impl warp::Reply, Infallible> {
+    let transitions = match reloader.transitions(&trigger) {
+        Ok(transitions) => transitions,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&json!({ "message": e.to_string() })),
+                reload_error_status(&e),
+            ))
+        }
+    };
+    let slate_id = trigger.slate_id().to_string();
+    // The actions block until they complete
+    let result = tokio::task::spawn_blocking(move || actions::fire(&slate_id, &transitions)).await;
+    Ok(match result {
+        Ok(records) => warp::reply::with_status(warp::reply::json(&records), StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&json!({ "message": e.to_string() })),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    })
+}
+
+/// Reloads the watcher definition of the body, or the watcher file when the body is empty.
+async fn reload(reloader: Reloader, body: Bytes) -> Result<impl warp::Reply, Infallible> {
+    let watcher = if body.is_empty() {
+        None
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                let message = format!("Invalid watcher definition: {}", e);
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&json!({ "message": message })),
+                    StatusCode::BAD_REQUEST,
+                ));
+            }
+        }
+    };
+    // Downloading the slates blocks
+    let result = tokio::task::spawn_blocking(move || reloader.reload(watcher)).await;
+    let (code, message) = match result {
+        Ok(Ok(())) => (StatusCode::OK, "Watcher reloaded".to_string()),
+        Ok(Err(e)) => (reload_error_status(&e), e.to_string()),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    if code == StatusCode::OK {
+        log::info!("{}", message);
+    } else {
+        log::warn!("Could not reload the watcher: {}", message);
+    }
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "message": message })),
+        code,
+    ))
+}
+
+fn reload_error_status(e: &ReloadError) -> StatusCode {
+    match e {
+        ReloadError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        ReloadError::RequiresRestart => StatusCode::CONFLICT,
+        ReloadError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Changes the threshold of a slate until the next reload, replying with the comparison of the
+/// latest frame with the slate under the new threshold.
+fn set_threshold(reloader: Reloader, change: ThresholdChange) -> impl warp::Reply {
+    if let Err(e) = reloader.set_threshold(&change) {
+        return warp::reply::with_status(
+            warp::reply::json(&json!({ "message": e.to_string() })),
+            reload_error_status(&e),
+        );
+    }
+    let similarity = video_stream::LATEST_DETECTION
+        .read()
+        .as_ref()
+        .and_then(|detection| {
+            detection
+                .slates
+                .iter()
+                .find(|slate| slate.slate_id == change.slate_id())
+                .and_then(|slate| slate.similarity)
+        });
+    warp::reply::with_status(
+        warp::reply::json(&json!({
+            "slate_id": change.slate_id(),
+            "threshold": change.threshold,
+            "similarity": similarity,
+            "is_match": similarity.map(|similarity| is_similar(similarity, change.threshold)),
+        })),
+        StatusCode::OK,
+    )
+}
+
+/// Samples the similarities of the frames with each slate for `duration` seconds, 60 by default,
+/// and replies with their distribution and a suggested threshold for each slate.
+async fn calibrate(query: HashMap<String, String>) -> Result<impl warp::Reply, Infallible> {
+    let seconds = match query
+        .get("duration")
+        .map(|duration| duration.parse::<u64>())
+    {
+        None => DEFAULT_CALIBRATION_SECONDS,
+        Some(Ok(seconds)) if seconds > 0 && seconds <= MAX_CALIBRATION_SECONDS => seconds,
+        Some(_) => {
+            let message = format!(
+                "The duration must be between 1 and {} seconds",
+                MAX_CALIBRATION_SECONDS
+            );
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+    let running = match calibration::start() {
+        Some(running) => running,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&json!({ "message": "A calibration is already running" })),
+                StatusCode::CONFLICT,
+            ))
+        }
+    };
+    log::info!("Calibrating the slates for {} seconds", seconds);
+    tokio::time::sleep(Duration::from_secs(seconds)).await;
+    let calibration = running.finish();
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({
+            "duration_seconds": seconds,
+            "slates": calibration.report(),
+        })),
+        StatusCode::OK,
+    ))
+}
+
+/// Records a clip of `duration` seconds of the incoming video, 30 by default, and replies with the
+/// URL of the clip once it is uploaded to the archive of the watcher.
+async fn record_clip(query: HashMap<String, String>) -> Result<impl warp::Reply, Infallible> {
+    let seconds = match query
+        .get("duration")
+        .map(|duration| duration.parse::<u64>())
+    {
+        None => DEFAULT_RECORDING_SECONDS,
+        Some(Ok(seconds)) if seconds > 0 && seconds <= MAX_RECORDING_SECONDS => seconds,
+        Some(_) => {
+            let message = format!(
+                "The duration must be between 1 and {} seconds",
+                MAX_RECORDING_SECONDS
+            );
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&json!({ "message": message })),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+    if !record::is_recordable() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&json!({
+                "message": "The video of the current source cannot be recorded"
+            })),
+            StatusCode::NOT_IMPLEMENTED,
+        ));
+    }
+    let running = match record::start() {
+        Some(running) => running,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&json!({
+                    "message": "A recording is already running or uploading"
+                })),
+                StatusCode::CONFLICT,
+            ))
+        }
+    };
+    log::info!("Recording the incoming video for {} seconds", seconds);
+    let started_at = chrono::Utc::now();
+    tokio::time::sleep(Duration::from_secs(seconds)).await;
+    let clip = running.finish();
+    if clip.is_empty() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&json!({ "message": "No video was received while recording" })),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    }
+    let size = clip.len();
+    // The next recording waits for the upload, even when the request is abandoned
+    let uploaded = tokio::task::spawn_blocking(move || {
+        let uploaded = archive::upload_clip(clip, started_at);
+        drop(running);
+        uploaded
+    })
+    .await;
+    match uploaded {
+        Ok(Some(Ok(url))) => Ok(warp::reply::with_status(
+            warp::reply::json(&json!({
+                "url": url,
+                "started_at": started_at.to_rfc3339(),
+                "duration_seconds": seconds,
+                "size_bytes": size,
+            })),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(warp::reply::with_status(
+            warp::reply::json(&json!({ "message": "The watcher has no archive for the clip" })),
+            StatusCode::PRECONDITION_FAILED,
+        )),
+        Ok(Some(Err(err))) => {
+            log::error!("Could not upload the clip: {:#}", err);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&json!({ "message": "Could not upload the clip" })),
+                StatusCode::BAD_GATEWAY,
+            ))
+        }
+        Err(err) => {
+            log::error!("Could not upload the clip: {}", err);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&json!({ "message": "Could not upload the clip" })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Liveness probe: the frame pipeline is going through its loop, even without frames.
+fn healthz() -> impl warp::Reply {
+    if heartbeat::is_alive(Instant::now()) {
+        warp::reply::with_status(
+            warp::reply::json(&json!({ "message": "Alive" })),
+            StatusCode::OK,
+        )
+    } else {
+        warp::reply::with_status(
+            warp::reply::json(&json!({ "message": "The frame pipeline is stuck." })),
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+    }
+}
+
+/// Readiness probe: the pipeline is running and produced a frame within `timeout`.
+fn readyz(timeout: Duration) -> impl warp::Reply {
+    let now = Instant::now();
+    let message = if !heartbeat::is_alive(now) {
+        "The frame pipeline is stuck.".to_string()
+    } else if !health::is_receiving_frames(now, timeout) {
+        format!("No frame received for {:?}.", timeout)
+    } else {
+        return warp::reply::with_status(
+            warp::reply::json(&json!({ "message": "Ready" })),
+            StatusCode::OK,
+        );
+    };
+    warp::reply::with_status(
+        warp::reply::json(&json!({ "message": message })),
+        StatusCode::SERVICE_UNAVAILABLE,
+    )
+}
+
+/// Rejection of the requests without the bearer token of the API.
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Lets through the requests with the bearer token of the API, `token`. Every request is
+/// rejected without a token, the endpoints changing the detection being disabled.
+fn authorized(token: Option<String>) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let expected = token.as_ref().map(|token| format!("Bearer {}", token));
+            async move {
+                match (expected, header) {
+                    (Some(expected), Some(header)) if same_token(&expected, &header) => Ok(()),
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Compares the tokens in constant time, not to leak the expected one.
+fn same_token(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Replies 401 to the requests rejected by `authorized`.
+async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, warp::Rejection> {
+    if err.find::<Unauthorized>().is_some() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&json!({ "message": "Missing or invalid token" })),
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+    Err(err)
+}
+
+/// Serves the metrics and the endpoints of the worker, until `shutdown` receives a value or its
+/// sender is dropped. The endpoints changing or calibrating the detection, and the one of the
+/// definition, need the bearer `control_token` of the API. The worker is ready while it received
+/// a frame within `ready_timeout`.
+pub fn run_metrics_service(
+    metrics_port: u16,
+    reloader: Reloader,
+    control_token: Option<String>,
+    ready_timeout: Duration,
+    shutdown: oneshot::Receiver<()>,
+) {
     let runtime = Builder::new_multi_thread()
         .thread_name("metrics_app")
         .max_blocking_threads(2)
         .enable_all()
         .build()
         .unwrap();
+    let config_reloader = reloader.clone();
     let routes = warp::get().and(
         warp::path("metrics")
             .map(get_metric_contents)
-            .or(warp::path("latest_frame").map(latest_frame)),
+            .or(warp::path("latest_frame")
+                .and(warp::query::<HashMap<String, String>>())
+                .map(latest_frame))
+            .or(warp::path("preview").map(preview))
+            .or(warp::path("state").map(detection_state))
+            .or(warp::path("healthz").map(healthz))
+            .or(warp::path("readyz").map(move || readyz(ready_timeout)))
+            .or(warp::path!("actions" / "history").map(action_history))
+            .or(warp::path("frames")
+                .and(warp::query::<HashMap<String, String>>())
+                .map(frame_history)),
     );
-    runtime.block_on(warp::serve(routes).run(([0, 0, 0, 0], metrics_port)));
+    // The definition has the credentials of the actions and of the source
+    let config_route = warp::get()
+        .and(warp::path!("config"))
+        .and(authorized(control_token.clone()))
+        .map(move || warp::reply::json(&config_reloader.current()));
+    let calibrate_route = warp::get()
+        .and(warp::path("calibrate"))
+        .and(authorized(control_token.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(calibrate);
+    let threshold_reloader = reloader.clone();
+    let threshold_route = warp::put()
+        .and(warp::path!("config" / "threshold"))
+        .and(authorized(control_token.clone()))
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .map(move |change| set_threshold(threshold_reloader.clone(), change));
+    let trigger_reloader = reloader.clone();
+    let trigger_route = warp::post()
+        .and(warp::path("trigger"))
+        .and(authorized(control_token.clone()))
+        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::json())
+        .and_then(move |body| trigger(trigger_reloader.clone(), body));
+    let reload_route = warp::post()
+        .and(warp::path("reload"))
+        .and(authorized(control_token.clone()))
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::bytes())
+        .and_then(move |body| reload(reloader.clone(), body));
+    let record_route = warp::post()
+        .and(warp::path("record"))
+        .and(authorized(control_token.clone()))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(record_clip);
+    let replay_route = warp::post()
+        .and(warp::path!("actions" / u64 / "replay"))
+        .and(authorized(control_token))
+        .and_then(replay_action);
+    let routes = routes
+        .or(reload_route)
+        .or(threshold_route)
+        .or(replay_route)
+        .or(record_route)
+        .or(trigger_route)
+        .or(calibrate_route)
+        .or(config_route)
+        .recover(handle_rejection);
+    let (_, server) =
+        warp::serve(routes).bind_with_graceful_shutdown(([0, 0, 0, 0], metrics_port), async {
+            shutdown.await.ok();
+        });
+    runtime.block_on(server);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn metrics_are_labeled() {
+        METRICS
+            .http_call_error
+            .with_label_values(&[
+                &transition_label(VideoMode::Content, VideoMode::Slate),
+                "http_call",
+            ])
+            .inc();
+
+        let contents = get_metric_contents();
+        assert!(contents
+            .contains(r#"http_call_error{action_type="http_call",transition="content_to_slate"}"#));
+    }
+
+    #[tokio::test]
+    async fn requires_the_control_token() {
+        let route = |token: Option<&str>| {
+            warp::post()
+                .and(authorized(token.map(str::to_string)))
+                .map(warp::reply)
+                .recover(handle_rejection)
+        };
+        let call = |header: Option<&str>| {
+            let request = warp::test::request().method("POST").path("/reload");
+            match header {
+                Some(header) => request.header("authorization", header),
+                None => request,
+            }
+        };
+
+        let response = call(Some("Bearer abc")).reply(&route(Some("abc"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = call(Some("Bearer abd")).reply(&route(Some("abc"))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = call(None).reply(&route(Some("abc"))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = call(Some("Bearer ")).reply(&route(None)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,196 @@
+use crate::img_detector::{DecodedFrame, FrameDetector};
+use color_eyre::{eyre::eyre, Result};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, ImageOutputFormat};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The frames are read at most once a second, OCR being much slower than comparing images and the
+/// slates staying on screen for a while.
+const READ_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The frames are enlarged before being read, their characters being too small for the OCR at the
+/// size they are compared with the slates.
+const SCALE: u32 = 4;
+
+/// Matches the text read on the frames with the text patterns of a slate.
+pub struct TextDetector {
+    patterns: Vec<String>,
+    /// When the text was last read, with its similarity.
+    last: Mutex<Option<(Instant, f64)>>,
+}
+
+impl TextDetector {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        engine::check()?;
+        Ok(Self {
+            patterns: patterns
+                .iter()
+                .map(|pattern| normalize(pattern))
+                .filter(|pattern| !pattern.is_empty())
+                .collect(),
+            last: Mutex::new(None),
+        })
+    }
+
+    /// Matches the text read on the slate image.
+    pub fn from_slate(slate: &GrayImage) -> Result<Self> {
+        let text = normalize(&read_text(slate)?);
+        if text.is_empty() {
+            return Err(eyre!(
+                "No text could be read on the slate image, the text of the slate must be defined"
+            ));
+        }
+        Self::new(&[text])
+    }
+}
+
+impl FrameDetector for TextDetector {
+    fn similarity(&self, frame: &DecodedFrame) -> f64 {
+        let mut last = self.last.lock().unwrap();
+        if let Some((read_at, similarity)) = *last {
+            if read_at.elapsed() < READ_INTERVAL {
+                return similarity;
+            }
+        }
+        let similarity = match read_text(&frame.grayscale()) {
+            Ok(text) => distance(&self.patterns, &normalize(&text)),
+            Err(err) => {
+                log::warn!("Could not read the text of the frame: {:#}", err);
+                1.0
+            }
+        };
+        *last = Some((Instant::now(), similarity));
+        similarity
+    }
+}
+
+fn read_text(image: &GrayImage) -> Result<String> {
+    let (width, height) = image.dimensions();
+    let enlarged = imageops::resize(image, width * SCALE, height * SCALE, FilterType::CatmullRom);
+    let mut png = Vec::new();
+    DynamicImage::ImageLuma8(enlarged).write_to(&mut png, ImageOutputFormat::Png)?;
+    engine::read(&png)
+}
+
+/// Upper case words separated by single spaces, as the OCR breaks the lines of the text.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| word.to_uppercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Smallest share of the characters of a pattern to edit to find it in the text, `1` when none
+/// of them is found.
+fn distance(patterns: &[String], text: &str) -> f64 {
+    patterns
+        .iter()
+        .map(|pattern| substring_distance(pattern, text))
+        .fold(1.0, f64::min)
+}
+
+/// Edit distance between the pattern and the closest substring of the text, over the length of
+/// the pattern, so the misread characters are tolerated.
+fn substring_distance(pattern: &str, text: &str) -> f64 {
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return 0.0;
+    }
+    // Distances of the prefixes of the pattern to the text read so far, the pattern starting
+    // anywhere in the text
+    let mut column: Vec<usize> = (0..=pattern.len()).collect();
+    let mut best = pattern.len();
+    for character in text.chars() {
+        let mut diagonal = column[0];
+        column[0] = 0;
+        for i in 1..=pattern.len() {
+            let above = column[i];
+            let substitution = diagonal + usize::from(pattern[i - 1] != character);
+            column[i] = (above + 1).min(column[i - 1] + 1).min(substitution);
+            diagonal = above;
+        }
+        best = best.min(column[pattern.len()]);
+    }
+    best as f64 / pattern.len() as f64
+}
+
+#[cfg(feature = "ocr")]
+mod engine {
+    use color_eyre::{eyre::eyre, Result};
+    use leptess::LepTess;
+    use std::cell::RefCell;
+
+    thread_local! {
+        // Loading the language model is slow, each thread reading the frames keeps its engine
+        static ENGINE: RefCell<Option<LepTess>> = RefCell::new(None);
+    }
+
+    fn with_engine<T>(read: impl FnOnce(&mut LepTess) -> Result<T>) -> Result<T> {
+        ENGINE.with(|engine| {
+            let mut engine = engine.borrow_mut();
+            if engine.is_none() {
+                let loaded = LepTess::new(None, "eng")
+                    .map_err(|err| eyre!("Could not load Tesseract: {}", err))?;
+                *engine = Some(loaded);
+            }
+            read(engine.as_mut().expect("Engine loaded above"))
+        })
+    }
+
+    pub fn check() -> Result<()> {
+        with_engine(|_| Ok(()))
+    }
+
+    pub fn read(png: &[u8]) -> Result<String> {
+        with_engine(|tesseract| {
+            tesseract
+                .set_image_from_mem(png)
+                .map_err(|err| eyre!("Could not load the image: {}", err))?;
+            tesseract
+                .get_utf8_text()
+                .map_err(|err| eyre!("Could not read the text: {}", err))
+        })
+    }
+}
+
+#[cfg(not(feature = "ocr"))]
+mod engine {
+    use color_eyre::{eyre::eyre, Result};
+
+    pub fn check() -> Result<()> {
+        Err(eyre!(
+            "The ocr detector is only available in workers built with the ocr feature"
+        ))
+    }
+
+    pub fn read(_png: &[u8]) -> Result<String> {
+        check().map(|_| String::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_patterns_in_text() {
+        let text = normalize("We'll be\nright  back soon");
+        assert_eq!(text, "WE'LL BE RIGHT BACK SOON");
+
+        assert_eq!(substring_distance("BE RIGHT BACK", &text), 0.0);
+        assert_eq!(
+            substring_distance("BE RIGHT BACK", "BE RIGHT 8ACK"),
+            1.0 / 13.0
+        );
+        assert_eq!(substring_distance("BE RIGHT BACK", ""), 1.0);
+
+        let patterns = vec![
+            "TECHNICAL DIFFICULTIES".to_string(),
+            "BE RIGHT BACK".to_string(),
+        ];
+        assert_eq!(distance(&patterns, &text), 0.0);
+        assert!(distance(&patterns, "LIVE") > 0.8);
+        assert_eq!(distance(&[], &text), 1.0);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::config::PackConfig;
+use crate::watcher_file;
+use color_eyre::Result;
+use hawkeye_core::models::PACK_METRICS_PORT;
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::Builder;
+use warp::Filter;
+
+/// Time the worker of a watcher of the pack waits before starting again once it exited.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Timeout of the requests collecting the metrics of the watchers of the pack.
+const METRICS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the configurations of the watchers of the pack are reloaded. The kubelet updates
+/// them within a minute once the API changed the watchers the pack runs.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A watcher of the pack, run by a child process of the worker. The detection state, metrics and
+/// frames of the worker belong to the process, so each watcher has its own.
+struct Member {
+    watcher_id: String,
+    ingest_port: u32,
+    path: PathBuf,
+    /// Configuration the worker was started with, so it is restarted once it changes.
+    contents: String,
+    /// Report token of the watcher, from the `<id>.token` next to its configuration, the worker
+    /// sending its heartbeats and events with it.
+    api_token: Option<String>,
+    child: Option<Child>,
+    exited_at: Option<Instant>,
+}
+
+impl Member {
+    fn load(path: &Path, contents: String) -> Result<Self> {
+        let watcher = watcher_file::load(path)?;
+        watcher.is_valid()?;
+        let ingest_port = watcher
+            .source
+            .ingest_port
+            .expect("Validated watchers have an ingest port");
+        Ok(Self {
+            watcher_id: watcher.id.unwrap_or_else(|| ingest_port.to_string()),
+            ingest_port,
+            path: path.to_path_buf(),
+            contents,
+            api_token: std::fs::read_to_string(path.with_extension("token"))
+                .ok()
+                .map(|token| token.trim().to_string()),
+            child: None,
+            exited_at: None,
+        })
+    }
+
+    /// Runs the worker of the watcher, with the configuration of the pack from the environment.
+    fn start(&mut self) {
+        let spawned = std::env::current_exe().and_then(|exe| {
+            let mut command = Command::new(exe);
+            command.arg(&self.path);
+            if let Some(token) = self.api_token.as_ref() {
+                command.env("HAWKEYE_API_TOKEN", token);
+            }
+            command.spawn()
+        });
+        match spawned {
+            Ok(child) => {
+                info!(
+                    "Started worker {} of watcher {}",
+                    child.id(),
+                    self.watcher_id
+                );
+                self.child = Some(child);
+            }
+            Err(e) => {
+                error!(
+                    "Could not start the worker of watcher {}: {}",
+                    self.watcher_id, e
+                );
+                self.exited_at = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Starts the worker again once it exited for `RESTART_DELAY`.
+    fn supervise(&mut self) {
+        match self.child.as_mut().map(Child::try_wait) {
+            Some(Ok(Some(status))) => {
+                warn!(
+                    "Worker of watcher {} exited with {}, restarting it in {:?}",
+                    self.watcher_id, status, RESTART_DELAY
+                );
+                self.child = None;
+                self.exited_at = Some(Instant::now());
+            }
+            Some(Ok(None)) => (),
+            Some(Err(e)) => error!(
+                "Could not check the worker of watcher {}: {}",
+                self.watcher_id, e
+            ),
+            None if self
+                .exited_at
+                .map_or(true, |exited_at| exited_at.elapsed() >= RESTART_DELAY) =>
+            {
+                self.start()
+            }
+            None => (),
+        }
+    }
+
+    /// Asks the worker to stop, it waits for the actions in progress like a worker running alone.
+    fn terminate(&mut self) {
+        if let Some(child) = self.child.as_ref() {
+            // SAFETY: sends a signal to the child process, which is not reaped before `wait`
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+            }
+        }
+    }
+
+    /// Forgets the worker once it exited, returns whether it did.
+    fn reap(&mut self) -> bool {
+        match self.child.as_mut().map(Child::try_wait) {
+            Some(Ok(None)) => false,
+            Some(Err(e)) => {
+                error!(
+                    "Could not check the worker of watcher {}: {}",
+                    self.watcher_id, e
+                );
+                false
+            }
+            _ => {
+                self.child = None;
+                true
+            }
+        }
+    }
+
+    fn wait(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            if let Err(e) = child.wait() {
+                error!(
+                    "Could not wait for the worker of watcher {}: {}",
+                    self.watcher_id, e
+                );
+            }
+        }
+    }
+}
+
+/// The configurations of the watchers in the directory, sorted.
+fn member_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        // The volumes of a `ConfigMap` also have hidden entries, like `..data`
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(true, |name| name.starts_with('.'));
+        if !hidden && path.extension().map_or(false, |ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Reloads the watchers of the pack from the directory. The workers of the watchers removed or
+/// changed are stopped, the ones of the watchers added or changed are started by `supervise`,
+/// and the others keep running.
+fn reload(dir: &Path, members: &mut Vec<Member>, stopping: &mut Vec<Member>) {
+    let paths = match member_paths(dir) {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("Could not list the watchers of the pack: {}", e);
+            return;
+        }
+    };
+    let mut loaded = Vec::new();
+    for path in paths {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("Could not read {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let unchanged = members
+            .iter()
+            .position(|m| m.path == path && m.contents == contents);
+        if let Some(i) = unchanged {
+            loaded.push(members.remove(i));
+            continue;
+        }
+        match Member::load(&path, contents) {
+            Ok(member) => {
+                info!("Running watcher {} in the pack", member.watcher_id);
+                loaded.push(member);
+            }
+            Err(e) => error!("Invalid configuration {}: {}", path.display(), e),
+        }
+    }
+    for mut member in members.drain(..) {
+        info!("Stopping watcher {} in the pack", member.watcher_id);
+        member.terminate();
+        stopping.push(member);
+    }
+    *members = loaded;
+}
+
+/// Runs the watchers of a pack side by side, each in its own worker process, until the pack is
+/// terminated. The watchers are reloaded from their directory, so starting or stopping one of
+/// them leaves the others running. The metrics of the watchers are served together on
+/// `PACK_METRICS_PORT`.
+pub fn run(config: &PackConfig) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || {
+        r.store(false, Ordering::SeqCst);
+    })
+    .expect("Error setting termination handler");
+
+    let ports = Arc::new(Mutex::new(Vec::new()));
+    let served_ports = ports.clone();
+    thread::spawn(move || run_metrics_service(PACK_METRICS_PORT as u16, served_ports));
+
+    let mut members: Vec<Member> = Vec::new();
+    // Stopped since they left the pack or changed, until their worker exits
+    let mut stopping: Vec<Member> = Vec::new();
+    let mut reloaded_at: Option<Instant> = None;
+    while running.load(Ordering::SeqCst) {
+        if reloaded_at.map_or(true, |at| at.elapsed() >= RELOAD_INTERVAL) {
+            reload(&config.members_dir, &mut members, &mut stopping);
+            *ports.lock().unwrap() = members
+                .iter()
+                .map(|member| (member.watcher_id.clone(), member.ingest_port))
+                .collect();
+            reloaded_at = Some(Instant::now());
+        }
+        for member in stopping.iter_mut() {
+            if member.reap() {
+                info!("Worker of watcher {} stopped", member.watcher_id);
+            }
+        }
+        stopping.retain(|member| member.child.is_some());
+        for member in members.iter_mut() {
+            // A changed watcher starts again once its previous worker released the port
+            if !stopping.iter().any(|s| s.ingest_port == member.ingest_port) {
+                member.supervise();
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    info!("Stopping the watchers of the pack..");
+    for member in members.iter_mut().chain(stopping.iter_mut()) {
+        member.terminate();
+    }
+    for member in members.iter_mut().chain(stopping.iter_mut()) {
+        member.wait();
+    }
+    Ok(())
+}
+
+fn run_metrics_service(metrics_port: u16, members: Arc<Mutex<Vec<(String, u32)>>>) {
+    let runtime = Builder::new_multi_thread()
+        .thread_name("pack_metrics_app")
+        .max_blocking_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+    let routes = warp::get().and(warp::path("metrics")).map(move || {
+        let members = members.lock().unwrap().clone();
+        collect_metrics(&members)
+    });
+    runtime.block_on(warp::serve(routes).run(([0, 0, 0, 0], metrics_port)));
+}
+
+/// The metrics of the watchers whose worker answers.
+fn collect_metrics(members: &[(String, u32)]) -> String {
+    let metrics: Vec<(String, String)> = members
+        .iter()
+        .filter_map(|(watcher_id, port)| {
+            let response = ureq::get(&format!("http://127.0.0.1:{}/metrics", port))
+                .timeout(METRICS_TIMEOUT)
+                .call();
+            if response.error() {
+                debug!(
+                    "No metrics from the worker of watcher {}: {}",
+                    watcher_id,
+                    response.status()
+                );
+                return None;
+            }
+            Some((watcher_id.clone(), response.into_string().ok()?))
+        })
+        .collect();
+    merge_metrics(&metrics)
+}
+
+/// A metric family in the text exposition format.
+#[derive(Default)]
+struct Family {
+    help: Option<String>,
+    kind: Option<String>,
+    samples: Vec<String>,
+}
+
+/// Merges the metrics of the watchers, in the text exposition format, labeling their samples with
+/// the `watcher_id` of their watcher. The families of the watchers are the same, so their help and
+/// type only appear once.
+fn merge_metrics(metrics: &[(String, String)]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    let mut families: HashMap<String, Family> = HashMap::new();
+    for (watcher_id, text) in metrics {
+        let mut current = String::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let mut words = line.splitn(4, ' ');
+            let sample_name = match (words.next(), words.next(), words.next()) {
+                (Some("#"), Some(comment), Some(name))
+                    if comment == "HELP" || comment == "TYPE" =>
+                {
+                    current = name.to_string();
+                    let family = families.entry(current.clone()).or_insert_with(|| {
+                        names.push(name.to_string());
+                        Family::default()
+                    });
+                    let header = if comment == "HELP" {
+                        &mut family.help
+                    } else {
+                        &mut family.kind
+                    };
+                    header.get_or_insert_with(|| line.to_string());
+                    continue;
+                }
+                (Some(word), _, _) if !word.starts_with('#') => word,
+                _ => continue,
+            };
+            // Samples without help nor type belong to a family of their own
+            if current.is_empty() || !sample_name.starts_with(current.as_str()) {
+                current = sample_name
+                    .split('{')
+                    .next()
+                    .unwrap_or(sample_name)
+                    .to_string();
+                if !families.contains_key(&current) {
+                    names.push(current.clone());
+                }
+            }
+            families
+                .entry(current.clone())
+                .or_default()
+                .samples
+                .push(with_watcher_label(line, watcher_id));
+        }
+    }
+
+    let mut merged = String::new();
+    for name in names {
+        let family = &families[&name];
+        for line in family
+            .help
+            .iter()
+            .chain(family.kind.iter())
+            .chain(family.samples.iter())
+        {
+            merged.push_str(line);
+            merged.push('\n');
+        }
+    }
+    merged
+}
+
+/// Adds the `watcher_id` label to the sample, first among its labels.
+fn with_watcher_label(sample: &str, watcher_id: &str) -> String {
+    let label = format!("watcher_id=\"{}\"", watcher_id);
+    match sample.find(|c: char| c == '{' || c == ' ') {
+        Some(i) if sample[i..].starts_with("{}") => {
+            format!("{}{{{}}}{}", &sample[..i], label, &sample[i + 2..])
+        }
+        Some(i) if sample[i..].starts_with('{') => {
+            format!("{}{{{},{}", &sample[..i], label, &sample[i + 1..])
+        }
+        Some(i) => format!("{}{{{}}}{}", &sample[..i], label, &sample[i..]),
+        None => sample.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels_the_metrics_of_each_watcher() {
+        let first = "# HELP content_found_in_stream Number of times the content was found\n\
+                     # TYPE content_found_in_stream counter\n\
+                     content_found_in_stream 3\n\
+                     # HELP slate_found_in_stream Number of times a slate image was found\n\
+                     # TYPE slate_found_in_stream counter\n\
+                     slate_found_in_stream{slate_id=\"default\"} 1\n";
+        let second = "# HELP content_found_in_stream Number of times the content was found\n\
+                      # TYPE content_found_in_stream counter\n\
+                      content_found_in_stream 5\n";
+        let merged = merge_metrics(&[
+            ("channel-1".to_string(), first.to_string()),
+            ("channel-2".to_string(), second.to_string()),
+        ]);
+
+        assert_eq!(
+            merged,
+            "# HELP content_found_in_stream Number of times the content was found\n\
+             # TYPE content_found_in_stream counter\n\
+             content_found_in_stream{watcher_id=\"channel-1\"} 3\n\
+             content_found_in_stream{watcher_id=\"channel-2\"} 5\n\
+             # HELP slate_found_in_stream Number of times a slate image was found\n\
+             # TYPE slate_found_in_stream counter\n\
+             slate_found_in_stream{watcher_id=\"channel-1\",slate_id=\"default\"} 1\n"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use crate::video_stream::LATEST_FRAME;
+use color_eyre::Result;
+use futures::{stream, Stream};
+use image::ImageOutputFormat;
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Number of frames kept in the history, one per `HISTORY_INTERVAL`.
+pub const HISTORY_SIZE: usize = 60;
+const HISTORY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between checks for a new frame while streaming the preview.
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(200);
+const PREVIEW_BOUNDARY: &str = "hawkeye-frame";
+const JPEG_QUALITY: u8 = 70;
+
+lazy_static! {
+    pub(crate) static ref FRAME_HISTORY: Mutex<FrameHistory> =
+        Mutex::new(FrameHistory::new(HISTORY_SIZE, HISTORY_INTERVAL));
+}
+
+/// A frame kept in the history.
+pub struct Thumbnail {
+    pub captured_at: SystemTime,
+    /// The frame as captured by the pipeline, a PNG image.
+    pub image: Vec<u8>,
+}
+
+/// Ring buffer of the last frames captured, giving context around a detection.
+pub struct FrameHistory {
+    capacity: usize,
+    interval: Duration,
+    frames: VecDeque<Thumbnail>,
+}
+
+impl FrameHistory {
+    /// Keeps up to `capacity` frames, at most one per `interval`.
+    pub fn new(capacity: usize, interval: Duration) -> Self {
+        Self {
+            capacity,
+            interval,
+            frames: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Adds the frame, unless the last one was added less than an interval ago.
+    pub fn push(&mut self, captured_at: SystemTime, image: &[u8]) {
+        if let Some(last) = self.frames.back() {
+            match captured_at.duration_since(last.captured_at) {
+                Ok(elapsed) if elapsed >= self.interval => (),
+                _ => return,
+            }
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(Thumbnail {
+            captured_at,
+            image: image.to_vec(),
+        });
+    }
+
+    /// Frames captured in the `period` before `now`, oldest first.
+    pub fn last(&self, period: Duration, now: SystemTime) -> impl Iterator<Item = &Thumbnail> {
+        self.frames.iter().filter(move |frame| {
+            now.duration_since(frame.captured_at)
+                .map_or(true, |age| age <= period)
+        })
+    }
+}
+
+/// Content type of the preview stream.
+pub fn preview_content_type() -> String {
+    format!("multipart/x-mixed-replace; boundary={}", PREVIEW_BOUNDARY)
+}
+
+/// Streams the latest frame as a multipart MJPEG, sending each frame once.
+pub fn preview_stream() -> impl Stream<Item = Result<Vec<u8>, Infallible>> {
+    let ticks = tokio::time::interval(PREVIEW_INTERVAL);
+    stream::unfold(
+        (ticks, None),
+        |(mut ticks, last): (_, Option<Vec<u8>>)| async move {
+            loop {
+                ticks.tick().await;
+                let frame = (*LATEST_FRAME.read()).clone();
+                let frame = match frame {
+                    Some(frame) if Some(&frame) != last.as_ref() => frame,
+                    _ => continue,
+                };
+                match to_jpeg(&frame) {
+                    Ok(jpeg) => return Some((Ok(preview_part(&jpeg)), (ticks, Some(frame)))),
+                    Err(err) => log::error!("Could not encode the preview frame: {:?}", err),
+                }
+            }
+        },
+    )
+}
+
+/// Encodes the frame as one part of the preview stream.
+fn preview_part(jpeg: &[u8]) -> Vec<u8> {
+    let mut part = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        PREVIEW_BOUNDARY,
+        jpeg.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(jpeg);
+    part.extend_from_slice(b"\r\n");
+    part
+}
+
+pub(crate) fn to_jpeg(png: &[u8]) -> Result<Vec<u8>> {
+    let img = image::load_from_memory(png)?;
+    let mut jpeg = Vec::new();
+    img.write_to(&mut jpeg, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+    Ok(jpeg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn history_keeps_one_frame_per_interval() {
+        let start = SystemTime::now();
+        let mut history = FrameHistory::new(3, Duration::from_secs(1));
+        for millis in (0..5000).step_by(500) {
+            history.push(start + Duration::from_millis(millis), &[millis as u8]);
+        }
+
+        let now = start + Duration::from_secs(5);
+        let kept: Vec<_> = history
+            .last(Duration::from_secs(10), now)
+            .map(|frame| frame.captured_at)
+            .collect();
+        assert_eq!(
+            kept,
+            vec![
+                start + Duration::from_secs(2),
+                start + Duration::from_secs(3),
+                start + Duration::from_secs(4),
+            ]
+        );
+        assert_eq!(history.last(Duration::from_secs(2), now).count(), 2);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::config::{AppConfig, PushMode};
+use crate::metrics::METRICS;
+use color_eyre::Result;
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{Encoder, TextEncoder};
+use prost::Message;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Job of the metrics pushed to the Pushgateway.
+const JOB: &str = "hawkeye-worker";
+
+/// Where and how the metrics are pushed.
+pub struct PushTarget {
+    pub url: String,
+    pub mode: PushMode,
+    pub interval: Duration,
+    pub auth: Option<PushAuth>,
+    /// Identifies the worker in the pushed metrics.
+    pub watcher_id: String,
+}
+
+pub enum PushAuth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl PushTarget {
+    /// Reads the push configuration of the worker, `None` when the metrics are only scraped.
+    pub fn from_config(config: &AppConfig, watcher_id: &str) -> Option<Self> {
+        let url = config.metrics_push_url.clone()?;
+        let auth = match (
+            &config.metrics_push_username,
+            &config.metrics_push_password,
+            &config.metrics_push_token,
+        ) {
+            (_, _, Some(token)) => Some(PushAuth::Bearer(token.clone())),
+            (Some(username), Some(password), None) => Some(PushAuth::Basic {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            _ => None,
+        };
+        Some(Self {
+            url,
+            mode: config.metrics_push_mode,
+            interval: Duration::from_secs(config.metrics_push_interval),
+            auth,
+            watcher_id: watcher_id.to_string(),
+        })
+    }
+}
+
+/// Pushes the metrics to the target in the background, for workers that cannot be scraped.
+pub fn spawn(target: PushTarget) {
+    log::info!(
+        "Pushing metrics to {} every {:?}",
+        target.url,
+        target.interval
+    );
+    thread::spawn(move || loop {
+        thread::sleep(target.interval);
+        if let Err(err) = push(&target) {
+            log::warn!("Could not push the metrics: {:#}", err);
+        }
+    });
+}
+
+fn push(target: &PushTarget) -> Result<()> {
+    let families = METRICS.gather();
+    let (url, content_type, body) = match target.mode {
+        PushMode::Pushgateway => {
+            let mut body = Vec::new();
+            let encoder = TextEncoder::new();
+            encoder.encode(&families, &mut body)?;
+            let url = format!(
+                "{}/metrics/job/{}/watcher_id/{}",
+                target.url.trim_end_matches('/'),
+                JOB,
+                target.watcher_id
+            );
+            (url, encoder.format_type().to_string(), body)
+        }
+        PushMode::RemoteWrite => {
+            let request = write_request(&families, &target.watcher_id, now_ms());
+            let body = snap::raw::Encoder::new().compress_vec(&request.encode_to_vec())?;
+            (
+                target.url.clone(),
+                "application/x-protobuf".to_string(),
+                body,
+            )
+        }
+    };
+
+    let mut request = match target.mode {
+        // Replaces the metrics of the previous push
+        PushMode::Pushgateway => ureq::put(&url),
+        PushMode::RemoteWrite => ureq::post(&url),
+    };
+    request
+        .timeout(Duration::from_secs(10))
+        .set("Content-Type", &content_type);
+    if target.mode == PushMode::RemoteWrite {
+        request
+            .set("Content-Encoding", "snappy")
+            .set("X-Prometheus-Remote-Write-Version", "0.1.0");
+    }
+    match &target.auth {
+        Some(PushAuth::Basic { username, password }) => {
+            request.auth(username, password);
+        }
+        Some(PushAuth::Bearer(token)) => {
+            request.set("Authorization", &format!("Bearer {}", token));
+        }
+        None => (),
+    }
+
+    let response = request.send_bytes(&body);
+    if response.error() {
+        return Err(color_eyre::eyre::eyre!(
+            "HTTP error ({}) while pushing metrics to {}",
+            response.status(),
+            url
+        ));
+    }
+    Ok(())
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Messages of the Prometheus remote write protocol.
+mod prompb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub timeseries: Vec<TimeSeries>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TimeSeries {
+        #[prost(message, repeated, tag = "1")]
+        pub labels: Vec<Label>,
+        #[prost(message, repeated, tag = "2")]
+        pub samples: Vec<Sample>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Label {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub value: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Sample {
+        #[prost(double, tag = "1")]
+        pub value: f64,
+        #[prost(int64, tag = "2")]
+        pub timestamp: i64,
+    }
+}
+
+/// Converts the metric families into time series, the histograms and summaries are split in the
+/// same series Prometheus stores when scraping them.
+fn write_request(
+    families: &[MetricFamily],
+    watcher_id: &str,
+    timestamp: i64,
+) -> prompb::WriteRequest {
+    let mut timeseries = Vec::new();
+    for family in families {
+        let name = family.get_name();
+        for metric in family.get_metric() {
+            let mut labels: Vec<(String, String)> = vec![
+                ("job".to_string(), JOB.to_string()),
+                ("watcher_id".to_string(), watcher_id.to_string()),
+            ];
+            labels.extend(
+                metric
+                    .get_label()
+                    .iter()
+                    .map(|pair| (pair.get_name().to_string(), pair.get_value().to_string())),
+            );
+            let mut series = |suffix: &str, extra: Option<(&str, String)>, value: f64| {
+                let mut labels = labels.clone();
+                labels.push(("__name__".to_string(), format!("{}{}", name, suffix)));
+                if let Some((label, label_value)) = extra {
+                    labels.push((label.to_string(), label_value));
+                }
+                labels.sort();
+                timeseries.push(prompb::TimeSeries {
+                    labels: labels
+                        .into_iter()
+                        .map(|(name, value)| prompb::Label { name, value })
+                        .collect(),
+                    samples: vec![prompb::Sample { value, timestamp }],
+                });
+            };
+
+            match family.get_field_type() {
+                MetricType::COUNTER => series("", None, metric.get_counter().get_value()),
+                MetricType::GAUGE => series("", None, metric.get_gauge().get_value()),
+                MetricType::UNTYPED => series("", None, metric.get_untyped().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        series(
+                            "_bucket",
+                            Some(("le", bucket.get_upper_bound().to_string())),
+                            bucket.get_cumulative_count() as f64,
+                        );
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    series("_bucket", Some(("le", "+Inf".to_string())), count);
+                    series("_sum", None, histogram.get_sample_sum());
+                    series("_count", None, count);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        series(
+                            "",
+                            Some(("quantile", quantile.get_quantile().to_string())),
+                            quantile.get_value(),
+                        );
+                    }
+                    series("_sum", None, summary.get_sample_sum());
+                    series("_count", None, summary.get_sample_count() as f64);
+                }
+            }
+        }
+    }
+    prompb::WriteRequest { timeseries }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use prometheus::{Histogram, HistogramOpts, IntCounter, Registry};
+
+    #[test]
+    fn converts_metrics_to_time_series() {
+        let registry = Registry::new();
+        let counter = IntCounter::new("content_found_in_stream", "Content found").unwrap();
+        let histogram = Histogram::with_opts(
+            HistogramOpts::new("frame_processing_seconds", "Processing").buckets(vec![0.1]),
+        )
+        .unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        counter.inc_by(3);
+        histogram.observe(0.05);
+
+        let request = write_request(&registry.gather(), "watcher-1", 1000);
+        let series: Vec<(String, f64)> = request
+            .timeseries
+            .iter()
+            .map(|series| {
+                let labels: Vec<String> = series
+                    .labels
+                    .iter()
+                    .map(|label| format!("{}={}", label.name, label.value))
+                    .collect();
+                (labels.join(","), series.samples[0].value)
+            })
+            .collect();
+
+        assert_eq!(
+            series,
+            vec![
+                (
+                    "__name__=content_found_in_stream,job=hawkeye-worker,watcher_id=watcher-1"
+                        .to_string(),
+                    3.0
+                ),
+                (
+                    "__name__=frame_processing_seconds_bucket,job=hawkeye-worker,le=0.1,watcher_id=watcher-1"
+                        .to_string(),
+                    1.0
+                ),
+                (
+                    "__name__=frame_processing_seconds_bucket,job=hawkeye-worker,le=+Inf,watcher_id=watcher-1"
+                        .to_string(),
+                    1.0
+                ),
+                (
+                    "__name__=frame_processing_seconds_sum,job=hawkeye-worker,watcher_id=watcher-1"
+                        .to_string(),
+                    0.05
+                ),
+                (
+                    "__name__=frame_processing_seconds_count,job=hawkeye-worker,watcher_id=watcher-1"
+                        .to_string(),
+                    1.0
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref RECORDING: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+    static ref RING: Mutex<Option<Ring>> = Mutex::new(None);
+}
+
+/// The packets of the transport stream of the last seconds, with when they were muxed.
+struct Ring {
+    window: Duration,
+    packets: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl Ring {
+    fn push(&mut self, packets: &[u8], now: Instant) {
+        self.packets.push_back((now, packets.to_vec()));
+        while let Some((muxed_at, _)) = self.packets.front() {
+            if now.duration_since(*muxed_at) <= self.window {
+                break;
+            }
+            self.packets.pop_front();
+        }
+    }
+
+    fn last(&self, period: Duration, now: Instant) -> Vec<u8> {
+        self.packets
+            .iter()
+            .filter(|(muxed_at, _)| now.duration_since(*muxed_at) <= period)
+            .flat_map(|(_, packets)| packets.iter().copied())
+            .collect()
+    }
+}
+
+/// Whether the pipeline of the current source muxes its video for the recordings. The sources
+/// pulled over HLS or DASH are decoded right away, so they cannot be recorded.
+static RECORDABLE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_recordable(recordable: bool) {
+    RECORDABLE.store(recordable, Ordering::SeqCst);
+}
+
+pub fn is_recordable() -> bool {
+    RECORDABLE.load(Ordering::SeqCst)
+}
+
+/// Whether a recording runs or uploads its clip. One at a time, so the clips requested do not
+/// pile up in memory and in the archive.
+static BUSY: AtomicBool = AtomicBool::new(false);
+
+/// Records the transport stream of the incoming video until the returned guard is finished or
+/// dropped, returns `None` when a recording is already running.
+pub fn start() -> Option<Running> {
+    if BUSY.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    *RECORDING.lock().unwrap() = Some(Vec::new());
+    Some(Running)
+}
+
+/// Keeps the transport stream of the last `window` in memory, for the clips of the transitions.
+/// Nothing is kept when `None`.
+pub fn keep_last(window: Option<Duration>) {
+    *RING.lock().unwrap() = window.map(|window| Ring {
+        window,
+        packets: VecDeque::new(),
+    });
+}
+
+/// The transport stream of the last `period`, at most the window kept.
+pub fn last(period: Duration) -> Vec<u8> {
+    RING.lock()
+        .unwrap()
+        .as_ref()
+        .map(|ring| ring.last(period, Instant::now()))
+        .unwrap_or_default()
+}
+
+/// Appends the packets of the transport stream muxed by the pipeline when recording, and keeps
+/// them for the clips of the transitions.
+pub fn record(packets: &[u8]) {
+    if let Some(recording) = RECORDING.lock().unwrap().as_mut() {
+        recording.extend_from_slice(packets);
+    }
+    if let Some(ring) = RING.lock().unwrap().as_mut() {
+        ring.push(packets, Instant::now());
+    }
+}
+
+/// A running recording, stopped when dropped so an abandoned request does not block the next
+/// recordings.
+pub struct Running;
+
+impl Running {
+    /// The transport stream recorded since the start. The next recording only starts once the
+    /// guard is dropped, after the clip is uploaded.
+    pub fn finish(&self) -> Vec<u8> {
+        RECORDING.lock().unwrap().take().unwrap_or_default()
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RECORDING.lock().unwrap().take();
+        BUSY.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_the_packets_while_running() {
+        record(b"before");
+        let running = start().unwrap();
+        assert!(start().is_none());
+        record(b"first");
+        record(b"second");
+        assert_eq!(running.finish(), b"firstsecond".to_vec());
+        // Until the clip is uploaded
+        assert!(start().is_none());
+        drop(running);
+
+        record(b"after");
+        let running = start().unwrap();
+        assert!(running.finish().is_empty());
+    }
+
+    #[test]
+    fn keeps_the_packets_of_the_window() {
+        let mut ring = Ring {
+            window: Duration::from_secs(10),
+            packets: VecDeque::new(),
+        };
+        let start = Instant::now();
+        ring.push(b"a", start);
+        ring.push(b"b", start + Duration::from_secs(5));
+        ring.push(b"c", start + Duration::from_secs(12));
+        assert_eq!(ring.packets.len(), 2);
+
+        let now = start + Duration::from_secs(12);
+        assert_eq!(ring.last(Duration::from_secs(60), now), b"bc".to_vec());
+        assert_eq!(ring.last(Duration::from_secs(3), now), b"c".to_vec());
+    }
+}
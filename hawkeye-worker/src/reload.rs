@@ -0,0 +1,309 @@
+use crate::img_detector::{new_detector, FrameDetector, Slate};
+use crate::ocr::TextDetector;
+use crate::slate;
+use crate::watcher_file;
+use color_eyre::Result;
+use crossbeam::channel::Sender;
+use hawkeye_core::models::{self, ThresholdChange, TransitionTrigger, Watcher, WatcherMode};
+use log::info;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Changes of the detection applied by the frame pipeline between two frames.
+pub enum Reload {
+    /// Slates replacing the ones the frames are compared with, with their definitions holding
+    /// the transitions triggering the actions, and the mode of the watcher.
+    Slates {
+        slates: Vec<Slate>,
+        definitions: Vec<models::Slate>,
+        mode: WatcherMode,
+    },
+    /// New threshold of a slate, kept until the next reload or restart of the worker.
+    Threshold { slate_id: String, threshold: f64 },
+}
+
+#[derive(Debug)]
+pub enum ReloadError {
+    /// The new definition of the watcher is not valid.
+    Invalid(String),
+    /// The new definition changes the source of the video, or the pod of the worker.
+    RequiresRestart,
+    Failed(color_eyre::Report),
+}
+
+impl std::fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReloadError::Invalid(message) => write!(f, "Invalid watcher definition: {}", message),
+            ReloadError::RequiresRestart => {
+                write!(
+                    f,
+                    "Only the slates, the transitions and the mode can be reloaded"
+                )
+            }
+            ReloadError::Failed(err) => write!(f, "Could not reload the slates: {:#}", err),
+        }
+    }
+}
+
+/// Loads the images of the slates, resolving the `slate://` URLs with the slate library.
+pub fn load_slates(slates: &[models::Slate], library_url: Option<&str>) -> Result<Vec<Slate>> {
+    let mut detectors = Vec::new();
+    for slate in slates {
+        let url = slate::resolve_url(&slate.url, library_url)?;
+        info!("Loading slate {} from {}", slate.id, url);
+        // The text of the slate is read on the frames, its image is not needed
+        let detector: Box<dyn FrameDetector> = match slate.text.as_deref() {
+            Some(text) => Box::new(TextDetector::new(text)?),
+            None => new_detector(
+                slate.detector(),
+                &slate::load_img(url.as_str())?,
+                slate.region.as_ref(),
+            )?,
+        };
+        detectors.push(Slate {
+            id: slate.id.clone(),
+            detector,
+            threshold: slate.threshold_or_default(),
+            region: slate.region,
+        });
+    }
+    Ok(detectors)
+}
+
+/// Applies new definitions of the watcher to the running worker, without losing the state of
+/// the stream.
+#[derive(Clone)]
+pub struct Reloader {
+    watcher_path: PathBuf,
+    library_url: Option<String>,
+    action_secrets: Vec<String>,
+    current: Arc<Mutex<Watcher>>,
+    sender: Sender<Reload>,
+}
+
+impl Reloader {
+    pub fn new(
+        watcher_path: PathBuf,
+        library_url: Option<String>,
+        current: Watcher,
+        sender: Sender<Reload>,
+    ) -> Self {
+        Self {
+            watcher_path,
+            library_url,
+            action_secrets: Vec::new(),
+            current: Arc::new(Mutex::new(current)),
+            sender,
+        }
+    }
+
+    /// The `Secret`s the HTTP actions of the reloaded definitions can reference, the ones allowed
+    /// by the API.
+    pub fn with_action_secrets(mut self, action_secrets: Vec<String>) -> Self {
+        self.action_secrets = action_secrets;
+        self
+    }
+
+    /// Reloads the slates and the transitions of the given definition, or of the watcher file
+    /// when missing, once Kubernetes updated the mounted `ConfigMap`.
+    pub fn reload(&self, watcher: Option<Watcher>) -> Result<(), ReloadError> {
+        let watcher = match watcher {
+            Some(watcher) => watcher,
+            None => {
+                let text = fs::read_to_string(&self.watcher_path)
+                    .map_err(|e| ReloadError::Failed(e.into()))?;
+                watcher_file::parse(&self.watcher_path, &text).map_err(ReloadError::Invalid)?
+            }
+        };
+        watcher
+            .validate()
+            .map_err(|errors| ReloadError::Invalid(errors.to_string()))?;
+        // The API checks them too, the worker does not trust the definitions it is sent
+        if let Some(name) = watcher
+            .secret_names()
+            .into_iter()
+            .find(|name| !self.action_secrets.iter().any(|allowed| allowed == name))
+        {
+            return Err(ReloadError::Invalid(format!(
+                "Secret {} is not allowed",
+                name
+            )));
+        }
+
+        // Reloads one definition at a time, in the order they were received
+        let mut current = self.current.lock().unwrap();
+        if watcher.requires_restart(&current) {
+            return Err(ReloadError::RequiresRestart);
+        }
+        let definitions = watcher.all_slates();
+        let slates =
+            load_slates(&definitions, self.library_url.as_deref()).map_err(ReloadError::Failed)?;
+        self.send(Reload::Slates {
+            slates,
+            definitions,
+            mode: watcher.mode.unwrap_or_default(),
+        })?;
+        *current = watcher;
+        Ok(())
+    }
+
+    /// The definition the worker runs, loaded at its start or by its last reload.
+    pub fn current(&self) -> Watcher {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Changes the threshold of a slate of the running worker, the watcher definition is not
+    /// changed.
+    pub fn set_threshold(&self, change: &ThresholdChange) -> Result<(), ReloadError> {
+        let current = self.current.lock().unwrap();
+        change
+            .validate(&current)
+            .map_err(|errors| ReloadError::Invalid(errors.to_string()))?;
+        self.send(Reload::Threshold {
+            slate_id: change.slate_id().to_string(),
+            threshold: change.threshold,
+        })
+    }
+
+    /// The transitions of the current definition to fire.
+    pub fn transitions(
+        &self,
+        trigger: &TransitionTrigger,
+    ) -> Result<Vec<models::Transition>, ReloadError> {
+        let current = self.current.lock().unwrap();
+        trigger
+            .validate(&current)
+            .map_err(|errors| ReloadError::Invalid(errors.to_string()))?;
+        Ok(trigger.transitions(&current).unwrap_or_default())
+    }
+
+    fn send(&self, reload: Reload) -> Result<(), ReloadError> {
+        self.sender.send(reload).map_err(|_| {
+            ReloadError::Failed(color_eyre::eyre::eyre!("The frame pipeline has stopped"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossbeam::channel::unbounded;
+
+    fn watcher() -> Watcher {
+        serde_json::from_value(serde_json::json!({
+            "slate_url": "file://../resources/slate_120px.jpg",
+            "source": {
+                "ingest_port": 5000,
+                "container": "mpeg-ts",
+                "codec": "h264",
+                "transport": { "protocol": "rtp" }
+            },
+            "transitions": [
+                { "from": "content", "to": "slate", "actions": [] }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reloads_slates() {
+        let (sender, receiver) = unbounded();
+        let reloader = Reloader::new(PathBuf::from("watcher.json"), None, watcher(), sender);
+
+        let mut reloaded = watcher();
+        reloaded.slates = serde_json::from_value(serde_json::json!([
+            {"id": "network", "url": "file://../resources/slate_120px.jpg", "threshold": 0.5}
+        ]))
+        .unwrap();
+        reloaded.mode = Some(WatcherMode::Monitor);
+        reloader.reload(Some(reloaded)).unwrap();
+
+        match receiver.try_recv().unwrap() {
+            Reload::Slates {
+                slates,
+                definitions,
+                mode,
+            } => {
+                assert_eq!(mode, WatcherMode::Monitor);
+                assert_eq!(definitions.len(), 2);
+                assert_eq!(slates[1].id, "network");
+                assert_eq!(slates[1].threshold, 0.5);
+            }
+            Reload::Threshold { .. } => panic!("Expected the slates to be reloaded"),
+        }
+    }
+
+    #[test]
+    fn changes_thresholds_of_known_slates() {
+        let (sender, receiver) = unbounded();
+        let reloader = Reloader::new(PathBuf::from("watcher.json"), None, watcher(), sender);
+
+        let unknown = ThresholdChange {
+            slate_id: Some("network".to_string()),
+            threshold: 0.5,
+        };
+        assert!(matches!(
+            reloader.set_threshold(&unknown),
+            Err(ReloadError::Invalid(_))
+        ));
+        reloader
+            .set_threshold(&ThresholdChange {
+                slate_id: None,
+                threshold: 0.5,
+            })
+            .unwrap();
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            Reload::Threshold { threshold, .. } if threshold == 0.5
+        ));
+    }
+
+    #[test]
+    fn source_changes_require_restart() {
+        let (sender, receiver) = unbounded();
+        let reloader = Reloader::new(PathBuf::from("watcher.json"), None, watcher(), sender);
+
+        let mut reloaded = watcher();
+        reloaded.source.ingest_port = Some(5001);
+        assert!(matches!(
+            reloader.reload(Some(reloaded)),
+            Err(ReloadError::RequiresRestart)
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn refuses_the_secrets_not_allowed() {
+        let (sender, receiver) = unbounded();
+        let reloader = Reloader::new(PathBuf::from("watcher.json"), None, watcher(), sender)
+            .with_action_secrets(vec!["ad-server".to_string()]);
+
+        let mut reloaded = watcher();
+        reloaded.transitions[0].actions = serde_json::from_value(serde_json::json!([{
+            "type": "http_call",
+            "method": "POST",
+            "url": "https://example.com/collect",
+            "authorization": {"secretRef": "api-credentials/token"}
+        }]))
+        .unwrap();
+        assert!(matches!(
+            reloader.reload(Some(reloaded.clone())),
+            Err(ReloadError::Invalid(_))
+        ));
+        reloaded.transitions[0].actions = serde_json::from_value(serde_json::json!([{
+            "type": "http_call",
+            "method": "POST",
+            "url": "https://example.com/collect",
+            "authorization": {"secretRef": "ad-server/token"}
+        }]))
+        .unwrap();
+        // The `Secret` is not mounted in the pod of the worker
+        assert!(matches!(
+            reloader.reload(Some(reloaded)),
+            Err(ReloadError::RequiresRestart)
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+}
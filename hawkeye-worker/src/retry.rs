@@ -0,0 +1,114 @@
+use crate::shutdown;
+use color_eyre::Result;
+use hawkeye_core::models::RetryPolicy;
+use log::warn;
+use prometheus::IntCounterVec;
+use rand::Rng;
+use std::cell::Cell;
+use std::thread;
+use std::time::Duration;
+
+thread_local! {
+    /// Retries made on the thread since the last `take_retries`.
+    static RETRIES: Cell<u32> = Cell::new(0);
+}
+
+/// Number of retries made by the actions executed on the thread since the last call.
+pub fn take_retries() -> u32 {
+    RETRIES.with(Cell::take)
+}
+
+/// The `retry` of an action, or the policy of its `retries`.
+pub fn policy(retry: Option<&RetryPolicy>, retries: Option<u8>) -> RetryPolicy {
+    retry
+        .cloned()
+        .unwrap_or_else(|| RetryPolicy::from_retries(retries))
+}
+
+/// Makes the attempts of an action until one succeeds, at most `max_attempts`, waiting longer
+/// after every failed one. Each attempt gets the timeout of the policy, if any, and the retries
+/// are counted by the `retried` metric.
+pub fn run<F>(
+    policy: &RetryPolicy,
+    retried: &IntCounterVec,
+    labels: &[&str],
+    mut attempt: F,
+) -> Result<()>
+where
+    F: FnMut(Option<Duration>) -> Result<()>,
+{
+    let timeout = policy.attempt_timeout_ms.map(Duration::from_millis);
+    let mut retry = 0;
+    loop {
+        match attempt(timeout) {
+            Ok(()) => return Ok(()),
+            Err(err) if retry + 1 >= policy.max_attempts() as u32 => return Err(err),
+            Err(err) => {
+                retry += 1;
+                let wait = backoff(policy, retry);
+                if shutdown::remaining().map_or(false, |remaining| wait >= remaining) {
+                    warn!("Not retrying, the worker is shutting down: {:#}", err);
+                    return Err(err);
+                }
+                warn!("Attempt failed, retrying in {:?}: {:#}", wait, err);
+                retried.with_label_values(labels).inc();
+                RETRIES.with(|retries| retries.set(retries.get() + 1));
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+/// Wait before the retry, with a random part of the jitter added or removed.
+fn backoff(policy: &RetryPolicy, retry: u32) -> Duration {
+    let backoff_ms = policy.backoff_ms(retry) as f64;
+    let jitter = match policy.jitter {
+        Some(jitter) if jitter > 0.0 => rand::thread_rng().gen_range(-jitter..=jitter),
+        _ => 0.0,
+    };
+    Duration::from_millis((backoff_ms * (1.0 + jitter)).round() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use color_eyre::eyre::eyre;
+    use prometheus::Opts;
+
+    #[test]
+    fn retries_until_an_attempt_succeeds() {
+        let retried = IntCounterVec::new(Opts::new("retried", "Retries"), &["transition"]).unwrap();
+        let policy = RetryPolicy {
+            max_attempts: Some(3),
+            backoff_ms: Some(10),
+            jitter: Some(0.5),
+            attempt_timeout_ms: Some(100),
+            ..RetryPolicy::default()
+        };
+
+        let mut attempts = 0;
+        let result = run(&policy, &retried, &["content_to_slate"], |timeout| {
+            assert_eq!(timeout, Some(Duration::from_millis(100)));
+            attempts += 1;
+            if attempts < 3 {
+                Err(eyre!("Unavailable"))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(retried.with_label_values(&["content_to_slate"]).get(), 2);
+        assert_eq!(take_retries(), 2);
+        assert_eq!(take_retries(), 0);
+
+        let mut attempts = 0;
+        let result = run(&policy, &retried, &["slate_to_content"], |_| {
+            attempts += 1;
+            Err(eyre!("Unavailable"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+        let wait = backoff(&policy, 2).as_millis();
+        assert!((10..=30).contains(&wait), "{}", wait);
+    }
+}
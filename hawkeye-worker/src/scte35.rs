@@ -0,0 +1,158 @@
+use crate::actions::ActionExecution;
+use crate::template::Variables;
+use color_eyre::Result;
+use hawkeye_core::models::{Scte35Command, Scte35Destination, Scte35Signal};
+use lazy_static::lazy_static;
+use log::info;
+use std::net::UdpSocket;
+use std::sync::Mutex;
+
+/// Ticks per second of the durations of SCTE-35.
+pub const TICKS_PER_SECOND: f64 = 90_000.0;
+
+const SPLICE_INSERT_COMMAND: u8 = 0x05;
+
+lazy_static! {
+    /// Splice event of the last ad break started, ended by the next return to network.
+    pub static ref LAST_SPLICE_EVENT_ID: Mutex<Option<u32>> = Mutex::new(None);
+}
+
+pub fn send(signal: &Scte35Signal, labels: &[&str], variables: &Variables) -> Result<()> {
+    let command = signal.command.unwrap_or(Scte35Command::SpliceInsert);
+    let splice_event_id = splice_event_id(command, signal.splice_event_id, variables);
+    let section = splice_insert(splice_event_id, command, signal.duration);
+    match &signal.destination {
+        Scte35Destination::Http(call) => {
+            let cue = base64::encode(&section);
+            let mut call = call.clone();
+            call.body = Some(match call.body.as_deref() {
+                Some(body) => body.replace("{{scte35}}", &cue),
+                None => cue,
+            });
+            call.execute(labels, variables)?;
+        }
+        Scte35Destination::Udp { address } => {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.send_to(&section, address.as_str())?;
+        }
+    }
+    if command == Scte35Command::SpliceInsert {
+        *LAST_SPLICE_EVENT_ID.lock().unwrap() = Some(splice_event_id);
+    }
+    info!(
+        "Sent SCTE-35 {:?} with splice event {}",
+        command, splice_event_id
+    );
+    Ok(())
+}
+
+/// The configured splice event, or the seconds of the timestamp for a new ad break and the last
+/// ad break started for a return to network.
+pub fn splice_event_id(
+    command: Scte35Command,
+    configured: Option<u32>,
+    variables: &Variables,
+) -> u32 {
+    configured
+        .or_else(|| match command {
+            Scte35Command::SpliceInsert => Some(variables.timestamp.timestamp() as u32),
+            Scte35Command::ReturnToNetwork => *LAST_SPLICE_EVENT_ID.lock().unwrap(),
+        })
+        .unwrap_or_default()
+}
+
+/// `splice_info_section` of a splice insert of the whole program, starting right away.
+fn splice_insert(splice_event_id: u32, command: Scte35Command, duration: Option<f64>) -> Vec<u8> {
+    let out_of_network = command == Scte35Command::SpliceInsert;
+    let duration = duration
+        .filter(|_| out_of_network)
+        .map(|seconds| (seconds * TICKS_PER_SECOND).round() as u64 & 0x1_FFFF_FFFF);
+
+    let mut splice_command = splice_event_id.to_be_bytes().to_vec();
+    // Not cancelled, then the reserved bits
+    splice_command.push(0x7F);
+    // Program splice and splice immediate, then the reserved bits
+    let mut flags = 0b0101_1111;
+    if out_of_network {
+        flags |= 0b1000_0000;
+    }
+    if duration.is_some() {
+        flags |= 0b0010_0000;
+    }
+    splice_command.push(flags);
+    if let Some(ticks) = duration {
+        // Auto return, then the reserved bits and the 33 bits of the duration
+        splice_command.push(0xFE | (ticks >> 32) as u8);
+        splice_command.extend_from_slice(&(ticks as u32).to_be_bytes());
+    }
+    // Unique program ID, avail num and avails expected
+    splice_command.extend_from_slice(&[0x00, 0x01, 0x00, 0x00]);
+
+    let command_length = splice_command.len();
+    // From the protocol version to the CRC
+    let section_length = command_length + 17;
+    let mut section = vec![
+        0xFC,
+        // Section syntax indicator, private indicator and SAP type 3 (not specified)
+        0x30 | (section_length >> 8) as u8,
+        section_length as u8,
+        // Protocol version
+        0x00,
+        // Not encrypted, no PTS adjustment
+        0x00,
+        0x00,
+        0x00,
+        0x00,
+        0x00,
+        // CW index
+        0xFF,
+        // Tier 0xFFF, then the length of the command
+        0xFF,
+        0xF0 | (command_length >> 8) as u8,
+        command_length as u8,
+        SPLICE_INSERT_COMMAND,
+    ];
+    section.extend_from_slice(&splice_command);
+    // No descriptors
+    section.extend_from_slice(&[0x00, 0x00]);
+    let crc = crc32_mpeg2(&section);
+    section.extend_from_slice(&crc.to_be_bytes());
+    section
+}
+
+/// CRC-32 of the MPEG-2 sections.
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in data {
+        crc ^= (*byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encodes_splice_insert_sections() {
+        assert_eq!(crc32_mpeg2(b"123456789"), 0x0376_E6E7);
+
+        let out = splice_insert(42, Scte35Command::SpliceInsert, Some(30.0));
+        assert_eq!(
+            base64::encode(&out),
+            "/DAgAAAAAAAA///wDwUAAAAqf//+ACky4AABAAAAAD7/aqc="
+        );
+        let back = splice_insert(42, Scte35Command::ReturnToNetwork, Some(30.0));
+        assert_eq!(
+            base64::encode(&back),
+            "/DAbAAAAAAAA///wCgUAAAAqf18AAQAAAABPwwQU"
+        );
+    }
+}
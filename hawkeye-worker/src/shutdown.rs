@@ -0,0 +1,96 @@
+use crossbeam::channel::{bounded, Receiver, RecvTimeoutError};
+use lazy_static::lazy_static;
+use log::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long the final event of the worker has to be reported.
+pub const FINAL_REPORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the metrics server has to complete the requests in progress.
+pub const METRICS_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// When the actions in progress must be complete, so the final steps of the shutdown end
+    /// within the grace period. `None` until the worker is asked to stop.
+    static ref DEADLINE: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Stops the frame pipeline on SIGINT and SIGTERM, the pod being killed `grace_period` later.
+pub fn install(running: Arc<AtomicBool>, grace_period: Duration) {
+    ctrlc::set_handler(move || {
+        info!("Shutting down, within {:?}", grace_period);
+        let final_steps = FINAL_REPORT_TIMEOUT + METRICS_SHUTDOWN_TIMEOUT;
+        *DEADLINE.lock().unwrap() =
+            Some(Instant::now() + grace_period.checked_sub(final_steps).unwrap_or_default());
+        running.store(false, Ordering::SeqCst);
+    })
+    .expect("Error setting termination handler");
+}
+
+/// Whether the worker was asked to stop.
+pub fn is_shutting_down() -> bool {
+    DEADLINE.lock().unwrap().is_some()
+}
+
+/// Time left to complete the actions in progress, `None` while the worker is not shutting down.
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .lock()
+        .unwrap()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// A thread whose end can be waited for with a timeout.
+pub struct Task {
+    handle: JoinHandle<()>,
+    /// Disconnected when the thread ends, even by panicking.
+    ended: Receiver<()>,
+}
+
+impl Task {
+    pub fn spawn<F>(f: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let (running, ended) = bounded::<()>(0);
+        let handle = thread::spawn(move || {
+            let _running = running;
+            f()
+        });
+        Self { handle, ended }
+    }
+
+    /// Waits for the thread to end, at most `timeout` when set. `None` when it did not end in
+    /// time.
+    pub fn wait(self, timeout: Option<Duration>) -> Option<thread::Result<()>> {
+        if let Some(timeout) = timeout {
+            if let Err(RecvTimeoutError::Timeout) = self.ended.recv_timeout(timeout) {
+                return None;
+            }
+        }
+        Some(self.handle.join())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn waits_for_the_tasks_until_the_timeout() {
+        let task = Task::spawn(|| ());
+        assert!(matches!(
+            task.wait(Some(Duration::from_secs(5))),
+            Some(Ok(()))
+        ));
+
+        let task = Task::spawn(|| thread::sleep(Duration::from_secs(5)));
+        assert!(task.wait(Some(Duration::from_millis(10))).is_none());
+
+        let task = Task::spawn(|| panic!("Unexpected error"));
+        assert!(matches!(task.wait(None), Some(Err(_))));
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+
+/// Header of the signature when the `signing` of the call sets none, the one of the lifecycle
+/// events of the API.
+pub const SIGNATURE_HEADER: &str = "X-Hawkeye-Signature";
+
+/// Header of the Unix time of the call, in seconds, so the receiver refuses the old calls.
+pub const TIMESTAMP_HEADER: &str = "X-Hawkeye-Timestamp";
+
+/// Header of the random nonce of the call, so the receiver refuses the calls it already got.
+pub const NONCE_HEADER: &str = "X-Hawkeye-Nonce";
+
+/// Signs the call with HMAC-SHA256, returning the headers to send with it: its timestamp, nonce
+/// and signature, in the format `sha256=<hex digest>`.
+pub fn headers(key: &str, header: Option<&str>, body: &str) -> Vec<(String, String)> {
+    let timestamp = Utc::now().timestamp().to_string();
+    let nonce = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+    let signature = sign(key, &timestamp, &nonce, body);
+    vec![
+        (TIMESTAMP_HEADER.to_string(), timestamp),
+        (NONCE_HEADER.to_string(), nonce),
+        (header.unwrap_or(SIGNATURE_HEADER).to_string(), signature),
+    ]
+}
+
+/// Signature of `<timestamp>.<nonce>.<body>`, the body being empty for the calls without one.
+pub fn sign(key: &str, timestamp: &str, nonce: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{}.{}", timestamp, nonce, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_the_timestamp_nonce_and_body() {
+        assert_eq!(
+            sign("secret", "1639000000", "abc", "{\"duration\":20}"),
+            "sha256=f5d808b5144b50e9cfd17cab5a1d333af4ad39dcdca1e2f97a3e35f18634a6fa"
+        );
+        assert_ne!(
+            sign("secret", "1639000000", "abc", "{}"),
+            sign("secret", "1639000000", "abd", "{}")
+        );
+
+        let headers = headers("secret", None, "{}");
+        let value = |name: &str| {
+            headers
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.clone())
+                .unwrap()
+        };
+        assert_eq!(
+            value(SIGNATURE_HEADER),
+            sign(
+                "secret",
+                &value(TIMESTAMP_HEADER),
+                &value(NONCE_HEADER),
+                "{}"
+            )
+        );
+        assert_eq!(value(NONCE_HEADER).len(), 32);
+    }
+}
@@ -15,6 +15,25 @@ use std::time::Duration;
 pub const SLATE_SIZE: (u32, u32) = (213, 120);
 const MEGABYTES: usize = 1024 * 1024;
 const VIDEO_FILE_EXTENSIONS: [&str; 2] = ["mp4", "mkv"];
+const LIBRARY_SCHEME: &str = "slate://";
+
+/// Resolves the `slate://<id>` URLs of the slates of the library to the URL they are downloaded
+/// from, other URLs are returned as they are.
+pub fn resolve_url(url: &str, library_url: Option<&str>) -> Result<String> {
+    match url.strip_prefix(LIBRARY_SCHEME) {
+        Some(id) => {
+            let library_url = library_url.ok_or_else(|| {
+                color_eyre::eyre::eyre!("No slate library configured to load {}", url)
+            })?;
+            Ok(format!(
+                "{}/v1/slates/{}/image",
+                library_url.trim_end_matches('/'),
+                id
+            ))
+        }
+        None => Ok(url.to_string()),
+    }
+}
 
 pub fn load_img(url: &str) -> Result<Vec<u8>> {
     let temp_file: TempFile = Url::new(url).try_into()?;
@@ -153,7 +172,12 @@ impl TryFrom<Url> for TempFile {
                     url.full_path()
                 ));
             }
-            let mut temp_file = TempFile::new("downloaded", url.extension()?)?;
+            // The slates of the library have no extension, only a content type
+            let ext = match url.extension() {
+                Ok(ext) => ext,
+                Err(_) => extension_of(res.content_type())?.to_string(),
+            };
+            let mut temp_file = TempFile::new("downloaded", ext)?;
             temp_file.write_all(res.into_reader())?;
             temp_file
         } else {
@@ -164,6 +188,19 @@ impl TryFrom<Url> for TempFile {
     }
 }
 
+fn extension_of(content_type: &str) -> Result<&'static str> {
+    match content_type {
+        "image/png" => Ok("png"),
+        "image/jpeg" => Ok("jpg"),
+        "video/mp4" => Ok("mp4"),
+        "video/x-matroska" => Ok("mkv"),
+        _ => Err(color_eyre::eyre::eyre!(
+            "Unsupported slate content type: {}",
+            content_type
+        )),
+    }
+}
+
 pub struct FrameCapture {
     source: TempFile,
     frame_size: (u32, u32),
@@ -190,3 +227,21 @@ impl FrameCapture {
         Err(color_eyre::eyre::eyre!("Failed to capture video frame"))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolves_slates_of_the_library() {
+        assert_eq!(
+            resolve_url("slate://abc-123", Some("http://hawkeye-api:8080/")).unwrap(),
+            "http://hawkeye-api:8080/v1/slates/abc-123/image"
+        );
+        assert!(resolve_url("slate://abc-123", None).is_err());
+        assert_eq!(
+            resolve_url("https://example.com/slate.png", None).unwrap(),
+            "https://example.com/slate.png"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::video_stream::Detection;
+use hawkeye_core::models::{Action, Slate, VideoMode, WatcherMode};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of executed actions kept in the history.
+const MAX_ACTIONS: usize = 50;
+
+lazy_static! {
+    pub(crate) static ref STATE: Mutex<DetectionState> = Mutex::new(DetectionState::default());
+}
+
+/// Health of the input of the worker, see `health`.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct InputHealth {
+    /// Packets received during the last second, or frames decoded for the HLS and DASH sources.
+    pub packets_per_second: f64,
+    /// Warnings of the decoder since the worker started.
+    pub decode_errors: u64,
+    /// PTS of the decoded frames going back, or jumping ahead, since the worker started.
+    pub pts_discontinuities: u64,
+    /// Whether no frame was received for the duration of the `stream_loss` trigger.
+    pub stream_lost: bool,
+}
+
+/// What the worker is detecting, served by the `/state` endpoint.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DetectionState {
+    /// Video mode of the last frames, missing until the first frame is analyzed.
+    pub mode: Option<VideoMode>,
+    /// Whether the actions are executed, or only counted.
+    pub watcher_mode: WatcherMode,
+    /// Milliseconds since the Unix epoch of the last change of mode.
+    pub last_transition_ms: Option<u64>,
+    pub slates: Vec<SlateScore>,
+    /// Current state of the state machine of the watcher, if it has one.
+    pub machine_state: Option<String>,
+    /// Loudness of the last audio measured, in dBFS, missing when the stream has no audio.
+    pub loudness: Option<f64>,
+    /// Frames analyzed since the worker started, including the black ones.
+    pub frames_processed: u64,
+    /// Milliseconds since the Unix epoch of the first frame analyzed.
+    pub stream_started_ms: Option<u64>,
+    /// Index of the source of the frames, `0` for the source and from `1` for the backup sources.
+    pub active_source: usize,
+    pub input: InputHealth,
+    /// Last actions executed, oldest first.
+    pub actions: VecDeque<ActionRecord>,
+    /// Index of the next action recorded.
+    #[serde(skip)]
+    next_action_index: u64,
+}
+
+/// Result of the comparison of the last frame with a slate.
+#[derive(Serialize, Clone, Debug)]
+pub struct SlateScore {
+    pub slate_id: String,
+    pub slate_url: String,
+    /// Highest similarity of a matching frame, including the changes made while the worker runs.
+    pub threshold: f64,
+    /// See `FrameDetector::similarity`, missing for black frames.
+    pub similarity: Option<f64>,
+    pub is_match: bool,
+}
+
+/// An action executed by a transition, or replayed.
+#[derive(Serialize, Clone, Debug)]
+pub struct ActionRecord {
+    /// Position of the action among the ones executed since the worker started, set when
+    /// recorded.
+    pub index: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Slate, or `black` or `freeze` trigger, whose transition executed the action.
+    pub slate_id: String,
+    pub from: VideoMode,
+    pub to: VideoMode,
+    /// Description of the action, or its type.
+    pub action: String,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Status of the last response of an HTTP call.
+    pub status_code: Option<u16>,
+    /// Milliseconds the execution took, all its attempts included.
+    pub latency_ms: u64,
+    pub retries: u32,
+    /// Index of the action this one replayed, if any.
+    pub replay_of: Option<u64>,
+    /// Definition of the action, executed again by a replay.
+    #[serde(skip)]
+    pub definition: Option<Action>,
+}
+
+impl DetectionState {
+    /// Slates the frames are compared with.
+    pub fn set_slates(&mut self, slates: &[Slate]) {
+        self.slates = slates
+            .iter()
+            .map(|slate| SlateScore {
+                slate_id: slate.id.clone(),
+                slate_url: slate.url.clone(),
+                threshold: slate.threshold_or_default(),
+                similarity: None,
+                is_match: false,
+            })
+            .collect();
+    }
+
+    pub fn set_threshold(&mut self, slate_id: &str, threshold: f64) {
+        for slate in self
+            .slates
+            .iter_mut()
+            .filter(|slate| slate.slate_id == slate_id)
+        {
+            slate.threshold = threshold;
+        }
+    }
+
+    pub fn record_frame(&mut self, detection: &Detection) {
+        self.frames_processed += 1;
+        self.input.stream_lost = false;
+        self.stream_started_ms.get_or_insert_with(now_ms);
+        for slate in self.slates.iter_mut() {
+            if let Some(result) = detection
+                .slates
+                .iter()
+                .find(|result| result.slate_id == slate.slate_id)
+            {
+                slate.similarity = result.similarity;
+                slate.is_match = result.is_match;
+            }
+        }
+    }
+
+    /// Records the mode of the stream, noting when it changes. Returns the previous mode when it
+    /// changed.
+    pub fn record_mode(&mut self, mode: VideoMode) -> Option<VideoMode> {
+        let changed_from = self.mode.filter(|last| *last != mode);
+        if changed_from.is_some() {
+            self.last_transition_ms = Some(now_ms());
+        }
+        self.mode = Some(mode);
+        changed_from
+    }
+
+    /// Adds the action to the history, dropping the oldest one when full, and returns it with
+    /// its index.
+    pub fn record_action(&mut self, mut record: ActionRecord) -> ActionRecord {
+        record.index = self.next_action_index;
+        self.next_action_index += 1;
+        if self.actions.len() == MAX_ACTIONS {
+            self.actions.pop_front();
+        }
+        self.actions.push_back(record.clone());
+        record
+    }
+
+    /// The action of the history with the index, unless it was dropped.
+    pub fn action(&self, index: u64) -> Option<&ActionRecord> {
+        self.actions.iter().find(|record| record.index == index)
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn state_records_transitions_and_actions() {
+        let mut state = DetectionState::default();
+        assert_eq!(state.record_mode(VideoMode::Content), None);
+        assert_eq!(state.last_transition_ms, None);
+        assert_eq!(
+            state.record_mode(VideoMode::Slate),
+            Some(VideoMode::Content)
+        );
+        assert!(state.last_transition_ms.is_some());
+
+        for i in 0..MAX_ACTIONS + 1 {
+            state.record_action(ActionRecord {
+                index: 0,
+                timestamp_ms: i as u64,
+                slate_id: "default".to_string(),
+                from: VideoMode::Content,
+                to: VideoMode::Slate,
+                action: "http_call".to_string(),
+                success: true,
+                error: None,
+                status_code: Some(200),
+                latency_ms: 12,
+                retries: 0,
+                replay_of: None,
+                definition: None,
+            });
+        }
+        assert_eq!(state.actions.len(), MAX_ACTIONS);
+        assert_eq!(state.actions.front().map(|a| a.timestamp_ms), Some(1));
+        assert!(state.action(0).is_none());
+        assert_eq!(
+            state.action(MAX_ACTIONS as u64).map(|a| a.timestamp_ms),
+            Some(MAX_ACTIONS as u64)
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use color_eyre::Result;
+use opentelemetry::global;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use tokio::runtime::{Builder, Runtime};
+use tracing::{info_span, Span};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::EnvFilter;
+
+/// Keeps the traces exported until dropped, sending the spans not exported yet.
+pub struct Telemetry {
+    _runtime: Runtime,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        global::shutdown_tracer_provider();
+    }
+}
+
+/// Installs the OTLP exporter sending the traces of the frame pipeline to the collector at
+/// `endpoint`, if any, and prints the logs like `pretty_env_logger` does, or as JSON objects when
+/// `json_logs` is set. The log records are captured when `capture_logs` is set.
+pub fn init(
+    endpoint: Option<&str>,
+    json_logs: bool,
+    watcher_id: &str,
+    capture_logs: bool,
+) -> Result<Telemetry> {
+    // The pipeline runs on plain threads, the spans are exported from a runtime of their own
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("telemetry")
+        .enable_all()
+        .build()?;
+    let guard = runtime.enter();
+    let tracer = match endpoint {
+        Some(endpoint) => Some(
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", "hawkeye-worker"),
+                    KeyValue::new("hawkeye.watcher_id", watcher_id.to_string()),
+                ])))
+                .install_batch(opentelemetry::runtime::Tokio)?,
+        ),
+        None => None,
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with((!json_logs).then(tracing_subscriber::fmt::layer))
+        .with(json_logs.then(|| tracing_subscriber::fmt::layer().json().flatten_event(true)))
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)));
+    tracing::subscriber::set_global_default(subscriber)?;
+    if capture_logs {
+        tracing_log::LogTracer::init()?;
+    }
+    drop(guard);
+    Ok(Telemetry { _runtime: runtime })
+}
+
+/// Span of the logs of the worker, so each record carries the `watcher_id`.
+pub fn watcher_span(watcher_id: &str) -> Span {
+    info_span!("watcher", watcher_id)
+}
@@ -0,0 +1,226 @@
+use crate::archive::Capture;
+use crate::state::{self, STATE};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use hawkeye_core::models::HttpCall;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use std::time::Duration;
+
+lazy_static! {
+    static ref WATCHER_ID: Mutex<String> = Mutex::new(String::new());
+}
+
+/// ID of the watcher rendered in the actions, set once when the worker starts.
+pub fn set_watcher_id(watcher_id: &str) {
+    *WATCHER_ID.lock().unwrap() = watcher_id.to_string();
+}
+
+/// Values of the `{{name}}` variables of the HTTP calls, when the action runs.
+#[derive(Clone, Debug)]
+pub struct Variables {
+    pub watcher_id: String,
+    /// Slate, or black or freeze trigger, whose transition runs the action.
+    pub slate_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Time since the first frame analyzed, missing before it.
+    pub stream_time: Option<Duration>,
+    /// Frame archived for the transition, when the watcher has an archive.
+    pub frame_url: Option<String>,
+    pub thumbnail_urls: Vec<String>,
+    /// Clip of the video around the transition, when the watcher captures them.
+    pub clip_url: Option<String>,
+}
+
+impl Variables {
+    pub fn new(slate_id: &str) -> Self {
+        let started_ms = STATE.lock().unwrap().stream_started_ms;
+        let now_ms = state::now_ms();
+        Self {
+            watcher_id: WATCHER_ID.lock().unwrap().clone(),
+            slate_id: slate_id.to_string(),
+            timestamp: Utc.timestamp_millis(now_ms as i64),
+            stream_time: started_ms
+                .map(|started_ms| Duration::from_millis(now_ms.saturating_sub(started_ms))),
+            frame_url: None,
+            thumbnail_urls: Vec::new(),
+            clip_url: None,
+        }
+    }
+
+    /// Also gives the URLs of the images archived for the transition.
+    pub fn with_capture(mut self, capture: Capture) -> Self {
+        self.frame_url = capture.frame_url;
+        self.thumbnail_urls = capture.thumbnail_urls;
+        self.clip_url = capture.clip_url;
+        self
+    }
+
+    fn value(&self, name: &str) -> Option<String> {
+        match name {
+            "watcher_id" => Some(self.watcher_id.clone()),
+            "slate_id" => Some(self.slate_id.clone()),
+            "timestamp" => Some(self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            "stream_time" => Some(self.stream_time.map(timecode).unwrap_or_default()),
+            "frame_url" => Some(self.frame_url.clone().unwrap_or_default()),
+            "thumbnail_urls" => Some(self.thumbnail_urls.join(",")),
+            "clip_url" => Some(self.clip_url.clone().unwrap_or_default()),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces the variables of the text, leaving the unknown ones as they are.
+pub fn render(text: &str, variables: &Variables) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let (before, from_start) = rest.split_at(start);
+        rendered.push_str(before);
+        let end = match from_start.find("}}") {
+            Some(end) => end,
+            None => {
+                rest = from_start;
+                break;
+            }
+        };
+        let placeholder = &from_start[..end + 2];
+        match variables.value(placeholder[2..end].trim()) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(placeholder),
+        }
+        rest = &from_start[end + 2..];
+    }
+    // The text after the last variable, or from an unclosed `{{`
+    rendered.push_str(rest);
+    rendered
+}
+
+/// The call with the variables of its URL, header values and body replaced.
+pub fn render_call(call: &HttpCall, variables: &Variables) -> HttpCall {
+    HttpCall {
+        url: render(&call.url, variables),
+        headers: call.headers.as_ref().map(|headers| {
+            headers
+                .iter()
+                .map(|(name, value)| (name.clone(), render(value, variables)))
+                .collect()
+        }),
+        body: call.body.as_deref().map(|body| render(body, variables)),
+        ..call.clone()
+    }
+}
+
+/// The message of the publishing actions, or a JSON object with the variables when missing. The
+/// object has the URLs of the archived images when there are some.
+pub fn render_message(message: Option<&str>, variables: &Variables) -> String {
+    if let Some(message) = message {
+        return render(message, variables);
+    }
+    let mut object = serde_json::json!({
+        "watcher_id": variables.watcher_id,
+        "slate_id": variables.slate_id,
+        "timestamp": variables.value("timestamp"),
+        "stream_time": variables.stream_time.map(timecode),
+    });
+    if let Some(frame_url) = variables.frame_url.as_ref() {
+        object["frame_url"] = serde_json::json!(frame_url);
+        object["thumbnail_urls"] = serde_json::json!(variables.thumbnail_urls);
+    }
+    if let Some(clip_url) = variables.clip_url.as_ref() {
+        object["clip_url"] = serde_json::json!(clip_url);
+    }
+    object.to_string()
+}
+
+/// `HH:MM:SS.mmm`, the hours going past 24.
+fn timecode(time: Duration) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_known_variables() {
+        let variables = Variables {
+            watcher_id: "channel-1".to_string(),
+            slate_id: "network".to_string(),
+            timestamp: Utc.ymd(2021, 12, 1).and_hms_milli(23, 0, 5, 250),
+            stream_time: Some(Duration::from_millis(3_723_004)),
+            frame_url: None,
+            thumbnail_urls: Vec::new(),
+            clip_url: None,
+        };
+        assert_eq!(
+            render(
+                "{\"watcher\":\"{{watcher_id}}\",\"slate\":\"{{ slate_id }}\",\"at\":\"{{timestamp}}\",\"pts\":\"{{stream_time}}\"}",
+                &variables
+            ),
+            "{\"watcher\":\"channel-1\",\"slate\":\"network\",\"at\":\"2021-12-01T23:00:05.250Z\",\"pts\":\"01:02:03.004\"}"
+        );
+        assert_eq!(
+            render("{{other}} and {{slate_id", &variables),
+            "{{other}} and {{slate_id"
+        );
+    }
+
+    #[test]
+    fn renders_default_message() {
+        let variables = Variables {
+            watcher_id: "channel-1".to_string(),
+            slate_id: "network".to_string(),
+            timestamp: Utc.ymd(2021, 12, 1).and_hms_milli(23, 0, 5, 250),
+            stream_time: None,
+            frame_url: None,
+            thumbnail_urls: Vec::new(),
+            clip_url: None,
+        };
+        let message: serde_json::Value =
+            serde_json::from_str(&render_message(None, &variables)).unwrap();
+        assert_eq!(
+            message,
+            serde_json::json!({
+                "watcher_id": "channel-1",
+                "slate_id": "network",
+                "timestamp": "2021-12-01T23:00:05.250Z",
+                "stream_time": null
+            })
+        );
+        assert_eq!(
+            render_message(Some("{{slate_id}} started"), &variables),
+            "network started"
+        );
+
+        let variables = variables.with_capture(Capture {
+            frame_url: Some("https://storage.googleapis.com/incidents/frame.jpg".to_string()),
+            thumbnail_urls: vec![
+                "https://storage.googleapis.com/incidents/thumbnail-2s.jpg".to_string(),
+                "https://storage.googleapis.com/incidents/thumbnail-1s.jpg".to_string(),
+            ],
+            clip_url: Some("https://storage.googleapis.com/incidents/clip.ts".to_string()),
+        });
+        let message: serde_json::Value =
+            serde_json::from_str(&render_message(None, &variables)).unwrap();
+        assert_eq!(
+            message["frame_url"],
+            "https://storage.googleapis.com/incidents/frame.jpg"
+        );
+        assert_eq!(message["thumbnail_urls"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            message["clip_url"],
+            "https://storage.googleapis.com/incidents/clip.ts"
+        );
+        assert_eq!(
+            render("{{frame_url}}", &variables),
+            "https://storage.googleapis.com/incidents/frame.jpg"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::slate;
+use crate::video_stream::{analysis_branch, VideoStream, VideoStreamIterator};
+use color_eyre::{eyre::eyre, Result};
+use hawkeye_core::models::{Analysis, Watcher, DEFAULT_ANALYSIS_FPS};
+use log::info;
+use std::time::{Duration, Instant};
+
+/// Scheme of the `--source` generating the frames, see `TestSource::new`.
+pub const TEST_SCHEME: &str = "test://";
+
+/// Generated frames, alternating content, black frames and the slates of the watcher on a
+/// schedule, to exercise the detection, transitions, actions and metrics without any stream.
+#[derive(Clone)]
+pub struct TestSource {
+    phases: Vec<(Pattern, Duration)>,
+    analysis: Option<Analysis>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Pattern {
+    /// A moving pattern, so the freeze trigger does not detect it.
+    Content,
+    Black,
+    /// The image of the slate, matching it exactly.
+    Slate {
+        id: String,
+        image: Vec<u8>,
+    },
+}
+
+impl TestSource {
+    /// The phases of the `schedule`, like `content=10,default=5,black=3`, each shown for its
+    /// seconds in turn, forever. A phase is `content`, `black`, or the ID of a slate of the
+    /// watcher, whose image is loaded like the slate.
+    pub fn new(schedule: &str, watcher: &Watcher, library_url: Option<&str>) -> Result<Self> {
+        let slates = watcher.all_slates();
+        let mut phases = Vec::new();
+        for phase in schedule.split(',') {
+            let (name, seconds) = phase
+                .split_once('=')
+                .ok_or_else(|| eyre!("Invalid phase {}, expected <pattern>=<seconds>", phase))?;
+            let seconds: f64 = seconds
+                .parse()
+                .ok()
+                .filter(|seconds: &f64| seconds.is_finite() && *seconds > 0.0)
+                .ok_or_else(|| eyre!("Invalid duration of the phase {}", phase))?;
+            let pattern = match name {
+                "content" => Pattern::Content,
+                "black" => Pattern::Black,
+                id => {
+                    let slate = slates.iter().find(|slate| slate.id == id).ok_or_else(|| {
+                        eyre!("Unknown pattern {}, expected content, black or a slate", id)
+                    })?;
+                    let url = slate::resolve_url(&slate.url, library_url)?;
+                    Pattern::Slate {
+                        id: id.to_string(),
+                        image: slate::load_img(&url)?,
+                    }
+                }
+            };
+            phases.push((pattern, Duration::from_secs_f64(seconds)));
+        }
+        Ok(Self {
+            phases,
+            analysis: None,
+        })
+    }
+
+    /// Samples and scales the generated frames as the watcher sets, see `analysis_branch`.
+    pub fn with_analysis(mut self, analysis: Option<Analysis>) -> Self {
+        self.analysis = analysis;
+        self
+    }
+
+    /// Index of the phase shown `elapsed` after the start.
+    fn phase_at(&self, elapsed: Duration) -> usize {
+        let cycle: Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        let mut position = Duration::from_secs_f64(elapsed.as_secs_f64() % cycle.as_secs_f64());
+        for (i, (_, duration)) in self.phases.iter().enumerate() {
+            if position < *duration {
+                return i;
+            }
+            position -= *duration;
+        }
+        self.phases.len() - 1
+    }
+}
+
+impl IntoIterator for TestSource {
+    type Item = Result<Option<Vec<u8>>>;
+    type IntoIter = TestSourceIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let analysis = self.analysis.as_ref();
+        let pattern = |name: &str| {
+            VideoStream::new(format!(
+                "videotestsrc is-live=true pattern={} ! video/x-raw,width=1280,height=720 ! {}",
+                name,
+                analysis_branch(analysis)
+            ))
+            .into_iter()
+        };
+        let fps = analysis.map_or(DEFAULT_ANALYSIS_FPS, Analysis::fps);
+        TestSourceIterator {
+            content: pattern("ball"),
+            black: pattern("black"),
+            frame_interval: Duration::from_secs_f64(1.0 / fps),
+            next_frame: Instant::now(),
+            started: Instant::now(),
+            current: None,
+            source: self,
+        }
+    }
+}
+
+pub struct TestSourceIterator {
+    source: TestSource,
+    /// Both patterns keep playing, so a phase starts without waiting for its pipeline.
+    content: VideoStreamIterator,
+    black: VideoStreamIterator,
+    /// Time between the frames of the slates, at the rate of the analysis.
+    frame_interval: Duration,
+    next_frame: Instant,
+    started: Instant,
+    current: Option<usize>,
+}
+
+impl Iterator for TestSourceIterator {
+    type Item = Result<Option<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let phase = self.source.phase_at(self.started.elapsed());
+        let (pattern, duration) = &self.source.phases[phase];
+        if self.current != Some(phase) {
+            self.current = Some(phase);
+            match pattern {
+                Pattern::Slate { id, .. } => info!("Showing slate {} for {:?}", id, duration),
+                pattern => info!("Showing {:?} for {:?}", pattern, duration),
+            }
+        }
+        match pattern {
+            Pattern::Content => self.content.next(),
+            Pattern::Black => self.black.next(),
+            Pattern::Slate { image, .. } => {
+                let now = Instant::now();
+                if now < self.next_frame {
+                    return Some(Ok(None));
+                }
+                self.next_frame = now + self.frame_interval;
+                Some(Ok(Some(image.clone())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn alternates_the_phases_of_the_schedule() {
+        let watcher: Watcher = serde_json::from_value(serde_json::json!({
+            "slate_url": "file://../resources/slate_120px.jpg",
+            "source": {
+                "ingest_port": 5000,
+                "container": "mpeg-ts",
+                "codec": "h264",
+                "transport": {"protocol": "rtp"}
+            },
+            "transitions": []
+        }))
+        .unwrap();
+        let source = TestSource::new("content=10,default=5,black=2.5", &watcher, None).unwrap();
+        assert_eq!(source.phases.len(), 3);
+        assert_eq!(source.phases[0].0, Pattern::Content);
+        assert!(matches!(&source.phases[1].0, Pattern::Slate { id, .. } if id == "default"));
+        assert_eq!(
+            source.phases[2],
+            (Pattern::Black, Duration::from_millis(2500))
+        );
+
+        let phase_at = |seconds: f64| source.phase_at(Duration::from_secs_f64(seconds));
+        assert_eq!(phase_at(0.0), 0);
+        assert_eq!(phase_at(10.0), 1);
+        assert_eq!(phase_at(16.0), 2);
+        assert_eq!(phase_at(17.5), 0);
+        assert_eq!(phase_at(28.0), 1);
+
+        assert!(TestSource::new("network=5", &watcher, None).is_err());
+        assert!(TestSource::new("content=0", &watcher, None).is_err());
+        assert!(TestSource::new("content", &watcher, None).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::img_detector::DecodedFrame;
+use crate::metrics::METRICS;
+use crate::state::STATE;
+use hawkeye_core::models::{
+    Trigger, VideoMode, Watcher, BLACK_TRIGGER_ID, FAILOVER_TRIGGER_ID, FREEZE_TRIGGER_ID,
+    STREAM_LOSS_TRIGGER_ID,
+};
+use prometheus::IntCounter;
+use std::time::{Duration, Instant};
+
+/// A condition of the frames, detected once it lasts long enough.
+struct Sustained {
+    duration: Duration,
+    since: Option<Instant>,
+    detected: bool,
+}
+
+impl Sustained {
+    fn new(trigger: &Trigger) -> Self {
+        Self {
+            duration: Duration::from_secs_f64(trigger.duration()),
+            since: None,
+            detected: false,
+        }
+    }
+
+    /// Whether the condition, met or not by the frame received at `now`, is detected. Returns
+    /// `true` on the frame it starts being detected.
+    fn update(&mut self, condition: bool, now: Instant) -> bool {
+        let was_detected = self.detected;
+        if condition {
+            let since = *self.since.get_or_insert(now);
+            self.detected = now.duration_since(since) >= self.duration;
+        } else {
+            self.since = None;
+            self.detected = false;
+        }
+        self.detected && !was_detected
+    }
+
+    fn mode(
+        &mut self,
+        condition: bool,
+        now: Instant,
+        mode: VideoMode,
+        onsets: &IntCounter,
+    ) -> VideoMode {
+        if self.update(condition, now) {
+            onsets.inc();
+        }
+        if self.detected {
+            mode
+        } else {
+            VideoMode::Content
+        }
+    }
+}
+
+/// Detects the frames staying black or frozen, or missing, long enough to trigger the
+/// transitions of the watcher, and the frames of its backup sources.
+pub struct FrameTriggers {
+    black: Option<Sustained>,
+    freeze: Option<(Sustained, f64)>,
+    stream_loss: Option<Sustained>,
+    previous: Option<DecodedFrame>,
+    /// The worker already waited for the frames before failing over, see `FailoverStream`.
+    failover: bool,
+}
+
+impl FrameTriggers {
+    pub fn new(watcher: &Watcher) -> Self {
+        Self {
+            black: watcher.black.as_ref().map(Sustained::new),
+            freeze: watcher
+                .freeze
+                .as_ref()
+                .map(|freeze| (Sustained::new(freeze), freeze.threshold())),
+            stream_loss: watcher.stream_loss.as_ref().map(Sustained::new),
+            previous: None,
+            failover: watcher.failover.is_some(),
+        }
+    }
+
+    /// Mode of the frame for each trigger of the watcher, by trigger ID.
+    pub fn update(
+        &mut self,
+        frame: DecodedFrame,
+        is_black: bool,
+        now: Instant,
+    ) -> Vec<(String, VideoMode)> {
+        let mut modes = Vec::new();
+        if let Some(black) = self.black.as_mut() {
+            let mode = black.mode(is_black, now, VideoMode::Black, &METRICS.black_detected);
+            modes.push((BLACK_TRIGGER_ID.to_string(), mode));
+        }
+        if let Some((freeze, threshold)) = self.freeze.as_mut() {
+            // Black frames are left to the black trigger, a fade to black is not a freeze
+            let frozen = !is_black
+                && self
+                    .previous
+                    .as_ref()
+                    .map_or(false, |previous| frame.difference(previous) <= *threshold);
+            let mode = freeze.mode(frozen, now, VideoMode::Frozen, &METRICS.freeze_detected);
+            modes.push((FREEZE_TRIGGER_ID.to_string(), mode));
+            self.previous = Some(frame);
+        }
+        if let Some(stream_loss) = self.stream_loss.as_mut() {
+            let mode = stream_loss.mode(false, now, VideoMode::Lost, &METRICS.stream_lost);
+            modes.push((STREAM_LOSS_TRIGGER_ID.to_string(), mode));
+        }
+        if self.failover {
+            let mode = if STATE.lock().unwrap().active_source == 0 {
+                VideoMode::Content
+            } else {
+                VideoMode::Backup
+            };
+            modes.push((FAILOVER_TRIGGER_ID.to_string(), mode));
+        }
+        modes
+    }
+
+    /// Mode of the stream loss trigger when no frame was received at `now`, only on the first
+    /// time the frames were missing long enough. The first frame received recovers the stream.
+    pub fn no_frame(&mut self, now: Instant) -> Option<(String, VideoMode)> {
+        let stream_loss = self.stream_loss.as_mut()?;
+        if stream_loss.update(true, now) {
+            METRICS.stream_lost.inc();
+            Some((STREAM_LOSS_TRIGGER_ID.to_string(), VideoMode::Lost))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::img_detector::SlateDetector;
+
+    fn decode(path: &str) -> DecodedFrame {
+        let black = std::fs::read("../resources/black_120px.jpg").unwrap();
+        let detector = SlateDetector::new(&black).unwrap();
+        detector.decode(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn detects_stream_loss_until_the_next_frame() {
+        let mut watcher = watcher(None, None);
+        watcher.stream_loss =
+            serde_json::from_value(serde_json::json!({"duration": 2, "transitions": []})).unwrap();
+        let mut triggers = FrameTriggers::new(&watcher);
+        let start = Instant::now();
+
+        assert_eq!(triggers.no_frame(start), None);
+        assert_eq!(triggers.no_frame(start + Duration::from_secs(1)), None);
+        assert_eq!(
+            triggers.no_frame(start + Duration::from_secs(2)),
+            Some(("stream_loss".to_string(), VideoMode::Lost))
+        );
+        assert_eq!(triggers.no_frame(start + Duration::from_secs(3)), None);
+
+        let modes = triggers.update(
+            decode("../resources/slate_120px.jpg"),
+            false,
+            start + Duration::from_secs(4),
+        );
+        assert_eq!(modes, vec![("stream_loss".to_string(), VideoMode::Content)]);
+        assert_eq!(triggers.no_frame(start + Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn reports_the_frames_of_backup_sources() {
+        let mut watcher = watcher(None, None);
+        watcher.failover = serde_json::from_value(serde_json::json!({"transitions": []})).unwrap();
+        let mut triggers = FrameTriggers::new(&watcher);
+
+        STATE.lock().unwrap().active_source = 1;
+        let modes = triggers.update(
+            decode("../resources/slate_120px.jpg"),
+            false,
+            Instant::now(),
+        );
+        STATE.lock().unwrap().active_source = 0;
+        assert_eq!(modes, vec![("failover".to_string(), VideoMode::Backup)]);
+    }
+
+    fn watcher(black: Option<f64>, freeze: Option<f64>) -> Watcher {
+        serde_json::from_value(serde_json::json!({
+            "slate_url": "file://../resources/slate_120px.jpg",
+            "source": {
+                "ingest_port": 5000,
+                "container": "mpeg-ts",
+                "codec": "h264",
+                "transport": { "protocol": "rtp" }
+            },
+            "transitions": [],
+            "black": black.map(|duration| serde_json::json!({"duration": duration, "transitions": []})),
+            "freeze": freeze.map(|duration| serde_json::json!({"duration": duration, "transitions": []})),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn detects_sustained_black_frames() {
+        let mut triggers = FrameTriggers::new(&watcher(Some(2.0), None));
+        let start = Instant::now();
+        let modes = |triggers: &mut FrameTriggers, is_black, seconds| {
+            let frame = decode("../resources/black_120px.jpg");
+            triggers.update(frame, is_black, start + Duration::from_secs(seconds))
+        };
+
+        assert_eq!(
+            modes(&mut triggers, true, 0),
+            vec![("black".to_string(), VideoMode::Content)]
+        );
+        assert_eq!(
+            modes(&mut triggers, true, 1),
+            vec![("black".to_string(), VideoMode::Content)]
+        );
+        assert_eq!(
+            modes(&mut triggers, true, 2),
+            vec![("black".to_string(), VideoMode::Black)]
+        );
+        assert_eq!(
+            modes(&mut triggers, false, 3),
+            vec![("black".to_string(), VideoMode::Content)]
+        );
+        assert_eq!(
+            modes(&mut triggers, true, 4),
+            vec![("black".to_string(), VideoMode::Content)]
+        );
+    }
+
+    #[test]
+    fn detects_frozen_frames() {
+        let mut triggers = FrameTriggers::new(&watcher(None, Some(1.0)));
+        let start = Instant::now();
+        let mut modes = |path, seconds| {
+            let frame = decode(path);
+            triggers.update(frame, false, start + Duration::from_secs(seconds))
+        };
+
+        let frozen = vec![("freeze".to_string(), VideoMode::Frozen)];
+        let content = vec![("freeze".to_string(), VideoMode::Content)];
+        assert_eq!(modes("../resources/slate_120px.jpg", 0), content);
+        assert_eq!(modes("../resources/slate_120px.jpg", 1), content);
+        assert_eq!(modes("../resources/slate_120px.jpg", 2), frozen);
+        assert_eq!(modes("../resources/slate_120px.jpg", 3), frozen);
+        assert_eq!(modes("../resources/non-slate_120px.jpg", 4), content);
+    }
+}
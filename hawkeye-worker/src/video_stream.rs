@@ -1,9 +1,18 @@
-use crate::img_detector::SlateDetector;
-use crate::metrics::{
-    FOUND_CONTENT_COUNTER, FOUND_SLATE_COUNTER, FRAME_PROCESSING_DURATION,
-    SIMILARITY_EXECUTION_COUNTER, SIMILARITY_EXECUTION_DURATION,
-};
+use crate::audio::{self, AUDIO_BRANCH, RAW_AUDIO_BRANCH};
+use crate::calibration;
+use crate::conditions::Facts;
+use crate::decoder;
+use crate::health::{self, PacketRate, FRAMES_PROBE, PACKETS_PROBE};
+use crate::heartbeat;
+use crate::img_detector::{is_similar, Slate, SlateDetector};
+use crate::metrics::METRICS;
+use crate::preview::FRAME_HISTORY;
+use crate::record;
+use crate::reload::Reload;
+use crate::shutdown;
 use crate::slate::SLATE_SIZE;
+use crate::state::STATE;
+use crate::triggers::FrameTriggers;
 use color_eyre::Result;
 use concread::CowCell;
 use crossbeam::channel::{bounded, Receiver, Sender, TryRecvError, TrySendError};
@@ -12,16 +21,58 @@ use gst::element_error;
 use gst::prelude::*;
 use gstreamer as gst;
 use gstreamer_app as gst_app;
-use hawkeye_core::models::{Codec, Container, VideoMode};
+use hawkeye_core::models::{
+    self, Analysis, Codec, Container, Decoder, Fec, Multicast, Protocol, Source, SrtMode,
+    VideoMode, WatcherMode, DEFAULT_ANALYSIS_FPS, RTMP_SERVER_PORT,
+};
 use lazy_static::lazy_static;
-use log::{debug, info};
+use log::{debug, info, warn};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::field::Empty;
+use tracing::{info_span, Span};
+
+/// Branch of the pipeline muxing the parsed video back into a transport stream, read by the
+/// `recorder` sink while a clip is recorded. The parser repeats the parameter sets on each key
+/// frame so a clip can be decoded from its first one.
+const RECORDING_BRANCH: &str =
+    "queue leaky=downstream ! mpegtsmux ! appsink name=recorder sync=false";
+
+/// How long the pipeline has to flush its data when the worker stops.
+const EOS_TIMEOUT: Duration = Duration::from_millis(500);
 
 lazy_static! {
     pub(crate) static ref LATEST_FRAME: CowCell<Option<Vec<u8>>> = CowCell::new(None);
+    pub(crate) static ref LATEST_DETECTION: CowCell<Option<Detection>> = CowCell::new(None);
+}
+
+/// Result of the analysis of the latest frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+    /// Black frames are not compared with the slates.
+    pub is_black: bool,
+    /// Comparison with each slate of the watcher.
+    pub slates: Vec<SlateMatch>,
+}
+
+/// Result of the comparison of a frame with a slate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlateMatch {
+    pub slate_id: String,
+    /// See `FrameDetector::similarity`, missing for black frames.
+    pub similarity: Option<f64>,
+    pub is_match: bool,
+    /// Area of the frame compared with the slate, the whole frame when missing.
+    pub region: Option<models::Region>,
+}
+
+impl Detection {
+    /// The first slate matching the frame, if any.
+    pub fn matched(&self) -> Option<&SlateMatch> {
+        self.slates.iter().find(|slate| slate.is_match)
+    }
 }
 
 #[derive(Debug, Display, Error)]
@@ -33,26 +84,38 @@ struct ErrorMessage {
     source: glib::Error,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum Event {
     Terminate,
-    Mode(VideoMode),
+    /// Mode of a frame for each slate and trigger, by ID, with what it shows for the conditions of
+    /// the transitions, and the span of its processing so the actions are traced within it.
+    Modes(Vec<(String, VideoMode)>, Facts, Span),
+    /// The slates were reloaded, with the transitions triggering the actions, and the mode of the
+    /// watcher.
+    Reload(Vec<models::Slate>, WatcherMode),
 }
 
 pub fn process_frames(
     frame_source: impl Iterator<Item = Result<Option<Vec<u8>>>>,
-    detector: SlateDetector,
+    mut slates: Vec<Slate>,
+    mut triggers: FrameTriggers,
+    watcher_id: &str,
     running: Arc<AtomicBool>,
+    reloads: Receiver<Reload>,
     action_sink: Sender<Event>,
 ) -> Result<()> {
     let black_image = include_bytes!("../../resources/black_120px.jpg");
     let black_detector = SlateDetector::new(black_image)?;
 
     let mut empty_iterations = 0;
+    let mut packet_rate = PacketRate::new(Instant::now());
     for frame in frame_source {
-        let frame_processing_timer = FRAME_PROCESSING_DURATION.start_timer();
+        heartbeat::alive();
+        packet_rate.sample(Instant::now());
+        let frame_processing_timer = METRICS.frame_processing_duration.start_timer();
         let local_buffer = match frame? {
             Some(contents) => {
+                health::record_frame(Instant::now());
                 log::trace!("Empty iterations: {}", empty_iterations);
                 empty_iterations = 0;
                 contents
@@ -62,46 +125,171 @@ pub fn process_frames(
                     break;
                 } else {
                     empty_iterations += 1;
+                    if let Some(mode) = triggers.no_frame(Instant::now()) {
+                        info!("No frame received, the stream is lost");
+                        STATE.lock().unwrap().input.stream_lost = true;
+                        let no_detection = Detection {
+                            is_black: false,
+                            slates: Vec::new(),
+                        };
+                        let facts = Facts::new(&no_detection, audio::loudness(), SystemTime::now());
+                        let span = info_span!(parent: None, "stream_loss", watcher_id);
+                        action_sink
+                            .send(Event::Modes(vec![mode], facts, span))
+                            .unwrap();
+                    }
                     thread::sleep(Duration::from_millis(100));
                     continue;
                 }
             }
         };
 
-        let is_black = black_detector.is_match(local_buffer.as_slice());
+        // Applied between frames, so a frame is compared with the slates and triggers the actions
+        // of the same definition
+        while let Ok(reload) = reloads.try_recv() {
+            match reload {
+                Reload::Slates {
+                    slates: reloaded,
+                    definitions,
+                    mode,
+                } => {
+                    info!(
+                        "Comparing the frames with {} reloaded slates",
+                        reloaded.len()
+                    );
+                    slates = reloaded;
+                    STATE.lock().unwrap().set_slates(&definitions);
+                    action_sink.send(Event::Reload(definitions, mode)).unwrap();
+                }
+                Reload::Threshold {
+                    slate_id,
+                    threshold,
+                } => {
+                    info!("Threshold of slate {} changed to {}", slate_id, threshold);
+                    for slate in slates.iter_mut().filter(|slate| slate.id == slate_id) {
+                        slate.threshold = threshold;
+                    }
+                    STATE.lock().unwrap().set_threshold(&slate_id, threshold);
+                }
+            }
+        }
+
+        // Each frame is a trace of its own, and not part of the span of the worker
+        let span = info_span!(
+            parent: None,
+            "frame",
+            watcher_id,
+            is_black = Empty,
+            slate_id = Empty,
+        );
+        let _enter = span.enter();
+
+        let frame = info_span!("decode").in_scope(|| black_detector.decode(&local_buffer))?;
+        let is_black = info_span!("black_detection").in_scope(|| black_detector.is_match(&frame));
 
-        let mut is_match = false;
-        if !is_black {
-            let t = SIMILARITY_EXECUTION_DURATION.start_timer();
+        let matches: Vec<SlateMatch> = slates
+            .iter()
+            .map(|slate| {
+                let mut similarity = None;
+                if !is_black {
+                    let span = info_span!("similarity", slate_id = %slate.id, similarity = Empty);
+                    let _enter = span.enter();
+                    let t = METRICS
+                        .similarity_duration
+                        .with_label_values(&[&slate.id])
+                        .start_timer();
 
-            is_match = detector.is_match(local_buffer.as_slice());
+                    let cropped = slate.region.as_ref().map(|region| frame.crop(region));
+                    let score = slate
+                        .detector
+                        .similarity(cropped.as_ref().unwrap_or(&frame));
+                    span.record("similarity", &score);
+                    similarity = Some(score);
 
-            let took_in_seconds = t.stop_and_record();
-            log::trace!("Similarity algorithm ran in {} seconds", took_in_seconds);
+                    let took_in_seconds = t.stop_and_record();
+                    log::trace!("Similarity algorithm ran in {} seconds", took_in_seconds);
+                }
+                SlateMatch {
+                    slate_id: slate.id.clone(),
+                    similarity,
+                    is_match: similarity.map_or(false, |s| is_similar(s, slate.threshold)),
+                    region: slate.region,
+                }
+            })
+            .collect();
+        let detection = Detection {
+            is_black,
+            slates: matches,
+        };
+        span.record("is_black", &is_black);
+        if let Some(matched) = detection.matched() {
+            span.record("slate_id", &matched.slate_id.as_str());
         }
 
+        FRAME_HISTORY
+            .lock()
+            .unwrap()
+            .push(SystemTime::now(), local_buffer.as_slice());
+
         {
             // Save latest image bytes
             let mut write_txn = LATEST_FRAME.write();
             // Moves the local buffer
             *write_txn = Some(local_buffer);
             write_txn.commit();
+
+            STATE.lock().unwrap().record_frame(&detection);
+            calibration::record(&detection);
+            let mut write_txn = LATEST_DETECTION.write();
+            *write_txn = Some(detection.clone());
+            write_txn.commit();
         }
 
+        let trigger_modes =
+            info_span!("triggers").in_scope(|| triggers.update(frame, is_black, Instant::now()));
+        let facts = Facts::new(&detection, audio::loudness(), SystemTime::now());
         if is_black {
+            // The slates keep their mode, only the triggers and the conditions see the black
+            // frames
+            action_sink
+                .send(Event::Modes(trigger_modes, facts, span.clone()))
+                .unwrap();
             continue;
         }
 
-        if is_match {
-            log::trace!("Found slate image in video stream!");
-            FOUND_SLATE_COUNTER.inc();
-            action_sink.send(Event::Mode(VideoMode::Slate)).unwrap();
-        } else {
-            FOUND_CONTENT_COUNTER.inc();
-            action_sink.send(Event::Mode(VideoMode::Content)).unwrap();
+        for slate in detection.slates.iter() {
+            if slate.is_match {
+                log::trace!("Found slate {} in video stream!", slate.slate_id);
+                METRICS
+                    .slate_found
+                    .with_label_values(&[&slate.slate_id])
+                    .inc();
+            }
+            METRICS
+                .similarity_executions
+                .with_label_values(&[&slate.slate_id])
+                .inc();
+        }
+        if detection.matched().is_none() {
+            METRICS.content_found.inc();
             log::trace!("Content in video stream!");
         }
-        SIMILARITY_EXECUTION_COUNTER.inc();
+        let mut modes: Vec<(String, VideoMode)> = detection
+            .slates
+            .into_iter()
+            .map(|slate| {
+                let mode = if slate.is_match {
+                    VideoMode::Slate
+                } else {
+                    VideoMode::Content
+                };
+                (slate.slate_id, mode)
+            })
+            .collect();
+        modes.extend(trigger_modes);
+        action_sink
+            .send(Event::Modes(modes, facts, span.clone()))
+            .unwrap();
 
         let took_in_seconds = frame_processing_timer.stop_and_record();
         log::trace!("Frame processing took {} seconds", took_in_seconds);
@@ -116,49 +304,439 @@ pub fn process_frames(
     Ok(())
 }
 
-pub struct RtpServer {
+/// Time SRT gives to recover the lost packets, when missing in the source.
+const DEFAULT_SRT_LATENCY_MS: u32 = 120;
+
+/// The frames of the source of the watcher, received on its ingest port or from its sender.
+#[derive(Clone)]
+pub struct SourceStream {
     ingest_port: u32,
-    container: Container,
-    codec: Codec,
+    source: Source,
+    analysis: Option<Analysis>,
 }
 
-impl RtpServer {
-    pub fn new(ingest_port: u32, container: Container, codec: Codec) -> Self {
+impl SourceStream {
+    pub fn new(ingest_port: u32, source: Source) -> Self {
         Self {
             ingest_port,
-            container,
-            codec,
+            source,
+            analysis: None,
         }
     }
+
+    /// Samples and scales the frames as the watcher sets, see `analysis_branch`.
+    pub fn with_analysis(mut self, analysis: Option<Analysis>) -> Self {
+        self.analysis = analysis;
+        self
+    }
+
+    fn decoder(&self) -> Decoder {
+        self.analysis
+            .as_ref()
+            .and_then(|analysis| analysis.decoder)
+            .unwrap_or_default()
+    }
 }
 
-impl IntoIterator for RtpServer {
+impl IntoIterator for SourceStream {
     type Item = Result<Option<Vec<u8>>>;
     type IntoIter = VideoStreamIterator;
 
     fn into_iter(self) -> Self::IntoIter {
-        let (width, height) = SLATE_SIZE;
-        let pipeline_description = match (self.container, self.codec) {
+        if let Protocol::Hls {
+            url,
+            max_bitrate_kbps,
+        }
+        | Protocol::Dash {
+            url,
+            max_bitrate_kbps,
+        } = &self.source.transport
+        {
+            decoder::prefer(self.decoder());
+            let description = pull_pipeline(url, *max_bitrate_kbps, self.analysis.as_ref());
+            return VideoStream::new(description).into_iter();
+        }
+        let h264_decoder = decoder::h264_decoder(self.decoder());
+        let pipeline_description = match (self.source.container, self.source.codec) {
+            // The audio branch comes first, the frames are read at the end of the description
             (Container::MpegTs, Codec::H264) => format!(
-                "udpsrc port={} caps=\"application/x-rtp, media=(string)video, clock-rate=(int)90000, encoding-name=(string)MP2T, payload=(int)33\" ! .recv_rtp_sink_0 rtpbin ! rtpmp2tdepay ! tsdemux ! h264parse ! avdec_h264 ! videorate ! video/x-raw,framerate=10/1 ! videoconvert ! videoscale ! capsfilter caps=\"video/x-raw, width={}, height={}\"",
-                self.ingest_port,
-                width,
-                height
+                "{} ! {} ! tsdemux name=demux demux. ! {} demux. ! queue ! h264parse config-interval=-1 ! tee name=recording recording. ! {} recording. ! queue ! {} ! {} ! {}",
+                input(
+                    &self.source.transport,
+                    self.ingest_port,
+                    self.source.multicast.as_ref()
+                ),
+                PACKETS_PROBE,
+                AUDIO_BRANCH,
+                RECORDING_BRANCH,
+                h264_decoder,
+                FRAMES_PROBE,
+                analysis_branch(self.analysis.as_ref())
+            ),
+            (Container::Flv, Codec::H264) => format!(
+                "{} ! {} ! flvdemux name=demux demux.audio ! {} demux.video ! queue ! h264parse config-interval=-1 ! tee name=recording recording. ! {} recording. ! queue ! {} ! {} ! {}",
+                input(
+                    &self.source.transport,
+                    self.ingest_port,
+                    self.source.multicast.as_ref()
+                ),
+                PACKETS_PROBE,
+                AUDIO_BRANCH,
+                RECORDING_BRANCH,
+                h264_decoder,
+                FRAMES_PROBE,
+                analysis_branch(self.analysis.as_ref())
             ),
             (Container::RawVideo, Codec::H264) => format!(
-                "udpsrc port={} caps=\"application/x-rtp, media=(string)video, clock-rate=(int)90000, encoding-name=(string)H264, payload=(int)96\" ! rtph264depay ! decodebin ! videorate ! video/x-raw,framerate=10/1 ! videoconvert ! videoscale ! capsfilter caps=\"video/x-raw, width={}, height={}\"",
-                self.ingest_port,
-                width,
-                height
+                "{} caps=\"application/x-rtp, media=(string)video, clock-rate=(int)90000, encoding-name=(string)H264, payload=(int)96\" ! {} ! rtph264depay ! h264parse config-interval=-1 ! tee name=recording recording. ! {} recording. ! queue ! {} ! {} ! {}",
+                udpsrc(self.ingest_port, self.source.multicast.as_ref()),
+                PACKETS_PROBE,
+                RECORDING_BRANCH,
+                h264_decoder,
+                FRAMES_PROBE,
+                analysis_branch(self.analysis.as_ref())
             ),
-            (_, _) => {
-                panic!("Container ({:?}) and Codec ({:?}) not available", self.container, self.codec);
+            (container, codec) => {
+                panic!("Container ({:?}) and Codec ({:?}) not available", container, codec);
             }
         };
         VideoStream::new(pipeline_description).into_iter()
     }
 }
 
+/// The frames of a capture, or of an RTSP stream, to run the worker outside Kubernetes instead
+/// of receiving the source of the watcher.
+#[derive(Clone)]
+pub struct LocalStream {
+    uri: String,
+    looping: bool,
+    analysis: Option<Analysis>,
+}
+
+impl LocalStream {
+    /// The frames of a `file://` capture, whose relative path is resolved from the working
+    /// directory, or of an `rtsp://` or `rtsps://` stream. With `looping`, the stream starts
+    /// again once it ended.
+    pub fn new(url: &str, looping: bool) -> Result<Self> {
+        let uri = if let Some(path) = url.strip_prefix("file://") {
+            let path = std::fs::canonicalize(path)
+                .map_err(|e| color_eyre::eyre::eyre!("Cannot read {}: {}", path, e))?;
+            format!("file://{}", path.display())
+        } else if url.starts_with("rtsp://") || url.starts_with("rtsps://") {
+            url.to_string()
+        } else {
+            return Err(color_eyre::eyre::eyre!(
+                "Unsupported source {}, expected a file:// or rtsp:// URL",
+                url
+            ));
+        };
+        Ok(Self {
+            uri,
+            looping,
+            analysis: None,
+        })
+    }
+
+    /// Samples and scales the frames as the watcher sets, see `analysis_branch`.
+    pub fn with_analysis(mut self, analysis: Option<Analysis>) -> Self {
+        self.analysis = analysis;
+        self
+    }
+}
+
+impl IntoIterator for LocalStream {
+    type Item = Result<Option<Vec<u8>>>;
+    type IntoIter = LocalStreamIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let description = local_pipeline(&self.uri, self.analysis.as_ref());
+        LocalStreamIterator {
+            current: VideoStream::new(&description).into_iter(),
+            description,
+            looping: self.looping,
+        }
+    }
+}
+
+pub struct LocalStreamIterator {
+    description: String,
+    looping: bool,
+    current: VideoStreamIterator,
+}
+
+impl Iterator for LocalStreamIterator {
+    type Item = Result<Option<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.current.next() {
+            None if self.looping => {
+                info!("End of the stream, starting it again");
+                self.current = VideoStream::new(&self.description).into_iter();
+                Some(Ok(None))
+            }
+            item => item,
+        }
+    }
+}
+
+/// The pipeline of the local streams, the container and codec being detected. The captures are
+/// played at their rate, like a live stream, so the triggers see the same durations.
+fn local_pipeline(uri: &str, analysis: Option<&Analysis>) -> String {
+    let sync = if uri.starts_with("file://") {
+        "identity sync=true ! "
+    } else {
+        ""
+    };
+    format!(
+        "uridecodebin uri=\"{}\" name=src src. ! audio/x-raw ! {}{} src. ! video/x-raw ! {}{} ! queue ! {}",
+        uri,
+        sync,
+        RAW_AUDIO_BRANCH,
+        sync,
+        FRAMES_PROBE,
+        analysis_branch(analysis)
+    )
+}
+
+/// The frames of the first source receiving them, switching to the next source, in order, when
+/// the current one received no frame for the timeout, or its pipeline stopped. The last source
+/// fails over to the first one again.
+pub struct FailoverStream {
+    sources: Vec<SourceStream>,
+    timeout: Duration,
+}
+
+impl FailoverStream {
+    pub fn new(sources: Vec<SourceStream>, timeout: Duration) -> Self {
+        Self { sources, timeout }
+    }
+}
+
+impl IntoIterator for FailoverStream {
+    type Item = Result<Option<Vec<u8>>>;
+    type IntoIter = FailoverIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let current = self.sources[0].clone().into_iter();
+        FailoverIterator {
+            sources: self.sources,
+            timeout: self.timeout,
+            active: 0,
+            current,
+            last_frame: Instant::now(),
+        }
+    }
+}
+
+pub struct FailoverIterator {
+    sources: Vec<SourceStream>,
+    timeout: Duration,
+    /// Index of the source of the frames, `0` for the source and from `1` for the backups.
+    active: usize,
+    current: VideoStreamIterator,
+    last_frame: Instant,
+}
+
+impl FailoverIterator {
+    fn fail_over(&mut self) {
+        let next = (self.active + 1) % self.sources.len();
+        warn!(
+            "No frame from source {} for {:?}, switching to source {}",
+            self.active, self.timeout, next
+        );
+        METRICS
+            .source_failovers
+            .with_label_values(&[&self.active.to_string(), &next.to_string()])
+            .inc();
+        METRICS.active_source.set(next as i64);
+        STATE.lock().unwrap().active_source = next;
+        // The pipeline of the current source stops when dropped
+        self.current = self.sources[next].clone().into_iter();
+        self.active = next;
+        self.last_frame = Instant::now();
+    }
+}
+
+impl Iterator for FailoverIterator {
+    type Item = Result<Option<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.current.next() {
+            Some(Ok(Some(frame))) => {
+                self.last_frame = Instant::now();
+                Some(Ok(Some(frame)))
+            }
+            Some(Ok(None)) if self.last_frame.elapsed() < self.timeout => Some(Ok(None)),
+            Some(Err(err)) => Some(Err(err)),
+            // No frame for too long, or the pipeline stopped
+            Some(Ok(None)) | None => {
+                self.fail_over();
+                Some(Ok(None))
+            }
+        }
+    }
+}
+
+/// The RTP stream of the ingest port, merged with the second leg of SMPTE 2022-7 and corrected
+/// with the FEC streams of SMPTE 2022-1. The jitter buffer of `rtpbin` drops the packets received
+/// on both legs.
+fn redundant_rtp_input(ingest_port: u32, fec: Option<Fec>, second_leg_port: Option<u32>) -> String {
+    let mut description = String::new();
+    let mut first_leg = format!("udpsrc port={} caps=\"{}\"", ingest_port, MP2T_RTP_CAPS);
+    if let Some(port) = second_leg_port {
+        description.push_str(&format!(
+            "udpsrc port={} caps=\"{}\" ! legs. ",
+            port, MP2T_RTP_CAPS
+        ));
+        first_leg.push_str(" ! funnel name=legs");
+    }
+    let fec_streams: &[u32] = match fec {
+        None => &[],
+        Some(Fec::OneDimension) => &[2],
+        Some(Fec::TwoDimensions) => &[2, 4],
+    };
+    for (i, offset) in fec_streams.iter().enumerate() {
+        description.push_str(&format!(
+            "udpsrc port={} caps=\"application/x-rtp, payload=(int)96\" ! rtp.recv_fec_sink_0_{} ",
+            ingest_port + offset,
+            i
+        ));
+    }
+    let fec_decoders = if fec.is_some() {
+        " fec-decoders='fec,0=\"rtpst2022-1-fecdec\\ size-time\\=1000000000\";'"
+    } else {
+        ""
+    };
+    description.push_str(&format!(
+        "{} ! .recv_rtp_sink_0 rtpbin name=rtp{} ! rtpmp2tdepay",
+        first_leg, fec_decoders
+    ));
+    description
+}
+
+/// The end of the pipelines, from the decoded frames to the ones compared with the slates: the
+/// frames are sampled at the rate of the analysis, then downscaled to its resolution, if any,
+/// before converting their colors and scaling them to the size of the slates.
+pub(crate) fn analysis_branch(analysis: Option<&Analysis>) -> String {
+    let (width, height) = SLATE_SIZE;
+    let fps = analysis.map_or(DEFAULT_ANALYSIS_FPS, Analysis::fps);
+    // Whole rates, like the default 10/1, or rates to the thousandth
+    let framerate = if fps.fract() == 0.0 {
+        format!("{}/1", fps)
+    } else {
+        format!("{}/1000", (fps * 1000.0).round())
+    };
+    let downscale = match analysis.and_then(|analysis| analysis.max_resolution) {
+        // The frames smaller than the resolution keep theirs, the width keeps the aspect ratio
+        Some(lines) => format!("videoscale ! video/x-raw,height=(int)[1,{}] ! ", lines),
+        None => String::new(),
+    };
+    format!(
+        "videorate ! video/x-raw,framerate={} ! {}videoconvert ! videoscale ! capsfilter caps=\"video/x-raw, width={}, height={}\"",
+        framerate, downscale, width, height
+    )
+}
+
+/// Bitrate making `uridecodebin` pick the lowest variant of the HLS and DASH sources, enough for
+/// the small frames compared with the slates.
+const LOWEST_VARIANT_KBPS: u32 = 1;
+
+/// The pipeline of the HLS and DASH sources. The demuxers of `uridecodebin` poll the playlist, or
+/// manifest, for the new segments and start from its live edge, the container and codec being
+/// detected.
+fn pull_pipeline(url: &str, max_bitrate_kbps: Option<u32>, analysis: Option<&Analysis>) -> String {
+    format!(
+        "uridecodebin uri=\"{}\" connection-speed={} name=src src. ! audio/x-raw ! {} src. ! video/x-raw ! {} ! queue ! {}",
+        url,
+        max_bitrate_kbps.unwrap_or(LOWEST_VARIANT_KBPS),
+        RAW_AUDIO_BRANCH,
+        FRAMES_PROBE,
+        analysis_branch(analysis)
+    )
+}
+
+/// Caps of the RTP packets carrying MPEG-TS.
+const MP2T_RTP_CAPS: &str = "application/x-rtp, media=(string)video, clock-rate=(int)90000, encoding-name=(string)MP2T, payload=(int)33";
+
+/// Time RIST gives to receive the lost packets again, when missing in the source.
+const DEFAULT_RIST_BUFFER_MS: u32 = 1000;
+
+/// Caps of the MPEG-TS packets sent in UDP datagrams without RTP.
+const MP2T_CAPS: &str = "video/mpegts, systemstream=(boolean)true, packetsize=(int)188";
+
+/// The `udpsrc` of the ingest port, joining the multicast group of the source, if any.
+fn udpsrc(ingest_port: u32, multicast: Option<&Multicast>) -> String {
+    let mut element = format!("udpsrc port={}", ingest_port);
+    // The addresses and interface are validated
+    if let Some(multicast) = multicast {
+        element.push_str(&format!(" address={}", multicast.group));
+        if let Some(interface) = &multicast.interface {
+            element.push_str(&format!(" multicast-iface={}", interface));
+        }
+        if let Some(source_address) = &multicast.source_address {
+            element.push_str(&format!(" multicast-source=\"+{}\"", source_address));
+        }
+    }
+    element
+}
+
+/// The elements receiving the MPEG-TS, or FLV for RTMP, stream of the source with its transport
+/// protocol.
+fn input(transport: &Protocol, ingest_port: u32, multicast: Option<&Multicast>) -> String {
+    match transport {
+        Protocol::Rtp {
+            fec: None,
+            second_leg_port: None,
+        } => format!(
+            "{} caps=\"{}\" ! .recv_rtp_sink_0 rtpbin ! rtpmp2tdepay",
+            udpsrc(ingest_port, multicast),
+            MP2T_RTP_CAPS
+        ),
+        Protocol::Udp => format!("{} caps=\"{}\"", udpsrc(ingest_port, multicast), MP2T_CAPS),
+        Protocol::Rtp {
+            fec,
+            second_leg_port,
+        } => redundant_rtp_input(ingest_port, *fec, *second_leg_port),
+        Protocol::Rist { buffer_ms } => format!(
+            "ristsrc address=0.0.0.0 port={} receiver-buffer={} encoding-name=MP2T ! rtpmp2tdepay",
+            ingest_port,
+            buffer_ms.unwrap_or(DEFAULT_RIST_BUFFER_MS)
+        ),
+        Protocol::Srt {
+            mode,
+            address,
+            passphrase,
+            latency_ms,
+        } => {
+            let uri = match mode.unwrap_or_default() {
+                SrtMode::Listener => format!("srt://:{}?mode=listener", ingest_port),
+                SrtMode::Caller => format!(
+                    "srt://{}?mode=caller",
+                    address.as_deref().unwrap_or_default()
+                ),
+            };
+            let mut element = format!(
+                "srtsrc uri=\"{}\" latency={}",
+                uri,
+                latency_ms.unwrap_or(DEFAULT_SRT_LATENCY_MS)
+            );
+            // Validated not to contain quotes
+            if let Some(passphrase) = passphrase {
+                element.push_str(&format!(" passphrase=\"{}\"", passphrase));
+            }
+            element
+        }
+        // Published to the RTMP server running next to the worker
+        Protocol::Rtmp { stream_key, .. } => format!(
+            "rtmpsrc location=\"rtmp://127.0.0.1:{}/live/{} live=1\"",
+            RTMP_SERVER_PORT, stream_key
+        ),
+        Protocol::Hls { .. } | Protocol::Dash { .. } => {
+            unreachable!("The HLS and DASH sources have a pipeline of their own")
+        }
+    }
+}
+
 pub struct VideoStream {
     pipeline_description: String,
 }
@@ -189,6 +767,8 @@ impl IntoIterator for VideoStream {
         .expect("Pipeline description invalid, cannot create")
         .downcast::<gst::Pipeline>()
         .expect("Expected a gst::Pipeline");
+        health::add_probes(&pipeline);
+        add_recorder(&pipeline);
 
         // Get access to the appsink element.
         let appsink = pipeline
@@ -278,6 +858,29 @@ impl IntoIterator for VideoStream {
     }
 }
 
+/// Feeds the transport stream of the `recorder` sink to the recordings, when the pipeline has one.
+fn add_recorder(pipeline: &gst::Pipeline) {
+    let recorder = pipeline
+        .by_name("recorder")
+        .and_then(|sink| sink.downcast::<gst_app::AppSink>().ok());
+    record::set_recordable(recorder.is_some());
+    if let Some(recorder) = recorder {
+        recorder.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(|appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    if let Some(buffer) = sample.buffer() {
+                        if let Ok(map) = buffer.map_readable() {
+                            record::record(map.as_slice());
+                        }
+                    }
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+    }
+}
+
 pub struct VideoStreamIterator {
     description: String,
     receiver: Receiver<Result<Option<Vec<u8>>>>,
@@ -289,6 +892,11 @@ impl Iterator for VideoStreamIterator {
     type Item = Result<Option<Vec<u8>>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        while let Some(msg) = self.bus.pop_filtered(&[gst::MessageType::Element]) {
+            if let Some(loudness) = msg.structure().and_then(audio::level_loudness) {
+                audio::record_loudness(loudness);
+            }
+        }
         match self.receiver.try_recv() {
             Ok(event) => return Some(event),
             Err(TryRecvError::Empty) => {
@@ -317,6 +925,14 @@ impl Iterator for VideoStreamIterator {
                             // TODO: Should return a proper error here, returning `None` will simply stop the iterator.
                             return None;
                         }
+                        MessageView::Warning(..) => {
+                            let from_decoder = msg
+                                .src()
+                                .map_or(false, |s| decoder::is_decoder(&s.path_string()));
+                            if from_decoder {
+                                health::record_decode_error();
+                            }
+                        }
                         _ => (),
                     }
                 }
@@ -334,9 +950,117 @@ impl Iterator for VideoStreamIterator {
 
 impl Drop for VideoStreamIterator {
     fn drop(&mut self) {
+        // Lets the elements flush their data, like the recording of a clip, when the worker stops
+        if shutdown::is_shutting_down() && self.pipeline.send_event(gst::event::Eos::new()) {
+            self.bus.timed_pop_filtered(
+                gst::ClockTime::from_mseconds(EOS_TIMEOUT.as_millis() as u64),
+                &[gst::MessageType::Eos, gst::MessageType::Error],
+            );
+        }
         if self.pipeline.set_state(gst::State::Null).is_err() {
             log::error!("Could not stop pipeline");
         }
         log::debug!("Pipeline stopped!");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn receives_srt_streams() {
+        let listener: Protocol = serde_json::from_value(serde_json::json!({
+            "protocol": "srt",
+            "passphrase": "0123456789abcdef"
+        }))
+        .unwrap();
+        assert_eq!(
+            input(&listener, 5000, None),
+            "srtsrc uri=\"srt://:5000?mode=listener\" latency=120 passphrase=\"0123456789abcdef\""
+        );
+
+        let caller: Protocol = serde_json::from_value(serde_json::json!({
+            "protocol": "srt",
+            "mode": "caller",
+            "address": "encoder.example.com:9000",
+            "latency_ms": 500
+        }))
+        .unwrap();
+        assert_eq!(
+            input(&caller, 5000, None),
+            "srtsrc uri=\"srt://encoder.example.com:9000?mode=caller\" latency=500"
+        );
+    }
+
+    #[test]
+    fn merges_rtp_legs_and_corrects_them_with_fec() {
+        let rtp = Protocol::Rtp {
+            fec: Some(Fec::TwoDimensions),
+            second_leg_port: Some(5010),
+        };
+        let description = input(&rtp, 5000, None);
+        assert!(description.starts_with("udpsrc port=5010 caps="));
+        assert!(description.contains(
+            "udpsrc port=5002 caps=\"application/x-rtp, payload=(int)96\" ! rtp.recv_fec_sink_0_0 "
+        ));
+        assert!(description.contains(
+            "udpsrc port=5004 caps=\"application/x-rtp, payload=(int)96\" ! rtp.recv_fec_sink_0_1 "
+        ));
+        assert!(description.ends_with("! funnel name=legs ! .recv_rtp_sink_0 rtpbin name=rtp fec-decoders='fec,0=\"rtpst2022-1-fecdec\\ size-time\\=1000000000\";' ! rtpmp2tdepay"));
+    }
+
+    #[test]
+    fn joins_multicast_groups() {
+        let multicast = Multicast {
+            group: "232.1.1.1".to_string(),
+            source_address: Some("10.0.0.12".to_string()),
+            interface: Some("eth1".to_string()),
+        };
+        assert_eq!(
+            input(&Protocol::Udp, 5000, Some(&multicast)),
+            "udpsrc port=5000 address=232.1.1.1 multicast-iface=eth1 multicast-source=\"+10.0.0.12\" caps=\"video/mpegts, systemstream=(boolean)true, packetsize=(int)188\""
+        );
+        assert_eq!(udpsrc(5000, None), "udpsrc port=5000");
+    }
+
+    #[test]
+    fn samples_and_downscales_the_frames_for_the_analysis() {
+        assert_eq!(
+            analysis_branch(None),
+            "videorate ! video/x-raw,framerate=10/1 ! videoconvert ! videoscale ! capsfilter caps=\"video/x-raw, width=213, height=120\""
+        );
+        let analysis = Analysis {
+            fps: Some(2.5),
+            max_resolution: Some(480),
+            decoder: None,
+        };
+        assert_eq!(
+            analysis_branch(Some(&analysis)),
+            "videorate ! video/x-raw,framerate=2500/1000 ! videoscale ! video/x-raw,height=(int)[1,480] ! videoconvert ! videoscale ! capsfilter caps=\"video/x-raw, width=213, height=120\""
+        );
+    }
+
+    #[test]
+    fn plays_the_local_captures_at_their_rate() {
+        let capture = LocalStream::new("file://Cargo.toml", true).unwrap();
+        assert!(capture.uri.starts_with("file:///"));
+        assert!(local_pipeline(&capture.uri, None)
+            .contains("src. ! video/x-raw ! identity sync=true ! identity name=frames"));
+        assert!(!local_pipeline("rtsp://camera.local/stream", None).contains("sync=true"));
+        assert!(LocalStream::new("file://missing.ts", false).is_err());
+        assert!(LocalStream::new("http://example.com/stream.ts", false).is_err());
+    }
+
+    #[test]
+    fn receives_rtmp_streams_from_the_server() {
+        let rtmp = Protocol::Rtmp {
+            stream_key: "channel-1".to_string(),
+            tls: Some(true),
+        };
+        assert_eq!(
+            input(&rtmp, 5000, None),
+            "rtmpsrc location=\"rtmp://127.0.0.1:1935/live/channel-1 live=1\""
+        );
+    }
+}
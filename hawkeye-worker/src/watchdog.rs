@@ -0,0 +1,138 @@
+use crate::config::StallAction;
+use crate::events;
+use crate::metrics::METRICS;
+use color_eyre::{eyre::eyre, Result};
+use log::{info, warn};
+use std::time::{Duration, Instant};
+
+/// How long the worker waits for the stall event to be reported before exiting.
+const EXIT_REPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The frames of the pipeline started by `start`, watched for stalls: when the pipeline produced
+/// no frame for the timeout while its input kept receiving packets, the pipeline is restarted, or
+/// the frames end with an error so the worker exits and is restarted by Kubernetes.
+///
+/// Without any packet, the source stopped sending and the stream loss is handled by the
+/// transitions instead. The HLS and DASH sources count their frames as the packets, so their
+/// stalls cannot be told from the stream losses.
+pub struct Watchdog<F, I> {
+    start: F,
+    /// `None` only while the pipeline restarts, the previous one being stopped first so its
+    /// ingest port is free.
+    current: Option<I>,
+    timeout: Duration,
+    action: StallAction,
+    last_frame: Instant,
+    /// Packets received when the last frame was produced.
+    packets: u64,
+}
+
+impl<F, I> Watchdog<F, I>
+where
+    F: FnMut() -> I,
+    I: Iterator<Item = Result<Option<Vec<u8>>>>,
+{
+    pub fn new(mut start: F, timeout: Duration, action: StallAction) -> Self {
+        let current = Some(start());
+        Self {
+            start,
+            current,
+            timeout,
+            action,
+            last_frame: Instant::now(),
+            packets: METRICS.input_packets.get(),
+        }
+    }
+
+    fn is_stalled(&self, now: Instant, packets: u64) -> bool {
+        now.saturating_duration_since(self.last_frame) >= self.timeout && packets > self.packets
+    }
+
+    fn recover(&mut self, now: Instant, packets: u64) -> Result<Option<Vec<u8>>> {
+        warn!(
+            "No frame for {:?} while receiving packets, the pipeline is stalled",
+            self.timeout
+        );
+        METRICS
+            .pipeline_stalls
+            .with_label_values(&[self.action.as_str()])
+            .inc();
+        events::report(events::stall(self.action));
+        match self.action {
+            StallAction::Restart => {
+                info!("Restarting the pipeline..");
+                self.current = None;
+                self.current = Some((self.start)());
+                self.last_frame = now;
+                self.packets = packets;
+                Ok(None)
+            }
+            StallAction::Exit => {
+                events::flush(EXIT_REPORT_TIMEOUT);
+                Err(eyre!(
+                    "The pipeline produced no frame for {:?}",
+                    self.timeout
+                ))
+            }
+        }
+    }
+}
+
+impl<F, I> Iterator for Watchdog<F, I>
+where
+    F: FnMut() -> I,
+    I: Iterator<Item = Result<Option<Vec<u8>>>>,
+{
+    type Item = Result<Option<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.current.as_mut().and_then(Iterator::next);
+        let now = Instant::now();
+        let packets = METRICS.input_packets.get();
+        match item {
+            Some(Ok(Some(_))) => {
+                self.last_frame = now;
+                self.packets = packets;
+            }
+            Some(Ok(None)) if self.is_stalled(now, packets) => {
+                return Some(self.recover(now, packets));
+            }
+            _ => (),
+        }
+        item
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::iter;
+
+    #[test]
+    fn recovers_the_stalled_pipelines() {
+        let starts = Cell::new(0);
+        let start = || {
+            starts.set(starts.get() + 1);
+            iter::repeat_with(|| Ok(None))
+        };
+        let mut watchdog = Watchdog::new(start, Duration::from_secs(0), StallAction::Restart);
+        // The source stopped sending
+        assert!(matches!(watchdog.next(), Some(Ok(None))));
+        assert_eq!(starts.get(), 1);
+
+        METRICS.input_packets.inc();
+        assert!(matches!(watchdog.next(), Some(Ok(None))));
+        assert_eq!(starts.get(), 2);
+        assert!(matches!(watchdog.next(), Some(Ok(None))));
+        assert_eq!(starts.get(), 2);
+
+        let mut watchdog = Watchdog::new(
+            || iter::repeat_with(|| Ok(None)),
+            Duration::from_secs(0),
+            StallAction::Exit,
+        );
+        METRICS.input_packets.inc();
+        assert!(matches!(watchdog.next(), Some(Err(_))));
+    }
+}
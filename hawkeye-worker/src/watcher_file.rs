@@ -0,0 +1,103 @@
+use color_eyre::{eyre::eyre, Result};
+use hawkeye_core::models::Watcher;
+use std::fs;
+use std::path::Path;
+
+/// Reads the watcher definition at `path`, see `parse`.
+pub fn load(path: &Path) -> Result<Watcher> {
+    let text = fs::read_to_string(path)?;
+    parse(path, &text).map_err(|e| eyre!(e))
+}
+
+/// Parses the watcher definition of the file at `path`, in YAML for the `.yaml` and `.yml` files
+/// and in JSON otherwise, once its `${NAME}` variables are replaced by the environment.
+pub fn parse(path: &Path, text: &str) -> Result<Watcher, String> {
+    let text = substitute(text, |name| std::env::var(name).ok())
+        .map_err(|e| format!("Invalid watcher {}: {}", path.display(), e))?;
+    let is_yaml = path
+        .extension()
+        .map_or(false, |extension| extension == "yaml" || extension == "yml");
+    let watcher = if is_yaml {
+        serde_yaml::from_str(&text).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    };
+    watcher.map_err(|e| format!("Invalid watcher {}: {}", path.display(), e))
+}
+
+/// Replaces the `${NAME}` variables by their value, and the `${NAME:-default}` ones by the
+/// default when the variable is not set, like the threshold of a slate or the URL of an action
+/// set by the deployment. `$$` stands for a `$`.
+fn substitute(text: &str, var: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut substituted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        substituted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            substituted.push('$');
+            rest = after;
+            continue;
+        }
+        let end = match (rest.strip_prefix("${"), rest.find('}')) {
+            (Some(_), Some(end)) => end,
+            _ => {
+                substituted.push('$');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let (name, default) = match rest[2..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&rest[2..end], None),
+        };
+        match var(name).or_else(|| default.map(String::from)) {
+            Some(value) => substituted.push_str(&value),
+            None => return Err(format!("Environment variable {} is not set", name)),
+        }
+        rest = &rest[end + 1..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn substitutes_the_environment_variables() {
+        let var = |name: &str| match name {
+            "ADS_URL" => Some("https://ads.example.com/break".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            substitute("url: ${ADS_URL}\nthreshold: ${THRESHOLD:-0.2}", var).unwrap(),
+            "url: https://ads.example.com/break\nthreshold: 0.2"
+        );
+        assert_eq!(substitute("$$5 or $5", var).unwrap(), "$5 or $5");
+        assert!(substitute("url: ${MISSING}", var).is_err());
+    }
+
+    #[test]
+    fn parses_yaml_and_json_watchers() {
+        let yaml = r#"
+slate_url: file://./resources/slate_120px.jpg
+source:
+  ingest_port: 5000
+  container: mpeg-ts
+  codec: h264
+  transport:
+    protocol: rtp
+transitions:
+  - from: content
+    to: slate
+    actions: []
+"#;
+        let watcher = parse(Path::new("watcher.yaml"), yaml).unwrap();
+        assert_eq!(watcher.source.ingest_port, Some(5000));
+        let json = serde_json::to_string(&watcher).unwrap();
+        assert_eq!(parse(Path::new("watcher.json"), &json).unwrap(), watcher);
+        assert!(parse(Path::new("watcher.json"), yaml).is_err());
+    }
+}